@@ -1,6 +1,9 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{FnArg, ImplItem, ImplItemFn, ItemImpl, Pat, Type, Visibility, parse_macro_input};
+use syn::{
+    Attribute, FnArg, ImplItem, ImplItemFn, Item, ItemEnum, ItemImpl, Pat, Type, Visibility,
+    parse_macro_input,
+};
 
 /// The `#[actor]` attribute macro that implements the Actor trait for a struct.
 /// This crate doesn't make a lot of sense by itself - instead look at
@@ -11,132 +14,1825 @@ use syn::{FnArg, ImplItem, ImplItemFn, ItemImpl, Pat, Type, Visibility, parse_ma
 /// 2. Generate message structs for each method's parameters
 /// 3. Implement the Actor trait's dispatch method that:
 ///    - Deserializes JSON messages
-///    - Matches method names from the JSON
+///    - Looks the method name up in a perfect hash table generated at compile time
 ///    - Calls the appropriate method with deserialized parameters
 ///    - Serializes and returns the result
+///
+/// A non-async `fn snapshot(&self) -> S` and `fn restore(&self, state: S)` (where `S` is
+/// serializable) in the same impl block are picked up as the actor's snapshot hooks, and a
+/// private `async fn init(&mut self)` (returning `()` or a `Result` whose error is `Display`) as
+/// its initializer, run before the server accepts connections.  A private
+/// `async fn on_shutdown(&self)` runs once the server has drained, before the actor is dropped.
+/// Private `async fn export_subject_data(&self, subject_id: &str) -> S` (where `S` is
+/// serializable) and `async fn delete_subject_data(&self, subject_id: &str)`, either of which may
+/// return a `Result` whose error is `Display`, serve data-subject requests; see
+/// `simple_json_server::privacy`.
+///
+/// A private `fn before_dispatch(&self, method: &str, params: &str) -> Result<(), ActorError>`
+/// runs before every call of the actor's methods and may refuse it, and a private
+/// `fn after_dispatch(&self, method: &str, result: &str)` runs after each call with its JSON
+/// response; either may be `async`.  See `simple_json_server::hooks`.
+///
+/// Methods can be marked `#[read]` (only reads state) or `#[write]` (changes state, the default).
+/// The server uses this to journal and replicate state changes and to reject writes on
+/// read-only replicas.  Calls normally run concurrently; a method explicitly marked `#[write]`
+/// waits for calls in progress to finish and runs on its own, like a write lock.
+///
+/// `#[concurrency(max = N)]` limits a method to `N` calls in flight at once, queueing the rest;
+/// `#[serialized]` is shorthand for `#[concurrency(max = 1)]`.  The limit is per method and is
+/// shared by every instance of the actor type in the process.
+///
+/// `#[blocking]` runs a method on the server's blocking pool, a bounded set of threads separate
+/// from the async runtime, so it may block (on file I/O, a synchronous client, heavy computation)
+/// without stalling other calls; see `simple_json_server::blocking`.
+///
+/// `#[streaming]` serializes a method's result as it is sent over HTTP instead of building the
+/// whole response in memory first, bounding the memory used by methods returning very large
+/// values.  The result must be `Send + 'static`.
+///
+/// `#[get]` also serves a `#[read]` method by `GET /<method>`, taking its parameters from the
+/// query string (`?tag=a&tag=b&filter[min]=10`) and reading the strings as the parameter types
+/// ask, so it can be linked to and cached.
+///
+/// `#[flag("beta_reports")]` serves a method only while the named feature flag is enabled; see
+/// `simple_json_server::flags`.
+///
+/// `#[accepts("text/plain")]` lists the request `Content-Type`s a method takes in place of the
+/// server's, for servers requiring one; see `simple_json_server::content_type`.
+///
+/// `#[deprecated(note = "Use add_v2", sunset = "2025-12-31")]` marks a method deprecated.  The
+/// server answers its calls with `Deprecation`, `Sunset` and `Warning` headers; `sunset` (a
+/// `YYYY-MM-DD` date) is optional and is removed, leaving a standard `#[deprecated]` for Rust
+/// callers.
+///
+/// `#[from_header("X-Tenant-Id")]` on a parameter reads it from the named HTTP request header
+/// instead of the JSON message, and `#[from_cookie("session")]` from the named cookie.  The
+/// value is deserialized into the parameter's type like any other (as a string, or as JSON for
+/// numbers and the like), a missing value is only accepted by an `Option`, and a call that fails
+/// either way is answered `400 Bad Request`.  WebSocket calls have no headers or cookies.
+///
+/// `#[sensitive]` on a parameter keeps its value out of the slow log, payload samples,
+/// `RequestContext::redacted_params` and the server's error messages.  `#[sensitive(encrypted)]`
+/// also takes the value encrypted (see `simple_json_server::encryption::Encryption::encrypt_param`)
+/// and decrypts it with the server's field encryption key before the method sees it.
+///
+/// `#[sanitize(trim, lowercase, max_len = 100)]` on a `String` parameter (or an `Option` or
+/// `Vec` of them) scrubs its value before the method runs.  The steps are `strip_control`,
+/// `normalize` (Unicode NFC), `trim`, `collapse_whitespace` and `lowercase` or `uppercase`,
+/// applied in that order, and a value longer than `max_len` characters afterwards is answered
+/// `400 Bad Request`; see `simple_json_server::sanitize`.
+///
+/// `#[if_match]` on one parameter of a method makes the call conditional: if it holds a version
+/// (or, when it is `None`, an `If-Match` header names one) that differs from what a private
+/// `fn current_version(&self, method: &str, params: &str) -> Option<V>` hook in the same impl
+/// returns, the call is answered `409 Conflict` (`412 Precondition Failed` for the header)
+/// without running; see `simple_json_server::preconditions`.
+///
+/// `#[max_size(image = 1_000_000)]` rejects calls whose `image` parameter (any `AsRef<[u8]>`
+/// type, such as `simple_json_server::binary::Base64`) is larger than the given number of bytes.
+///
+/// `#[example(params = r#"{"a": 1, "b": 2}"#, response = "3")]` gives a canonical example call,
+/// used by the generated documentation instead of placeholder values.  `response` is optional
+/// and both must be valid JSON.  A method may have several examples; the first is shown.  Under
+/// `cfg(test)` each method with examples gets a generated test (in a `<type>_wire_format`
+/// module) checking that the example parameters deserialize as dispatch expects and that the
+/// example response deserializes into the method's return type.
+///
+/// `#[event(topic = "price", payload = r#"{"symbol": "ACME", "price": 12.5}"#)]` after `#[actor]`
+/// on the impl block documents an event topic the actor publishes to WebSocket subscribers
+/// (see `simple_json_server::events`), with an example payload.
+///
+/// `#[actor(mock)]` also generates a `Mock<Type>` actor serving the same methods, with an
+/// `expect_<method>()` per method to program its answers; see `simple_json_server::mock`.
+///
+/// `#[actor(messages)]` makes each method's message public: a `<Method>Message` struct with a
+/// public field per parameter, implementing `simple_json_server::message::Message` for the
+/// actor, so calls can be built and handled as typed values.  It also generates a
+/// `<Type>Request` enum with a variant per method, holding the parameters the JSON carries and
+/// deserializing from the WebSocket envelope (`{"method": "add", "params": {...}}`), so
+/// gateways and tests can match calls exhaustively.  The arguments combine, as in
+/// `#[actor(mock, messages)]`.
+///
+/// On an enum used as a parameter type, `#[actor]` leaves the enum unchanged and adds the JSON
+/// values it accepts to its documentation, following its serde `rename`, `rename_all`, `alias`,
+/// `skip`, `tag` and `content` attributes.
 #[proc_macro_attribute]
-#[allow(clippy::collapsible_if)] // Intentionally avoiding let-chains for MSRV compatibility (Rust 1.85)
-pub fn actor(_args: TokenStream, input: TokenStream) -> TokenStream {
-    let input_impl = parse_macro_input!(input as ItemImpl);
+pub fn actor(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    match parse_macro_input!(input as Item) {
+        Item::Impl(input_impl) => actor_impl(input_impl, args),
+        Item::Enum(input_enum) if args == ActorArgs::default() => actor_enum(input_enum),
+        Item::Enum(input_enum) => syn::Error::new_spanned(
+            input_enum,
+            "#[actor(mock)] and #[actor(messages)] apply to an impl block",
+        )
+        .to_compile_error()
+        .into(),
+        item => syn::Error::new_spanned(item, "#[actor] applies to an impl block or an enum")
+            .to_compile_error()
+            .into(),
+    }
+}
+
+/// What `#[actor(...)]` asks to generate besides the `Actor` implementation.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ActorArgs {
+    /// `mock`: a `Mock<Type>` actor.
+    mock: bool,
+    /// `messages`: public message structs implementing `Message`.
+    messages: bool,
+}
 
+/// Parse the arguments of `#[actor(...)]`: nothing, or `mock` and `messages` separated by commas.
+fn parse_args(args: TokenStream) -> syn::Result<ActorArgs> {
+    let mut parsed = ActorArgs::default();
+    let parser = syn::punctuated::Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated;
+    for arg in syn::parse::Parser::parse(parser, args)? {
+        if arg == "mock" {
+            parsed.mock = true;
+        } else if arg == "messages" {
+            parsed.messages = true;
+        } else {
+            return Err(syn::Error::new(arg.span(), "expected `mock` or `messages`"));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Implement the Actor trait for the type of `input_impl`.
+#[allow(clippy::collapsible_if)] // Intentionally avoiding let-chains for MSRV compatibility (Rust 1.85)
+fn actor_impl(mut input_impl: ItemImpl, args: ActorArgs) -> TokenStream {
+    let ActorArgs { mock, messages } = args;
     // Extract the struct type this impl is for
-    let struct_type = &input_impl.self_ty;
+    let struct_type = input_impl.self_ty.clone();
+
+    // Collect all public async methods
+    let mut methods = Vec::new();
+    let mut method_infos = Vec::new();
+    let mut message_structs = Vec::new();
+    let mut dispatch_arms = Vec::new();
+    let mut routes: Vec<String> = Vec::new();
+    let mut hooks = Vec::new();
+    // Whether `before_dispatch` and `after_dispatch` are defined, and are async
+    let mut before_dispatch = None;
+    let mut after_dispatch = None;
+    let mut wire_tests = Vec::new();
+    let mut mock_arms = Vec::new();
+    let mut mock_expectations = Vec::new();
+    let mut typed_messages = Vec::new();
+    let mut request_variants = Vec::new();
+    let mut errors = Vec::new();
+
+    // Whether `current_version` is defined, and is async, for methods with an `#[if_match]`
+    let current_version = input_impl.items.iter().find_map(|item| match item {
+        ImplItem::Fn(method) if method.sig.ident == "current_version" => {
+            Some(method.sig.asyncness.is_some())
+        }
+        _ => None,
+    });
+
+    let events = match take_events(&mut input_impl.attrs) {
+        Ok(events) => events,
+        Err(e) => {
+            errors.push(e.to_compile_error());
+            Vec::new()
+        }
+    };
+
+    for item in &mut input_impl.items {
+        if let ImplItem::Fn(method) = item {
+            if is_public_async_method(method) {
+                // Marker attributes are consumed here; they are not real attributes
+                let is_read = take_marker(&mut method.attrs, "read");
+                let is_exclusive = take_marker(&mut method.attrs, "write");
+                let is_blocking = take_marker(&mut method.attrs, "blocking");
+                let is_streaming = take_marker(&mut method.attrs, "streaming");
+                let is_get = take_marker(&mut method.attrs, "get");
+                if is_get && !is_read {
+                    let message = "`#[get]` methods must also be `#[read]`";
+                    errors
+                        .push(syn::Error::new(method.sig.ident.span(), message).to_compile_error());
+                }
+                let max_concurrency = match take_concurrency(&mut method.attrs) {
+                    Ok(max) => max,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        None
+                    }
+                };
+                let max_sizes = match take_max_sizes(&mut method.attrs) {
+                    Ok(max_sizes) => max_sizes,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let flag = match take_flag(&mut method.attrs) {
+                    Ok(flag) => flag,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        None
+                    }
+                };
+                let accepts = match take_accepts(&mut method.attrs) {
+                    Ok(accepts) => accepts,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let examples = match take_examples(&mut method.attrs) {
+                    Ok(examples) => examples,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let deprecation = match take_deprecation(&mut method.attrs) {
+                    Ok(deprecation) => deprecation,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        None
+                    }
+                };
+                let bindings = match take_bindings(&mut method.sig) {
+                    Ok(bindings) => bindings,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let sensitive = match take_sensitive(&mut method.sig, &bindings) {
+                    Ok(sensitive) => sensitive,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let sanitizers = match take_sanitizers(&mut method.sig) {
+                    Ok(sanitizers) => sanitizers,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        Vec::new()
+                    }
+                };
+                let if_match = match take_if_match(&mut method.sig) {
+                    Ok(if_match) => if_match,
+                    Err(e) => {
+                        errors.push(e.to_compile_error());
+                        None
+                    }
+                };
+                let method = &*method;
+
+                let method_name = &method.sig.ident;
+                let method_name_str = method_name.to_string();
+
+                // Each method's arm is chosen by its index in the route table
+                let route = match routes.iter().position(|name| *name == method_name_str) {
+                    Some(route) => route,
+                    None => {
+                        routes.push(method_name_str.clone());
+                        routes.len() - 1
+                    }
+                };
+
+                // Extract parameters (excluding &self)
+                let params = extract_method_params(method);
+                let binding = |name: &syn::Ident| {
+                    bindings
+                        .iter()
+                        .find(|(bound, _)| bound == name)
+                        .map(|(_, binding)| binding)
+                };
+                // Parameters bound to headers and cookies are not part of the JSON message
+                let body_params: Vec<_> = params
+                    .iter()
+                    .filter(|(name, _)| binding(name).is_none())
+                    .collect();
+
+                // Generate message struct name
+                let message_struct_name = syn::Ident::new(
+                    &format!("{}Message", snake_case_to_pascal_case(&method_name_str)),
+                    method_name.span(),
+                );
+
+                // Generate message struct
+                let message_struct = if !body_params.is_empty() {
+                    let param_fields: Vec<_> = body_params
+                        .iter()
+                        .map(|(name, ty)| {
+                            quote! { #name: #ty }
+                        })
+                        .collect();
+
+                    quote! {
+                        #[derive(serde::Deserialize)]
+                        struct #message_struct_name {
+                            #(#param_fields),*
+                        }
+                    }
+                } else {
+                    // For methods with no parameters, create an empty struct
+                    quote! {
+                        #[derive(serde::Deserialize)]
+                        struct #message_struct_name {}
+                    }
+                };
+                message_structs.push(message_struct.clone());
+
+                let return_type = match &method.sig.output {
+                    syn::ReturnType::Default => quote! { () },
+                    syn::ReturnType::Type(_, ty) => quote! { #ty },
+                };
+
+                // Declared examples must match what dispatch accepts and what the method returns
+                if !examples.is_empty() {
+                    let params_checks = examples.iter().map(|example| {
+                        let params = example.params.to_string();
+                        quote! {
+                            if let Err(e) = serde_json::from_str::<#message_struct_name>(#params) {
+                                panic!(
+                                    "Example parameters {} of `{}` do not match its signature: {}",
+                                    #params, #method_name_str, e
+                                );
+                            }
+                        }
+                    });
+                    let response_checks = examples
+                        .iter()
+                        .filter_map(|example| example.response.as_ref())
+                        .map(|response| {
+                            let response = response.to_string();
+                            quote! {
+                                (&::simple_json_server::__private::ResponseCheck::<#return_type>::new())
+                                    .check(#method_name_str, #response);
+                            }
+                        });
+                    wire_tests.push(quote! {
+                        #[test]
+                        fn #method_name() {
+                            use ::simple_json_server::__private::{CheckResponse as _, SkipResponseCheck as _};
+                            #message_struct
+                            #(#params_checks)*
+                            #(#response_checks)*
+                        }
+                    });
+                }
+
+                // Each argument comes from the message or from the request it was bound to
+                let args: Vec<_> = params
+                    .iter()
+                    .map(|(name, _)| match binding(name) {
+                        Some(_) => quote! { #name },
+                        None => quote! { msg_params.#name },
+                    })
+                    .collect();
+                let sanitized = |name: &syn::Ident| {
+                    sanitizers
+                        .iter()
+                        .find(|(marked, _)| marked == name)
+                        .map(|(_, sanitizer)| sanitizer)
+                };
+                let bound_params: Vec<_> = params
+                    .iter()
+                    .filter_map(|(name, ty)| {
+                        let source = binding(name)?.source();
+                        let name_str = name.to_string();
+                        let mutability = sanitized(name).map(|_| quote! { mut });
+                        Some(quote! {
+                            let #mutability #name = match ::simple_json_server::__private::bound_param::<#ty>(
+                                #method_name_str, #name_str, #source
+                            ) {
+                                Ok(value) => value,
+                                Err(response) => return response,
+                            };
+                        })
+                    })
+                    .collect();
+
+                // Sanitized parameters are scrubbed, and refused if still too long, before the
+                // method runs
+                let sanitize_checks: Vec<_> = params
+                    .iter()
+                    .filter_map(|(name, _)| {
+                        let sanitizer = sanitized(name)?.tokens();
+                        let name_str = name.to_string();
+                        let arg = match binding(name) {
+                            Some(_) => quote! { #name },
+                            None => quote! { msg_params.#name },
+                        };
+                        Some(quote! {
+                            if let Err(response) = ::simple_json_server::__private::sanitize_param(
+                                #method_name_str, #name_str, &mut #arg, &#sanitizer
+                            ) {
+                                return response;
+                            }
+                        })
+                    })
+                    .collect();
+                // A stale expected version refuses the call before the method runs
+                let version_check = if_match.as_ref().map(|name| {
+                    let Some(is_async) = current_version else {
+                        let message = "`#[if_match]` needs a `current_version` hook in this impl";
+                        return syn::Error::new(name.span(), message).to_compile_error();
+                    };
+                    let awaited = is_async.then(|| quote! { .await });
+                    let name_str = name.to_string();
+                    let arg = match binding(name) {
+                        Some(_) => quote! { #name },
+                        None => quote! { msg_params.#name },
+                    };
+                    quote! {
+                        if let Err(response) = ::simple_json_server::__private::check_version(
+                            #method_name_str,
+                            #name_str,
+                            &#arg,
+                            self.current_version(#method_name_str, msg) #awaited,
+                        ) {
+                            return response;
+                        }
+                    }
+                });
+                let msg_binding = if body_params
+                    .iter()
+                    .any(|(name, _)| sanitized(name).is_some())
+                {
+                    quote! { mut msg_params }
+                } else {
+                    quote! { msg_params }
+                };
+
+                // Oversized parameters are rejected before the method runs
+                let size_checks = max_sizes.iter().map(|(name, max)| {
+                    if !params.iter().any(|(param, _)| param == name) {
+                        let message = format!("`{}` is not a parameter of this method", name);
+                        return syn::Error::new(name.span(), message).to_compile_error();
+                    }
+                    let name_str = name.to_string();
+                    let arg = match binding(name) {
+                        Some(_) => quote! { #name },
+                        None => quote! { msg_params.#name },
+                    };
+                    quote! {
+                        let len = ::simple_json_server::__private::byte_len(&#arg);
+                        if len > #max {
+                            return ::simple_json_server::__private::too_large(
+                                #method_name_str, #name_str, len, #max
+                            );
+                        }
+                    }
+                });
+
+                // Typed messages carry every parameter, bound ones included, and call the method
+                // directly
+                if messages {
+                    let doc = format!(
+                        "A call of `{}`, handled with `simple_json_server::Actor::handle`.",
+                        method_name_str
+                    );
+                    let names: Vec<_> = params.iter().map(|(name, _)| name).collect();
+                    let types = params.iter().map(|(_, ty)| ty);
+                    let self_ty = &struct_type;
+                    typed_messages.push(quote! {
+                        #[doc = #doc]
+                        #[derive(serde::Deserialize)]
+                        pub struct #message_struct_name {
+                            #(pub #names: #types),*
+                        }
+
+                        impl ::simple_json_server::message::Message<#self_ty> for #message_struct_name {
+                            type Response = #return_type;
+
+                            fn handle(
+                                self,
+                                actor: &#self_ty,
+                            ) -> impl std::future::Future<Output = Self::Response> + Send {
+                                async move { actor.#method_name(#(self.#names),*).await }
+                            }
+                        }
+                    });
+
+                    // The request enum has a variant per method, holding what the JSON carries
+                    let variant = syn::Ident::new(
+                        &snake_case_to_pascal_case(&method_name_str),
+                        method_name.span(),
+                    );
+                    let fields = body_params.iter().map(|(name, ty)| quote! { #name: #ty });
+                    let doc = format!("A call of `{}`.", method_name_str);
+                    request_variants.push((
+                        variant.clone(),
+                        quote! {
+                            #[doc = #doc]
+                            #[serde(rename = #method_name_str)]
+                            #variant { #(#fields),* }
+                        },
+                        method_name_str.clone(),
+                    ));
+                }
+
+                // The mock answers from expectations taking the parameters as a tuple
+                if mock {
+                    let param_types = params.iter().map(|(_, ty)| ty);
+                    let args_type = quote! { (#(#param_types,)*) };
+                    let expect_name =
+                        syn::Ident::new(&format!("expect_{}", method_name_str), method_name.span());
+                    let expect_doc = format!("Expect calls to `{}`.", method_name_str);
+                    mock_expectations.push(quote! {
+                        #[doc = #expect_doc]
+                        pub fn #expect_name(
+                            &self,
+                        ) -> ::simple_json_server::mock::Expectation<'_, #args_type, #return_type> {
+                            self.expectations.expect(#method_name_str)
+                        }
+                    });
+                    mock_arms.push(quote! {
+                        Some(#route) => {
+                            match ::simple_json_server::__private::parse_params::<#message_struct_name>(msg) {
+                                Ok(#msg_binding) => {
+                                    #(#bound_params)*
+                                    #(#sanitize_checks)*
+                                    let args = (#(#args,)*);
+                                    match self.expectations.call::<#args_type, #return_type>(#method_name_str, args) {
+                                        Some(result) => match serde_json::to_string(&result) {
+                                            Ok(json_result) => json_result,
+                                            Err(e) => serde_json::to_string(&format!("Failed to serialize result for {}: {}", #method_name_str, e))
+                                                .unwrap_or_else(|_| "\"Serialization error\"".to_string())
+                                        },
+                                        None => serde_json::to_string(&format!("No expectation for {}", #method_name_str))
+                                            .unwrap_or_else(|_| "\"Unexpected call\"".to_string()),
+                                    }
+                                }
+                                Err(e) => ::simple_json_server::__private::rejected_params::<#message_struct_name>(
+                                    #method_name_str, msg, e, &[]
+                                )
+                            }
+                        }
+                    });
+                }
+                let method_call = if params.is_empty() {
+                    quote! { self.#method_name().await }
+                } else {
+                    quote! { self.#method_name(#(#args),*).await }
+                };
+
+                // Limited methods wait for a permit from their own semaphore before running
+                let method_call = match max_concurrency {
+                    Some(max) => {
+                        let max = max as usize;
+                        quote! {
+                            {
+                                static PERMITS: ::simple_json_server::__private::Semaphore =
+                                    ::simple_json_server::__private::Semaphore::const_new(#max);
+                                let _permit = PERMITS.acquire().await.expect("method semaphore is never closed");
+                                #method_call
+                            }
+                        }
+                    }
+                    None => method_call,
+                };
+
+                // Calls returning `Err` are marked as failed so per-request work is rolled back
+                let mark_failed = if returns_result(method) {
+                    quote! {
+                        if result.is_err() {
+                            if let Some(ctx) = ::simple_json_server::RequestContext::current() {
+                                ctx.set_failed();
+                            }
+                        }
+                    }
+                } else {
+                    quote! {}
+                };
+
+                // Bad enum values are reported with the values the parameter accepts
+                let enum_checks = body_params.iter().map(|(name, ty)| {
+                    let name = name.to_string();
+                    quote! {
+                        (#name, ::simple_json_server::__private::check_enum::<#ty>
+                            as ::simple_json_server::__private::EnumCheck)
+                    }
+                });
+
+                // Streaming results are left for the transport to serialize as it sends them
+                let serialize_result = if is_streaming {
+                    quote! {
+                        ::simple_json_server::__private::stream_result(#method_name_str, result)
+                    }
+                } else {
+                    quote! {
+                        match serde_json::to_string(&result) {
+                            Ok(json_result) => json_result,
+                            Err(e) => serde_json::to_string(&format!("Failed to serialize result for {}: {}", #method_name_str, e))
+                                .unwrap_or_else(|_| "\"Serialization error\"".to_string())
+                        }
+                    }
+                };
+
+                dispatch_arms.push(quote! {
+                    Some(#route) => {
+                        match ::simple_json_server::__private::parse_params::<#message_struct_name>(msg) {
+                            Ok(#msg_binding) => {
+                                #(#bound_params)*
+                                #(#sanitize_checks)*
+                                #version_check
+                                #(#size_checks)*
+                                let result = #method_call;
+                                #mark_failed
+                                #serialize_result
+                            }
+                            Err(e) => ::simple_json_server::__private::rejected_params::<#message_struct_name>(
+                                #method_name_str, msg, e, &[#(#enum_checks),*]
+                            )
+                        }
+                    }
+                });
+
+                let kind = if is_read {
+                    quote! { ::simple_json_server::MethodKind::Read }
+                } else {
+                    quote! { ::simple_json_server::MethodKind::Write }
+                };
+                let max_concurrency_info = match max_concurrency {
+                    Some(max) => quote! { Some(#max) },
+                    None => quote! { None },
+                };
+                let flag_info = match &flag {
+                    Some(flag) => quote! { Some(#flag) },
+                    None => quote! { None },
+                };
+                let deprecation_info = match &deprecation {
+                    Some(Deprecation { note, sunset }) => {
+                        let note = option_tokens(note);
+                        let sunset = option_tokens(sunset);
+                        quote! {
+                            Some(::simple_json_server::Deprecation { note: #note, sunset: #sunset })
+                        }
+                    }
+                    None => quote! { None },
+                };
+                let example_infos = examples.iter().map(|example| {
+                    let params = example.params.to_string();
+                    let response = match &example.response {
+                        Some(response) => {
+                            let response = response.to_string();
+                            quote! { Some(#response) }
+                        }
+                        None => quote! { None },
+                    };
+                    quote! {
+                        ::simple_json_server::MethodExample { params: #params, response: #response }
+                    }
+                });
+                let param_infos = params.iter().map(|(name, ty)| {
+                    let source = match binding(name) {
+                        Some(binding) => binding.source(),
+                        None => quote! { ::simple_json_server::ParamSource::Body },
+                    };
+                    let (is_sensitive, is_encrypted) = sensitive
+                        .iter()
+                        .find(|(marked, _)| marked == name)
+                        .map_or((false, false), |(_, encrypted)| (true, *encrypted));
+                    let name = name.to_string();
+                    let ty = quote!(#ty).to_string();
+                    quote! {
+                        ::simple_json_server::MethodParam {
+                            name: #name,
+                            ty: #ty,
+                            source: #source,
+                            sensitive: #is_sensitive,
+                            encrypted: #is_encrypted,
+                        }
+                    }
+                });
+                let returns = return_type.to_string();
+                let doc_info = extract_method_doc(method).unwrap_or_default();
+                method_infos.push(quote! {
+                    ::simple_json_server::MethodInfo {
+                        name: #method_name_str,
+                        doc: #doc_info,
+                        params: &[#(#param_infos),*],
+                        returns: #returns,
+                        kind: #kind,
+                        exclusive: #is_exclusive,
+                        max_concurrency: #max_concurrency_info,
+                        blocking: #is_blocking,
+                        streaming: #is_streaming,
+                        get: #is_get,
+                        flag: #flag_info,
+                        accepts: &[#(#accepts),*],
+                        deprecated: #deprecation_info,
+                        examples: &[#(#example_infos),*],
+                    }
+                });
+
+                methods.push(ActorMethod {
+                    method: method.clone(),
+                    is_read,
+                    is_exclusive,
+                    max_concurrency,
+                    is_blocking,
+                    is_streaming,
+                    is_get,
+                    max_sizes,
+                    flag,
+                    accepts,
+                    examples,
+                    deprecation,
+                    bindings,
+                    sensitive,
+                    sanitizers,
+                    if_match,
+                });
+            } else if method.sig.ident == "before_dispatch" {
+                before_dispatch = Some(method.sig.asyncness.is_some());
+            } else if method.sig.ident == "after_dispatch" {
+                after_dispatch = Some(method.sig.asyncness.is_some());
+            } else if let Some(hook) = generate_hook(method) {
+                hooks.push(hook);
+            }
+        }
+    }
+
+    // Method names are looked up in a perfect hash table built now, rather than compared in turn
+    let route_table = {
+        let names: Vec<&str> = routes.iter().map(String::as_str).collect();
+        let hash = phf_generator::generate_hash(&names);
+        let key = hash.key;
+        let disps = hash.disps.iter().map(|(d1, d2)| quote! { (#d1, #d2) });
+        let entries = hash.map.iter().map(|&route| {
+            let name = &routes[route];
+            quote! { (#name, #route) }
+        });
+        quote! {
+            static ROUTES: ::simple_json_server::__private::Routes =
+                ::simple_json_server::__private::Routes {
+                    key: #key,
+                    disps: &[#(#disps),*],
+                    entries: &[#(#entries),*],
+                };
+        }
+    };
+
+    // Generate documentation for the Actor implementation
+    let doc_string = generate_actor_documentation(&methods, &events, &struct_type);
+
+    // Generate the Actor trait implementation
+    let routed = quote! {
+        match ROUTES.get(method_name).copied() {
+            #(#dispatch_arms)*
+            _ => ::simple_json_server::__private::unknown_method(method_name, msg),
+        }
+    };
+    let dispatch = generate_dispatch_hooks(routed, before_dispatch, after_dispatch);
+
+    let actor_impl = quote! {
+        #[doc = #doc_string]
+        #[allow(deprecated)] // Deprecated methods are still served
+        impl crate::Actor for #struct_type {
+            fn dispatch(&self, method_name: &str, msg: &str) -> impl std::future::Future<Output = String> + Send {
+                async move {
+                // Define message structs locally
+                #(#message_structs)*
+
+                // Parameters are deserialized straight from the message text, so `RawJson`
+                // parameters keep theirs unparsed
+                #route_table
+                #dispatch
+                }
+            }
+
+            fn methods(&self) -> &'static [::simple_json_server::MethodInfo] {
+                const METHODS: &[::simple_json_server::MethodInfo] = &[#(#method_infos),*];
+                METHODS
+            }
+
+            #(#hooks)*
+        }
+    };
+
+    // Methods with examples get a test checking them against the wire format
+    let wire_tests = match &struct_type.as_ref() {
+        Type::Path(path) if !wire_tests.is_empty() && input_impl.generics.params.is_empty() => {
+            let type_name = &path.path.segments.last().expect("a type path").ident;
+            let module = syn::Ident::new(
+                &format!("{}_wire_format", type_name).to_lowercase(),
+                type_name.span(),
+            );
+            quote! {
+                #[cfg(test)]
+                #[allow(dead_code, unused_imports)]
+                mod #module {
+                    use super::*;
+                    #(#wire_tests)*
+                }
+            }
+        }
+        _ => quote! {},
+    };
+
+    // A mock serving the same methods from programmed expectations
+    let mock_actor = match &struct_type.as_ref() {
+        Type::Path(path) if mock && input_impl.generics.params.is_empty() => {
+            let type_name = &path.path.segments.last().expect("a type path").ident;
+            let mock_name = syn::Ident::new(&format!("Mock{}", type_name), type_name.span());
+            let mock_doc = format!(
+                "A mock of [`{}`] answering calls from programmed expectations; see `simple_json_server::mock`.",
+                type_name
+            );
+            quote! {
+                #[doc = #mock_doc]
+                #[derive(Debug, Clone, Default)]
+                pub struct #mock_name {
+                    expectations: ::simple_json_server::mock::Expectations,
+                }
+
+                impl #mock_name {
+                    /// A mock with no expectations.
+                    pub fn new() -> Self {
+                        Self::default()
+                    }
+
+                    #(#mock_expectations)*
+
+                    /// How many calls to `method` the mock has answered.
+                    pub fn calls(&self, method: &str) -> usize {
+                        self.expectations.calls(method)
+                    }
+
+                    /// Panic unless every expectation was called the expected number of times
+                    /// and no call went unanswered.
+                    pub fn verify(&self) {
+                        self.expectations.verify()
+                    }
+                }
+
+                impl crate::Actor for #mock_name {
+                    fn dispatch(&self, method_name: &str, msg: &str) -> impl std::future::Future<Output = String> + Send {
+                        async move {
+                            #(#message_structs)*
+
+                            #route_table
+                            match ROUTES.get(method_name).copied() {
+                                #(#mock_arms)*
+                                _ => ::simple_json_server::__private::unknown_method(method_name, msg),
+                            }
+                        }
+                    }
+
+                    fn methods(&self) -> &'static [::simple_json_server::MethodInfo] {
+                        const METHODS: &[::simple_json_server::MethodInfo] = &[#(#method_infos),*];
+                        METHODS
+                    }
+                }
+            }
+        }
+        _ if mock => syn::Error::new_spanned(
+            &input_impl.self_ty,
+            "#[actor(mock)] needs a non-generic type",
+        )
+        .to_compile_error(),
+        _ => quote! {},
+    };
+
+    let typed_messages = match &struct_type.as_ref() {
+        Type::Path(path) if messages && input_impl.generics.params.is_empty() => {
+            let type_name = &path.path.segments.last().expect("a type path").ident;
+            let request_name = syn::Ident::new(&format!("{}Request", type_name), type_name.span());
+            let request_doc = format!(
+                "Any call of [`{}`], in the form WebSocket clients send: \
+                 `{{\"method\": \"name\", \"params\": {{...}}}}`.",
+                type_name
+            );
+            let variants = request_variants.iter().map(|(_, variant, _)| variant);
+            let method_arms = request_variants.iter().map(|(variant, _, name)| {
+                quote! { Self::#variant { .. } => #name }
+            });
+            quote! {
+                #(#typed_messages)*
+
+                #[doc = #request_doc]
+                #[derive(serde::Deserialize)]
+                #[serde(tag = "method", content = "params")]
+                pub enum #request_name {
+                    #(#variants),*
+                }
+
+                impl #request_name {
+                    /// The name of the method called.
+                    pub fn method(&self) -> &'static str {
+                        match self {
+                            #(#method_arms),*
+                        }
+                    }
+                }
+            }
+        }
+        _ if messages => syn::Error::new_spanned(
+            &input_impl.self_ty,
+            "#[actor(messages)] needs a non-generic type",
+        )
+        .to_compile_error(),
+        _ => quote! {},
+    };
+
+    // Combine original impl with generated Actor impl
+    let expanded = quote! {
+        #(#errors)*
+
+        #input_impl
+
+        #actor_impl
+
+        #mock_actor
+
+        #typed_messages
+
+        #wire_tests
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Document the JSON values of an enum used as a parameter type, leaving the enum unchanged.
+fn actor_enum(mut input_enum: ItemEnum) -> TokenStream {
+    let doc_string = match enum_documentation(&input_enum) {
+        Ok(doc_string) => doc_string,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    input_enum
+        .attrs
+        .push(syn::parse_quote! { #[doc = #doc_string] });
+    TokenStream::from(quote! { #input_enum })
+}
+
+/// How an enum's variants are represented in JSON, from its `#[serde(...)]` attributes.
+#[derive(Default)]
+struct EnumRepr {
+    rename_all: Option<String>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+}
+
+/// How one variant is named in JSON, from its `#[serde(...)]` attributes.
+#[derive(Default)]
+struct VariantRepr {
+    rename: Option<String>,
+    aliases: Vec<String>,
+    skip: bool,
+}
+
+/// Read the `#[serde(...)]` attributes of an enum or variant, calling `f` with each
+/// `name = "value"` (or `None` for bare flags) and ignoring anything else.
+fn parse_serde_attrs(
+    attrs: &[Attribute],
+    mut f: impl FnMut(&str, Option<String>),
+) -> syn::Result<()> {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            let name = meta
+                .path
+                .get_ident()
+                .map(|ident| ident.to_string())
+                .unwrap_or_default();
+            if meta.input.peek(syn::Token![=]) {
+                let value = meta.value()?;
+                // Only string values can affect variant names
+                match value.parse::<syn::Lit>()? {
+                    syn::Lit::Str(text) => f(&name, Some(text.value())),
+                    _ => f(&name, None),
+                }
+            } else if meta.input.peek(syn::token::Paren) {
+                // e.g. `rename(deserialize = "...")`: the deserialize name is the one accepted
+                meta.parse_nested_meta(|inner| {
+                    if inner.input.peek(syn::Token![=]) {
+                        let text: syn::LitStr = inner.value()?.parse()?;
+                        if inner.path.is_ident("deserialize") {
+                            f(&name, Some(text.value()));
+                        }
+                    }
+                    Ok(())
+                })?;
+            } else {
+                f(&name, None);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// The documentation section listing the JSON values `input_enum` accepts.
+fn enum_documentation(input_enum: &ItemEnum) -> syn::Result<String> {
+    use serde_json::{Value, json};
+
+    let mut repr = EnumRepr::default();
+    parse_serde_attrs(&input_enum.attrs, |name, value| match name {
+        "rename_all" => repr.rename_all = value,
+        "tag" => repr.tag = value,
+        "content" => repr.content = value,
+        "untagged" => repr.untagged = true,
+        _ => {}
+    })?;
+
+    let mut doc = String::new();
+    doc.push_str("\n\n**JSON values:**");
+    if repr.untagged {
+        doc.push_str(" the value of any one variant, without its name:\n\n");
+    } else {
+        doc.push_str(" one of\n\n");
+    }
+
+    for variant in &input_enum.variants {
+        let mut variant_repr = VariantRepr::default();
+        parse_serde_attrs(&variant.attrs, |name, value| match (name, value) {
+            ("rename", Some(value)) => variant_repr.rename = Some(value),
+            ("alias", Some(value)) => variant_repr.aliases.push(value),
+            ("skip" | "skip_deserializing", _) => variant_repr.skip = true,
+            _ => {}
+        })?;
+        if variant_repr.skip {
+            continue;
+        }
+
+        let name = variant_repr.rename.unwrap_or_else(|| {
+            rename_variant(&variant.ident.to_string(), repr.rename_all.as_deref())
+        });
+        // What the variant carries: nothing, one value, a tuple, or named fields
+        let content = match &variant.fields {
+            syn::Fields::Unit => None,
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Some(example_json(&fields.unnamed[0].ty))
+            }
+            syn::Fields::Unnamed(fields) => Some(Value::Array(
+                fields
+                    .unnamed
+                    .iter()
+                    .map(|field| example_json(&field.ty))
+                    .collect(),
+            )),
+            syn::Fields::Named(fields) => Some(Value::Object(
+                fields
+                    .named
+                    .iter()
+                    .map(|field| {
+                        let key = field.ident.as_ref().map(|i| i.to_string());
+                        (key.unwrap_or_default(), example_json(&field.ty))
+                    })
+                    .collect(),
+            )),
+        };
+        let value = match (&repr, content) {
+            (EnumRepr { untagged: true, .. }, content) => content.unwrap_or(Value::Null),
+            (
+                EnumRepr {
+                    tag: Some(tag),
+                    content: Some(key),
+                    ..
+                },
+                Some(content),
+            ) => {
+                json!({ tag.clone(): name, key.clone(): content })
+            }
+            (EnumRepr { tag: Some(tag), .. }, Some(Value::Object(fields))) => {
+                let mut tagged = serde_json::Map::new();
+                tagged.insert(tag.clone(), json!(name));
+                tagged.extend(fields);
+                Value::Object(tagged)
+            }
+            (EnumRepr { tag: Some(tag), .. }, _) => json!({ tag.clone(): name }),
+            (_, None) => json!(name),
+            (_, Some(content)) => json!({ name: content }),
+        };
+
+        doc.push_str(&format!("- `{}`", value));
+        if !variant_repr.aliases.is_empty() {
+            let aliases: Vec<_> = variant_repr
+                .aliases
+                .iter()
+                .map(|alias| format!("`\"{}\"`", alias))
+                .collect();
+            doc.push_str(&format!(" (also named {})", aliases.join(", ")));
+        }
+        doc.push('\n');
+    }
+    Ok(doc)
+}
+
+/// Apply a serde `rename_all` rule to a variant name.
+fn rename_variant(name: &str, rule: Option<&str>) -> String {
+    // Split the PascalCase variant name into lower-case words
+    let mut words: Vec<String> = Vec::new();
+    for c in name.chars() {
+        if c.is_uppercase() || words.is_empty() {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().extend(c.to_lowercase());
+    }
+    match rule {
+        Some("lowercase") => name.to_lowercase(),
+        Some("UPPERCASE") => name.to_uppercase(),
+        Some("camelCase") => {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().collect::<String>() + chars.as_str())
+                .unwrap_or_default()
+        }
+        Some("snake_case") => words.join("_"),
+        Some("SCREAMING_SNAKE_CASE") => words.join("_").to_uppercase(),
+        Some("kebab-case") => words.join("-"),
+        Some("SCREAMING-KEBAB-CASE") => words.join("-").to_uppercase(),
+        _ => name.to_string(),
+    }
+}
+
+/// Lifecycle hooks are plain (non-public or non-async) methods with well-known names; generate
+/// the `Actor` trait method that calls `method` if it is one.
+fn generate_hook(method: &ImplItemFn) -> Option<proc_macro2::TokenStream> {
+    if method.sig.asyncness.is_some() {
+        return match method.sig.ident.to_string().as_str() {
+            // async fn init(&mut self), returning () or Result<(), impl Display>
+            "init" => {
+                let result = if returns_result(method) {
+                    quote! { Self::init(self).await.map_err(|e| e.to_string()) }
+                } else {
+                    quote! { Self::init(self).await; Ok(()) }
+                };
+                Some(quote! {
+                    fn init(&mut self) -> impl std::future::Future<Output = Result<(), String>> + Send {
+                        async move { #result }
+                    }
+                })
+            }
+            // async fn on_shutdown(&self)
+            "on_shutdown" => Some(quote! {
+                fn on_shutdown(&self) -> impl std::future::Future<Output = ()> + Send {
+                    async move { Self::on_shutdown(self).await }
+                }
+            }),
+            // async fn export_subject_data(&self, subject_id: &str), returning impl Serialize or
+            // Result<impl Serialize, impl Display>
+            "export_subject_data" => {
+                let data = if returns_result(method) {
+                    quote! {
+                        Self::export_subject_data(self, subject_id)
+                            .await
+                            .map_err(|e| ::simple_json_server::privacy::SubjectError::Failed(e.to_string()))?
+                    }
+                } else {
+                    quote! { Self::export_subject_data(self, subject_id).await }
+                };
+                Some(quote! {
+                    fn export_subject_data(
+                        &self,
+                        subject_id: &str,
+                    ) -> impl std::future::Future<
+                        Output = Result<String, ::simple_json_server::privacy::SubjectError>,
+                    > + Send {
+                        async move {
+                            let data = #data;
+                            serde_json::to_string(&data)
+                                .map_err(|e| ::simple_json_server::privacy::SubjectError::Failed(e.to_string()))
+                        }
+                    }
+                })
+            }
+            // async fn delete_subject_data(&self, subject_id: &str), returning () or
+            // Result<(), impl Display>
+            "delete_subject_data" => {
+                let result = if returns_result(method) {
+                    quote! {
+                        Self::delete_subject_data(self, subject_id)
+                            .await
+                            .map_err(|e| ::simple_json_server::privacy::SubjectError::Failed(e.to_string()))
+                    }
+                } else {
+                    quote! { Self::delete_subject_data(self, subject_id).await; Ok(()) }
+                };
+                Some(quote! {
+                    fn delete_subject_data(
+                        &self,
+                        subject_id: &str,
+                    ) -> impl std::future::Future<
+                        Output = Result<(), ::simple_json_server::privacy::SubjectError>,
+                    > + Send {
+                        async move { #result }
+                    }
+                })
+            }
+            _ => None,
+        };
+    }
+    match method.sig.ident.to_string().as_str() {
+        // fn snapshot(&self) -> impl Serialize
+        "snapshot" => Some(quote! {
+            fn snapshot_state(&self) -> Result<String, String> {
+                serde_json::to_string(&self.snapshot()).map_err(|e| e.to_string())
+            }
+        }),
+        // fn restore(&self, state: impl Deserialize)
+        "restore" => Some(quote! {
+            fn restore_state(&self, state: &str) -> Result<(), String> {
+                let state = serde_json::from_str(state).map_err(|e| e.to_string())?;
+                self.restore(state);
+                Ok(())
+            }
+        }),
+        _ => None,
+    }
+}
+
+/// Wrap the `routed` dispatch of a call in the impl's `before_dispatch` and `after_dispatch`
+/// hooks, given whether each is defined and async.  They run only for known methods, and a call
+/// refused by `before_dispatch` is answered with its error.
+fn generate_dispatch_hooks(
+    routed: proc_macro2::TokenStream,
+    before: Option<bool>,
+    after: Option<bool>,
+) -> proc_macro2::TokenStream {
+    if before.is_none() && after.is_none() {
+        return routed;
+    }
+    let awaited = |is_async: bool| is_async.then(|| quote! { .await });
+    let before = before.map(|is_async| {
+        let awaited = awaited(is_async);
+        quote! {
+            if known {
+                if let Err(e) = self.before_dispatch(method_name, msg) #awaited {
+                    return ::simple_json_server::__private::refuse(e);
+                }
+            }
+        }
+    });
+    let after = after.map(|is_async| {
+        let awaited = awaited(is_async);
+        quote! {
+            if known {
+                self.after_dispatch(method_name, &response) #awaited;
+            }
+        }
+    });
+    quote! {
+        let known = ROUTES.get(method_name).is_some();
+        #before
+        // Arms return their response early, so they run in a block of their own
+        let response = async { #routed }.await;
+        #after
+        response
+    }
+}
+
+/// Remove the marker attribute `#[name]` from `attrs`, returning whether it was present.
+fn take_marker(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+    let before = attrs.len();
+    attrs.retain(|attr| !attr.path().is_ident(name));
+    attrs.len() != before
+}
+
+/// Check if a method is public and async
+/// Remove `#[concurrency(max = N)]` or `#[serialized]` from `attrs`, returning the limit.
+fn take_concurrency(attrs: &mut Vec<Attribute>) -> syn::Result<Option<u32>> {
+    let mut max = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if attr.path().is_ident("serialized") {
+            max = Some(1);
+            false
+        } else if attr.path().is_ident("concurrency") {
+            let parsed = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("max") {
+                    let value: syn::LitInt = meta.value()?.parse()?;
+                    let value: u32 = value.base10_parse()?;
+                    if value == 0 {
+                        return Err(meta.error("`max` must be at least 1"));
+                    }
+                    max = Some(value);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `max = N`"))
+                }
+            });
+            if parsed.is_ok() && max.is_none() {
+                result = Err(syn::Error::new_spanned(attr, "expected `max = N`"));
+            } else if let Err(e) = parsed {
+                result = Err(e);
+            }
+            false
+        } else {
+            true
+        }
+    });
+    result.map(|()| max)
+}
+
+/// Remove `#[flag("name")]` from `attrs`, returning the flag name.
+fn take_flag(attrs: &mut Vec<Attribute>) -> syn::Result<Option<String>> {
+    let mut flag = None;
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("flag") {
+            return true;
+        }
+        match attr.parse_args::<syn::LitStr>() {
+            Ok(name) => flag = Some(name.value()),
+            Err(e) => result = Err(e),
+        }
+        false
+    });
+    result.map(|()| flag)
+}
+
+/// Remove `#[accepts("text/plain", ...)]` from `attrs`, returning the media types it lists.
+fn take_accepts(attrs: &mut Vec<Attribute>) -> syn::Result<Vec<String>> {
+    let mut accepts = Vec::new();
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("accepts") {
+            return true;
+        }
+        let parsed = attr.parse_args_with(
+            syn::punctuated::Punctuated::<syn::LitStr, syn::Token![,]>::parse_terminated,
+        );
+        match parsed {
+            Ok(types) if types.is_empty() => {
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    "expected at least one media type",
+                ))
+            }
+            Ok(types) => accepts.extend(types.iter().map(syn::LitStr::value)),
+            Err(e) => result = Err(e),
+        }
+        false
+    });
+    result.map(|()| accepts)
+}
+
+/// How a method is deprecated, from its `#[deprecated]` attribute.
+struct Deprecation {
+    note: Option<String>,
+    sunset: Option<String>,
+}
+
+/// Read `#[deprecated]`, `#[deprecated = "note"]` or `#[deprecated(note = "...", since = "...",
+/// sunset = "YYYY-MM-DD")]` from `attrs`, removing the `sunset` key rustc does not know.
+fn take_deprecation(attrs: &mut [Attribute]) -> syn::Result<Option<Deprecation>> {
+    let Some(attr) = attrs
+        .iter_mut()
+        .find(|attr| attr.path().is_ident("deprecated"))
+    else {
+        return Ok(None);
+    };
+    let mut deprecation = Deprecation {
+        note: None,
+        sunset: None,
+    };
+    let mut since = None;
+    match &attr.meta {
+        syn::Meta::Path(_) => return Ok(Some(deprecation)),
+        syn::Meta::NameValue(name_value) => {
+            if let syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(note),
+                ..
+            }) = &name_value.value
+            {
+                deprecation.note = Some(note.value());
+            }
+            return Ok(Some(deprecation));
+        }
+        syn::Meta::List(_) => {}
+    }
+    attr.parse_nested_meta(|meta| {
+        let value: syn::LitStr = meta.value()?.parse()?;
+        if meta.path.is_ident("note") {
+            deprecation.note = Some(value.value());
+        } else if meta.path.is_ident("since") {
+            since = Some(value);
+        } else if meta.path.is_ident("sunset") {
+            if !is_date(&value.value()) {
+                return Err(syn::Error::new(
+                    value.span(),
+                    "`sunset` must be a date like \"2025-12-31\"",
+                ));
+            }
+            deprecation.sunset = Some(value.value());
+        } else {
+            return Err(meta.error("expected `note`, `since` or `sunset`"));
+        }
+        Ok(())
+    })?;
+
+    *attr = if deprecation.note.is_none() && since.is_none() {
+        syn::parse_quote! { #[deprecated] }
+    } else {
+        let (note, since) = (deprecation.note.iter(), since.iter());
+        syn::parse_quote! { #[deprecated(#(note = #note,)* #(since = #since)*)] }
+    };
+    Ok(Some(deprecation))
+}
+
+/// Whether `date` is a plausible `YYYY-MM-DD` date.
+fn is_date(date: &str) -> bool {
+    let number = |part: &str, len: usize| -> Option<u32> {
+        if part.len() != len || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        part.parse().ok()
+    };
+    match date.split('-').collect::<Vec<_>>().as_slice() {
+        [year, month, day] => {
+            number(year, 4).is_some()
+                && matches!(number(month, 2), Some(1..=12))
+                && matches!(number(day, 2), Some(1..=31))
+        }
+        _ => false,
+    }
+}
 
-    // Collect all public async methods
-    let mut methods = Vec::new();
-    let mut message_structs = Vec::new();
-    let mut dispatch_arms = Vec::new();
+fn option_tokens(value: &Option<String>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    }
+}
 
-    for item in &input_impl.items {
-        if let ImplItem::Fn(method) = item {
-            if is_public_async_method(method) {
-                let method_name = &method.sig.ident;
-                let method_name_str = method_name.to_string();
+/// Remove every `#[max_size(param = N, ...)]` from `attrs`, returning each limited parameter
+/// with its limit in bytes.
+fn take_max_sizes(attrs: &mut Vec<Attribute>) -> syn::Result<Vec<(syn::Ident, usize)>> {
+    let mut max_sizes = Vec::new();
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("max_size") {
+            return true;
+        }
+        let parsed = attr.parse_nested_meta(|meta| {
+            let Some(name) = meta.path.get_ident().cloned() else {
+                return Err(meta.error("expected `parameter = N`"));
+            };
+            let value: syn::LitInt = meta.value()?.parse()?;
+            max_sizes.push((name, value.base10_parse()?));
+            Ok(())
+        });
+        if let Err(e) = parsed {
+            result = Err(e);
+        }
+        false
+    });
+    result.map(|()| max_sizes)
+}
 
-                // Extract parameters (excluding &self)
-                let params = extract_method_params(method);
+/// Where a parameter marked `#[from_header("...")]` or `#[from_cookie("...")]` is read from.
+enum Binding {
+    Header(String),
+    Cookie(String),
+}
 
-                // Generate message struct name
-                let message_struct_name = syn::Ident::new(
-                    &format!("{}Message", snake_case_to_pascal_case(&method_name_str)),
-                    method_name.span(),
-                );
+impl Binding {
+    /// The `simple_json_server::ParamSource` for this binding.
+    fn source(&self) -> proc_macro2::TokenStream {
+        match self {
+            Binding::Header(name) => quote! { ::simple_json_server::ParamSource::Header(#name) },
+            Binding::Cookie(name) => quote! { ::simple_json_server::ParamSource::Cookie(#name) },
+        }
+    }
 
-                // Generate message struct
-                if !params.is_empty() {
-                    let param_fields: Vec<_> = params
-                        .iter()
-                        .map(|(name, ty)| {
-                            quote! { #name: #ty }
-                        })
-                        .collect();
+    fn describe(&self) -> String {
+        match self {
+            Binding::Header(name) => format!("header `{}`", name),
+            Binding::Cookie(name) => format!("cookie `{}`", name),
+        }
+    }
+}
 
-                    message_structs.push(quote! {
-                        #[derive(serde::Deserialize)]
-                        struct #message_struct_name {
-                            #(#param_fields),*
-                        }
-                    });
-                } else {
-                    // For methods with no parameters, create an empty struct
-                    message_structs.push(quote! {
-                        #[derive(serde::Deserialize)]
-                        struct #message_struct_name {}
-                    });
+/// Remove `#[from_header("...")]` and `#[from_cookie("...")]` from the method's parameters,
+/// returning each bound parameter with where it is read from.
+fn take_bindings(sig: &mut syn::Signature) -> syn::Result<Vec<(syn::Ident, Binding)>> {
+    let mut bindings = Vec::new();
+    let mut result = Ok(());
+    for input in &mut sig.inputs {
+        let FnArg::Typed(pat_type) = input else {
+            continue;
+        };
+        let mut binding = None;
+        pat_type.attrs.retain(|attr| {
+            let is_header = attr.path().is_ident("from_header");
+            if !is_header && !attr.path().is_ident("from_cookie") {
+                return true;
+            }
+            let name = match attr.parse_args::<syn::LitStr>() {
+                Ok(name) => name.value(),
+                Err(e) => {
+                    result = Err(e);
+                    return false;
                 }
+            };
+            let valid = !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+            if !valid {
+                let what = if is_header { "header" } else { "cookie" };
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    format!("`{}` is not a valid {} name", name, what),
+                ));
+            } else if binding.is_some() {
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    "a parameter can be bound to only one header or cookie",
+                ));
+            } else if is_header {
+                binding = Some(Binding::Header(name));
+            } else {
+                binding = Some(Binding::Cookie(name));
+            }
+            false
+        });
+        if let (Some(binding), Pat::Ident(pat_ident)) = (binding, &*pat_type.pat) {
+            bindings.push((pat_ident.ident.clone(), binding));
+        }
+    }
+    result.map(|()| bindings)
+}
 
-                // Generate dispatch arm
-                let param_names: Vec<_> = params.iter().map(|(name, _)| name).collect();
-                let method_call = if params.is_empty() {
-                    quote! { self.#method_name().await }
-                } else {
-                    quote! { self.#method_name(#(msg_params.#param_names),*).await }
-                };
-
-                dispatch_arms.push(quote! {
-                    #method_name_str => {
-                        match serde_json::from_value::<#message_struct_name>(params) {
-                            Ok(msg_params) => {
-                                let result = #method_call;
-                                match serde_json::to_string(&result) {
-                                    Ok(json_result) => json_result,
-                                    Err(e) => serde_json::to_string(&format!("Failed to serialize result for {}: {}", #method_name_str, e))
-                                        .unwrap_or_else(|_| "\"Serialization error\"".to_string())
-                                }
-                            }
-                            Err(e) => serde_json::to_string(&format!("Failed to deserialize parameters for {}: {}", #method_name_str, e))
-                                .unwrap_or_else(|_| "\"Deserialization error\"".to_string())
-                        }
+/// Remove `#[sensitive]` and `#[sensitive(encrypted)]` from the method's parameters, returning
+/// each sensitive parameter with whether it is encrypted.  Only parameters taken from the JSON
+/// message can be encrypted.
+fn take_sensitive(
+    sig: &mut syn::Signature,
+    bindings: &[(syn::Ident, Binding)],
+) -> syn::Result<Vec<(syn::Ident, bool)>> {
+    let mut sensitive = Vec::new();
+    let mut result = Ok(());
+    for input in &mut sig.inputs {
+        let FnArg::Typed(pat_type) = input else {
+            continue;
+        };
+        let mut marked = None;
+        pat_type.attrs.retain(|attr| {
+            if !attr.path().is_ident("sensitive") {
+                return true;
+            }
+            let mut encrypted = false;
+            if !matches!(attr.meta, syn::Meta::Path(_)) {
+                let parsed = attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("encrypted") {
+                        encrypted = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `encrypted`"))
                     }
                 });
-
-                methods.push(method);
+                if let Err(e) = parsed {
+                    result = Err(e);
+                    return false;
+                }
             }
+            if marked.is_some() {
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    "a parameter can be marked `#[sensitive]` only once",
+                ));
+            }
+            marked = Some((encrypted, attr.clone()));
+            false
+        });
+        let (Some((encrypted, attr)), Pat::Ident(pat_ident)) = (marked, &*pat_type.pat) else {
+            continue;
+        };
+        let bound = bindings.iter().any(|(name, _)| *name == pat_ident.ident);
+        if encrypted && bound {
+            result = Err(syn::Error::new_spanned(
+                attr,
+                "only parameters taken from the JSON message can be encrypted",
+            ));
         }
+        sensitive.push((pat_ident.ident.clone(), encrypted));
     }
+    result.map(|()| sensitive)
+}
 
-    // Generate documentation for the Actor implementation
-    let doc_string = generate_actor_documentation(&methods, struct_type);
+/// The steps of a `#[sanitize(...)]` attribute, in the order they are applied.
+#[derive(Default)]
+struct Sanitizer {
+    steps: Vec<&'static str>,
+    max_len: Option<usize>,
+}
 
-    // Generate the Actor trait implementation
-    let actor_impl = quote! {
-        #[doc = #doc_string]
-        impl crate::Actor for #struct_type {
-            fn dispatch(&self, method_name: &str, msg: &str) -> impl std::future::Future<Output = String> + Send {
-                async move {
-                // Define message structs locally
-                #(#message_structs)*
+/// The steps `#[sanitize(...)]` accepts, in the order they are applied.
+const SANITIZE_STEPS: &[&str] = &[
+    "strip_control",
+    "normalize",
+    "trim",
+    "collapse_whitespace",
+    "lowercase",
+    "uppercase",
+];
 
-                // Parse the incoming JSON message
-                let parsed: Result<serde_json::Value, _> = serde_json::from_str(msg);
-                let params = match parsed {
-                    Ok(val) => val,
-                    Err(e) => return serde_json::to_string(&format!("Failed to parse JSON: {}", e)).unwrap_or_else(|_| "\"JSON parse error\"".to_string()),
-                };
+impl Sanitizer {
+    /// A `simple_json_server::sanitize::Sanitizer` taking these steps.
+    fn tokens(&self) -> proc_macro2::TokenStream {
+        let steps = self
+            .steps
+            .iter()
+            .map(|step| syn::Ident::new(step, proc_macro2::Span::call_site()));
+        let max_len = self.max_len.map(|max| quote! { .max_len(#max) });
+        quote! { ::simple_json_server::sanitize::Sanitizer::new() #(.#steps())* #max_len }
+    }
 
+    fn describe(&self) -> String {
+        let mut steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| step.replace('_', " "))
+            .collect();
+        if let Some(max) = self.max_len {
+            steps.push(format!("at most {} characters", max));
+        }
+        steps.join(", ")
+    }
+}
 
-                // Execute async methods directly
-                match method_name {
-                    #(#dispatch_arms)*
-                    _ => serde_json::to_string(&format!("Unknown method: {}", method_name))
-                        .unwrap_or_else(|_| "\"Unknown method error\"".to_string())
+/// Remove `#[sanitize(...)]` from the method's parameters, returning each sanitized parameter
+/// with its steps.
+fn take_sanitizers(sig: &mut syn::Signature) -> syn::Result<Vec<(syn::Ident, Sanitizer)>> {
+    let mut sanitizers = Vec::new();
+    let mut result = Ok(());
+    for input in &mut sig.inputs {
+        let FnArg::Typed(pat_type) = input else {
+            continue;
+        };
+        let mut sanitizer: Option<Sanitizer> = None;
+        pat_type.attrs.retain(|attr| {
+            if !attr.path().is_ident("sanitize") {
+                return true;
+            }
+            let steps = sanitizer.get_or_insert_with(Sanitizer::default);
+            let parsed = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("max_len") {
+                    let value: syn::LitInt = meta.value()?.parse()?;
+                    steps.max_len = Some(value.base10_parse()?);
+                    return Ok(());
                 }
+                let Some(&step) = SANITIZE_STEPS.iter().find(|step| meta.path.is_ident(step))
+                else {
+                    return Err(meta.error(format!(
+                        "expected one of {} or `max_len = N`",
+                        SANITIZE_STEPS.join(", ")
+                    )));
+                };
+                if !steps.steps.contains(&step) {
+                    steps.steps.push(step);
                 }
+                Ok(())
+            });
+            if let Err(e) = parsed {
+                result = Err(e);
+            } else if steps.steps.contains(&"lowercase") && steps.steps.contains(&"uppercase") {
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    "a parameter can't be both lowercased and uppercased",
+                ));
             }
+            false
+        });
+        if let (Some(mut sanitizer), Pat::Ident(pat_ident)) = (sanitizer, &*pat_type.pat) {
+            sanitizer
+                .steps
+                .sort_by_key(|step| SANITIZE_STEPS.iter().position(|known| known == step));
+            sanitizers.push((pat_ident.ident.clone(), sanitizer));
         }
-    };
+    }
+    result.map(|()| sanitizers)
+}
 
-    // Combine original impl with generated Actor impl
-    let expanded = quote! {
-        #input_impl
+/// Remove `#[if_match]` from the method's parameters, returning the parameter it marked.
+fn take_if_match(sig: &mut syn::Signature) -> syn::Result<Option<syn::Ident>> {
+    let mut if_match = None;
+    let mut result = Ok(());
+    for input in &mut sig.inputs {
+        let FnArg::Typed(pat_type) = input else {
+            continue;
+        };
+        let before = pat_type.attrs.len();
+        pat_type
+            .attrs
+            .retain(|attr| !attr.path().is_ident("if_match"));
+        if pat_type.attrs.len() == before {
+            continue;
+        }
+        let Pat::Ident(pat_ident) = &*pat_type.pat else {
+            continue;
+        };
+        if if_match.is_some() {
+            result = Err(syn::Error::new_spanned(
+                &pat_ident.ident,
+                "only one parameter of a method can be marked `#[if_match]`",
+            ));
+        }
+        if_match = Some(pat_ident.ident.clone());
+    }
+    result.map(|()| if_match)
+}
 
-        #actor_impl
-    };
+/// A canonical example call from an `#[example(...)]` attribute.
+struct Example {
+    params: serde_json::Value,
+    response: Option<serde_json::Value>,
+}
 
-    TokenStream::from(expanded)
+/// An event topic declared with `#[event(...)]` on the impl block.
+struct Event {
+    topic: String,
+    payload: serde_json::Value,
+}
+
+/// Remove every `#[event(topic = "...", payload = "...")]` from `attrs`.
+fn take_events(attrs: &mut Vec<Attribute>) -> syn::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("event") {
+            return true;
+        }
+        let mut topic = None;
+        let mut payload = None;
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("topic") {
+                let text: syn::LitStr = meta.value()?.parse()?;
+                topic = Some(text.value());
+            } else if meta.path.is_ident("payload") {
+                let text: syn::LitStr = meta.value()?.parse()?;
+                let value = serde_json::from_str(&text.value())
+                    .map_err(|e| syn::Error::new(text.span(), format!("invalid JSON: {}", e)))?;
+                payload = Some(value);
+            } else {
+                return Err(meta.error("expected `topic` or `payload`"));
+            }
+            Ok(())
+        });
+        match (parsed, topic, payload) {
+            (Err(e), _, _) => result = Err(e),
+            (Ok(()), Some(topic), Some(payload)) => events.push(Event { topic, payload }),
+            (Ok(()), _, _) => {
+                result = Err(syn::Error::new_spanned(
+                    attr,
+                    "expected `topic = \"...\"` and `payload = \"...\"`",
+                ))
+            }
+        }
+        false
+    });
+    result.map(|()| events)
+}
+
+/// Remove every `#[example(params = "...", response = "...")]` from `attrs`.
+fn take_examples(attrs: &mut Vec<Attribute>) -> syn::Result<Vec<Example>> {
+    let mut examples = Vec::new();
+    let mut result = Ok(());
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("example") {
+            return true;
+        }
+        let mut params = None;
+        let mut response = None;
+        let parsed = attr.parse_nested_meta(|meta| {
+            let field = if meta.path.is_ident("params") {
+                &mut params
+            } else if meta.path.is_ident("response") {
+                &mut response
+            } else {
+                return Err(meta.error("expected `params` or `response`"));
+            };
+            let text: syn::LitStr = meta.value()?.parse()?;
+            let value: serde_json::Value = serde_json::from_str(&text.value())
+                .map_err(|e| syn::Error::new(text.span(), format!("invalid JSON: {}", e)))?;
+            *field = Some((value, text.span()));
+            Ok(())
+        });
+        match (parsed, params) {
+            (Err(e), _) => result = Err(e),
+            (Ok(()), None) => {
+                result = Err(syn::Error::new_spanned(attr, "expected `params = \"...\"`"))
+            }
+            (Ok(()), Some((params, span))) if !params.is_object() => {
+                result = Err(syn::Error::new(span, "`params` must be a JSON object"))
+            }
+            (Ok(()), Some((params, _))) => examples.push(Example {
+                params,
+                response: response.map(|(value, _)| value),
+            }),
+        }
+        false
+    });
+    result.map(|()| examples)
 }
 
-/// Check if a method is public and async
 fn is_public_async_method(method: &ImplItemFn) -> bool {
     // Check if method is public
     let is_public = matches!(method.vis, Visibility::Public(_));
@@ -147,6 +1843,21 @@ fn is_public_async_method(method: &ImplItemFn) -> bool {
     is_public && is_async
 }
 
+/// Whether the method's return type is spelled `Result<..>` (with any path prefix).
+fn returns_result(method: &ImplItemFn) -> bool {
+    match &method.sig.output {
+        syn::ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
+    }
+}
+
 /// Extract method parameters (excluding &self)
 fn extract_method_params(method: &ImplItemFn) -> Vec<(syn::Ident, Type)> {
     let mut params = Vec::new();
@@ -179,7 +1890,86 @@ fn snake_case_to_pascal_case(s: &str) -> String {
 }
 
 /// Generate comprehensive documentation for the Actor implementation
-fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type) -> String {
+/// A public async method of the actor, with the markers that were stripped from it.
+struct ActorMethod {
+    method: ImplItemFn,
+    is_read: bool,
+    is_exclusive: bool,
+    max_concurrency: Option<u32>,
+    is_blocking: bool,
+    is_streaming: bool,
+    is_get: bool,
+    max_sizes: Vec<(syn::Ident, usize)>,
+    flag: Option<String>,
+    accepts: Vec<String>,
+    examples: Vec<Example>,
+    deprecation: Option<Deprecation>,
+    bindings: Vec<(syn::Ident, Binding)>,
+    /// Parameters marked `#[sensitive]`, and whether each is also encrypted.
+    sensitive: Vec<(syn::Ident, bool)>,
+    sanitizers: Vec<(syn::Ident, Sanitizer)>,
+    /// The parameter marked `#[if_match]`, holding the version the call expects.
+    if_match: Option<syn::Ident>,
+}
+
+impl ActorMethod {
+    /// How a parameter is shown in the documentation, with where it is read from if that is not
+    /// the JSON message.
+    fn describe_param(&self, name: &syn::Ident, ty: &Type) -> String {
+        let mut param = format!("`{}`: `{}`", name, quote!(#ty));
+        if let Some(kind) = validated_kind(ty) {
+            param.push_str(&format!(" (validated {})", kind));
+        }
+        if let Some((_, binding)) = self.bindings.iter().find(|(bound, _)| bound == name) {
+            param.push_str(&format!(" (from the {})", binding.describe()));
+        }
+        match self.sensitive.iter().find(|(marked, _)| marked == name) {
+            Some((_, true)) => param.push_str(" (sensitive, encrypted)"),
+            Some((_, false)) => param.push_str(" (sensitive)"),
+            None => {}
+        }
+        param
+    }
+
+    fn is_bound(&self, name: &syn::Ident) -> bool {
+        self.bindings.iter().any(|(bound, _)| bound == name)
+    }
+}
+
+/// What a parameter of one of `simple_json_server::validated`'s types holds, if `ty` is one,
+/// looking through `Option` and `Vec`.
+fn validated_kind(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let last = path.path.segments.last()?;
+    if last.ident == "Option" || last.ident == "Vec" {
+        if let syn::PathArguments::AngleBracketed(args) = &last.arguments {
+            return match args.args.first()? {
+                syn::GenericArgument::Type(inner) => validated_kind(inner),
+                _ => None,
+            };
+        }
+    }
+    let in_module = path.path.segments.len() == 1
+        || path
+            .path
+            .segments
+            .iter()
+            .any(|segment| segment.ident == "validated");
+    match last.ident.to_string().as_str() {
+        "Url" if in_module => Some("URL"),
+        "EmailAddress" if in_module => Some("email address"),
+        "PhoneNumber" if in_module => Some("E.164 phone number"),
+        _ => None,
+    }
+}
+
+fn generate_actor_documentation(
+    methods: &[ActorMethod],
+    events: &[Event],
+    struct_type: &syn::Type,
+) -> String {
     let mut doc = String::new();
 
     // Header
@@ -195,7 +1985,8 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
     doc.push_str("| Method | Parameters | Return Type |\n");
     doc.push_str("|--------|------------|-------------|\n");
 
-    for method in methods {
+    for actor_method in methods {
+        let method = &actor_method.method;
         let method_name = &method.sig.ident;
         let params = extract_method_params(method);
         let return_type = &method.sig.output;
@@ -205,7 +1996,7 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
         } else {
             params
                 .iter()
-                .map(|(name, ty)| format!("`{}`: `{}`", name, quote!(#ty)))
+                .map(|(name, ty)| actor_method.describe_param(name, ty))
                 .collect::<Vec<_>>()
                 .join(", ")
         };
@@ -222,7 +2013,25 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
     }
 
     // Detailed method documentation
-    for method in methods {
+    for actor_method in methods {
+        let ActorMethod {
+            method,
+            is_read,
+            is_exclusive,
+            max_concurrency,
+            is_blocking,
+            is_streaming,
+            is_get,
+            max_sizes,
+            flag,
+            accepts,
+            examples,
+            deprecation,
+            bindings: _,
+            sensitive: _,
+            sanitizers,
+            if_match,
+        } = actor_method;
         let method_name = &method.sig.ident;
         let method_name_str = method_name.to_string();
         let params = extract_method_params(method);
@@ -242,7 +2051,7 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
         } else {
             doc.push_str("- **Parameters:**\n");
             for (name, ty) in &params {
-                doc.push_str(&format!("  - `{}`: `{}`\n", name, quote!(#ty)));
+                doc.push_str(&format!("  - {}\n", actor_method.describe_param(name, ty)));
             }
             doc.push('\n');
         }
@@ -253,11 +2062,85 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
             syn::ReturnType::Type(_, ty) => format!("`{}`", quote!(#ty)),
         };
         doc.push_str(&format!("- **Returns:** {}\n\n", return_str));
+        if *is_read {
+            doc.push_str("- **Read-only:** this method does not change the actor's state\n\n");
+        }
+        if *is_exclusive {
+            doc.push_str("- **Exclusive:** no other call runs while this method does\n\n");
+        }
+        if let Some(max) = max_concurrency {
+            doc.push_str(&format!(
+                "- **Concurrency:** at most {} call(s) run at once\n\n",
+                max
+            ));
+        }
+        if *is_blocking {
+            doc.push_str("- **Blocking:** runs on the server's blocking pool\n\n");
+        }
+        if *is_streaming {
+            doc.push_str("- **Streaming:** the response is serialized as it is sent\n\n");
+        }
+        if *is_get {
+            doc.push_str(&format!(
+                "- **GET:** also served by `GET /{}` with its parameters in the query string\n\n",
+                method_name
+            ));
+        }
+
+        if let Some(flag) = flag {
+            doc.push_str(&format!(
+                "- **Feature flag:** only served while `{}` is enabled\n\n",
+                flag
+            ));
+        }
+        if !accepts.is_empty() {
+            let accepts: Vec<_> = accepts.iter().map(|t| format!("`{}`", t)).collect();
+            doc.push_str(&format!("- **Content-Type:** {}\n\n", accepts.join(", ")));
+        }
+        if let Some(Deprecation { note, sunset }) = deprecation {
+            doc.push_str("- **Deprecated:**");
+            if let Some(note) = note {
+                doc.push_str(&format!(" {}", note));
+            }
+            if let Some(sunset) = sunset {
+                doc.push_str(&format!(" (removed after {})", sunset));
+            }
+            doc.push_str("\n\n");
+        }
+        if let Some(name) = if_match {
+            doc.push_str(&format!(
+                "- **Precondition:** runs only if `{}` (or an `If-Match` header) names the current version\n\n",
+                name
+            ));
+        }
+        for (name, sanitizer) in sanitizers {
+            doc.push_str(&format!(
+                "- **Sanitized:** `{}` ({})\n\n",
+                name,
+                sanitizer.describe()
+            ));
+        }
+        for (name, max) in max_sizes {
+            doc.push_str(&format!(
+                "- **Size limit:** `{}` may hold at most {} bytes\n\n",
+                name, max
+            ));
+        }
+
+        // Declared examples take the place of placeholder values
+        let example = examples.first();
+        let pretty_params = example.map(|example| pretty_json(&example.params));
+        let params: Vec<_> = params
+            .into_iter()
+            .filter(|(name, _)| !actor_method.is_bound(name))
+            .collect();
 
         // JSON payload example
         doc.push_str("**JSON Payload:**\n");
         doc.push_str("```json\n");
-        if params.is_empty() {
+        if let Some(pretty) = &pretty_params {
+            doc.push_str(&format!("{}\n", pretty));
+        } else if params.is_empty() {
             doc.push_str("{}\n");
         } else {
             doc.push_str("{\n");
@@ -270,6 +2153,34 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
         }
         doc.push_str("```\n\n");
 
+        if *is_get {
+            let query_params = match example {
+                Some(example) => example.params.clone(),
+                None => serde_json::Value::Object(
+                    params
+                        .iter()
+                        .map(|(name, ty)| (name.to_string(), example_json(ty)))
+                        .collect(),
+                ),
+            };
+            let query = query_string(&query_params);
+            doc.push_str("**Query String:**\n");
+            doc.push_str("```text\n");
+            if query.is_empty() {
+                doc.push_str(&format!("GET /{}\n", method_name));
+            } else {
+                doc.push_str(&format!("GET /{}?{}\n", method_name, query));
+            }
+            doc.push_str("```\n\n");
+        }
+
+        if let Some(response) = example.and_then(|example| example.response.as_ref()) {
+            doc.push_str("**Example Response:**\n");
+            doc.push_str("```json\n");
+            doc.push_str(&format!("{}\n", pretty_json(response)));
+            doc.push_str("```\n\n");
+        }
+
         // WebSocket payload example
         doc.push_str("**WebSocket Payload:**\n");
         doc.push_str("For web socket usage, we must embed the method name in the request separately from the parameters");
@@ -282,7 +2193,9 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
             "{{\n  \"method\": \"{}\",\n  \"params\": ",
             method_name
         ));
-        if params.is_empty() {
+        if let Some(pretty) = &pretty_params {
+            doc.push_str(&format!("{}\n", pretty.replace('\n', "\n  ")));
+        } else if params.is_empty() {
             doc.push_str("{}\n");
         } else {
             doc.push_str("{\n");
@@ -299,7 +2212,16 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
         // Usage example
         doc.push_str("**Usage Example from Javascript:**\n");
         doc.push_str("```js\n");
-        if params.is_empty() {
+        if let Some(example) = example {
+            doc.push_str(&format!(
+                "result = await fetch(\"http://localhost:9000/{}\", {{\n",
+                method_name_str
+            ));
+            doc.push_str("  method: 'POST',\n");
+            doc.push_str("  headers: { 'Content-Type': 'application/json' },\n");
+            doc.push_str(&format!("  body: JSON.stringify({})\n", example.params));
+            doc.push_str("});\n");
+        } else if params.is_empty() {
             doc.push_str(&format!(
                 "result = await fetch(\"http://localhost:9000/{}\", {{\n",
                 method_name_str
@@ -345,9 +2267,126 @@ fn generate_actor_documentation(methods: &[&ImplItemFn], struct_type: &syn::Type
         doc.push_str("`application/x-www-form-urlencoded` by default.\n\n");
     }
 
+    if !events.is_empty() {
+        generate_events_documentation(&mut doc, events);
+    }
+
     doc
 }
 
+/// Describe the WebSocket subscription protocol and each declared event topic.
+fn generate_events_documentation(doc: &mut String, events: &[Event]) {
+    doc.push_str("---\n");
+    doc.push_str("# Subscriptions\n\n");
+    doc.push_str("Over a WebSocket connection, clients can subscribe to the events this actor ");
+    doc.push_str("publishes. Each subscribe and unsubscribe message is confirmed:\n\n");
+    doc.push_str("| Client sends | Server replies |\n");
+    doc.push_str("|--------------|----------------|\n");
+    doc.push_str("| `{\"subscribe\": \"<topic>\"}` | `{\"subscribed\": \"<topic>\"}` |\n");
+    doc.push_str("| `{\"unsubscribe\": \"<topic>\"}` | `{\"unsubscribed\": \"<topic>\"}` |\n\n");
+    doc.push_str("While subscribed, each event arrives as its own message, interleaved with ");
+    doc.push_str("responses to method calls on the same connection:\n");
+    doc.push_str("```json\n");
+    doc.push_str("{\"event\": \"<topic>\", \"data\": <payload>}\n");
+    doc.push_str("```\n\n");
+    doc.push_str("A client that falls too far behind is sent `{\"lagged\": N}`, where `N` is the ");
+    doc.push_str("number of events it missed. Events published while nobody is subscribed are ");
+    doc.push_str("not delivered later, unless the client subscribes with a subscriber id:\n");
+    doc.push_str("```json\n");
+    doc.push_str("{\"subscribe\": \"<topic>\", \"subscriber\": \"<id>\"}\n");
+    doc.push_str("```\n");
+    doc.push_str("Its events then carry a sequence number, `{\"event\": \"<topic>\", \"seq\": N, ");
+    doc.push_str("\"data\": <payload>}`, and are sent again when it subscribes under the same id ");
+    doc.push_str("until it acknowledges them with `{\"ack\": N}`. If the server logs events, a ");
+    doc.push_str("client can also catch up by subscribing with `\"since\": N`, the sequence ");
+    doc.push_str("number of the last event it saw.\n\n");
+
+    for Event { topic, payload } in events {
+        doc.push_str(&format!("## Event `{}`\n\n", topic));
+
+        doc.push_str("**Subscribe:**\n");
+        doc.push_str("```json\n");
+        doc.push_str(&format!("{{\"subscribe\": \"{}\"}}\n", topic));
+        doc.push_str("```\n\n");
+
+        doc.push_str("**Example Event:**\n");
+        doc.push_str("```json\n");
+        doc.push_str(&format!(
+            "{{\n  \"event\": \"{}\",\n  \"data\": {}\n}}\n",
+            topic,
+            pretty_json(payload).replace('\n', "\n  ")
+        ));
+        doc.push_str("```\n\n");
+
+        doc.push_str("**Usage Example from Javascript:**\n");
+        doc.push_str("```js\n");
+        doc.push_str("const ws = new WebSocket(\"ws://localhost:9000\");\n");
+        doc.push_str(&format!(
+            "ws.onopen = () => ws.send(JSON.stringify({{subscribe: \"{}\"}}));\n",
+            topic
+        ));
+        doc.push_str("ws.onmessage = (message) => {\n");
+        doc.push_str("  const frame = JSON.parse(message.data);\n");
+        doc.push_str(&format!("  if (frame.event === \"{}\") {{\n", topic));
+        doc.push_str("    console.log(frame.data);\n");
+        doc.push_str("  }\n");
+        doc.push_str("};\n");
+        doc.push_str("```\n\n");
+    }
+}
+
+/// Example parameters as a query string: arrays repeat their key, objects nest in brackets and
+/// arrays of objects are indexed, as `GET` methods read them.
+fn query_string(params: &serde_json::Value) -> String {
+    use serde_json::Value;
+
+    fn encode(text: &str) -> String {
+        text.bytes()
+            .map(|b| match b {
+                b' ' => "+".to_string(),
+                b if b.is_ascii_alphanumeric() || b"-._~[]".contains(&b) => (b as char).to_string(),
+                b => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    fn flatten(key: &str, value: &Value, pairs: &mut Vec<String>) {
+        match value {
+            Value::Null => {}
+            Value::String(text) => pairs.push(format!("{}={}", encode(key), encode(text))),
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    match value {
+                        Value::Array(_) | Value::Object(_) => {
+                            flatten(&format!("{}[{}]", key, i), value, pairs)
+                        }
+                        value => flatten(key, value, pairs),
+                    }
+                }
+            }
+            Value::Object(fields) => {
+                for (name, value) in fields {
+                    flatten(&format!("{}[{}]", key, name), value, pairs);
+                }
+            }
+            scalar => pairs.push(format!("{}={}", encode(key), scalar)),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    if let Value::Object(fields) = params {
+        for (name, value) in fields {
+            flatten(name, value, &mut pairs);
+        }
+    }
+    pairs.join("&")
+}
+
+/// `value` as indented JSON, for the generated documentation
+fn pretty_json(value: &serde_json::Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
 /// Extract documentation comments from a method
 #[allow(clippy::collapsible_if)] // Intentionally avoiding let-chains for MSRV compatibility (Rust 1.85)
 fn extract_method_doc(method: &ImplItemFn) -> Option<String> {
@@ -383,31 +2422,187 @@ fn extract_method_doc(method: &ImplItemFn) -> Option<String> {
 
 /// Generate example values for different types
 fn generate_example_value(ty: &Type) -> String {
-    let type_str = quote!(#ty).to_string();
-
-    match type_str.as_str() {
-        "i32" | "i64" | "i8" | "i16" | "isize" => "42".to_string(),
-        "u32" | "u64" | "u8" | "u16" | "usize" => "42".to_string(),
-        "f32" | "f64" => "3.14".to_string(),
-        "bool" => "true".to_string(),
-        "String" => "\"example\"".to_string(),
-        "char" => "'x'".to_string(),
-        s if s.starts_with("Option") => "null".to_string(),
-        s if s.starts_with("Vec") => "[]".to_string(),
-        s if s.contains("HashMap") || s.contains("BTreeMap") => "{}".to_string(),
-        _ => {
-            // For custom types, try to provide a reasonable default
-            if type_str.contains("String") {
-                "\"example\"".to_string()
-            } else if type_str.contains("i32") || type_str.contains("i64") {
-                "42".to_string()
-            } else if type_str.contains("f32") || type_str.contains("f64") {
-                "3.14".to_string()
-            } else if type_str.contains("bool") {
-                "true".to_string()
-            } else {
-                "\"value\"".to_string()
+    example_json(ty).to_string()
+}
+
+/// An example JSON value for `ty`, following the structure of the type: containers hold an
+/// example of their element type, `Option<T>` and smart pointers show `T`, and common library
+/// types (dates, UUIDs, addresses) get values in their serde format.  Types defined elsewhere
+/// can't be inspected by the macro and get a placeholder; use `#[example]` for those.
+fn example_json(ty: &Type) -> serde_json::Value {
+    use serde_json::{Value, json};
+
+    match ty {
+        Type::Reference(reference) => example_json(&reference.elem),
+        Type::Paren(paren) => example_json(&paren.elem),
+        Type::Group(group) => example_json(&group.elem),
+        Type::Array(array) => json!([example_json(&array.elem)]),
+        Type::Slice(slice) => json!([example_json(&slice.elem)]),
+        Type::Tuple(tuple) if tuple.elems.is_empty() => Value::Null,
+        Type::Tuple(tuple) => Value::Array(tuple.elems.iter().map(example_json).collect()),
+        Type::Path(path) => {
+            let Some(segment) = path.path.segments.last() else {
+                return json!("value");
+            };
+            let args: Vec<&Type> = match &segment.arguments {
+                syn::PathArguments::AngleBracketed(angle) => angle
+                    .args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        syn::GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let arg = |i: usize| args.get(i).map_or(json!("value"), |ty| example_json(ty));
+
+            match segment.ident.to_string().as_str() {
+                "i8" | "i16" | "i32" | "i64" | "i128" | "isize" => json!(42),
+                "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => json!(42),
+                "f32" | "f64" => json!(2.5),
+                "bool" => json!(true),
+                "String" | "str" => json!("example"),
+                "char" => json!("x"),
+                "Option" | "Box" | "Rc" | "Arc" | "Cow" | "Reverse" | "Wrapping" => arg(0),
+                "Vec" | "VecDeque" | "LinkedList" | "HashSet" | "BTreeSet" | "BinaryHeap"
+                | "IndexSet" => json!([arg(0)]),
+                "HashMap" | "BTreeMap" | "IndexMap" => json!({ "key": arg(1) }),
+                "Value" => json!({}),
+                "DateTime" => json!("2024-01-01T12:00:00Z"),
+                "NaiveDateTime" => json!("2024-01-01T12:00:00"),
+                "NaiveDate" => json!("2024-01-01"),
+                "NaiveTime" => json!("12:00:00"),
+                "Uuid" => json!("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+                "Base64" => json!("aGVsbG8="),
+                // rust_decimal serializes as a string to keep full precision
+                "Decimal" => json!("12.34"),
+                "Duration" => json!({ "secs": 1, "nanos": 0 }),
+                "IpAddr" | "Ipv4Addr" => json!("127.0.0.1"),
+                "Ipv6Addr" => json!("::1"),
+                "SocketAddr" => json!("127.0.0.1:8080"),
+                "PathBuf" | "Path" => json!("path/to/file"),
+                "Url" => json!("https://example.com/"),
+                _ => json!("value"),
             }
         }
+        _ => json!("value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(ty: &str) -> String {
+        generate_example_value(&syn::parse_str(ty).unwrap())
+    }
+
+    #[test]
+    fn test_example_values_follow_the_type() {
+        assert_eq!(example("u64"), "42");
+        assert_eq!(example("&str"), r#""example""#);
+        assert_eq!(example("Option<Vec<f64>>"), "[2.5]");
+        assert_eq!(
+            example("std::collections::HashMap<String, (i32, bool)>"),
+            r#"{"key":[42,true]}"#
+        );
+        assert_eq!(example("Arc<[char; 2]>"), r#"["x"]"#);
+        assert_eq!(
+            example("Vec<chrono::DateTime<chrono::Utc>>"),
+            r#"["2024-01-01T12:00:00Z"]"#
+        );
+        assert_eq!(example("rust_decimal::Decimal"), r#""12.34""#);
+        assert_eq!(example("Base64<Bytes>"), r#""aGVsbG8=""#);
+        assert_eq!(example("Option<Vec<MyStruct>>"), r#"["value"]"#);
+        assert_eq!(example("()"), "null");
+    }
+
+    #[test]
+    fn test_deprecation_keeps_a_standard_attribute() {
+        let mut method: ImplItemFn = syn::parse_quote! {
+            #[deprecated(note = "Use sum", sunset = "2025-12-31")]
+            pub async fn add(&self) {}
+        };
+        let deprecation = take_deprecation(&mut method.attrs).unwrap().unwrap();
+        assert_eq!(deprecation.note.as_deref(), Some("Use sum"));
+        assert_eq!(deprecation.sunset.as_deref(), Some("2025-12-31"));
+        let attr = &method.attrs[0];
+        assert_eq!(
+            quote!(#attr).to_string(),
+            quote!(#[deprecated(note = "Use sum",)]).to_string()
+        );
+
+        let mut method: ImplItemFn = syn::parse_quote! {
+            #[deprecated(sunset = "2025-13-01")]
+            pub async fn add(&self) {}
+        };
+        assert!(take_deprecation(&mut method.attrs).is_err());
+        assert!(is_date("2025-02-28"));
+        assert!(!is_date("2025-2-28"));
+    }
+
+    #[test]
+    fn test_enum_documentation_follows_serde_attributes() {
+        let doc = |source: &str| enum_documentation(&syn::parse_str(source).unwrap()).unwrap();
+
+        let colors = doc(r#"
+            #[serde(rename_all = "snake_case")]
+            enum Color {
+                DarkRed,
+                #[serde(rename = "GREEN", alias = "green")]
+                Green,
+                #[serde(skip)]
+                Unused,
+                Custom(String),
+            }
+        "#);
+        assert!(colors.contains("- `\"dark_red\"`\n"));
+        assert!(colors.contains("- `\"GREEN\"` (also named `\"green\"`)\n"));
+        assert!(!colors.contains("unused"));
+        assert!(colors.contains(r#"- `{"custom":"example"}`"#));
+
+        let shapes = doc(r#"
+            #[serde(tag = "kind", rename_all = "lowercase")]
+            enum Shape {
+                Circle { radius: f64 },
+                Empty,
+            }
+        "#);
+        assert!(shapes.contains(r#"- `{"kind":"circle","radius":2.5}`"#));
+        assert!(shapes.contains(r#"- `{"kind":"empty"}`"#));
+
+        let adjacent = doc(r#"
+            #[serde(tag = "t", content = "c")]
+            enum Message { Text(String) }
+        "#);
+        assert!(adjacent.contains(r#"- `{"c":"example","t":"Text"}`"#));
+    }
+
+    #[test]
+    fn test_query_strings_follow_the_params() {
+        let params = serde_json::json!({
+            "text": "red shoes",
+            "tags": ["sale", "new"],
+            "filter": {"min": 10, "exact": true},
+            "rows": [{"id": "a&b"}],
+            "page": null,
+        });
+        assert_eq!(
+            query_string(&params),
+            "filter[exact]=true&filter[min]=10&rows[0][id]=a%26b&tags=sale&tags=new&text=red+shoes"
+        );
+    }
+
+    #[test]
+    fn test_rename_variant() {
+        assert_eq!(rename_variant("DarkRed", None), "DarkRed");
+        assert_eq!(rename_variant("DarkRed", Some("snake_case")), "dark_red");
+        assert_eq!(rename_variant("DarkRed", Some("camelCase")), "darkRed");
+        assert_eq!(
+            rename_variant("DarkRed", Some("SCREAMING-KEBAB-CASE")),
+            "DARK-RED"
+        );
+        assert_eq!(rename_variant("DarkRed", Some("lowercase")), "darkred");
     }
 }
@@ -0,0 +1,26 @@
+//! The demo actor, shared by the demo server and `cargo xtask js-client`.
+
+use simple_json_server::{Actor, actor};
+
+/// A simple actor to demonstrate the move semantics
+#[derive(Debug, Clone)]
+pub struct SimpleServerDemo {
+    pub id: String,
+}
+
+impl SimpleServerDemo {
+    pub fn new(id: String) -> Self {
+        Self { id }
+    }
+}
+
+#[actor]
+impl SimpleServerDemo {
+    pub async fn get_id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub async fn greet(&self, name: String) -> String {
+        format!("Hello {}, I'm {}", name, self.id)
+    }
+}
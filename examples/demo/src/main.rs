@@ -1,27 +1,5 @@
-use simple_json_server::{Actor, actor};
-
-/// A simple actor to demonstrate the move semantics
-#[derive(Debug, Clone)]
-pub struct SimpleServerDemo {
-    pub id: String,
-}
-
-impl SimpleServerDemo {
-    pub fn new(id: String) -> Self {
-        Self { id }
-    }
-}
-
-#[actor]
-impl SimpleServerDemo {
-    pub async fn get_id(&self) -> String {
-        self.id.clone()
-    }
-
-    pub async fn greet(&self, name: String) -> String {
-        format!("Hello {}, I'm {}", name, self.id)
-    }
-}
+use simple_json_server::{Actor, ServerOptions, startup};
+use simple_json_server_demo::SimpleServerDemo;
 
 #[tokio::main]
 async fn main() {
@@ -36,21 +14,14 @@ async fn main() {
     // let result = actor.dispatch("get_id", "{}");
     // println!("Dispatch result: {}", result);
 
-    println!("Starting server on port 9000...");
-    println!("This server supports both HTTP and WebSocket connections.");
-    println!();
-
-    // This consumes the actor - after this line, `actor` can no longer be used
-    actor.create(9000); // Start HTTP server
+    // This consumes the actor - after this line, `actor` can no longer be used.  Once the
+    // server is listening it prints a JSON line with its addresses, transports and methods.
+    actor.create_with(ServerOptions::new(9000).on_startup(startup::print_json)); // Start HTTP server
 
     // The following line would cause a compile error because `actor` has been moved:
     // println!("Actor ID after move: {}", actor.id); // ❌ Compile error!
 
-    println!("✅ Server started successfully!");
-    println!();
-    println!("📡 Available Interfaces:");
-    println!("  HTTP:      http://127.0.0.1:9000");
-    println!("  WebSocket: ws://127.0.0.1:9000");
+    println!("This server supports both HTTP and WebSocket connections.");
     println!();
     println!("🧪 Test with curl:");
     println!("  curl -X POST http://127.0.0.1:9000/get_id -d '{{}}'");
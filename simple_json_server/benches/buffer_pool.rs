@@ -0,0 +1,79 @@
+//! Compares allocator traffic serving large request bodies with and without a buffer pool.
+//!
+//! Run with `cargo bench --bench buffer_pool`.  The counts include the client's allocations,
+//! which are the same in both runs, so the difference is the server's.
+
+mod common;
+
+use simple_json_server::buffers::BufferPool;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: common::Counting = common::Counting;
+
+#[derive(Debug, Clone)]
+pub struct Ingest;
+
+#[actor]
+impl Ingest {
+    /// Accept a document
+    pub async fn store(&self, document: String) -> usize {
+        document.len()
+    }
+}
+
+const REQUESTS: usize = 2_000;
+const CONCURRENCY: usize = 64;
+const BODY_BYTES: usize = 256 * 1024;
+
+async fn run(name: &str, options: ServerOptions) {
+    let server = Ingest.start(options);
+    let port = server.listening().await[0].port();
+    let client = reqwest::Client::new();
+    let url = format!("http://127.0.0.1:{port}/store");
+    let body = format!(r#"{{"document": "{}"}}"#, "x".repeat(BODY_BYTES));
+
+    let send = |client: reqwest::Client, url: String, body: String| async move {
+        let response = client.post(url).body(body).send().await.unwrap();
+        assert!(response.status().is_success());
+    };
+    // Warm up connections (and the pool) before counting
+    futures_util::future::join_all(
+        (0..CONCURRENCY).map(|_| send(client.clone(), url.clone(), body.clone())),
+    )
+    .await;
+
+    let (allocations, bytes) = common::allocations();
+    let started = Instant::now();
+    for _ in 0..REQUESTS / CONCURRENCY {
+        futures_util::future::join_all(
+            (0..CONCURRENCY).map(|_| send(client.clone(), url.clone(), body.clone())),
+        )
+        .await;
+    }
+    let elapsed = started.elapsed();
+    let calls = (REQUESTS / CONCURRENCY * CONCURRENCY) as u64;
+    let (allocations_after, bytes_after) = common::allocations();
+    let (allocations, bytes) = (allocations_after - allocations, bytes_after - bytes);
+    println!(
+        "{:<12} {:>8.1} allocations/call {:>10} bytes allocated/call {:>8.0} calls/s",
+        name,
+        allocations as f64 / calls as f64,
+        bytes / calls,
+        calls as f64 / elapsed.as_secs_f64()
+    );
+    server.drain().await;
+}
+
+#[tokio::main]
+async fn main() {
+    run("unpooled", ServerOptions::new(0)).await;
+    let pool = BufferPool::new(CONCURRENCY);
+    run("pooled", ServerOptions::new(0).buffer_pool(pool.clone())).await;
+    println!(
+        "pool: {} buffers reused, {} allocated",
+        pool.reused(),
+        pool.allocated()
+    );
+}
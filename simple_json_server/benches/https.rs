@@ -0,0 +1,90 @@
+//! Throughput and allocator traffic of the HTTPS path, for small and large responses.
+//!
+//! Run with `cargo bench --bench https`.  The counts include the client's allocations.
+
+mod common;
+
+use simple_json_server::security::SecurityPreset;
+use simple_json_server::{actor, Actor, ServerOptions, TlsConfig};
+use std::time::Instant;
+
+#[global_allocator]
+static GLOBAL: common::Counting = common::Counting;
+
+#[derive(Debug, Clone)]
+pub struct Catalog;
+
+#[actor]
+impl Catalog {
+    /// A small response
+    pub async fn price(&self, sku: u32) -> f64 {
+        sku as f64 * 1.5
+    }
+
+    /// A large response
+    pub async fn page(&self, size: usize) -> Vec<String> {
+        (0..size).map(|n| format!("item-{n:08}")).collect()
+    }
+}
+
+const CONCURRENCY: usize = 64;
+
+async fn run(name: &str, port: u16, method: &str, body: &str, requests: usize) {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    let url = format!("https://127.0.0.1:{port}/{method}");
+    let send = |client: reqwest::Client, url: String, body: String| async move {
+        let response = client.post(url).body(body).send().await.unwrap();
+        assert!(response.status().is_success());
+        response.bytes().await.unwrap();
+    };
+    let batch = || {
+        futures_util::future::join_all(
+            (0..CONCURRENCY).map(|_| send(client.clone(), url.clone(), body.to_string())),
+        )
+    };
+    // Warm up the TLS sessions before counting
+    batch().await;
+
+    let (allocations, bytes) = common::allocations();
+    let started = Instant::now();
+    for _ in 0..requests / CONCURRENCY {
+        batch().await;
+    }
+    let elapsed = started.elapsed();
+    let (allocations_after, bytes_after) = common::allocations();
+    let calls = (requests / CONCURRENCY * CONCURRENCY) as u64;
+    println!(
+        "{:<8} {:>8.1} allocations/call {:>9} bytes allocated/call {:>8.0} calls/s",
+        name,
+        (allocations_after - allocations) as f64 / calls as f64,
+        (bytes_after - bytes) / calls,
+        calls as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join("simple_json_server_bench_cert.pem");
+    let key_path = dir.join("simple_json_server_bench_key.pem");
+    std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    let tls = TlsConfig::new(
+        cert_path.to_string_lossy().to_string(),
+        key_path.to_string_lossy().to_string(),
+    );
+
+    let server = Catalog.start(
+        ServerOptions::new(0)
+            .tls(tls)
+            .security(SecurityPreset::strict()),
+    );
+    let port = server.listening().await[0].port();
+    run("small", port, "price", r#"{"sku": 7}"#, 20_000).await;
+    run("large", port, "page", r#"{"size": 4000}"#, 4_000).await;
+    server.drain().await;
+}
@@ -0,0 +1,420 @@
+//! Operational endpoints served under `/__admin/`, separate from the actor's own methods.
+
+use crate::handle::{Drain, Lifecycle, Listener};
+use crate::privacy::SubjectError;
+use crate::secrets::Secret;
+use crate::server::{Reply, ServerState};
+use crate::snapshot::Snapshot;
+use crate::{Actor, ServerHandle};
+use hyper::header::AUTHORIZATION;
+use hyper::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Path prefix of the admin API.
+pub(crate) const PREFIX: &str = "/__admin/";
+
+/// Serve the admin operation `op` if the request carries the admin token.
+pub(crate) async fn handle<T>(
+    state: &ServerState<T>,
+    method: &str,
+    op: &str,
+    headers: &HeaderMap,
+    body: &str,
+) -> Reply
+where
+    T: Actor + Send + Sync + 'static,
+{
+    if let Err(reply) = authorize(state, headers) {
+        return reply;
+    }
+
+    match (op, method) {
+        ("snapshot", "POST") => snapshot(state).await,
+        ("restore", "POST") => restore(state).await,
+        ("drain", "POST") => drain(state, body),
+        ("disable", "POST") => disable(state, body),
+        ("enable", "POST") => enable(state, body),
+        ("export_subject", "POST") => export_subject(state, body).await,
+        ("delete_subject", "POST") => delete_subject(state, body).await,
+        ("disabled", _) => {
+            let server = ServerHandle::from_lifecycle(Arc::clone(&state.lifecycle));
+            Reply::ok(serde_json::to_string(&server.disabled_methods()).unwrap_or_default())
+        }
+        ("stats", _) => {
+            Reply::ok(serde_json::to_string(&state.options.stats.methods()).unwrap_or_default())
+        }
+        ("usage", _) => match &state.options.quotas {
+            Some(quotas) => {
+                Reply::ok(serde_json::to_string(&quotas.all_usage()).unwrap_or_default())
+            }
+            None => Reply::error(
+                StatusCode::NOT_FOUND,
+                "No quotas are configured".to_string(),
+            ),
+        },
+        ("exemplars", _) => match &state.options.sampler {
+            Some(sampler) => {
+                Reply::ok(serde_json::to_string(&sampler.exemplars()).unwrap_or_default())
+            }
+            None => Reply::error(
+                StatusCode::NOT_FOUND,
+                "Payload sampling is not configured".to_string(),
+            ),
+        },
+        ("memory", _) => match &state.options.memory {
+            Some(memory) => Reply::ok(serde_json::to_string(&memory.usage()).unwrap_or_default()),
+            None => Reply::error(
+                StatusCode::NOT_FOUND,
+                "Memory accounting is not configured".to_string(),
+            ),
+        },
+        ("blocking", _) => match &state.blocking {
+            Some(pool) => Reply::ok(serde_json::to_string(&pool.stats()).unwrap_or_default()),
+            None => Reply::error(
+                StatusCode::NOT_FOUND,
+                "The actor has no blocking methods".to_string(),
+            ),
+        },
+        ("metrics", _) => {
+            let labels = match &state.options.kubernetes {
+                Some(kubernetes) => kubernetes.labels(),
+                None => Vec::new(),
+            };
+            let stats = &state.options.stats;
+            let mut metrics = stats.prometheus_labeled(&labels);
+            if let Some(memory) = &state.options.memory {
+                let usage = memory.usage();
+                let mut accounts = vec![
+                    ("mailbox".to_string(), usage.mailbox),
+                    ("send_queues".to_string(), usage.send_queues),
+                ];
+                accounts.extend(usage.gauges);
+                metrics.push_str(&stats.gauge("memory_bytes", "account", &accounts, &labels));
+            }
+            let server = ServerHandle::from_lifecycle(Arc::clone(&state.lifecycle));
+            let measures = [
+                ("connections".to_string(), server.connections() as u64),
+                ("tasks".to_string(), server.tasks() as u64),
+            ];
+            metrics.push_str(&stats.gauge("server", "measure", &measures, &labels));
+            if let Some(pool) = &state.blocking {
+                let pool = pool.stats();
+                let measures = [
+                    ("threads".to_string(), pool.threads as u64),
+                    ("busy".to_string(), pool.busy as u64),
+                    ("queued".to_string(), pool.queued as u64),
+                ];
+                metrics.push_str(&stats.gauge("blocking_pool", "measure", &measures, &labels));
+            }
+            Reply {
+                content_type: "text/plain; version=0.0.4",
+                ..Reply::ok(metrics)
+            }
+        }
+        (
+            "snapshot" | "restore" | "drain" | "disable" | "enable" | "export_subject"
+            | "delete_subject",
+            _,
+        ) => Reply::error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("Use POST for {}", op),
+        ),
+        _ => Reply::error(
+            StatusCode::NOT_FOUND,
+            format!("Unknown admin operation: {}", op),
+        ),
+    }
+}
+
+/// Check that the request carries the admin token, or the reply refusing it.
+pub(crate) fn authorize<T>(state: &ServerState<T>, headers: &HeaderMap) -> Result<(), Reply> {
+    let Some(token) = &state.options.admin_token else {
+        return Err(Reply::error(
+            StatusCode::NOT_FOUND,
+            "The admin API is disabled".to_string(),
+        ));
+    };
+    let presented = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(presented.as_bytes(), token.read().unwrap().as_bytes()) {
+        return Err(Reply::error(
+            StatusCode::UNAUTHORIZED,
+            "Invalid admin token".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fetch the admin token from `secret`, and keep it up to date as the secret refreshes while
+/// the server runs.
+pub(crate) async fn fetch_token(
+    secret: Secret,
+    lifecycle: &Arc<Lifecycle>,
+) -> Result<Arc<RwLock<String>>, String> {
+    let value = secret
+        .fetch()
+        .await
+        .map_err(|e| format!("Failed to fetch the admin token: {}", e))?;
+    let token = Arc::new(RwLock::new(token_from(&value)?));
+    let current = Arc::clone(&token);
+    lifecycle.spawn_background(async move {
+        secret
+            .watch_from(value, |value| {
+                *current.write().unwrap() = token_from(value)?;
+                Ok(())
+            })
+            .await
+    });
+    Ok(token)
+}
+
+fn token_from(value: &[u8]) -> Result<String, String> {
+    match std::str::from_utf8(value) {
+        Ok("") => Err("The admin token is empty".to_string()),
+        Ok(token) => Ok(token.to_string()),
+        Err(_) => Err("The admin token is not UTF-8".to_string()),
+    }
+}
+
+/// Pause the mailbox, capture the actor's state, and write it to the snapshot store.  The
+/// journal entries the snapshot includes are then dropped.
+async fn snapshot<T: Actor>(state: &ServerState<T>) -> Reply {
+    let Some(store) = &state.options.snapshot_store else {
+        return Reply::error(
+            StatusCode::BAD_REQUEST,
+            "No snapshot store is configured".to_string(),
+        );
+    };
+
+    let journal = state.options.journal.as_ref();
+    let (captured, journal_seq) = {
+        let _paused = state.mailbox.write().await;
+        let journal_seq = journal.map_or(0, |journal| journal.last_seq());
+        (state.actor.snapshot_state(), journal_seq)
+    };
+    let snapshot = match captured {
+        Ok(data) => Snapshot::now(data, journal_seq),
+        Err(e) => return Reply::error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    match store.0.save(&snapshot).await {
+        Ok(()) => {
+            log::info!("Wrote snapshot taken at {}", snapshot.taken_at_ms);
+            if let Some(journal) = journal {
+                journal.truncate_before(journal_seq + 1);
+            }
+            Reply::ok(
+                json!({
+                    "taken_at_ms": snapshot.taken_at_ms,
+                    "bytes": snapshot.state.len(),
+                })
+                .to_string(),
+            )
+        }
+        Err(e) => Reply::error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to write snapshot: {}", e),
+        ),
+    }
+}
+
+/// Replace the actor's state with the latest snapshot in the store.
+async fn restore<T: Actor>(state: &ServerState<T>) -> Reply {
+    let Some(store) = &state.options.snapshot_store else {
+        return Reply::error(
+            StatusCode::BAD_REQUEST,
+            "No snapshot store is configured".to_string(),
+        );
+    };
+
+    let snapshot = match store.0.latest().await {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => {
+            return Reply::error(StatusCode::NOT_FOUND, "No snapshot to restore".to_string())
+        }
+        Err(e) => {
+            return Reply::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load snapshot: {}", e),
+            )
+        }
+    };
+
+    let _paused = state.mailbox.write().await;
+    match state.actor.restore_state(&snapshot.state) {
+        Ok(()) => {
+            log::info!("Restored snapshot taken at {}", snapshot.taken_at_ms);
+            Reply::ok(json!({ "taken_at_ms": snapshot.taken_at_ms }).to_string())
+        }
+        Err(e) => Reply::error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// The body of a data-subject request.
+#[derive(Debug, Deserialize)]
+struct SubjectRequest {
+    subject_id: String,
+}
+
+fn subject_request(body: &str) -> Result<String, Reply> {
+    let request: SubjectRequest = serde_json::from_str(body)
+        .map_err(|e| Reply::error(StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    if request.subject_id.is_empty() {
+        return Err(Reply::error(
+            StatusCode::BAD_REQUEST,
+            "The subject id is empty".to_string(),
+        ));
+    }
+    Ok(request.subject_id)
+}
+
+/// The reply to a data-subject request the actor couldn't fulfil.
+fn subject_failed(action: &str, error: SubjectError) -> Reply {
+    match error {
+        SubjectError::Unsupported => Reply::error(StatusCode::NOT_IMPLEMENTED, error.to_string()),
+        SubjectError::Failed(e) => Reply::error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to {} the subject's data: {}", action, e),
+        ),
+    }
+}
+
+/// Collect what the actor holds about a data subject.
+async fn export_subject<T: Actor>(state: &ServerState<T>, body: &str) -> Reply {
+    let subject_id = match subject_request(body) {
+        Ok(subject_id) => subject_id,
+        Err(reply) => return reply,
+    };
+    let exported = {
+        let _shared = state.mailbox.read().await;
+        state.actor.export_subject_data(&subject_id).await
+    };
+    let data = match exported {
+        Ok(data) => data,
+        Err(e) => return subject_failed("export", e),
+    };
+    let data: serde_json::Value = serde_json::from_str(&data).unwrap_or_default();
+    log::info!("Exported a data subject's data through the admin API");
+    Reply::ok(json!({ "subject_id": subject_id, "data": data }).to_string())
+}
+
+/// Erase what the actor holds about a data subject, with no calls running.
+async fn delete_subject<T: Actor>(state: &ServerState<T>, body: &str) -> Reply {
+    let subject_id = match subject_request(body) {
+        Ok(subject_id) => subject_id,
+        Err(reply) => return reply,
+    };
+    let deleted = {
+        let _paused = state.mailbox.write().await;
+        state.actor.delete_subject_data(&subject_id).await
+    };
+    match deleted {
+        Ok(()) => {
+            log::info!("Deleted a data subject's data through the admin API");
+            Reply::ok(json!({ "subject_id": subject_id, "deleted": true }).to_string())
+        }
+        Err(e) => subject_failed("delete", e),
+    }
+}
+
+/// The body of a drain request; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DrainRequest {
+    listeners: Vec<Listener>,
+    deadline_ms: Option<u64>,
+}
+
+/// Start draining the server in the background: the request's own connection is one of those
+/// being drained, so it can't wait for them to close.
+fn drain<T>(state: &ServerState<T>, body: &str) -> Reply {
+    let request: DrainRequest = match body.trim() {
+        "" => DrainRequest::default(),
+        body => match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => {
+                return Reply::error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid drain request: {}", e),
+                )
+            }
+        },
+    };
+    let listeners = match request.listeners.is_empty() {
+        true => vec![Listener::Tcp, Listener::Http3],
+        false => request.listeners,
+    };
+    let mut drain = Drain::new();
+    for &listener in &listeners {
+        drain = drain.listener(listener);
+    }
+    if let Some(deadline_ms) = request.deadline_ms {
+        drain = drain.deadline(Duration::from_millis(deadline_ms));
+    }
+    log::info!("Draining requested through the admin API");
+    let server = ServerHandle::from_lifecycle(Arc::clone(&state.lifecycle));
+    tokio::spawn(async move { server.drain_with(drain).await });
+    Reply {
+        status: StatusCode::ACCEPTED,
+        ..Reply::ok(json!({ "draining": listeners }).to_string())
+    }
+}
+
+/// The body of a disable or enable request.
+#[derive(Debug, Deserialize)]
+struct SwitchRequest {
+    method: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+fn switch_request<T>(state: &ServerState<T>, body: &str) -> Result<SwitchRequest, Reply>
+where
+    T: Actor,
+{
+    let request: SwitchRequest = serde_json::from_str(body)
+        .map_err(|e| Reply::error(StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
+    if state.method_info(&request.method).is_none() {
+        return Err(Reply::error(
+            StatusCode::NOT_FOUND,
+            format!("Unknown method: {}", request.method),
+        ));
+    }
+    Ok(request)
+}
+
+/// Answer calls to a method with `503` until it is enabled again.
+fn disable<T: Actor>(state: &ServerState<T>, body: &str) -> Reply {
+    let request = match switch_request(state, body) {
+        Ok(request) => request,
+        Err(reply) => return reply,
+    };
+    let reason = request
+        .reason
+        .unwrap_or_else(|| "disabled by an operator".to_string());
+    let server = ServerHandle::from_lifecycle(Arc::clone(&state.lifecycle));
+    server.disable_method(&request.method, &reason);
+    Reply::ok(json!({ "disabled": request.method, "reason": reason }).to_string())
+}
+
+/// Serve a disabled method again.
+fn enable<T: Actor>(state: &ServerState<T>, body: &str) -> Reply {
+    let request = match switch_request(state, body) {
+        Ok(request) => request,
+        Err(reply) => return reply,
+    };
+    let server = ServerHandle::from_lifecycle(Arc::clone(&state.lifecycle));
+    let was_disabled = server.enable_method(&request.method);
+    Reply::ok(json!({ "enabled": request.method, "was_disabled": was_disabled }).to_string())
+}
+
+/// Compare without leaking how long the matching prefix is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,412 @@
+//! Bounded outbound queues for WebSocket connections, and what to do with slow consumers.
+//!
+//! Every WebSocket connection writes through a queue of at most [`SendQueue::new`]'s capacity
+//! frames, so a client that stops reading (a stalled browser tab, say) holds a bounded amount of
+//! server memory however many events are published.  When the queue is full, the
+//! [`SlowConsumer`] policy decides what gives:
+//!
+//! - [`SlowConsumer::Disconnect`] (the default) closes the connection; the client reconnects
+//!   and, with an acknowledging subscription or a replay (see [`crate::events`]), catches up.
+//! - [`SlowConsumer::DropOldest`] drops the oldest queued event.
+//! - [`SlowConsumer::CoalesceLatest`] keeps only the latest queued event of each topic, for
+//!   events that carry a whole state rather than a change to it.
+//!
+//! Replies to calls are never dropped or coalesced: when the queue holds nothing but replies,
+//! the connection is closed whatever the policy.
+//!
+//! A queue can also be limited in bytes with [`SendQueue::max_bytes`], for events of very
+//! different sizes; the policy applies in the same way once the limit would be exceeded.
+//!
+//! ```rust
+//! use simple_json_server::backpressure::{SendQueue, SlowConsumer};
+//! use simple_json_server::ServerOptions;
+//!
+//! let queue = SendQueue::new(256).policy(SlowConsumer::CoalesceLatest);
+//! let options = ServerOptions::new(8080).websocket(true).send_queue(queue.clone());
+//!
+//! // Later, e.g. from a metrics exporter
+//! println!("dropped {}, coalesced {}", queue.dropped(), queue.coalesced());
+//! ```
+
+use crate::memory::{ConnectionAccount, MemoryBudget};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+/// What to do when a connection's send queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlowConsumer {
+    /// Close the connection.
+    #[default]
+    Disconnect,
+    /// Drop the oldest queued event.
+    DropOldest,
+    /// Replace a queued event with a newer one of the same topic, and otherwise drop the oldest
+    /// queued event.
+    CoalesceLatest,
+}
+
+/// Send queue settings, plus the counters they are applied to.  Clones share the counters, so
+/// keep one to read the metrics of a running server.
+#[derive(Debug, Clone)]
+pub struct SendQueue {
+    capacity: usize,
+    max_bytes: Option<usize>,
+    policy: SlowConsumer,
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl SendQueue {
+    /// Queue at most `capacity` frames per connection, disconnecting slow consumers.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            max_bytes: None,
+            policy: SlowConsumer::default(),
+            stats: Arc::default(),
+        }
+    }
+
+    /// What to do when a queue is full (default [`SlowConsumer::Disconnect`]).
+    pub fn policy(mut self, policy: SlowConsumer) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Also queue at most `bytes` of frames per connection.  A single frame larger than this is
+    /// still queued if nothing else is.
+    pub fn max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// How many events have been dropped from full queues.
+    pub fn dropped(&self) -> u64 {
+        self.stats.dropped.load(Ordering::Relaxed)
+    }
+
+    /// How many queued events have been replaced by newer ones of the same topic.
+    pub fn coalesced(&self) -> u64 {
+        self.stats.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// How many connections have been closed for not keeping up.
+    pub fn disconnected(&self) -> u64 {
+        self.stats.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+/// The frames waiting to be written to one connection.
+pub(crate) struct Outbox {
+    settings: SendQueue,
+    queue: Mutex<Queue>,
+    ready: Notify,
+    memory: Option<ConnectionAccount>,
+}
+
+#[derive(Default)]
+struct Queue {
+    frames: VecDeque<Frame>,
+    /// The length of the queued frames.
+    bytes: usize,
+    /// No more frames are accepted; the writer stops once the queue is empty.
+    closed: bool,
+    /// Closed because the client did not keep up.
+    overflowed: bool,
+}
+
+struct Frame {
+    /// The topic of an event; replies have none.
+    topic: Option<String>,
+    message: Message,
+}
+
+impl Queue {
+    fn push(&mut self, frame: Frame) {
+        self.bytes += frame.message.len();
+        self.frames.push_back(frame);
+    }
+
+    fn remove(&mut self, index: usize) {
+        if let Some(frame) = self.frames.remove(index) {
+            self.bytes -= frame.message.len();
+        }
+    }
+
+    fn pop(&mut self) -> Option<Frame> {
+        let frame = self.frames.pop_front()?;
+        self.bytes -= frame.message.len();
+        Some(frame)
+    }
+
+    /// Whether a frame of `len` bytes can't be queued without dropping another.
+    fn is_full(&self, settings: &SendQueue, len: usize) -> bool {
+        !self.frames.is_empty()
+            && (self.frames.len() >= settings.capacity
+                || settings.max_bytes.is_some_and(|max| self.bytes + len > max))
+    }
+}
+
+impl Outbox {
+    pub(crate) fn new(settings: SendQueue) -> Self {
+        Self {
+            settings,
+            queue: Mutex::default(),
+            ready: Notify::new(),
+            memory: None,
+        }
+    }
+
+    /// Count the queued frames against `memory`.
+    pub(crate) fn with_memory(mut self, memory: Option<&MemoryBudget>) -> Self {
+        self.memory = memory.map(MemoryBudget::connection);
+        self
+    }
+
+    fn account(&self, queue: &Queue) {
+        if let Some(memory) = &self.memory {
+            memory.set(queue.bytes);
+        }
+    }
+
+    /// Queue a reply to a call.  Returns false if the connection should be closed.
+    pub(crate) fn reply(&self, text: String) -> bool {
+        self.push(None, text)
+    }
+
+    /// Queue an event of `topic`.  Returns false if the connection should be closed.
+    pub(crate) fn event(&self, topic: &str, text: String) -> bool {
+        self.push(Some(topic), text)
+    }
+
+    /// Queue events resent or replayed on subscription, however full the queue is: the event
+    /// bus already bounds how many there are, and they are never dropped.
+    pub(crate) fn resend(&self, frames: Vec<String>) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return;
+        }
+        for text in frames {
+            queue.push(Frame {
+                topic: None,
+                message: Message::Text(text),
+            });
+        }
+        self.account(&queue);
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    fn push(&self, topic: Option<&str>, text: String) -> bool {
+        let stats = &self.settings.stats;
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed {
+            return false;
+        }
+        if let (Some(topic), SlowConsumer::CoalesceLatest) = (topic, self.settings.policy) {
+            if let Some(queued) = queue
+                .frames
+                .iter()
+                .position(|f| f.topic.as_deref() == Some(topic))
+            {
+                queue.remove(queued);
+                stats.coalesced.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        while queue.is_full(&self.settings, text.len()) {
+            let oldest_event = match self.settings.policy {
+                SlowConsumer::Disconnect => None,
+                SlowConsumer::DropOldest | SlowConsumer::CoalesceLatest => {
+                    queue.frames.iter().position(|f| f.topic.is_some())
+                }
+            };
+            match oldest_event {
+                Some(oldest) => {
+                    queue.remove(oldest);
+                    stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                None => {
+                    stats.disconnected.fetch_add(1, Ordering::Relaxed);
+                    queue.closed = true;
+                    queue.overflowed = true;
+                    self.account(&queue);
+                    return false;
+                }
+            }
+        }
+        queue.push(Frame {
+            topic: topic.map(str::to_string),
+            message: Message::Text(text),
+        });
+        self.account(&queue);
+        drop(queue);
+        self.ready.notify_one();
+        true
+    }
+
+    /// Accept no more frames, and end with `close` (if any) once those queued are written.
+    pub(crate) fn close(&self, close: Option<Message>) {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.closed {
+            queue.closed = true;
+            if let Some(message) = close {
+                queue.push(Frame {
+                    topic: None,
+                    message,
+                });
+            }
+            self.account(&queue);
+        }
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    /// Whether the connection was closed because the client did not keep up.
+    pub(crate) fn overflowed(&self) -> bool {
+        self.queue.lock().unwrap().overflowed
+    }
+
+    /// Accept no more frames and forget those queued, once the connection is gone.
+    pub(crate) fn abandon(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.closed = true;
+        queue.frames.clear();
+        queue.bytes = 0;
+        self.account(&queue);
+        drop(queue);
+        self.ready.notify_one();
+    }
+
+    /// The next frame to write, or `None` once the outbox is closed and empty.
+    pub(crate) async fn next(&self) -> Option<Message> {
+        loop {
+            let ready = self.ready.notified();
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if let Some(frame) = queue.pop() {
+                    self.account(&queue);
+                    return Some(frame.message);
+                }
+                if queue.closed {
+                    return None;
+                }
+            }
+            ready.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(outbox: &Outbox) -> Vec<String> {
+        let mut queue = outbox.queue.lock().unwrap();
+        queue.bytes = 0;
+        queue
+            .frames
+            .drain(..)
+            .map(|frame| frame.message.into_text().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_disconnect_when_full() {
+        let settings = SendQueue::new(2);
+        let outbox = Outbox::new(settings.clone());
+        assert!(outbox.reply("1".to_string()));
+        assert!(outbox.event("price", "2".to_string()));
+        assert!(!outbox.event("price", "3".to_string()));
+        assert!(!outbox.reply("4".to_string()));
+        assert_eq!(settings.disconnected(), 1);
+        assert!(outbox.overflowed());
+    }
+
+    #[test]
+    fn test_drop_oldest_event() {
+        let settings = SendQueue::new(2).policy(SlowConsumer::DropOldest);
+        let outbox = Outbox::new(settings.clone());
+        assert!(outbox.reply("1".to_string()));
+        assert!(outbox.event("price", "2".to_string()));
+        assert!(outbox.event("price", "3".to_string()));
+        assert_eq!(settings.dropped(), 1);
+        assert_eq!(texts(&outbox), ["1", "3"]);
+
+        // Replies are never dropped
+        assert!(outbox.reply("4".to_string()));
+        assert!(outbox.reply("5".to_string()));
+        assert!(!outbox.event("price", "6".to_string()));
+        assert_eq!(settings.disconnected(), 1);
+    }
+
+    #[test]
+    fn test_coalesce_latest_per_topic() {
+        let settings = SendQueue::new(3).policy(SlowConsumer::CoalesceLatest);
+        let outbox = Outbox::new(settings.clone());
+        for (topic, text) in [
+            ("price", "1"),
+            ("halt", "2"),
+            ("price", "3"),
+            ("price", "4"),
+        ] {
+            assert!(outbox.event(topic, text.to_string()));
+        }
+        assert_eq!(settings.coalesced(), 2);
+        assert_eq!(texts(&outbox), ["2", "4"]);
+    }
+
+    #[test]
+    fn test_resent_events_bypass_the_limit() {
+        let outbox = Outbox::new(SendQueue::new(1));
+        assert!(outbox.reply("1".to_string()));
+        outbox.resend(vec!["2".to_string(), "3".to_string()]);
+        assert_eq!(texts(&outbox), ["1", "2", "3"]);
+        assert!(!outbox.overflowed());
+    }
+
+    #[test]
+    fn test_byte_limit_and_accounting() {
+        let settings = SendQueue::new(100)
+            .max_bytes(10)
+            .policy(SlowConsumer::DropOldest);
+        let memory = MemoryBudget::new();
+        let outbox = Outbox::new(settings.clone()).with_memory(Some(&memory));
+        // Queued whatever its size, as nothing else is
+        assert!(outbox.event("price", "x".repeat(20)));
+        assert_eq!(memory.usage().send_queues, 20);
+        assert!(outbox.event("price", "1234".to_string()));
+        assert!(outbox.event("price", "5678".to_string()));
+        assert_eq!(settings.dropped(), 1);
+        assert_eq!(memory.usage().largest_connection, 8);
+
+        drop(outbox);
+        assert_eq!(memory.usage().send_queues, 0);
+        assert_eq!(memory.usage().connections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_close_after_queued_frames() {
+        let outbox = Outbox::new(SendQueue::default());
+        assert!(outbox.reply("1".to_string()));
+        outbox.close(Some(Message::Close(None)));
+        assert!(!outbox.reply("2".to_string()));
+        assert_eq!(outbox.next().await, Some(Message::Text("1".to_string())));
+        assert_eq!(outbox.next().await, Some(Message::Close(None)));
+        assert_eq!(outbox.next().await, None);
+    }
+}
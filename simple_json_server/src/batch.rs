@@ -0,0 +1,320 @@
+//! Fewer requests between actors that call each other often.
+//!
+//! Actors deployed side by side tend to make many small calls to each other, often the same
+//! read several times at once.  An [`ActorRef`](crate::ActorRef) configured with [`Batching`]
+//! cuts that chatter in two ways:
+//!
+//! - Calls to the methods named with [`Batching::coalesce`] are deduplicated: while a call is in
+//!   flight, identical calls (same method, same parameters) wait for its answer instead of
+//!   sending their own.  Only name methods that don't change state, since coalesced calls run
+//!   once.
+//! - Calls made within a short window of each other are sent together in one `POST /__batch`
+//!   request, and each caller gets its own answer back.
+//!
+//! ```rust
+//! use simple_json_server::batch::Batching;
+//! use simple_json_server::ActorRef;
+//! use std::time::Duration;
+//!
+//! let prices = ActorRef::new("http://prices:8080").with_batching(
+//!     Batching::new()
+//!         .window(Duration::from_millis(2))
+//!         .max_calls(32)
+//!         .coalesce("quote"),
+//! );
+//! ```
+//!
+//! Every server answers `POST /__batch` with a JSON array of calls,
+//! `[{"method": "quote", "params": {"sku": "A1"}}, ...]`, running them concurrently, each as if it
+//! had arrived on its own with the batch request's headers.  The response lists their outcomes in
+//! order, `[{"status": 200, "result": 4.5}, ...]`, where `result` is what the call would have
+//! answered on its own.  Servers take up to 64 calls per batch by default; see
+//! [`ServerOptions::max_batch`](crate::ServerOptions::max_batch).
+//!
+//! A batch carries the earliest deadline of its calls, but not the trace context of the calls
+//! being served, since it is sent from a background task.  A call alone in its window is sent
+//! on its own.  If the server doesn't answer `/__batch` (`404` or `405`), or refuses the batch
+//! as a whole (`400`), the calls are sent one by one instead.  Circuit breakers count each call
+//! of a batch, and coalesced calls once.
+
+use crate::client::ClientError;
+use crate::server::{self, ServerState, JSON};
+use crate::Actor;
+use futures_util::future::{BoxFuture, FutureExt, Shared, WeakShared};
+use hyper::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// The path batches are posted to.
+pub(crate) const PATH: &str = "/__batch";
+
+/// How many calls a server takes in one batch unless configured otherwise.
+pub(crate) const DEFAULT_MAX_BATCH: usize = 64;
+
+/// Settings for coalescing and batching the calls of an [`ActorRef`](crate::ActorRef); see the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Batching {
+    window: Duration,
+    max_calls: usize,
+    coalesce: HashSet<String>,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Batching {
+    /// Batch calls made within 2ms of each other, up to 16 at a time, and coalesce none.
+    pub fn new() -> Self {
+        Self {
+            window: Duration::from_millis(2),
+            max_calls: 16,
+            coalesce: HashSet::new(),
+        }
+    }
+
+    /// How long the first call of a batch waits for others to join it.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Send a batch as soon as it has this many calls.  `1` turns batching off, leaving only
+    /// coalescing.
+    pub fn max_calls(mut self, max_calls: usize) -> Self {
+        self.max_calls = max_calls.max(1);
+        self
+    }
+
+    /// Answer identical in-flight calls to `method` from one request.  Only for methods that
+    /// don't change state.
+    pub fn coalesce(mut self, method: impl Into<String>) -> Self {
+        self.coalesce.insert(method.into());
+        self
+    }
+}
+
+type Outcome = Result<String, ClientError>;
+type Call = BoxFuture<'static, Outcome>;
+
+/// A call waiting for its batch to be sent.
+pub(crate) struct Pending {
+    pub(crate) method: String,
+    pub(crate) body: String,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) reply: oneshot::Sender<Outcome>,
+}
+
+/// What the caller that queued a call has to do to get its batch sent.
+pub(crate) enum Flush {
+    /// Nothing: a batch is already waiting for its window to close.
+    Pending,
+    /// Send this batch once the window closes, if it hasn't been sent by then.
+    After(Duration, u64),
+    /// Send these calls now, as the batch is full.
+    Now(Vec<Pending>),
+}
+
+#[derive(Default)]
+struct Queue {
+    calls: Vec<Pending>,
+    /// Counts batches, so a window that closes after its batch was sent full leaves the next
+    /// batch alone.
+    generation: u64,
+}
+
+/// The coalescing and batching state shared by the clones of an `ActorRef`.
+pub(crate) struct Batcher {
+    settings: Batching,
+    in_flight: Mutex<HashMap<(String, String), WeakShared<Call>>>,
+    queue: Mutex<Queue>,
+}
+
+impl Batcher {
+    pub(crate) fn new(settings: Batching) -> Self {
+        Self {
+            settings,
+            in_flight: Mutex::new(HashMap::new()),
+            queue: Mutex::new(Queue::default()),
+        }
+    }
+
+    pub(crate) fn coalesces(&self, method: &str) -> bool {
+        self.settings.coalesce.contains(method)
+    }
+
+    pub(crate) fn batches(&self) -> bool {
+        self.settings.max_calls > 1
+    }
+
+    /// Answer a call from the identical call in flight, or make it with `call` if there is none.
+    pub(crate) async fn coalesce<F>(&self, method: &str, body: String, call: F) -> Outcome
+    where
+        F: FnOnce(String) -> Call,
+    {
+        let key = (method.to_string(), body);
+        let shared = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key).and_then(WeakShared::upgrade) {
+                Some(shared) if shared.peek().is_none() => shared,
+                _ => {
+                    // Forget calls that finished or whose callers all went away
+                    in_flight.retain(|_, call| call.upgrade().is_some_and(|c| c.peek().is_none()));
+                    let shared: Shared<Call> = call(key.1.clone()).shared();
+                    if let Some(weak) = shared.downgrade() {
+                        in_flight.insert(key, weak);
+                    }
+                    shared
+                }
+            }
+        };
+        shared.await
+    }
+
+    /// Queue a call for the next batch.
+    pub(crate) fn enqueue(&self, call: Pending) -> Flush {
+        let mut queue = self.queue.lock().unwrap();
+        queue.calls.push(call);
+        if queue.calls.len() >= self.settings.max_calls {
+            queue.generation += 1;
+            Flush::Now(std::mem::take(&mut queue.calls))
+        } else if queue.calls.len() == 1 {
+            Flush::After(self.settings.window, queue.generation)
+        } else {
+            Flush::Pending
+        }
+    }
+
+    /// Take the calls of batch `generation` once its window has closed, unless it was sent full.
+    pub(crate) fn take(&self, generation: u64) -> Vec<Pending> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.generation != generation {
+            return Vec::new();
+        }
+        queue.generation += 1;
+        std::mem::take(&mut queue.calls)
+    }
+}
+
+#[derive(Serialize)]
+struct CallOut<'a> {
+    method: &'a str,
+    params: &'a RawValue,
+}
+
+#[derive(Deserialize)]
+struct CallIn<'a> {
+    method: String,
+    #[serde(borrow, default)]
+    params: Option<&'a RawValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Answer {
+    status: u16,
+    result: Box<RawValue>,
+}
+
+/// The body of a batch of `calls`, or `None` if a call's parameters aren't JSON.
+pub(crate) fn encode(calls: &[Pending]) -> Option<String> {
+    let params = calls
+        .iter()
+        .map(|call| serde_json::from_str::<&RawValue>(&call.body).ok())
+        .collect::<Option<Vec<_>>>()?;
+    let calls: Vec<_> = calls
+        .iter()
+        .zip(params)
+        .map(|(call, params)| CallOut {
+            method: &call.method,
+            params,
+        })
+        .collect();
+    serde_json::to_string(&calls).ok()
+}
+
+/// The outcomes of `count` calls from the response to their batch.
+pub(crate) fn decode(response: &str, count: usize) -> Result<Vec<Outcome>, ClientError> {
+    let answers: Vec<Answer> =
+        serde_json::from_str(response).map_err(|e| ClientError::Serialization(e.to_string()))?;
+    if answers.len() != count {
+        return Err(ClientError::Serialization(format!(
+            "Expected {} answers to a batch, got {}",
+            count,
+            answers.len()
+        )));
+    }
+    Ok(answers
+        .into_iter()
+        .map(|answer| {
+            let result = answer.result.get().to_string();
+            if (200..300).contains(&answer.status) {
+                Ok(result)
+            } else {
+                Err(ClientError::Status(answer.status, result))
+            }
+        })
+        .collect())
+}
+
+/// Answer `POST /__batch`: run each call as `dispatch` would have on its own, concurrently, and
+/// list their outcomes in order.
+pub(crate) async fn handle<T>(
+    state: &Arc<ServerState<T>>,
+    headers: &HeaderMap,
+    body: &str,
+) -> server::Reply
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let calls: Vec<CallIn> = match serde_json::from_str(body) {
+        Ok(calls) => calls,
+        Err(e) => {
+            return server::Reply::error(
+                StatusCode::BAD_REQUEST,
+                format!("A batch is an array of calls: {}", e),
+            )
+        }
+    };
+    let max = state.options.max_batch.unwrap_or(DEFAULT_MAX_BATCH);
+    if calls.len() > max {
+        return server::Reply::error(
+            StatusCode::BAD_REQUEST,
+            format!("Batches take at most {} calls", max),
+        );
+    }
+
+    let meta = server::call_meta(state, headers);
+    let replies = futures_util::future::join_all(calls.iter().map(|call| {
+        let params = call.params.map_or("{}", RawValue::get);
+        server::dispatch(state, &call.method, params, meta.clone())
+    }))
+    .await;
+    let answers: Result<Vec<Answer>, _> = replies
+        .into_iter()
+        .map(|reply| {
+            // Answers that aren't JSON are sent as strings
+            let json = reply.content_type == JSON
+                && serde_json::from_str::<&RawValue>(&reply.body).is_ok();
+            let result = if json {
+                RawValue::from_string(reply.body)
+            } else {
+                serde_json::value::to_raw_value(&reply.body)
+            };
+            result.map(|result| Answer {
+                status: reply.status.as_u16(),
+                result,
+            })
+        })
+        .collect();
+    match answers.and_then(|answers| serde_json::to_string(&answers)) {
+        Ok(body) => server::Reply::ok(body),
+        Err(e) => server::Reply::error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
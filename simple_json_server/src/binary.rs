@@ -0,0 +1,131 @@
+//! Binary parameters and results, sent as base64 strings.
+//!
+//! JSON has no byte strings, and serde's default for `Vec<u8>` is an array of numbers, several
+//! times larger than the data and awkward for other languages to produce.  Wrap byte buffers in
+//! [`Base64`] to send them as standard base64 strings instead (padding is optional on input):
+//!
+//! ```rust
+//! use simple_json_server::binary::Base64;
+//! use simple_json_server::{actor, Actor};
+//!
+//! struct Images;
+//!
+//! #[actor]
+//! impl Images {
+//!     #[max_size(image = 1_000_000)]
+//!     pub async fn upload(&self, name: String, image: Base64) -> usize {
+//!         image.len()
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! `#[max_size(param = N)]` on a method rejects calls whose `param` decodes to more than `N`
+//! bytes with `400 Bad Request`.  It works for any parameter whose type is `AsRef<[u8]>`, and a
+//! method may limit several parameters in one attribute.
+
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+/// Standard alphabet; padding is written but not required when reading.
+const ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Bytes that travel as a base64 string.  `T` is the buffer type, e.g. `Vec<u8>` (the default)
+/// or `bytes::Bytes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Base64<T = Vec<u8>>(pub T);
+
+impl<T> Base64<T> {
+    /// The wrapped buffer.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Base64<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Base64<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for Base64<T> {
+    fn from(bytes: T) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Base64<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]>> Serialize for Base64<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&ENGINE.encode(self.0.as_ref()))
+    }
+}
+
+impl<'de, T: From<Vec<u8>>> Deserialize<'de> for Base64<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Base64Visitor;
+
+        impl Visitor<'_> for Base64Visitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a base64 string")
+            }
+
+            fn visit_str<E: de::Error>(self, text: &str) -> Result<Vec<u8>, E> {
+                ENGINE
+                    .decode(text)
+                    .map_err(|e| E::custom(format!("invalid base64: {}", e)))
+            }
+        }
+
+        let bytes = deserializer.deserialize_str(Base64Visitor)?;
+        Ok(Self(T::from(bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Base64 = vec![0, 1, 2, 250, 255].into();
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(json, r#""AAEC+v8=""#);
+        assert_eq!(serde_json::from_str::<Base64>(&json).unwrap(), data);
+
+        // Padding is optional, and other buffer types work the same way
+        let bytes: Base64<Bytes> = serde_json::from_str(r#""aGVsbG8""#).unwrap();
+        assert_eq!(&bytes[..], b"hello");
+    }
+
+    #[test]
+    fn test_invalid_base64() {
+        let e = serde_json::from_str::<Base64>(r#""not base64!""#).unwrap_err();
+        assert!(e.to_string().contains("invalid base64"));
+        assert!(serde_json::from_str::<Base64>("[1, 2]").is_err());
+    }
+}
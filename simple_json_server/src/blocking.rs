@@ -0,0 +1,396 @@
+//! A dedicated, bounded pool of threads for methods that block.
+//!
+//! A method that blocks its thread (reading files, calling a synchronous client, crunching
+//! numbers) holds up every other call scheduled on the same runtime worker, and moving such work
+//! to tokio's own blocking threads lets a burst of calls grow hundreds of them.  Methods marked
+//! `#[blocking]` run instead on a [`BlockingPool`] of the server's own:
+//!
+//! - threads are started as calls arrive, up to a maximum, and those idle for a while beyond the
+//!   minimum are stopped again;
+//! - calls arriving while every thread is busy wait in a bounded queue;
+//! - calls arriving while the queue is full are turned away with `503 Service Unavailable` and a
+//!   `Retry-After` header, or made to wait for room with [`WhenFull::Wait`].
+//!
+//! A server with `#[blocking]` methods and no pool configured gets a default one.  The pool's
+//! threads, queue and rejections can be read from Rust or, with an admin token set, from
+//! `GET /__admin/blocking` and as gauges in `GET /__admin/metrics`.
+//!
+//! ```rust
+//! use simple_json_server::blocking::{BlockingPool, WhenFull};
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Thumbnails;
+//!
+//! #[actor]
+//! impl Thumbnails {
+//!     /// Reads and resizes the image on disk
+//!     #[blocking]
+//!     pub async fn resize(&self, path: String) -> Result<u64, String> {
+//!         std::fs::metadata(&path).map(|meta| meta.len()).map_err(|e| e.to_string())
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let pool = BlockingPool::new(8).queue(32).when_full(WhenFull::Reject);
+//! let options = ServerOptions::new(8080).blocking_pool(pool.clone());
+//!
+//! // Later, e.g. from a metrics exporter
+//! println!("{} calls queued, {} rejected", pool.stats().queued, pool.stats().rejected);
+//! # }
+//! ```
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::{oneshot, Notify};
+
+/// Threads in the pool a server creates when none is configured.
+const DEFAULT_MAX_THREADS: usize = 16;
+
+/// What happens to calls arriving while the pool's queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WhenFull {
+    /// Turn them away with `503 Service Unavailable`.
+    #[default]
+    Reject,
+    /// Make them wait for room in the queue, up to their deadline.
+    Wait,
+}
+
+/// Blocking pool settings, plus the threads they apply to.  Clones share the same threads and
+/// counters, so keep one to read the metrics of a running server.
+#[derive(Debug, Clone)]
+pub struct BlockingPool {
+    max_threads: usize,
+    min_threads: usize,
+    queue: usize,
+    idle_timeout: Duration,
+    when_full: WhenFull,
+    retry_after: Duration,
+    inner: Arc<Inner>,
+}
+
+/// A snapshot of a pool's threads and queue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockingStats {
+    /// Threads running now.
+    pub threads: usize,
+    /// Threads running a call now.
+    pub busy: usize,
+    /// Calls waiting for a thread.
+    pub queued: usize,
+    /// The most threads the pool will run.
+    pub max_threads: usize,
+    /// The most calls that may wait for a thread.
+    pub queue_capacity: usize,
+    /// Calls run, whether or not their caller was still waiting.
+    pub completed: u64,
+    /// Calls turned away because the queue was full.
+    pub rejected: u64,
+}
+
+/// Why a call did not run to completion on the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RunError {
+    /// The queue was full.
+    Full,
+    /// The call panicked.
+    Panicked,
+}
+
+/// Closes the pool once the last clone is dropped, letting its threads finish.
+#[derive(Debug, Default)]
+struct Inner {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    work: Condvar,
+    room: Notify,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    /// Calls admitted and not yet finished, queued or running.
+    admitted: usize,
+    threads: usize,
+    idle: usize,
+    closed: bool,
+}
+
+/// A call to run, returning whether it ran rather than being skipped.
+struct Job(Box<dyn FnOnce() -> bool + Send>);
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Job")
+    }
+}
+
+impl Default for BlockingPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_THREADS)
+    }
+}
+
+impl BlockingPool {
+    /// Run at most `max_threads` threads, queueing up to four calls per thread beyond them.
+    pub fn new(max_threads: usize) -> Self {
+        let max_threads = max_threads.max(1);
+        Self {
+            max_threads,
+            min_threads: 1,
+            queue: max_threads * 4,
+            idle_timeout: Duration::from_secs(10),
+            when_full: WhenFull::Reject,
+            retry_after: Duration::from_secs(1),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Keep at least `min_threads` threads once started, however long they are idle (default 1).
+    pub fn min_threads(mut self, min_threads: usize) -> Self {
+        self.min_threads = min_threads;
+        self
+    }
+
+    /// Let up to `calls` calls wait for a thread (default four per thread).
+    pub fn queue(mut self, calls: usize) -> Self {
+        self.queue = calls;
+        self
+    }
+
+    /// Stop threads beyond the minimum after they are idle for `idle_timeout` (default 10
+    /// seconds).
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// What happens to calls arriving while the queue is full (default [`WhenFull::Reject`]).
+    pub fn when_full(mut self, when_full: WhenFull) -> Self {
+        self.when_full = when_full;
+        self
+    }
+
+    /// How long rejected callers are asked to wait before retrying (default 1 second).
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The pool's threads and queue now.
+    pub fn stats(&self) -> BlockingStats {
+        let shared = &self.inner.shared;
+        let state = shared.state.lock().unwrap();
+        let running = state.admitted - state.jobs.len();
+        BlockingStats {
+            threads: state.threads,
+            busy: running.min(state.threads),
+            queued: state.jobs.len(),
+            max_threads: self.max_threads,
+            queue_capacity: self.queue,
+            completed: shared.completed.load(Ordering::Relaxed),
+            rejected: shared.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Run `f` on one of the pool's threads, once there is room for it.  If the returned future
+    /// is dropped first, `f` is skipped if it has not started.
+    pub(crate) async fn run<F, R>(&self, f: F) -> Result<R, RunError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.admit().await?;
+        let (tx, rx) = oneshot::channel();
+        self.submit(Job(Box::new(move || {
+            if tx.is_closed() {
+                return false;
+            }
+            if let Ok(result) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                let _ = tx.send(result);
+            }
+            true
+        })));
+        rx.await.map_err(|_| RunError::Panicked)
+    }
+
+    pub(crate) fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    /// Count a call against the threads and queue, waiting for room if so configured.
+    async fn admit(&self) -> Result<(), RunError> {
+        let shared = &self.inner.shared;
+        let capacity = self.max_threads + self.queue;
+        loop {
+            let room = shared.room.notified();
+            tokio::pin!(room);
+            room.as_mut().enable();
+            {
+                let mut state = shared.state.lock().unwrap();
+                if state.admitted < capacity {
+                    state.admitted += 1;
+                    return Ok(());
+                }
+            }
+            if self.when_full == WhenFull::Reject {
+                shared.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(RunError::Full);
+            }
+            room.await;
+        }
+    }
+
+    /// Queue an admitted job, waking an idle thread or starting a new one for it.
+    fn submit(&self, job: Job) {
+        let shared = &self.inner.shared;
+        let mut state = shared.state.lock().unwrap();
+        state.jobs.push_back(job);
+        if state.idle > 0 {
+            shared.work.notify_one();
+        }
+        if state.jobs.len() > state.idle && state.threads < self.max_threads {
+            let worker = Worker {
+                shared: Arc::clone(shared),
+                min_threads: self.min_threads,
+                idle_timeout: self.idle_timeout,
+            };
+            let spawned = std::thread::Builder::new()
+                .name("blocking-pool".to_string())
+                .spawn(move || worker.run());
+            match spawned {
+                Ok(_) => state.threads += 1,
+                Err(e) => log::error!("Failed to start a blocking pool thread: {}", e),
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.work.notify_all();
+    }
+}
+
+/// One of the pool's threads.
+struct Worker {
+    shared: Arc<Shared>,
+    min_threads: usize,
+    idle_timeout: Duration,
+}
+
+impl Worker {
+    fn run(self) {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                if (job.0)() {
+                    shared.completed.fetch_add(1, Ordering::Relaxed);
+                }
+                state = shared.state.lock().unwrap();
+                state.admitted -= 1;
+                shared.room.notify_one();
+                continue;
+            }
+            if state.closed {
+                break;
+            }
+            state.idle += 1;
+            let (guard, waited) = shared.work.wait_timeout(state, self.idle_timeout).unwrap();
+            state = guard;
+            state.idle -= 1;
+            if waited.timed_out() && state.jobs.is_empty() && state.threads > self.min_threads {
+                break;
+            }
+        }
+        state.threads -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_grows_to_the_limit_and_shrinks() {
+        let pool = BlockingPool::new(2)
+            .queue(1)
+            .min_threads(0)
+            .idle_timeout(Duration::from_millis(50));
+        let (release, held) = mpsc::channel::<()>();
+        let held = Arc::new(Mutex::new(held));
+        let calls: Vec<_> = (0..3)
+            .map(|_| {
+                let pool = pool.clone();
+                let held = Arc::clone(&held);
+                tokio::spawn(
+                    async move { pool.run(move || held.lock().unwrap().recv().unwrap()).await },
+                )
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let stats = pool.stats();
+        assert_eq!((stats.threads, stats.busy, stats.queued), (2, 2, 1));
+
+        // Threads and queue are full
+        assert_eq!(pool.run(|| ()).await, Err(RunError::Full));
+        assert_eq!(pool.stats().rejected, 1);
+
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        for call in calls {
+            assert_eq!(call.await.unwrap(), Ok(()));
+        }
+        assert_eq!(pool.stats().completed, 3);
+
+        let started = Instant::now();
+        while pool.stats().threads > 0 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_room_when_configured() {
+        let pool = BlockingPool::new(1).queue(0).when_full(WhenFull::Wait);
+        let first = {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                pool.run(|| std::thread::sleep(Duration::from_millis(100)))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(pool.run(|| 2).await, Ok(2));
+        assert_eq!(first.await.unwrap(), Ok(()));
+        assert_eq!(pool.stats().rejected, 0);
+    }
+
+    #[tokio::test]
+    async fn test_panics_are_contained() {
+        let pool = BlockingPool::new(1);
+        assert_eq!(
+            pool.run(|| panic!("boom")).await,
+            Err::<(), _>(RunError::Panicked)
+        );
+        assert_eq!(pool.run(|| 1).await, Ok(1));
+        assert_eq!(pool.stats().threads, 1);
+    }
+}
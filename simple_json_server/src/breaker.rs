@@ -0,0 +1,241 @@
+//! Circuit breaking for calls to other actors.
+//!
+//! An [`ActorRef`](crate::ActorRef) configured with a [`CircuitBreaker`] counts consecutive
+//! failed calls (transport errors and `5xx` responses).  Once the failure threshold is reached
+//! the circuit opens and calls fail immediately with [`ClientError::CircuitOpen`] instead of
+//! piling onto a struggling actor.  After the reset timeout one trial call is let through: if it
+//! succeeds the circuit closes again, otherwise it stays open for another timeout.
+//!
+//! ```rust
+//! use simple_json_server::breaker::CircuitBreaker;
+//! use simple_json_server::ActorRef;
+//! use std::time::Duration;
+//!
+//! let inventory = ActorRef::new("http://inventory:8080").with_circuit_breaker(
+//!     CircuitBreaker::new()
+//!         .failure_threshold(3)
+//!         .reset_timeout(Duration::from_secs(10))
+//!         .on_state_change(|url, state| log::warn!("Circuit to {} is now {:?}", url, state)),
+//! );
+//! ```
+//!
+//! [`ClientError::CircuitOpen`]: crate::ClientError::CircuitOpen
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The state of a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail fast without being sent.
+    Open,
+    /// One trial call is in flight to find out whether the actor has recovered.
+    HalfOpen,
+}
+
+type StateChangeHook = Arc<dyn Fn(&str, CircuitState) + Send + Sync>;
+
+/// Settings for the circuit breaker of an [`ActorRef`](crate::ActorRef).
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    reset_timeout: Duration,
+    on_state_change: Option<StateChangeHook>,
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("failure_threshold", &self.failure_threshold)
+            .field("reset_timeout", &self.reset_timeout)
+            .finish()
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CircuitBreaker {
+    /// A breaker that opens after 5 consecutive failures and tries again after 30 seconds.
+    pub fn new() -> Self {
+        Self {
+            failure_threshold: 5,
+            reset_timeout: Duration::from_secs(30),
+            on_state_change: None,
+        }
+    }
+
+    /// Open the circuit after `failures` consecutive failed calls.
+    pub fn failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures.max(1);
+        self
+    }
+
+    /// How long the circuit stays open before a trial call is allowed.
+    pub fn reset_timeout(mut self, timeout: Duration) -> Self {
+        self.reset_timeout = timeout;
+        self
+    }
+
+    /// Call `hook` with the actor's URL and the new state whenever the circuit changes state,
+    /// e.g. to update a metric or log the transition.
+    pub fn on_state_change(
+        mut self,
+        hook: impl Fn(&str, CircuitState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change = Some(Arc::new(hook));
+        self
+    }
+}
+
+/// The live circuit of one actor, shared by clones of its `ActorRef`.
+#[derive(Debug)]
+pub(crate) struct Circuit {
+    settings: CircuitBreaker,
+    status: Mutex<Status>,
+}
+
+#[derive(Debug)]
+struct Status {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Instant,
+}
+
+impl Circuit {
+    pub(crate) fn new(settings: CircuitBreaker) -> Self {
+        Self {
+            settings,
+            status: Mutex::new(Status {
+                state: CircuitState::Closed,
+                failures: 0,
+                opened_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        self.status.lock().unwrap().state
+    }
+
+    /// Whether a call may be sent now.  An open circuit whose timeout has passed moves to
+    /// half-open and admits this one call as the trial.
+    pub(crate) fn admit(&self, url: &str) -> bool {
+        let mut status = self.status.lock().unwrap();
+        match status.state {
+            CircuitState::Closed => true,
+            _ if status.opened_at.elapsed() < self.settings.reset_timeout => false,
+            // The trial call was abandoned without an outcome; allow another
+            CircuitState::HalfOpen => {
+                status.opened_at = Instant::now();
+                true
+            }
+            CircuitState::Open => {
+                status.state = CircuitState::HalfOpen;
+                status.opened_at = Instant::now();
+                drop(status);
+                self.notify(url, CircuitState::HalfOpen);
+                true
+            }
+        }
+    }
+
+    /// Record the outcome of an admitted call.
+    pub(crate) fn record(&self, url: &str, success: bool) {
+        let mut status = self.status.lock().unwrap();
+        let before = status.state;
+        if success {
+            status.state = CircuitState::Closed;
+            status.failures = 0;
+        } else {
+            status.failures += 1;
+            if before == CircuitState::HalfOpen
+                || status.failures >= self.settings.failure_threshold
+            {
+                status.state = CircuitState::Open;
+                status.opened_at = Instant::now();
+            }
+        }
+        let after = status.state;
+        drop(status);
+        if after != before {
+            self.notify(url, after);
+        }
+    }
+
+    fn notify(&self, url: &str, state: CircuitState) {
+        if let Some(hook) = &self.settings.on_state_change {
+            hook(url, state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_threshold_and_recovers() {
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&transitions);
+        let circuit = Circuit::new(
+            CircuitBreaker::new()
+                .failure_threshold(2)
+                .reset_timeout(Duration::from_millis(20))
+                .on_state_change(move |_, state| seen.lock().unwrap().push(state)),
+        );
+
+        assert!(circuit.admit("x"));
+        circuit.record("x", false);
+        assert_eq!(circuit.state(), CircuitState::Closed);
+        circuit.record("x", false);
+        assert_eq!(circuit.state(), CircuitState::Open);
+        assert!(!circuit.admit("x"));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(circuit.admit("x"));
+        // Only one trial call at a time
+        assert!(!circuit.admit("x"));
+        circuit.record("x", true);
+        assert_eq!(circuit.state(), CircuitState::Closed);
+
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            vec![
+                CircuitState::Open,
+                CircuitState::HalfOpen,
+                CircuitState::Closed
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_trial_reopens() {
+        let circuit = Circuit::new(
+            CircuitBreaker::new()
+                .failure_threshold(1)
+                .reset_timeout(Duration::from_millis(10)),
+        );
+        circuit.record("x", false);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(circuit.admit("x"));
+        circuit.record("x", false);
+        assert_eq!(circuit.state(), CircuitState::Open);
+        assert!(!circuit.admit("x"));
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let circuit = Circuit::new(CircuitBreaker::new().failure_threshold(2));
+        circuit.record("x", false);
+        circuit.record("x", true);
+        circuit.record("x", false);
+        assert_eq!(circuit.state(), CircuitState::Closed);
+    }
+}
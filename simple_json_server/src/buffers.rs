@@ -0,0 +1,197 @@
+//! Reuse of the buffers request bodies are read into, to take load off the allocator.
+//!
+//! Every request body is read into a buffer, which for a large body arriving in many pieces is
+//! reallocated several times as it grows.  Under high concurrency that allocator traffic shows
+//! up in latency.  With a [`BufferPool`] bodies are read into buffers kept from earlier
+//! requests, already grown to size, and handed back once the call is answered.  Buffers that
+//! grew larger than [`BufferPool::max_capacity`] are freed rather than kept, so one huge request
+//! doesn't pin its memory.
+//!
+//! The pool is opt-in, as it trades memory held between requests for fewer allocations; the
+//! `buffer_pool` benchmark compares the two:
+//!
+//! ```bash
+//! cargo bench --bench buffer_pool
+//! ```
+//!
+//! ```rust
+//! use simple_json_server::buffers::BufferPool;
+//! use simple_json_server::ServerOptions;
+//!
+//! let pool = BufferPool::new(256);
+//! let options = ServerOptions::new(8080).buffer_pool(pool.clone());
+//!
+//! // Later, e.g. from a metrics exporter
+//! println!("reused {}, allocated {}", pool.reused(), pool.allocated());
+//! ```
+
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Capacity of newly allocated buffers.
+const INITIAL_CAPACITY: usize = 8 * 1024;
+
+/// Buffer pool settings, plus the buffers kept.  Clones share the same buffers and counters.
+#[derive(Debug, Clone)]
+pub struct BufferPool {
+    max_buffers: usize,
+    max_capacity: usize,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    idle: Mutex<Vec<Vec<u8>>>,
+    reused: AtomicU64,
+    allocated: AtomicU64,
+}
+
+impl BufferPool {
+    /// Keep up to `max_buffers` idle buffers between requests.
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            max_buffers,
+            max_capacity: 1024 * 1024,
+            shared: Arc::default(),
+        }
+    }
+
+    /// Free buffers that grew larger than `bytes` instead of keeping them (default 1 MiB).
+    pub fn max_capacity(mut self, bytes: usize) -> Self {
+        self.max_capacity = bytes;
+        self
+    }
+
+    /// How many requests were read into a kept buffer.
+    pub fn reused(&self) -> u64 {
+        self.shared.reused.load(Ordering::Relaxed)
+    }
+
+    /// How many buffers were allocated because none was idle.
+    pub fn allocated(&self) -> u64 {
+        self.shared.allocated.load(Ordering::Relaxed)
+    }
+
+    /// How many buffers are idle now.
+    pub fn idle(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    fn give_back(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_capacity {
+            return;
+        }
+        buffer.clear();
+        let mut idle = self.shared.idle.lock().unwrap();
+        if idle.len() < self.max_buffers {
+            idle.push(buffer);
+        }
+    }
+}
+
+/// A buffer for one request body, handed back to its pool (if any) when dropped.
+pub(crate) struct Buffer {
+    bytes: Vec<u8>,
+    pool: Option<BufferPool>,
+}
+
+impl Buffer {
+    /// An empty buffer, from `pool` if there is one.
+    pub(crate) fn take(pool: Option<&BufferPool>) -> Self {
+        let Some(pool) = pool else {
+            return Self {
+                bytes: Vec::new(),
+                pool: None,
+            };
+        };
+        let kept = pool.shared.idle.lock().unwrap().pop();
+        let bytes = match kept {
+            Some(bytes) => {
+                pool.shared.reused.fetch_add(1, Ordering::Relaxed);
+                bytes
+            }
+            None => {
+                pool.shared.allocated.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(INITIAL_CAPACITY)
+            }
+        };
+        Self {
+            bytes,
+            pool: Some(pool.clone()),
+        }
+    }
+
+    /// Read the whole of `body` into the buffer.
+    pub(crate) async fn read<B>(mut self, mut body: B) -> Result<Self, B::Error>
+    where
+        B: hyper::body::Body<Data = Bytes> + Unpin,
+    {
+        while let Some(frame) = body.frame().await {
+            if let Some(data) = frame?.data_ref() {
+                self.bytes.extend_from_slice(data);
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl Deref for Buffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.bytes
+    }
+}
+
+impl DerefMut for Buffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.bytes
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.give_back(std::mem::take(&mut self.bytes));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+
+    #[tokio::test]
+    async fn test_buffers_are_reused() {
+        let pool = BufferPool::new(1).max_capacity(64 * 1024);
+        let body = Full::new(Bytes::from("x".repeat(20_000)));
+        let first = Buffer::take(Some(&pool)).read(body).await.unwrap();
+        assert_eq!(first.len(), 20_000);
+        let second = Buffer::take(Some(&pool));
+        drop(first);
+        drop(second);
+        // Only one is kept
+        assert_eq!(pool.idle(), 1);
+        assert_eq!(pool.allocated(), 2);
+
+        let third = Buffer::take(Some(&pool));
+        assert!(third.is_empty());
+        assert_eq!(pool.reused(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_buffers_are_freed() {
+        let pool = BufferPool::new(4).max_capacity(INITIAL_CAPACITY);
+        let body = Full::new(Bytes::from("x".repeat(INITIAL_CAPACITY + 1)));
+        drop(Buffer::take(Some(&pool)).read(body).await.unwrap());
+        assert_eq!(pool.idle(), 0);
+
+        drop(Buffer::take(None));
+        drop(Buffer::take(Some(&pool)));
+        assert_eq!(pool.idle(), 1);
+    }
+}
@@ -0,0 +1,464 @@
+//! Calling actors from Rust.
+//!
+//! An [`ActorRef`] is a cheap, cloneable handle to an actor served over HTTP or HTTPS by another
+//! process (or another task in this one).  Calls are plain JSON-RPC requests, exactly what a
+//! browser or `curl` would send.
+//!
+//! ```rust,no_run
+//! use simple_json_server::ActorRef;
+//! use serde_json::json;
+//!
+//! # async fn example() -> Result<(), simple_json_server::ClientError> {
+//! let calculator = ActorRef::new("http://127.0.0.1:8080");
+//! let sum: f64 = calculator.call("add", &json!({"a": 1.0, "b": 2.0})).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For tests, [`ActorRef::loopback`] serves an actor in-process instead: calls go through the
+//! same request encoding, routing and response handling as over HTTP, without opening a socket.
+
+use crate::batch::{self, Batcher, Batching, Flush, Pending};
+use crate::breaker::{Circuit, CircuitBreaker, CircuitState};
+use crate::runtime::{self, Runtime};
+use crate::server::{self, Loopback};
+use crate::signing::Signing;
+use crate::srv::Service;
+use crate::{Actor, ServerOptions};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+/// Errors returned when calling a remote actor.
+#[derive(Debug, Clone)]
+pub enum ClientError {
+    /// The request could not be built or delivered (bad URL, connection refused, ...).
+    Transport(String),
+    /// The server answered with a non-success HTTP status; carries the status and response body.
+    Status(u16, String),
+    /// The parameters could not be serialized or the response could not be deserialized.
+    Serialization(String),
+    /// The call was not sent because the actor's circuit breaker is open; carries the URL.
+    CircuitOpen(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "Transport error: {}", e),
+            ClientError::Status(status, body) => write!(f, "HTTP {}: {}", status, body),
+            ClientError::Serialization(e) => write!(f, "Serialization error: {}", e),
+            ClientError::CircuitOpen(url) => write!(f, "Circuit to {} is open", url),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// A handle to an actor reachable over HTTP(S).
+#[derive(Clone)]
+pub struct ActorRef {
+    url: String,
+    transport: Transport,
+    circuit: Option<Arc<Circuit>>,
+    /// The service whose instances calls go to, for handles made from SRV URLs.
+    service: Option<Arc<Service>>,
+    batcher: Option<Arc<Batcher>>,
+    signing: Option<Signing>,
+}
+
+/// How requests reach the actor.
+#[allow(clippy::large_enum_variant)] // Almost every handle uses HTTP
+#[derive(Clone)]
+enum Transport {
+    Http(Client<HttpsConnector<HttpConnector>, Full<Bytes>>),
+    Loopback(Arc<dyn Loopback>),
+}
+
+impl fmt::Debug for ActorRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActorRef").field("url", &self.url).finish()
+    }
+}
+
+impl ActorRef {
+    /// Create a handle to the actor served at `url`, e.g. `http://127.0.0.1:8080`.
+    pub fn new(url: impl Into<String>) -> Self {
+        let builder = match hyper_rustls::HttpsConnectorBuilder::new().with_native_roots() {
+            Ok(builder) => builder,
+            Err(e) => {
+                log::warn!("Failed to load native root certificates: {}", e);
+                hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(
+                    rustls::ClientConfig::builder()
+                        .with_root_certificates(rustls::RootCertStore::empty())
+                        .with_no_client_auth(),
+                )
+            }
+        };
+        let connector = builder.https_or_http().enable_http1().build();
+
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            transport: Transport::Http(
+                Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector),
+            ),
+            circuit: None,
+            service: None,
+            batcher: None,
+            signing: None,
+        }
+    }
+
+    /// Create a handle to the actor at `url`, which may also name a service to find in DNS:
+    ///
+    /// - `srv://_calc._tcp.service.consul` looks up the SRV records of the name with the system's
+    ///   name servers, and calls the instances they list over HTTP (`srvs://` for HTTPS).
+    /// - `srv://127.0.0.1:8600/_calc._tcp.service.consul` asks the given name server instead,
+    ///   e.g. a Consul agent's DNS interface.
+    ///
+    /// Each call goes to one instance, picked by the records' priorities and weights.  The name
+    /// is looked up again when the records' TTL runs out, so calls follow instances as they come
+    /// and go, e.g. the pods behind a Kubernetes headless service.  Fails if the first lookup
+    /// finds no instances; later failed lookups keep the instances last found.  Must be called
+    /// within a Tokio runtime.
+    ///
+    /// ```rust,no_run
+    /// use simple_json_server::ActorRef;
+    ///
+    /// # async fn example() -> Result<(), simple_json_server::ClientError> {
+    /// let calculator = ActorRef::connect("srv://_calc._tcp.service.consul").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect(url: impl Into<String>) -> Result<Self, ClientError> {
+        let url = url.into();
+        let service = match Service::parse(&url) {
+            None => return Ok(Self::new(url)),
+            Some(service) => Arc::new(service.map_err(ClientError::Transport)?),
+        };
+        let runtime = runtime::current();
+        let refresh = runtime
+            .run({
+                let service = Arc::clone(&service);
+                async move { service.refresh().await }
+            })
+            .await
+            .map_err(ClientError::Transport)?;
+        runtime.spawn(follow(Arc::downgrade(&service), refresh));
+        Ok(Self {
+            service: Some(service),
+            ..Self::new(url)
+        })
+    }
+
+    /// Serve `actor` in-process and return a handle calling it without a socket, for fast and
+    /// deterministic tests.  Calls are encoded and answered exactly as over HTTP, with `options`
+    /// applied; options for listening (port, TLS, WebSocket) and background tasks (replication,
+    /// clustering, snapshots on start) have no effect.
+    pub fn loopback<T>(actor: T, options: ServerOptions) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+    {
+        Self {
+            url: "loopback://actor".to_string(),
+            transport: Transport::Loopback(server::loopback(actor, options)),
+            circuit: None,
+            service: None,
+            batcher: None,
+            signing: None,
+        }
+    }
+
+    /// Guard calls with a circuit breaker, so that once the actor keeps failing further calls
+    /// fail fast with [`ClientError::CircuitOpen`] until it recovers.  Clones of the returned
+    /// handle share the circuit.  See [`crate::breaker`].
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit = Some(Arc::new(Circuit::new(breaker)));
+        self
+    }
+
+    /// Coalesce identical in-flight calls and send calls made close together in one request.
+    /// Clones of the returned handle share the batches.  See [`crate::batch`].
+    pub fn with_batching(mut self, batching: Batching) -> Self {
+        self.batcher = Some(Arc::new(Batcher::new(batching)));
+        self
+    }
+
+    /// Sign every request with `signing`'s secret, for servers that require signed requests.
+    /// See [`crate::signing`].
+    pub fn with_signing(mut self, signing: Signing) -> Self {
+        self.signing = Some(signing);
+        self
+    }
+
+    /// The state of the circuit breaker, if one is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit.as_ref().map(|circuit| circuit.state())
+    }
+
+    /// The base URL of the actor.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Call `method` with `params` (serialized as the JSON body) and deserialize the response.
+    pub async fn call<P, R>(&self, method: &str, params: &P) -> Result<R, ClientError>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let body =
+            serde_json::to_string(params).map_err(|e| ClientError::Serialization(e.to_string()))?;
+        let response = self.call_raw(method, body).await?;
+        serde_json::from_str(&response).map_err(|e| ClientError::Serialization(e.to_string()))
+    }
+
+    /// Call `method` with an already serialized JSON body and return the raw JSON response.
+    ///
+    /// When called while serving a request that has a deadline, the deadline is passed on so
+    /// the downstream actor gives up at the same time as the original caller, and the request's
+    /// trace context is passed on so the call joins its trace.
+    pub async fn call_raw(&self, method: &str, body: String) -> Result<String, ClientError> {
+        let deadline = crate::RequestContext::current().and_then(|ctx| ctx.deadline());
+        self.call_with_deadline(method, body, deadline).await
+    }
+
+    pub(crate) async fn call_with_deadline(
+        &self,
+        method: &str,
+        body: String,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let Some(batcher) = self.batcher.as_ref().filter(|b| b.coalesces(method)) else {
+            return self.guarded(method, body, deadline).await;
+        };
+        let this = self.clone();
+        let name = method.to_string();
+        batcher
+            .coalesce(method, body, move |body| {
+                Box::pin(async move { this.guarded(&name, body, deadline).await })
+            })
+            .await
+    }
+
+    /// Make a call through the circuit breaker, if one is configured.
+    async fn guarded(
+        &self,
+        method: &str,
+        body: String,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let Some(circuit) = &self.circuit else {
+            return self.deliver(method, body, deadline).await;
+        };
+        if !circuit.admit(&self.url) {
+            return Err(ClientError::CircuitOpen(self.url.clone()));
+        }
+        let result = self.deliver(method, body, deadline).await;
+        // Only failures of the actor count against it, not rejected requests
+        let failed = match &result {
+            Err(ClientError::Transport(_)) => true,
+            Err(ClientError::Status(status, _)) => *status >= 500,
+            _ => false,
+        };
+        circuit.record(&self.url, !failed);
+        result
+    }
+
+    /// Send a call on its own, or queue it for the next batch if batching is configured.
+    async fn deliver(
+        &self,
+        method: &str,
+        body: String,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let Some(batcher) = self.batcher.as_ref().filter(|b| b.batches()) else {
+            return self.send(method, body, deadline).await;
+        };
+        let (reply, answer) = tokio::sync::oneshot::channel();
+        let call = Pending {
+            method: method.to_string(),
+            body,
+            deadline,
+            reply,
+        };
+        match batcher.enqueue(call) {
+            Flush::Pending => {}
+            Flush::After(window, generation) => {
+                let this = self.clone();
+                runtime::current().spawn(async move {
+                    tokio::time::sleep(window).await;
+                    let Some(batcher) = &this.batcher else {
+                        return;
+                    };
+                    let calls = batcher.take(generation);
+                    this.flush(calls).await;
+                });
+            }
+            Flush::Now(calls) => {
+                let this = self.clone();
+                runtime::current().spawn(async move { this.flush(calls).await });
+            }
+        }
+        answer
+            .await
+            .unwrap_or_else(|_| Err(ClientError::Transport("The batch was dropped".to_string())))
+    }
+
+    /// Send a batch of calls and hand each its answer.
+    async fn flush(&self, mut calls: Vec<Pending>) {
+        if calls.len() <= 1 {
+            if let Some(call) = calls.pop() {
+                let result = self.send(&call.method, call.body, call.deadline).await;
+                let _ = call.reply.send(result);
+            }
+            return;
+        }
+        // Parameters that aren't JSON are sent as they are, to be refused as usual
+        let Some(body) = batch::encode(&calls) else {
+            return self.send_each(calls).await;
+        };
+        let deadline = calls.iter().filter_map(|call| call.deadline).min();
+        let answers = self
+            .post(batch::PATH, body, deadline)
+            .await
+            .and_then(|response| batch::decode(&response, calls.len()));
+        match answers {
+            Ok(answers) => {
+                for (call, answer) in calls.into_iter().zip(answers) {
+                    let _ = call.reply.send(answer);
+                }
+            }
+            // Servers without batches, or batches the server won't take as a whole
+            Err(ClientError::Status(400 | 404 | 405, _)) => self.send_each(calls).await,
+            Err(e) => {
+                for call in calls {
+                    let _ = call.reply.send(Err(e.clone()));
+                }
+            }
+        }
+    }
+
+    /// Send each of `calls` on its own, concurrently.
+    async fn send_each(&self, calls: Vec<Pending>) {
+        futures_util::future::join_all(calls.into_iter().map(|call| async move {
+            let result = self.send(&call.method, call.body, call.deadline).await;
+            let _ = call.reply.send(result);
+        }))
+        .await;
+    }
+
+    async fn send(
+        &self,
+        method: &str,
+        body: String,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        self.post(&format!("/{}", method), body, deadline).await
+    }
+
+    /// POST `body` to `path` and return the response body.
+    async fn post(
+        &self,
+        path: &str,
+        body: String,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let base = match &self.service {
+            Some(service) => service.pick().ok_or_else(|| {
+                ClientError::Transport(format!("{} has no instances", service.name))
+            })?,
+            None => self.url.clone(),
+        };
+        let uri = format!("{}{}", base, path);
+        let mut request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(&uri)
+            .header("Content-Type", "application/json");
+        if let Some(signing) = &self.signing {
+            // Sign the path the server sees, including any prefix of the base URL
+            let signed_path = uri
+                .parse::<hyper::Uri>()
+                .ok()
+                .and_then(|uri| uri.path_and_query().map(|p| p.to_string()))
+                .unwrap_or_else(|| path.to_string());
+            for (name, value) in signing.headers(&signed_path, &body) {
+                request = request.header(name, value);
+            }
+        }
+        if let Some(deadline) = deadline {
+            request = request.header(
+                crate::deadline::DEADLINE_HEADER,
+                crate::deadline::to_header(deadline),
+            );
+        }
+        // Continue the trace of the call being served, if any
+        if let Some(ctx) = crate::RequestContext::current() {
+            request = request.header(crate::trace::TRACEPARENT, ctx.trace().traceparent());
+            if let Some(tracestate) = ctx.trace().tracestate() {
+                request = request.header(crate::trace::TRACESTATE, tracestate);
+            }
+        }
+        let request = request
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        let (status, bytes) = match &self.transport {
+            Transport::Http(client) => {
+                let client = client.clone();
+                let exchange = async move {
+                    let response = client
+                        .request(request)
+                        .await
+                        .map_err(|e| ClientError::Transport(e.to_string()))?;
+                    let status = response.status();
+                    let bytes = response
+                        .into_body()
+                        .collect()
+                        .await
+                        .map_err(|e| ClientError::Transport(e.to_string()))?
+                        .to_bytes();
+                    Ok::<_, ClientError>((status, bytes))
+                };
+                runtime::current().run(exchange).await?
+            }
+            Transport::Loopback(server) => {
+                let response = server.call(request).await;
+                let status = response.status();
+                let Ok(body) = response.into_body().collect().await;
+                (status, body.to_bytes())
+            }
+        };
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+
+        if status.is_success() {
+            Ok(text)
+        } else {
+            Err(ClientError::Status(status.as_u16(), text))
+        }
+    }
+}
+
+/// Look `service` up again whenever its records expire, for as long as a handle uses it.
+async fn follow(service: Weak<Service>, mut refresh: Duration) {
+    loop {
+        tokio::time::sleep(refresh).await;
+        let Some(service) = service.upgrade() else {
+            break;
+        };
+        refresh = match service.refresh().await {
+            Ok(refresh) => refresh,
+            Err(e) => {
+                log::warn!("{}", e);
+                Duration::from_secs(5)
+            }
+        };
+    }
+}
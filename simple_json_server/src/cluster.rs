@@ -0,0 +1,481 @@
+//! Cluster mode: serve one logical actor from several processes.
+//!
+//! Actors keep their state in memory, so naively running several copies of a server behind a
+//! load balancer silently breaks any per-session state: two requests for the same session can
+//! land on different processes.  A [`Cluster`] coordinates the processes through a shared
+//! [`ClusterBackend`] so that:
+//!
+//! - exactly one node holds the *leadership* lease at a time (see [`Cluster::is_leader`]), which
+//!   is useful for singleton work such as scheduled jobs or compaction, and
+//! - every stateful session (identified by the `X-Session-Key` request header) is owned by a
+//!   single node.  A request arriving at any other node is answered with a
+//!   `307 Temporary Redirect` to the owner, which preserves the method and body.
+//!
+//! Sessions and leadership are leases: they expire unless renewed, so a crashed node's sessions
+//! move to whichever node sees them next.
+//!
+//! [`MemoryBackend`] shares state between nodes in the same process and is mostly useful for
+//! tests.  With the `postgres` feature, `PostgresBackend` keeps the leases in a Postgres table
+//! shared by every node.  Other stores, e.g. Redis (`SET NX PX`), can implement
+//! [`ClusterBackend`] themselves.
+//!
+//! # Example
+//!
+//! ```rust
+//! use simple_json_server::cluster::{Cluster, MemoryBackend};
+//! use simple_json_server::ServerOptions;
+//!
+//! let backend = MemoryBackend::new();
+//! let cluster = Cluster::new("node-a", "http://10.0.0.1:8080", backend);
+//! let options = ServerOptions::new(8080).cluster(cluster);
+//! ```
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The request header carrying the key of the stateful session a request belongs to.
+pub const SESSION_HEADER: &str = "X-Session-Key";
+
+/// A process taking part in a cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    /// Unique identifier of the node.
+    pub id: String,
+    /// Base URL other nodes (and redirected clients) use to reach this node, e.g. `http://10.0.0.1:8080`.
+    pub url: String,
+}
+
+/// Shared coordination store used by every node of a cluster.
+///
+/// Both operations are leases: the returned [`NodeInfo`] is the current holder, which is the
+/// calling node when the lease was free, expired, or already held by it (in which case it is
+/// renewed for another `ttl`).
+pub trait ClusterBackend: Send + Sync {
+    /// Acquire or renew the cluster-wide leadership lease for `node`.
+    fn acquire_leadership<'a>(
+        &'a self,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>>;
+
+    /// Acquire or renew ownership of `session` for `node`.
+    fn claim_session<'a>(
+        &'a self,
+        session: &'a str,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+struct Lease {
+    holder: NodeInfo,
+    expires: Instant,
+}
+
+impl Lease {
+    /// Grant the lease to `node` if it is free, expired or already held by it; return the holder.
+    fn acquire(slot: &mut Option<Lease>, node: &NodeInfo, ttl: Duration) -> NodeInfo {
+        let now = Instant::now();
+        match slot {
+            Some(lease) if lease.expires > now && lease.holder.id != node.id => {
+                lease.holder.clone()
+            }
+            _ => {
+                *slot = Some(Lease {
+                    holder: node.clone(),
+                    expires: now + ttl,
+                });
+                node.clone()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct MemoryState {
+    leader: Option<Lease>,
+    sessions: HashMap<String, Option<Lease>>,
+}
+
+/// A [`ClusterBackend`] that keeps leases in memory.  Clones share the same state, so several
+/// servers in one process can form a cluster.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl MemoryBackend {
+    /// Create an empty backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ClusterBackend for MemoryBackend {
+    fn acquire_leadership<'a>(
+        &'a self,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut state = self.state.lock().unwrap();
+        let leader = Lease::acquire(&mut state.leader, node, ttl);
+        Box::pin(async move { Ok(leader) })
+    }
+
+    fn claim_session<'a>(
+        &'a self,
+        session: &'a str,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state
+            .sessions
+            .retain(|_, lease| lease.as_ref().is_some_and(|l| l.expires > now));
+        let slot = state.sessions.entry(session.to_string()).or_default();
+        let owner = Lease::acquire(slot, node, ttl);
+        Box::pin(async move { Ok(owner) })
+    }
+}
+
+/// How many times a node tries for a lease another node is deciding on before giving up.
+#[cfg(feature = "postgres")]
+const LOCK_ATTEMPTS: u32 = 10;
+
+/// A [`ClusterBackend`] that keeps leases in a Postgres table, one row per lease (feature
+/// `postgres`).  A node takes or renews a lease in a transaction holding
+/// `pg_try_advisory_xact_lock` on the lease's name, so only one node decides at a time.  The
+/// table (`cluster_leases` by default) is created on first use, and the leader removes
+/// expired sessions from it.
+///
+/// ```rust,no_run
+/// use simple_json_server::cluster::{Cluster, PostgresBackend};
+/// use simple_json_server::ServerOptions;
+///
+/// let mut config = deadpool_postgres::Config::new();
+/// config.url = Some("postgres://localhost/app".to_string());
+/// let pool = config
+///     .create_pool(Some(deadpool_postgres::Runtime::Tokio1), tokio_postgres::NoTls)
+///     .unwrap();
+/// let cluster = Cluster::new("node-a", "http://10.0.0.1:8080", PostgresBackend::new(pool));
+/// let options = ServerOptions::new(8080).cluster(cluster);
+/// ```
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresBackend {
+    pool: deadpool_postgres::Pool,
+    table: String,
+    created: Arc<tokio::sync::OnceCell<()>>,
+}
+
+#[cfg(feature = "postgres")]
+impl std::fmt::Debug for PostgresBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresBackend")
+            .field("table", &self.table)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresBackend {
+    /// Keep leases in the database `pool` connects to.
+    pub fn new(pool: deadpool_postgres::Pool) -> Self {
+        Self {
+            pool,
+            table: "cluster_leases".to_string(),
+            created: Arc::default(),
+        }
+    }
+
+    /// The table to keep leases in (default `cluster_leases`).  The name is used in SQL as it
+    /// is, so it must be a plain identifier.
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Take or renew the lease `name` for `node`, returning its holder.
+    async fn acquire(
+        &self,
+        name: &str,
+        node: &NodeInfo,
+        ttl: Duration,
+    ) -> Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let mut client = self.pool.get().await?;
+        let create = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                name TEXT PRIMARY KEY,
+                node_id TEXT NOT NULL,
+                node_url TEXT NOT NULL,
+                expires_at TIMESTAMPTZ NOT NULL
+            )",
+            self.table
+        );
+        self.created
+            .get_or_try_init(|| client.batch_execute(&create))
+            .await?;
+        let lock = format!("{}:{}", self.table, name);
+        for _ in 0..LOCK_ATTEMPTS {
+            let tx = client.transaction().await?;
+            let locked: bool = tx
+                .query_one("SELECT pg_try_advisory_xact_lock(hashtext($1))", &[&lock])
+                .await?
+                .get(0);
+            let holder = tx
+                .query_opt(
+                    &format!(
+                        "SELECT node_id, node_url FROM {} WHERE name = $1 AND expires_at > now()",
+                        self.table
+                    ),
+                    &[&name],
+                )
+                .await?
+                .map(|row| NodeInfo {
+                    id: row.get(0),
+                    url: row.get(1),
+                });
+            match holder {
+                Some(holder) if holder.id != node.id => return Ok(holder),
+                _ if locked => {
+                    tx.execute(
+                        &format!(
+                            "INSERT INTO {} (name, node_id, node_url, expires_at)
+                             VALUES ($1, $2, $3, now() + make_interval(secs => $4))
+                             ON CONFLICT (name) DO UPDATE SET node_id = EXCLUDED.node_id,
+                                 node_url = EXCLUDED.node_url, expires_at = EXCLUDED.expires_at",
+                            self.table
+                        ),
+                        &[&name, &node.id, &node.url, &ttl.as_secs_f64()],
+                    )
+                    .await?;
+                    tx.commit().await?;
+                    return Ok(node.clone());
+                }
+                // Another node is deciding who holds the lease; see what it decides
+                _ => {}
+            }
+            drop(tx);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Err(format!("Lease {} is contended", name).into())
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl ClusterBackend for PostgresBackend {
+    fn acquire_leadership<'a>(
+        &'a self,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            let leader = self.acquire("leader", node, ttl).await?;
+            if leader.id == node.id {
+                let client = self.pool.get().await?;
+                client
+                    .execute(
+                        &format!(
+                            "DELETE FROM {} WHERE name LIKE 'session:%' AND expires_at <= now()",
+                            self.table
+                        ),
+                        &[],
+                    )
+                    .await?;
+            }
+            Ok(leader)
+        })
+    }
+
+    fn claim_session<'a>(
+        &'a self,
+        session: &'a str,
+        node: &'a NodeInfo,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            self.acquire(&format!("session:{}", session), node, ttl)
+                .await
+        })
+    }
+}
+
+/// Membership of this process in a cluster.  Pass it to [`crate::ServerOptions::cluster`].
+#[derive(Clone)]
+pub struct Cluster {
+    node: NodeInfo,
+    backend: Arc<dyn ClusterBackend>,
+    lease_ttl: Duration,
+    session_ttl: Duration,
+    leader: Arc<Mutex<Option<NodeInfo>>>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("node", &self.node)
+            .field("lease_ttl", &self.lease_ttl)
+            .field("session_ttl", &self.session_ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cluster {
+    /// Join the cluster coordinated by `backend` as node `id`, reachable at `url`.
+    pub fn new(
+        id: impl Into<String>,
+        url: impl Into<String>,
+        backend: impl ClusterBackend + 'static,
+    ) -> Self {
+        Self {
+            node: NodeInfo {
+                id: id.into(),
+                url: url.into().trim_end_matches('/').to_string(),
+            },
+            backend: Arc::new(backend),
+            lease_ttl: Duration::from_secs(10),
+            session_ttl: Duration::from_secs(300),
+            leader: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// How long the leadership lease lasts without renewal (default 10 seconds).  Leaders renew
+    /// three times per period.
+    pub fn lease_ttl(mut self, ttl: Duration) -> Self {
+        self.lease_ttl = ttl;
+        self
+    }
+
+    /// How long a session stays pinned to its node after its last request (default 5 minutes).
+    pub fn session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// This node.
+    pub fn node(&self) -> &NodeInfo {
+        &self.node
+    }
+
+    /// The leader as of the last election round, if known.
+    pub fn leader(&self) -> Option<NodeInfo> {
+        self.leader.lock().unwrap().clone()
+    }
+
+    /// Whether this node held the leadership lease as of the last election round.
+    pub fn is_leader(&self) -> bool {
+        self.leader()
+            .is_some_and(|leader| leader.id == self.node.id)
+    }
+
+    /// Run one election round: acquire or renew the leadership lease and record the result.
+    /// Servers started with a cluster do this periodically on their own.
+    pub async fn refresh_leadership(
+        &self,
+    ) -> Result<NodeInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let result = self
+            .backend
+            .acquire_leadership(&self.node, self.lease_ttl)
+            .await;
+        let mut leader = self.leader.lock().unwrap();
+        match &result {
+            Ok(current) => {
+                if leader.as_ref() != Some(current) {
+                    log::info!("Cluster leader is now node {}", current.id);
+                }
+                *leader = Some(current.clone());
+            }
+            // Without the backend we cannot tell who leads, so never assume it is us
+            Err(_) => *leader = None,
+        }
+        result
+    }
+
+    /// Find the node owning `session`, claiming it for this node when it is unowned.
+    /// Returns `None` when this node is the owner.
+    pub async fn route(
+        &self,
+        session: &str,
+    ) -> Result<Option<NodeInfo>, Box<dyn std::error::Error + Send + Sync>> {
+        let owner = self
+            .backend
+            .claim_session(session, &self.node, self.session_ttl)
+            .await?;
+        Ok((owner.id != self.node.id).then_some(owner))
+    }
+
+    /// Keep the leadership lease fresh for as long as the server runs.
+    pub(crate) async fn run_election(self) {
+        loop {
+            if let Err(e) = self.refresh_leadership().await {
+                log::warn!("Cluster leader election failed: {}", e);
+            }
+            tokio::time::sleep(self.lease_ttl / 3).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_leader() {
+        let backend = MemoryBackend::new();
+        let a = Cluster::new("a", "http://a", backend.clone());
+        let b = Cluster::new("b", "http://b", backend);
+
+        assert_eq!(a.refresh_leadership().await.unwrap().id, "a");
+        assert_eq!(b.refresh_leadership().await.unwrap().id, "a");
+        assert!(a.is_leader());
+        assert!(!b.is_leader());
+        assert_eq!(b.leader().unwrap().url, "http://a");
+    }
+
+    #[tokio::test]
+    async fn test_leadership_moves_when_lease_expires() {
+        let backend = MemoryBackend::new();
+        let a = Cluster::new("a", "http://a", backend.clone()).lease_ttl(Duration::from_millis(20));
+        let b = Cluster::new("b", "http://b", backend).lease_ttl(Duration::from_millis(20));
+
+        a.refresh_leadership().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(b.refresh_leadership().await.unwrap().id, "b");
+        assert!(b.is_leader());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_sticky() {
+        let backend = MemoryBackend::new();
+        let a = Cluster::new("a", "http://a/", backend.clone());
+        let b = Cluster::new("b", "http://b", backend);
+
+        assert_eq!(a.route("cart-1").await.unwrap(), None);
+        let owner = b.route("cart-1").await.unwrap().unwrap();
+        assert_eq!(owner.id, "a");
+        assert_eq!(owner.url, "http://a");
+        assert_eq!(b.route("cart-2").await.unwrap(), None);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_unreachable_postgres_never_leads() {
+        let mut config = deadpool_postgres::Config::new();
+        config.url = Some("postgres://user@127.0.0.1:9/db".to_string());
+        let pool = config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap();
+        let a = Cluster::new("a", "http://a", PostgresBackend::new(pool));
+
+        assert!(a.refresh_leadership().await.is_err());
+        assert!(!a.is_leader());
+        assert!(a.route("cart-1").await.is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Compression of persisted snapshots (feature `compression`).
+//!
+//! Snapshots of chatty, event-sourced actors can be large and mostly repetitive JSON, which
+//! compresses well.  A [`Compression`] picks the algorithm: zstd at a chosen level, or lz4 when
+//! speed matters more than size.
+//!
+//! [`FileSnapshotStore::compressed`](crate::snapshot::FileSnapshotStore::compressed) compresses
+//! snapshot files as they are written, without holding the compressed snapshot in memory, and
+//! [`CompressedSnapshots`](crate::snapshot::CompressedSnapshots) compresses the state of the
+//! snapshots kept in any other [`SnapshotStore`](crate::snapshot::SnapshotStore):
+//!
+//! ```rust
+//! use simple_json_server::compression::Compression;
+//! use simple_json_server::encryption::Encryption;
+//! use simple_json_server::snapshot::{CompressedSnapshots, EncryptedSnapshots, FileSnapshotStore};
+//!
+//! let files = FileSnapshotStore::new("/var/lib/inventory").compressed(Compression::zstd(9));
+//!
+//! let encryption = Encryption::new([7; 32]).unwrap();
+//! let sealed = CompressedSnapshots::new(
+//!     EncryptedSnapshots::new(FileSnapshotStore::new("/var/lib/inventory"), encryption),
+//!     Compression::lz4(),
+//! );
+//! ```
+//!
+//! Snapshots written before compression was turned on still restore, and each compressed
+//! snapshot records its algorithm, so the setting can be changed at any time.  To compress
+//! and encrypt, compress first, as above: encrypted data doesn't compress, so the
+//! [`EncryptedSnapshots`](crate::snapshot::EncryptedSnapshots) goes inside the
+//! `CompressedSnapshots`.
+//!
+//! The [journal](crate::journal) is kept in memory, so it has nothing to compress.
+
+use std::io::{self, Read, Write};
+
+/// A compression algorithm and level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// zstd at a level from 1 (fastest) to 22 (smallest); 3 is zstd's default.
+    Zstd(i32),
+    /// lz4, which has no levels: fast, with a lower ratio than zstd.
+    Lz4,
+}
+
+impl Compression {
+    /// zstd at `level`, from 1 (fastest) to 22 (smallest).
+    pub fn zstd(level: i32) -> Self {
+        Compression::Zstd(level.clamp(1, 22))
+    }
+
+    /// lz4.
+    pub fn lz4() -> Self {
+        Compression::Lz4
+    }
+
+    /// The file extension of data compressed this way, and its marker in compressed text.
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Compression::Zstd(_) => "zst",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// The algorithm whose [`extension`](Self::extension) is `extension`.
+    pub(crate) fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "zst" => Some(Compression::Zstd(3)),
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Serialize `value` as JSON into `writer`, compressing it on the way.
+    pub(crate) fn write_json<W: Write, T: serde::Serialize>(
+        &self,
+        writer: W,
+        value: &T,
+    ) -> io::Result<W> {
+        match self {
+            Compression::Zstd(level) => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, *level)?;
+                serde_json::to_writer(&mut encoder, value)?;
+                encoder.finish()
+            }
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                serde_json::to_writer(&mut encoder, value)?;
+                encoder.finish().map_err(io::Error::other)
+            }
+        }
+    }
+
+    /// Deserialize JSON compressed this way from `reader`, decompressing it on the way.
+    pub(crate) fn read_json<R: Read, T: serde::de::DeserializeOwned>(
+        &self,
+        reader: R,
+    ) -> io::Result<T> {
+        let value = match self {
+            Compression::Zstd(_) => {
+                serde_json::from_reader(zstd::stream::read::Decoder::new(reader)?)
+            }
+            Compression::Lz4 => serde_json::from_reader(lz4_flex::frame::FrameDecoder::new(reader)),
+        };
+        value.map_err(io::Error::from)
+    }
+
+    /// `data` compressed.
+    pub(crate) fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Zstd(level) => zstd::encode_all(data, *level),
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+                encoder.finish().map_err(io::Error::other)
+            }
+        }
+    }
+
+    /// `data`, compressed this way, decompressed.
+    pub(crate) fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Compression::Zstd(_) => {
+                zstd::stream::read::Decoder::new(data)?.read_to_end(&mut decompressed)?;
+            }
+            Compression::Lz4 => {
+                lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        let state = r#"{"events": ["added", "added", "added", "added", "added"]}"#.repeat(100);
+        for compression in [Compression::zstd(19), Compression::lz4()] {
+            let compressed = compression.compress(state.as_bytes()).unwrap();
+            assert!(compressed.len() < state.len() / 10, "{:?}", compression);
+            assert_eq!(
+                compression.decompress(&compressed).unwrap(),
+                state.as_bytes()
+            );
+
+            let file = compression.write_json(Vec::new(), &state).unwrap();
+            let read: String = compression.read_json(&file[..]).unwrap();
+            assert_eq!(read, state);
+        }
+        assert!(Compression::lz4().decompress(b"not lz4").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Requiring calls to declare a JSON `Content-Type`.
+//!
+//! By default a server reads every call's body as JSON whatever its `Content-Type` says, which
+//! some proxies and security scanners flag, and which lets a browser form post
+//! (`application/x-www-form-urlencoded`, sent without a CORS preflight) reach a method.  With
+//! [`ContentTypes`] configured, HTTP calls must carry one of the allowed types and others are
+//! answered `415 Unsupported Media Type`, with the allowed types in an `Accept-Post` header:
+//!
+//! ```rust
+//! use simple_json_server::content_type::ContentTypes;
+//! use simple_json_server::ServerOptions;
+//!
+//! let options = ServerOptions::new(8080).content_types(ContentTypes::json());
+//! ```
+//!
+//! Parameters such as `; charset=utf-8` are ignored.  A method called by clients that send
+//! something else can name the types it takes with `#[accepts("text/plain")]`, replacing the
+//! server's list for that method; the body must still be JSON.  WebSocket messages and the admin
+//! API are not checked.
+
+/// The media types calls may be sent with; see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentTypes {
+    allowed: Vec<String>,
+}
+
+impl ContentTypes {
+    /// Allow only `application/json`.
+    pub fn json() -> Self {
+        Self {
+            allowed: vec!["application/json".to_string()],
+        }
+    }
+
+    /// Also allow `media_type`, e.g. `application/vnd.api+json`.
+    pub fn allow(mut self, media_type: impl Into<String>) -> Self {
+        self.allowed.push(media_type.into());
+        self
+    }
+
+    /// Whether a call to a method accepting `overrides` (if it declares any) may be sent with
+    /// `content_type`.
+    pub(crate) fn admits(&self, content_type: Option<&str>, overrides: &[&str]) -> bool {
+        let Some(media_type) = content_type.and_then(|v| v.split(';').next()) else {
+            return false;
+        };
+        let media_type = media_type.trim();
+        if overrides.is_empty() {
+            self.allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+        } else {
+            overrides
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+        }
+    }
+
+    /// The types admitted for a method accepting `overrides`.
+    pub(crate) fn allowed<'a>(&'a self, overrides: &[&'a str]) -> Vec<&'a str> {
+        if overrides.is_empty() {
+            self.allowed.iter().map(String::as_str).collect()
+        } else {
+            overrides.to_vec()
+        }
+    }
+
+    /// The types admitted for a method accepting `overrides`, for the `Accept-Post` header.
+    pub(crate) fn accept_post(&self, overrides: &[&str]) -> String {
+        self.allowed(overrides).join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_types() {
+        let types = ContentTypes::json().allow("application/vnd.api+json");
+        assert!(types.admits(Some("application/json"), &[]));
+        assert!(types.admits(Some("Application/JSON; charset=utf-8"), &[]));
+        assert!(types.admits(Some("application/vnd.api+json"), &[]));
+        assert!(!types.admits(Some("text/plain"), &[]));
+        assert!(!types.admits(Some("application/x-www-form-urlencoded"), &[]));
+        assert!(!types.admits(None, &[]));
+        assert_eq!(
+            types.accept_post(&[]),
+            "application/json, application/vnd.api+json"
+        );
+    }
+
+    #[test]
+    fn test_method_overrides() {
+        let types = ContentTypes::json();
+        assert!(types.admits(Some("text/plain"), &["text/plain"]));
+        assert!(!types.admits(Some("application/json"), &["text/plain"]));
+        assert_eq!(types.accept_post(&["text/plain"]), "text/plain");
+    }
+}
@@ -0,0 +1,553 @@
+//! Per-request context available to actor methods while they are being served.
+//!
+//! Actor methods only receive their JSON parameters, which keeps them easy to call from any
+//! language.  Everything else the server knows about a request lives in a [`RequestContext`],
+//! reachable from inside a method with [`RequestContext::current`].
+
+use crate::streaming::Streamed;
+use crate::trace::TraceContext;
+use crate::ActorRef;
+use hyper::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+tokio::task_local! {
+    static CURRENT: RequestContext;
+}
+
+/// Information about the request currently being served.  Cloning is cheap and clones refer to
+/// the same request.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    inner: Arc<ContextInner>,
+}
+
+#[derive(Debug)]
+struct ContextInner {
+    request_id: String,
+    method: String,
+    params: String,
+    headers: HeaderMap,
+    resources: Resources,
+    extensions: Mutex<TypeMap>,
+    failed: AtomicBool,
+    /// Set once the caller has gone away without waiting for the response.
+    cancelled: watch::Sender<bool>,
+    forward: Mutex<Option<ActorRef>>,
+    /// Whether the parameters came from a query string, as strings read as the types ask.
+    from_query: bool,
+    /// The method's parameters marked `#[sensitive]`.
+    sensitive: Vec<&'static str>,
+    /// Whether the transport can serialize a streaming method's result as it is sent.
+    streams: bool,
+    stream: Mutex<Option<Streamed>>,
+    deadline: Option<Instant>,
+    queue_wait: Mutex<Duration>,
+    status: Mutex<Option<StatusCode>>,
+    trace: TraceContext,
+}
+
+type TypeMap = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+/// Values shared by every request to a server, registered with
+/// [`crate::ServerOptions::resource`] and looked up by type.
+#[derive(Clone, Default)]
+pub(crate) struct Resources(Arc<TypeMap>);
+
+impl Resources {
+    pub(crate) fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Resources({})", self.0.len())
+    }
+}
+
+/// Returned by methods that hand their call over to another actor with
+/// [`RequestContext::forward`].  The caller receives the other actor's response instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Forward;
+
+impl RequestContext {
+    pub(crate) fn new(method: &str, params: &str, resources: Resources) -> Self {
+        Self {
+            inner: Arc::new(ContextInner {
+                request_id: generate_request_id(),
+                method: method.to_string(),
+                params: params.to_string(),
+                headers: HeaderMap::new(),
+                resources,
+                extensions: Mutex::new(HashMap::new()),
+                failed: AtomicBool::new(false),
+                cancelled: watch::Sender::new(false),
+                forward: Mutex::new(None),
+                from_query: false,
+                sensitive: Vec::new(),
+                streams: false,
+                stream: Mutex::new(None),
+                deadline: None,
+                queue_wait: Mutex::new(Duration::ZERO),
+                status: Mutex::new(None),
+                trace: TraceContext::new_root(),
+            }),
+        }
+    }
+
+    /// Use the request id the caller sent instead of a generated one.  Only valid before the
+    /// context is shared.
+    pub(crate) fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        if let Some(request_id) = request_id {
+            Arc::get_mut(&mut self.inner)
+                .expect("request id is set before the context is shared")
+                .request_id = request_id;
+        }
+        self
+    }
+
+    /// Set the deadline the caller asked for.  Only valid before the context is shared.
+    pub(crate) fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("deadline is set before the context is shared")
+            .deadline = deadline;
+        self
+    }
+
+    /// Keep the request's headers for [`header`](Self::header) and [`cookie`](Self::cookie).
+    /// Only valid before the context is shared.
+    pub(crate) fn with_headers(mut self, headers: HeaderMap) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("headers are set before the context is shared")
+            .headers = headers;
+        self
+    }
+
+    /// Mark the parameters as read from a `GET` request's query string.  Only valid before the
+    /// context is shared.
+    pub(crate) fn with_query(mut self, from_query: bool) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("the parameters' source is set before the context is shared")
+            .from_query = from_query;
+        self
+    }
+
+    /// Keep the parameters named `sensitive` out of [`redacted_params`](Self::redacted_params)
+    /// and error messages.  Only valid before the context is shared.
+    pub(crate) fn with_sensitive(mut self, sensitive: Vec<&'static str>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("sensitive parameters are set before the context is shared")
+            .sensitive = sensitive;
+        self
+    }
+
+    /// Let streaming methods leave their result to the transport.  Only valid before the
+    /// context is shared.
+    pub(crate) fn with_streaming(mut self, streams: bool) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("streaming is set before the context is shared")
+            .streams = streams;
+        self
+    }
+
+    /// Continue the caller's trace instead of starting a new one.  Only valid before the context
+    /// is shared.
+    pub(crate) fn with_trace(mut self, trace: Option<TraceContext>) -> Self {
+        if let Some(trace) = trace {
+            Arc::get_mut(&mut self.inner)
+                .expect("trace is set before the context is shared")
+                .trace = trace;
+        }
+        self
+    }
+
+    /// The context of the request being served by the calling task, or `None` when called
+    /// outside of a server (for example when invoking `dispatch` directly).
+    pub fn current() -> Option<RequestContext> {
+        CURRENT.try_with(|ctx| ctx.clone()).ok()
+    }
+
+    /// An id for correlating log lines about this call: the caller's `X-Request-Id` header if
+    /// it sent one, otherwise generated by the server.
+    pub fn request_id(&self) -> &str {
+        &self.inner.request_id
+    }
+
+    /// The request's span of a distributed trace; see [`crate::trace`].
+    pub fn trace(&self) -> &TraceContext {
+        &self.inner.trace
+    }
+
+    /// The name of the method being called.
+    pub fn method(&self) -> &str {
+        &self.inner.method
+    }
+
+    /// The raw JSON parameters of the call.  Parameters marked `#[sensitive(encrypted)]` are
+    /// still encrypted.
+    pub fn params(&self) -> &str {
+        &self.inner.params
+    }
+
+    /// The JSON parameters of the call with those marked `#[sensitive]` replaced by
+    /// `"[REDACTED]"`, for logging and auditing.  Parameters that are not valid JSON are left
+    /// out entirely if the method has sensitive parameters.
+    pub fn redacted_params(&self) -> String {
+        if self.inner.sensitive.is_empty() {
+            return self.inner.params.clone();
+        }
+        crate::slowlog::redact(&self.inner.params, &[], &self.inner.sensitive, usize::MAX)
+    }
+
+    /// The value of the request header `name`, if it was sent and is visible ASCII.  Only HTTP
+    /// calls have headers.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.inner.headers.get(name)?.to_str().ok()
+    }
+
+    /// The value of the cookie `name` from the request's `Cookie` headers, without surrounding
+    /// quotes.  Only HTTP calls have cookies.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.inner
+            .headers
+            .get_all(hyper::header::COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(cookie, _)| *cookie == name)
+            .map(|(_, value)| value.trim_matches('"'))
+    }
+
+    /// The server-wide resource of type `T`, if one was registered with
+    /// [`crate::ServerOptions::resource`].  This is how handlers reach shared infrastructure such
+    /// as connection pools without threading it through the actor's own fields.
+    pub fn resource<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.inner.resources.get()
+    }
+
+    /// The per-request value of type `T`, if the server attached one to this request (e.g. the
+    /// request's database transaction).
+    pub fn extension<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let extensions = self.inner.extensions.lock().unwrap();
+        let value = extensions.get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    pub(crate) fn insert_extension<T: Any + Send + Sync>(&self, value: T) {
+        self.inner
+            .extensions
+            .lock()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// When the caller stops waiting for the response, if it sent a deadline (see the
+    /// `X-Request-Deadline` and `grpc-timeout` headers).  The server gives up on the call at
+    /// this point and answers `504 Gateway Timeout`.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.inner.deadline
+    }
+
+    /// How much of the caller's budget is left, or `None` without a deadline.  Methods can use
+    /// this to skip optional work or pass a smaller timeout to a slow dependency.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.inner
+            .deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Mark the call as failed, so work done on its behalf (such as its database transaction)
+    /// is rolled back.  Methods returning a `Result` are marked automatically when they return
+    /// `Err`.
+    pub fn set_failed(&self) {
+        self.inner.failed.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the call has been marked as failed.
+    pub fn failed(&self) -> bool {
+        self.inner.failed.load(Ordering::SeqCst)
+    }
+
+    /// Whether the caller has gone away (closed its connection, reset its HTTP/2 stream or
+    /// dropped its WebSocket) before the call was answered.  Nobody will read the response, so
+    /// long-running work, such as a `#[blocking]` method or a task the method spawned, can stop.
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.cancelled.borrow()
+    }
+
+    /// Wait until the caller has gone away; see [`is_cancelled`](Self::is_cancelled).  Meant for
+    /// `tokio::select!` against the work it would stop.  Never completes for calls that are
+    /// answered.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.inner.cancelled.subscribe();
+        // The sender lives as long as this context, so waiting cannot fail
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+
+    /// Cancel the call if the returned guard is dropped before it is disarmed, as it is when
+    /// the transport drops an unanswered call because the caller went away.
+    pub(crate) fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop(Some(self.clone()))
+    }
+
+    /// Hand this call over to `target`: once the method returns, the server sends the same
+    /// method and parameters to `target` and relays its response to the caller.  The actor is
+    /// free to serve other requests while the downstream call is in flight, so routers and
+    /// gateways can be written as ordinary actors:
+    ///
+    /// ```rust
+    /// use simple_json_server::{actor, Actor, ActorRef, Forward, RequestContext};
+    ///
+    /// #[derive(Clone)]
+    /// struct Router {
+    ///     shards: Vec<ActorRef>,
+    /// }
+    ///
+    /// #[actor]
+    /// impl Router {
+    ///     pub async fn lookup(&self, key: String) -> Forward {
+    ///         let shard = &self.shards[key.len() % self.shards.len()];
+    ///         RequestContext::current().unwrap().forward(shard)
+    ///     }
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub fn forward(&self, target: &ActorRef) -> Forward {
+        *self.inner.forward.lock().unwrap() = Some(target.clone());
+        Forward
+    }
+
+    /// Run `future` with this context as the current one.
+    pub(crate) async fn scope<F: std::future::Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// Record how long the call waited for the actor's mailbox.
+    pub(crate) fn set_queue_wait(&self, wait: Duration) {
+        *self.inner.queue_wait.lock().unwrap() = wait;
+    }
+
+    /// How long the call waited for the actor's mailbox.
+    pub(crate) fn queue_wait(&self) -> Duration {
+        *self.inner.queue_wait.lock().unwrap()
+    }
+
+    /// Answer the call with `status` instead of `200 OK`, e.g. when its parameters were invalid.
+    pub(crate) fn set_status(&self, status: StatusCode) {
+        *self.inner.status.lock().unwrap() = Some(status);
+    }
+
+    /// The status set with [`RequestContext::set_status`], if any.
+    pub(crate) fn status(&self) -> Option<StatusCode> {
+        *self.inner.status.lock().unwrap()
+    }
+
+    /// The forwarding target set by the method, if any.
+    pub(crate) fn take_forward(&self) -> Option<ActorRef> {
+        self.inner.forward.lock().unwrap().take()
+    }
+
+    /// The method's parameters marked `#[sensitive]`.
+    pub(crate) fn sensitive(&self) -> &[&'static str] {
+        &self.inner.sensitive
+    }
+
+    /// Whether a streaming method may leave its result to the transport.
+    pub(crate) fn params_from_query(&self) -> bool {
+        self.inner.from_query
+    }
+
+    pub(crate) fn streams(&self) -> bool {
+        self.inner.streams
+    }
+
+    /// Leave the method's result to the transport to serialize.
+    pub(crate) fn set_stream(&self, result: Streamed) {
+        *self.inner.stream.lock().unwrap() = Some(result);
+    }
+
+    /// The result left by a streaming method, if any.
+    pub(crate) fn take_stream(&self) -> Option<Streamed> {
+        self.inner.stream.lock().unwrap().take()
+    }
+}
+
+/// Cancels its call when dropped, unless [disarmed](Self::disarm) once the call is answered.
+pub(crate) struct CancelOnDrop(Option<RequestContext>);
+
+impl CancelOnDrop {
+    pub(crate) fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.0.take() {
+            log::debug!(
+                "Caller went away before {} was answered (request {})",
+                ctx.method(),
+                ctx.request_id()
+            );
+            ctx.inner.cancelled.send_replace(true);
+        }
+    }
+}
+
+/// A request id unique within this process and unlikely to repeat across processes.
+fn generate_request_id() -> String {
+    static PREFIX: OnceLock<u32> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let prefix = PREFIX.get_or_init(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        nanos ^ std::process::id().rotate_left(16)
+    });
+    format!("{:08x}-{:x}", prefix, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// The `X-Request-Id` header value, if it is a reasonable id to repeat in logs.
+pub(crate) fn request_id_from_headers(headers: &hyper::HeaderMap) -> Option<String> {
+    let id = headers.get("x-request-id")?.to_str().ok()?;
+    let valid = !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_current_is_scoped() {
+        assert!(RequestContext::current().is_none());
+
+        let ctx = RequestContext::new("add", r#"{"a": 1}"#, Resources::default());
+        let method = ctx
+            .clone()
+            .scope(async { RequestContext::current().unwrap().method().to_string() })
+            .await;
+        assert_eq!(method, "add");
+        assert!(RequestContext::current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_forward_is_recorded() {
+        let ctx = RequestContext::new("add", "{}", Resources::default());
+        let target = ActorRef::new("http://127.0.0.1:9");
+        ctx.clone()
+            .scope(async { RequestContext::current().unwrap().forward(&target) })
+            .await;
+        assert_eq!(ctx.take_forward().unwrap().url(), "http://127.0.0.1:9");
+        assert!(ctx.take_forward().is_none());
+    }
+
+    struct Fallible;
+
+    #[crate::actor]
+    impl Fallible {
+        pub async fn check(&self, ok: bool) -> Result<u32, String> {
+            let limit = RequestContext::current()
+                .unwrap()
+                .resource::<u32>()
+                .copied();
+            if ok {
+                limit.ok_or_else(|| "no limit".to_string())
+            } else {
+                Err("not ok".to_string())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_err_results_mark_the_call_failed() {
+        use crate::Actor;
+
+        let mut resources = Resources::default();
+        resources.insert(10u32);
+        for (params, failed) in [(r#"{"ok": true}"#, false), (r#"{"ok": false}"#, true)] {
+            let ctx = RequestContext::new("check", params, resources.clone());
+            ctx.clone().scope(Fallible.dispatch("check", params)).await;
+            assert_eq!(ctx.failed(), failed);
+        }
+    }
+
+    #[test]
+    fn test_resources_and_extensions_by_type() {
+        let mut resources = Resources::default();
+        resources.insert(42u32);
+        resources.insert("pool".to_string());
+
+        let ctx = RequestContext::new("add", "{}", resources);
+        assert_eq!(ctx.resource::<u32>(), Some(&42));
+        assert_eq!(ctx.resource::<String>().unwrap(), "pool");
+        assert!(ctx.resource::<i64>().is_none());
+
+        assert!(ctx.extension::<u8>().is_none());
+        ctx.insert_extension(7u8);
+        assert_eq!(*ctx.extension::<u8>().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_request_ids() {
+        let a = RequestContext::new("add", "{}", Resources::default());
+        let b = RequestContext::new("add", "{}", Resources::default());
+        assert_ne!(a.request_id(), b.request_id());
+        let c = RequestContext::new("add", "{}", Resources::default())
+            .with_request_id(Some("abc-123".to_string()));
+        assert_eq!(c.request_id(), "abc-123");
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("x-request-id", "abc-123".parse().unwrap());
+        assert_eq!(
+            request_id_from_headers(&headers).as_deref(),
+            Some("abc-123")
+        );
+        headers.insert("x-request-id", "has spaces".parse().unwrap());
+        assert_eq!(request_id_from_headers(&headers), None);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_calls_are_cancelled() {
+        let answered = RequestContext::new("add", "{}", Resources::default());
+        answered.cancel_on_drop().disarm();
+        assert!(!answered.is_cancelled());
+
+        let dropped = RequestContext::new("add", "{}", Resources::default());
+        let waiting = tokio::spawn({
+            let dropped = dropped.clone();
+            async move { dropped.cancelled().await }
+        });
+        drop(dropped.cancel_on_drop());
+        assert!(dropped.is_cancelled());
+        waiting.await.unwrap();
+    }
+
+    #[test]
+    fn test_headers_and_cookies() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        headers.append("cookie", "theme=dark; session=\"abc\"".parse().unwrap());
+        headers.append("cookie", "lang=en".parse().unwrap());
+        let ctx = RequestContext::new("add", "{}", Resources::default()).with_headers(headers);
+        assert_eq!(ctx.header("X-Tenant-Id"), Some("acme"));
+        assert_eq!(ctx.header("x-missing"), None);
+        assert_eq!(ctx.cookie("session"), Some("abc"));
+        assert_eq!(ctx.cookie("lang"), Some("en"));
+        assert_eq!(ctx.cookie("sess"), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! A dead-letter store for calls that nobody is waiting on and that keep failing.
+//!
+//! Events delivered to actors by [`EventBus::subscribe`](crate::events::EventBus::subscribe)
+//! have no caller to report a failure to.  Without a dead-letter store a failed delivery is
+//! logged and forgotten; with one, it is retried a few times and then kept, with the error, so
+//! it can be inspected and sent again once the problem is fixed.
+//!
+//! ```rust,no_run
+//! use simple_json_server::deadletter::DeadLetters;
+//! use simple_json_server::events::EventBus;
+//!
+//! # async fn example() {
+//! let dead_letters = DeadLetters::new().attempts(3);
+//! let events = EventBus::new().dead_letters(dead_letters.clone());
+//!
+//! // Later, e.g. after deploying a fix
+//! for letter in dead_letters.list() {
+//!     println!("{} failed {} times: {}", letter.method, letter.attempts, letter.error);
+//! }
+//! dead_letters.requeue_all().await;
+//! # }
+//! ```
+//!
+//! A call fails if it cannot be delivered, is answered with an error status, or the method
+//! returns `Err` (answered as `{"Err": ...}`).  The store is in memory and bounded: once it holds
+//! [`DeadLetters::capacity`] letters, the oldest is dropped to make room.
+
+use crate::ActorRef;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A call that failed every attempt.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    /// Identifies the letter in the store.
+    pub id: u64,
+    /// The method that was called.
+    pub method: String,
+    /// The parameters it was called with.
+    pub params: Value,
+    /// The error from the last attempt.
+    pub error: String,
+    /// How many times the call was attempted.
+    pub attempts: u32,
+    /// When the last attempt failed.
+    pub failed_at: SystemTime,
+    actor: ActorRef,
+}
+
+impl DeadLetter {
+    /// The URL of the actor the call was for.
+    pub fn url(&self) -> &str {
+        self.actor.url()
+    }
+}
+
+/// Retry settings and the store of dead letters.  Clones share the same store.
+#[derive(Clone)]
+pub struct DeadLetters {
+    attempts: u32,
+    retry_delay: Duration,
+    capacity: usize,
+    next_id: Arc<AtomicU64>,
+    letters: Arc<Mutex<VecDeque<DeadLetter>>>,
+}
+
+impl fmt::Debug for DeadLetters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetters")
+            .field("attempts", &self.attempts)
+            .field("retry_delay", &self.retry_delay)
+            .field("letters", &self.len())
+            .finish()
+    }
+}
+
+impl Default for DeadLetters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeadLetters {
+    /// An empty store.  Calls are attempted 3 times, 100 milliseconds apart, and up to 10,000
+    /// letters are kept.
+    pub fn new() -> Self {
+        Self {
+            attempts: 3,
+            retry_delay: Duration::from_millis(100),
+            capacity: 10_000,
+            next_id: Arc::new(AtomicU64::new(1)),
+            letters: Arc::default(),
+        }
+    }
+
+    /// How many times a call is attempted before it becomes a dead letter.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// How long to wait between attempts.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Keep at most `capacity` letters, dropping the oldest beyond that.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// The letters in the store, oldest first.
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// How many letters are in the store.
+    pub fn len(&self) -> usize {
+        self.letters.lock().unwrap().len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove the letter `id` from the store without sending it again.
+    pub fn discard(&self, id: u64) -> Option<DeadLetter> {
+        let mut letters = self.letters.lock().unwrap();
+        let index = letters.iter().position(|letter| letter.id == id)?;
+        letters.remove(index)
+    }
+
+    /// Send the letter `id` again, once.  On success it leaves the store and the response body
+    /// is returned; on failure it stays, with the new error.  `None` if there is no such letter.
+    pub async fn requeue(&self, id: u64) -> Option<Result<String, String>> {
+        let mut letter = self.discard(id)?;
+        let result = attempt(&letter.actor, &letter.method, letter.params.to_string()).await;
+        if let Err(e) = &result {
+            letter.error = e.clone();
+            letter.attempts += 1;
+            letter.failed_at = SystemTime::now();
+            self.letters.lock().unwrap().push_back(letter);
+        }
+        Some(result)
+    }
+
+    /// Send every letter in the store again, once, and return how many succeeded.
+    pub async fn requeue_all(&self) -> usize {
+        let ids: Vec<u64> = self.list().iter().map(|letter| letter.id).collect();
+        let mut succeeded = 0;
+        for id in ids {
+            if let Some(Ok(_)) = self.requeue(id).await {
+                succeeded += 1;
+            }
+        }
+        succeeded
+    }
+
+    /// Call `method` on `actor` with `params`, retrying, and store it as a dead letter if every
+    /// attempt fails.
+    pub(crate) async fn deliver(&self, actor: &ActorRef, method: &str, params: Value) {
+        let body = params.to_string();
+        let mut attempts = 0;
+        let error = loop {
+            attempts += 1;
+            match attempt(actor, method, body.clone()).await {
+                Ok(_) => return,
+                Err(e) if attempts >= self.attempts => break e,
+                Err(_) => tokio::time::sleep(self.retry_delay).await,
+            }
+        };
+        log::warn!(
+            "Call to {} on {} failed {} times, keeping it as a dead letter: {}",
+            method,
+            actor.url(),
+            attempts,
+            error
+        );
+        let mut letters = self.letters.lock().unwrap();
+        if letters.len() >= self.capacity {
+            letters.pop_front();
+        }
+        letters.push_back(DeadLetter {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            method: method.to_string(),
+            params,
+            error,
+            attempts,
+            failed_at: SystemTime::now(),
+            actor: actor.clone(),
+        });
+    }
+}
+
+/// Make a call, counting a method's `Err` as a failure as well as transport and status errors.
+pub(crate) async fn attempt(
+    actor: &ActorRef,
+    method: &str,
+    body: String,
+) -> Result<String, String> {
+    let response = actor
+        .call_raw(method, body)
+        .await
+        .map_err(|e| e.to_string())?;
+    match serde_json::from_str::<Value>(&response) {
+        Ok(Value::Object(object)) if object.len() == 1 && object.contains_key("Err") => {
+            Err(format!("{} returned {}", method, object["Err"]))
+        }
+        _ => Ok(response),
+    }
+}
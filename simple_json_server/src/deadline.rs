@@ -0,0 +1,110 @@
+//! Request deadlines carried in HTTP headers.
+//!
+//! A caller can say how long it is willing to wait with either header:
+//!
+//! - `X-Request-Deadline`: the absolute deadline, in milliseconds since the Unix epoch.
+//! - `grpc-timeout`: the remaining budget in gRPC's format, a number followed by a unit
+//!   (`H`, `M`, `S`, `m`, `u` or `n`), e.g. `250m` for 250 milliseconds.
+//!
+//! If both are present the earlier deadline wins.  [`crate::ActorRef`] sends
+//! `X-Request-Deadline` on calls made while serving a request that has a deadline, so the budget
+//! follows the request from actor to actor.
+
+use hyper::HeaderMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub(crate) const DEADLINE_HEADER: &str = "x-request-deadline";
+pub(crate) const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+/// The deadline requested by `headers`, if any.
+pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Instant> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let absolute = header(DEADLINE_HEADER)
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(|ms| from_unix_ms(ms, SystemTime::now(), Instant::now()));
+    let relative = header(GRPC_TIMEOUT_HEADER)
+        .and_then(parse_grpc_timeout)
+        .map(|timeout| Instant::now() + timeout);
+    match (absolute, relative) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The `X-Request-Deadline` header value for `deadline`.
+pub(crate) fn to_header(deadline: Instant) -> String {
+    to_unix_ms(deadline, SystemTime::now(), Instant::now()).to_string()
+}
+
+fn from_unix_ms(ms: u64, now: SystemTime, now_instant: Instant) -> Instant {
+    let deadline = UNIX_EPOCH + Duration::from_millis(ms);
+    match deadline.duration_since(now) {
+        Ok(remaining) => now_instant + remaining,
+        // Already passed; how long ago does not matter
+        Err(_) => now_instant,
+    }
+}
+
+fn to_unix_ms(deadline: Instant, now: SystemTime, now_instant: Instant) -> u64 {
+    let deadline = now + deadline.saturating_duration_since(now_instant);
+    deadline
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 3600),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_grpc_timeout("2S"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_grpc_timeout("10"), None);
+        assert_eq!(parse_grpc_timeout("m"), None);
+        assert_eq!(parse_grpc_timeout("1234567890S"), None);
+    }
+
+    #[test]
+    fn test_unix_ms_round_trip() {
+        let now = SystemTime::now();
+        let now_instant = Instant::now();
+        let deadline = now_instant + Duration::from_millis(1500);
+        let ms = to_unix_ms(deadline, now, now_instant);
+        let restored = from_unix_ms(ms, now, now_instant);
+        assert!(deadline - restored < Duration::from_millis(1));
+
+        // Deadlines in the past are clamped to now
+        assert_eq!(from_unix_ms(0, now, now_instant), now_instant);
+    }
+
+    #[test]
+    fn test_earlier_header_wins() {
+        let mut headers = HeaderMap::new();
+        headers.insert(GRPC_TIMEOUT_HEADER, "1S".parse().unwrap());
+        let relative = from_headers(&headers).unwrap();
+
+        headers.insert(DEADLINE_HEADER, to_header(Instant::now()).parse().unwrap());
+        assert!(from_headers(&headers).unwrap() < relative);
+    }
+}
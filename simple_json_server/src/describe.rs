@@ -0,0 +1,84 @@
+//! Descriptions of methods for `OPTIONS /<method>`.
+//!
+//! Generic HTTP tooling discovers what a resource supports with `OPTIONS`.  For a method of the
+//! actor the server answers with an `Allow` header and a JSON description of how to call it:
+//! its parameters and result, the attributes it was declared with, and what a call must carry
+//! to be accepted.  CORS preflights, which send `Access-Control-Request-Method`, are answered as
+//! before.
+
+use crate::server::ServerState;
+use crate::{MethodInfo, MethodKind, ParamSource};
+use serde_json::{json, Value};
+
+/// The methods the path of `info` accepts, for the `Allow` header.
+pub(crate) fn allow(info: Option<&MethodInfo>) -> &'static str {
+    if info.is_some_and(|info| info.get) {
+        "GET, HEAD, POST, OPTIONS"
+    } else {
+        "POST, OPTIONS"
+    }
+}
+
+/// Describe `info` as served by `state`.
+pub(crate) fn describe<T>(state: &ServerState<T>, info: &MethodInfo) -> Value {
+    let options = &state.options;
+    let params: Vec<Value> = info
+        .params
+        .iter()
+        .map(|param| {
+            // Parameters read from the request name the header or cookie holding them
+            let (source, key) = match param.source {
+                ParamSource::Body => ("body", None),
+                ParamSource::Header(name) => ("header", Some(name)),
+                ParamSource::Cookie(name) => ("cookie", Some(name)),
+            };
+            json!({ "name": param.name, "type": param.ty, "in": source, "key": key })
+        })
+        .collect();
+    let examples: Vec<Value> = info
+        .examples
+        .iter()
+        .map(|example| {
+            json!({
+                "params": parse(example.params),
+                "response": example.response.map(parse),
+            })
+        })
+        .collect();
+    let deprecated = info
+        .deprecated
+        .map(|deprecated| json!({ "note": deprecated.note, "sunset": deprecated.sunset }));
+    let requires = json!({
+        "content_type": options
+            .content_types
+            .as_ref()
+            .map(|content_types| content_types.allowed(info.accepts)),
+        "client_header": options
+            .quotas
+            .as_ref()
+            .and_then(|quotas| quotas.required_header()),
+    });
+    json!({
+        "name": info.name,
+        "doc": info.doc,
+        "kind": match info.kind {
+            MethodKind::Read => "read",
+            MethodKind::Write => "write",
+        },
+        "params": params,
+        "returns": info.returns,
+        "exclusive": info.exclusive,
+        "max_concurrency": info.max_concurrency,
+        "blocking": info.blocking,
+        "streaming": info.streaming,
+        "get": info.get,
+        "deprecated": deprecated,
+        "examples": examples,
+        "requires": requires,
+    })
+}
+
+/// An example's JSON, checked at compile time to be valid.
+fn parse(json: &str) -> Value {
+    serde_json::from_str(json).unwrap_or(Value::Null)
+}
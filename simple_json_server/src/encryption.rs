@@ -0,0 +1,314 @@
+//! Authenticated encryption of actor state written to disk, and of sensitive parameters, with
+//! AES-256-GCM.
+//!
+//! An [`Encryption`] holds a 256-bit key, given as 32 raw bytes or their base64 (as printed by
+//! `openssl rand -base64 32`), typically fetched from a [`Secret`] with
+//! [`Encryption::from_secret`].  Snapshots are encrypted by wrapping their store in an
+//! [`EncryptedSnapshots`](crate::snapshot::EncryptedSnapshots), and the values of an
+//! [`ActorStore`](crate::store::ActorStore) with `ActorStore::encrypted`.
+//!
+//! ```rust,no_run
+//! use simple_json_server::encryption::Encryption;
+//! use simple_json_server::secrets::Secret;
+//! use simple_json_server::snapshot::{EncryptedSnapshots, FileSnapshotStore};
+//! use simple_json_server::ServerOptions;
+//!
+//! # async fn example() -> Result<(), simple_json_server::encryption::EncryptionError> {
+//! let encryption = Encryption::from_secret(&Secret::env("SNAPSHOT_KEY")).await?;
+//! let store = EncryptedSnapshots::new(FileSnapshotStore::new("/var/lib/inventory"), encryption);
+//! let options = ServerOptions::new(8080).snapshots(store).restore_on_start(true);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each value is sealed with a fresh random nonce and bound to where it is stored (the
+//! snapshot's timestamp, or the store's namespace and key), so a value copied elsewhere fails
+//! to decrypt rather than being read as another.  Values that were tampered with, were
+//! encrypted with another key, or were never encrypted are refused with an
+//! [`EncryptionError`].
+//!
+//! To rotate keys, encrypt with the new key and keep the old one with
+//! [`Encryption::previous_key`] until everything written with it has been rewritten.
+//!
+//! [Journals](crate::journal) are kept in memory and never written to disk, so there is
+//! nothing of theirs to encrypt.
+//!
+//! # Sensitive parameters
+//!
+//! Parameters marked `#[sensitive(encrypted)]` arrive encrypted and are decrypted by a server
+//! given the key with [`ServerOptions::field_encryption`](crate::ServerOptions::field_encryption),
+//! so the plaintext exists only inside the method.  Callers encrypt them with
+//! [`Encryption::encrypt_param`]:
+//!
+//! ```rust
+//! use serde_json::json;
+//! use simple_json_server::encryption::Encryption;
+//!
+//! let encryption = Encryption::new([7; 32]).unwrap();
+//! let params = json!({
+//!     "amount": 100,
+//!     "card": encryption.encrypt_param("pay", "card", &json!("4111 1111 1111 1111")),
+//! });
+//! ```
+//!
+//! Journals and replicas see these parameters encrypted too; replicas decrypt them with their
+//! own `field_encryption` key.
+
+use crate::secrets::{Secret, SecretError};
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Marks an encrypted value, and the format it is in.
+const PREFIX: &str = "sjs-aes256gcm:";
+
+/// Bytes of nonce at the start of every sealed value.
+const NONCE_LEN: usize = 12;
+
+/// Why a key couldn't be used, or a value couldn't be decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// The key is not 32 bytes, raw or base64.
+    Key(String),
+    /// The key couldn't be fetched from its secret.
+    Secret(SecretError),
+    /// The value is not encrypted.
+    NotEncrypted,
+    /// The value was tampered with, moved, or encrypted with a key not given.
+    Undecryptable,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::Key(e) => write!(f, "Invalid encryption key: {}", e),
+            EncryptionError::Secret(e) => write!(f, "Failed to fetch the encryption key: {}", e),
+            EncryptionError::NotEncrypted => write!(f, "The value is not encrypted"),
+            EncryptionError::Undecryptable => write!(
+                f,
+                "The value can't be decrypted: it was tampered with or encrypted with another key"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// The key values are encrypted with, and earlier keys they can still be decrypted with; see
+/// the [module documentation](self).  Clones share the keys.
+#[derive(Clone)]
+pub struct Encryption {
+    /// The current key first.
+    keys: Arc<Vec<Aes256Gcm>>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryption")
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+impl Encryption {
+    /// Encrypt with `key`, 32 raw bytes or their base64.
+    pub fn new(key: impl AsRef<[u8]>) -> Result<Self, EncryptionError> {
+        Ok(Self {
+            keys: Arc::new(vec![cipher(key.as_ref())?]),
+        })
+    }
+
+    /// Encrypt with the key held by `secret`.
+    pub async fn from_secret(secret: &Secret) -> Result<Self, EncryptionError> {
+        let key = secret.fetch().await.map_err(EncryptionError::Secret)?;
+        Self::new(key)
+    }
+
+    /// Also decrypt values encrypted with `key`, an earlier key.
+    pub fn previous_key(mut self, key: impl AsRef<[u8]>) -> Result<Self, EncryptionError> {
+        let cipher = cipher(key.as_ref())?;
+        Arc::make_mut(&mut self.keys).push(cipher);
+        Ok(self)
+    }
+
+    /// Encrypt `plaintext`, bound to `context`, as text.
+    pub fn encrypt(&self, plaintext: &[u8], context: &[u8]) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad: context,
+        };
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.keys[0]
+                .encrypt(&nonce, payload)
+                .expect("AES-GCM encrypts values of any reasonable size"),
+        );
+        format!("{}{}", PREFIX, STANDARD.encode(sealed))
+    }
+
+    /// Decrypt `text`, which [`Encryption::encrypt`] made with the same `context`.
+    pub fn decrypt(&self, text: &str, context: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let sealed = text
+            .strip_prefix(PREFIX)
+            .ok_or(EncryptionError::NotEncrypted)?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|_| EncryptionError::Undecryptable)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(EncryptionError::Undecryptable);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::from(<[u8; NONCE_LEN]>::try_from(nonce).expect("split at its length"));
+        self.keys
+            .iter()
+            .find_map(|key| {
+                let payload = Payload {
+                    msg: ciphertext,
+                    aad: context,
+                };
+                key.decrypt(&nonce, payload).ok()
+            })
+            .ok_or(EncryptionError::Undecryptable)
+    }
+
+    /// Like [`Encryption::decrypt`], for text that was encrypted.
+    pub(crate) fn decrypt_string(
+        &self,
+        text: &str,
+        context: &[u8],
+    ) -> Result<String, EncryptionError> {
+        String::from_utf8(self.decrypt(text, context)?).map_err(|_| EncryptionError::Undecryptable)
+    }
+
+    /// Encrypt `value` as the parameter `param` of `method`, marked `#[sensitive(encrypted)]`.
+    /// The result is a JSON string to send in its place.
+    pub fn encrypt_param(&self, method: &str, param: &str, value: &Value) -> Value {
+        let context = param_context(method, param);
+        Value::String(self.encrypt(value.to_string().as_bytes(), &context))
+    }
+
+    /// Decrypt the parameter `param` of `method` from what [`Encryption::encrypt_param`] made.
+    pub(crate) fn decrypt_param(
+        &self,
+        method: &str,
+        param: &str,
+        value: &Value,
+    ) -> Result<Value, EncryptionError> {
+        let text = value.as_str().ok_or(EncryptionError::NotEncrypted)?;
+        let plaintext = self.decrypt(text, &param_context(method, param))?;
+        serde_json::from_slice(&plaintext).map_err(|_| EncryptionError::Undecryptable)
+    }
+}
+
+/// What a parameter's value is bound to, so it can't be moved to another parameter or method.
+fn param_context(method: &str, param: &str) -> Vec<u8> {
+    format!("{}\0{}", method, param).into_bytes()
+}
+
+fn cipher(key: &[u8]) -> Result<Aes256Gcm, EncryptionError> {
+    let decoded;
+    let key = match key.len() {
+        32 => key,
+        _ => {
+            let text = std::str::from_utf8(key)
+                .map_err(|_| EncryptionError::Key(format!("{} bytes, not 32", key.len())))?;
+            decoded = STANDARD.decode(text.trim()).map_err(|_| {
+                EncryptionError::Key(format!("{} bytes, not 32 or their base64", key.len()))
+            })?;
+            &decoded
+        }
+    };
+    Aes256Gcm::new_from_slice(key)
+        .map_err(|_| EncryptionError::Key(format!("{} bytes, not 32", key.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_values_round_trip_in_their_context_only() {
+        let encryption = Encryption::new(KEY).unwrap();
+        let sealed = encryption.encrypt(b"balance: 100", b"accounts/1");
+        assert!(sealed.starts_with(PREFIX));
+        assert!(!sealed.contains("balance"));
+        assert_ne!(sealed, encryption.encrypt(b"balance: 100", b"accounts/1"));
+        assert_eq!(
+            encryption.decrypt(&sealed, b"accounts/1").unwrap(),
+            b"balance: 100"
+        );
+
+        assert_eq!(
+            encryption.decrypt(&sealed, b"accounts/2"),
+            Err(EncryptionError::Undecryptable)
+        );
+        let mut tampered = sealed.clone().into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_eq!(
+            encryption.decrypt(&String::from_utf8(tampered).unwrap(), b"accounts/1"),
+            Err(EncryptionError::Undecryptable)
+        );
+        assert_eq!(
+            encryption.decrypt("balance: 100", b"accounts/1"),
+            Err(EncryptionError::NotEncrypted)
+        );
+    }
+
+    #[test]
+    fn test_params_are_bound_to_their_method_and_name() {
+        let encryption = Encryption::new(KEY).unwrap();
+        let value = serde_json::json!({"number": "4111", "cvc": 123});
+        let sealed = encryption.encrypt_param("pay", "card", &value);
+        assert!(!sealed.to_string().contains("4111"));
+        assert_eq!(
+            encryption.decrypt_param("pay", "card", &sealed).unwrap(),
+            value
+        );
+        assert_eq!(
+            encryption.decrypt_param("pay", "billing", &sealed),
+            Err(EncryptionError::Undecryptable)
+        );
+        assert_eq!(
+            encryption.decrypt_param("refund", "card", &sealed),
+            Err(EncryptionError::Undecryptable)
+        );
+        assert_eq!(
+            encryption.decrypt_param("pay", "card", &value),
+            Err(EncryptionError::NotEncrypted)
+        );
+    }
+
+    #[test]
+    fn test_keys_rotate() {
+        let old = Encryption::new(KEY).unwrap();
+        let sealed = old.encrypt(b"state", b"");
+
+        let new_key = STANDARD.encode([9; 32]);
+        let new = Encryption::new(&new_key).unwrap();
+        assert_eq!(
+            new.decrypt(&sealed, b""),
+            Err(EncryptionError::Undecryptable)
+        );
+        let rotated = new.previous_key(KEY).unwrap();
+        assert_eq!(rotated.decrypt(&sealed, b"").unwrap(), b"state");
+        // New values use the new key
+        let resealed = rotated.encrypt(b"state", b"");
+        assert_eq!(
+            old.decrypt(&resealed, b""),
+            Err(EncryptionError::Undecryptable)
+        );
+
+        assert!(matches!(
+            Encryption::new("too short"),
+            Err(EncryptionError::Key(_))
+        ));
+    }
+}
@@ -0,0 +1,575 @@
+//! Server push: events published by an actor and delivered to WebSocket subscribers.
+//!
+//! An actor keeps a clone of an [`EventBus`] and publishes to named topics; the same bus is
+//! passed to [`ServerOptions::events`](crate::ServerOptions::events) so WebSocket clients can
+//! subscribe.  On a WebSocket connection a client sends `{"subscribe": "<topic>"}` and gets
+//! `{"subscribed": "<topic>"}` back; from then on every event on the topic arrives as
+//! `{"event": "<topic>", "data": ...}` until it sends `{"unsubscribe": "<topic>"}`.  A client
+//! that falls too far behind is sent `{"lagged": N}` with the number of events it missed.
+//!
+//! ```rust,no_run
+//! use simple_json_server::events::EventBus;
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Ticker {
+//!     events: EventBus,
+//! }
+//!
+//! #[actor]
+//! #[event(topic = "price", payload = r#"{"symbol": "ACME", "price": 12.5}"#)]
+//! impl Ticker {
+//!     pub async fn set_price(&self, symbol: String, price: f64) {
+//!         let _ = self.events.publish("price", &serde_json::json!({ "symbol": symbol, "price": price }));
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let events = EventBus::new();
+//!     let options = ServerOptions::new(8080).websocket(true).events(events.clone());
+//!     Ticker { events }.create_with(options);
+//! }
+//! ```
+//!
+//! Events are not stored for anonymous subscribers: they only see events published while they
+//! are subscribed.
+//!
+//! # Between actors
+//!
+//! Actors in the same process can use a bus to talk without knowing about each other:
+//! [`EventBus::subscribe`] calls a method of an actor, typically an
+//! [`ActorRef::loopback`](crate::ActorRef::loopback) so no network is involved, with the data
+//! of each event published on a topic as the call's parameters.
+//!
+//! ```rust,no_run
+//! use simple_json_server::events::EventBus;
+//! use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+//!
+//! struct Audit;
+//!
+//! #[actor]
+//! impl Audit {
+//!     pub async fn record(&self, symbol: String, price: f64) {
+//!         println!("{} is now {}", symbol, price);
+//!     }
+//! }
+//!
+//! # async fn example() {
+//! let events = EventBus::new();
+//! let audit = ActorRef::loopback(Audit, ServerOptions::default());
+//! events.subscribe("price", audit, "record");
+//! events.publish("price", &serde_json::json!({ "symbol": "ACME", "price": 12.5 })).unwrap();
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! Each subscription makes its calls one at a time, in the order the events were published, so
+//! a slow subscriber only delays itself.  Failed calls are logged and skipped, unless the bus has
+//! a [`DeadLetters`] store: then they are retried, and kept there if they still fail.
+//!
+//! # At-least-once delivery
+//!
+//! A client that cannot afford to miss events names itself when subscribing:
+//! `{"subscribe": "<topic>", "subscriber": "<id>"}`.  Its events then carry a sequence number,
+//! `{"event": "<topic>", "seq": 42, "data": ...}`, and the server keeps each one until the client
+//! acknowledges it with `{"ack": 42}` (which acknowledges every earlier event too).  When the
+//! client reconnects and subscribes again under the same id, the events it has not acknowledged
+//! are sent again first, including those published while it was away.  Events may therefore
+//! arrive twice; the sequence number tells duplicates apart.  The server keeps at most
+//! [`EventBus::max_unacked`] events per subscriber, dropping the oldest (reported with
+//! `{"lagged": N}` when they would have been resent), and forgets subscribers that have been
+//! disconnected for longer than [`EventBus::subscriber_ttl`].
+//!
+//! # Replay
+//!
+//! A bus built with [`EventBus::history`] keeps a log of the latest events of each topic, so a
+//! client can catch up on what it missed and use the events to keep its own copy of some state
+//! in sync.  It subscribes with the sequence number of the last event it saw,
+//! `{"subscribe": "<topic>", "since": 41}`, and is answered with
+//! `{"subscribed": "<topic>", "replayed": N, "complete": true}` followed by the `N` logged events
+//! after 41, then by new events as they are published.  Its events carry sequence numbers as
+//! above; `since` 0 replays the whole log.  `complete` is false when the log no longer reaches
+//! back to the requested event, in which case the client should resynchronize some other way.
+
+use crate::deadletter::DeadLetters;
+use crate::ActorRef;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// How many events a subscriber may fall behind before it starts missing them.
+const CAPACITY: usize = 1024;
+
+/// An event published on a topic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// The topic the event was published on.
+    #[serde(rename = "event")]
+    pub topic: String,
+    /// The event's JSON payload.
+    pub data: Value,
+    /// The event's sequence number, increasing across all topics of a bus.  Only sent to
+    /// subscribers that acknowledge events.
+    #[serde(default, skip_serializing)]
+    pub seq: u64,
+}
+
+impl Event {
+    /// The event as sent to a subscriber that acknowledges events.
+    pub(crate) fn to_acked_frame(&self) -> String {
+        serde_json::json!({ "event": self.topic, "seq": self.seq, "data": self.data }).to_string()
+    }
+}
+
+/// A publish/subscribe channel for events.  Clones publish to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    next_seq: Arc<AtomicU64>,
+    retained: Arc<Mutex<HashMap<String, Retained>>>,
+    max_unacked: usize,
+    subscriber_ttl: Duration,
+    log: Arc<Mutex<HashMap<String, Log>>>,
+    history: usize,
+    dead_letters: Option<DeadLetters>,
+}
+
+/// The events kept for a subscriber until it acknowledges them.
+#[derive(Debug)]
+struct Retained {
+    topics: HashSet<String>,
+    events: VecDeque<Event>,
+    /// Events dropped since they were last resent, for being over the limit.
+    dropped: u64,
+    connections: usize,
+    last_seen: Instant,
+}
+
+/// The latest events of a topic, for replay.
+#[derive(Debug, Default)]
+struct Log {
+    events: VecDeque<Event>,
+    /// The sequence number of the last event dropped from the log.
+    dropped_through: u64,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// A bus with no subscribers yet and no event log.  Acknowledging subscribers have up to
+    /// 1000 events kept for them and are forgotten after 5 minutes away.
+    pub fn new() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            next_seq: Arc::new(AtomicU64::new(1)),
+            retained: Arc::default(),
+            max_unacked: 1000,
+            subscriber_ttl: Duration::from_secs(5 * 60),
+            log: Arc::default(),
+            history: 0,
+            dead_letters: None,
+        }
+    }
+
+    /// Log the latest `events` of each topic, for subscribers to replay.
+    pub fn history(mut self, events: usize) -> Self {
+        self.history = events;
+        self
+    }
+
+    /// Keep at most `max` unacknowledged events per acknowledging subscriber.
+    pub fn max_unacked(mut self, max: usize) -> Self {
+        self.max_unacked = max.max(1);
+        self
+    }
+
+    /// Forget an acknowledging subscriber, and the events kept for it, once it has been
+    /// disconnected for `ttl`.
+    pub fn subscriber_ttl(mut self, ttl: Duration) -> Self {
+        self.subscriber_ttl = ttl;
+        self
+    }
+
+    /// Publish `data` on `topic` to everyone currently subscribed to it, and keep it for the
+    /// acknowledging subscribers of the topic.
+    pub fn publish<T: Serialize>(&self, topic: &str, data: &T) -> Result<(), serde_json::Error> {
+        let event = Event {
+            topic: topic.to_string(),
+            data: serde_json::to_value(data)?,
+            seq: self.next_seq.fetch_add(1, Ordering::SeqCst),
+        };
+        {
+            let mut retained = self.retained.lock().unwrap();
+            retained.retain(|_, subscriber| {
+                subscriber.connections > 0 || subscriber.last_seen.elapsed() < self.subscriber_ttl
+            });
+            for subscriber in retained.values_mut() {
+                if subscriber.topics.contains(topic) {
+                    if subscriber.events.len() >= self.max_unacked {
+                        subscriber.events.pop_front();
+                        subscriber.dropped += 1;
+                    }
+                    subscriber.events.push_back(event.clone());
+                }
+            }
+        }
+        if self.history > 0 {
+            let mut log = self.log.lock().unwrap();
+            let log = log.entry(topic.to_string()).or_default();
+            if log.events.len() >= self.history {
+                if let Some(dropped) = log.events.pop_front() {
+                    log.dropped_through = dropped.seq;
+                }
+            }
+            log.events.push_back(event.clone());
+        }
+        // No receivers is not an error: nobody is listening right now
+        let _ = self.sender.send(event);
+        Ok(())
+    }
+
+    /// Receive every event published from now on, on all topics.
+    pub(crate) fn receiver(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Retry the calls made by [`EventBus::subscribe`] that fail, and keep those that still
+    /// fail in `dead_letters`.  See [`crate::deadletter`].
+    pub fn dead_letters(mut self, dead_letters: DeadLetters) -> Self {
+        self.dead_letters = Some(dead_letters);
+        self
+    }
+
+    /// Call `method` of `actor` with the data of every event published on `topic` from now on,
+    /// until the returned subscription is cancelled.  Must be called within a Tokio runtime.
+    pub fn subscribe(
+        &self,
+        topic: impl Into<String>,
+        actor: ActorRef,
+        method: impl Into<String>,
+    ) -> Subscription {
+        let topic = topic.into();
+        let method = method.into();
+        let mut receiver = self.receiver();
+        let dead_letters = self.dead_letters.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.topic == topic => {
+                        if let Some(dead_letters) = &dead_letters {
+                            dead_letters.deliver(&actor, &method, event.data).await;
+                        } else if let Err(e) = actor.call_raw(&method, event.data.to_string()).await
+                        {
+                            log::warn!(
+                                "Failed to deliver event {} on `{}` to {}: {}",
+                                event.seq,
+                                topic,
+                                method,
+                                e
+                            );
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        log::warn!(
+                            "Subscriber {} to `{}` missed {} events",
+                            method,
+                            topic,
+                            missed
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Subscription { task }
+    }
+
+    /// The logged events of `topic` after `since`, and whether the log still holds all of them.
+    pub(crate) fn replay(&self, topic: &str, since: u64) -> (Vec<Event>, bool) {
+        if self.history == 0 {
+            // Nothing is logged: only a subscriber that has seen everything is up to date
+            let last = self.next_seq.load(Ordering::SeqCst) - 1;
+            return (Vec::new(), since >= last);
+        }
+        let log = self.log.lock().unwrap();
+        match log.get(topic) {
+            Some(log) => (
+                log.events
+                    .iter()
+                    .filter(|event| event.seq > since)
+                    .cloned()
+                    .collect(),
+                log.dropped_through <= since,
+            ),
+            None => (Vec::new(), true),
+        }
+    }
+
+    /// Count a connection of `subscriber`, until the returned guard is dropped.
+    pub(crate) fn attach(&self, subscriber: &str) -> Attached {
+        let mut retained = self.retained.lock().unwrap();
+        let entry = retained
+            .entry(subscriber.to_string())
+            .or_insert_with(|| Retained {
+                topics: HashSet::new(),
+                events: VecDeque::new(),
+                dropped: 0,
+                connections: 0,
+                last_seen: Instant::now(),
+            });
+        entry.connections += 1;
+        Attached {
+            bus: self.clone(),
+            subscriber: subscriber.to_string(),
+        }
+    }
+}
+
+/// An actor's subscription to a topic, from [`EventBus::subscribe`].  Dropping it leaves the
+/// subscription in place.
+#[derive(Debug)]
+pub struct Subscription {
+    task: JoinHandle<()>,
+}
+
+impl Subscription {
+    /// Stop delivering events.  Calls in progress are abandoned.
+    pub fn cancel(self) {
+        self.task.abort();
+    }
+}
+
+/// A connection of an acknowledging subscriber.
+#[derive(Debug)]
+pub(crate) struct Attached {
+    bus: EventBus,
+    subscriber: String,
+}
+
+impl Attached {
+    pub(crate) fn subscriber(&self) -> &str {
+        &self.subscriber
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Retained) -> R) -> Option<R> {
+        let mut retained = self.bus.retained.lock().unwrap();
+        retained.get_mut(&self.subscriber).map(f)
+    }
+
+    /// Keep events of `topic` for the subscriber, and return the frames to resend: the
+    /// unacknowledged events of the topic, preceded by `{"lagged": N}` if some were dropped.
+    pub(crate) fn subscribe(&self, topic: &str) -> Vec<String> {
+        self.with(|subscriber| {
+            subscriber.topics.insert(topic.to_string());
+            let mut frames = Vec::new();
+            if subscriber.dropped > 0 {
+                frames.push(serde_json::json!({ "lagged": subscriber.dropped }).to_string());
+                subscriber.dropped = 0;
+            }
+            frames.extend(
+                subscriber
+                    .events
+                    .iter()
+                    .filter(|event| event.topic == topic)
+                    .map(Event::to_acked_frame),
+            );
+            frames
+        })
+        .unwrap_or_default()
+    }
+
+    /// Stop keeping events of `topic` for the subscriber.
+    pub(crate) fn unsubscribe(&self, topic: &str) {
+        self.with(|subscriber| {
+            subscriber.topics.remove(topic);
+            subscriber.events.retain(|event| event.topic != topic);
+        });
+    }
+
+    /// Whether the subscriber acknowledges the events of `topic`.
+    pub(crate) fn acknowledges(&self, topic: &str) -> bool {
+        self.with(|subscriber| subscriber.topics.contains(topic))
+            .unwrap_or(false)
+    }
+
+    /// Forget the events up to and including `seq`.
+    pub(crate) fn ack(&self, seq: u64) {
+        self.with(|subscriber| subscriber.events.retain(|event| event.seq > seq));
+    }
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        self.with(|subscriber| {
+            subscriber.connections -= 1;
+            subscriber.last_seen = Instant::now();
+        });
+    }
+}
+
+/// A subscription request from a WebSocket client.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Request {
+    Subscribe {
+        topic: String,
+        /// Set by subscribers that acknowledge events.
+        subscriber: Option<String>,
+        /// Set by subscribers replaying the events after a sequence number.
+        since: Option<u64>,
+    },
+    Unsubscribe(String),
+    Ack(u64),
+}
+
+impl Request {
+    /// The request in `message`, if it is `{"subscribe": topic}` (optionally with a
+    /// `"subscriber"` and a `"since"`), `{"unsubscribe": topic}` or `{"ack": seq}`.
+    pub(crate) fn parse(message: &Value) -> Option<Self> {
+        if let Some(topic) = message.get("subscribe").and_then(Value::as_str) {
+            Some(Request::Subscribe {
+                topic: topic.to_string(),
+                subscriber: message
+                    .get("subscriber")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                since: message.get("since").and_then(Value::as_u64),
+            })
+        } else if let Some(seq) = message.get("ack").and_then(Value::as_u64) {
+            Some(Request::Ack(seq))
+        } else {
+            message
+                .get("unsubscribe")
+                .and_then(Value::as_str)
+                .map(|topic| Request::Unsubscribe(topic.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_requests() {
+        assert_eq!(
+            Request::parse(&json!({"subscribe": "price"})),
+            Some(Request::Subscribe {
+                topic: "price".to_string(),
+                subscriber: None,
+                since: None
+            })
+        );
+        assert_eq!(
+            Request::parse(&json!({"subscribe": "price", "subscriber": "tab-1"})),
+            Some(Request::Subscribe {
+                topic: "price".to_string(),
+                subscriber: Some("tab-1".to_string()),
+                since: None
+            })
+        );
+        assert_eq!(
+            Request::parse(&json!({"subscribe": "price", "since": 41})),
+            Some(Request::Subscribe {
+                topic: "price".to_string(),
+                subscriber: None,
+                since: Some(41)
+            })
+        );
+        assert_eq!(Request::parse(&json!({"ack": 7})), Some(Request::Ack(7)));
+        assert_eq!(
+            Request::parse(&json!({"unsubscribe": "price"})),
+            Some(Request::Unsubscribe("price".to_string()))
+        );
+        assert_eq!(
+            Request::parse(&json!({"method": "add", "params": {}})),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_published_events_reach_receivers() {
+        let bus = EventBus::new();
+        bus.publish("before", &1).unwrap();
+        let mut receiver = bus.receiver();
+        bus.clone()
+            .publish("price", &json!({"price": 12.5}))
+            .unwrap();
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"event": "price", "data": {"price": 12.5}})
+        );
+    }
+
+    #[test]
+    fn test_unacked_events_are_kept() {
+        let bus = EventBus::new().max_unacked(2);
+        let attached = bus.attach("tab-1");
+        assert!(attached.subscribe("price").is_empty());
+        for price in 1..=3 {
+            bus.publish("price", &price).unwrap();
+        }
+        bus.publish("other", &0).unwrap();
+        drop(attached);
+
+        // The oldest event was dropped; the others are resent until acknowledged
+        let attached = bus.attach("tab-1");
+        let frames = attached.subscribe("price");
+        assert_eq!(
+            frames,
+            [
+                r#"{"lagged":1}"#,
+                r#"{"data":2,"event":"price","seq":2}"#,
+                r#"{"data":3,"event":"price","seq":3}"#,
+            ]
+        );
+        attached.ack(2);
+        assert_eq!(attached.subscribe("price").len(), 1);
+        attached.ack(3);
+        assert!(attached.subscribe("price").is_empty());
+    }
+
+    #[test]
+    fn test_idle_subscribers_are_forgotten() {
+        let bus = EventBus::new().subscriber_ttl(Duration::ZERO);
+        let attached = bus.attach("tab-1");
+        attached.subscribe("price");
+        drop(attached);
+        bus.publish("price", &1).unwrap();
+        assert!(bus.retained.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_replay_from_the_log() {
+        let bus = EventBus::new().history(2);
+        for price in 1..=3 {
+            bus.publish("price", &price).unwrap();
+        }
+        bus.publish("other", &0).unwrap();
+
+        let (events, complete) = bus.replay("price", 2);
+        assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), [3]);
+        assert!(complete);
+        // The first event was dropped from the log
+        let (events, complete) = bus.replay("price", 0);
+        assert_eq!(events.len(), 2);
+        assert!(!complete);
+        assert_eq!(bus.replay("missing", 0), (Vec::new(), true));
+
+        let unlogged = EventBus::new();
+        assert_eq!(unlogged.replay("price", 0), (Vec::new(), true));
+        unlogged.publish("price", &1).unwrap();
+        assert_eq!(unlogged.replay("price", 0), (Vec::new(), false));
+        assert_eq!(unlogged.replay("price", 1), (Vec::new(), true));
+    }
+}
@@ -0,0 +1,72 @@
+//! Custom answers for calls the actor has no method for and for requests that aren't calls.
+//!
+//! By default a call to an unknown method is answered with the JSON string
+//! `"Unknown method: <name>"` and a request with any verb but `POST` (or `OPTIONS`) with
+//! `405 Method Not Allowed`.  A [`Fallback`] registered with
+//! [`ServerOptions::fallback`](crate::ServerOptions::fallback) answers unknown methods instead,
+//! over HTTP and WebSocket alike, e.g. to return errors in an application's own shape or to send
+//! the call on to another actor:
+//!
+//! ```rust
+//! use simple_json_server::fallback::FallbackResponse;
+//! use simple_json_server::{ActorRef, ServerOptions};
+//!
+//! // Methods this server doesn't know are handled by the legacy service
+//! let options = ServerOptions::new(8080)
+//!     .fallback(ActorRef::new("http://legacy:8080"))
+//!     .method_not_allowed(FallbackResponse::json(
+//!         405,
+//!         &serde_json::json!({"error": "use POST /<method>"}),
+//!     ));
+//! ```
+//!
+//! The fallback runs with the call's [`RequestContext`](crate::RequestContext), so a forwarded
+//! call keeps its deadline.  Quotas apply to fallback calls, but the mailbox, the journal and
+//! the statistics don't.
+
+use crate::{ActorRef, ClientError};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Serialize;
+
+/// A response made by a fallback: a status code and a JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackResponse {
+    /// The HTTP status code.  WebSocket clients only see the body.
+    pub status: u16,
+    /// The body, already encoded as JSON.
+    pub body: String,
+}
+
+impl FallbackResponse {
+    /// A response whose body is `body` encoded as JSON.
+    pub fn json(status: u16, body: &impl Serialize) -> Self {
+        Self {
+            status,
+            body: serde_json::to_string(body).unwrap_or_default(),
+        }
+    }
+}
+
+/// Answers calls to methods the actor doesn't have.
+pub trait Fallback: Send + Sync {
+    /// Answer a call to `method` with `params`, the request body.
+    fn call<'a>(&'a self, method: &'a str, params: &'a str) -> BoxFuture<'a, FallbackResponse>;
+}
+
+/// Sends unknown calls on to another actor and passes its response back.
+impl Fallback for ActorRef {
+    fn call<'a>(&'a self, method: &'a str, params: &'a str) -> BoxFuture<'a, FallbackResponse> {
+        async move {
+            match self.call_raw(method, params.to_string()).await {
+                Ok(body) => FallbackResponse { status: 200, body },
+                Err(ClientError::Status(status, body)) => FallbackResponse { status, body },
+                Err(e) => FallbackResponse::json(
+                    502,
+                    &format!("Failed to forward {} to {}: {}", method, self.url(), e),
+                ),
+            }
+        }
+        .boxed()
+    }
+}
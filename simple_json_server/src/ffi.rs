@@ -0,0 +1,211 @@
+//! Calling an actor from C, C++ or Python (through `ctypes`) in-process, without a network
+//! server.
+//!
+//! [`export_actor!`](crate::export_actor) defines four `extern "C"` functions for one actor
+//! type, to be built into a `cdylib` or `staticlib`:
+//!
+//! ```c
+//! void *actor_create(void);
+//! char *actor_dispatch(void *actor, const char *method, const char *json);
+//! void actor_free_string(char *json);
+//! void actor_destroy(void *actor);
+//! ```
+//!
+//! `actor_create` builds the actor, runs its `init` as a server would before serving, and
+//! returns an opaque handle, or `NULL` if building it panicked or `init` failed.
+//! `actor_dispatch` calls a method with its parameters as a JSON object (`NULL` is taken as
+//! `{}`) and returns what [`Actor::dispatch`] does: the method's result as JSON, or a JSON
+//! string describing the error for unknown methods and invalid parameters.  The result must be
+//! released with `actor_free_string`.  It returns `NULL` if `actor` or `method` is `NULL`, an
+//! argument isn't UTF-8, or the method panicked.  `actor_destroy` runs the actor's
+//! `on_shutdown` and frees it.
+//!
+//! Each actor has its own Tokio runtime, which its methods run on and which runs any tasks they
+//! spawn.  `actor_dispatch` blocks until the method returns and may be called from several
+//! threads at once, so methods run concurrently as they would on a server; but nothing of the
+//! server runs, so `#[write]` methods don't take turns, and middleware, quotas, journaling and
+//! the like are skipped.
+//!
+//! ```rust,no_run
+//! // src/lib.rs of a crate with `crate-type = ["cdylib"]`
+//! use simple_json_server::{actor, export_actor, Actor};
+//!
+//! #[derive(Default)]
+//! pub struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i64, b: i64) -> i64 {
+//!         a + b
+//!     }
+//! }
+//!
+//! export_actor!(Calculator, Calculator::default());
+//! # fn main() {}
+//! ```
+//!
+//! ```python
+//! import ctypes
+//!
+//! lib = ctypes.CDLL("./libcalculator.so")
+//! lib.actor_create.restype = ctypes.c_void_p
+//! lib.actor_dispatch.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.c_char_p]
+//! lib.actor_dispatch.restype = ctypes.c_void_p
+//! lib.actor_free_string.argtypes = [ctypes.c_void_p]
+//! lib.actor_destroy.argtypes = [ctypes.c_void_p]
+//!
+//! calculator = lib.actor_create()
+//! result = lib.actor_dispatch(calculator, b"add", b'{"a": 1, "b": 2}')
+//! print(ctypes.string_at(result).decode())  # 3
+//! lib.actor_free_string(result)
+//! lib.actor_destroy(calculator)
+//! ```
+//!
+//! A library exports one actor this way; the functions the macro wraps, [`create`],
+//! [`dispatch`], [`free_string`] and [`destroy`], can be exported under other names to offer
+//! several.
+
+use crate::Actor;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use tokio::runtime::Runtime;
+
+/// An actor and the runtime its methods run on, behind the handle C code holds.
+pub struct FfiActor<A> {
+    actor: A,
+    runtime: Runtime,
+}
+
+/// Build the actor with `create` and return a handle for it after running its `init`, or
+/// return `NULL` if `create` panics, `init` fails or the runtime can't be started.
+pub fn create<A: Actor>(create: impl FnOnce() -> A) -> *mut FfiActor<A> {
+    let created = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut actor = create();
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+        runtime.block_on(actor.init())?;
+        Ok::<_, String>(FfiActor { actor, runtime })
+    }));
+    match created {
+        Ok(Ok(handle)) => Box::into_raw(Box::new(handle)),
+        Ok(Err(e)) => {
+            log::error!("Failed to create the actor: {}", e);
+            std::ptr::null_mut()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Call `method` with the JSON parameters `json` and return the result as a string to be
+/// released with [`free_string`], or `NULL`; see the [module documentation](self).
+///
+/// # Safety
+///
+/// `actor` must be `NULL` or a handle from [`create`] for the same actor type that hasn't been
+/// destroyed, and `method` and `json` must each be `NULL` or a NUL-terminated string.
+pub unsafe fn dispatch<A: Actor + Sync>(
+    actor: *const FfiActor<A>,
+    method: *const c_char,
+    json: *const c_char,
+) -> *mut c_char {
+    if actor.is_null() || method.is_null() {
+        return std::ptr::null_mut();
+    }
+    // SAFETY: the caller guarantees the handle is live and the strings are NUL-terminated
+    let handle = unsafe { &*actor };
+    let Ok(method) = unsafe { CStr::from_ptr(method) }.to_str() else {
+        return std::ptr::null_mut();
+    };
+    let json = match json.is_null() {
+        true => "{}",
+        false => match unsafe { CStr::from_ptr(json) }.to_str() {
+            Ok(json) => json,
+            Err(_) => return std::ptr::null_mut(),
+        },
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        handle.runtime.block_on(handle.actor.dispatch(method, json))
+    }));
+    match result.ok().and_then(|result| CString::new(result).ok()) {
+        Some(result) => result.into_raw(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Release a string returned by [`dispatch`].
+///
+/// # Safety
+///
+/// `json` must be `NULL` or a string returned by [`dispatch`] that hasn't been released yet.
+pub unsafe fn free_string(json: *mut c_char) {
+    if !json.is_null() {
+        // SAFETY: the string was allocated by `CString::into_raw` in `dispatch`
+        drop(unsafe { CString::from_raw(json) });
+    }
+}
+
+/// Run the actor's `on_shutdown` and free it with its runtime.
+///
+/// # Safety
+///
+/// `actor` must be `NULL` or a handle from [`create`] for the same actor type that hasn't been
+/// destroyed yet, and no call to [`dispatch`] with it may be in progress.
+pub unsafe fn destroy<A: Actor>(actor: *mut FfiActor<A>) {
+    if actor.is_null() {
+        return;
+    }
+    // SAFETY: the handle was allocated by `Box::into_raw` in `create`
+    let handle = unsafe { Box::from_raw(actor) };
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        handle.runtime.block_on(handle.actor.on_shutdown())
+    }));
+}
+
+/// Export `actor_create`, `actor_dispatch`, `actor_free_string` and `actor_destroy` for the
+/// actor type `$actor`, built by the expression `$create`; see the [`ffi`](crate::ffi) module.
+#[macro_export]
+macro_rules! export_actor {
+    ($actor:ty, $create:expr $(,)?) => {
+        /// Build the actor; see `simple_json_server::ffi`.
+        #[no_mangle]
+        pub extern "C" fn actor_create() -> *mut $crate::ffi::FfiActor<$actor> {
+            $crate::ffi::create::<$actor>(|| $create)
+        }
+
+        /// Call a method of the actor; see `simple_json_server::ffi`.
+        ///
+        /// # Safety
+        ///
+        /// See `simple_json_server::ffi::dispatch`.
+        #[no_mangle]
+        pub unsafe extern "C" fn actor_dispatch(
+            actor: *const $crate::ffi::FfiActor<$actor>,
+            method: *const ::std::ffi::c_char,
+            json: *const ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { $crate::ffi::dispatch::<$actor>(actor, method, json) }
+        }
+
+        /// Release a string returned by `actor_dispatch`.
+        ///
+        /// # Safety
+        ///
+        /// See `simple_json_server::ffi::free_string`.
+        #[no_mangle]
+        pub unsafe extern "C" fn actor_free_string(json: *mut ::std::ffi::c_char) {
+            unsafe { $crate::ffi::free_string(json) }
+        }
+
+        /// Shut the actor down and free it; see `simple_json_server::ffi`.
+        ///
+        /// # Safety
+        ///
+        /// See `simple_json_server::ffi::destroy`.
+        #[no_mangle]
+        pub unsafe extern "C" fn actor_destroy(actor: *mut $crate::ffi::FfiActor<$actor>) {
+            unsafe { $crate::ffi::destroy::<$actor>(actor) }
+        }
+    };
+}
@@ -0,0 +1,132 @@
+//! Partial responses, selected with a `fields` query parameter.
+//!
+//! A call to a `#[read]` method may name the parts of the result it wants, and gets only those:
+//!
+//! ```text
+//! GET /profile?id=7&fields=name,address.city
+//! POST /profile?fields=name,address.city
+//! ```
+//!
+//! Fields are separated by commas and nested fields are reached with dots.  Arrays are
+//! projected element by element, so `fields=items.sku` on `{"items": [...]}` keeps the `sku` of
+//! each item.  Fields the result doesn't have are left out rather than refused, and a result
+//! that isn't an object or an array of them is sent whole.  Only successful responses are
+//! projected, and not those of `#[streaming]` methods.
+//!
+//! For a `#[get]` method, `fields` is taken out of the query before the rest becomes the
+//! method's parameters, unless the method has a parameter of its own named `fields`.
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// The name of the query parameter.
+pub(crate) const PARAM: &str = "fields";
+
+/// The fields to keep, as a tree of names; an empty subtree keeps the whole value.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Fields(BTreeMap<String, Fields>);
+
+impl Fields {
+    /// The projection asked for in `query`, if it has a `fields` parameter.
+    pub(crate) fn from_query(query: &str) -> Result<Option<Fields>, String> {
+        let mut fields: Option<Fields> = None;
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if key != PARAM {
+                continue;
+            }
+            let value = crate::query::decode(value)?;
+            let fields = fields.get_or_insert_with(Fields::default);
+            for path in value
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+            {
+                if path.split('.').any(str::is_empty) {
+                    return Err(format!("Invalid field `{}` in `{}`", path, PARAM));
+                }
+                let mut node = &mut *fields;
+                for name in path.split('.') {
+                    node = node.0.entry(name.to_string()).or_default();
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// `query` without its `fields` parameter.
+    pub(crate) fn strip(query: &str) -> String {
+        query
+            .split('&')
+            .filter(|pair| pair.split_once('=').map_or(*pair, |(key, _)| key) != PARAM)
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// The JSON text `body` with only these fields, or `body` itself if it isn't JSON.
+    pub(crate) fn project(&self, body: String) -> String {
+        match serde_json::from_str::<Value>(&body) {
+            Ok(value) => self.apply(value).to_string(),
+            Err(_) => body,
+        }
+    }
+
+    fn apply(&self, value: Value) -> Value {
+        if self.0.is_empty() {
+            return value;
+        }
+        match value {
+            Value::Object(mut object) => {
+                let mut kept = Map::new();
+                for (name, fields) in &self.0 {
+                    if let Some(field) = object.remove(name) {
+                        kept.insert(name.clone(), fields.apply(field));
+                    }
+                }
+                Value::Object(kept)
+            }
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|item| self.apply(item)).collect())
+            }
+            value => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_projection() {
+        let fields = Fields::from_query("id=7&fields=name,address.city,orders.sku")
+            .unwrap()
+            .unwrap();
+        let body = json!({
+            "name": "Ada",
+            "email": "ada@example.com",
+            "address": {"city": "London", "street": "St James's Square"},
+            "orders": [{"sku": "A1", "qty": 2}, {"sku": "B2", "qty": 1}],
+        });
+        let projected: Value = serde_json::from_str(&fields.project(body.to_string())).unwrap();
+        assert_eq!(
+            projected,
+            json!({
+                "name": "Ada",
+                "address": {"city": "London"},
+                "orders": [{"sku": "A1"}, {"sku": "B2"}],
+            })
+        );
+        assert_eq!(fields.project("42".to_string()), "42");
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(Fields::from_query("id=7").unwrap(), None);
+        assert!(Fields::from_query("fields=a..b").is_err());
+        let fields = Fields::from_query("fields=a%2Cb").unwrap().unwrap();
+        assert_eq!(fields.0.len(), 2);
+        assert_eq!(Fields::strip("id=7&fields=name&tag=x"), "id=7&tag=x");
+    }
+}
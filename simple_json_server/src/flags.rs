@@ -0,0 +1,181 @@
+//! Feature flags for dark-launching methods.
+//!
+//! A method marked `#[flag("beta_reports")]` is only served while the `beta_reports` flag is
+//! enabled.  The server asks a [`FlagProvider`] on every call, so flags can be flipped while it
+//! runs.  Calls to a disabled method are answered `404 Not Found` as if the method did not
+//! exist, or `403 Forbidden` with [`FeatureFlags::forbid`].  Flagged methods are disabled on a
+//! server without feature flags configured.
+//!
+//! ```rust
+//! use simple_json_server::flags::{FeatureFlags, StaticFlags};
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Reports;
+//!
+//! #[actor]
+//! impl Reports {
+//!     #[flag("beta_reports")]
+//!     pub async fn quarterly(&self) -> String {
+//!         "Q3".to_string()
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let flags = StaticFlags::new();
+//! let options = ServerOptions::new(8080).feature_flags(FeatureFlags::new(flags.clone()));
+//!
+//! // Later
+//! flags.enable("beta_reports");
+//! # }
+//! ```
+//!
+//! [`StaticFlags`] are set from code and [`EnvFlags`] read environment variables.  Flags kept in
+//! a remote service are supported by implementing [`FlagProvider`].
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Decides whether a flag is enabled.
+pub trait FlagProvider: Send + Sync {
+    /// Whether `flag` is currently enabled.
+    fn is_enabled<'a>(&'a self, flag: &'a str) -> BoxFuture<'a, bool>;
+}
+
+/// Flags enabled and disabled from code.  Clones share the same set of flags.
+#[derive(Debug, Clone, Default)]
+pub struct StaticFlags {
+    enabled: Arc<RwLock<HashSet<String>>>,
+}
+
+impl StaticFlags {
+    /// A set of flags with none enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable `flag`.
+    pub fn enable(&self, flag: impl Into<String>) {
+        self.enabled.write().unwrap().insert(flag.into());
+    }
+
+    /// Disable `flag`.
+    pub fn disable(&self, flag: &str) {
+        self.enabled.write().unwrap().remove(flag);
+    }
+}
+
+impl FlagProvider for StaticFlags {
+    fn is_enabled<'a>(&'a self, flag: &'a str) -> BoxFuture<'a, bool> {
+        let enabled = self.enabled.read().unwrap().contains(flag);
+        async move { enabled }.boxed()
+    }
+}
+
+/// Flags read from environment variables: `beta_reports` is enabled when `<prefix>BETA_REPORTS`
+/// is `1`, `true`, `yes` or `on`.  The environment is read on every call.
+#[derive(Debug, Clone)]
+pub struct EnvFlags {
+    prefix: String,
+}
+
+impl EnvFlags {
+    /// Read flags from variables named `prefix` followed by the upper-cased flag name, e.g.
+    /// `FLAG_` for `FLAG_BETA_REPORTS`.
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    fn variable(&self, flag: &str) -> String {
+        let name: String = flag
+            .chars()
+            .map(|c| match c {
+                '-' | '.' => '_',
+                c => c.to_ascii_uppercase(),
+            })
+            .collect();
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+impl FlagProvider for EnvFlags {
+    fn is_enabled<'a>(&'a self, flag: &'a str) -> BoxFuture<'a, bool> {
+        let enabled = std::env::var(self.variable(flag)).is_ok_and(|value| {
+            ["1", "true", "yes", "on"]
+                .iter()
+                .any(|truthy| value.trim().eq_ignore_ascii_case(truthy))
+        });
+        async move { enabled }.boxed()
+    }
+}
+
+/// Feature flag settings for a server; see [`crate::ServerOptions::feature_flags`].
+#[derive(Clone)]
+pub struct FeatureFlags {
+    provider: Arc<dyn FlagProvider>,
+    forbid: bool,
+}
+
+impl fmt::Debug for FeatureFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeatureFlags")
+            .field("forbid", &self.forbid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FeatureFlags {
+    /// Consult `provider` for the flags of flagged methods.
+    pub fn new(provider: impl FlagProvider + 'static) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            forbid: false,
+        }
+    }
+
+    /// Answer calls to disabled methods with `403 Forbidden` instead of hiding them behind
+    /// `404 Not Found`.
+    pub fn forbid(mut self) -> Self {
+        self.forbid = true;
+        self
+    }
+
+    pub(crate) async fn is_enabled(&self, flag: &str) -> bool {
+        self.provider.is_enabled(flag).await
+    }
+
+    pub(crate) fn forbids(&self) -> bool {
+        self.forbid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_flags() {
+        let flags = StaticFlags::new();
+        let shared = flags.clone();
+        assert!(!flags.is_enabled("beta").await);
+        shared.enable("beta");
+        assert!(flags.is_enabled("beta").await);
+        shared.disable("beta");
+        assert!(!flags.is_enabled("beta").await);
+    }
+
+    #[tokio::test]
+    async fn test_env_flags() {
+        let flags = EnvFlags::new("SJS_TEST_FLAG_");
+        assert_eq!(flags.variable("beta-reports"), "SJS_TEST_FLAG_BETA_REPORTS");
+        std::env::set_var("SJS_TEST_FLAG_ON", "True");
+        std::env::set_var("SJS_TEST_FLAG_OFF", "0");
+        assert!(flags.is_enabled("on").await);
+        assert!(!flags.is_enabled("off").await);
+        assert!(!flags.is_enabled("unset").await);
+    }
+}
@@ -0,0 +1,385 @@
+//! A front door that routes calls to other actors, assembled from configuration.
+//!
+//! A [`GatewayActor`] serves no methods of its own.  Each call is matched against a list of
+//! [`Route`]s, first match wins, and sent on to that route's actor; the downstream response,
+//! status included, is passed back to the caller.  A pattern is a method name, optionally with
+//! one `*` matching any text, and a route can rewrite the method name it sends, with `*` standing
+//! for the text the pattern's `*` matched.  Calls matching no route are answered like calls to an
+//! unknown method.
+//!
+//! The routes can come from any format serde reads, e.g. JSON:
+//!
+//! ```rust,no_run
+//! use simple_json_server::gateway::{GatewayActor, GatewayConfig};
+//! use simple_json_server::{Actor, ServerOptions};
+//!
+//! # async fn example() -> Result<(), serde_json::Error> {
+//! let config: GatewayConfig = serde_json::from_str(
+//!     r#"{
+//!         "routes": [
+//!             {"methods": "billing_*", "url": "http://billing:8080", "rewrite": "*"},
+//!             {"methods": "*", "url": "http://legacy:8080"}
+//!         ],
+//!         "retries": 2,
+//!         "health_method": "health"
+//!     }"#,
+//! )?;
+//! GatewayActor::from_config(config).create_with(ServerOptions::new(8080));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! or from code, with [`GatewayActor::new`] and [`GatewayActor::route`].
+//!
+//! Calls that fail to reach their actor, or are answered `502`, `503` or `504`, are retried up
+//! to [`GatewayActor::retries`] times.  A retried call may have run downstream already, so only
+//! enable retries for actors whose methods are safe to repeat.
+//!
+//! The gateway keeps count of each route's calls and failures.  [`GatewayActor::health`] sums
+//! them up, and with a health method configured callers get the same report by calling it, with
+//! `503 Service Unavailable` while any route is failing.
+
+use crate::breaker::CircuitState;
+use crate::{Actor, ActorRef, ClientError, RequestContext};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A gateway's routes and settings, as read from a configuration file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewayConfig {
+    /// The routes, in the order they are tried.
+    pub routes: Vec<RouteConfig>,
+    /// How many times to retry a call that fails to reach its actor.
+    #[serde(default)]
+    pub retries: u32,
+    /// How long to wait between attempts, in milliseconds.
+    #[serde(default = "default_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+    /// The method that answers with the gateway's health, if any.
+    #[serde(default)]
+    pub health_method: Option<String>,
+}
+
+fn default_retry_delay_ms() -> u64 {
+    100
+}
+
+/// One route of a [`GatewayConfig`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteConfig {
+    /// The methods the route takes, e.g. `add` or `billing_*`.
+    pub methods: String,
+    /// The URL of the actor calls are sent to.
+    pub url: String,
+    /// The method name to send instead, e.g. `*` to drop a prefix matched by `billing_*`.
+    #[serde(default)]
+    pub rewrite: Option<String>,
+}
+
+/// Where calls to some methods go.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pattern: String,
+    target: ActorRef,
+    rewrite: Option<String>,
+    health: Arc<Mutex<Counts>>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    calls: u64,
+    failures: u64,
+    consecutive_failures: u64,
+    last_error: Option<String>,
+}
+
+impl Route {
+    /// Send calls to methods matching `pattern` to `target`.
+    pub fn new(pattern: impl Into<String>, target: ActorRef) -> Self {
+        Self {
+            pattern: pattern.into(),
+            target,
+            rewrite: None,
+            health: Arc::default(),
+        }
+    }
+
+    /// Call `template` instead of the method called, with `*` replaced by the text the pattern's
+    /// `*` matched.
+    pub fn rewrite(mut self, template: impl Into<String>) -> Self {
+        self.rewrite = Some(template.into());
+        self
+    }
+
+    /// The method to call downstream for a call to `method`, if this route takes it.
+    fn method_for(&self, method: &str) -> Option<String> {
+        let matched = match self.pattern.split_once('*') {
+            None if method == self.pattern => "",
+            None => return None,
+            Some((prefix, suffix)) => method
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))?,
+        };
+        Some(match &self.rewrite {
+            Some(template) => template.replacen('*', matched, 1),
+            None => method.to_string(),
+        })
+    }
+
+    fn record(&self, result: &Result<String, ClientError>) {
+        let mut counts = self.health.lock().unwrap();
+        counts.calls += 1;
+        match result {
+            Err(e) if is_failure(e) => {
+                counts.failures += 1;
+                counts.consecutive_failures += 1;
+                counts.last_error = Some(e.to_string());
+            }
+            _ => counts.consecutive_failures = 0,
+        }
+    }
+
+    fn health(&self) -> RouteHealth {
+        let counts = self.health.lock().unwrap();
+        let circuit_open = self.target.circuit_state() == Some(CircuitState::Open);
+        RouteHealth {
+            methods: self.pattern.clone(),
+            url: self.target.url().to_string(),
+            healthy: counts.consecutive_failures == 0 && !circuit_open,
+            calls: counts.calls,
+            failures: counts.failures,
+            last_error: counts.last_error.clone(),
+        }
+    }
+}
+
+/// Whether an error says the actor is unwell, rather than that the call was refused.
+fn is_failure(error: &ClientError) -> bool {
+    match error {
+        ClientError::Transport(_) | ClientError::CircuitOpen(_) => true,
+        ClientError::Status(status, _) => *status >= 500,
+        ClientError::Serialization(_) => false,
+    }
+}
+
+/// Whether a failed call is worth sending again.
+fn is_retryable(error: &ClientError) -> bool {
+    match error {
+        ClientError::Transport(_) => true,
+        ClientError::Status(status, _) => matches!(status, 502..=504),
+        ClientError::CircuitOpen(_) | ClientError::Serialization(_) => false,
+    }
+}
+
+/// The health of a gateway's routes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GatewayHealth {
+    /// Whether every route is healthy.
+    pub healthy: bool,
+    /// Each route's health, in route order.
+    pub routes: Vec<RouteHealth>,
+}
+
+/// The health of one route.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteHealth {
+    /// The route's pattern.
+    pub methods: String,
+    /// The URL of the route's actor.
+    pub url: String,
+    /// Whether the last call succeeded (or none was made) and the actor's circuit isn't open.
+    pub healthy: bool,
+    /// How many calls the route has sent on.
+    pub calls: u64,
+    /// How many of those failed.
+    pub failures: u64,
+    /// The error of the latest failure.
+    pub last_error: Option<String>,
+}
+
+/// An actor that routes calls to other actors; see the [module documentation](self).  Clones
+/// share the routes' health, so keep one to check on a running gateway.
+#[derive(Debug, Clone)]
+pub struct GatewayActor {
+    routes: Vec<Route>,
+    retries: u32,
+    retry_delay: Duration,
+    health_method: Option<String>,
+}
+
+impl Default for GatewayActor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GatewayActor {
+    /// A gateway without routes, which doesn't retry.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            retries: 0,
+            retry_delay: Duration::from_millis(default_retry_delay_ms()),
+            health_method: None,
+        }
+    }
+
+    /// A gateway with the routes and settings of `config`.
+    pub fn from_config(config: GatewayConfig) -> Self {
+        let mut gateway = Self::new()
+            .retries(config.retries)
+            .retry_delay(Duration::from_millis(config.retry_delay_ms));
+        gateway.health_method = config.health_method;
+        for route in config.routes {
+            let mut next = Route::new(route.methods, ActorRef::new(route.url));
+            next.rewrite = route.rewrite;
+            gateway = gateway.route(next);
+        }
+        gateway
+    }
+
+    /// Add a route, tried after those already added.
+    pub fn route(mut self, route: Route) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    /// Retry calls that fail to reach their actor up to `retries` times.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait between attempts (default 100 milliseconds).
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Answer calls to `method` with the gateway's [`GatewayHealth`] instead of routing them.
+    pub fn health_method(mut self, method: impl Into<String>) -> Self {
+        self.health_method = Some(method.into());
+        self
+    }
+
+    /// The health of every route.
+    pub fn health(&self) -> GatewayHealth {
+        let routes: Vec<RouteHealth> = self.routes.iter().map(Route::health).collect();
+        GatewayHealth {
+            healthy: routes.iter().all(|route| route.healthy),
+            routes,
+        }
+    }
+
+    /// Send a call on along `route`, retrying as configured, and return the status and JSON body
+    /// to answer with.
+    async fn forward(&self, route: &Route, method: &str, params: &str) -> (StatusCode, String) {
+        let mut attempt = 0;
+        let result = loop {
+            let result = route.target.call_raw(method, params.to_string()).await;
+            route.record(&result);
+            match result {
+                Err(e) if attempt < self.retries && is_retryable(&e) => {
+                    attempt += 1;
+                    log::warn!("Retrying {} on {}: {}", method, route.target.url(), e);
+                    tokio::time::sleep(self.retry_delay).await;
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Ok(body) => (StatusCode::OK, body),
+            Err(ClientError::Status(status, body)) => (
+                StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+                body,
+            ),
+            Err(e @ ClientError::CircuitOpen(_)) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                serde_json::to_string(&e.to_string()).unwrap_or_default(),
+            ),
+            Err(e) => (
+                StatusCode::BAD_GATEWAY,
+                serde_json::to_string(&format!(
+                    "Failed to forward {} to {}: {}",
+                    method,
+                    route.target.url(),
+                    e
+                ))
+                .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+impl Actor for GatewayActor {
+    async fn dispatch(&self, method_name: &str, msg: &str) -> String {
+        let (status, body) = if self.health_method.as_deref() == Some(method_name) {
+            let health = self.health();
+            let status = if health.healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            (status, serde_json::to_string(&health).unwrap_or_default())
+        } else {
+            let routed = self
+                .routes
+                .iter()
+                .find_map(|route| Some((route, route.method_for(method_name)?)));
+            match routed {
+                Some((route, method)) => self.forward(route, &method, msg).await,
+                None => (
+                    StatusCode::OK,
+                    serde_json::to_string(&format!("Unknown method: {}", method_name)).unwrap(),
+                ),
+            }
+        };
+        if let Some(ctx) = RequestContext::current() {
+            ctx.set_status(status);
+            if !status.is_success() {
+                ctx.set_failed();
+            }
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_and_rewrites() {
+        let target = ActorRef::new("http://127.0.0.1:1");
+        let exact = Route::new("add", target.clone());
+        assert_eq!(exact.method_for("add").as_deref(), Some("add"));
+        assert_eq!(exact.method_for("adder"), None);
+
+        let prefixed = Route::new("billing_*", target.clone()).rewrite("*");
+        assert_eq!(
+            prefixed.method_for("billing_charge").as_deref(),
+            Some("charge")
+        );
+        assert_eq!(prefixed.method_for("charge"), None);
+
+        let renamed = Route::new("v1_*_item", target).rewrite("*_item_v2");
+        assert_eq!(
+            renamed.method_for("v1_get_item").as_deref(),
+            Some("get_item_v2")
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config: GatewayConfig = serde_json::from_str(
+            r#"{"routes": [{"methods": "*", "url": "http://a:1", "rewrite": "x_*"}]}"#,
+        )
+        .unwrap();
+        let gateway = GatewayActor::from_config(config);
+        assert_eq!(gateway.retries, 0);
+        assert_eq!(gateway.retry_delay, Duration::from_millis(100));
+        assert_eq!(gateway.routes[0].method_for("y").as_deref(), Some("x_y"));
+    }
+}
@@ -0,0 +1,597 @@
+//! Controlling a running server: finding out where it listens and draining it.
+//!
+//! [`Actor::start`](crate::Actor::start) returns a [`ServerHandle`].  Draining a server stops it
+//! accepting connections, lets calls in progress finish (HTTP keep-alive connections are closed
+//! after their current request, WebSocket connections are sent a close frame) and resolves once
+//! the server has stopped: the last connection is gone, the actor's
+//! [`on_shutdown`](crate::Actor::on_shutdown) hook has run with the mailbox to itself, and the
+//! actor has been dropped, releasing whatever it holds (files, connection pools, temporary
+//! files).
+//!
+//! [`ServerHandle::drain_with`] gives finer control for rolling restarts: it drains just one
+//! [`Listener`], and with [`Drain::deadline`] closes the connections still open after a while,
+//! cutting off their calls.  The admin API's `POST /__admin/drain` does the same, with an
+//! optional body such as `{"listeners": ["http3"], "deadline_ms": 10000}`.
+//!
+//! A method misbehaving in production can be turned off without stopping the rest of the
+//! actor: [`ServerHandle::disable_method`] answers its calls with `503 Service Unavailable` and a
+//! reason until [`ServerHandle::enable_method`].  The admin API's `POST /__admin/disable` and
+//! `POST /__admin/enable`, with a body such as `{"method": "pay", "reason": "INC-12"}`, do the
+//! same, and `GET /__admin/disabled` lists the disabled methods.
+//!
+//! Every task a server spawns, one per connection plus background work such as cluster
+//! elections and registry heartbeats, is tracked.  The background tasks are stopped when the
+//! server shuts down, and the server only counts as stopped once all of them have finished, so
+//! nothing it started outlives it.  [`ServerHandle::tasks`] counts them; a count that keeps
+//! growing while [`ServerHandle::connections`] doesn't points at a leak.
+//!
+//! # Zero-downtime upgrades
+//!
+//! With [`ServerOptions::reuse_port`](crate::ServerOptions::reuse_port) set (Unix only), several
+//! processes can listen on the same port and the kernel spreads new connections between them.
+//! To deploy a new binary without dropping requests, start it alongside the old one, wait until
+//! it is listening, then drain the old one:
+//!
+//! ```rust,no_run
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let server = Calculator.start(ServerOptions::new(8080).reuse_port(true));
+//!     server.listening().await;
+//!
+//!     // ... serve until the new version is listening and signals this one, then
+//!     server.drain().await;
+//! }
+//! ```
+
+use crate::monitor::{LifecycleEvent, Monitor};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// A handle to a running server.  Clones refer to the same server.
+#[derive(Debug, Clone)]
+pub struct ServerHandle {
+    lifecycle: Arc<Lifecycle>,
+}
+
+/// One of the ways a server accepts connections, drained separately with
+/// [`ServerHandle::drain_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Listener {
+    /// The TCP listener serving HTTP or WebSocket connections, with or without TLS.
+    Tcp,
+    /// The QUIC endpoint serving HTTP/3, when enabled.
+    Http3,
+}
+
+impl Listener {
+    const ALL: [Listener; 2] = [Listener::Tcp, Listener::Http3];
+}
+
+/// How to drain a server: which listeners, and how long calls in progress may take.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Drain {
+    listeners: Vec<Listener>,
+    deadline: Option<Duration>,
+}
+
+impl Drain {
+    /// Drain every listener, letting calls in progress finish however long they take.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drain `listener`, and any others given, instead of every listener.
+    pub fn listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// Close the connections still open `deadline` after draining starts, cutting off their
+    /// calls in progress.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    fn listeners(&self) -> Vec<Listener> {
+        if self.listeners.is_empty() {
+            Listener::ALL.to_vec()
+        } else {
+            self.listeners.clone()
+        }
+    }
+}
+
+/// Shared between a server and its handles.
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    /// The addresses the server accepts connections on, once it is listening.
+    addrs: watch::Sender<Option<Vec<SocketAddr>>>,
+    /// Set once the server has shut down and dropped its actor.
+    stopped: watch::Sender<bool>,
+    /// Indexed by [`Listener`].
+    listeners: [ListenerState; 2],
+    /// Spawned tasks not yet finished.
+    tasks: watch::Sender<usize>,
+    /// Background tasks, stopped when the server shuts down.
+    background: Mutex<Vec<AbortHandle>>,
+    /// Why the server failed, if it did.
+    failure: Mutex<Option<String>>,
+    /// Where the server reports its lifecycle events, and under what name.
+    monitor: OnceLock<(Monitor, String)>,
+    /// When the server last reported turning calls away.
+    saturated: Mutex<Option<Instant>>,
+    /// Methods turned off at runtime, with the reason given.
+    disabled: Mutex<BTreeMap<String, String>>,
+}
+
+/// Where one of a server's listeners is in draining.
+#[derive(Debug)]
+struct ListenerState {
+    /// Set once the listener should stop accepting connections.
+    closing: watch::Sender<bool>,
+    /// Set once its open connections should be closed, calls in progress or not.
+    forced: watch::Sender<bool>,
+    /// Open connections.
+    connections: watch::Sender<usize>,
+}
+
+impl ServerHandle {
+    pub(crate) fn new() -> Self {
+        let listener = || ListenerState {
+            closing: watch::channel(false).0,
+            forced: watch::channel(false).0,
+            connections: watch::channel(0).0,
+        };
+        Self {
+            lifecycle: Arc::new(Lifecycle {
+                addrs: watch::channel(None).0,
+                stopped: watch::channel(false).0,
+                listeners: [listener(), listener()],
+                tasks: watch::channel(0).0,
+                background: Mutex::default(),
+                failure: Mutex::default(),
+                monitor: OnceLock::new(),
+                saturated: Mutex::default(),
+                disabled: Mutex::default(),
+            }),
+        }
+    }
+
+    pub(crate) fn from_lifecycle(lifecycle: Arc<Lifecycle>) -> Self {
+        Self { lifecycle }
+    }
+
+    pub(crate) fn lifecycle(&self) -> Arc<Lifecycle> {
+        Arc::clone(&self.lifecycle)
+    }
+
+    /// The addresses the server accepts connections on, once it has bound its socket.  Empty if
+    /// the server failed to start.
+    pub async fn listening(&self) -> Vec<SocketAddr> {
+        let mut addrs = self.lifecycle.addrs.subscribe();
+        let bound = addrs.wait_for(Option::is_some).await;
+        match bound {
+            Ok(addrs) => addrs.clone().unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// How many connections are open.
+    pub fn connections(&self) -> usize {
+        Listener::ALL
+            .iter()
+            .map(|&listener| self.connections_on(listener))
+            .sum()
+    }
+
+    /// How many connections `listener` has open.
+    pub fn connections_on(&self, listener: Listener) -> usize {
+        *self.lifecycle.listener(listener).connections.borrow()
+    }
+
+    /// How many tasks the server is running: one per open connection, plus its background work.
+    pub fn tasks(&self) -> usize {
+        *self.lifecycle.tasks.borrow()
+    }
+
+    /// Whether any of the server's listeners is draining.
+    pub fn is_draining(&self) -> bool {
+        self.lifecycle.is_draining()
+    }
+
+    /// Whether the server has stopped and dropped its actor, after draining or failing to start.
+    pub fn is_stopped(&self) -> bool {
+        *self.lifecycle.stopped.borrow()
+    }
+
+    /// Resolves once the server has stopped, however it came to stop.
+    pub async fn stopped(&self) {
+        let mut stopped = self.lifecycle.stopped.subscribe();
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+
+    /// Why the server failed, if it did: its actor failed to initialize, it couldn't listen, or
+    /// a method panicked with [`ServerOptions::stop_on_panic`](crate::ServerOptions::stop_on_panic)
+    /// set.  `None` for servers that are running or were drained.
+    pub fn failure(&self) -> Option<String> {
+        self.lifecycle.failure.lock().unwrap().clone()
+    }
+
+    /// Answer calls to `method` with `503 Service Unavailable` and `reason` until it is enabled
+    /// again, e.g. while one endpoint is misbehaving and the rest of the actor is healthy.
+    /// Calls already running finish.  The name is not checked against the actor's methods; the
+    /// admin API's `POST /__admin/disable` does the same and checks it.
+    pub fn disable_method(&self, method: impl Into<String>, reason: impl Into<String>) {
+        let (method, reason) = (method.into(), reason.into());
+        log::warn!("Disabling {}: {}", method, reason);
+        self.lifecycle
+            .disabled
+            .lock()
+            .unwrap()
+            .insert(method, reason);
+    }
+
+    /// Serve calls to `method` again; false if it wasn't disabled.
+    pub fn enable_method(&self, method: &str) -> bool {
+        let enabled = self
+            .lifecycle
+            .disabled
+            .lock()
+            .unwrap()
+            .remove(method)
+            .is_some();
+        if enabled {
+            log::info!("Enabling {}", method);
+        }
+        enabled
+    }
+
+    /// The disabled methods, with the reasons given.
+    pub fn disabled_methods(&self) -> BTreeMap<String, String> {
+        self.lifecycle.disabled.lock().unwrap().clone()
+    }
+
+    /// Stop accepting connections, wait for the open ones to finish their calls and close, then
+    /// for the actor's shutdown hook to run and the actor to be dropped.  Wrap this in
+    /// [`tokio::time::timeout`] to bound how long a deploy waits for slow clients, or use
+    /// [`ServerHandle::drain_with`] to cut them off.
+    pub async fn drain(&self) {
+        self.drain_with(Drain::new()).await;
+    }
+
+    /// Stop the listeners in `drain` accepting connections and wait for their open connections
+    /// to close: HTTP connections after their current request, WebSocket connections (sent a
+    /// `1001 Going Away` close frame) and HTTP/3 connections (sent `GOAWAY`) once their calls
+    /// in progress are answered, or at the deadline.  Once every listener is drained the server
+    /// stops, as with [`ServerHandle::drain`].
+    pub async fn drain_with(&self, drain: Drain) {
+        let listeners = drain.listeners();
+        for &listener in &listeners {
+            self.lifecycle.close(listener);
+        }
+        let closed = async {
+            for &listener in &listeners {
+                let mut connections = self.lifecycle.listener(listener).connections.subscribe();
+                let _ = connections.wait_for(|open| *open == 0).await;
+            }
+            if Listener::ALL
+                .iter()
+                .all(|&l| *self.lifecycle.listener(l).closing.borrow())
+            {
+                let mut stopped = self.lifecycle.stopped.subscribe();
+                let _ = stopped.wait_for(|stopped| *stopped).await;
+            }
+        };
+        tokio::pin!(closed);
+        if let Some(deadline) = drain.deadline {
+            if tokio::time::timeout(deadline, &mut closed).await.is_ok() {
+                return;
+            }
+            log::warn!("Closing connections still open at the drain deadline");
+            for &listener in &listeners {
+                self.lifecycle.listener(listener).forced.send_replace(true);
+            }
+        }
+        closed.await;
+    }
+}
+
+impl Lifecycle {
+    fn listener(&self, listener: Listener) -> &ListenerState {
+        &self.listeners[listener as usize]
+    }
+
+    /// Resolves once `listener` should stop accepting connections.
+    pub(crate) async fn closing(&self, listener: Listener) {
+        let mut closing = self.listener(listener).closing.subscribe();
+        let _ = closing.wait_for(|closing| *closing).await;
+    }
+
+    /// Resolves once the connections of `listener` should be closed without waiting any longer.
+    pub(crate) async fn forced(&self, listener: Listener) {
+        let mut forced = self.listener(listener).forced.subscribe();
+        let _ = forced.wait_for(|forced| *forced).await;
+    }
+
+    /// Whether any listener has been told to stop accepting connections.
+    pub(crate) fn is_draining(&self) -> bool {
+        self.listeners.iter().any(|state| *state.closing.borrow())
+    }
+
+    /// Record why the server failed, unless an earlier failure already was.
+    pub(crate) fn fail(&self, reason: impl Into<String>) {
+        self.failure
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| reason.into());
+    }
+
+    /// Fail the server and stop it as a drain would, letting calls in progress finish.
+    pub(crate) fn crash(&self, reason: impl Into<String>) {
+        let reason = reason.into();
+        log::error!("Stopping the server: {}", reason);
+        self.fail(reason);
+        for listener in Listener::ALL {
+            self.close(listener);
+        }
+    }
+
+    /// Tell `listener` to stop accepting connections, reporting that the server is draining
+    /// the first time a listener is.
+    fn close(&self, listener: Listener) {
+        let draining = self.is_draining();
+        self.listener(listener).closing.send_replace(true);
+        if !draining {
+            self.report(|name| LifecycleEvent::Draining { name });
+        }
+    }
+
+    /// Report the server's lifecycle events to `monitor`, as `name`.
+    pub(crate) fn watch(&self, monitor: Option<(Monitor, String)>) {
+        if let Some(monitor) = monitor {
+            let _ = self.monitor.set(monitor);
+        }
+    }
+
+    fn report(&self, event: impl FnOnce(String) -> LifecycleEvent) {
+        if let Some((monitor, name)) = self.monitor.get() {
+            monitor.report(event(name.clone()));
+        }
+    }
+
+    /// Report that a call to `method` was turned away because the server is `reason`, unless
+    /// that was already reported within the last second.
+    pub(crate) fn saturated(&self, method: &str, reason: &str) {
+        if self.monitor.get().is_none() {
+            return;
+        }
+        {
+            let mut last = self.saturated.lock().unwrap();
+            if last.is_some_and(|last| last.elapsed() < Duration::from_secs(1)) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        self.report(|name| LifecycleEvent::MailboxSaturated {
+            name,
+            method: method.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Why `method` is disabled, if it is.
+    pub(crate) fn disabled(&self, method: &str) -> Option<String> {
+        self.disabled.lock().unwrap().get(method).cloned()
+    }
+
+    /// Record where the server listens; empty if it failed to start.
+    pub(crate) fn set_listening(&self, addrs: Vec<SocketAddr>) {
+        if !addrs.is_empty() {
+            self.report(|name| LifecycleEvent::Started {
+                name,
+                addrs: addrs.clone(),
+            });
+        }
+        self.addrs.send_replace(Some(addrs));
+    }
+
+    /// Resolves once every connection has closed.
+    pub(crate) async fn idle(&self) {
+        for state in &self.listeners {
+            let mut connections = state.connections.subscribe();
+            let _ = connections.wait_for(|open| *open == 0).await;
+        }
+    }
+
+    /// Spawn `task`, counting it until it finishes or is aborted.
+    pub(crate) fn spawn<F>(self: &Arc<Self>, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.send_modify(|running| *running += 1);
+        let guard = TaskGuard(Arc::clone(self));
+        tokio::spawn(async move {
+            let _guard = guard;
+            task.await
+        })
+    }
+
+    /// Spawn `task` to run until the server shuts down.
+    pub(crate) fn spawn_background<F>(self: &Arc<Self>, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = self.spawn(task).abort_handle();
+        let mut background = self.background.lock().unwrap();
+        // Tasks such as per-connection ones come and go; forget those that have finished
+        background.retain(|task| !task.is_finished());
+        background.push(handle);
+    }
+
+    /// Stop the background tasks and wait for every task to finish.
+    pub(crate) async fn finish_tasks(&self) {
+        for task in self.background.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        let mut tasks = self.tasks.subscribe();
+        let _ = tasks.wait_for(|running| *running == 0).await;
+    }
+
+    /// Held while the server runs: it counts as stopped once the guard is dropped, even if it
+    /// panics.
+    pub(crate) fn running(self: &Arc<Self>) -> RunningGuard {
+        RunningGuard(Arc::clone(self))
+    }
+
+    /// Count a connection of `listener` as open until the guard is dropped.
+    pub(crate) fn connection(self: &Arc<Self>, listener: Listener) -> ConnectionGuard {
+        self.listener(listener)
+            .connections
+            .send_modify(|open| *open += 1);
+        ConnectionGuard(Arc::clone(self), listener)
+    }
+}
+
+/// See [`Lifecycle::running`].
+pub(crate) struct RunningGuard(Arc<Lifecycle>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.fail("The server panicked");
+        }
+        let failure = self.0.failure.lock().unwrap().clone();
+        self.0
+            .report(|name| LifecycleEvent::Stopped { name, failure });
+        self.0.stopped.send_replace(true);
+    }
+}
+
+/// See [`Lifecycle::spawn`].
+struct TaskGuard(Arc<Lifecycle>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.tasks.send_modify(|running| *running -= 1);
+    }
+}
+
+/// See [`Lifecycle::connection`].
+pub(crate) struct ConnectionGuard(Arc<Lifecycle>, Listener);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0
+            .listener(self.1)
+            .connections
+            .send_modify(|open| *open -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_the_server_to_stop() {
+        let handle = ServerHandle::new();
+        let lifecycle = handle.lifecycle();
+        let running = lifecycle.running();
+        let connection = lifecycle.connection(Listener::Tcp);
+        assert_eq!(handle.connections(), 1);
+
+        let draining = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.drain().await }
+        });
+        lifecycle.closing(Listener::Tcp).await;
+        drop(connection);
+        assert_eq!(handle.connections(), 0);
+        assert!(!draining.is_finished());
+
+        drop(running);
+        draining.await.unwrap();
+        assert!(handle.is_stopped());
+        assert_eq!(handle.connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_draining_one_listener_with_a_deadline() {
+        let handle = ServerHandle::new();
+        let lifecycle = handle.lifecycle();
+        let _tcp = lifecycle.connection(Listener::Tcp);
+        let http3 = lifecycle.connection(Listener::Http3);
+        // A connection that only closes when forced to
+        let stuck = tokio::spawn({
+            let lifecycle = Arc::clone(&lifecycle);
+            async move {
+                lifecycle.forced(Listener::Http3).await;
+                drop(http3);
+            }
+        });
+
+        let drain = Drain::new()
+            .listener(Listener::Http3)
+            .deadline(Duration::from_millis(50));
+        handle.drain_with(drain).await;
+        stuck.await.unwrap();
+        assert_eq!(handle.connections_on(Listener::Http3), 0);
+        assert_eq!(handle.connections_on(Listener::Tcp), 1);
+        assert!(handle.is_draining());
+        assert!(!handle.is_stopped());
+    }
+
+    #[tokio::test]
+    async fn test_tasks_are_tracked() {
+        let handle = ServerHandle::new();
+        let lifecycle = handle.lifecycle();
+        let (done, finish) = tokio::sync::oneshot::channel::<()>();
+        let task = lifecycle.spawn(async move {
+            let _ = finish.await;
+        });
+        lifecycle.spawn_background(std::future::pending());
+        assert_eq!(handle.tasks(), 2);
+
+        let finished = tokio::spawn({
+            let lifecycle = Arc::clone(&lifecycle);
+            async move { lifecycle.finish_tasks().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // The background task is stopped, the other one is waited for
+        assert_eq!(handle.tasks(), 1);
+        assert!(!finished.is_finished());
+
+        done.send(()).unwrap();
+        task.await.unwrap();
+        finished.await.unwrap();
+        assert_eq!(handle.tasks(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_listening() {
+        let handle = ServerHandle::new();
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        handle.lifecycle().set_listening(vec![addr]);
+        assert_eq!(handle.listening().await, vec![addr]);
+    }
+}
@@ -0,0 +1,105 @@
+//! Actor-local code run around every call.
+//!
+//! Cross-cutting concerns that belong to one actor rather than the whole server, such as
+//! touching a last-active timestamp or refusing calls while the actor is in maintenance, can be
+//! written once as hooks in the `#[actor]` impl instead of in every method.  A private
+//! `before_dispatch` runs before each call with the method name and its JSON parameters, and may
+//! refuse it with an [`ActorError`]; a private `after_dispatch` runs after each call with the
+//! JSON response.  Either may be `async`:
+//!
+//! ```rust
+//! use simple_json_server::hooks::ActorError;
+//! use simple_json_server::{actor, Actor};
+//! use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+//!
+//! #[derive(Default)]
+//! pub struct Inventory {
+//!     maintenance: AtomicBool,
+//!     calls: AtomicU64,
+//! }
+//!
+//! #[actor]
+//! impl Inventory {
+//!     pub async fn count(&self, sku: String) -> u32 {
+//!         3
+//!     }
+//!
+//!     fn before_dispatch(&self, method: &str, params: &str) -> Result<(), ActorError> {
+//!         if self.maintenance.load(Ordering::Relaxed) {
+//!             return Err(ActorError::new(503, format!("{} is unavailable", method)));
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn after_dispatch(&self, method: &str, result: &str) {
+//!         self.calls.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! A refused call is answered with the error's status and its message as a JSON string, and
+//! `after_dispatch` is not run for it.  Hooks run only for the actor's own methods, not for
+//! unknown method names, and mocks generated with `#[actor(mock)]` don't run them.
+
+use crate::RequestContext;
+use hyper::StatusCode;
+use std::fmt;
+
+/// Why `before_dispatch` refused a call: the HTTP status to answer with and a message for the
+/// caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorError {
+    status: u16,
+    message: String,
+}
+
+impl ActorError {
+    /// Refuse a call with `status`, e.g. `403` or `503`.
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Refuse a call with `400 Bad Request`.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(400, message)
+    }
+
+    /// Refuse a call with `403 Forbidden`.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(403, message)
+    }
+
+    /// The HTTP status the call is answered with.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The message sent to the caller.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ActorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for ActorError {}
+
+/// Build the response to a call refused by `before_dispatch`, and mark it with the error's
+/// status (`500 Internal Server Error` if that isn't a valid one).
+#[doc(hidden)]
+pub fn refuse(error: ActorError) -> String {
+    if let Some(ctx) = RequestContext::current() {
+        let status =
+            StatusCode::from_u16(error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        ctx.set_status(status);
+    }
+    serde_json::to_string(&error.message).unwrap_or_else(|_| "\"Call refused\"".to_string())
+}
@@ -0,0 +1,156 @@
+//! The experimental HTTP/3 (QUIC) listener, enabled with the `http3` feature and
+//! [`ServerOptions::http3`](crate::ServerOptions::http3).
+//!
+//! It listens on UDP on the same port as the HTTPS server and answers requests exactly as the
+//! HTTPS server does: calls, CORS preflights and the admin API all go through the same
+//! [`respond`].
+
+use crate::buffers::Buffer;
+use crate::handle::Listener;
+use crate::server::{bad_request, log_connection_error, respond, ServerState};
+use crate::Actor;
+use h3::server::RequestResolver;
+use http_body_util::BodyExt;
+use hyper::body::{Buf, Bytes};
+use hyper::Response;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Serve HTTP/3 on UDP `addr` with the HTTPS server's TLS configuration, until the server
+/// drains.
+pub(crate) async fn serve<T>(
+    state: Arc<ServerState<T>>,
+    addr: SocketAddr,
+    mut tls: rustls::ServerConfig,
+) where
+    T: Actor + Send + Sync + 'static,
+{
+    // Draining waits for the endpoint as well as its connections
+    let _endpoint = state.lifecycle.connection(Listener::Http3);
+
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = match quinn::crypto::rustls::QuicServerConfig::try_from(tls) {
+        Ok(crypto) => crypto,
+        Err(e) => {
+            log::error!("TLS configuration cannot be used for HTTP/3: {}", e);
+            return;
+        }
+    };
+    let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    let endpoint = match quinn::Endpoint::server(config, addr) {
+        Ok(endpoint) => endpoint,
+        Err(e) => {
+            log::error!("Failed to bind HTTP/3 server to {addr:?}: {}", e);
+            return;
+        }
+    };
+    log::info!("HTTP/3 server listening on https://{} (UDP)", addr);
+
+    loop {
+        let incoming = tokio::select! {
+            incoming = endpoint.accept() => match incoming {
+                Some(incoming) => incoming,
+                None => break,
+            },
+            _ = state.lifecycle.closing(Listener::Http3) => break,
+        };
+
+        let state = Arc::clone(&state);
+        let connection = state.lifecycle.connection(Listener::Http3);
+        let lifecycle = Arc::clone(&state.lifecycle);
+        lifecycle.spawn(async move {
+            let _connection = connection;
+            if let Err(e) = serve_connection(state, incoming).await {
+                log_connection_error("HTTP/3", &*e);
+            }
+        });
+    }
+}
+
+/// Serve the requests of one QUIC connection, each on its own task.
+async fn serve_connection<T>(
+    state: Arc<ServerState<T>>,
+    incoming: quinn::Incoming,
+) -> Result<(), BoxError>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let connection = h3_quinn::Connection::new(incoming.await?);
+    let mut connection = h3::server::Connection::<_, Bytes>::new(connection).await?;
+    let mut requests = JoinSet::new();
+    let mut draining = false;
+
+    loop {
+        let resolver = tokio::select! {
+            resolver = connection.accept() => match resolver {
+                Ok(Some(resolver)) => resolver,
+                Ok(None) => break,
+                Err(e) if e.is_h3_no_error() => break,
+                Err(e) => return Err(e.into()),
+            },
+            // Send GOAWAY: requests already started are answered, the client opens no more
+            _ = state.lifecycle.closing(Listener::Http3), if !draining => {
+                draining = true;
+                connection.shutdown(0).await?;
+                continue;
+            }
+            _ = state.lifecycle.forced(Listener::Http3), if draining => break,
+        };
+
+        let state = Arc::clone(&state);
+        requests.spawn(async move {
+            if let Err(e) = serve_request(state, resolver).await {
+                log::error!("HTTP/3 request error: {}", e);
+            }
+        });
+    }
+
+    // Past the drain deadline, requests still running are cut off
+    tokio::select! {
+        _ = async { while requests.join_next().await.is_some() {} } => {}
+        _ = state.lifecycle.forced(Listener::Http3) => requests.abort_all(),
+    }
+    Ok(())
+}
+
+async fn serve_request<T>(
+    state: Arc<ServerState<T>>,
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+) -> Result<(), BoxError>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let (request, mut stream) = resolver.resolve_request().await?;
+
+    let mut body = Buffer::take(state.options.buffer_pool.as_ref());
+    while let Some(mut chunk) = stream.recv_data().await? {
+        while chunk.has_remaining() {
+            let piece = chunk.chunk();
+            body.extend_from_slice(piece);
+            let len = piece.len();
+            chunk.advance(len);
+        }
+    }
+    let response = match std::str::from_utf8(&body) {
+        Ok(body) => {
+            let method = request.method().as_str();
+            let path = request.uri().path();
+            let query = request.uri().query();
+            respond(&state, method, path, query, request.headers(), body, false).await
+        }
+        Err(_) => bad_request("Invalid UTF-8 in request body"),
+    };
+
+    let (parts, body) = response.into_parts();
+    let body = body.collect().await?.to_bytes();
+    stream
+        .send_response(Response::from_parts(parts, ()))
+        .await?;
+    stream.send_data(body).await?;
+    stream.finish().await?;
+    Ok(())
+}
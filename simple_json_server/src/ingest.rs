@@ -0,0 +1,327 @@
+//! Durable ingest: writes accepted before they run, and never lost once accepted.
+//!
+//! A webhook receiver must answer quickly, and must not lose a delivery it has answered.  With
+//! [`crate::ServerOptions::durable_ingest`], an HTTP call to a `#[write]` method is appended to
+//! a local write-ahead log and flushed to disk, then answered `202 Accepted` with
+//! `{"accepted": N}`, its position in the log, before the method runs.  A worker then runs the
+//! logged calls one at a time, in the order they arrived.  Calls still in the log when the
+//! process stops, or crashes, are run when a server is next started with the same log:
+//!
+//! ```rust,no_run
+//! use simple_json_server::ingest::DurableIngest;
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Hooks;
+//!
+//! #[actor]
+//! impl Hooks {
+//!     #[write]
+//!     pub async fn payment_received(&self, id: String, amount: u64) {}
+//! }
+//!
+//! # async fn example() {
+//! let ingest = DurableIngest::open("/var/lib/hooks/ingest.wal").unwrap();
+//! Hooks.start(ServerOptions::new(8080).durable_ingest(ingest));
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! Handling is at least once: a call interrupted by a crash runs again from the start.  Its
+//! result is only logged, since its caller has already been answered; calls refused because
+//! the server is overloaded (`429` or `503`) are retried after [`DurableIngest::retry_delay`].
+//! Read methods, `GET` calls, WebSocket calls and batches are served as usual.
+//!
+//! The log holds each call's parameters and headers as received, apart from `Authorization`,
+//! so it should be protected like the data it carries.  It is emptied whenever every call in
+//! it has run.  A log must only be used by one server at a time.
+
+use crate::server::{self, ServerState};
+use crate::Actor;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Headers left out of the log: the call was authenticated when it was accepted.
+const UNLOGGED_HEADERS: [&str; 2] = ["authorization", "proxy-authorization"];
+
+/// One line of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record {
+    Call(Call),
+    Done { done: u64 },
+}
+
+/// An accepted call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Call {
+    seq: u64,
+    method: String,
+    params: String,
+    headers: Vec<(String, String)>,
+}
+
+/// A write-ahead log of accepted calls; see the [module documentation](self).
+#[derive(Clone)]
+pub struct DurableIngest {
+    inner: Arc<Inner>,
+    retry_delay: Duration,
+}
+
+struct Inner {
+    path: PathBuf,
+    /// The log file, and the calls in it that haven't run yet.  Calls are added to the queue
+    /// under the file's lock, so the queue is in log order.
+    log: Mutex<Log>,
+    arrived: Notify,
+}
+
+struct Log {
+    file: File,
+    next_seq: u64,
+    pending: VecDeque<Call>,
+}
+
+impl fmt::Debug for DurableIngest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DurableIngest")
+            .field("path", &self.inner.path)
+            .field("pending", &self.pending())
+            .field("retry_delay", &self.retry_delay)
+            .finish()
+    }
+}
+
+impl DurableIngest {
+    /// Open the log at `path`, creating it if needed, and recover the calls in it that haven't
+    /// run yet.  Overloaded calls are retried after 1 second.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let (calls, valid) = recover(&mut file)?;
+        let next_seq = calls.back().map_or(1, |call| call.seq + 1);
+        // A line torn by a crash was never accepted; cut it off so appends start on a new line
+        let len = if calls.is_empty() { 0 } else { valid };
+        if file.metadata()?.len() != len {
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+        if !calls.is_empty() {
+            log::info!("Recovered {} ingested calls from {:?}", calls.len(), path);
+        }
+        Ok(Self {
+            inner: Arc::new(Inner {
+                path,
+                log: Mutex::new(Log {
+                    file,
+                    next_seq,
+                    pending: calls,
+                }),
+                arrived: Notify::new(),
+            }),
+            retry_delay: Duration::from_secs(1),
+        })
+    }
+
+    /// How long to wait before retrying a call the server was too busy to run.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// How many accepted calls haven't finished running yet.
+    pub fn pending(&self) -> usize {
+        self.inner.log.lock().unwrap().pending.len()
+    }
+
+    /// Append a call to the log and flush it to disk, returning its position.
+    pub(crate) async fn accept(
+        &self,
+        method: &str,
+        params: &str,
+        headers: &HeaderMap,
+    ) -> io::Result<u64> {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !UNLOGGED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let (method, params) = (method.to_string(), params.to_string());
+        let inner = Arc::clone(&self.inner);
+        let seq = tokio::task::spawn_blocking(move || {
+            let mut log = inner.log.lock().unwrap();
+            let call = Call {
+                seq: log.next_seq,
+                method,
+                params,
+                headers,
+            };
+            let mut line = serde_json::to_vec(&Record::Call(call.clone()))?;
+            line.push(b'\n');
+            log.file.write_all(&line)?;
+            log.file.sync_data()?;
+            log.next_seq += 1;
+            log.pending.push_back(call);
+            Ok::<_, io::Error>(log.next_seq - 1)
+        })
+        .await??;
+        self.inner.arrived.notify_one();
+        Ok(seq)
+    }
+
+    /// The oldest call that hasn't run yet.
+    fn next(&self) -> Option<Call> {
+        self.inner.log.lock().unwrap().pending.front().cloned()
+    }
+
+    /// Record that call `seq`, the oldest, has run.  Once none are left the log is emptied.
+    async fn done(&self, seq: u64) -> io::Result<()> {
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || {
+            let mut log = inner.log.lock().unwrap();
+            if log.pending.front().is_some_and(|call| call.seq == seq) {
+                log.pending.pop_front();
+            }
+            if log.pending.is_empty() {
+                log.file.set_len(0)?;
+                log.file.sync_data()
+            } else {
+                // Not flushed: if it is lost, the call runs again
+                let mut line = serde_json::to_vec(&Record::Done { done: seq })?;
+                line.push(b'\n');
+                log.file.write_all(&line)
+            }
+        })
+        .await?
+    }
+}
+
+/// The calls in the log that haven't run, and the length of its complete lines.
+fn recover(file: &mut File) -> io::Result<(VecDeque<Call>, u64)> {
+    file.rewind()?;
+    let mut calls = VecDeque::new();
+    let mut valid = 0;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        match serde_json::from_str(&line) {
+            Ok(Record::Call(call)) => calls.push_back(call),
+            Ok(Record::Done { done }) => calls.retain(|call| call.seq != done),
+            Err(_) => break,
+        }
+        valid += read as u64;
+    }
+    Ok((calls, valid))
+}
+
+/// Run the calls accepted into `ingest`, in order, until the server shuts down.
+pub(crate) async fn process<T>(state: Arc<ServerState<T>>, ingest: DurableIngest)
+where
+    T: Actor + Send + Sync + 'static,
+{
+    loop {
+        let Some(call) = ingest.next() else {
+            ingest.inner.arrived.notified().await;
+            continue;
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in &call.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        let mut meta = server::call_meta(&state, &headers);
+        // The caller stopped waiting when the call was accepted
+        meta.deadline = None;
+        let reply = server::dispatch(&state, &call.method, &call.params, meta).await;
+        if matches!(
+            reply.status,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ) {
+            log::warn!(
+                "Ingested call {} to {} was refused, retrying in {:?}: {}",
+                call.seq,
+                call.method,
+                ingest.retry_delay,
+                reply.body
+            );
+            tokio::time::sleep(ingest.retry_delay).await;
+            continue;
+        }
+        if !reply.status.is_success() {
+            log::warn!(
+                "Ingested call {} to {} failed with {}: {}",
+                call.seq,
+                call.method,
+                reply.status,
+                reply.body
+            );
+        }
+        if let Err(e) = ingest.done(call.seq).await {
+            log::error!("Failed to record ingested call {} as done: {}", call.seq, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recovers_calls_that_have_not_run() {
+        let path = std::env::temp_dir().join(format!("ingest_test_{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ingest = DurableIngest::open(&path).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.insert("x-event", HeaderValue::from_static("paid"));
+        for params in [r#"{"id": 1}"#, r#"{"id": 2}"#, r#"{"id": 3}"#] {
+            ingest.accept("deliver", params, &headers).await.unwrap();
+        }
+        ingest.done(1).await.unwrap();
+        drop(ingest);
+        // A crash while a call was being appended
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"seq":4,"method":"deli"#).unwrap();
+        drop(file);
+
+        let ingest = DurableIngest::open(&path).unwrap();
+        assert_eq!(ingest.pending(), 2);
+        let call = ingest.next().unwrap();
+        assert_eq!((call.seq, call.params.as_str()), (2, r#"{"id": 2}"#));
+        assert_eq!(call.headers, [("x-event".to_string(), "paid".to_string())]);
+        assert_eq!(
+            ingest
+                .accept("deliver", "{}", &HeaderMap::new())
+                .await
+                .unwrap(),
+            4
+        );
+
+        for seq in 2..=4 {
+            ingest.done(seq).await.unwrap();
+        }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
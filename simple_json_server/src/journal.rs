@@ -0,0 +1,334 @@
+//! An ordered log of the state-changing calls an actor has served.
+//!
+//! When a server is given a [`Journal`] (see [`crate::ServerOptions::journal`]), every call to a
+//! `#[write]` method is appended to it once the method returns.  Replaying the journal against a
+//! fresh actor rebuilds its state, which is what [replication](crate::replication) does.
+//!
+//! Writes to a journaled actor are applied one at a time so the journal order is always the
+//! order in which the actor saw them.  An entry keeps the request headers and cookies its
+//! method's `#[from_header]` and `#[from_cookie]` parameters were bound to, and is replayed in a
+//! [`RequestContext`](crate::RequestContext) carrying them.
+//!
+//! The journal is kept in memory until [`Journal::truncate_before`] drops the entries before
+//! a point.  A server does this when it takes a [snapshot](crate::snapshot), which records the
+//! last entry it includes.
+//!
+//! # Duplicate writes
+//!
+//! A client that retries a write after a timeout, or a transport that delivers a message twice,
+//! would apply its effect twice.  A write can carry a dedup key to prevent this: the
+//! `Idempotency-Key` request header, or whatever [`Journal::dedup_key`] derives from the call.
+//! The journal remembers the keys of its most recent entries (see [`Journal::dedup_window`]);
+//! a write whose key is among them is not run again but answered with the response of the
+//! first one.  Entries are numbered, so a replica replaying the journal skips any it has
+//! already applied.
+//!
+//! ```rust
+//! use simple_json_server::journal::Journal;
+//!
+//! // Payments carry their own id, which makes a good key
+//! let journal = Journal::new().dedup_key(|method, params| {
+//!     let params: serde_json::Value = serde_json::from_str(params).ok()?;
+//!     Some(format!("{}:{}", method, params.get("payment_id")?))
+//! });
+//! ```
+
+use crate::options::Shared;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+type DedupKey = dyn Fn(&str, &str) -> Option<String> + Send + Sync;
+
+/// One recorded call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Position in the journal; the first entry is 1.
+    pub seq: u64,
+    /// The method that was called.
+    pub method: String,
+    /// The raw JSON parameters of the call.
+    pub params: String,
+    /// The call's dedup key, if it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The request headers the call's bound parameters were read from, by lowercase name.
+    /// Bound cookies are kept together as a `cookie` header.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// A shared, in-memory journal.  Cloning is cheap and clones refer to the same journal.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    inner: Arc<JournalInner>,
+    dedup_window: usize,
+    dedup_key: Option<Shared<DedupKey>>,
+}
+
+#[derive(Debug)]
+struct JournalInner {
+    entries: Mutex<Entries>,
+    latest: watch::Sender<u64>,
+    writes: tokio::sync::Mutex<()>,
+    recent: Mutex<Recent>,
+}
+
+/// The entries kept, oldest first, and the sequence number of the first of them (or of the next
+/// entry, while none are kept).
+#[derive(Debug)]
+struct Entries {
+    first: u64,
+    entries: VecDeque<JournalEntry>,
+}
+
+/// The dedup keys of the latest keyed entries, and the responses to their calls.
+#[derive(Debug, Default)]
+struct Recent {
+    keys: VecDeque<String>,
+    responses: HashMap<String, String>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    /// Create an empty journal that remembers the dedup keys of the last 1000 keyed writes.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(JournalInner {
+                entries: Mutex::new(Entries {
+                    first: 1,
+                    entries: VecDeque::new(),
+                }),
+                latest: watch::channel(0).0,
+                writes: tokio::sync::Mutex::new(()),
+                recent: Mutex::default(),
+            }),
+            dedup_window: 1000,
+            dedup_key: None,
+        }
+    }
+
+    /// Remember the dedup keys of the last `writes` keyed writes; 0 turns deduplication off.
+    pub fn dedup_window(mut self, writes: usize) -> Self {
+        self.dedup_window = writes;
+        self
+    }
+
+    /// Derive a write's dedup key from its method and raw JSON parameters.  Writes for which
+    /// `key` returns `None` fall back to the `Idempotency-Key` header.
+    pub fn dedup_key(
+        mut self,
+        key: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.dedup_key = Some(Shared(Arc::new(key)));
+        self
+    }
+
+    /// The sequence number of the last entry, or 0 if the journal is empty.
+    pub fn last_seq(&self) -> u64 {
+        *self.inner.latest.borrow()
+    }
+
+    /// The sequence number of the oldest entry kept, or of the next entry if none are kept.
+    /// Entries before it have been dropped with [`Journal::truncate_before`].
+    pub fn first_seq(&self) -> u64 {
+        self.inner.entries.lock().unwrap().first
+    }
+
+    /// All entries kept with a sequence number of at least `seq`, in order.
+    pub fn entries_since(&self, seq: u64) -> Vec<JournalEntry> {
+        let entries = self.inner.entries.lock().unwrap();
+        let skip = usize::try_from(seq.saturating_sub(entries.first)).unwrap_or(usize::MAX);
+        entries.entries.iter().skip(skip).cloned().collect()
+    }
+
+    /// Drop the entries before `seq`, once they are no longer needed: a server does this when
+    /// a snapshot is taken, as the snapshot holds their effect.  Sequence numbers carry on
+    /// from where they were, and dedup keys are still remembered.
+    pub fn truncate_before(&self, seq: u64) {
+        let mut entries = self.inner.entries.lock().unwrap();
+        let count = usize::try_from(seq.saturating_sub(entries.first))
+            .unwrap_or(usize::MAX)
+            .min(entries.entries.len());
+        entries.entries.drain(..count);
+        entries.first += count as u64;
+    }
+
+    /// Number the entries of an empty journal from `seq`, for a server whose state was restored
+    /// from a snapshot including the entries before it.
+    pub(crate) fn start_at(&self, seq: u64) {
+        let mut entries = self.inner.entries.lock().unwrap();
+        if entries.entries.is_empty() && seq > entries.first {
+            entries.first = seq;
+            self.inner.latest.send_replace(seq - 1);
+        }
+    }
+
+    /// The dedup key of a call, from the [`Journal::dedup_key`] hook or else `header`.
+    pub(crate) fn key_for(
+        &self,
+        method: &str,
+        params: &str,
+        header: Option<&str>,
+    ) -> Option<String> {
+        if self.dedup_window == 0 {
+            return None;
+        }
+        self.dedup_key
+            .as_ref()
+            .and_then(|key| (key.0)(method, params))
+            .or_else(|| header.map(str::to_string))
+    }
+
+    /// The response to the recent write with dedup key `key`, if there was one.
+    pub(crate) fn recorded(&self, key: &str) -> Option<String> {
+        let recent = self.inner.recent.lock().unwrap();
+        recent.responses.get(key).cloned()
+    }
+
+    /// Append a call and return its sequence number.  A call with a dedup `key` has its
+    /// `response` remembered for repeats of it.
+    pub(crate) fn append(
+        &self,
+        method: &str,
+        params: &str,
+        key: Option<String>,
+        headers: BTreeMap<String, String>,
+        response: &str,
+    ) -> u64 {
+        let mut entries = self.inner.entries.lock().unwrap();
+        let seq = entries.first + entries.entries.len() as u64;
+        if let Some(key) = &key {
+            let mut recent = self.inner.recent.lock().unwrap();
+            if recent.keys.len() >= self.dedup_window {
+                if let Some(oldest) = recent.keys.pop_front() {
+                    recent.responses.remove(&oldest);
+                }
+            }
+            recent.keys.push_back(key.clone());
+            recent.responses.insert(key.clone(), response.to_string());
+        }
+        entries.entries.push_back(JournalEntry {
+            seq,
+            method: method.to_string(),
+            params: params.to_string(),
+            key,
+            headers,
+        });
+        self.inner.latest.send_replace(seq);
+        seq
+    }
+
+    /// Watch the sequence number of the last entry.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<u64> {
+        self.inner.latest.subscribe()
+    }
+
+    /// Hold while running a write and appending it, so journal order matches execution order.
+    pub(crate) async fn lock_writes(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.inner.writes.lock().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read_back() {
+        let journal = Journal::new();
+        assert_eq!(journal.last_seq(), 0);
+
+        assert_eq!(
+            journal.append("add", r#"{"a": 1}"#, None, BTreeMap::new(), "1"),
+            1
+        );
+        assert_eq!(
+            journal.append("add", r#"{"a": 2}"#, None, BTreeMap::new(), "3"),
+            2
+        );
+        assert_eq!(journal.last_seq(), 2);
+
+        let entries = journal.entries_since(2);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].params, r#"{"a": 2}"#);
+        assert_eq!(journal.entries_since(0).len(), 2);
+    }
+
+    #[test]
+    fn test_truncate_before() {
+        let journal = Journal::new();
+        for i in 0..5 {
+            journal.append("add", "{}", Some(i.to_string()), BTreeMap::new(), "{}");
+        }
+        journal.truncate_before(4);
+        assert_eq!(journal.first_seq(), 4);
+        let seqs = |from| -> Vec<u64> {
+            let entries = journal.entries_since(from);
+            entries.iter().map(|entry| entry.seq).collect()
+        };
+        assert_eq!(seqs(0), [4, 5]);
+        assert_eq!(seqs(5), [5]);
+        assert_eq!(seqs(6), Vec::<u64>::new());
+        assert_eq!(journal.recorded("0").as_deref(), Some("{}"));
+
+        // Numbering carries on, even once every entry has been dropped
+        journal.truncate_before(100);
+        assert_eq!(journal.first_seq(), 6);
+        assert!(journal.entries_since(0).is_empty());
+        assert_eq!(journal.append("add", "{}", None, BTreeMap::new(), "{}"), 6);
+        assert_eq!(journal.last_seq(), 6);
+        journal.truncate_before(2);
+        assert_eq!(seqs(0), [6]);
+
+        let restored = Journal::new();
+        restored.start_at(11);
+        assert_eq!(restored.last_seq(), 10);
+        assert_eq!(
+            restored.append("add", "{}", None, BTreeMap::new(), "{}"),
+            11
+        );
+        assert_eq!(restored.entries_since(0)[0].seq, 11);
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let journal = Journal::new().dedup_window(2);
+        for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+            journal.append(
+                "add",
+                "{}",
+                Some(key.to_string()),
+                BTreeMap::new(),
+                &i.to_string(),
+            );
+        }
+        assert_eq!(journal.recorded("a"), None);
+        assert_eq!(journal.recorded("b").as_deref(), Some("1"));
+        assert_eq!(journal.recorded("c").as_deref(), Some("2"));
+        assert_eq!(journal.entries_since(3)[0].key.as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_dedup_keys() {
+        let journal = Journal::new()
+            .dedup_key(|method, params| (method == "pay").then(|| params.to_string()));
+        assert_eq!(
+            journal.key_for("pay", "{}", Some("h")).as_deref(),
+            Some("{}")
+        );
+        assert_eq!(
+            journal.key_for("add", "{}", Some("h")).as_deref(),
+            Some("h")
+        );
+        assert_eq!(journal.key_for("add", "{}", None), None);
+        let off = journal.dedup_window(0);
+        assert_eq!(off.key_for("pay", "{}", Some("h")), None);
+    }
+}
@@ -0,0 +1,241 @@
+//! Running actors on Kubernetes: probes, graceful termination and pod metadata.
+//!
+//! With [`ServerOptions::kubernetes`](crate::ServerOptions::kubernetes) the server answers two
+//! `GET` endpoints for the kubelet's probes:
+//!
+//! - `/healthz` (liveness) answers `200` whenever the server is serving requests.
+//! - `/readyz` (readiness) answers `200` while the actor's mailbox takes calls, and `503` while
+//!   it is held, e.g. during a snapshot restore, for longer than [`Kubernetes::mailbox_wait`],
+//!   and from the moment the pod starts terminating.
+//!
+//! When a pod is deleted, Kubernetes sends `SIGTERM` and removes it from its services'
+//! endpoints at the same time, so for a few seconds new calls keep arriving.
+//! [`Kubernetes::run`] waits for `SIGTERM` and then fails readiness, keeps serving for
+//! [`Kubernetes::pre_stop_delay`] until the endpoints are updated, and drains the server within
+//! [`Kubernetes::grace_period`], which should be a few seconds less than the pod's
+//! `terminationGracePeriodSeconds`:
+//!
+//! ```rust,no_run
+//! use simple_json_server::k8s::Kubernetes;
+//! use simple_json_server::logging::JsonLogger;
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let k8s = Kubernetes::from_env();
+//!     JsonLogger::new(log::LevelFilter::Info)
+//!         .fields(k8s.labels())
+//!         .init()
+//!         .unwrap();
+//!     let server = Calculator.start(ServerOptions::new(8080).kubernetes(k8s.clone()));
+//!     k8s.run(&server).await;
+//! }
+//! ```
+//!
+//! [`Kubernetes::from_env`] reads the pod's name, namespace and node from the `POD_NAME`,
+//! `POD_NAMESPACE` and `NODE_NAME` environment variables, which the pod spec sets with the
+//! downward API.  They label the series of the admin API's `metrics` endpoint, and, as above,
+//! can be added to every JSON log line.
+
+use crate::server::{Reply, ServerState};
+use crate::{Actor, ServerHandle};
+use hyper::StatusCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Path of the liveness probe.
+pub(crate) const LIVENESS: &str = "/healthz";
+/// Path of the readiness probe.
+pub(crate) const READINESS: &str = "/readyz";
+
+/// Probe and termination settings, and the pod's metadata.  Clones share whether the pod is
+/// terminating, so keep one to call [`Kubernetes::run`] with.
+#[derive(Debug, Clone)]
+pub struct Kubernetes {
+    pod: Option<String>,
+    namespace: Option<String>,
+    node: Option<String>,
+    mailbox_wait: Duration,
+    pre_stop_delay: Duration,
+    grace_period: Duration,
+    terminating: Arc<AtomicBool>,
+}
+
+impl Default for Kubernetes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Kubernetes {
+    /// Settings without pod metadata.  The mailbox may be held for up to a second before the
+    /// pod counts as unready; on termination the server keeps serving for 5 seconds and then
+    /// drains for up to 20.
+    pub fn new() -> Self {
+        Self {
+            pod: None,
+            namespace: None,
+            node: None,
+            mailbox_wait: Duration::from_secs(1),
+            pre_stop_delay: Duration::from_secs(5),
+            grace_period: Duration::from_secs(20),
+            terminating: Arc::default(),
+        }
+    }
+
+    /// Settings with the pod metadata in `POD_NAME`, `POD_NAMESPACE` and `NODE_NAME`, if set.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        Self {
+            pod: var("POD_NAME"),
+            namespace: var("POD_NAMESPACE"),
+            node: var("NODE_NAME"),
+            ..Self::new()
+        }
+    }
+
+    /// The pod's name.
+    pub fn pod(mut self, pod: impl Into<String>) -> Self {
+        self.pod = Some(pod.into());
+        self
+    }
+
+    /// The pod's namespace.
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// The node the pod runs on.
+    pub fn node(mut self, node: impl Into<String>) -> Self {
+        self.node = Some(node.into());
+        self
+    }
+
+    /// How long the readiness probe waits for the mailbox before answering `503`.
+    pub fn mailbox_wait(mut self, wait: Duration) -> Self {
+        self.mailbox_wait = wait;
+        self
+    }
+
+    /// How long to keep serving after `SIGTERM` before draining.
+    pub fn pre_stop_delay(mut self, delay: Duration) -> Self {
+        self.pre_stop_delay = delay;
+        self
+    }
+
+    /// How long draining may take before calls still in progress are abandoned.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// The pod's metadata as `pod`, `namespace` and `node` labels, for those that are known.
+    pub fn labels(&self) -> Vec<(String, String)> {
+        [
+            ("pod", &self.pod),
+            ("namespace", &self.namespace),
+            ("node", &self.node),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+        .collect()
+    }
+
+    /// Whether the pod has started terminating.
+    pub fn is_terminating(&self) -> bool {
+        self.terminating.load(Ordering::SeqCst)
+    }
+
+    /// Wait for `SIGTERM` (Ctrl-C where there is none), then [`Kubernetes::terminate`] `server`.
+    pub async fn run(&self, server: &ServerHandle) {
+        wait_for_sigterm().await;
+        log::info!("Received SIGTERM; terminating");
+        self.terminate(server).await;
+    }
+
+    /// Fail readiness, keep serving for the pre-stop delay, then drain `server` within the grace
+    /// period.
+    pub async fn terminate(&self, server: &ServerHandle) {
+        self.terminating.store(true, Ordering::SeqCst);
+        tokio::time::sleep(self.pre_stop_delay).await;
+        if tokio::time::timeout(self.grace_period, server.drain())
+            .await
+            .is_err()
+        {
+            log::warn!(
+                "{} connections still open after the grace period",
+                server.connections()
+            );
+        }
+    }
+
+    /// Answer the probe at `path`, if it is one.
+    pub(crate) async fn probe<T>(&self, state: &ServerState<T>, path: &str) -> Option<Reply>
+    where
+        T: Actor + Send + Sync + 'static,
+    {
+        let ready = match path {
+            LIVENESS => true,
+            READINESS => {
+                !self.is_terminating()
+                    && !state.lifecycle.is_draining()
+                    && tokio::time::timeout(self.mailbox_wait, state.mailbox.read())
+                        .await
+                        .is_ok()
+            }
+            _ => return None,
+        };
+        Some(if ready {
+            Reply::ok("\"ok\"".to_string())
+        } else {
+            Reply::error(StatusCode::SERVICE_UNAVAILABLE, "not ready".to_string())
+        })
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_sigterm() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        Err(e) => {
+            log::error!("Failed to listen for SIGTERM: {}", e);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_sigterm() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let k8s = Kubernetes::new().pod("calc-0").namespace("prod");
+        assert_eq!(
+            k8s.labels(),
+            [
+                ("pod".to_string(), "calc-0".to_string()),
+                ("namespace".to_string(), "prod".to_string())
+            ]
+        );
+    }
+}
@@ -76,11 +76,235 @@
 
 #![allow(clippy::needless_doctest_main)]
 
+// Lets code generated by the `#[actor]` macro name this crate from inside it, too.
+extern crate self as simple_json_server;
+
 // Re-export the actor macro
 pub use actor_attribute_macro::actor;
 
+mod admin;
+pub mod backpressure;
+pub mod batch;
+pub mod binary;
+pub mod blocking;
+pub mod breaker;
+pub mod buffers;
+mod client;
+pub mod cluster;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod content_type;
+mod context;
+pub mod deadletter;
+mod deadline;
+mod describe;
+pub mod encryption;
+pub mod events;
+pub mod fallback;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fields;
+pub mod flags;
+pub mod gateway;
+pub mod handle;
+pub mod hooks;
+#[cfg(feature = "http3")]
+mod http3;
+pub mod ingest;
+pub mod journal;
+pub mod k8s;
+pub mod limits;
+pub mod logging;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod memory;
+pub mod message;
+mod middleware;
+pub mod mock;
+pub mod monitor;
+pub mod numbers;
+mod options;
+#[cfg(feature = "store")]
+pub mod outbox;
+mod params;
+pub mod pool;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preconditions;
+mod pretty;
+pub mod privacy;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+mod query;
+pub mod quota;
+pub mod raw;
+pub mod registry;
+pub mod replication;
+mod runtime;
+pub mod saga;
+pub mod sampling;
+pub mod sanitize;
+pub mod sdk;
+pub mod secrets;
+pub mod security;
+mod server;
+pub mod shedding;
+pub mod signing;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod slowlog;
+pub mod snapshot;
+pub mod socket;
+mod srv;
+pub mod startup;
+pub mod stats;
+#[cfg(feature = "store")]
+pub mod store;
+mod streaming;
+pub mod supervisor;
 pub mod tls;
+pub mod trace;
+pub mod validated;
+pub mod versioning;
+pub mod warmup;
+mod wire;
+mod ws_client;
+pub use client::{ActorRef, ClientError};
+pub use context::{Forward, RequestContext};
+pub use handle::ServerHandle;
+pub use options::ServerOptions;
 pub use tls::TlsConfig;
+pub use ws_client::{Reconnect, WsActorRef};
+
+use runtime::Runtime as _;
+
+// Re-exported with serde support, so method parameters and results can use these types without
+// the actor's crate enabling serde features itself.
+#[cfg(feature = "chrono")]
+pub use chrono;
+#[cfg(feature = "decimal")]
+pub use rust_decimal;
+#[cfg(feature = "uuid")]
+pub use uuid;
+// Re-exported so stores for `snapshot::ObjectStoreSnapshots` can be built without depending on a
+// matching version of the crate.
+#[cfg(feature = "object-store")]
+pub use object_store;
+
+/// Items used by code generated by the `#[actor]` macro.  Not public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::hooks::refuse;
+    pub use crate::params::{
+        bound_param, byte_len, check_enum, parse_params, rejected_params, sanitize_param,
+        too_large, unknown_method, EnumCheck,
+    };
+    pub use crate::preconditions::check_version;
+    pub use crate::streaming::stream_result;
+    pub use crate::wire::{CheckResponse, ResponseCheck, SkipResponseCheck};
+    pub use tokio::sync::Semaphore;
+
+    /// The table generated dispatch looks method names up in, giving each method's index.
+    pub type Routes = phf::Map<&'static str, usize>;
+}
+
+/// Runtime description of a method exposed by an actor, generated by the `#[actor]` macro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodInfo {
+    /// The method name, as used in the URL path or the WebSocket `method` field.
+    pub name: &'static str,
+    /// The method's doc comment, empty if it has none.
+    pub doc: &'static str,
+    /// The method's parameters, in declaration order.
+    pub params: &'static [MethodParam],
+    /// The method's return type, as written in its signature.
+    pub returns: &'static str,
+    /// Whether the method reads or changes the actor's state.
+    pub kind: MethodKind,
+    /// Whether calls take exclusive access to the actor, waiting for other calls to finish and
+    /// holding off new ones.  True for methods explicitly marked `#[write]`.
+    pub exclusive: bool,
+    /// How many calls of the method may run at once, if limited with `#[concurrency(max = N)]`
+    /// or `#[serialized]`.
+    pub max_concurrency: Option<u32>,
+    /// Whether calls run on the server's blocking pool rather than the async runtime, declared
+    /// with `#[blocking]`.  See [`blocking`].
+    pub blocking: bool,
+    /// Whether the response is serialized as it is sent over HTTP rather than built in memory
+    /// first, declared with `#[streaming]`.
+    pub streaming: bool,
+    /// Whether the method is also served by `GET /<method>` with its parameters in the query
+    /// string, declared with `#[get]`.
+    pub get: bool,
+    /// The feature flag that must be enabled for the method to be served, declared with
+    /// `#[flag("name")]`.  See [`flags`].
+    pub flag: Option<&'static str>,
+    /// The request `Content-Type`s the method takes in place of the server's, declared with
+    /// `#[accepts("text/plain")]`; empty if it takes the server's.  See [`content_type`].
+    pub accepts: &'static [&'static str],
+    /// Set for methods marked `#[deprecated]`; their calls are answered with `Deprecation`,
+    /// `Sunset` and `Warning` headers.
+    pub deprecated: Option<Deprecation>,
+    /// Canonical example calls, declared with `#[example(params = "...", response = "...")]`.
+    pub examples: &'static [MethodExample],
+}
+
+/// How a method is deprecated, declared with
+/// `#[deprecated(note = "...", sunset = "YYYY-MM-DD")]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    /// What callers should do instead.
+    pub note: Option<&'static str>,
+    /// The date after which the method may be removed, as `YYYY-MM-DD`.
+    pub sunset: Option<&'static str>,
+}
+
+/// A parameter of a method: its name in the JSON message and its Rust type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodParam {
+    /// The parameter's name.
+    pub name: &'static str,
+    /// The parameter's type, as written in the method's signature.
+    pub ty: &'static str,
+    /// Where the parameter's value comes from.
+    pub source: ParamSource,
+    /// Whether the value is kept out of logs, payload samples and error messages, declared
+    /// with `#[sensitive]`.
+    pub sensitive: bool,
+    /// Whether the value arrives encrypted and is decrypted before the method sees it,
+    /// declared with `#[sensitive(encrypted)]`.  See [`encryption`].
+    pub encrypted: bool,
+}
+
+/// Where a method parameter's value is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamSource {
+    /// The JSON message.
+    Body,
+    /// The named HTTP request header, for parameters marked `#[from_header("...")]`.
+    Header(&'static str),
+    /// The named cookie, for parameters marked `#[from_cookie("...")]`.
+    Cookie(&'static str),
+}
+
+/// An example call of a method, as compact JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodExample {
+    /// The call's parameters, a JSON object.
+    pub params: &'static str,
+    /// The expected result, if given.
+    pub response: Option<&'static str>,
+}
+
+/// How a method uses the actor's state, declared with `#[read]` or `#[write]` on the method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodKind {
+    /// The method only reads state.  Read methods are served by read-only replicas and are not
+    /// journaled.
+    Read,
+    /// The method may change state.  This is the default for methods without a marker.
+    Write,
+}
 
 /// The Actor trait must be implemented by all servers.  Implementation is most commonly achieved by using
 /// the `#[actor]` macro with any other Rust `struct` and `impl`.
@@ -92,6 +316,68 @@ pub trait Actor {
         msg: &str,
     ) -> impl std::future::Future<Output = String> + Send;
 
+    /// Describes the methods this actor exposes.  The `#[actor]` macro generates this; manual
+    /// implementations that don't override it expose no metadata.
+    fn methods(&self) -> &'static [MethodInfo] {
+        &[]
+    }
+
+    /// Calls a method with a typed message instead of JSON; see [`message`].
+    fn handle<M: message::Message<Self>>(
+        &self,
+        message: M,
+    ) -> impl std::future::Future<Output = M::Response> + Send {
+        message.handle(self)
+    }
+
+    /// Serializes the actor's state for a backup; see [`snapshot`].  The `#[actor]` macro
+    /// generates this from a `fn snapshot(&self) -> S` in the impl block.
+    fn snapshot_state(&self) -> Result<String, String> {
+        Err("This actor does not support snapshots".to_string())
+    }
+
+    /// Replaces the actor's state with one produced by [`Actor::snapshot_state`].  The
+    /// `#[actor]` macro generates this from a `fn restore(&self, state: S)` in the impl block.
+    fn restore_state(&self, _state: &str) -> Result<(), String> {
+        Err("This actor does not support snapshots".to_string())
+    }
+
+    /// Prepares the actor before its server accepts connections, e.g. to open connections or
+    /// fill caches; an error stops the server from starting.  The `#[actor]` macro generates
+    /// this from a private `async fn init(&mut self)` in the impl block.  See [`warmup`].
+    fn init(&mut self) -> impl std::future::Future<Output = Result<(), String>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Releases what the actor holds when its server stops, after the last connection has
+    /// closed and with no calls in progress; the actor is dropped right after.  The `#[actor]`
+    /// macro generates this from a private `async fn on_shutdown(&self)` in the impl block.  See
+    /// [`handle`].
+    fn on_shutdown(&self) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Collects what the actor holds about the data subject `subject_id`, as JSON, for a
+    /// data-subject access request.  The `#[actor]` macro generates this from a private
+    /// `async fn export_subject_data(&self, subject_id: &str)` in the impl block.  See
+    /// [`privacy`].
+    fn export_subject_data(
+        &self,
+        _subject_id: &str,
+    ) -> impl std::future::Future<Output = Result<String, privacy::SubjectError>> + Send {
+        async { Err(privacy::SubjectError::Unsupported) }
+    }
+
+    /// Erases what the actor holds about the data subject `subject_id`.  The `#[actor]` macro
+    /// generates this from a private `async fn delete_subject_data(&self, subject_id: &str)` in
+    /// the impl block.  See [`privacy`].
+    fn delete_subject_data(
+        &self,
+        _subject_id: &str,
+    ) -> impl std::future::Future<Output = Result<(), privacy::SubjectError>> + Send {
+        async { Err(privacy::SubjectError::Unsupported) }
+    }
+
     /// Creates a new actor with TLS support by spawning a thread to listen on the specified port for incoming JSON messages and processes them using dispatch.
     /// If websocket is true, the server will use the websocket protocol instead of HTTP.
     /// If tls_config is provided, the server will use TLS/SSL encryption.
@@ -100,26 +386,38 @@ pub trait Actor {
     where
         Self: Send + Sync + Sized + 'static,
     {
-        let actor = std::sync::Arc::new(self);
-
-        // Try to spawn on existing runtime first, fallback to new thread with runtime
-        let handle = tokio::runtime::Handle::current();
-        handle.spawn(async move {
-            match (websocket, tls_config) {
-                (true, Some(tls_config)) => {
-                    start_websocket_server_with_tls(actor, port, tls_config).await;
-                }
-                (true, None) => {
-                    start_websocket_server(actor, port).await;
-                }
-                (false, Some(tls_config)) => {
-                    start_http_server_with_tls(actor, port, tls_config).await;
-                }
-                (false, None) => {
-                    start_http_server(actor, port).await;
-                }
-            }
-        });
+        let mut options = ServerOptions::new(port).websocket(websocket);
+        if let Some(tls_config) = tls_config {
+            options = options.tls(tls_config);
+        }
+        self.create_with(options);
+    }
+
+    /// Creates a new actor from a full set of [`ServerOptions`] by spawning a task on the current
+    /// tokio runtime to listen for incoming JSON messages and process them using dispatch.
+    /// This is the most general way to start a server; the other `create` methods call it.
+    ///
+    /// This method consumes the actor, preventing further use after starting the server.
+    fn create_with(self, options: ServerOptions)
+    where
+        Self: Send + Sync + Sized + 'static,
+    {
+        self.start(options);
+    }
+
+    /// Like [`Actor::create_with`], but returns a [`ServerHandle`] for finding out where the
+    /// server listens and for draining it, e.g. during a zero-downtime upgrade.
+    ///
+    /// Must be called within a tokio runtime, unless the `smol` or `async-std` feature is
+    /// enabled: the server then runs on a small tokio runtime the crate starts on first use, and
+    /// the handle and [`ActorRef`] calls can be awaited from any executor.
+    fn start(self, options: ServerOptions) -> ServerHandle
+    where
+        Self: Send + Sync + Sized + 'static,
+    {
+        let server = ServerHandle::new();
+        runtime::current().spawn(server::run(self, options, server.lifecycle()));
+        server
     }
 
     /// Creates a new actor using HTTP and without TLS. The simplest case so with the least
@@ -182,348 +480,5 @@ pub trait Actor {
     }
 }
 
-use futures_util::{SinkExt, StreamExt};
-use http_body_util::Full;
-use hyper::body::Bytes;
-use hyper::service::service_fn;
-use hyper::{Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
-use hyper_util::server::conn::auto::Builder;
-use std::convert::Infallible;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-
-/// Start an HTTP server that processes JSON messages
-async fn start_http_server<T>(actor: Arc<T>, port: u16)
-where
-    T: Actor + Send + Sync + 'static,
-{
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = TcpListener::bind(&addr).await.unwrap_or_else(|e| {
-        panic!("Failed to bind HTTP server to {addr:?}: {}", e);
-    });
-
-    log::info!("HTTP server listening on http://{}", addr);
-
-    loop {
-        let (stream, _) = match listener.accept().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                log::error!("Failed to accept connection: {}", e);
-                continue;
-            }
-        };
-
-        let actor = Arc::clone(&actor);
-
-        tokio::spawn(async move {
-            let io = TokioIo::new(stream);
-            let service = service_fn(move |req| {
-                let actor = Arc::clone(&actor);
-                async move { handle_http_request(actor, req).await }
-            });
-
-            if let Err(e) = Builder::new(hyper_util::rt::TokioExecutor::new())
-                .serve_connection(io, service)
-                .await
-            {
-                log::error!("HTTP connection error: {}", e);
-            }
-        });
-    }
-}
-
-/// Handle individual HTTP requests (unified for HTTP and HTTPS)
-async fn handle_http_request<T>(
-    actor: Arc<T>,
-    req: Request<hyper::body::Incoming>,
-) -> Result<Response<Full<Bytes>>, Infallible>
-where
-    T: Actor + Send + Sync + 'static,
-{
-    let method = req.method().as_str().to_string();
-    let path = req.uri().path().to_string();
-
-    // Read the request body
-    let body_str = match http_body_util::BodyExt::collect(req.into_body()).await {
-        Ok(collected) => match std::str::from_utf8(&collected.to_bytes()) {
-            Ok(s) => s.to_string(),
-            Err(_) => {
-                return Ok(Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Full::new(Bytes::from("Invalid UTF-8 in request body")))
-                    .unwrap());
-            }
-        },
-        Err(_) => {
-            return Ok(Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(Bytes::from("Failed to read request body")))
-                .unwrap());
-        }
-    };
-
-    // Process the HTTP request
-    if method == "POST" {
-        // Extract method name from path (e.g., "/add" -> "add")
-        let method_name = path.trim_start_matches('/');
-
-        // Process the message using the actor
-        let response_body = (*actor).dispatch(method_name, &body_str).await;
-
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "application/json")
-            .header("Access-Control-Allow-Origin", "*")
-            .header("Access-Control-Allow-Methods", "POST, OPTIONS")
-            .header("Access-Control-Allow-Headers", "Content-Type")
-            .body(Full::new(Bytes::from(response_body)))
-            .unwrap())
-    } else if method == "OPTIONS" {
-        // Handle CORS preflight requests
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header("Access-Control-Allow-Origin", "*")
-            .header("Access-Control-Allow-Methods", "POST, OPTIONS")
-            .header("Access-Control-Allow-Headers", "Content-Type")
-            .header("Content-Length", "0")
-            .body(Full::new(Bytes::new()))
-            .unwrap())
-    } else {
-        Ok(Response::builder()
-            .status(StatusCode::METHOD_NOT_ALLOWED)
-            .header("Content-Type", "text/plain")
-            .header("Access-Control-Allow-Origin", "*")
-            .body(Full::new(Bytes::from("Method Not Allowed")))
-            .unwrap())
-    }
-}
-
-/// Start a WebSocket server that processes JSON messages
-async fn start_websocket_server<T>(actor: Arc<T>, port: u16)
-where
-    T: Actor + Send + Sync + 'static,
-{
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .unwrap_or_else(|e| {
-            panic!(
-                "Failed to bind WebSocket server with address {addr:?}: {}",
-                e
-            );
-        });
-
-    log::info!("WebSocket server listening on ws://{}", addr);
-
-    loop {
-        let (stream, _) = match listener.accept().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                log::error!("Failed to accept WebSocket connection: {}", e);
-                continue;
-            }
-        };
-
-        let actor = Arc::clone(&actor);
-        tokio::spawn(async move {
-            // Handle WebSocket upgrade and connection
-            if let Err(e) = handle_websocket_connection(actor, stream).await {
-                log::error!("WebSocket connection error: {}", e);
-            }
-        });
-    }
-}
-
-/// Handle individual WebSocket connections (unified for both TLS and non-TLS)
-async fn handle_websocket_connection<T, S>(
-    actor: Arc<T>,
-    stream: S,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
-where
-    T: Actor + Send + Sync + 'static,
-    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
-{
-    let ws_stream = accept_async(stream).await?;
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-
-    while let Some(msg) = ws_receiver.next().await {
-        match msg? {
-            Message::Text(text) => {
-                // Parse the JSON message
-                match serde_json::from_str::<serde_json::Value>(&text) {
-                    Ok(json) => {
-                        // TLS behavior: strict validation
-                        if let (Some(method), Some(params)) = (
-                            json.get("method").and_then(|v| v.as_str()),
-                            json.get("params"),
-                        ) {
-                            let params_str = params.to_string();
-                            let response = (*actor).dispatch(method, &params_str).await;
-
-                            if let Err(_e) = ws_sender.send(Message::Text(response)).await {
-                                log::error!("Failed to send WebSocket response: {}", _e);
-                                break;
-                            }
-                        } else {
-                            let error_response = serde_json::json!({
-                                    "error": "Invalid message format. Expected {\"method\": \"method_name\", \"params\": {...}}"
-                                }).to_string();
-
-                            if let Err(e) = ws_sender.send(Message::Text(error_response)).await {
-                                log::error!("Failed to send WebSocket error response: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        let error_response =
-                            serde_json::json!({"error": format!("JSON parse error: {}", e)})
-                                .to_string();
-
-                        if let Err(e) = ws_sender.send(Message::Text(error_response)).await {
-                            log::error!("Failed to send WebSocket error response: {}", e);
-                            break;
-                        }
-                    }
-                }
-            }
-            Message::Close(_) => {
-                break;
-            }
-            _ => {
-                // Ignore other message types (binary, ping, pong)
-            }
-        }
-    }
-
-    Ok(())
-}
-
-/// Start an HTTP server with optional TLS support
-async fn start_http_server_with_tls<T>(actor: Arc<T>, port: u16, tls_config: TlsConfig)
-where
-    T: Actor + Send + Sync + 'static,
-{
-    // HTTPS server with TLS
-    match tls_config.load_server_config().await {
-        Ok(tls_server_config) => {
-            let addr = SocketAddr::from(([0, 0, 0, 0], port));
-            let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-            let tls_acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_server_config));
-
-            log::info!("HTTPS server listening on https://{}", addr);
-
-            loop {
-                let (stream, _) = match listener.accept().await {
-                    Ok(conn) => conn,
-                    Err(e) => {
-                        log::error!("Failed to accept HTTPS connection: {}", e);
-                        continue;
-                    }
-                };
-
-                let actor = Arc::clone(&actor);
-                let tls_acceptor = tls_acceptor.clone();
-
-                tokio::spawn(async move {
-                    match tls_acceptor.accept(stream).await {
-                        Ok(tls_stream) => {
-                            if let Err(e) = handle_https_connection(actor, tls_stream).await {
-                                log::error!("HTTPS connection error: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("TLS handshake error: {}", e);
-                        }
-                    }
-                });
-            }
-        }
-        Err(e) => {
-            log::error!("Failed to load TLS configuration: {}", e);
-        }
-    }
-}
-
-/// Start a WebSocket server with optional TLS support
-async fn start_websocket_server_with_tls<T>(actor: Arc<T>, port: u16, tls_config: TlsConfig)
-where
-    T: Actor + Send + Sync + 'static,
-{
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .unwrap_or_else(|e| {
-            panic!("Failed to bind WSS server address {addr:?}: {}", e);
-        });
-
-    // WSS server with TLS
-    match tls_config.load_server_config().await {
-        Ok(tls_server_config) => {
-            let tls_acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_server_config));
-
-            log::info!("WSS server listening on wss://{}", addr);
-
-            loop {
-                let (stream, _) = match listener.accept().await {
-                    Ok(conn) => conn,
-                    Err(e) => {
-                        log::error!("Failed to accept WSS connection: {}", e);
-                        continue;
-                    }
-                };
-
-                let actor = Arc::clone(&actor);
-                let tls_acceptor = tls_acceptor.clone();
-
-                tokio::spawn(async move {
-                    match tls_acceptor.accept(stream).await {
-                        Ok(tls_stream) => {
-                            if let Err(e) = handle_websocket_connection(actor, tls_stream).await {
-                                log::error!("WSS connection error: {}", e);
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("TLS handshake error: {}", e);
-                        }
-                    }
-                });
-            }
-        }
-        Err(e) => {
-            log::error!("Failed to load TLS configuration: {}", e);
-        }
-    }
-}
-
-/// Handle individual HTTPS connections using hyper-rustls
-async fn handle_https_connection<T>(
-    actor: Arc<T>,
-    stream: tokio_rustls::server::TlsStream<tokio::net::TcpStream>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
-where
-    T: Actor + Send + Sync + 'static,
-{
-    let io = TokioIo::new(stream);
-
-    let service = service_fn(move |req| {
-        let actor = actor.clone();
-        async move { handle_http_request(actor, req).await }
-    });
-
-    // Serve the HTTP request using hyper 1.7 API
-    if let Err(e) = Builder::new(hyper_util::rt::TokioExecutor::new())
-        .serve_connection(io, service)
-        .await
-    {
-        log::error!("HTTPS connection error: {}", e);
-    }
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod test_actor;
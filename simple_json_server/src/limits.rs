@@ -0,0 +1,177 @@
+//! Limits on the shape of call parameters, checked before they are parsed.
+//!
+//! A small request body can still be expensive to parse: a few kilobytes of `[[[[...` nest
+//! deeply enough to exhaust a recursive parser's stack, and a long array of tiny elements
+//! allocates far more than its text.  Every call's parameters are scanned once, without
+//! recursion, and a call exceeding a limit is rejected with `400 Bad Request` before anything
+//! deserializes it:
+//!
+//! ```rust
+//! use simple_json_server::limits::JsonLimits;
+//! use simple_json_server::ServerOptions;
+//!
+//! let limits = JsonLimits::new()
+//!     .max_depth(16)
+//!     .max_string_len(64 * 1024)
+//!     .max_array_len(10_000);
+//! let options = ServerOptions::new(8080).json_limits(limits);
+//! ```
+//!
+//! By default parameters may nest 64 levels deep, and strings and arrays are unlimited.  String
+//! lengths are measured in bytes as sent, escapes included.
+
+/// Limits on nesting depth, string length and array size; see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonLimits {
+    max_depth: usize,
+    max_string_len: Option<usize>,
+    max_array_len: Option<usize>,
+}
+
+impl Default for JsonLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_string_len: None,
+            max_array_len: None,
+        }
+    }
+}
+
+impl JsonLimits {
+    /// The default limits: 64 levels of nesting, with strings and arrays unlimited.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many arrays and objects may be nested inside each other, counting the parameters'
+    /// own object as the first level.  serde_json refuses more than 128 in any case.
+    pub fn max_depth(mut self, levels: usize) -> Self {
+        self.max_depth = levels;
+        self
+    }
+
+    /// The longest string, or object key, allowed anywhere in the parameters, in bytes.
+    pub fn max_string_len(mut self, bytes: usize) -> Self {
+        self.max_string_len = Some(bytes);
+        self
+    }
+
+    /// The most elements allowed in any one array in the parameters.
+    pub fn max_array_len(mut self, elements: usize) -> Self {
+        self.max_array_len = Some(elements);
+        self
+    }
+
+    /// Describe the first limit `json` exceeds, if any.  Invalid JSON is left for the parser
+    /// to report.
+    pub(crate) fn check(&self, json: &str) -> Result<(), String> {
+        let bytes = json.as_bytes();
+        // The elements counted so far in each open array, or `None` for an object
+        let mut open: Vec<Option<usize>> = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i];
+            // Anything but the end of an empty array starts its first element
+            if !byte.is_ascii_whitespace() && byte != b']' {
+                if let Some(Some(elements @ 0)) = open.last_mut() {
+                    *elements = 1;
+                    self.check_array(1)?;
+                }
+            }
+            match byte {
+                b'"' => {
+                    let start = i + 1;
+                    i = start;
+                    while i < bytes.len() && bytes[i] != b'"' {
+                        i += if bytes[i] == b'\\' { 2 } else { 1 };
+                    }
+                    let len = i.min(bytes.len()) - start;
+                    if let Some(max) = self.max_string_len.filter(|max| len > *max) {
+                        return Err(format!(
+                            "A string in the parameters is longer than {} bytes",
+                            max
+                        ));
+                    }
+                }
+                b'[' | b'{' => {
+                    open.push((byte == b'[').then_some(0));
+                    if open.len() > self.max_depth {
+                        return Err(format!(
+                            "Parameters are nested more than {} levels deep",
+                            self.max_depth
+                        ));
+                    }
+                }
+                b']' | b'}' => {
+                    open.pop();
+                }
+                b',' => {
+                    if let Some(Some(elements)) = open.last_mut() {
+                        *elements += 1;
+                        self.check_array(*elements)?;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn check_array(&self, elements: usize) -> Result<(), String> {
+        match self.max_array_len {
+            Some(max) if elements > max => Err(format!(
+                "An array in the parameters has more than {} elements",
+                max
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth() {
+        let limits = JsonLimits::new().max_depth(3);
+        assert!(limits.check(r#"{"a": [{"b": 1}], "c": {}}"#).is_ok());
+        assert_eq!(
+            limits.check(r#"{"a": [[[1]]]}"#).unwrap_err(),
+            "Parameters are nested more than 3 levels deep"
+        );
+        // Brackets inside strings don't count
+        assert!(limits.check(r#"{"a": "[[[[\"[[["}"#).is_ok());
+        assert!(JsonLimits::new().check(&"[".repeat(10_000)).is_err());
+    }
+
+    #[test]
+    fn test_strings() {
+        let limits = JsonLimits::new().max_string_len(5);
+        assert!(limits.check(r#"{"name": "abcde"}"#).is_ok());
+        assert!(limits.check(r#"{"name": "ab\"d"}"#).is_ok());
+        assert_eq!(
+            limits.check(r#"{"name": "abcdef"}"#).unwrap_err(),
+            "A string in the parameters is longer than 5 bytes"
+        );
+        assert!(limits.check(r#"{"longer_key": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_arrays() {
+        let limits = JsonLimits::new().max_array_len(2);
+        assert!(limits
+            .check(r#"{"a": [], "b": [1, [2, 3]], "c": {"x": 1, "y": 2, "z": 3}}"#)
+            .is_ok());
+        assert!(limits.check(r#"{"a": ["x,y,z", "w"]}"#).is_ok());
+        assert_eq!(
+            limits.check(r#"{"a": [1, 2, 3]}"#).unwrap_err(),
+            "An array in the parameters has more than 2 elements"
+        );
+        assert!(JsonLimits::new().max_array_len(0).check("[ ]").is_ok());
+        assert!(JsonLimits::new().max_array_len(0).check("[0]").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+//! JSON log output for log aggregation.
+//!
+//! The server logs through the [`log`] facade, so any logger works.  [`JsonLogger`] is a ready
+//! made one that writes each record as a single line of JSON with a timestamp, the level, the
+//! target, the message, any structured key-value fields and, when logged while serving a call,
+//! the request id, the method and the call's trace and span ids (see [`crate::trace`]):
+//!
+//! ```json
+//! {"timestamp":"2026-01-01T12:00:00.000Z","level":"WARN","target":"simple_json_server::slowlog","message":"Slow call to add took 312ms","request_id":"0b1d6c2e-7","method":"add","trace_id":"4bf92f3577b34da6a3ce929d0e0e4736","span_id":"00f067aa0ba902b7","fields":{"total_ms":312}}
+//! ```
+//!
+//! ```rust,no_run
+//! use log::LevelFilter;
+//!
+//! simple_json_server::logging::init_json(LevelFilter::Info).expect("logger already set");
+//! ```
+
+use crate::RequestContext;
+use log::kv::{Key, Value as KvValue, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Install a [`JsonLogger`] writing to standard error as the global logger.
+pub fn init_json(level: LevelFilter) -> Result<(), SetLoggerError> {
+    JsonLogger::new(level).init()
+}
+
+/// A [`log::Log`] implementation that writes one JSON object per line.
+pub struct JsonLogger {
+    level: LevelFilter,
+    out: Mutex<Box<dyn Write + Send>>,
+    static_fields: Map<String, Value>,
+}
+
+impl JsonLogger {
+    /// Log records at `level` and above to standard error.
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            level,
+            out: Mutex::new(Box::new(std::io::stderr())),
+            static_fields: Map::new(),
+        }
+    }
+
+    /// Write to `out` instead of standard error.
+    pub fn writer(mut self, out: impl Write + Send + 'static) -> Self {
+        self.out = Mutex::new(Box::new(out));
+        self
+    }
+
+    /// Add `fields` to every line, e.g. the pod's metadata from
+    /// [`Kubernetes::labels`](crate::k8s::Kubernetes::labels).
+    pub fn fields(
+        mut self,
+        fields: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        for (name, value) in fields {
+            self.static_fields
+                .insert(name.into(), Value::String(value.into()));
+        }
+        self
+    }
+
+    /// Install this logger as the global logger.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let level = self.level;
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let mut line = Map::new();
+        line.insert("timestamp".into(), rfc3339(SystemTime::now()).into());
+        line.insert("level".into(), record.level().as_str().into());
+        line.insert("target".into(), record.target().into());
+        line.insert("message".into(), record.args().to_string().into());
+        for (name, value) in &self.static_fields {
+            line.insert(name.clone(), value.clone());
+        }
+        if let Some(ctx) = RequestContext::current() {
+            line.insert("request_id".into(), ctx.request_id().into());
+            line.insert("method".into(), ctx.method().into());
+            line.insert("trace_id".into(), ctx.trace().trace_id().into());
+            line.insert("span_id".into(), ctx.trace().span_id().into());
+        }
+        let mut fields = Fields(Map::new());
+        let _ = record.key_values().visit(&mut fields);
+        if !fields.0.is_empty() {
+            line.insert("fields".into(), Value::Object(fields.0));
+        }
+        Value::Object(line).to_string()
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap().flush();
+    }
+}
+
+/// Collects a record's key-value pairs, keeping numbers and booleans as JSON values.
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), log::kv::Error> {
+        let value = if let Some(n) = value.to_u64() {
+            Value::from(n)
+        } else if let Some(n) = value.to_i64() {
+            Value::from(n)
+        } else if let Some(n) = value.to_f64() {
+            Value::from(n)
+        } else if let Some(b) = value.to_bool() {
+            Value::from(b)
+        } else {
+            Value::from(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// `time` as an RFC 3339 timestamp in UTC with millisecond precision.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01 (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Resources;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(rfc3339(leap_day), "2024-02-29T12:34:56.789Z");
+    }
+
+    /// A writer the test can read back.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_include_request_and_fields() {
+        let buffer = Buffer::default();
+        let logger = JsonLogger::new(LevelFilter::Info)
+            .writer(buffer.clone())
+            .fields([("pod", "calc-0")]);
+        let ctx = RequestContext::new("add", "{}", Resources::default());
+        let request_id = ctx.request_id().to_string();
+        let trace_id = ctx.trace().trace_id();
+        ctx.scope(async {
+            logger.log(
+                &Record::builder()
+                    .level(log::Level::Warn)
+                    .target("test")
+                    .args(format_args!("slow"))
+                    .key_values(&[("total_ms", 312)])
+                    .build(),
+            );
+        })
+        .await;
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Debug)
+                .args(format_args!("filtered out"))
+                .build(),
+        );
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["message"], "slow");
+        assert_eq!(line["method"], "add");
+        assert_eq!(line["request_id"], request_id.as_str());
+        assert_eq!(line["trace_id"], trace_id.as_str());
+        assert_eq!(line["fields"]["total_ms"], 312);
+        assert_eq!(line["pod"], "calc-0");
+    }
+}
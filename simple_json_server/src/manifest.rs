@@ -0,0 +1,579 @@
+//! Serving several actors from one declarative manifest (feature `manifest`).
+//!
+//! A process that serves several actors would otherwise need a `main` that builds
+//! [`ServerOptions`] for each of them by hand.  A manifest describes the servers instead, in
+//! TOML or YAML, one table per server named after the actor it serves:
+//!
+//! ```toml
+//! [servers.calculator]
+//! port = 8080
+//! host = "127.0.0.1"
+//!
+//! [servers.inventory]
+//! port = 8443
+//! websocket = true
+//! read_only = false
+//! security = "strict"
+//! tls = { cert = "certs/inventory.pem", key = "certs/inventory.key" }
+//! auth = { admin_token_env = "INVENTORY_ADMIN_TOKEN", client_header = "X-Api-Key" }
+//! ```
+//!
+//! The code only supplies the actors, by name, and [`serve_manifest`] starts a server for each:
+//!
+//! ```rust,no_run
+//! use simple_json_server::manifest::{serve_manifest, Actors};
+//! use simple_json_server::{actor, Actor};
+//!
+//! struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! struct Inventory;
+//!
+//! #[actor]
+//! impl Inventory {
+//!     pub async fn count(&self, sku: String) -> u32 {
+//!         3
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let actors = Actors::new()
+//!         .actor("calculator", Calculator)
+//!         .actor("inventory", Inventory::default());
+//!     let servers = serve_manifest("servers.toml", actors).unwrap();
+//!     tokio::signal::ctrl_c().await.unwrap();
+//!     servers.drain().await;
+//! }
+//! ```
+//!
+//! Each server takes these settings; only `port` is required:
+//!
+//! - `host`, `websocket`, `read_only` and `http3` (which needs `tls` and the `http3` feature), as
+//!   the [`ServerOptions`] methods of the same names.
+//! - `security`: `"default"` or `"strict"`, for [`SecurityPreset::strict`].
+//! - `tls`: the `cert` and `key` PEM files.  Relative paths are relative to the manifest file.
+//! - `auth`: the admin API's token, as `admin_token` or, to keep it out of the file, the name of
+//!   an environment variable holding it as `admin_token_env`; `client_header`, which makes every
+//!   call identify its client in that header; and `daily_requests` and `daily_bytes` limits per
+//!   client.  See [`crate::quota`].
+//!
+//! Unknown settings are errors, as are servers without an actor and actors without a server, and
+//! nothing is started unless every server can be.  Settings the manifest can't express, such as
+//! resources or statistics, are added in code with [`Actors::actor_with`].
+//!
+//! # Supervisors
+//!
+//! Servers can be put under [supervisors](crate::supervisor), which restart them with fresh
+//! actors when they fail.  Each supervisor lists its servers, and may list other supervisors as
+//! its children; those not listed by another are started at the top:
+//!
+//! ```toml
+//! [supervisors.root]
+//! servers = ["calculator"]
+//! supervisors = ["stock"]
+//!
+//! [supervisors.stock]
+//! strategy = "all_for_one"
+//! max_restarts = 5
+//! within_secs = 60
+//! servers = ["inventory", "pricing"]
+//! ```
+//!
+//! `strategy` is `"one_for_one"` (the default) or `"all_for_one"`, and `max_restarts` and
+//! `within_secs` default to those of [`Supervisor::new`].  A supervisor starts its servers, in
+//! the order listed, before its supervisors.  The actors of supervised servers are given with
+//! [`Actors::restartable`], so a fresh one can be made for each restart, and their servers are
+//! reached through [`Servers::supervisor`] rather than [`Servers::get`].  A server or supervisor
+//! can only have one supervisor.
+
+use crate::quota::Quotas;
+use crate::security::SecurityPreset;
+use crate::supervisor::{self, Strategy, Supervisor, SupervisorHandle};
+use crate::{Actor, ServerHandle, ServerOptions, TlsConfig};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Errors returned when loading or serving a manifest.
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The manifest file could not be read.
+    Read(String),
+    /// The manifest is not valid TOML or YAML, or has settings that don't exist.
+    Parse(String),
+    /// The manifest's settings can't be used, or don't match the actors given to serve it.
+    Invalid(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Read(e) => write!(f, "Failed to read manifest: {}", e),
+            ManifestError::Parse(e) => write!(f, "Failed to parse manifest: {}", e),
+            ManifestError::Invalid(e) => write!(f, "Invalid manifest: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// The servers of a process, by the name of the actor each serves; see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    servers: BTreeMap<String, ServerSpec>,
+    #[serde(default)]
+    supervisors: BTreeMap<String, SupervisorSpec>,
+    /// The directory of the manifest file, which relative paths are relative to.
+    #[serde(skip)]
+    base: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerSpec {
+    port: u16,
+    host: Option<IpAddr>,
+    #[serde(default)]
+    websocket: bool,
+    #[serde(default)]
+    http3: bool,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    security: Security,
+    tls: Option<TlsSpec>,
+    #[serde(default)]
+    auth: AuthSpec,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SupervisorSpec {
+    #[serde(default)]
+    strategy: StrategySpec,
+    max_restarts: Option<u32>,
+    within_secs: Option<u64>,
+    #[serde(default)]
+    servers: Vec<String>,
+    #[serde(default)]
+    supervisors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StrategySpec {
+    #[default]
+    OneForOne,
+    AllForOne,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Security {
+    #[default]
+    Default,
+    Strict,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSpec {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthSpec {
+    admin_token: Option<String>,
+    admin_token_env: Option<String>,
+    client_header: Option<String>,
+    daily_requests: Option<u64>,
+    daily_bytes: Option<u64>,
+}
+
+impl Manifest {
+    /// Parse a manifest written in TOML.
+    pub fn from_toml(text: &str) -> Result<Self, ManifestError> {
+        toml::from_str(text).map_err(|e| ManifestError::Parse(e.to_string()))
+    }
+
+    /// Parse a manifest written in YAML.
+    pub fn from_yaml(text: &str) -> Result<Self, ManifestError> {
+        serde_yaml::from_str(text).map_err(|e| ManifestError::Parse(e.to_string()))
+    }
+
+    /// Read a manifest from a `.toml`, `.yaml` or `.yml` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ManifestError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ManifestError::Read(format!("{}: {}", path.display(), e)))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let mut manifest = match extension {
+            "toml" => Self::from_toml(&text)?,
+            "yaml" | "yml" => Self::from_yaml(&text)?,
+            _ => {
+                return Err(ManifestError::Read(format!(
+                    "{}: expected a .toml, .yaml or .yml file",
+                    path.display()
+                )))
+            }
+        };
+        manifest.base = path.parent().map(Path::to_path_buf);
+        Ok(manifest)
+    }
+
+    /// The names of the servers, in order.
+    pub fn servers(&self) -> impl Iterator<Item = &str> {
+        self.servers.keys().map(String::as_str)
+    }
+
+    /// The options the manifest gives the server `name`.
+    pub fn options(&self, name: &str) -> Result<ServerOptions, ManifestError> {
+        let spec = self
+            .servers
+            .get(name)
+            .ok_or_else(|| ManifestError::Invalid(format!("No server named {}", name)))?;
+        let invalid = |message: &str| ManifestError::Invalid(format!("{}: {}", name, message));
+
+        let mut options = ServerOptions::new(spec.port)
+            .websocket(spec.websocket)
+            .read_only(spec.read_only);
+        if let Some(host) = spec.host {
+            options = options.host(host);
+        }
+        if let Security::Strict = spec.security {
+            options = options.security(SecurityPreset::strict());
+        }
+        if let Some(tls) = &spec.tls {
+            let cert = self.resolve(&tls.cert);
+            let key = self.resolve(&tls.key);
+            options = options.tls(TlsConfig::new(
+                cert.to_string_lossy(),
+                key.to_string_lossy(),
+            ));
+        }
+        if spec.http3 {
+            if spec.tls.is_none() {
+                return Err(invalid("http3 needs tls"));
+            }
+            #[cfg(feature = "http3")]
+            {
+                options = options.http3(true);
+            }
+            #[cfg(not(feature = "http3"))]
+            return Err(invalid("http3 needs the `http3` feature"));
+        }
+
+        let auth = &spec.auth;
+        let admin_token =
+            match (&auth.admin_token, &auth.admin_token_env) {
+                (Some(_), Some(_)) => {
+                    return Err(invalid("set admin_token or admin_token_env, not both"));
+                }
+                (Some(token), None) => Some(token.clone()),
+                (None, Some(var)) => Some(std::env::var(var).map_err(|_| {
+                    invalid(&format!("the environment variable {} is not set", var))
+                })?),
+                (None, None) => None,
+            };
+        if let Some(token) = admin_token {
+            options = options.admin_token(token);
+        }
+        if auth.client_header.is_some()
+            || auth.daily_requests.is_some()
+            || auth.daily_bytes.is_some()
+        {
+            let mut quotas = Quotas::new();
+            if let Some(header) = &auth.client_header {
+                quotas = quotas.header(header).require_client(true);
+            }
+            if let Some(requests) = auth.daily_requests {
+                quotas = quotas.daily_requests(requests);
+            }
+            if let Some(bytes) = auth.daily_bytes {
+                quotas = quotas.daily_bytes(bytes);
+            }
+            options = options.quotas(quotas);
+        }
+        Ok(options)
+    }
+
+    /// Start a server for each of `actors`, with the options the manifest gives it, and the
+    /// manifest's supervisors.  Nothing is started if a server has no actor, an actor has no
+    /// server, a server's options can't be built, or the supervisors don't fit together.  Must
+    /// be called within a Tokio runtime.
+    pub fn serve(&self, actors: Actors) -> Result<Servers, ManifestError> {
+        if let Some(name) = actors
+            .actors
+            .keys()
+            .find(|n| !self.servers.contains_key(*n))
+        {
+            return Err(ManifestError::Invalid(format!(
+                "The manifest has no server for the actor {}",
+                name
+            )));
+        }
+        let mut starts = Vec::with_capacity(self.servers.len());
+        let mut actors = actors.actors;
+        for name in self.servers.keys() {
+            let options = self.options(name)?;
+            let start = actors.remove(name).ok_or_else(|| {
+                ManifestError::Invalid(format!("No actor was given for the server {}", name))
+            })?;
+            starts.push((name.clone(), options, start));
+        }
+
+        let mut parents = BTreeMap::new();
+        for (parent, spec) in &self.supervisors {
+            for server in &spec.servers {
+                if !self.servers.contains_key(server) {
+                    return Err(ManifestError::Invalid(format!(
+                        "The supervisor {} has no server {}",
+                        parent, server
+                    )));
+                }
+            }
+            for child in &spec.supervisors {
+                if !self.supervisors.contains_key(child) {
+                    return Err(ManifestError::Invalid(format!(
+                        "The supervisor {} has no supervisor {}",
+                        parent, child
+                    )));
+                }
+            }
+            for child in spec.servers.iter().chain(&spec.supervisors) {
+                if let Some(other) = parents.insert(child.as_str(), parent.as_str()) {
+                    return Err(ManifestError::Invalid(format!(
+                        "{} is supervised by both {} and {}",
+                        child, other, parent
+                    )));
+                }
+            }
+        }
+        let mut unsupervised = BTreeMap::new();
+        let mut supervised = BTreeMap::new();
+        for (name, options, start) in starts {
+            if !parents.contains_key(name.as_str()) {
+                unsupervised.insert(name, (options, start));
+                continue;
+            }
+            let Start::Restartable(start) = start else {
+                return Err(ManifestError::Invalid(format!(
+                    "The server {} is supervised, so its actor must be restartable",
+                    name
+                )));
+            };
+            supervised.insert(name, (options.stop_on_panic(true), start));
+        }
+        // With one supervisor each, the supervisors not under a top-level one are in a cycle
+        let top: Vec<_> = self
+            .supervisors
+            .keys()
+            .filter(|name| !parents.contains_key(name.as_str()))
+            .collect();
+        let mut reached = top.clone();
+        let mut next = 0;
+        while let Some(name) = reached.get(next) {
+            reached.extend(&self.supervisors[*name].supervisors);
+            next += 1;
+        }
+        if reached.len() < self.supervisors.len() {
+            return Err(ManifestError::Invalid(
+                "The supervisors supervise each other in a cycle".to_string(),
+            ));
+        }
+        let supervisors: Vec<_> = top
+            .into_iter()
+            .map(|name| (name.clone(), self.supervisor(name, &supervised)))
+            .collect();
+
+        let handles = unsupervised
+            .into_iter()
+            .map(|(name, (options, start))| (name, start.start(options)))
+            .collect();
+        let supervisors = supervisors
+            .into_iter()
+            .map(|(name, supervisor)| (name, supervisor.start()))
+            .collect();
+        Ok(Servers {
+            handles,
+            supervisors,
+        })
+    }
+
+    /// The supervisor `name` with its children, for a manifest already checked to have them.
+    fn supervisor(
+        &self,
+        name: &str,
+        servers: &BTreeMap<String, (ServerOptions, supervisor::Start)>,
+    ) -> Supervisor {
+        let spec = &self.supervisors[name];
+        let strategy = match spec.strategy {
+            StrategySpec::OneForOne => Strategy::OneForOne,
+            StrategySpec::AllForOne => Strategy::AllForOne,
+        };
+        let mut supervisor = Supervisor::new(strategy).max_restarts(
+            spec.max_restarts.unwrap_or(supervisor::MAX_RESTARTS),
+            spec.within_secs
+                .map_or(supervisor::RESTARTS_WITHIN, Duration::from_secs),
+        );
+        for server in &spec.servers {
+            let (options, start) = &servers[server];
+            supervisor = supervisor.server(server.clone(), options.clone(), Arc::clone(start));
+        }
+        for child in &spec.supervisors {
+            supervisor = supervisor.supervisor(child.clone(), self.supervisor(child, servers));
+        }
+        supervisor
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        match &self.base {
+            Some(base) if path.is_relative() => base.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+enum Start {
+    Once(Box<dyn FnOnce(ServerOptions) -> ServerHandle + Send>),
+    Restartable(Arc<dyn Fn(ServerOptions) -> ServerHandle + Send + Sync>),
+}
+
+impl Start {
+    fn start(self, options: ServerOptions) -> ServerHandle {
+        match self {
+            Start::Once(start) => start(options),
+            Start::Restartable(start) => start(options),
+        }
+    }
+}
+
+/// The actors a manifest's servers serve, by name.
+#[derive(Default)]
+pub struct Actors {
+    actors: BTreeMap<String, Start>,
+}
+
+impl fmt::Debug for Actors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.actors.keys()).finish()
+    }
+}
+
+impl Actors {
+    /// No actors yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `actor` from the manifest's server `name`.
+    pub fn actor<T>(self, name: impl Into<String>, actor: T) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+    {
+        self.actor_with(name, actor, |options| options)
+    }
+
+    /// Serve `actor` from the manifest's server `name`, with the options `configure` makes of
+    /// the manifest's, e.g. to add resources or statistics.
+    pub fn actor_with<T, F>(mut self, name: impl Into<String>, actor: T, configure: F) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+        F: FnOnce(ServerOptions) -> ServerOptions + Send + 'static,
+    {
+        let start = Start::Once(Box::new(move |options| actor.start(configure(options))));
+        self.actors.insert(name.into(), start);
+        self
+    }
+
+    /// Serve actors made by `actor` from the manifest's server `name`, which a supervisor can
+    /// restart with a fresh one.
+    pub fn restartable<T, F>(self, name: impl Into<String>, actor: F) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        self.restartable_with(name, actor, |options| options)
+    }
+
+    /// Serve actors made by `actor` from the manifest's server `name`, with the options
+    /// `configure` makes of the manifest's.
+    pub fn restartable_with<T, F, C>(
+        mut self,
+        name: impl Into<String>,
+        actor: F,
+        configure: C,
+    ) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+        C: Fn(ServerOptions) -> ServerOptions + Send + Sync + 'static,
+    {
+        let start = Start::Restartable(Arc::new(move |options| actor().start(configure(options))));
+        self.actors.insert(name.into(), start);
+        self
+    }
+}
+
+/// The running servers and supervisors of a manifest, by name.
+#[derive(Debug, Clone)]
+pub struct Servers {
+    handles: BTreeMap<String, ServerHandle>,
+    supervisors: BTreeMap<String, SupervisorHandle>,
+}
+
+impl Servers {
+    /// The server `name`, if it has no supervisor.
+    pub fn get(&self, name: &str) -> Option<&ServerHandle> {
+        self.handles.get(name)
+    }
+
+    /// The top-level supervisor `name`; its servers, and those of the supervisors under it, are
+    /// found with [`SupervisorHandle::server`].
+    pub fn supervisor(&self, name: &str) -> Option<&SupervisorHandle> {
+        self.supervisors.get(name)
+    }
+
+    /// The servers without a supervisor and their names, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ServerHandle)> {
+        self.handles
+            .iter()
+            .map(|(name, handle)| (name.as_str(), handle))
+    }
+
+    /// Drain every server at once and shut down the supervisors, resolving once all have
+    /// stopped; see [`ServerHandle::drain`] and [`SupervisorHandle::shut_down`].
+    pub async fn drain(&self) {
+        let servers =
+            futures_util::future::join_all(self.handles.values().map(ServerHandle::drain));
+        let supervisors = futures_util::future::join_all(
+            self.supervisors.values().map(SupervisorHandle::shut_down),
+        );
+        futures_util::future::join(servers, supervisors).await;
+    }
+}
+
+/// Read the manifest at `path` and start a server for each of `actors`; see
+/// [`Manifest::load`] and [`Manifest::serve`].
+pub fn serve_manifest(path: impl AsRef<Path>, actors: Actors) -> Result<Servers, ManifestError> {
+    Manifest::load(path)?.serve(actors)
+}
@@ -0,0 +1,331 @@
+//! Approximate accounting of the memory a server holds, with a ceiling that sheds load.
+//!
+//! Running many actors in one process needs some idea of what each of them holds.  A
+//! [`MemoryBudget`] counts the bytes of:
+//!
+//! - calls admitted to the actor's mailbox and not yet answered, whether waiting or running;
+//! - frames queued to be written to WebSocket connections, per connection;
+//! - whatever the actor reports itself, such as caches and session stores, through named
+//!   [`MemoryGauge`]s.
+//!
+//! These are payload sizes rather than what the allocator sees, so they undercount, but they
+//! move with the load and are cheap to keep.  They can be read from Rust or, with an admin token
+//! set, from `GET /__admin/memory` and as gauges in `GET /__admin/metrics`.
+//!
+//! With a ceiling set, calls are turned away with `503 Service Unavailable` and a `Retry-After`
+//! header while the total is too high, by priority (the `X-Priority` header, as for
+//! [load shedding](crate::shedding)):
+//!
+//! - above 80% of the ceiling, `low` priority calls are shed;
+//! - above the ceiling, `normal` priority calls are shed as well;
+//! - `high` priority calls are never shed.
+//!
+//! The bytes queued for any one connection can be limited with
+//! [`SendQueue::max_bytes`](crate::backpressure::SendQueue::max_bytes).
+//!
+//! ```rust
+//! use simple_json_server::memory::MemoryBudget;
+//! use simple_json_server::ServerOptions;
+//!
+//! let memory = MemoryBudget::new().ceiling(256 * 1024 * 1024);
+//! let options = ServerOptions::new(8080).memory(memory.clone());
+//!
+//! // Reported by the actor as its cache grows and shrinks
+//! let cache = memory.gauge("cache");
+//! cache.add(4096);
+//!
+//! // Later, e.g. from a metrics exporter
+//! println!("holding {} bytes", memory.usage().total);
+//! ```
+
+use crate::shedding::Priority;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Share of the ceiling above which `low` priority calls are shed.
+const LOW_PRIORITY_SHARE: f64 = 0.8;
+
+/// Memory accounting settings, plus the accounts they are applied to.  Clones share the same
+/// accounts, so keep one to read the usage of a running server.
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    ceiling: Option<u64>,
+    retry_after: Duration,
+    accounts: Arc<Accounts>,
+}
+
+#[derive(Debug, Default)]
+struct Accounts {
+    mailbox: AtomicU64,
+    send_queues: AtomicU64,
+    connections: Mutex<HashMap<u64, Arc<AtomicU64>>>,
+    next_connection: AtomicU64,
+    gauges: Mutex<BTreeMap<String, MemoryGauge>>,
+    shed: AtomicU64,
+}
+
+/// A snapshot of a server's memory accounts, in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    /// Everything below, added up.
+    pub total: u64,
+    /// Calls admitted to the mailbox and not yet answered.
+    pub mailbox: u64,
+    /// Frames queued for all WebSocket connections.
+    pub send_queues: u64,
+    /// How many WebSocket connections are open.
+    pub connections: usize,
+    /// Frames queued for the connection with the most.
+    pub largest_connection: u64,
+    /// The gauges reported by the actor, by name.
+    pub gauges: BTreeMap<String, u64>,
+    /// The configured ceiling, if any.
+    pub ceiling: Option<u64>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBudget {
+    /// Account for memory without shedding any calls.
+    pub fn new() -> Self {
+        Self {
+            ceiling: None,
+            retry_after: Duration::from_secs(1),
+            accounts: Arc::default(),
+        }
+    }
+
+    /// Shed calls while the total exceeds `bytes`, or 80% of it for `low` priority calls.
+    pub fn ceiling(mut self, bytes: u64) -> Self {
+        self.ceiling = Some(bytes);
+        self
+    }
+
+    /// How long rejected callers are asked to wait before retrying (default 1 second).
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The gauge named `name`, created at zero the first time it is asked for.  Clones of the
+    /// budget return the same gauge.
+    pub fn gauge(&self, name: &str) -> MemoryGauge {
+        let mut gauges = self.accounts.gauges.lock().unwrap();
+        gauges.entry(name.to_string()).or_default().clone()
+    }
+
+    /// The bytes held now.
+    pub fn usage(&self) -> MemoryUsage {
+        let accounts = &self.accounts;
+        let (connections, largest_connection) = {
+            let connections = accounts.connections.lock().unwrap();
+            let largest = connections
+                .values()
+                .map(|bytes| bytes.load(Ordering::Relaxed))
+                .max();
+            (connections.len(), largest.unwrap_or_default())
+        };
+        let gauges: BTreeMap<String, u64> = accounts
+            .gauges
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, gauge)| (name.clone(), gauge.get()))
+            .collect();
+        let mailbox = accounts.mailbox.load(Ordering::Relaxed);
+        let send_queues = accounts.send_queues.load(Ordering::Relaxed);
+        MemoryUsage {
+            total: mailbox + send_queues + gauges.values().sum::<u64>(),
+            mailbox,
+            send_queues,
+            connections,
+            largest_connection,
+            gauges,
+            ceiling: self.ceiling,
+        }
+    }
+
+    /// How many calls have been shed for lack of memory.
+    pub fn shed_count(&self) -> u64 {
+        self.accounts.shed.load(Ordering::Relaxed)
+    }
+
+    /// Count `bytes` against the mailbox until the returned charge is dropped.
+    pub(crate) fn charge_mailbox(&self, bytes: usize) -> Charge {
+        self.accounts
+            .mailbox
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        Charge {
+            accounts: Arc::clone(&self.accounts),
+            bytes: bytes as u64,
+        }
+    }
+
+    /// Open an account for a WebSocket connection's send queue, closed when dropped.
+    pub(crate) fn connection(&self) -> ConnectionAccount {
+        let accounts = Arc::clone(&self.accounts);
+        let id = accounts.next_connection.fetch_add(1, Ordering::Relaxed);
+        let bytes = Arc::new(AtomicU64::new(0));
+        accounts
+            .connections
+            .lock()
+            .unwrap()
+            .insert(id, Arc::clone(&bytes));
+        ConnectionAccount {
+            accounts,
+            id,
+            bytes,
+        }
+    }
+
+    /// Whether a call of `priority` should be turned away now.
+    pub(crate) fn should_shed(&self, priority: Priority) -> bool {
+        let Some(ceiling) = self.ceiling else {
+            return false;
+        };
+        let threshold = match priority {
+            Priority::High => return false,
+            Priority::Normal => ceiling,
+            Priority::Low => (ceiling as f64 * LOW_PRIORITY_SHARE) as u64,
+        };
+        let shed = self.usage().total > threshold;
+        if shed {
+            self.accounts.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        shed
+    }
+
+    pub(crate) fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+/// Bytes the actor holds in something the server can't see, such as a cache.  Clones share
+/// the same count.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryGauge(Arc<AtomicU64>);
+
+impl MemoryGauge {
+    /// Set the bytes held.
+    pub fn set(&self, bytes: u64) {
+        self.0.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Add `bytes` to those held.
+    pub fn add(&self, bytes: u64) {
+        self.0.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Take `bytes` from those held, stopping at zero.
+    pub fn sub(&self, bytes: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |held| {
+                Some(held.saturating_sub(bytes))
+            });
+    }
+
+    /// The bytes held.
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Bytes counted against the mailbox, until dropped.
+pub(crate) struct Charge {
+    accounts: Arc<Accounts>,
+    bytes: u64,
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        self.accounts
+            .mailbox
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// The bytes queued for one WebSocket connection.
+pub(crate) struct ConnectionAccount {
+    accounts: Arc<Accounts>,
+    id: u64,
+    bytes: Arc<AtomicU64>,
+}
+
+impl ConnectionAccount {
+    /// Record that the connection's queue now holds `bytes`.
+    pub(crate) fn set(&self, bytes: usize) {
+        let bytes = bytes as u64;
+        let before = self.bytes.swap(bytes, Ordering::Relaxed);
+        let send_queues = &self.accounts.send_queues;
+        if bytes >= before {
+            send_queues.fetch_add(bytes - before, Ordering::Relaxed);
+        } else {
+            send_queues.fetch_sub(before - bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for ConnectionAccount {
+    fn drop(&mut self) {
+        self.set(0);
+        self.accounts.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts_add_up() {
+        let memory = MemoryBudget::new();
+        let call = memory.charge_mailbox(100);
+        let first = memory.connection();
+        let second = memory.connection();
+        first.set(300);
+        second.set(50);
+        memory.gauge("cache").add(1000);
+        memory.clone().gauge("cache").sub(200);
+
+        let usage = memory.usage();
+        assert_eq!(usage.mailbox, 100);
+        assert_eq!(usage.send_queues, 350);
+        assert_eq!(usage.connections, 2);
+        assert_eq!(usage.largest_connection, 300);
+        assert_eq!(usage.gauges["cache"], 800);
+        assert_eq!(usage.total, 1250);
+
+        drop(call);
+        drop(first);
+        second.set(10);
+        let usage = memory.usage();
+        assert_eq!(usage.total, 810);
+        assert_eq!(usage.connections, 1);
+    }
+
+    #[test]
+    fn test_sheds_by_priority() {
+        let memory = MemoryBudget::new().ceiling(1000);
+        let cache = memory.gauge("cache");
+        cache.set(900);
+        assert!(memory.should_shed(Priority::Low));
+        assert!(!memory.should_shed(Priority::Normal));
+
+        cache.set(1100);
+        assert!(memory.should_shed(Priority::Normal));
+        assert!(!memory.should_shed(Priority::High));
+        assert_eq!(memory.shed_count(), 2);
+
+        cache.sub(u64::MAX);
+        assert!(!memory.should_shed(Priority::Low));
+        assert!(!MemoryBudget::new().should_shed(Priority::Low));
+    }
+}
@@ -0,0 +1,68 @@
+//! Typed messages, for calling an actor's methods as values rather than JSON.
+//!
+//! JSON dispatch suits the server, but code that builds calls programmatically, such as a
+//! gateway choosing which call to forward or a test table of calls and expected answers, is
+//! better served by values the compiler checks.  `#[actor(messages)]` makes the message the
+//! macro generates for each method public, as a `<Method>Message` struct with a public field per
+//! parameter, and implements [`Message`] for it:
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor};
+//!
+//! pub struct Calculator;
+//!
+//! #[actor(messages)]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let sum = Calculator.handle(AddMessage { a: 1, b: 2 }).await;
+//! assert_eq!(sum, 3);
+//! # }
+//! ```
+//!
+//! It also generates a `<Type>Request` enum with a variant per method, deserializing from the
+//! envelope WebSocket clients send.  Variants hold the parameters the JSON carries, so those
+//! bound to headers or cookies are left out, and matching on the enum stops compiling when a
+//! method is added, removed or changes its parameters:
+//!
+//! ```rust
+//! # use simple_json_server::{actor, Actor};
+//! # pub struct Calculator;
+//! # #[actor(messages)]
+//! # impl Calculator {
+//! #     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//! #         a + b
+//! #     }
+//! # }
+//! # fn main() {
+//! let text = r#"{"id": 1, "method": "add", "params": {"a": 1, "b": 2}}"#;
+//! match serde_json::from_str(text).unwrap() {
+//!     CalculatorRequest::Add { a, b } => assert_eq!(a + b, 3),
+//! }
+//! # }
+//! ```
+//!
+//! Handling a message calls the method directly, as [`Actor::dispatch`] would with the same
+//! parameters but without going through JSON.  Parameters bound to headers or cookies are
+//! fields like any other.  The messages also derive `Deserialize`, so JSON can be parsed into
+//! them.  Each struct is named after its method alone, so two actors declared with
+//! `#[actor(messages)]` in one module must not share method names.
+
+#[cfg(doc)]
+use crate::Actor;
+use std::future::Future;
+
+/// A call of one of `A`'s methods, generated by `#[actor(messages)]`; see the
+/// [module documentation](self).
+pub trait Message<A: ?Sized>: Sized + Send {
+    /// What the method returns.
+    type Response;
+
+    /// Call the method on `actor` with the message's parameters.
+    fn handle(self, actor: &A) -> impl Future<Output = Self::Response> + Send;
+}
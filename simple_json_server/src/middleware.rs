@@ -0,0 +1,14 @@
+//! Hooks the server runs around every call, used by integrations that need per-request setup
+//! and teardown.
+
+use crate::RequestContext;
+use futures_util::future::BoxFuture;
+
+/// Runs before and after each call dispatched to the actor.
+pub(crate) trait Middleware: Send + Sync {
+    /// Called before the method runs, e.g. to attach per-request extensions to `ctx`.
+    fn before<'a>(&'a self, ctx: &'a RequestContext) -> BoxFuture<'a, ()>;
+
+    /// Called once the method has returned; `ctx.failed()` tells whether it succeeded.
+    fn after<'a>(&'a self, ctx: &'a RequestContext) -> BoxFuture<'a, ()>;
+}
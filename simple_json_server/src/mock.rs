@@ -0,0 +1,219 @@
+//! Mock actors, for testing code that calls actors without the real implementation.
+//!
+//! `#[actor(mock)]` generates, next to the actor, a `Mock<Type>` that serves the same methods
+//! and parameters but answers each call from programmed expectations.  `expect_<method>()`
+//! starts an expectation; `returning` gives the closure that answers the calls, taking the
+//! parameters as a tuple.  Serve the mock like any actor and point the code under test at it.
+//!
+//! ```rust,no_run
+//! use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+//! use serde_json::json;
+//!
+//! pub struct Greeter;
+//!
+//! #[actor(mock)]
+//! impl Greeter {
+//!     pub async fn greet(&self, name: String) -> String {
+//!         format!("Hello, {}!", name)
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), simple_json_server::ClientError> {
+//! let mock = MockGreeter::new();
+//! mock.expect_greet()
+//!     .times(1)
+//!     .returning(|(name,)| format!("Hi {}", name));
+//! mock.clone().create_with(ServerOptions::new(8080));
+//!
+//! let greeting: String = ActorRef::new("http://127.0.0.1:8080")
+//!     .call("greet", &json!({"name": "Ada"}))
+//!     .await?;
+//! assert_eq!(greeting, "Hi Ada");
+//! mock.verify();
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! Expectations are matched in the order they were set up, skipping those that have been called
+//! their number of `times`.  A call no expectation matches is answered with an error message
+//! and makes [`Expectations::verify`] fail.
+
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+type Respond<A, R> = Arc<dyn Fn(A) -> R + Send + Sync>;
+
+/// The expectations of a mock actor.  Clones share them, so a mock can be served while the test
+/// keeps a clone to program and verify it.
+#[derive(Clone, Default)]
+pub struct Expectations {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    expected: Vec<Expected>,
+    unexpected: Vec<&'static str>,
+}
+
+struct Expected {
+    method: &'static str,
+    times: Option<usize>,
+    calls: usize,
+    /// A `Respond<A, R>` for the method's parameters and return type.
+    respond: Box<dyn Any + Send>,
+}
+
+impl fmt::Debug for Expectations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.lock().unwrap();
+        let expected: Vec<_> = state.expected.iter().map(|e| e.method).collect();
+        f.debug_struct("Expectations")
+            .field("expected", &expected)
+            .field("unexpected", &state.unexpected)
+            .finish()
+    }
+}
+
+impl Expectations {
+    /// Start an expectation of calls to `method`.
+    pub fn expect<A, R>(&self, method: &'static str) -> Expectation<'_, A, R> {
+        Expectation {
+            expectations: self,
+            method,
+            times: None,
+            types: PhantomData,
+        }
+    }
+
+    /// Answer a call to `method` from the first matching expectation, or `None` if there is
+    /// none.
+    pub fn call<A: 'static, R: 'static>(&self, method: &'static str, args: A) -> Option<R> {
+        let respond = {
+            let mut state = self.inner.lock().unwrap();
+            let expected = state
+                .expected
+                .iter_mut()
+                .find(|e| e.method == method && e.times.is_none_or(|times| e.calls < times));
+            match expected {
+                Some(expected) => {
+                    expected.calls += 1;
+                    expected.respond.downcast_ref::<Respond<A, R>>().cloned()
+                }
+                None => {
+                    state.unexpected.push(method);
+                    None
+                }
+            }
+        };
+        respond.map(|respond| respond(args))
+    }
+
+    /// How many calls to `method` the expectations have answered.
+    pub fn calls(&self, method: &str) -> usize {
+        let state = self.inner.lock().unwrap();
+        state
+            .expected
+            .iter()
+            .filter(|e| e.method == method)
+            .map(|e| e.calls)
+            .sum()
+    }
+
+    /// Panic if an expectation was called a different number of `times` than it expects, or a
+    /// call matched no expectation.
+    pub fn verify(&self) {
+        let state = self.inner.lock().unwrap();
+        let mut problems: Vec<String> = state
+            .unexpected
+            .iter()
+            .map(|method| format!("unexpected call to `{}`", method))
+            .collect();
+        for expected in &state.expected {
+            if let Some(times) = expected.times.filter(|times| *times != expected.calls) {
+                problems.push(format!(
+                    "`{}` expected {} calls, got {}",
+                    expected.method, times, expected.calls
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            panic!("Mock expectations not met: {}", problems.join("; "));
+        }
+    }
+}
+
+/// An expectation being set up; it takes effect once [`Expectation::returning`] is called.
+/// `A` is the tuple of the method's parameters and `R` its return type.
+#[must_use = "an expectation takes effect once `returning` is called"]
+pub struct Expectation<'a, A, R> {
+    expectations: &'a Expectations,
+    method: &'static str,
+    times: Option<usize>,
+    types: PhantomData<fn(A) -> R>,
+}
+
+impl<A: 'static, R: 'static> Expectation<'_, A, R> {
+    /// Expect exactly `times` calls; later calls fall through to the next expectation.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// Answer the calls with `respond`, which is given the parameters as a tuple.
+    pub fn returning(self, respond: impl Fn(A) -> R + Send + Sync + 'static) {
+        let respond: Respond<A, R> = Arc::new(respond);
+        self.expectations
+            .inner
+            .lock()
+            .unwrap()
+            .expected
+            .push(Expected {
+                method: self.method,
+                times: self.times,
+                calls: 0,
+                respond: Box::new(respond),
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expectations_in_order() {
+        let expectations = Expectations::default();
+        expectations
+            .expect::<(i32, i32), i32>("add")
+            .times(1)
+            .returning(|_| 0);
+        expectations
+            .expect::<(i32, i32), i32>("add")
+            .returning(|(a, b)| a + b);
+
+        assert_eq!(expectations.call("add", (1, 2)), Some(0));
+        assert_eq!(expectations.call("add", (1, 2)), Some(3));
+        assert_eq!(expectations.calls("add"), 2);
+        expectations.verify();
+
+        assert_eq!(expectations.call::<(), i32>("reset", ()), None);
+        let result = std::panic::catch_unwind(|| expectations.verify());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "`add` expected 2 calls, got 1")]
+    fn test_verify_counts_calls() {
+        let expectations = Expectations::default();
+        expectations
+            .expect::<(i32, i32), i32>("add")
+            .times(2)
+            .returning(|(a, b)| a + b);
+        expectations.call::<(i32, i32), i32>("add", (1, 2));
+        expectations.verify();
+    }
+}
@@ -0,0 +1,195 @@
+//! Lifecycle events, for monitors and tests to watch servers and supervisors.
+//!
+//! A server given a [`Monitor`] with [`ServerOptions::monitor`](crate::ServerOptions::monitor)
+//! reports, under the name it was given there, when it starts listening, starts draining and
+//! stops, and when it turns calls away because it is saturated.  A
+//! [`Supervisor`](crate::supervisor::Supervisor) given one with
+//! [`Supervisor::monitor`](crate::supervisor::Supervisor::monitor) reports the children it
+//! restarts, and passes the monitor on to those that have none, naming them by their path in
+//! the tree (e.g. `stock/inventory`).
+//!
+//! ```rust,no_run
+//! use simple_json_server::monitor::{LifecycleEvent, Monitor};
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let monitor = Monitor::new();
+//!     let mut events = monitor.subscribe();
+//!     let server = Calculator.start(ServerOptions::new(8080).monitor(monitor, "calculator"));
+//!     while let Some(event) = events.next().await {
+//!         if let LifecycleEvent::Stopped { name, failure } = event {
+//!             println!("{} stopped: {:?}", name, failure);
+//!             break;
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Every event is also logged.  With [`Monitor::events`] they are published on an
+//! [`EventBus`]'s [`TOPIC`] as well, as JSON such as
+//! `{"event": "restarted", "name": "stock/inventory", "reason": "Method count panicked"}`, so
+//! WebSocket clients and other actors can subscribe to them.  A saturated server reports at
+//! most one `mailbox_saturated` event a second.
+
+use crate::events::EventBus;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+use tokio::sync::broadcast;
+
+/// The [`EventBus`] topic lifecycle events are published on.
+pub const TOPIC: &str = "lifecycle";
+
+/// How many events a subscriber may fall behind before it starts missing them.
+const CAPACITY: usize = 256;
+
+/// Something that happened to a server or a supervisor's child, named as in the
+/// [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LifecycleEvent {
+    /// The server is listening on `addrs`.
+    Started {
+        name: String,
+        addrs: Vec<SocketAddr>,
+    },
+    /// A supervisor restarted the child `name`, a server or a supervisor, after it failed.
+    Restarted { name: String, reason: String },
+    /// The server stopped accepting connections, because it is being drained or has failed.
+    Draining { name: String },
+    /// The server stopped, and dropped its actor; `failure` says why if it failed.
+    Stopped {
+        name: String,
+        failure: Option<String>,
+    },
+    /// The server turned a call to `method` away, with `503 Service Unavailable`, because it is
+    /// overloaded, low on memory or out of blocking threads.
+    MailboxSaturated {
+        name: String,
+        method: String,
+        reason: String,
+    },
+}
+
+impl LifecycleEvent {
+    /// The name of the server or child the event is about.
+    pub fn name(&self) -> &str {
+        match self {
+            LifecycleEvent::Started { name, .. }
+            | LifecycleEvent::Restarted { name, .. }
+            | LifecycleEvent::Draining { name }
+            | LifecycleEvent::Stopped { name, .. }
+            | LifecycleEvent::MailboxSaturated { name, .. } => name,
+        }
+    }
+}
+
+impl fmt::Display for LifecycleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleEvent::Started { name, addrs } => write!(f, "{} started on {:?}", name, addrs),
+            LifecycleEvent::Restarted { name, reason } => {
+                write!(f, "{} restarted after: {}", name, reason)
+            }
+            LifecycleEvent::Draining { name } => write!(f, "{} is draining", name),
+            LifecycleEvent::Stopped {
+                name,
+                failure: None,
+            } => write!(f, "{} stopped", name),
+            LifecycleEvent::Stopped {
+                name,
+                failure: Some(failure),
+            } => write!(f, "{} stopped after: {}", name, failure),
+            LifecycleEvent::MailboxSaturated {
+                name,
+                method,
+                reason,
+            } => write!(f, "{} is {}; {} was not run", name, reason, method),
+        }
+    }
+}
+
+/// Where servers and supervisors report their [`LifecycleEvent`]s.  Clones report to the same
+/// subscribers.
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    sender: broadcast::Sender<LifecycleEvent>,
+    events: Option<EventBus>,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+            events: None,
+        }
+    }
+}
+
+impl Monitor {
+    /// A monitor nobody subscribes to yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also publish every event on `events`' [`TOPIC`].
+    pub fn events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Receive every event reported from now on.
+    pub fn subscribe(&self) -> LifecycleEvents {
+        LifecycleEvents {
+            receiver: self.sender.subscribe(),
+        }
+    }
+
+    pub(crate) fn report(&self, event: LifecycleEvent) {
+        match &event {
+            LifecycleEvent::Started { .. } | LifecycleEvent::Stopped { failure: None, .. } => {
+                log::info!("{}", event)
+            }
+            _ => log::warn!("{}", event),
+        }
+        if let Some(events) = &self.events {
+            if let Err(e) = events.publish(TOPIC, &event) {
+                log::warn!("Failed to publish a lifecycle event: {}", e);
+            }
+        }
+        // No receivers is not an error: nobody is watching right now
+        let _ = self.sender.send(event);
+    }
+}
+
+/// The events reported to a [`Monitor`] since [`Monitor::subscribe`].
+#[derive(Debug)]
+pub struct LifecycleEvents {
+    receiver: broadcast::Receiver<LifecycleEvent>,
+}
+
+impl LifecycleEvents {
+    /// The next event, waiting for one if need be; `None` once every clone of the monitor has
+    /// been dropped.  Events missed by falling too far behind are skipped.
+    pub async fn next(&mut self) -> Option<LifecycleEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("A lifecycle subscriber missed {} events", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! How numbers in call parameters are handled.
+//!
+//! Parameters are deserialized straight from the request text, so integer parameters (`i64`,
+//! `u64`, `i128`, ...) are exact over their whole range, including `u64` values above 2^53 that
+//! JavaScript clients can't represent, and `rust_decimal::Decimal` parameters (feature
+//! `decimal`) keep every digit.  An integer parameter given a fraction such as `1.5` is
+//! rejected, never truncated.
+//!
+//! `f64` parameters are another matter: by default a number with more digits than an `f64`
+//! holds is silently rounded.  With [`Numbers::Strict`] a call containing a number that can't
+//! be represented exactly, as an `i64`, a `u64` or an `f64`, is rejected with
+//! `400 Bad Request` instead:
+//!
+//! ```rust
+//! use simple_json_server::numbers::Numbers;
+//! use simple_json_server::ServerOptions;
+//!
+//! let options = ServerOptions::new(8080).numbers(Numbers::Strict);
+//! ```
+//!
+//! Strict mode checks the numbers' text, not the parameters' types, so a large integer given
+//! to an `f64` parameter still rounds.
+//!
+//! Code that goes through `serde_json::Value`, such as versioning migrations, turns numbers
+//! into `f64`s, `i64`s or `u64`s along the way.  The `arbitrary_precision` feature enables
+//! serde_json's feature of that name so values keep each number's exact text end to end.
+
+/// The number handling policy; see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Numbers {
+    /// Round numbers to the nearest value of the parameter's type where needed.
+    #[default]
+    Lenient,
+    /// Reject calls containing numbers that can't be represented exactly.
+    Strict,
+}
+
+impl Numbers {
+    /// The first number in `json` this policy rejects, if any.
+    pub(crate) fn rejected<'a>(&self, json: &'a str) -> Option<&'a str> {
+        match self {
+            Numbers::Lenient => None,
+            Numbers::Strict => numbers(json).find(|number| !is_exact(number)),
+        }
+    }
+}
+
+/// The number literals in `json`, skipping those inside strings.
+fn numbers(json: &str) -> impl Iterator<Item = &str> {
+    let bytes = json.as_bytes();
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            match bytes[i] {
+                b'"' => {
+                    i += 1;
+                    while i < bytes.len() && bytes[i] != b'"' {
+                        i += if bytes[i] == b'\\' { 2 } else { 1 };
+                    }
+                    i += 1;
+                }
+                b'-' | b'0'..=b'9' => {
+                    let start = i;
+                    while i < bytes.len()
+                        && matches!(bytes[i], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                    {
+                        i += 1;
+                    }
+                    return Some(&json[start..i]);
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    })
+}
+
+/// Whether `number` is exactly an `i64`, a `u64` or an `f64`.
+fn is_exact(number: &str) -> bool {
+    if !number.contains(['.', 'e', 'E']) {
+        return number.parse::<i64>().is_ok() || number.parse::<u64>().is_ok();
+    }
+    match number.parse::<f64>() {
+        Ok(float) if float.is_finite() => decimal(number) == decimal(&format!("{:e}", float)),
+        _ => false,
+    }
+}
+
+/// A decimal number as its sign, significant digits and power of ten, e.g. `-1.50e3` as
+/// `(true, "15", 2)`.  Zero has no digits.
+fn decimal(number: &str) -> (bool, String, i64) {
+    let (negative, number) = match number.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, number),
+    };
+    let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().unwrap_or(0)),
+        None => (number, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", integer, fraction);
+    let significant = digits.trim_start_matches('0').trim_end_matches('0');
+    if significant.is_empty() {
+        return (false, String::new(), 0);
+    }
+    let trailing_zeros = digits.len() - digits.trim_end_matches('0').len();
+    let power = exponent - fraction.len() as i64 + trailing_zeros as i64;
+    (negative, significant.to_string(), power)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_numbers_outside_strings() {
+        let json = r#"{"a": -1.5e3, "b": "2 \" 3", "c": [4, 0.25]}"#;
+        assert_eq!(numbers(json).collect::<Vec<_>>(), ["-1.5e3", "4", "0.25"]);
+    }
+
+    #[test]
+    fn test_exact_numbers() {
+        for exact in [
+            "0",
+            "-0.0",
+            "1.50",
+            "0.1",
+            "1e3",
+            "18446744073709551615",
+            "-9223372036854775808",
+        ] {
+            assert!(is_exact(exact), "{}", exact);
+        }
+        for inexact in [
+            "18446744073709551616",
+            "0.10000000000000000001",
+            "12345678901234567890.5",
+            "1e400",
+        ] {
+            assert!(!is_exact(inexact), "{}", inexact);
+        }
+    }
+
+    #[test]
+    fn test_policies() {
+        let json = r#"{"price": 0.30000000000000000001, "note": "0.30000000000000000001"}"#;
+        assert_eq!(Numbers::Lenient.rejected(json), None);
+        assert_eq!(
+            Numbers::Strict.rejected(json),
+            Some("0.30000000000000000001")
+        );
+        assert_eq!(
+            Numbers::Strict.rejected(r#"{"id": 9007199254740993}"#),
+            None
+        );
+    }
+}
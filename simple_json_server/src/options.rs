@@ -0,0 +1,498 @@
+use crate::backpressure::SendQueue;
+use crate::blocking::BlockingPool;
+use crate::buffers::BufferPool;
+use crate::cluster::Cluster;
+use crate::content_type::ContentTypes;
+use crate::context::Resources;
+use crate::encryption::Encryption;
+use crate::events::EventBus;
+use crate::fallback::{Fallback, FallbackResponse};
+use crate::flags::FeatureFlags;
+use crate::ingest::DurableIngest;
+use crate::journal::Journal;
+use crate::k8s::Kubernetes;
+use crate::limits::JsonLimits;
+use crate::memory::MemoryBudget;
+use crate::middleware::Middleware;
+use crate::monitor::Monitor;
+use crate::numbers::Numbers;
+use crate::quota::Quotas;
+use crate::registry::RegistryClient;
+use crate::replication::Replication;
+use crate::sampling::PayloadSampler;
+use crate::secrets::Secret;
+use crate::security::SecurityPreset;
+use crate::shedding::LoadShedding;
+use crate::signing::Signing;
+use crate::slowlog::SlowLog;
+use crate::snapshot::SnapshotStore;
+use crate::socket::SocketOptions;
+use crate::startup::StartupEvent;
+use crate::stats::Stats;
+use crate::versioning::Versioning;
+use crate::TlsConfig;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Options controlling how an actor is served.  This is the most general way to start a
+/// server; the `create_*` methods on [`crate::Actor`] are shorthands for common combinations.
+///
+/// # Example
+///
+/// ```rust
+/// use simple_json_server::{ServerOptions, TlsConfig};
+///
+/// let options = ServerOptions::new(8443)
+///     .websocket(true)
+///     .tls(TlsConfig::new("cert.pem", "key.pem"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    pub(crate) host: Option<IpAddr>,
+    pub(crate) ipv6_only: bool,
+    pub(crate) reuse_port: bool,
+    pub(crate) socket: SocketOptions,
+    pub(crate) port: u16,
+    pub(crate) websocket: bool,
+    pub(crate) tls: Option<TlsConfig>,
+    #[cfg(feature = "http3")]
+    pub(crate) http3: bool,
+    pub(crate) cluster: Option<Cluster>,
+    pub(crate) registry: Option<RegistryBinding>,
+    pub(crate) journal: Option<Journal>,
+    pub(crate) replication: Option<Replication>,
+    pub(crate) read_only: bool,
+    pub(crate) durable_ingest: Option<DurableIngest>,
+    /// Shared so the token can rotate while the server runs.
+    pub(crate) admin_token: Option<Arc<RwLock<String>>>,
+    pub(crate) admin_token_secret: Option<Secret>,
+    pub(crate) snapshot_store: Option<Shared<dyn SnapshotStore>>,
+    pub(crate) restore_on_start: bool,
+    pub(crate) resources: Resources,
+    pub(crate) middleware: Vec<Shared<dyn Middleware>>,
+    pub(crate) load_shedding: Option<LoadShedding>,
+    pub(crate) memory: Option<MemoryBudget>,
+    pub(crate) stats: Stats,
+    pub(crate) slow_log: Option<SlowLog>,
+    pub(crate) sampler: Option<PayloadSampler>,
+    pub(crate) events: Option<EventBus>,
+    pub(crate) send_queue: SendQueue,
+    pub(crate) buffer_pool: Option<BufferPool>,
+    pub(crate) blocking_pool: Option<BlockingPool>,
+    pub(crate) feature_flags: Option<FeatureFlags>,
+    pub(crate) quotas: Option<Quotas>,
+    pub(crate) signing: Option<Signing>,
+    pub(crate) field_encryption: Option<Encryption>,
+    pub(crate) security: SecurityPreset,
+    pub(crate) content_types: Option<ContentTypes>,
+    pub(crate) versioning: Option<Versioning>,
+    pub(crate) numbers: Numbers,
+    pub(crate) json_limits: JsonLimits,
+    pub(crate) debug: bool,
+    pub(crate) fallback: Option<Shared<dyn Fallback>>,
+    pub(crate) method_not_allowed: Option<FallbackResponse>,
+    pub(crate) kubernetes: Option<Kubernetes>,
+    pub(crate) on_startup: Option<StartupHook>,
+    pub(crate) warm_up: Vec<(String, String)>,
+    pub(crate) max_batch: Option<usize>,
+    pub(crate) stop_on_panic: bool,
+    pub(crate) monitor: Option<(Monitor, String)>,
+}
+
+type StartupHook = Shared<dyn Fn(&StartupEvent) + Send + Sync>;
+
+/// A shared, user-supplied implementation of one of the crate's extension traits.
+pub(crate) struct Shared<T: ?Sized>(pub(crate) Arc<T>);
+
+impl<T: ?Sized> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("..")
+    }
+}
+
+/// Where and under which name a server announces itself to a registry.
+#[derive(Debug, Clone)]
+pub(crate) struct RegistryBinding {
+    pub(crate) client: RegistryClient,
+    pub(crate) name: String,
+    pub(crate) address: String,
+    pub(crate) ttl: Duration,
+}
+
+impl ServerOptions {
+    /// Create options for a plain HTTP server listening on `port`.
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            ..Self::default()
+        }
+    }
+
+    /// The address to listen on (default `0.0.0.0`, every IPv4 interface).  The IPv6 unspecified
+    /// address `::` listens on every interface for both IPv6 and IPv4 (dual-stack), falling back
+    /// to IPv4 only where IPv6 is unavailable; see [`ServerOptions::ipv6_only`].
+    pub fn host(mut self, host: impl Into<IpAddr>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Accept only IPv6 connections when listening on an IPv6 address, instead of dual-stack.
+    pub fn ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = ipv6_only;
+        self
+    }
+
+    /// Set `SO_REUSEPORT` on the listening socket (Unix only), so another process can listen on
+    /// the same port.  This lets a new version of a server start while the old one drains; see
+    /// [`crate::handle`].
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    /// TCP settings such as `TCP_NODELAY`, keepalive and the accept backlog; see
+    /// [`crate::socket`].
+    pub fn socket(mut self, socket: SocketOptions) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Serve the WebSocket protocol instead of HTTP.
+    pub fn websocket(mut self, websocket: bool) -> Self {
+        self.websocket = websocket;
+        self
+    }
+
+    /// Encrypt connections with TLS (HTTPS or WSS).
+    pub fn tls(mut self, tls_config: TlsConfig) -> Self {
+        self.tls = Some(tls_config);
+        self
+    }
+
+    /// Also serve HTTP/3 (QUIC) on the same port over UDP, with the same TLS configuration
+    /// (experimental).  HTTPS responses carry an `Alt-Svc` header advertising it, so capable
+    /// clients switch over.  Needs [`ServerOptions::tls`] and is ignored for WebSocket servers.
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, http3: bool) -> Self {
+        self.http3 = http3;
+        self
+    }
+
+    /// Join a cluster of processes serving the same actor.  See [`crate::cluster`].
+    pub fn cluster(mut self, cluster: Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Announce this server to a registry as `name`, reachable at `address`, once it starts.
+    /// The registration is kept alive with heartbeats for as long as the server runs; see
+    /// [`crate::registry`].
+    pub fn register(
+        mut self,
+        client: RegistryClient,
+        name: impl Into<String>,
+        address: impl Into<String>,
+    ) -> Self {
+        self.registry = Some(RegistryBinding {
+            client,
+            name: name.into(),
+            address: address.into(),
+            ttl: Duration::from_secs(30),
+        });
+        self
+    }
+
+    /// How long the registry keeps this server's registration without a heartbeat (default
+    /// 30 seconds).  Has no effect unless [`ServerOptions::register`] is also used.
+    pub fn registry_ttl(mut self, ttl: Duration) -> Self {
+        if let Some(binding) = &mut self.registry {
+            binding.ttl = ttl;
+        }
+        self
+    }
+
+    /// Record every call to a `#[write]` method in `journal`.  See [`crate::journal`].
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Take part in primary/replica replication.  A primary journals its writes (creating a
+    /// journal if none was given); a replica is read-only.  See [`crate::replication`].
+    pub fn replication(mut self, replication: Replication) -> Self {
+        self.replication = Some(replication);
+        self
+    }
+
+    /// Answer HTTP calls to `#[write]` methods with `202 Accepted` once they are logged to
+    /// `ingest`, and run them afterwards, in order.  See [`crate::ingest`].
+    pub fn durable_ingest(mut self, ingest: DurableIngest) -> Self {
+        self.durable_ingest = Some(ingest);
+        self
+    }
+
+    /// Reject calls to `#[write]` methods with `403 Forbidden`.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Enable the admin API under `/__admin/`, authenticated with `Authorization: Bearer <token>`.
+    /// Without a token the admin API is disabled.
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(Arc::new(RwLock::new(token.into())));
+        self.admin_token_secret = None;
+        self
+    }
+
+    /// Enable the admin API like [`ServerOptions::admin_token`], with the token fetched from
+    /// `secret` when the server starts, and again as it refreshes.  See [`crate::secrets`].
+    pub fn admin_token_secret(mut self, secret: Secret) -> Self {
+        self.admin_token = None;
+        self.admin_token_secret = Some(secret);
+        self
+    }
+
+    /// Where the admin API writes snapshots of the actor's state to and restores them from.
+    /// See [`crate::snapshot`].
+    pub fn snapshots(mut self, store: impl SnapshotStore + 'static) -> Self {
+        self.snapshot_store = Some(Shared(Arc::new(store)));
+        self
+    }
+
+    /// Restore the latest snapshot from the snapshot store before serving any calls.
+    pub fn restore_on_start(mut self, restore: bool) -> Self {
+        self.restore_on_start = restore;
+        self
+    }
+
+    /// Make `value` available to every call through [`crate::RequestContext::resource`].
+    /// Resources are looked up by type, so registering a second value of the same type
+    /// replaces the first.
+    pub fn resource<T: std::any::Any + Send + Sync>(mut self, value: T) -> Self {
+        self.resources.insert(value);
+        self
+    }
+
+    /// Turn calls away with `503 Service Unavailable` when they queue for too long.  See
+    /// [`crate::shedding`].
+    pub fn load_shedding(mut self, shedding: LoadShedding) -> Self {
+        self.load_shedding = Some(shedding);
+        self
+    }
+
+    /// Account for the memory the server holds in `memory`, turning calls away with `503 Service
+    /// Unavailable` above its ceiling.  See [`crate::memory`].
+    pub fn memory(mut self, memory: MemoryBudget) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Record call statistics into `stats`, so that a clone kept by the caller can read them.
+    /// Statistics are collected either way; see [`crate::stats`].
+    pub fn stats(mut self, stats: Stats) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Report calls slower than the slow log's threshold.  See [`crate::slowlog`].
+    pub fn slow_log(mut self, slow_log: SlowLog) -> Self {
+        self.slow_log = Some(slow_log);
+        self
+    }
+
+    /// Keep a sample of calls' redacted payloads in `sampler`.  See [`crate::sampling`].
+    pub fn sample_payloads(mut self, sampler: PayloadSampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Let WebSocket clients subscribe to events published on `events`.  See [`crate::events`].
+    pub fn events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Bound each WebSocket connection's outbound queue, and choose what happens to clients
+    /// that do not keep up.  See [`crate::backpressure`].
+    pub fn send_queue(mut self, queue: SendQueue) -> Self {
+        self.send_queue = queue;
+        self
+    }
+
+    /// Read request bodies into buffers reused from earlier requests.  See [`crate::buffers`].
+    pub fn buffer_pool(mut self, pool: BufferPool) -> Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Run methods marked `#[blocking]` on `pool` rather than a default pool of 16 threads.  See
+    /// [`crate::blocking`].
+    pub fn blocking_pool(mut self, pool: BlockingPool) -> Self {
+        self.blocking_pool = Some(pool);
+        self
+    }
+
+    /// Serve methods marked `#[flag("name")]` only while their flag is enabled.  See
+    /// [`crate::flags`].
+    pub fn feature_flags(mut self, flags: FeatureFlags) -> Self {
+        self.feature_flags = Some(flags);
+        self
+    }
+
+    /// Meter calls per client and turn clients over their daily quota away with
+    /// `429 Too Many Requests`.  See [`crate::quota`].
+    pub fn quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    /// Only serve HTTP requests signed with `signing`'s secret, and refuse replayed ones.  See
+    /// [`crate::signing`].
+    pub fn signing(mut self, signing: Signing) -> Self {
+        self.signing = Some(signing);
+        self
+    }
+
+    /// Decrypt parameters marked `#[sensitive(encrypted)]` with `encryption` before their
+    /// method sees them.  Without it, calls to such methods are refused.  See
+    /// [`crate::encryption`].
+    pub fn field_encryption(mut self, encryption: Encryption) -> Self {
+        self.field_encryption = Some(encryption);
+        self
+    }
+
+    /// Add security headers and restrict cross-origin calls; see [`crate::security`].  Without
+    /// this, any origin may call the server.
+    pub fn security(mut self, preset: SecurityPreset) -> Self {
+        self.security = preset;
+        self
+    }
+
+    /// Answer HTTP calls sent without an allowed `Content-Type` with `415 Unsupported Media
+    /// Type`; see [`crate::content_type`].
+    pub fn content_types(mut self, content_types: ContentTypes) -> Self {
+        self.content_types = Some(content_types);
+        self
+    }
+
+    /// Accept versioned payloads, upgrading old ones with migration functions; see
+    /// [`crate::versioning`].
+    pub fn versioning(mut self, versioning: Versioning) -> Self {
+        self.versioning = Some(versioning);
+        self
+    }
+
+    /// Reject calls whose numbers can't be represented exactly, with [`Numbers::Strict`].  See
+    /// [`crate::numbers`].
+    pub fn numbers(mut self, numbers: Numbers) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    /// Limit how deeply call parameters nest and how long their strings and arrays may be; see
+    /// [`crate::limits`].
+    pub fn json_limits(mut self, limits: JsonLimits) -> Self {
+        self.json_limits = limits;
+        self
+    }
+
+    /// Let callers add `?debug=1` to a URL to get how long the request took, in a
+    /// `Server-Timing` header and a `debug` field wrapped around the JSON response.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Answer calls to methods the actor doesn't have with `fallback`, e.g. an [`ActorRef`](crate::ActorRef)
+    /// to proxy them to.  Only methods missing from [`Actor::methods`](crate::Actor::methods)
+    /// reach it, so actors implemented by hand need to describe their methods.  See
+    /// [`crate::fallback`].
+    pub fn fallback(mut self, fallback: impl Fallback + 'static) -> Self {
+        self.fallback = Some(Shared(Arc::new(fallback)));
+        self
+    }
+
+    /// Answer requests with verbs other than `POST` and `OPTIONS` with `response` instead of
+    /// `405 Method Not Allowed`.
+    pub fn method_not_allowed(mut self, response: FallbackResponse) -> Self {
+        self.method_not_allowed = Some(response);
+        self
+    }
+
+    /// Serve liveness and readiness probes, and label metrics with the pod's metadata; see
+    /// [`crate::k8s`].
+    pub fn kubernetes(mut self, kubernetes: Kubernetes) -> Self {
+        self.kubernetes = Some(kubernetes);
+        self
+    }
+
+    /// Call `hook` once the server is listening, with where and what it serves, e.g.
+    /// [`startup::print_json`](crate::startup::print_json); see [`crate::startup`].
+    pub fn on_startup(mut self, hook: impl Fn(&StartupEvent) + Send + Sync + 'static) -> Self {
+        self.on_startup = Some(Shared(Arc::new(hook)));
+        self
+    }
+
+    /// Call `method` with `params` before accepting connections, e.g. to fill a cache.  Calls
+    /// are made in the order they are added; see [`crate::warmup`].
+    pub fn warm_up(mut self, method: impl Into<String>, params: serde_json::Value) -> Self {
+        self.warm_up.push((method.into(), params.to_string()));
+        self
+    }
+
+    /// The most calls a `POST /__batch` request may carry (default 64); `0` turns batches off.
+    /// See [`crate::batch`].
+    pub fn max_batch(mut self, calls: usize) -> Self {
+        self.max_batch = Some(calls);
+        self
+    }
+
+    /// Stop the server when a method panics, as a drain would, instead of only failing the call.
+    /// The server's [`failure`](crate::ServerHandle::failure) says which method it was.
+    /// Servers started by a [`Supervisor`](crate::supervisor::Supervisor) always do, so they
+    /// are restarted with a fresh actor.
+    pub fn stop_on_panic(mut self, stop: bool) -> Self {
+        self.stop_on_panic = stop;
+        self
+    }
+
+    /// Report the server's lifecycle events to `monitor`, as `name`; see [`crate::monitor`].
+    pub fn monitor(mut self, monitor: Monitor, name: impl Into<String>) -> Self {
+        self.monitor = Some((monitor, name.into()));
+        self
+    }
+
+    /// Use a Postgres connection pool; see [`crate::postgres`].
+    #[cfg(feature = "postgres")]
+    pub fn postgres(self, postgres: crate::postgres::Postgres) -> Self {
+        let mut options = self.resource(postgres.pool().clone());
+        if postgres.transaction_per_request_enabled() {
+            options.middleware.push(Shared(Arc::new(postgres)));
+        }
+        options
+    }
+
+    /// The address to listen on.
+    pub(crate) fn host_addr(&self) -> IpAddr {
+        self.host.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// The URL scheme clients use to reach a server built from these options.
+    pub(crate) fn scheme(&self) -> &'static str {
+        match (self.websocket, self.tls.is_some()) {
+            (true, true) => "wss",
+            (true, false) => "ws",
+            (false, true) => "https",
+            (false, false) => "http",
+        }
+    }
+}
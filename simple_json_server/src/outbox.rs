@@ -0,0 +1,310 @@
+//! A transactional outbox: events emitted together with the changes that cause them (feature
+//! `store`).
+//!
+//! A method that changes stored state and also tells other services about it has a problem if
+//! it does the two separately: a crash between them loses the event, or sends one for a change
+//! that never happened.  With an outbox, the event is written to the [`ActorStore`] in the same
+//! transaction as the change, with [`Transaction::emit`](crate::store::Transaction::emit), and an
+//! [`Outbox`] relay delivers it afterwards, retrying until it gets through:
+//!
+//! ```rust,no_run
+//! use simple_json_server::events::EventBus;
+//! use simple_json_server::outbox::Outbox;
+//! use simple_json_server::store::ActorStore;
+//! use simple_json_server::{actor, Actor, ActorRef};
+//!
+//! pub struct Orders {
+//!     store: ActorStore,
+//! }
+//!
+//! #[actor]
+//! impl Orders {
+//!     pub async fn place(&self, id: String, total: u64) -> Result<(), String> {
+//!         self.store
+//!             .transaction(|tx| {
+//!                 tx.put(&format!("order/{}", id), &total)?;
+//!                 tx.emit("order_placed", &serde_json::json!({ "id": id, "total": total }))
+//!             })
+//!             .map_err(|e| e.to_string())
+//!     }
+//! }
+//!
+//! # async fn example() {
+//! let store = ActorStore::open("orders.db").unwrap();
+//! let events = EventBus::new();
+//! let relay = Outbox::new(store.clone())
+//!     .publish_to(events.clone())
+//!     .route("order_placed", ActorRef::new("https://billing.internal"), "order_placed")
+//!     .start();
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! Each event is published on its topic to the [`EventBus`] given to [`Outbox::publish_to`],
+//! and sent as the parameters of a call to every actor [routed](Outbox::route) to its topic,
+//! which may be another service's webhook.  An event leaves the outbox once every delivery has
+//! succeeded; a call that fails, is answered with an error status, or returns `Err` is tried
+//! again later, waiting [`Outbox::retry_delay`] and then twice as long each time up to
+//! [`Outbox::max_retry_delay`].  Until then the event stays in the database, so it survives
+//! restarts.
+//!
+//! Delivery is at least once: a retried event is delivered again to every destination, and a
+//! crash after delivering an event but before removing it sends it again on restart.  Events
+//! are first tried in the order they were committed, but one that is being retried does not
+//! hold back those after it.  With an [encrypted](ActorStore::encrypted) store, payloads are
+//! encrypted too, and the relay must be given a store with the same key.
+
+use crate::deadletter;
+use crate::events::EventBus;
+use crate::store::{ActorStore, StoreError};
+use crate::ActorRef;
+use rusqlite::params;
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+/// How many due events are read from the database at a time.
+const BATCH: i64 = 100;
+
+/// An event waiting in the outbox.
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    /// Identifies the event in the outbox, in the order events were committed.
+    pub id: i64,
+    /// The topic it was emitted on.
+    pub topic: String,
+    /// Its payload.
+    pub payload: Value,
+    /// How many delivery attempts have failed.
+    pub attempts: u32,
+    /// The error from the last failed attempt.
+    pub last_error: Option<String>,
+}
+
+/// Delivers the events committed to an [`ActorStore`]'s outbox; see the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    store: ActorStore,
+    bus: Option<EventBus>,
+    routes: Vec<(String, ActorRef, String)>,
+    retry_delay: Duration,
+    max_retry_delay: Duration,
+}
+
+impl Outbox {
+    /// A relay for the events emitted in transactions of `store`, or of any view of the same
+    /// database.  Failed deliveries are retried after 1 second, backing off to 5 minutes.
+    pub fn new(store: ActorStore) -> Self {
+        Self {
+            store,
+            bus: None,
+            routes: Vec::new(),
+            retry_delay: Duration::from_secs(1),
+            max_retry_delay: Duration::from_secs(300),
+        }
+    }
+
+    /// Publish every event on its topic to `bus`.
+    pub fn publish_to(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// Call `method` of `actor` with the payload of every event on `topic`.
+    pub fn route(
+        mut self,
+        topic: impl Into<String>,
+        actor: ActorRef,
+        method: impl Into<String>,
+    ) -> Self {
+        self.routes.push((topic.into(), actor, method.into()));
+        self
+    }
+
+    /// How long to wait before retrying an event the first time.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// The longest wait between retries of an event.
+    pub fn max_retry_delay(mut self, delay: Duration) -> Self {
+        self.max_retry_delay = delay;
+        self
+    }
+
+    /// The events waiting in the outbox, oldest first.
+    pub fn pending(&self) -> Result<Vec<PendingEvent>, StoreError> {
+        let conn = self.store.connection();
+        let mut statement = conn.prepare(
+            "SELECT id, topic, payload, attempts, last_error FROM actor_outbox ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, u32>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        rows.map(|row| {
+            let (id, topic, payload, attempts, last_error) = row?;
+            let payload = self.store.decode_event(&topic, &payload)?;
+            let payload = serde_json::from_str(&payload)
+                .map_err(|e| StoreError::Serialization(e.to_string()))?;
+            Ok(PendingEvent {
+                id,
+                topic,
+                payload,
+                attempts,
+                last_error,
+            })
+        })
+        .collect()
+    }
+
+    /// Try once to deliver every event that is due, and return how many were delivered.
+    pub async fn deliver_due(&self) -> Result<usize, StoreError> {
+        let mut delivered = 0;
+        let mut after = 0;
+        loop {
+            let due = self.due(after)?;
+            let Some((last, _, _)) = due.last() else {
+                return Ok(delivered);
+            };
+            after = *last;
+            for (id, topic, payload) in due {
+                match self.deliver(&topic, &payload).await {
+                    Ok(()) => {
+                        self.store
+                            .connection()
+                            .execute("DELETE FROM actor_outbox WHERE id = ?1", params![id])?;
+                        delivered += 1;
+                    }
+                    Err(e) => self.failed(id, &topic, &e)?,
+                }
+            }
+        }
+    }
+
+    /// Deliver events in the background as they are committed, until the relay is stopped.
+    /// Must be called within a Tokio runtime.
+    pub fn start(self) -> OutboxRelay {
+        let task = tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.deliver_due().await {
+                    log::error!("Failed to read the outbox: {}", e);
+                }
+                let wait = match self.next_due() {
+                    Ok(Some(due)) => due,
+                    Ok(None) => self.max_retry_delay,
+                    Err(_) => self.retry_delay,
+                };
+                tokio::select! {
+                    _ = self.store.emitted().notified() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+        OutboxRelay { task }
+    }
+}
+
+impl Outbox {
+    /// Up to a batch of the events after `after` that are due, with their decoded payloads.
+    /// Events that can't be decoded are counted as failed attempts.
+    fn due(&self, after: i64) -> Result<Vec<(i64, String, String)>, StoreError> {
+        let rows = {
+            let conn = self.store.connection();
+            let mut statement = conn.prepare(
+                "SELECT id, topic, payload FROM actor_outbox
+                 WHERE id > ?1 AND next_attempt <= ?2 ORDER BY id LIMIT ?3",
+            )?;
+            let rows = statement.query_map(params![after, now_millis(), BATCH], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+            })?;
+            rows.collect::<Result<Vec<(i64, String, String)>, _>>()?
+        };
+        let mut due = Vec::with_capacity(rows.len());
+        for (id, topic, payload) in rows {
+            match self.store.decode_event(&topic, &payload) {
+                Ok(payload) => due.push((id, topic, payload)),
+                Err(e) => self.failed(id, &topic, &e.to_string())?,
+            }
+        }
+        Ok(due)
+    }
+
+    /// Deliver an event to the bus and every actor routed to its topic.
+    async fn deliver(&self, topic: &str, payload: &str) -> Result<(), String> {
+        if let Some(bus) = &self.bus {
+            let data: Value = serde_json::from_str(payload).map_err(|e| e.to_string())?;
+            bus.publish(topic, &data).map_err(|e| e.to_string())?;
+        }
+        let routes = self.routes.iter().filter(|(routed, _, _)| routed == topic);
+        for (_, actor, method) in routes {
+            deadletter::attempt(actor, method, payload.to_string()).await?;
+        }
+        Ok(())
+    }
+
+    /// Record a failed attempt to deliver event `id`, and when to try it again.
+    fn failed(&self, id: i64, topic: &str, error: &str) -> Result<(), StoreError> {
+        let conn = self.store.connection();
+        let attempts: u32 = conn.query_row(
+            "SELECT attempts FROM actor_outbox WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+        let delay = self
+            .retry_delay
+            .saturating_mul(2u32.saturating_pow(attempts.min(31)))
+            .min(self.max_retry_delay);
+        log::warn!(
+            "Failed to deliver outbox event {} on `{}` ({} attempts), retrying in {:?}: {}",
+            id,
+            topic,
+            attempts + 1,
+            delay,
+            error
+        );
+        conn.execute(
+            "UPDATE actor_outbox SET attempts = attempts + 1, next_attempt = ?2, last_error = ?3
+             WHERE id = ?1",
+            params![id, now_millis() + delay.as_millis() as i64, error],
+        )?;
+        Ok(())
+    }
+
+    /// How long until the next event is due, if there are any.
+    fn next_due(&self) -> Result<Option<Duration>, StoreError> {
+        let next: Option<i64> = self.store.connection().query_row(
+            "SELECT MIN(next_attempt) FROM actor_outbox",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(next.map(|next| Duration::from_millis((next - now_millis()).max(0) as u64)))
+    }
+}
+
+/// A running relay, from [`Outbox::start`].  Dropping it leaves the relay running.
+#[derive(Debug)]
+pub struct OutboxRelay {
+    task: JoinHandle<()>,
+}
+
+impl OutboxRelay {
+    /// Stop delivering events.  Events not yet delivered stay in the outbox.
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
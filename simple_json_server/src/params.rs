@@ -0,0 +1,471 @@
+//! Error reporting for method parameters that fail to deserialize or are too large.
+//!
+//! Serde's errors are written for Rust programmers.  When a parameter is an enum, callers are
+//! better served by a list of the values it accepts, so the server works that list out from the
+//! enum's own `Deserialize` impl (which knows about `rename`, `rename_all` and `tag`) and
+//! answers `400 Bad Request` with it.
+//!
+//! Parameters bound to a request header or cookie with `#[from_header]` or `#[from_cookie]`
+//! are read here too, and reported the same way.
+//!
+//! Serde's errors quote the values they reject, so they are left out of the response when they
+//! would show the value of a parameter marked `#[sensitive]`.
+
+use crate::sanitize::{Sanitize, Sanitizer};
+use crate::{query, ParamSource, RequestContext};
+use hyper::StatusCode;
+use serde::de::value::MapDeserializer;
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde_json::Value;
+use std::fmt;
+
+/// The JSON values a serde enum accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumValues {
+    /// The field naming the variant, for internally tagged enums (`#[serde(tag = "...")]`).
+    /// Externally tagged enums have none.
+    pub tag: Option<&'static str>,
+    /// The variant names as they appear in JSON, after renaming.
+    pub variants: &'static [&'static str],
+}
+
+impl fmt::Display for EnumValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let variants = self
+            .variants
+            .iter()
+            .map(|variant| format!("\"{}\"", variant))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.tag {
+            Some(tag) => write!(f, "an object with \"{}\" set to one of {}", tag, variants),
+            None => write!(f, "one of {}", variants),
+        }
+    }
+}
+
+/// The values `T` accepts if it is an externally or internally tagged enum, or `None` for any
+/// other type (including untagged enums, whose variants have no names in JSON).
+pub fn enum_values<T: DeserializeOwned>() -> Option<EnumValues> {
+    match T::deserialize(Probe { tag: None }) {
+        Err(ProbeError::Variants(variants)) => Some(EnumValues {
+            tag: None,
+            variants,
+        }),
+        // Internally tagged enums first ask for their tag; offer one no variant is named
+        Err(ProbeError::MissingField(tag)) => match T::deserialize(Probe { tag: Some(tag) }) {
+            Err(ProbeError::Variants(variants)) => Some(EnumValues {
+                tag: Some(tag),
+                variants,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Checks one parameter's value against its type; see [`check_enum`].
+pub type EnumCheck = fn(&Value) -> Option<EnumValues>;
+
+/// Build the response to a call whose parameters did not deserialize into the method's
+/// message struct, and mark the call `400 Bad Request`.  `checks` pairs each parameter name
+/// with [`check_enum`] for its type, so a bad enum value can be reported by name.
+pub fn invalid_params(
+    method: &str,
+    params: &Value,
+    error: serde_json::Error,
+    checks: &[(&str, EnumCheck)],
+) -> String {
+    if let Some(ctx) = RequestContext::current() {
+        ctx.set_status(StatusCode::BAD_REQUEST);
+    }
+    let invalid_enum = checks.iter().find_map(|(name, check)| {
+        let values = check(params.get(*name)?)?;
+        Some(format!(
+            "Invalid value for parameter `{}` of {}: expected {}",
+            name, method, values
+        ))
+    });
+    let message = invalid_enum.unwrap_or_else(|| {
+        let error = error.to_string();
+        if reveals_sensitive(&error, params) {
+            format!(
+                "Failed to deserialize parameters for {} (details withheld to protect sensitive parameters)",
+                method
+            )
+        } else {
+            format!("Failed to deserialize parameters for {}: {}", method, error)
+        }
+    });
+    serde_json::to_string(&message).unwrap_or_else(|_| "\"Deserialization error\"".to_string())
+}
+
+/// Whether `message` shows any part of the value of one of the call's `#[sensitive]`
+/// parameters in `params`.
+fn reveals_sensitive(message: &str, params: &Value) -> bool {
+    fn reveals(message: &str, value: &Value) -> bool {
+        match value {
+            Value::Null => false,
+            Value::String(text) => !text.is_empty() && message.contains(text.as_str()),
+            Value::Array(items) => items.iter().any(|item| reveals(message, item)),
+            Value::Object(fields) => fields.values().any(|field| reveals(message, field)),
+            _ => message.contains(&value.to_string()),
+        }
+    }
+    let Some(ctx) = RequestContext::current() else {
+        return false;
+    };
+    ctx.sensitive().iter().any(|name| {
+        params
+            .get(*name)
+            .is_some_and(|value| reveals(message, value))
+    })
+}
+
+/// Deserialize a method's message struct `M` straight from the message text.
+pub fn parse_params<M: DeserializeOwned>(msg: &str) -> Result<M, serde_json::Error> {
+    let parsed = serde_json::from_str(msg);
+    // Parameters from a query string are all strings, read as the types ask
+    if parsed.is_err() && from_query() {
+        if let Ok(params) = serde_json::from_str::<Value>(msg) {
+            return query::deserialize(params);
+        }
+    }
+    // With arbitrary precision, numbers buffered by internally tagged and untagged enums only
+    // deserialize from a parsed value
+    #[cfg(feature = "arbitrary_precision")]
+    if parsed.is_err() {
+        if let Ok(params) = serde_json::from_str::<Value>(msg) {
+            return M::deserialize(params).or(parsed);
+        }
+    }
+    parsed
+}
+
+/// Build the response to a call whose message text did not deserialize into the method's
+/// message struct `M`.  The text is only parsed now, to tell invalid JSON from parameters of the
+/// wrong shape and to find bad enum values.
+pub fn rejected_params<M: DeserializeOwned>(
+    method: &str,
+    msg: &str,
+    error: serde_json::Error,
+    checks: &[(&str, EnumCheck)],
+) -> String {
+    match serde_json::from_str::<Value>(msg) {
+        // Reported from the parsed value, like other deserialization errors, if it fails there too
+        Ok(params) => {
+            let reparsed = if from_query() {
+                query::deserialize::<M>(params.clone()).err()
+            } else {
+                M::deserialize(&params).err()
+            };
+            let error = reparsed.unwrap_or(error);
+            invalid_params(method, &params, error, checks)
+        }
+        Err(e) => parse_error(e),
+    }
+}
+
+/// Whether the call being served took its parameters from a query string.
+fn from_query() -> bool {
+    RequestContext::current().is_some_and(|ctx| ctx.params_from_query())
+}
+
+/// Build the response to a call of a method the actor doesn't have.
+pub fn unknown_method(method: &str, msg: &str) -> String {
+    if let Err(e) = serde_json::from_str::<de::IgnoredAny>(msg) {
+        return parse_error(e);
+    }
+    serde_json::to_string(&format!("Unknown method: {}", method))
+        .unwrap_or_else(|_| "\"Unknown method error\"".to_string())
+}
+
+fn parse_error(error: serde_json::Error) -> String {
+    serde_json::to_string(&format!("Failed to parse JSON: {}", error))
+        .unwrap_or_else(|_| "\"JSON parse error\"".to_string())
+}
+
+/// The value of `param`, bound to `source`, in the request being served.  The text is taken as
+/// a JSON string, or as JSON if the type wants something else (so `u32` reads `42`), and a
+/// missing value as `null`, which only `Option`s accept.  On failure the response to send is
+/// returned, and the call is marked `400 Bad Request`.
+pub fn bound_param<T: DeserializeOwned>(
+    method: &str,
+    param: &str,
+    source: ParamSource,
+) -> Result<T, String> {
+    let ctx = RequestContext::current();
+    let sensitive = ctx
+        .as_ref()
+        .is_some_and(|ctx| ctx.sensitive().contains(&param));
+    let (kind, name, value) = match source {
+        ParamSource::Header(name) => ("header", name, ctx.as_ref().and_then(|c| c.header(name))),
+        ParamSource::Cookie(name) => ("cookie", name, ctx.as_ref().and_then(|c| c.cookie(name))),
+        ParamSource::Body => ("parameter", param, None),
+    };
+    let parsed = match value {
+        Some(value) => T::deserialize(Value::String(value.to_string()))
+            .or_else(|e| serde_json::from_str(value).map_err(|_| e))
+            .map_err(|e| match enum_values::<T>() {
+                Some(values) => format!(
+                    "Invalid value for {} `{}` of {}: expected {}",
+                    kind, name, method, values
+                ),
+                None if sensitive => format!("Invalid value for {} `{}` of {}", kind, name, method),
+                None => format!("Invalid value for {} `{}` of {}: {}", kind, name, method, e),
+            }),
+        None => T::deserialize(Value::Null).map_err(|_| {
+            format!(
+                "Missing {} `{}` for parameter `{}` of {}",
+                kind, name, param, method
+            )
+        }),
+    };
+    parsed.map_err(|message| {
+        if let Some(ctx) = &ctx {
+            ctx.set_status(StatusCode::BAD_REQUEST);
+        }
+        serde_json::to_string(&message).unwrap_or_else(|_| "\"Invalid parameter\"".to_string())
+    })
+}
+
+/// Sanitize the parameter `param` of `method` as declared with `#[sanitize(...)]`.  If it is
+/// still too long, the response to send is returned, and the call is marked `400 Bad Request`.
+pub fn sanitize_param<T: Sanitize>(
+    method: &str,
+    param: &str,
+    value: &mut T,
+    sanitizer: &Sanitizer,
+) -> Result<(), String> {
+    value.sanitize(sanitizer).map_err(|len| {
+        if let Some(ctx) = RequestContext::current() {
+            ctx.set_status(StatusCode::BAD_REQUEST);
+        }
+        let message = format!(
+            "Parameter `{}` of {} is {} characters; at most {} are allowed",
+            param,
+            method,
+            len,
+            sanitizer.limit().unwrap_or_default()
+        );
+        serde_json::to_string(&message).unwrap_or_else(|_| "\"Invalid parameter\"".to_string())
+    })
+}
+
+/// Build the response to a call whose `param` holds `len` bytes, more than the `max` allowed by
+/// `#[max_size]`, and mark the call `400 Bad Request`.
+pub fn too_large(method: &str, param: &str, len: usize, max: usize) -> String {
+    if let Some(ctx) = RequestContext::current() {
+        ctx.set_status(StatusCode::BAD_REQUEST);
+    }
+    let message = format!(
+        "Parameter `{}` of {} is {} bytes; at most {} are allowed",
+        param, method, len, max
+    );
+    serde_json::to_string(&message).unwrap_or_else(|_| "\"Parameter too large\"".to_string())
+}
+
+/// The size of a parameter limited by `#[max_size]`.
+pub fn byte_len<T: AsRef<[u8]> + ?Sized>(value: &T) -> usize {
+    value.as_ref().len()
+}
+
+/// If `T` is an enum and `value` is not one of its values, the values it accepts.
+pub fn check_enum<T: DeserializeOwned>(value: &Value) -> Option<EnumValues> {
+    match serde_json::from_value::<T>(value.clone()) {
+        Ok(_) => None,
+        Err(_) => enum_values::<T>(),
+    }
+}
+
+/// A deserializer that feeds a type just enough input to learn its variant names from the
+/// errors it reports.
+struct Probe {
+    tag: Option<&'static str>,
+}
+
+/// What a [`Probe`] learned, reported through the deserializer's error type.
+#[derive(Debug)]
+enum ProbeError {
+    Variants(&'static [&'static str]),
+    MissingField(&'static str),
+    Other,
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        ProbeError::Other
+    }
+
+    fn unknown_variant(_variant: &str, expected: &'static [&'static str]) -> Self {
+        ProbeError::Variants(expected)
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ProbeError::MissingField(field)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Probe {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // Internally tagged enums deserialize from a map holding their tag.  No variant is
+        // named after a NUL character, so the tag is always unknown.
+        let entries = self.tag.map(|tag| (tag, "\0"));
+        visitor.visit_map(MapDeserializer::new(entries.into_iter()))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(ProbeError::Variants(variants))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        // Structs are not enums, whatever their fields are
+        Err(ProbeError::Other)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct newtype_struct seq tuple tuple_struct map identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    #[allow(dead_code)]
+    enum Color {
+        DarkRed,
+        #[serde(rename = "GREEN")]
+        Green,
+        Custom(String),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(tag = "kind")]
+    #[allow(dead_code)]
+    enum Shape {
+        Circle { radius: f64 },
+        Square { side: f64 },
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    #[allow(dead_code)]
+    enum Untagged {
+        Number(i64),
+        Text(String),
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Paint {
+        color: Color,
+    }
+
+    #[test]
+    fn test_enum_values() {
+        assert_eq!(
+            enum_values::<Color>(),
+            Some(EnumValues {
+                tag: None,
+                variants: &["dark_red", "GREEN", "custom"],
+            })
+        );
+        assert_eq!(
+            enum_values::<Shape>(),
+            Some(EnumValues {
+                tag: Some("kind"),
+                variants: &["Circle", "Square"],
+            })
+        );
+        assert_eq!(enum_values::<Untagged>(), None);
+        assert_eq!(enum_values::<Paint>(), None);
+        assert_eq!(enum_values::<Option<Shape>>(), enum_values::<Shape>());
+        assert_eq!(enum_values::<Option<String>>(), None);
+        assert_eq!(enum_values::<Value>(), None);
+        assert_eq!(enum_values::<u32>(), None);
+    }
+
+    #[test]
+    fn test_enum_values_display() {
+        let shape = enum_values::<Shape>().unwrap();
+        assert_eq!(
+            shape.to_string(),
+            r#"an object with "kind" set to one of "Circle", "Square""#
+        );
+        assert!(
+            check_enum::<Shape>(&serde_json::json!({"kind": "Circle", "radius": 1.0})).is_none()
+        );
+        assert_eq!(
+            check_enum::<Shape>(&serde_json::json!("Circle")),
+            Some(shape)
+        );
+    }
+    #[tokio::test]
+    async fn test_bound_params() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("x-tenant-id", "acme".parse().unwrap());
+        headers.insert("x-limit", "42".parse().unwrap());
+        headers.insert("x-color", "blue".parse().unwrap());
+        let ctx = RequestContext::new("list", "{}", crate::context::Resources::default())
+            .with_headers(headers);
+        let header = ParamSource::Header;
+        ctx.clone()
+            .scope(async {
+                let tenant: Result<String, _> =
+                    bound_param("list", "tenant", header("X-Tenant-Id"));
+                assert_eq!(tenant.unwrap(), "acme");
+                let limit: Result<u32, _> = bound_param("list", "limit", header("X-Limit"));
+                assert_eq!(limit.unwrap(), 42);
+                let numeric: Result<String, _> = bound_param("list", "limit", header("X-Limit"));
+                assert_eq!(numeric.unwrap(), "42");
+                let absent: Result<Option<String>, _> =
+                    bound_param("list", "trace", header("X-Trace"));
+                assert_eq!(absent.unwrap(), None);
+            })
+            .await;
+        assert_eq!(ctx.status(), None);
+
+        ctx.clone()
+            .scope(async {
+                let missing: Result<String, _> = bound_param("list", "trace", header("X-Trace"));
+                assert_eq!(
+                    missing.unwrap_err(),
+                    r#""Missing header `X-Trace` for parameter `trace` of list""#
+                );
+                let color: Result<Color, _> = bound_param("list", "color", header("X-Color"));
+                assert!(color.is_err_and(|e| e.contains("`X-Color` of list: expected one of")));
+            })
+            .await;
+        assert_eq!(ctx.status(), Some(StatusCode::BAD_REQUEST));
+    }
+}
@@ -0,0 +1,324 @@
+//! Calling an actor served by several processes, without an external load balancer.
+//!
+//! An [`ActorPool`] holds an [`ActorRef`] per server and spreads calls across them, by
+//! [`Strategy::RoundRobin`] (the default) or [`Strategy::LeastLatency`].  Calls made with
+//! [`ActorPool::call_keyed`] are sticky instead: every call with the same key goes to the same
+//! server while it is up, e.g. to keep a user's session on the node that caches it.  Keys are
+//! spread by rendezvous hashing, so a server going down only moves the keys it had.
+//!
+//! A call that cannot reach its server (or finds its circuit breaker open) fails over to the
+//! next one, and the server is skipped for [`ActorPool::cooldown`].  Any response, error statuses
+//! included, counts as reaching the server and is returned as is.  With
+//! [`ActorPool::health_check`] the servers are also probed in the background, so one that comes
+//! back is used again as soon as it answers.
+//!
+//! ```rust,no_run
+//! use simple_json_server::pool::{ActorPool, Strategy};
+//! use serde_json::json;
+//! use std::time::Duration;
+//!
+//! # async fn example() -> Result<(), simple_json_server::ClientError> {
+//! let carts = ActorPool::new(["http://carts-0:8080", "http://carts-1:8080", "http://carts-2:8080"])
+//!     .strategy(Strategy::LeastLatency);
+//! let _checks = carts.health_check("ping", Duration::from_secs(5));
+//!
+//! let total: f64 = carts.call_keyed("user-42", "total", &json!({"user": 42})).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ActorRef, ClientError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// How an [`ActorPool`] picks the server for a call without a key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Each server in turn.
+    #[default]
+    RoundRobin,
+    /// The server that has been answering fastest lately.
+    LeastLatency,
+}
+
+/// Handles to the servers of one actor, called as one.  Clones share the servers' health.
+#[derive(Debug, Clone)]
+pub struct ActorPool {
+    servers: Arc<Vec<Server>>,
+    strategy: Strategy,
+    cooldown: Duration,
+    next: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct Server {
+    actor: ActorRef,
+    /// Average response time in microseconds, 0 until the first response.
+    latency_us: AtomicU64,
+    /// Skip the server until then, after failing to reach it.
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Server {
+    fn is_up(&self, now: Instant) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| until <= now)
+    }
+
+    fn mark_down(&self, cooldown: Duration) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + cooldown);
+    }
+
+    fn mark_up(&self) {
+        *self.down_until.lock().unwrap() = None;
+    }
+
+    fn record_latency(&self, elapsed: Duration) {
+        let sample = (elapsed.as_micros() as u64).max(1);
+        let average = self.latency_us.load(Ordering::Relaxed);
+        // Weigh the latest response a fifth, so one slow call doesn't send everyone elsewhere
+        let average = match average {
+            0 => sample,
+            average => (average * 4 + sample) / 5,
+        };
+        self.latency_us.store(average, Ordering::Relaxed);
+    }
+}
+
+/// Whether an error means the call never reached the server.
+fn is_unreachable(error: &ClientError) -> bool {
+    matches!(
+        error,
+        ClientError::Transport(_) | ClientError::CircuitOpen(_)
+    )
+}
+
+impl ActorPool {
+    /// A pool of the actors served at `urls`.
+    pub fn new(urls: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self::from_actors(urls.into_iter().map(ActorRef::new))
+    }
+
+    /// A pool of existing handles, e.g. ones with circuit breakers.
+    pub fn from_actors(actors: impl IntoIterator<Item = ActorRef>) -> Self {
+        let servers = actors
+            .into_iter()
+            .map(|actor| Server {
+                actor,
+                latency_us: AtomicU64::new(0),
+                down_until: Mutex::new(None),
+            })
+            .collect();
+        Self {
+            servers: Arc::new(servers),
+            strategy: Strategy::default(),
+            cooldown: Duration::from_secs(10),
+            next: Arc::default(),
+        }
+    }
+
+    /// How to pick the server for calls without a key.
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// How long to skip a server that couldn't be reached (default 10 seconds).
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// The URLs of the servers that are currently used.
+    pub fn healthy(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.servers
+            .iter()
+            .filter(|server| server.is_up(now))
+            .map(|server| server.actor.url().to_string())
+            .collect()
+    }
+
+    /// Call `method` with `params` on one of the servers and deserialize the response.
+    pub async fn call<P, R>(&self, method: &str, params: &P) -> Result<R, ClientError>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let body =
+            serde_json::to_string(params).map_err(|e| ClientError::Serialization(e.to_string()))?;
+        let response = self.send(None, method, body).await?;
+        serde_json::from_str(&response).map_err(|e| ClientError::Serialization(e.to_string()))
+    }
+
+    /// Call `method` with an already serialized JSON body and return the raw JSON response.
+    pub async fn call_raw(&self, method: &str, body: String) -> Result<String, ClientError> {
+        self.send(None, method, body).await
+    }
+
+    /// Like [`ActorPool::call`], but on the server that `key` sticks to.
+    pub async fn call_keyed<P, R>(
+        &self,
+        key: &str,
+        method: &str,
+        params: &P,
+    ) -> Result<R, ClientError>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let body =
+            serde_json::to_string(params).map_err(|e| ClientError::Serialization(e.to_string()))?;
+        let response = self.send(Some(key), method, body).await?;
+        serde_json::from_str(&response).map_err(|e| ClientError::Serialization(e.to_string()))
+    }
+
+    async fn send(
+        &self,
+        key: Option<&str>,
+        method: &str,
+        body: String,
+    ) -> Result<String, ClientError> {
+        let mut last_error = None;
+        for index in self.order(key) {
+            let server = &self.servers[index];
+            let started = Instant::now();
+            match server.actor.call_raw(method, body.clone()).await {
+                Err(e) if is_unreachable(&e) => {
+                    log::warn!("Failing over from {}: {}", server.actor.url(), e);
+                    server.mark_down(self.cooldown);
+                    last_error = Some(e);
+                }
+                result => {
+                    server.record_latency(started.elapsed());
+                    return result;
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| ClientError::Transport("The pool is empty".to_string())))
+    }
+
+    /// The servers to try for a call, best first: those that are up, then those that are down
+    /// in case they have come back.
+    fn order(&self, key: Option<&str>) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.servers.len()).collect();
+        match (key, self.strategy) {
+            (Some(key), _) => order.sort_by_key(|&i| {
+                let mut hasher = DefaultHasher::new();
+                (key, i, self.servers[i].actor.url()).hash(&mut hasher);
+                std::cmp::Reverse(hasher.finish())
+            }),
+            (None, Strategy::RoundRobin) => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                order.rotate_left(start % self.servers.len().max(1));
+            }
+            (None, Strategy::LeastLatency) => {
+                order.sort_by_key(|&i| self.servers[i].latency_us.load(Ordering::Relaxed))
+            }
+        }
+        let now = Instant::now();
+        // A stable sort keeps the strategy's order within each group
+        order.sort_by_key(|&i| !self.servers[i].is_up(now));
+        order
+    }
+
+    /// Call `method` on every server once, and use those that answer (with any status) again.
+    pub async fn probe(&self, method: &str) {
+        probe(&self.servers, method, self.cooldown).await;
+    }
+
+    /// Call [`ActorPool::probe`] every `interval` in the background, until the returned handle
+    /// is cancelled or every clone of the pool is dropped.  Must be called within a Tokio runtime.
+    pub fn health_check(&self, method: impl Into<String>, interval: Duration) -> HealthCheck {
+        let servers: Weak<Vec<Server>> = Arc::downgrade(&self.servers);
+        let method = method.into();
+        let cooldown = self.cooldown;
+        let task = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let Some(servers) = servers.upgrade() else {
+                    break;
+                };
+                probe(&servers, &method, cooldown).await;
+            }
+        });
+        HealthCheck { task }
+    }
+}
+
+async fn probe(servers: &[Server], method: &str, cooldown: Duration) {
+    let probes = servers.iter().map(|server| async move {
+        let started = Instant::now();
+        match server.actor.call_raw(method, "{}".to_string()).await {
+            Err(e) if is_unreachable(&e) => server.mark_down(cooldown),
+            _ => {
+                server.record_latency(started.elapsed());
+                server.mark_up();
+            }
+        }
+    });
+    futures_util::future::join_all(probes).await;
+}
+
+/// Background health checks of an [`ActorPool`], from [`ActorPool::health_check`].  Dropping it
+/// leaves the checks running.
+#[derive(Debug)]
+pub struct HealthCheck {
+    task: JoinHandle<()>,
+}
+
+impl HealthCheck {
+    /// Stop probing the servers.
+    pub fn cancel(self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(urls: &[&str]) -> ActorPool {
+        ActorPool::new(urls.iter().copied())
+    }
+
+    #[test]
+    fn test_round_robin_skips_servers_that_are_down() {
+        let pool = pool(&["http://a", "http://b", "http://c"]);
+        assert_eq!(pool.order(None), [0, 1, 2]);
+        assert_eq!(pool.order(None), [1, 2, 0]);
+        pool.servers[0].mark_down(Duration::from_secs(60));
+        assert_eq!(pool.order(None), [2, 1, 0]);
+        assert_eq!(pool.healthy(), ["http://b", "http://c"]);
+    }
+
+    #[test]
+    fn test_least_latency() {
+        let pool = pool(&["http://a", "http://b"]).strategy(Strategy::LeastLatency);
+        pool.servers[0].record_latency(Duration::from_millis(20));
+        pool.servers[1].record_latency(Duration::from_millis(5));
+        assert_eq!(pool.order(None), [1, 0]);
+    }
+
+    #[test]
+    fn test_keys_stick_to_a_server() {
+        let pool = pool(&["http://a", "http://b", "http://c"]);
+        let first = pool.order(Some("user-1"))[0];
+        assert_eq!(pool.order(Some("user-1"))[0], first);
+
+        // Only keys of a server that goes down move
+        pool.servers[first].mark_down(Duration::from_secs(60));
+        let moved = pool.order(Some("user-1"));
+        assert_ne!(moved[0], first);
+        assert_eq!(*moved.last().unwrap(), first);
+    }
+}
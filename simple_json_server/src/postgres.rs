@@ -0,0 +1,234 @@
+//! Pooled Postgres connections for actors (feature `postgres`).
+//!
+//! [`Postgres`] wraps a [`deadpool_postgres::Pool`].  Registering it with
+//! [`crate::ServerOptions::postgres`] makes the pool available to every call as a resource:
+//!
+//! ```rust,ignore
+//! let pool = RequestContext::current().unwrap().resource::<Pool>().unwrap().clone();
+//! ```
+//!
+//! With [`Postgres::transaction_per_request`] enabled, each call can also use a transaction
+//! that the server manages for it.  The transaction is opened the first time the method asks
+//! for it with [`Transaction::current`], and once the method returns it is committed, or
+//! rolled back if the method returned `Err` (or called [`crate::RequestContext::set_failed`]).
+//!
+//! ```rust,no_run
+//! use simple_json_server::postgres::{Postgres, Transaction};
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//!
+//! #[derive(Clone)]
+//! struct Accounts;
+//!
+//! #[actor]
+//! impl Accounts {
+//!     pub async fn transfer(&self, from: i64, to: i64, cents: i64) -> Result<(), String> {
+//!         let tx = Transaction::current().ok_or("no transaction")?;
+//!         let client = tx.client().await.map_err(|e| e.to_string())?;
+//!         client
+//!             .execute("UPDATE accounts SET cents = cents - $1 WHERE id = $2", &[&cents, &from])
+//!             .await
+//!             .map_err(|e| e.to_string())?;
+//!         client
+//!             .execute("UPDATE accounts SET cents = cents + $1 WHERE id = $2", &[&cents, &to])
+//!             .await
+//!             .map_err(|e| e.to_string())?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let mut config = deadpool_postgres::Config::new();
+//! config.url = Some("postgres://localhost/bank".to_string());
+//! let pool = config
+//!     .create_pool(Some(deadpool_postgres::Runtime::Tokio1), tokio_postgres::NoTls)
+//!     .unwrap();
+//!
+//! Accounts.create_with(
+//!     ServerOptions::new(8080).postgres(Postgres::new(pool).transaction_per_request(true)),
+//! );
+//! # }
+//! ```
+
+use crate::middleware::Middleware;
+use crate::RequestContext;
+use deadpool_postgres::{Object, Pool};
+use futures_util::future::BoxFuture;
+use std::fmt;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// Errors returned when obtaining a request's transaction.
+#[derive(Debug)]
+pub enum PostgresError {
+    /// No connection could be taken from the pool.
+    Pool(String),
+    /// The database rejected a statement.
+    Database(String),
+}
+
+impl fmt::Display for PostgresError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostgresError::Pool(e) => write!(f, "Connection pool error: {}", e),
+            PostgresError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PostgresError {}
+
+/// A Postgres connection pool and how the server should use it.
+#[derive(Clone)]
+pub struct Postgres {
+    pool: Pool,
+    transaction_per_request: bool,
+}
+
+impl fmt::Debug for Postgres {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Postgres")
+            .field("transaction_per_request", &self.transaction_per_request)
+            .finish()
+    }
+}
+
+impl Postgres {
+    /// Use `pool` for the server's connections.
+    pub fn new(pool: Pool) -> Self {
+        Self {
+            pool,
+            transaction_per_request: false,
+        }
+    }
+
+    /// Give every call a transaction, committed or rolled back when the method returns.
+    pub fn transaction_per_request(mut self, enabled: bool) -> Self {
+        self.transaction_per_request = enabled;
+        self
+    }
+
+    /// The connection pool.
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    pub(crate) fn transaction_per_request_enabled(&self) -> bool {
+        self.transaction_per_request
+    }
+}
+
+/// The transaction of the call being served.  It is opened lazily, so calls that never ask
+/// for a connection don't take one from the pool.
+pub struct Transaction {
+    pool: Pool,
+    client: Mutex<Option<Object>>,
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction").finish_non_exhaustive()
+    }
+}
+
+impl Transaction {
+    /// The transaction of the call being served, if the server runs with
+    /// [`Postgres::transaction_per_request`].
+    pub fn current() -> Option<std::sync::Arc<Transaction>> {
+        RequestContext::current()?.extension::<Transaction>()
+    }
+
+    /// The connection the transaction runs on, opening the transaction first if needed.  Hold
+    /// the returned guard only while issuing statements.
+    pub async fn client(&self) -> Result<MappedMutexGuard<'_, Object>, PostgresError> {
+        let mut client = self.client.lock().await;
+        if client.is_none() {
+            let connection = self
+                .pool
+                .get()
+                .await
+                .map_err(|e| PostgresError::Pool(e.to_string()))?;
+            connection
+                .batch_execute("BEGIN")
+                .await
+                .map_err(|e| PostgresError::Database(e.to_string()))?;
+            *client = Some(connection);
+        }
+        Ok(MutexGuard::map(client, |client| client.as_mut().unwrap()))
+    }
+
+    /// Commit (or, if `failed`, roll back) the transaction if it was opened.
+    async fn finish(&self, failed: bool) {
+        let Some(client) = self.client.lock().await.take() else {
+            return;
+        };
+        let statement = if failed { "ROLLBACK" } else { "COMMIT" };
+        if let Err(e) = client.batch_execute(statement).await {
+            log::error!("Failed to {} request transaction: {}", statement, e);
+        }
+    }
+}
+
+impl Middleware for Postgres {
+    fn before<'a>(&'a self, ctx: &'a RequestContext) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            ctx.insert_extension(Transaction {
+                pool: self.pool.clone(),
+                client: Mutex::new(None),
+            });
+        })
+    }
+
+    fn after<'a>(&'a self, ctx: &'a RequestContext) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            if let Some(transaction) = ctx.extension::<Transaction>() {
+                transaction.finish(ctx.failed()).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Resources;
+
+    fn unreachable_pool() -> Pool {
+        let mut config = deadpool_postgres::Config::new();
+        config.url = Some("postgres://user@127.0.0.1:9/db".to_string());
+        config
+            .create_pool(
+                Some(deadpool_postgres::Runtime::Tokio1),
+                tokio_postgres::NoTls,
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_transaction_is_opened_lazily() {
+        let postgres = Postgres::new(unreachable_pool()).transaction_per_request(true);
+        let ctx = RequestContext::new("transfer", "{}", Resources::default());
+        postgres.before(&ctx).await;
+
+        let seen = ctx
+            .clone()
+            .scope(async { Transaction::current().is_some() })
+            .await;
+        assert!(seen);
+
+        // Never asking for the connection means the unreachable database is never contacted
+        postgres.after(&ctx).await;
+        assert!(Transaction::current().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_database_is_reported() {
+        let postgres = Postgres::new(unreachable_pool()).transaction_per_request(true);
+        let ctx = RequestContext::new("transfer", "{}", Resources::default());
+        postgres.before(&ctx).await;
+
+        let transaction = ctx.extension::<Transaction>().unwrap();
+        assert!(matches!(
+            transaction.client().await,
+            Err(PostgresError::Pool(_))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Conditional writes: optimistic concurrency with expected versions and `If-Match`.
+//!
+//! A method that changes versioned state can mark one parameter `#[if_match]`.  The caller puts
+//! the version it last read there (or in an `If-Match` header), and the call only runs if that
+//! is still the current version; otherwise it is refused before the method sees it.  The
+//! current version comes from a private `current_version` hook in the same `#[actor]` impl,
+//! given the method name and its JSON parameters, which may be `async`:
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor};
+//! use std::sync::Mutex;
+//!
+//! #[derive(Default)]
+//! pub struct Documents {
+//!     text: Mutex<(u64, String)>,
+//! }
+//!
+//! #[actor]
+//! impl Documents {
+//!     #[write]
+//!     pub async fn edit(&self, #[if_match] version: Option<u64>, text: String) -> u64 {
+//!         let mut doc = self.text.lock().unwrap();
+//!         *doc = (doc.0 + 1, text);
+//!         doc.0
+//!     }
+//!
+//!     fn current_version(&self, method: &str, params: &str) -> Option<u64> {
+//!         Some(self.text.lock().unwrap().0)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! - A version given in the parameter that differs from the current one is answered
+//!   `409 Conflict`.
+//! - Without the parameter (`null` or left out), an `If-Match` header is checked instead:
+//!   `If-Match: "5"` (a list of entity tags, weak ones included, or `*` for any version) that
+//!   doesn't match is answered `412 Precondition Failed`.
+//! - With neither, the call runs unconditionally.
+//!
+//! `current_version` returns `None` when there is nothing to compare with, e.g. the document
+//! doesn't exist yet, and then any expected version fails.  Versions are compared as JSON, and
+//! with `If-Match` as text, so numbers, strings and other serializable values all work.  The
+//! check runs inside the call, so for a `#[write]` method no other call can change the version
+//! between the check and the method.
+
+use crate::RequestContext;
+use hyper::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Check the `#[if_match]` parameter `param` of `method`, holding `expected`, against the
+/// actor's `current` version.  If the precondition fails, the response to send is returned,
+/// and the call is marked `409 Conflict` or `412 Precondition Failed`.
+#[doc(hidden)]
+pub fn check_version<E: Serialize, C: Serialize>(
+    method: &str,
+    param: &str,
+    expected: &E,
+    current: Option<C>,
+) -> Result<(), String> {
+    let current = current.and_then(|current| serde_json::to_value(current).ok());
+    let expected = serde_json::to_value(expected).unwrap_or(Value::Null);
+    let ctx = RequestContext::current();
+    let (status, message) = if !expected.is_null() {
+        if current.as_ref() == Some(&expected) {
+            return Ok(());
+        }
+        let message = match &current {
+            Some(current) => format!(
+                "Version conflict in {}: `{}` is {}, but the current version is {}",
+                method, param, expected, current
+            ),
+            None => format!(
+                "Version conflict in {}: `{}` is {}, but there is no current version",
+                method, param, expected
+            ),
+        };
+        (StatusCode::CONFLICT, message)
+    } else {
+        let Some(if_match) = ctx.as_ref().and_then(|ctx| ctx.header("if-match")) else {
+            return Ok(());
+        };
+        if current
+            .as_ref()
+            .is_some_and(|current| matches(if_match, current))
+        {
+            return Ok(());
+        }
+        let message = match &current {
+            Some(current) => format!(
+                "Precondition failed for {}: the current version is {}",
+                method,
+                tag(current)
+            ),
+            None => format!(
+                "Precondition failed for {}: there is no current version",
+                method
+            ),
+        };
+        (StatusCode::PRECONDITION_FAILED, message)
+    };
+    if let Some(ctx) = &ctx {
+        ctx.set_status(status);
+    }
+    Err(serde_json::to_string(&message).unwrap_or_else(|_| "\"Precondition failed\"".to_string()))
+}
+
+/// The text of a version as an entity tag: strings as they are, other values as JSON.
+fn tag(version: &Value) -> String {
+    match version {
+        Value::String(text) => text.clone(),
+        version => version.to_string(),
+    }
+}
+
+/// Whether the `If-Match` header value `if_match` names `current`.
+fn matches(if_match: &str, current: &Value) -> bool {
+    let current = tag(current);
+    if_match.split(',').map(str::trim).any(|etag| {
+        let etag = etag.strip_prefix("W/").unwrap_or(etag);
+        let etag = etag
+            .strip_prefix('"')
+            .and_then(|etag| etag.strip_suffix('"'))
+            .unwrap_or(etag);
+        etag == "*" || etag == current
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_if_match() {
+        assert!(matches(r#""5""#, &json!(5)));
+        assert!(matches(r#"W/"4", "5""#, &json!(5)));
+        assert!(matches("*", &json!("abc")));
+        assert!(matches(r#""abc""#, &json!("abc")));
+        assert!(!matches(r#""4""#, &json!(5)));
+    }
+
+    #[test]
+    fn test_expected_versions() {
+        assert_eq!(check_version("edit", "version", &Some(3), Some(3)), Ok(()));
+        assert_eq!(
+            check_version("edit", "version", &None::<u64>, Some(3)),
+            Ok(())
+        );
+        assert_eq!(
+            check_version("edit", "version", &Some(2), Some(3)),
+            Err(
+                r#""Version conflict in edit: `version` is 2, but the current version is 3""#
+                    .to_string()
+            )
+        );
+        assert!(check_version("edit", "version", &"v1", None::<String>).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Response formats for exploring a server by hand, e.g. with curl.
+//!
+//! Either `?pretty=1` in the URL or `application/json+pretty` in the `Accept` header indents a
+//! JSON response the way `serde_json::to_string_pretty` would:
+//!
+//! ```text
+//! $ curl -X POST 'localhost:8080/divide?pretty=1' -d '{"a": 1, "b": 0}'
+//! {
+//!   "Err": "Division by zero"
+//! }
+//! ```
+//!
+//! A server started with [`ServerOptions::debug`](crate::ServerOptions::debug) also honours
+//! `?debug=1`, which sends how long the request took in a `Server-Timing` header and, for JSON
+//! responses, wraps the body with a trailing `debug` field:
+//!
+//! ```text
+//! {"result": {"Err": "Division by zero"}, "debug": {"path": "/divide", "status": 200, "elapsed_us": 87}}
+//! ```
+//!
+//! Both flags can be combined, and neither changes anything for clients that don't ask.
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, ACCEPT, CONTENT_TYPE};
+use hyper::{HeaderMap, Response};
+use std::time::Duration;
+
+const PRETTY: &str = "application/json+pretty";
+
+/// How a request asked for its response to be formatted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Format {
+    pretty: bool,
+    debug: bool,
+}
+
+impl Format {
+    /// The format requested by a URL's query string and headers.  `?debug=1` is ignored unless
+    /// `debug_enabled`.
+    pub(crate) fn from_request(
+        query: Option<&str>,
+        headers: &HeaderMap,
+        debug_enabled: bool,
+    ) -> Self {
+        let accepts_pretty = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|media| media.split(';').next().unwrap_or_default().trim() == PRETTY);
+        Self {
+            pretty: accepts_pretty || has_flag(query, "pretty"),
+            debug: debug_enabled && has_flag(query, "debug"),
+        }
+    }
+
+    /// Reformat `response` to the request at `path`, which took `elapsed` to answer.
+    pub(crate) async fn apply(
+        self,
+        response: Response<Full<Bytes>>,
+        path: &str,
+        elapsed: Duration,
+    ) -> Response<Full<Bytes>> {
+        if self == Self::default() {
+            return response;
+        }
+        let (mut parts, body) = response.into_parts();
+        if self.debug {
+            let timing = format!("dispatch;dur={:.3}", elapsed.as_secs_f64() * 1000.0);
+            if let Ok(timing) = HeaderValue::from_str(&timing) {
+                parts.headers.insert("Server-Timing", timing);
+            }
+        }
+        let is_json = parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        let Ok(body) = body.collect().await;
+        let body = body.to_bytes();
+        let text = match std::str::from_utf8(&body) {
+            Ok(text) if is_json && !text.is_empty() => text,
+            _ => return Response::from_parts(parts, Full::new(body)),
+        };
+        let mut text = text.to_string();
+        if self.debug {
+            let debug = serde_json::json!({
+                "path": path,
+                "status": parts.status.as_u16(),
+                "elapsed_us": elapsed.as_micros() as u64,
+            });
+            text = format!(r#"{{"result":{},"debug":{}}}"#, text, debug);
+        }
+        if self.pretty {
+            text = indent(&text);
+        }
+        Response::from_parts(parts, Full::new(Bytes::from(text)))
+    }
+}
+
+/// Whether a query string sets `name`, as `name`, `name=1` or `name=true`.
+fn has_flag(query: Option<&str>, name: &str) -> bool {
+    query.is_some_and(|query| {
+        query.split('&').any(|pair| match pair.split_once('=') {
+            Some((key, value)) => key == name && matches!(value, "1" | "true"),
+            None => pair == name,
+        })
+    })
+}
+
+/// Indent a JSON text with two spaces per level, copying strings and numbers untouched.
+fn indent(json: &str) -> String {
+    fn newline(out: &mut String, depth: usize) {
+        out.push('\n');
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+    }
+
+    let mut out = String::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                match chars.next_if(|c| matches!(c, '}' | ']')) {
+                    Some(close) => out.push(close),
+                    None => {
+                        depth += 1;
+                        newline(&mut out, depth);
+                    }
+                }
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indent_matches_serde_json() {
+        let json = r#"{"a": [1, 2.5, {}], "b": {"c": "x, {y}: \"z\""}, "d": [], "e": null}"#;
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        let expected = serde_json::to_string_pretty(&value).unwrap();
+        assert_eq!(indent(json), expected);
+        assert_eq!(indent(r#""Unknown method: x""#), r#""Unknown method: x""#);
+    }
+
+    #[test]
+    fn test_requested_format() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            Format::from_request(Some("pretty=1&debug"), &headers, false),
+            Format {
+                pretty: true,
+                debug: false
+            }
+        );
+        assert_eq!(
+            Format::from_request(Some("pretty=0&debug=true"), &headers, true),
+            Format {
+                pretty: false,
+                debug: true
+            }
+        );
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html, application/json+pretty;q=0.9"),
+        );
+        assert!(Format::from_request(None, &headers, false).pretty);
+    }
+}
@@ -0,0 +1,71 @@
+//! Data-subject requests: exporting or erasing what an actor holds about one person.
+//!
+//! Privacy laws such as the GDPR let people ask for a copy of their data and for its deletion.
+//! An actor takes part by defining two private hooks in its `#[actor]` impl block, each given
+//! the subject's id (whatever identifies a user or customer in the service):
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor};
+//! use std::collections::HashMap;
+//! use std::sync::Mutex;
+//!
+//! struct Profiles {
+//!     emails: Mutex<HashMap<String, String>>,
+//! }
+//!
+//! #[actor]
+//! impl Profiles {
+//!     pub async fn set_email(&self, user: String, email: String) {
+//!         self.emails.lock().unwrap().insert(user, email);
+//!     }
+//!
+//!     async fn export_subject_data(&self, subject_id: &str) -> Option<String> {
+//!         self.emails.lock().unwrap().get(subject_id).cloned()
+//!     }
+//!
+//!     async fn delete_subject_data(&self, subject_id: &str) {
+//!         self.emails.lock().unwrap().remove(subject_id);
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! `export_subject_data` returns anything serializable, and either hook may return a `Result`
+//! whose error is `Display`.  They become [`Actor::export_subject_data`] and
+//! [`Actor::delete_subject_data`].
+//!
+//! With an admin token set, every server answers `POST /__admin/export_subject` and
+//! `POST /__admin/delete_subject` with a body such as `{"subject_id": "u-123"}`, so one
+//! script can fulfil a request across all of a service's actors.  Exports are answered with
+//! `{"subject_id": ..., "data": ...}`.  Deletions wait for calls in progress to finish and
+//! hold off new ones until they are done.  Actors without the hooks answer
+//! `501 Not Implemented`.
+//!
+//! Deletions are not journaled, so read-only replicas must be sent the request too, and
+//! [snapshots](crate::snapshot) taken earlier still hold the data.
+
+#[cfg(doc)]
+use crate::Actor;
+use std::fmt;
+
+/// Why a data-subject request couldn't be fulfilled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubjectError {
+    /// The actor has no hook for the request.
+    Unsupported,
+    /// The actor's hook failed.
+    Failed(String),
+}
+
+impl fmt::Display for SubjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectError::Unsupported => {
+                write!(f, "This actor does not support data-subject requests")
+            }
+            SubjectError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SubjectError {}
@@ -0,0 +1,163 @@
+//! CPU and heap profiles of a running server, in the [pprof](https://github.com/google/pprof)
+//! format, so slow or memory-hungry handlers can be investigated in production without
+//! redeploying an instrumented build.
+//!
+//! With the `profiling` feature enabled and an admin token set, a server serves:
+//!
+//! * `GET /debug/pprof/profile?seconds=N`: samples the CPU for `N` seconds (30 by default, at
+//!   most 300) and returns the profile.  Only one CPU profile can be taken at a time.
+//! * `GET /debug/pprof/heap`: the allocations sampled by jemalloc's heap profiler.
+//!
+//! Both are authorized like the admin API, with the admin token as a bearer token:
+//!
+//! ```bash
+//! curl -H "Authorization: Bearer $TOKEN" -o cpu.pb "http://localhost:8080/debug/pprof/profile?seconds=10"
+//! go tool pprof -http=:8000 cpu.pb
+//! ```
+//!
+//! Heap profiles need the application to allocate with jemalloc, with profiling turned on.  Add
+//! `tikv-jemallocator` with its `profiling` and `unprefixed_malloc_on_supported_platforms`
+//! features, and in the binary:
+//!
+//! ```rust,ignore
+//! #[global_allocator]
+//! static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+//!
+//! #[allow(non_upper_case_globals)]
+//! #[export_name = "malloc_conf"]
+//! pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+//! ```
+//!
+//! Otherwise `/debug/pprof/heap` answers `404 Not Found`.
+
+use crate::admin;
+use crate::server::{Reply, ServerState};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{HeaderMap, Response, StatusCode};
+use pprof::protos::Message;
+use std::time::Duration;
+
+/// Path prefix of the profiling endpoints.
+pub(crate) const PREFIX: &str = "/debug/pprof/";
+
+/// CPU profile length when none is asked for.
+const DEFAULT_SECONDS: u64 = 30;
+/// Longest CPU profile that can be asked for.
+const MAX_SECONDS: u64 = 300;
+/// CPU samples per second; not a multiple of common timer frequencies, to avoid lockstep.
+const FREQUENCY: i32 = 99;
+
+/// Serve the profile at `op` if the request carries the admin token.
+pub(crate) async fn handle<T>(
+    state: &ServerState<T>,
+    op: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+) -> Response<Full<Bytes>> {
+    let profile = match admin::authorize(state, headers) {
+        Ok(()) => match op {
+            "profile" => cpu(seconds(query)).await,
+            "heap" => heap().await,
+            _ => Err(Reply::error(
+                StatusCode::NOT_FOUND,
+                format!("Unknown profile: {}", op),
+            )),
+        },
+        Err(reply) => Err(reply),
+    };
+    match profile {
+        Ok(profile) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/octet-stream")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.pb\"", op),
+            )
+            .body(Full::new(Bytes::from(profile)))
+            .unwrap(),
+        Err(reply) => Response::builder()
+            .status(reply.status)
+            .header("Content-Type", reply.content_type)
+            .body(Full::new(Bytes::from(reply.body)))
+            .unwrap(),
+    }
+}
+
+/// The CPU profile length asked for by `?seconds=N`.
+fn seconds(query: Option<&str>) -> u64 {
+    query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("seconds="))
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(DEFAULT_SECONDS)
+        .clamp(1, MAX_SECONDS)
+}
+
+/// Sample the CPU for `seconds` seconds.
+async fn cpu(seconds: u64) -> Result<Vec<u8>, Reply> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| match e {
+            pprof::Error::Running => Reply::error(
+                StatusCode::CONFLICT,
+                "A CPU profile is already being taken".to_string(),
+            ),
+            e => failed(e),
+        })?;
+    tokio::time::sleep(Duration::from_secs(seconds)).await;
+    let profile = guard
+        .report()
+        .build()
+        .and_then(|report| report.pprof())
+        .map_err(failed)?;
+    Ok(profile.encode_to_vec())
+}
+
+/// Dump jemalloc's heap profile.
+async fn heap() -> Result<Vec<u8>, Reply> {
+    // Checked first, as `PROF_CTL` panics if jemalloc can't say
+    let enabled = tikv_jemalloc_ctl::profiling::prof::read().unwrap_or(false);
+    let Some(ctl) = enabled.then(|| jemalloc_pprof::PROF_CTL.as_ref()).flatten() else {
+        return Err(Reply::error(
+            StatusCode::NOT_FOUND,
+            "Heap profiling needs jemalloc with profiling enabled".to_string(),
+        ));
+    };
+    let mut ctl = ctl.clone().lock_owned().await;
+    if !ctl.activated() {
+        return Err(Reply::error(
+            StatusCode::NOT_FOUND,
+            "Heap profiling is not active".to_string(),
+        ));
+    }
+    // Writes and reads back a temporary file
+    tokio::task::spawn_blocking(move || ctl.dump_pprof())
+        .await
+        .map_err(failed)?
+        .map_err(failed)
+}
+
+fn failed(e: impl std::fmt::Display) -> Reply {
+    Reply::error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to take the profile: {}", e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seconds() {
+        assert_eq!(seconds(None), DEFAULT_SECONDS);
+        assert_eq!(seconds(Some("debug=1&seconds=5")), 5);
+        assert_eq!(seconds(Some("seconds=0")), 1);
+        assert_eq!(seconds(Some("seconds=100000")), MAX_SECONDS);
+        assert_eq!(seconds(Some("seconds=soon")), DEFAULT_SECONDS);
+    }
+}
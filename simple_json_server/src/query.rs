@@ -0,0 +1,376 @@
+//! Parameters of `#[get]` methods, read from the query string.
+//!
+//! A method marked `#[get]` (and `#[read]`, since `GET` must be safe to repeat) is also served
+//! by `GET /<method>`, with its parameters in the query string in the style of `serde_qs`:
+//!
+//! ```text
+//! GET /search?text=red+shoes&tag=sale&tag=new&filter[min]=10&filter[sizes][]=42
+//! ```
+//!
+//! Repeated keys and keys ending in `[]` make arrays, bracketed keys nest objects, and `[0]`,
+//! `[1]`... index arrays.  The query is turned into a JSON object of strings and goes through the
+//! same pipeline as a JSON body (limits, validation, dispatch and error reporting); when the
+//! method's types want numbers, booleans or sequences, the strings are read as those, so
+//! `?limit=10` fills a `u32` and a single `?tag=sale` a `Vec<String>`.
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde_json::{Map, Value};
+
+/// How deeply keys may nest, as in `a[b][c]`.
+const MAX_DEPTH: usize = 64;
+
+/// One step of a parameter's key: a field name, or `[]` for the next element of an array.
+enum Segment {
+    Key(String),
+    Push,
+}
+
+/// The JSON text of the parameters in `query`.
+pub(crate) fn to_json(query: &str) -> Result<String, String> {
+    let mut params = Value::Object(Map::new());
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = decode(key)?;
+        let path = parse_key(&key)?;
+        insert(&mut params, &path, decode(value)?)
+            .map_err(|()| format!("Query parameter `{}` conflicts with another", key))?;
+    }
+    index_arrays(&mut params);
+    Ok(params.to_string())
+}
+
+/// Percent-decode a key or value, with `+` for a space.
+pub(crate) fn decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = text
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("Invalid percent-encoding in `{}`", text))?;
+                decoded.push(byte);
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| format!("`{}` is not UTF-8", text))
+}
+
+/// Split `filter[sizes][]` into `filter`, `sizes` and a push.
+fn parse_key(key: &str) -> Result<Vec<Segment>, String> {
+    let malformed = || format!("Malformed query parameter `{}`", key);
+    let (name, mut rest) = key
+        .split_once('[')
+        .map_or((key, ""), |(name, _)| (name, &key[name.len()..]));
+    if name.is_empty() {
+        return Err(malformed());
+    }
+    let mut path = vec![Segment::Key(name.to_string())];
+    while !rest.is_empty() {
+        let (inner, after) = rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .ok_or_else(malformed)?;
+        path.push(match inner {
+            "" => Segment::Push,
+            inner => Segment::Key(inner.to_string()),
+        });
+        rest = after;
+    }
+    if path.len() > MAX_DEPTH {
+        return Err(format!("Query parameter `{}` is nested too deeply", key));
+    }
+    // Elements pushed onto an array are values, not objects
+    if path[..path.len() - 1]
+        .iter()
+        .any(|segment| matches!(segment, Segment::Push))
+    {
+        return Err(malformed());
+    }
+    Ok(path)
+}
+
+/// Store `value` at `path` in `target`, failing if something else is already there.
+fn insert(target: &mut Value, path: &[Segment], value: String) -> Result<(), ()> {
+    match path.split_first() {
+        None => match target {
+            Value::Null => *target = Value::String(value),
+            Value::String(_) => {
+                let first = target.take();
+                *target = Value::Array(vec![first, Value::String(value)]);
+            }
+            Value::Array(values) => values.push(Value::String(value)),
+            _ => return Err(()),
+        },
+        Some((Segment::Push, _)) => match target {
+            Value::Null => *target = Value::Array(vec![Value::String(value)]),
+            Value::String(_) => {
+                let first = target.take();
+                *target = Value::Array(vec![first, Value::String(value)]);
+            }
+            Value::Array(values) => values.push(Value::String(value)),
+            _ => return Err(()),
+        },
+        Some((Segment::Key(key), rest)) => {
+            if target.is_null() {
+                *target = Value::Object(Map::new());
+            }
+            let Value::Object(fields) = target else {
+                return Err(());
+            };
+            insert(
+                fields.entry(key.as_str()).or_insert(Value::Null),
+                rest,
+                value,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Turn objects keyed `0`, `1`, ... into arrays, ordered by index.
+fn index_arrays(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            fields.values_mut().for_each(index_arrays);
+            if fields.is_empty() || !fields.keys().all(|key| key.parse::<usize>().is_ok()) {
+                return;
+            }
+            let mut elements: Vec<(usize, Value)> = std::mem::take(fields)
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.parse().ok()?, value)))
+                .collect();
+            elements.sort_by_key(|(index, _)| *index);
+            *value = Value::Array(elements.into_iter().map(|(_, value)| value).collect());
+        }
+        Value::Array(values) => values.iter_mut().for_each(index_arrays),
+        _ => {}
+    }
+}
+
+/// Deserialize parameters from a query string, reading strings as the types ask.
+pub(crate) fn deserialize<T: DeserializeOwned>(params: Value) -> Result<T, serde_json::Error> {
+    T::deserialize(Lenient(params))
+}
+
+/// A value from a query string, whose strings also deserialize as numbers and booleans, and
+/// whose single values also deserialize as sequences of one.
+struct Lenient(Value);
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for Lenient {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_seq<'de, V: Visitor<'de>>(
+    values: Vec<Value>,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    let mut seq = SeqDeserializer::new(values.into_iter().map(Lenient));
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+fn visit_map<'de, V: Visitor<'de>>(
+    fields: Map<String, Value>,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    let mut map =
+        MapDeserializer::new(fields.into_iter().map(|(key, value)| (key, Lenient(value))));
+    let value = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(value)
+}
+
+/// Scalars are read from strings holding their JSON.
+macro_rules! deserialize_scalars {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            let value = match self.0 {
+                Value::String(text) => match serde_json::from_str(&text) {
+                    Ok(scalar @ (Value::Number(_) | Value::Bool(_))) => scalar,
+                    _ => Value::String(text),
+                },
+                value => value,
+            };
+            value.$method(visitor)
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Array(values) => visit_seq(values, visitor),
+            Value::Object(fields) => visit_map(fields, visitor),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_scalars! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(Lenient(value)),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Array(values) => visit_seq(values, visitor),
+            value => visit_seq(vec![value], visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    fn parse(query: &str) -> Value {
+        serde_json::from_str(&to_json(query).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_query_strings_become_json() {
+        assert_eq!(parse(""), json!({}));
+        assert_eq!(
+            parse("text=red+shoes&tag=sale&tag=new&note=50%25%20off&flag"),
+            json!({"text": "red shoes", "tag": ["sale", "new"], "note": "50% off", "flag": ""})
+        );
+        assert_eq!(
+            parse("filter[min]=10&filter[sizes][]=42&filter[sizes][]=43&one[]=x"),
+            json!({"filter": {"min": "10", "sizes": ["42", "43"]}, "one": ["x"]})
+        );
+        assert_eq!(
+            parse("rows[1][id]=b&rows[0][id]=a"),
+            json!({"rows": [{"id": "a"}, {"id": "b"}]})
+        );
+    }
+
+    #[test]
+    fn test_malformed_query_strings() {
+        assert!(to_json("a=%zz").is_err());
+        assert!(to_json("a=%+1").is_err());
+        assert!(to_json("a[b=1").is_err());
+        assert!(to_json("[a]=1").is_err());
+        assert!(to_json("a[][b]=1").is_err());
+        assert_eq!(
+            to_json("a=1&a[b]=2").unwrap_err(),
+            "Query parameter `a[b]` conflicts with another"
+        );
+        let deep = format!("a{}=1", "[b]".repeat(100));
+        assert!(to_json(&deep).unwrap_err().contains("nested too deeply"));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Filter {
+        min: u32,
+        exact: Option<bool>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Order {
+        Newest,
+        Cheapest,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Search {
+        text: String,
+        limit: u32,
+        price: f64,
+        tags: Vec<String>,
+        sizes: Vec<u8>,
+        filter: Filter,
+        order: Order,
+        page: Option<u32>,
+    }
+
+    #[test]
+    fn test_strings_are_read_as_the_types_ask() {
+        let query = "text=10&limit=10&price=9.5&tags=sale&sizes=42&sizes=43&filter[min]=3\
+                     &filter[exact]=true&order=cheapest";
+        let params: Value = serde_json::from_str(&to_json(query).unwrap()).unwrap();
+        let search: Search = deserialize(params).unwrap();
+        assert_eq!(
+            search,
+            Search {
+                text: "10".to_string(),
+                limit: 10,
+                price: 9.5,
+                tags: vec!["sale".to_string()],
+                sizes: vec![42, 43],
+                filter: Filter {
+                    min: 3,
+                    exact: Some(true),
+                },
+                order: Order::Cheapest,
+                page: None,
+            }
+        );
+
+        let error = deserialize::<Filter>(json!({"min": "many"})).unwrap_err();
+        assert!(error.to_string().contains("expected u32"));
+    }
+}
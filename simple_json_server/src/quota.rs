@@ -0,0 +1,328 @@
+//! Per-client daily quotas and usage accounting, for metered public APIs.
+//!
+//! Callers identify themselves with an API key in the `X-Api-Key` header (the header name is
+//! configurable).  The server counts each client's calls and bytes (request plus response
+//! bodies) per UTC day, and once a client has used up its daily quota further calls are answered
+//! `429 Too Many Requests`, with a `Retry-After` header pointing at midnight UTC when the
+//! counters reset.
+//!
+//! ```rust
+//! use simple_json_server::quota::Quotas;
+//! use simple_json_server::ServerOptions;
+//!
+//! let quotas = Quotas::new()
+//!     .daily_requests(10_000)
+//!     .daily_bytes(50_000_000)
+//!     .require_client(true);
+//! let options = ServerOptions::new(8080).quotas(quotas.clone());
+//!
+//! // Later
+//! if let Some(usage) = quotas.usage("key-123") {
+//!     println!("{} calls, {} bytes today", usage.requests, usage.bytes);
+//! }
+//! ```
+//!
+//! Any key is counted by default, so a caller could make up new keys to dodge its quota.  For
+//! a public API, name the keys to accept with [`Quotas::allowed_clients`] (or check them with
+//! [`Quotas::validate_client`]): calls with other keys are answered `401 Unauthorized` and never
+//! counted.  At most [`Quotas::max_clients`] keys (100,000 by default) are tracked a day; calls
+//! from further new keys are answered `429` until the counters reset.
+//!
+//! With an admin token configured, `GET /__admin/usage` returns every client's usage for the
+//! day as JSON.  Quotas apply to HTTP calls; WebSocket calls carry no per-call headers and are
+//! not metered.
+
+use crate::options::Shared;
+use hyper::HeaderMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// One client's usage during the current day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    /// Calls admitted.
+    pub requests: u64,
+    /// Request and response body bytes of the admitted calls.
+    pub bytes: u64,
+    /// Calls turned away for being over quota.
+    pub rejected: u64,
+}
+
+/// Why a call was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QuotaError {
+    /// The call carried no client id and one is required.
+    MissingClient,
+    /// The call's client id was not accepted.
+    UnknownClient,
+    /// As many clients as allowed are already tracked today; carries the seconds until the
+    /// counters reset.
+    TooManyClients(u64),
+    /// The client has used up its quota; carries the seconds until it resets.
+    Exceeded(u64),
+}
+
+/// Daily quota settings, plus the usage they are applied to.  Clones share the same usage, so
+/// keep one to read the numbers of a running server.
+#[derive(Debug, Clone)]
+pub struct Quotas {
+    header: String,
+    daily_requests: Option<u64>,
+    daily_bytes: Option<u64>,
+    require_client: bool,
+    validate_client: Option<ClientValidator>,
+    max_clients: usize,
+    usage: Arc<Mutex<DailyUsage>>,
+}
+
+type ClientValidator = Shared<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct DailyUsage {
+    day: u64,
+    clients: HashMap<String, Usage>,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Quotas {
+    /// Count usage by the `X-Api-Key` header without limiting it.
+    pub fn new() -> Self {
+        Self {
+            header: "x-api-key".to_string(),
+            daily_requests: None,
+            daily_bytes: None,
+            require_client: false,
+            validate_client: None,
+            max_clients: 100_000,
+            usage: Arc::default(),
+        }
+    }
+
+    /// The header identifying the client (default `X-Api-Key`).
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into().to_ascii_lowercase();
+        self
+    }
+
+    /// How many calls each client may make per UTC day.
+    pub fn daily_requests(mut self, requests: u64) -> Self {
+        self.daily_requests = Some(requests);
+        self
+    }
+
+    /// How many request and response body bytes each client may transfer per UTC day.  A call
+    /// is admitted while the client is under the limit, so the last call may take it over.
+    pub fn daily_bytes(mut self, bytes: u64) -> Self {
+        self.daily_bytes = Some(bytes);
+        self
+    }
+
+    /// Reject calls without a client id with `401 Unauthorized` (by default they are served
+    /// and not metered).
+    pub fn require_client(mut self, require: bool) -> Self {
+        self.require_client = require;
+        self
+    }
+
+    /// Only accept the client ids `validate` returns `true` for; calls with other ids are
+    /// answered `401 Unauthorized` before anything is counted.
+    pub fn validate_client(
+        mut self,
+        validate: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.validate_client = Some(Shared(Arc::new(validate)));
+        self
+    }
+
+    /// Only accept the client ids in `clients`.  See [`Quotas::validate_client`].
+    pub fn allowed_clients<I, S>(self, clients: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let clients: HashSet<String> = clients.into_iter().map(Into::into).collect();
+        self.validate_client(move |client| clients.contains(client))
+    }
+
+    /// How many clients to track per UTC day (default 100,000).  Calls from further new clients
+    /// are answered `429 Too Many Requests` until the counters reset.
+    pub fn max_clients(mut self, clients: usize) -> Self {
+        self.max_clients = clients;
+        self
+    }
+
+    /// `client`'s usage today, if it has made any calls.
+    pub fn usage(&self, client: &str) -> Option<Usage> {
+        self.today(now_secs()).clients.get(client).copied()
+    }
+
+    /// Every client's usage today, ordered by client id.
+    pub fn all_usage(&self) -> BTreeMap<String, Usage> {
+        let usage = self.today(now_secs());
+        usage
+            .clients
+            .iter()
+            .map(|(client, usage)| (client.clone(), *usage))
+            .collect()
+    }
+
+    /// The header calls must identify their client with, if they must.
+    pub(crate) fn required_header(&self) -> Option<&str> {
+        self.require_client.then_some(self.header.as_str())
+    }
+
+    /// The client id sent in `headers`, if any.
+    pub(crate) fn client(&self, headers: &HeaderMap) -> Option<String> {
+        let id = headers.get(&self.header)?.to_str().ok()?.trim();
+        (!id.is_empty()).then(|| id.to_string())
+    }
+
+    /// Count a call by `client` if it is within its quota.
+    pub(crate) fn admit(&self, client: Option<&str>) -> Result<(), QuotaError> {
+        self.admit_at(client, now_secs())
+    }
+
+    fn admit_at(&self, client: Option<&str>, now: u64) -> Result<(), QuotaError> {
+        let Some(client) = client else {
+            return match self.require_client {
+                true => Err(QuotaError::MissingClient),
+                false => Ok(()),
+            };
+        };
+        if let Some(validate) = &self.validate_client {
+            if !(validate.0)(client) {
+                return Err(QuotaError::UnknownClient);
+            }
+        }
+        let reset_secs = SECONDS_PER_DAY - now % SECONDS_PER_DAY;
+        let mut today = self.today(now);
+        if !today.clients.contains_key(client) && today.clients.len() >= self.max_clients {
+            return Err(QuotaError::TooManyClients(reset_secs));
+        }
+        let usage = today.clients.entry(client.to_string()).or_default();
+        let over = self.daily_requests.is_some_and(|max| usage.requests >= max)
+            || self.daily_bytes.is_some_and(|max| usage.bytes >= max);
+        if over {
+            usage.rejected += 1;
+            return Err(QuotaError::Exceeded(reset_secs));
+        }
+        usage.requests += 1;
+        Ok(())
+    }
+
+    /// Add the bytes of an admitted call to `client`'s usage.
+    pub(crate) fn record_bytes(&self, client: &str, bytes: usize) {
+        // Only admitted clients are tracked; one dropped at midnight starts afresh
+        if let Some(usage) = self.today(now_secs()).clients.get_mut(client) {
+            usage.bytes += bytes as u64;
+        }
+    }
+
+    /// The usage table, emptied first if the day has changed since it was last used.
+    fn today(&self, now: u64) -> std::sync::MutexGuard<'_, DailyUsage> {
+        let mut usage = self.usage.lock().unwrap();
+        let day = now / SECONDS_PER_DAY;
+        if usage.day != day {
+            usage.day = day;
+            usage.clients.clear();
+        }
+        usage
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quotas_reset_daily() {
+        let quotas = Quotas::new().daily_requests(2);
+        let noon = 100 * SECONDS_PER_DAY + SECONDS_PER_DAY / 2;
+        assert_eq!(quotas.admit_at(Some("a"), noon), Ok(()));
+        assert_eq!(quotas.admit_at(Some("a"), noon), Ok(()));
+        assert_eq!(
+            quotas.admit_at(Some("a"), noon),
+            Err(QuotaError::Exceeded(SECONDS_PER_DAY / 2))
+        );
+        // Other clients have their own quota, and anonymous calls are not metered
+        assert_eq!(quotas.admit_at(Some("b"), noon), Ok(()));
+        assert_eq!(quotas.admit_at(None, noon), Ok(()));
+
+        let usage = quotas.today(noon).clients["a"];
+        assert_eq!((usage.requests, usage.rejected), (2, 1));
+
+        assert_eq!(quotas.admit_at(Some("a"), noon + SECONDS_PER_DAY), Ok(()));
+    }
+
+    #[test]
+    fn test_byte_quota_and_required_client() {
+        let quotas = Quotas::new().daily_bytes(100).require_client(true);
+        assert_eq!(quotas.admit(None), Err(QuotaError::MissingClient));
+        assert_eq!(quotas.admit(Some("a")), Ok(()));
+        quotas.record_bytes("a", 150);
+        assert!(matches!(
+            quotas.admit(Some("a")),
+            Err(QuotaError::Exceeded(_))
+        ));
+        assert_eq!(quotas.usage("a").unwrap().bytes, 150);
+        assert_eq!(quotas.all_usage().len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_clients_are_refused_uncounted() {
+        let quotas = Quotas::new().allowed_clients(["alice", "bob"]);
+        assert_eq!(quotas.admit(Some("alice")), Ok(()));
+        assert_eq!(
+            quotas.admit(Some("mallory")),
+            Err(QuotaError::UnknownClient)
+        );
+        quotas.record_bytes("mallory", 10);
+        assert_eq!(quotas.usage("mallory"), None);
+        assert_eq!(quotas.all_usage().len(), 1);
+
+        let quotas = Quotas::new().validate_client(|client| client.starts_with("key-"));
+        assert_eq!(quotas.admit(Some("key-1")), Ok(()));
+        assert_eq!(quotas.admit(Some("other")), Err(QuotaError::UnknownClient));
+    }
+
+    #[test]
+    fn test_tracked_clients_are_capped() {
+        let quotas = Quotas::new().max_clients(2);
+        let noon = 100 * SECONDS_PER_DAY + SECONDS_PER_DAY / 2;
+        assert_eq!(quotas.admit_at(Some("a"), noon), Ok(()));
+        assert_eq!(quotas.admit_at(Some("b"), noon), Ok(()));
+        assert_eq!(
+            quotas.admit_at(Some("c"), noon),
+            Err(QuotaError::TooManyClients(SECONDS_PER_DAY / 2))
+        );
+        // Clients already tracked carry on, and the cap resets with the counters
+        assert_eq!(quotas.admit_at(Some("a"), noon), Ok(()));
+        assert_eq!(quotas.today(noon).clients.len(), 2);
+        assert_eq!(quotas.admit_at(Some("c"), noon + SECONDS_PER_DAY), Ok(()));
+    }
+
+    #[test]
+    fn test_client_header() {
+        let quotas = Quotas::new().header("X-Client-Id");
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client-id", "acme".parse().unwrap());
+        assert_eq!(quotas.client(&headers).as_deref(), Some("acme"));
+        assert_eq!(Quotas::new().client(&headers), None);
+    }
+}
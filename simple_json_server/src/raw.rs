@@ -0,0 +1,158 @@
+//! Parameters kept as raw JSON text, deserialized only if and when the method needs them.
+//!
+//! A method that mostly passes a payload on (a gateway, a relay, a queue) gains nothing from
+//! deserializing it into Rust types only to serialize it again.  A [`RawJson`] parameter keeps the
+//! caller's JSON text exactly as sent, numbers and all; [`RawJson::parse`] deserializes it on
+//! demand, and serializing it writes the text back unchanged.
+//!
+//! ```rust
+//! use serde::Deserialize;
+//! use simple_json_server::raw::RawJson;
+//! use simple_json_server::{actor, Actor, ActorRef};
+//!
+//! #[derive(Deserialize)]
+//! struct Order {
+//!     total: f64,
+//! }
+//!
+//! struct Relay {
+//!     fulfilment: ActorRef,
+//! }
+//!
+//! #[actor]
+//! impl Relay {
+//!     /// Pass an order on, only looking inside the urgent ones
+//!     pub async fn submit(&self, urgent: bool, order: RawJson<Order>) -> Result<String, String> {
+//!         if urgent {
+//!             let order = order.parse().map_err(|e| e.to_string())?;
+//!             log::info!("urgent order of {}", order.total);
+//!         }
+//!         self.fulfilment
+//!             .call_raw("submit", order.get().to_string())
+//!             .await
+//!             .map_err(|e| e.to_string())
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! `#[max_size(order = N)]` limits the length of the text.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// JSON text that deserializes to a `T` (by default any JSON value), kept as sent.
+pub struct RawJson<T = serde_json::Value> {
+    raw: Box<RawValue>,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> RawJson<T> {
+    /// Wrap JSON text already known to be valid.
+    pub fn from_raw(raw: Box<RawValue>) -> Self {
+        Self {
+            raw,
+            value: PhantomData,
+        }
+    }
+
+    /// The JSON text, as the caller sent it.
+    pub fn get(&self) -> &str {
+        self.raw.get()
+    }
+
+    /// The JSON text, for passing on.
+    pub fn into_raw(self) -> Box<RawValue> {
+        self.raw
+    }
+}
+
+impl<T: Serialize> RawJson<T> {
+    /// Serialize `value` to JSON text.
+    pub fn new(value: &T) -> Result<Self, serde_json::Error> {
+        serde_json::value::to_raw_value(value).map(Self::from_raw)
+    }
+}
+
+impl<T: DeserializeOwned> RawJson<T> {
+    /// Deserialize the text into a `T`.
+    pub fn parse(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_str(self.raw.get())
+    }
+}
+
+impl<T> Clone for RawJson<T> {
+    fn clone(&self) -> Self {
+        Self::from_raw(self.raw.clone())
+    }
+}
+
+impl<T> fmt::Debug for RawJson<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RawJson").field(&self.raw.get()).finish()
+    }
+}
+
+impl<T> fmt::Display for RawJson<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.raw.get())
+    }
+}
+
+impl<T> AsRef<[u8]> for RawJson<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.raw.get().as_bytes()
+    }
+}
+
+impl<T> Serialize for RawJson<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for RawJson<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Box::<RawValue>::deserialize(deserializer).map(Self::from_raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Envelope {
+        id: u32,
+        payload: RawJson<Vec<f64>>,
+    }
+
+    #[test]
+    fn test_text_is_kept_as_sent() {
+        let text = r#"{"id": 7, "payload": [1.50, 2e3 , 3]}"#;
+        let envelope: Envelope = serde_json::from_str(text).unwrap();
+        assert_eq!(envelope.payload.get(), "[1.50, 2e3 , 3]");
+        assert_eq!(envelope.payload.parse().unwrap(), vec![1.5, 2000.0, 3.0]);
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            r#"{"id":7,"payload":[1.50, 2e3 , 3]}"#
+        );
+    }
+
+    #[test]
+    fn test_from_values() {
+        let raw = RawJson::new(&json!({"a": [1, 2]})).unwrap();
+        assert_eq!(raw.to_string(), r#"{"a":[1,2]}"#);
+        assert_eq!(raw.as_ref().len(), 11);
+
+        // Parsed values have their text reserialized
+        let envelope: Envelope =
+            serde_json::from_value(json!({"id": 1, "payload": [1.0]})).unwrap();
+        assert_eq!(envelope.payload.get(), "[1.0]");
+        assert!(envelope.payload.clone().parse().is_ok());
+    }
+}
@@ -0,0 +1,377 @@
+//! A lightweight naming registry for multi-service deployments.
+//!
+//! Addresses in `simple_json_server` are well known, which keeps cross-language clients simple
+//! but makes topologies with many services brittle.  The [`Registry`] is itself an actor: other
+//! servers register their name, methods and address with it at startup, keep the registration
+//! alive with heartbeats, and are forgotten once their TTL lapses.  Clients resolve names to
+//! URLs (or straight to an [`ActorRef`]) through a [`RegistryClient`].
+//!
+//! ```rust,no_run
+//! use simple_json_server::registry::{Registry, RegistryClient};
+//! use simple_json_server::{Actor, ServerOptions};
+//! # use simple_json_server::actor;
+//! # #[derive(Clone)]
+//! # struct Calculator;
+//! # #[actor]
+//! # impl Calculator {
+//! #     pub async fn add(&self, a: f64, b: f64) -> f64 { a + b }
+//! # }
+//!
+//! # async fn example() -> Result<(), simple_json_server::ClientError> {
+//! // The registry runs like any other actor
+//! Registry::new().create(7000);
+//!
+//! // Servers announce themselves when they start
+//! let registry = RegistryClient::new("http://127.0.0.1:7000");
+//! Calculator.create_with(
+//!     ServerOptions::new(8080).register(registry.clone(), "calculator", "http://127.0.0.1:8080"),
+//! );
+//!
+//! // Clients look them up by name
+//! let calculator = registry.connect("calculator").await?;
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use crate::{actor, ActorRef, ClientError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A live registration as reported by the registry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Registration {
+    /// The logical name clients resolve.
+    pub name: String,
+    /// The base URL the actor is served at.
+    pub address: String,
+    /// The methods the actor exposes.
+    pub methods: Vec<String>,
+    /// How long the registration lives without a heartbeat, in milliseconds.
+    pub ttl_ms: u64,
+}
+
+struct Entry {
+    registration: Registration,
+    expires: Instant,
+}
+
+/// The registry actor.  Several instances of a service may register under the same name with
+/// different addresses.
+#[derive(Clone)]
+pub struct Registry {
+    entries: Arc<Mutex<HashMap<String, Vec<Entry>>>>,
+    max_ttl: Duration,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            entries: Arc::default(),
+            max_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl Registry {
+    /// Create an empty registry accepting TTLs of up to an hour.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse registrations with a TTL longer than `ttl`.
+    pub fn max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// When a registration with a TTL of `ttl_ms` made or renewed at `now` expires, or `None`
+    /// if the TTL is longer than the registry allows.
+    fn expiry(&self, now: Instant, ttl_ms: u64) -> Option<Instant> {
+        let ttl = Duration::from_millis(ttl_ms);
+        if ttl > self.max_ttl {
+            return None;
+        }
+        now.checked_add(ttl)
+    }
+
+    /// Live registrations for `name`, dropping any that have expired.
+    fn live(&self, name: &str) -> Vec<Registration> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        let Some(instances) = entries.get_mut(name) else {
+            return Vec::new();
+        };
+        instances.retain(|entry| entry.expires > now);
+        instances
+            .iter()
+            .map(|entry| entry.registration.clone())
+            .collect()
+    }
+}
+
+#[actor]
+impl Registry {
+    /// Register (or re-register) an instance of `name` served at `address`.  Fails if `ttl_ms`
+    /// is longer than the registry allows (see [`Registry::max_ttl`]).
+    pub async fn register(
+        &self,
+        name: String,
+        address: String,
+        methods: Vec<String>,
+        ttl_ms: u64,
+    ) -> Result<Registration, String> {
+        let expires = self.expiry(Instant::now(), ttl_ms).ok_or_else(|| {
+            format!(
+                "A TTL of {} ms is longer than the {} ms allowed",
+                ttl_ms,
+                self.max_ttl.as_millis()
+            )
+        })?;
+        let registration = Registration {
+            name: name.clone(),
+            address: address.clone(),
+            methods,
+            ttl_ms,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let instances = entries.entry(name).or_default();
+        instances.retain(|entry| entry.registration.address != address);
+        instances.push(Entry {
+            registration: registration.clone(),
+            expires,
+        });
+        Ok(registration)
+    }
+
+    /// Extend the lifetime of a registration.  Returns false if it is unknown or already
+    /// expired, in which case the instance should register again.
+    pub async fn heartbeat(&self, name: String, address: String) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        let entry = entries.get_mut(&name).and_then(|instances| {
+            instances
+                .iter_mut()
+                .find(|entry| entry.registration.address == address && entry.expires > now)
+        });
+        let Some(entry) = entry else {
+            return false;
+        };
+        match self.expiry(now, entry.registration.ttl_ms) {
+            Some(expires) => {
+                entry.expires = expires;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a registration.  Returns false if it was not registered.
+    pub async fn deregister(&self, name: String, address: String) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(instances) = entries.get_mut(&name) else {
+            return false;
+        };
+        let before = instances.len();
+        instances.retain(|entry| entry.registration.address != address);
+        before != instances.len()
+    }
+
+    /// The addresses of all live instances of `name`.
+    pub async fn resolve(&self, name: String) -> Vec<String> {
+        self.live(&name)
+            .into_iter()
+            .map(|registration| registration.address)
+            .collect()
+    }
+
+    /// The full registrations of all live instances of `name`.
+    pub async fn lookup(&self, name: String) -> Vec<Registration> {
+        self.live(&name)
+    }
+
+    /// Every live registration.
+    pub async fn list(&self) -> Vec<Registration> {
+        let names: Vec<String> = self.entries.lock().unwrap().keys().cloned().collect();
+        names.iter().flat_map(|name| self.live(name)).collect()
+    }
+}
+
+/// Talks to a [`Registry`] served at a known URL.
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    registry: ActorRef,
+}
+
+impl RegistryClient {
+    /// Create a client for the registry served at `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            registry: ActorRef::new(url),
+        }
+    }
+
+    /// Register an instance; see [`Registry::register`].
+    pub async fn register(&self, registration: &Registration) -> Result<(), ClientError> {
+        self.registry
+            .call::<_, Result<Registration, String>>("register", registration)
+            .await?
+            .map(|_| ())
+            .map_err(|e| ClientError::Transport(format!("The registry refused: {}", e)))
+    }
+
+    /// Send a heartbeat; see [`Registry::heartbeat`].
+    pub async fn heartbeat(&self, name: &str, address: &str) -> Result<bool, ClientError> {
+        self.registry
+            .call("heartbeat", &json!({"name": name, "address": address}))
+            .await
+    }
+
+    /// Remove a registration; see [`Registry::deregister`].
+    pub async fn deregister(&self, name: &str, address: &str) -> Result<bool, ClientError> {
+        self.registry
+            .call("deregister", &json!({"name": name, "address": address}))
+            .await
+    }
+
+    /// The addresses of all live instances of `name`.
+    pub async fn resolve(&self, name: &str) -> Result<Vec<String>, ClientError> {
+        self.registry.call("resolve", &json!({"name": name})).await
+    }
+
+    /// The full registrations of all live instances of `name`.
+    pub async fn lookup(&self, name: &str) -> Result<Vec<Registration>, ClientError> {
+        self.registry.call("lookup", &json!({"name": name})).await
+    }
+
+    /// Resolve `name` and return a handle to the first live instance.
+    pub async fn connect(&self, name: &str) -> Result<ActorRef, ClientError> {
+        self.resolve(name)
+            .await?
+            .into_iter()
+            .next()
+            .map(ActorRef::new)
+            .ok_or_else(|| ClientError::Transport(format!("No live instances of {}", name)))
+    }
+
+    /// Register `registration` and keep it alive until the process exits, re-registering
+    /// whenever the registry has forgotten it (e.g. after a registry restart).
+    pub(crate) async fn keep_registered(self, registration: Registration) {
+        let interval =
+            Duration::from_millis(registration.ttl_ms / 3).max(Duration::from_millis(10));
+        let mut registered = false;
+        loop {
+            if registered {
+                match self
+                    .heartbeat(&registration.name, &registration.address)
+                    .await
+                {
+                    Ok(alive) => registered = alive,
+                    Err(e) => {
+                        log::warn!("Registry heartbeat for {} failed: {}", registration.name, e)
+                    }
+                }
+            }
+            if !registered {
+                match self.register(&registration).await {
+                    Ok(()) => {
+                        log::info!(
+                            "Registered {} at {} with the registry",
+                            registration.name,
+                            registration.address
+                        );
+                        registered = true;
+                    }
+                    Err(e) => log::warn!("Failed to register {}: {}", registration.name, e),
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Actor;
+
+    #[tokio::test]
+    async fn test_register_and_resolve() {
+        let registry = Registry::new();
+        let message =
+            r#"{"name": "calc", "address": "http://a", "methods": ["add"], "ttl_ms": 60000}"#;
+        registry.dispatch("register", message).await;
+
+        let result = registry.dispatch("resolve", r#"{"name": "calc"}"#).await;
+        assert_eq!(result, r#"["http://a"]"#);
+        let result = registry.dispatch("resolve", r#"{"name": "other"}"#).await;
+        assert_eq!(result, "[]");
+    }
+
+    #[tokio::test]
+    async fn test_registrations_expire_without_heartbeats() {
+        let registry = Registry::new();
+        registry
+            .register("calc".into(), "http://a".into(), vec![], 30)
+            .await
+            .unwrap();
+        assert!(registry.heartbeat("calc".into(), "http://a".into()).await);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(registry.resolve("calc".into()).await.is_empty());
+        assert!(!registry.heartbeat("calc".into(), "http://a".into()).await);
+    }
+
+    #[tokio::test]
+    async fn test_re_registering_replaces_instance() {
+        let registry = Registry::new();
+        registry
+            .register("calc".into(), "http://a".into(), vec!["add".into()], 60000)
+            .await
+            .unwrap();
+        registry
+            .register("calc".into(), "http://b".into(), vec!["add".into()], 60000)
+            .await
+            .unwrap();
+        registry
+            .register("calc".into(), "http://a".into(), vec!["sub".into()], 60000)
+            .await
+            .unwrap();
+
+        let instances = registry.lookup("calc".into()).await;
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].methods, vec!["sub".to_string()]);
+        assert!(registry.deregister("calc".into(), "http://b".into()).await);
+        assert_eq!(registry.list().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_huge_ttls_are_refused() {
+        let registry = Registry::new().max_ttl(Duration::from_secs(60));
+        let message = format!(
+            r#"{{"name": "calc", "address": "http://a", "methods": [], "ttl_ms": {}}}"#,
+            u64::MAX
+        );
+        let result = registry.dispatch("register", &message).await;
+        assert!(
+            result.contains("longer than the 60000 ms allowed"),
+            "{}",
+            result
+        );
+        assert!(registry
+            .register("calc".into(), "http://a".into(), vec![], 60_001)
+            .await
+            .is_err());
+
+        // The registry still serves
+        registry
+            .register("calc".into(), "http://a".into(), vec![], 60_000)
+            .await
+            .unwrap();
+        assert!(registry.heartbeat("calc".into(), "http://a".into()).await);
+        assert_eq!(registry.resolve("calc".into()).await, ["http://a"]);
+    }
+}
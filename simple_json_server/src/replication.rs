@@ -0,0 +1,306 @@
+//! Primary/replica replication for read-heavy deployments.
+//!
+//! The primary journals every `#[write]` call (see [`crate::journal`]) and streams the journal
+//! over a WebSocket on a separate replication port.  Replicas connect to it, replay each entry
+//! against their own copy of the actor, and serve `#[read]` methods locally.  Replicas are
+//! read-only: calls to write methods are rejected with `403 Forbidden`, so clients send writes
+//! to the primary.
+//!
+//! Replication is asynchronous; a replica may briefly lag behind the primary.  If the
+//! connection drops the replica reconnects and resumes from the last entry it applied.
+//!
+//! Each entry is applied inside a [`RequestContext`](crate::RequestContext) carrying the
+//! headers and cookies the call's `#[from_header]` and `#[from_cookie]` parameters were bound
+//! to on the primary, so those parameters take the same values on the replica.
+//!
+//! A snapshot taken on the primary drops the journal entries it includes.  A replica that
+//! needs entries which have been dropped restores the latest snapshot in its own
+//! [snapshot store](crate::ServerOptions::snapshots), which should be the primary's, and
+//! follows on from there.  A replica that restores a snapshot on start does the same.
+//!
+//! The replication port speaks plain WebSocket and should only be reachable from the replicas.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use simple_json_server::replication::Replication;
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//! # #[derive(Clone, Default)]
+//! # struct Catalog;
+//! # #[actor]
+//! # impl Catalog {
+//! #     #[read]
+//! #     pub async fn get(&self, id: u32) -> Option<String> { None }
+//! #     #[write]
+//! #     pub async fn put(&self, id: u32, name: String) {}
+//! # }
+//!
+//! # async fn example() {
+//! // The primary serves clients on 8080 and replicas on 9080
+//! Catalog::default().create_with(ServerOptions::new(8080).replication(Replication::primary(9080)));
+//!
+//! // Each replica serves reads on its own port
+//! Catalog::default()
+//!     .create_with(ServerOptions::new(8081).replication(Replication::replica("ws://primary:9080")));
+//! # }
+//! # fn main() {}
+//! ```
+
+use crate::handle::Lifecycle;
+use crate::journal::{Journal, JournalEntry};
+use crate::server::{self, ServerState};
+use crate::Actor;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+/// The role a server plays in replication.
+#[derive(Debug, Clone)]
+pub struct Replication {
+    role: Role,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Role {
+    Primary { port: u16 },
+    Replica { primary: String },
+}
+
+/// Sent by a replica when it connects: the first journal entry it needs.
+#[derive(Debug, Serialize, Deserialize)]
+struct Subscribe {
+    from: u64,
+}
+
+/// Sent by the primary: the next journal entry, or word that the entries the replica needs
+/// have been dropped (see [`Journal::truncate_before`]).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Update {
+    Entry(JournalEntry),
+    Truncated { truncated_before: u64 },
+}
+
+impl Replication {
+    /// Act as the primary, streaming the journal to replicas that connect on `port`, on the
+    /// host (and with the IPv6 settings) the server listens on.
+    pub fn primary(port: u16) -> Self {
+        Self {
+            role: Role::Primary { port },
+        }
+    }
+
+    /// Act as a read-only replica of the primary whose replication port is at `url`, e.g.
+    /// `ws://10.0.0.1:9080`.
+    pub fn replica(url: impl Into<String>) -> Self {
+        Self {
+            role: Role::Replica {
+                primary: url.into(),
+            },
+        }
+    }
+
+    pub(crate) fn role(&self) -> &Role {
+        &self.role
+    }
+}
+
+/// Serve the journal to replicas connecting to `listener`, which accepts connections on
+/// `addrs`, until the server shuts down.  Each replica is streamed to by a background task of
+/// `lifecycle`, so shutting down closes its connection.
+pub(crate) async fn serve_primary(
+    journal: Journal,
+    listener: TcpListener,
+    addrs: Vec<SocketAddr>,
+    lifecycle: Arc<Lifecycle>,
+) {
+    let urls: Vec<String> = addrs.iter().map(|addr| format!("ws://{}", addr)).collect();
+    log::info!("Replication primary listening on {}", urls.join(" and "));
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::error!("Failed to accept replica connection: {}", e);
+                continue;
+            }
+        };
+        let journal = journal.clone();
+        lifecycle.spawn_background(async move {
+            if let Err(e) = stream_journal(journal, stream).await {
+                log::warn!("Replica connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// Stream journal entries to one replica, starting where it asks to.
+async fn stream_journal(
+    journal: Journal,
+    stream: tokio::net::TcpStream,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sender, mut receiver) = ws_stream.split();
+
+    let mut next = loop {
+        match receiver.next().await {
+            Some(Ok(Message::Text(text))) => break serde_json::from_str::<Subscribe>(&text)?.from,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => return Ok(()),
+        }
+    };
+
+    let mut latest = journal.subscribe();
+    loop {
+        let entries = journal.entries_since(next);
+        let first = entries
+            .first()
+            .map_or_else(|| journal.first_seq(), |entry| entry.seq);
+        if first > next {
+            let truncated = Update::Truncated {
+                truncated_before: first,
+            };
+            sender
+                .send(Message::Text(serde_json::to_string(&truncated)?))
+                .await?;
+            return Ok(());
+        }
+        for entry in entries {
+            next = entry.seq + 1;
+            sender
+                .send(Message::Text(serde_json::to_string(&Update::Entry(entry))?))
+                .await?;
+        }
+        tokio::select! {
+            changed = latest.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            message = receiver.next() => match message {
+                None | Some(Ok(Message::Close(_))) => return Ok(()),
+                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Follow the primary at `url` from entry `next`, applying its journal to the actor, until the
+/// process exits.
+pub(crate) async fn follow<T>(state: Arc<ServerState<T>>, url: String, mut next: u64)
+where
+    T: Actor + Send + Sync + 'static,
+{
+    loop {
+        match apply_from(&state, &url, &mut next).await {
+            Ok(Some(truncated_before)) => {
+                match restore_snapshot(&state, truncated_before, &mut next).await {
+                    Ok(()) => continue,
+                    Err(e) => log::warn!("Replication from {} can't catch up: {}", url, e),
+                }
+            }
+            Ok(None) => log::warn!("Replication stream from {} ended", url),
+            Err(e) => log::warn!("Replication from {} failed: {}", url, e),
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Apply entries from the primary in order, advancing `next` past each one applied, until the
+/// stream ends or the primary reports it has dropped the entries before the one returned.
+async fn apply_from<T: Actor>(
+    state: &ServerState<T>,
+    url: &str,
+    next: &mut u64,
+) -> Result<Option<u64>, Box<dyn std::error::Error + Send + Sync>> {
+    let (ws_stream, _) = tokio_tungstenite::connect_async(url).await?;
+    let (mut sender, mut receiver) = ws_stream.split();
+    sender
+        .send(Message::Text(serde_json::to_string(&Subscribe {
+            from: *next,
+        })?))
+        .await?;
+    log::info!("Replicating from {} starting at entry {}", url, next);
+
+    while let Some(message) = receiver.next().await {
+        let Message::Text(text) = message? else {
+            continue;
+        };
+        let entry = match serde_json::from_str(&text)? {
+            Update::Entry(entry) => entry,
+            Update::Truncated { truncated_before } => return Ok(Some(truncated_before)),
+        };
+        if entry.seq < *next {
+            continue;
+        }
+        if entry.seq > *next {
+            return Err(format!("expected entry {} but received {}", next, entry.seq).into());
+        }
+        let (_shared, _exclusive);
+        if state.is_exclusive(&entry.method) {
+            _exclusive = state.mailbox.write().await;
+        } else {
+            _shared = state.mailbox.read().await;
+        }
+        match server::decrypt_params(state, &entry.method, &entry.params) {
+            Ok(params) => {
+                let ctx = server::replay_context(state, &entry);
+                ctx.scope(state.actor.dispatch(&entry.method, &params))
+                    .await;
+            }
+            Err(reply) => log::warn!(
+                "Failed to apply journal entry {} ({}): {}",
+                entry.seq,
+                entry.method,
+                reply.body
+            ),
+        }
+        *next += 1;
+    }
+    Ok(None)
+}
+
+/// Catch up from the latest snapshot in the replica's snapshot store, as the primary has
+/// dropped the journal entries before `truncated_before`.
+async fn restore_snapshot<T: Actor>(
+    state: &ServerState<T>,
+    truncated_before: u64,
+    next: &mut u64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(store) = &state.options.snapshot_store else {
+        return Err(format!(
+            "the primary has dropped entries before {}; give the replica the primary's snapshot \
+             store to catch up from",
+            truncated_before
+        )
+        .into());
+    };
+    let snapshot = store
+        .0
+        .latest()
+        .await?
+        .ok_or("there is no snapshot to catch up from")?;
+    if snapshot.journal_seq + 1 < truncated_before {
+        return Err(format!(
+            "the latest snapshot includes entries up to {}, but the primary has dropped \
+             entries before {}",
+            snapshot.journal_seq, truncated_before
+        )
+        .into());
+    }
+    let _paused = state.mailbox.write().await;
+    state.actor.restore_state(&snapshot.state)?;
+    *next = snapshot.journal_seq + 1;
+    log::info!(
+        "Restored the snapshot taken at {}, which includes entries up to {}",
+        snapshot.taken_at_ms,
+        snapshot.journal_seq
+    );
+    Ok(())
+}
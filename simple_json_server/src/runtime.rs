@@ -0,0 +1,108 @@
+//! The async runtime the crate's own tasks and I/O run on.
+//!
+//! Servers and [`ActorRef`](crate::ActorRef) calls are built on tokio: hyper, rustls,
+//! tungstenite and quinn all do their I/O through tokio's reactor and timers.  By default they
+//! run on the tokio runtime of the caller, so servers must be started from within one.
+//!
+//! With the `smol` or `async-std` feature, applications on those executors can serve and call
+//! actors too.  When no tokio runtime is current, the crate runs its tasks on a small runtime of
+//! its own, started on first use, and calls made from the application's executor are handed to
+//! it; their results come back through wakers, which every executor understands.  Inside a tokio
+//! runtime nothing changes.
+
+use std::future::Future;
+
+/// Spawning and driving futures that need tokio, whichever executor the caller is on.
+pub(crate) trait Runtime {
+    /// Run `task` in the background.
+    fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static;
+
+    /// Run `future`, which may use tokio's I/O and timers, and return its output to the
+    /// executor polling the returned future.
+    fn run<F>(&self, future: F) -> impl Future<Output = F::Output> + Send
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static;
+}
+
+/// The caller's tokio runtime.
+#[cfg(not(any(feature = "smol", feature = "async-std")))]
+pub(crate) struct Tokio;
+
+#[cfg(not(any(feature = "smol", feature = "async-std")))]
+impl Runtime for Tokio {
+    fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::runtime::Handle::current().spawn(task);
+    }
+
+    fn run<F>(&self, future: F) -> impl Future<Output = F::Output> + Send
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        future
+    }
+}
+
+/// The caller's tokio runtime if there is one, otherwise the crate's own.
+#[cfg(any(feature = "smol", feature = "async-std"))]
+pub(crate) struct Hosted;
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl Hosted {
+    fn handle() -> tokio::runtime::Handle {
+        use std::sync::OnceLock;
+        static HOSTED: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            return handle;
+        }
+        HOSTED
+            .get_or_init(|| {
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(2)
+                    .thread_name("simple-json-server")
+                    .enable_all()
+                    .build()
+                    .expect("failed to start the server runtime")
+            })
+            .handle()
+            .clone()
+    }
+}
+
+#[cfg(any(feature = "smol", feature = "async-std"))]
+impl Runtime for Hosted {
+    fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self::handle().spawn(task);
+    }
+
+    async fn run<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return future.await;
+        }
+        match Self::handle().spawn(future).await {
+            Ok(output) => output,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// The runtime selected by the enabled features.
+pub(crate) fn current() -> impl Runtime {
+    #[cfg(not(any(feature = "smol", feature = "async-std")))]
+    return Tokio;
+    #[cfg(any(feature = "smol", feature = "async-std"))]
+    return Hosted;
+}
@@ -0,0 +1,499 @@
+//! Multi-step workflows across actors, with compensation on failure.
+//!
+//! Calls to different actors cannot share a transaction.  A [`Saga`] runs a sequence of steps
+//! instead, each optionally paired with a *compensation* that undoes it.  If a step fails, the
+//! compensations of every step that already succeeded run in reverse order, leaving the system
+//! as if the workflow never started.
+//!
+//! Progress is recorded in a [`SagaStore`] after every call.  Running a saga again with the same
+//! id picks up where it stopped, skipping steps (and compensations) that already completed, so a
+//! workflow interrupted by a crash is finished by simply running it again after restart.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use simple_json_server::saga::{Call, FileSagaStore, Saga};
+//! use simple_json_server::ActorRef;
+//! use serde_json::json;
+//!
+//! # async fn example() -> Result<(), simple_json_server::saga::SagaError> {
+//! let orders = ActorRef::new("http://orders:8080");
+//! let payments = ActorRef::new("http://payments:8080");
+//! let stock = ActorRef::new("http://stock:8080");
+//!
+//! let results = Saga::new("order-42")
+//!     .step(Call::new(&orders, "create_order", json!({"id": 42})))
+//!     .compensate(Call::new(&orders, "cancel_order", json!({"id": 42})))
+//!     .step(Call::new(&payments, "charge", json!({"order": 42, "cents": 1999})))
+//!     // Compensations can use the result of the step they undo
+//!     .compensate_with(move |charge| Call::new(&payments, "refund", json!({"charge": charge})))
+//!     .step(Call::new(&stock, "reserve", json!({"order": 42})))
+//!     .store(FileSagaStore::new("/var/lib/myapp/sagas"))
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::ActorRef;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// One call to an actor method.
+#[derive(Debug, Clone)]
+pub struct Call {
+    actor: ActorRef,
+    method: String,
+    params: Value,
+}
+
+impl Call {
+    /// Call `method` on `actor` with `params` serialized as the JSON body.
+    pub fn new(actor: &ActorRef, method: impl Into<String>, params: impl Serialize) -> Self {
+        Self {
+            actor: actor.clone(),
+            method: method.into(),
+            params: serde_json::to_value(params).unwrap_or(Value::Null),
+        }
+    }
+
+    async fn invoke(&self) -> Result<Value, String> {
+        self.actor
+            .call(&self.method, &self.params)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+type StepFn = Box<dyn Fn(&[Value]) -> Call + Send + Sync>;
+type CompensationFn = Box<dyn Fn(&Value) -> Call + Send + Sync>;
+
+struct Step {
+    call: StepFn,
+    compensation: Option<CompensationFn>,
+}
+
+/// Where a saga is in its lifecycle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SagaStatus {
+    /// Steps are still being run.
+    Running,
+    /// A step failed and completed steps are being undone.
+    Compensating {
+        /// Index of the step that failed.
+        step: usize,
+        /// Why it failed.
+        error: String,
+    },
+    /// Every step succeeded.
+    Completed,
+    /// A step failed and every completed step was undone.
+    Compensated {
+        /// Index of the step that failed.
+        step: usize,
+        /// Why it failed.
+        error: String,
+    },
+}
+
+/// The persisted progress of a saga.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SagaProgress {
+    /// The saga id.
+    pub id: String,
+    /// Where the saga is in its lifecycle.
+    pub status: SagaStatus,
+    /// The results of the steps that succeeded, in order.
+    pub results: Vec<Value>,
+    /// How many of the succeeded steps have been compensated (starting from the last one).
+    pub compensated: usize,
+}
+
+/// Errors returned by [`Saga::run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SagaError {
+    /// A step failed; every step before it was compensated.
+    Aborted {
+        /// Index of the step that failed.
+        step: usize,
+        /// Why it failed.
+        error: String,
+    },
+    /// A step failed and one of the compensations failed too.  The saga is left compensating;
+    /// running it again retries the remaining compensations.
+    CompensationFailed {
+        /// Index of the step whose compensation failed.
+        step: usize,
+        /// Why it failed.
+        error: String,
+    },
+    /// Progress could not be loaded or saved.
+    Store(String),
+}
+
+impl fmt::Display for SagaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SagaError::Aborted { step, error } => {
+                write!(f, "Saga aborted at step {}: {}", step, error)
+            }
+            SagaError::CompensationFailed { step, error } => {
+                write!(f, "Compensation of step {} failed: {}", step, error)
+            }
+            SagaError::Store(e) => write!(f, "Saga store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SagaError {}
+
+/// Persists [`SagaProgress`] so interrupted sagas can be resumed.
+pub trait SagaStore: Send + Sync {
+    /// Load the progress of saga `id`, if it was ever saved.
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SagaProgress>, Box<dyn std::error::Error + Send + Sync>>>;
+
+    /// Save the progress of a saga, replacing any earlier version.
+    fn save<'a>(
+        &'a self,
+        progress: &'a SagaProgress,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// Keeps saga progress in memory.  This is the default store; it survives failed steps but not
+/// process restarts.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySagaStore {
+    sagas: Arc<Mutex<HashMap<String, SagaProgress>>>,
+}
+
+impl MemorySagaStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SagaStore for MemorySagaStore {
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SagaProgress>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { Ok(self.sagas.lock().unwrap().get(id).cloned()) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        progress: &'a SagaProgress,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            self.sagas
+                .lock()
+                .unwrap()
+                .insert(progress.id.clone(), progress.clone());
+            Ok(())
+        })
+    }
+}
+
+/// Keeps each saga's progress in a JSON file named after its id inside a directory.  Bytes of
+/// the id other than ASCII letters, digits, `-` and `_` are percent-encoded in the file name, so
+/// every id gets a file of its own.
+#[derive(Debug, Clone)]
+pub struct FileSagaStore {
+    dir: PathBuf,
+}
+
+impl FileSagaStore {
+    /// Store progress in `dir`, which is created on first save if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        let mut file = String::with_capacity(id.len());
+        for b in id.bytes() {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                file.push(b as char);
+            } else {
+                file.push_str(&format!("%{:02X}", b));
+            }
+        }
+        self.dir.join(format!("{}.json", file))
+    }
+}
+
+impl SagaStore for FileSagaStore {
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<SagaProgress>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(id)).await {
+                Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        progress: &'a SagaProgress,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Write then rename so a crash never leaves a truncated file behind
+            let path = self.path(&progress.id);
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, serde_json::to_vec(progress)?).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            Ok(())
+        })
+    }
+}
+
+/// A workflow of calls across actors.  Built with [`Saga::new`], [`Saga::step`] and
+/// [`Saga::compensate`], then executed with [`Saga::run`].
+pub struct Saga {
+    id: String,
+    steps: Vec<Step>,
+    store: Arc<dyn SagaStore>,
+}
+
+impl fmt::Debug for Saga {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Saga")
+            .field("id", &self.id)
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+impl Saga {
+    /// Start building the saga `id`.  The id identifies its progress in the store, so it must
+    /// be unique per workflow instance (e.g. include the order number).
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            steps: Vec::new(),
+            store: Arc::new(MemorySagaStore::new()),
+        }
+    }
+
+    /// Append a step.
+    pub fn step(self, call: Call) -> Self {
+        self.step_with(move |_| call.clone())
+    }
+
+    /// Append a step built from the results of the steps before it, e.g. to charge the order
+    /// id returned by the first step.
+    pub fn step_with<F>(mut self, call: F) -> Self
+    where
+        F: Fn(&[Value]) -> Call + Send + Sync + 'static,
+    {
+        self.steps.push(Step {
+            call: Box::new(call),
+            compensation: None,
+        });
+        self
+    }
+
+    /// Undo the last step with `call` if a later step fails.
+    pub fn compensate(self, call: Call) -> Self {
+        self.compensate_with(move |_| call.clone())
+    }
+
+    /// Undo the last step with a call built from that step's result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no step has been added yet.
+    pub fn compensate_with<F>(mut self, call: F) -> Self
+    where
+        F: Fn(&Value) -> Call + Send + Sync + 'static,
+    {
+        self.steps
+            .last_mut()
+            .expect("compensate called before any step")
+            .compensation = Some(Box::new(call));
+        self
+    }
+
+    /// Record progress in `store` instead of in memory.
+    pub fn store(mut self, store: impl SagaStore + 'static) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Run the saga (or resume it, if the store has progress for its id) and return the results
+    /// of every step.
+    pub async fn run(&self) -> Result<Vec<Value>, SagaError> {
+        let mut progress = self
+            .store
+            .load(&self.id)
+            .await
+            .map_err(|e| SagaError::Store(e.to_string()))?
+            .unwrap_or_else(|| SagaProgress {
+                id: self.id.clone(),
+                status: SagaStatus::Running,
+                results: Vec::new(),
+                compensated: 0,
+            });
+
+        if progress.status == SagaStatus::Running {
+            while progress.results.len() < self.steps.len() {
+                let index = progress.results.len();
+                let call = (self.steps[index].call)(&progress.results);
+                match call.invoke().await {
+                    Ok(result) => progress.results.push(result),
+                    Err(error) => {
+                        log::warn!(saga = self.id.as_str(), step = index; "Saga {} failed at step {}: {}", self.id, index, error);
+                        progress.status = SagaStatus::Compensating { step: index, error };
+                    }
+                }
+                if progress.status == SagaStatus::Running
+                    && progress.results.len() == self.steps.len()
+                {
+                    progress.status = SagaStatus::Completed;
+                }
+                self.save(&progress).await?;
+                if progress.status != SagaStatus::Running {
+                    break;
+                }
+            }
+        }
+
+        if let SagaStatus::Compensating { step, error } = progress.status.clone() {
+            while progress.compensated < progress.results.len() {
+                let index = progress.results.len() - 1 - progress.compensated;
+                if let Some(compensation) = &self.steps[index].compensation {
+                    let call = compensation(&progress.results[index]);
+                    if let Err(error) = call.invoke().await {
+                        log::error!(
+                            "Saga {} failed to compensate step {}: {}",
+                            self.id,
+                            index,
+                            error
+                        );
+                        return Err(SagaError::CompensationFailed { step: index, error });
+                    }
+                }
+                progress.compensated += 1;
+                self.save(&progress).await?;
+            }
+            progress.status = SagaStatus::Compensated { step, error };
+            self.save(&progress).await?;
+        }
+
+        match progress.status {
+            SagaStatus::Compensated { step, error } => Err(SagaError::Aborted { step, error }),
+            _ => Ok(progress.results),
+        }
+    }
+
+    async fn save(&self, progress: &SagaProgress) -> Result<(), SagaError> {
+        self.store
+            .save(progress)
+            .await
+            .map_err(|e| SagaError::Store(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn unreachable() -> ActorRef {
+        // Port 9 (discard) is never served in tests
+        ActorRef::new("http://127.0.0.1:9")
+    }
+
+    #[tokio::test]
+    async fn test_completed_saga_is_not_rerun() {
+        let store = MemorySagaStore::new();
+        let progress = SagaProgress {
+            id: "done".to_string(),
+            status: SagaStatus::Completed,
+            results: vec![json!(1), json!(2)],
+            compensated: 0,
+        };
+        store.save(&progress).await.unwrap();
+
+        let results = Saga::new("done")
+            .step(Call::new(&unreachable(), "a", json!({})))
+            .step(Call::new(&unreachable(), "b", json!({})))
+            .store(store)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(results, vec![json!(1), json!(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_step_without_compensations_aborts() {
+        let store = MemorySagaStore::new();
+        let result = Saga::new("fails")
+            .step(Call::new(&unreachable(), "a", json!({})))
+            .store(store.clone())
+            .run()
+            .await;
+        assert!(matches!(result, Err(SagaError::Aborted { step: 0, .. })));
+
+        let progress = store.load("fails").await.unwrap().unwrap();
+        assert!(matches!(
+            progress.status,
+            SagaStatus::Compensated { step: 0, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("saga_store_test_{}", std::process::id()));
+        let store = FileSagaStore::new(&dir);
+        assert!(store.load("order/1").await.unwrap().is_none());
+
+        let progress = SagaProgress {
+            id: "order/1".to_string(),
+            status: SagaStatus::Compensating {
+                step: 1,
+                error: "declined".to_string(),
+            },
+            results: vec![json!({"id": 1})],
+            compensated: 0,
+        };
+        store.save(&progress).await.unwrap();
+        assert_eq!(store.load("order/1").await.unwrap(), Some(progress));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_store_keeps_similar_ids_apart() {
+        let dir = std::env::temp_dir().join(format!("saga_ids_test_{}", std::process::id()));
+        let store = FileSagaStore::new(&dir);
+        let progress = |id: &str| SagaProgress {
+            id: id.to_string(),
+            status: SagaStatus::Completed,
+            results: vec![json!(id)],
+            compensated: 0,
+        };
+        for id in ["a/b", "a_b", "a%2Fb", "a.b"] {
+            store.save(&progress(id)).await.unwrap();
+        }
+        for id in ["a/b", "a_b", "a%2Fb", "a.b"] {
+            assert_eq!(store.load(id).await.unwrap(), Some(progress(id)));
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Capture of a sample of full request and response payloads, for debugging.
+//!
+//! Logging every payload is too expensive, but a few real examples of each method's traffic
+//! make most problems easy to reproduce.  A [`PayloadSampler`] keeps the most recent sampled
+//! calls as [`Exemplar`]s, with their parameters and response redacted like the
+//! [slow log](crate::slowlog)'s, including parameters marked `#[sensitive]`.  They can be read from Rust or, with an admin token set, from
+//! `GET /__admin/exemplars`.
+//!
+//! Whether a call is sampled depends on its trace id (see [`crate::trace`]), so every actor
+//! sampling at the same rate captures the same calls of a trace.
+//!
+//! ```rust
+//! use simple_json_server::sampling::PayloadSampler;
+//! use simple_json_server::ServerOptions;
+//!
+//! // Keep 1% of calls, up to the latest 200
+//! let sampler = PayloadSampler::new(0.01).capacity(200).redact(["card_number"]);
+//! let options = ServerOptions::new(8080).sample_payloads(sampler.clone());
+//! ```
+
+use crate::context::RequestContext;
+use crate::logging::rfc3339;
+use crate::slowlog::{redact, DEFAULT_REDACTED};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Exemplars kept by default.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// Longest request or response text kept; longer payloads are truncated.
+const MAX_PAYLOAD_LEN: usize = 16 * 1024;
+
+/// A sampled call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Exemplar {
+    /// When the call was answered, as an RFC 3339 timestamp.
+    pub timestamp: String,
+    /// The method called.
+    pub method: String,
+    /// The call's request id.
+    pub request_id: String,
+    /// The id of the trace the call belongs to.
+    pub trace_id: String,
+    /// The HTTP status the call was answered with.
+    pub status: u16,
+    /// Time from the call arriving to the response being ready, in milliseconds.
+    pub duration_ms: u64,
+    /// The call's JSON parameters, with sensitive fields redacted.
+    pub request: String,
+    /// The response body, with sensitive fields redacted.
+    pub response: String,
+}
+
+/// Payload sampling settings and the exemplars captured, passed to
+/// [`crate::ServerOptions::sample_payloads`].  Clones share the exemplars.
+#[derive(Debug, Clone)]
+pub struct PayloadSampler {
+    rate: f64,
+    capacity: usize,
+    redacted: Vec<String>,
+    exemplars: Arc<Mutex<VecDeque<Exemplar>>>,
+}
+
+impl PayloadSampler {
+    /// Sample a fraction `rate` of calls, between 0 and 1.
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate: rate.clamp(0.0, 1.0),
+            capacity: DEFAULT_CAPACITY,
+            redacted: DEFAULT_REDACTED.iter().map(|f| f.to_string()).collect(),
+            exemplars: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Keep the latest `capacity` exemplars (by default 100), dropping older ones.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Also redact fields named `fields`, in addition to the defaults (`password`, `secret`,
+    /// `token`, `authorization` and `api_key`).
+    pub fn redact<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.redacted.extend(fields.into_iter().map(Into::into));
+        self
+    }
+
+    /// The exemplars captured, oldest first.
+    pub fn exemplars(&self) -> Vec<Exemplar> {
+        self.exemplars.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Keep the call as an exemplar if it is sampled.
+    pub(crate) fn observe(
+        &self,
+        ctx: &RequestContext,
+        status: u16,
+        elapsed: Duration,
+        response: &str,
+    ) {
+        if self.capacity == 0 || !self.sampled(&ctx.trace().trace_id()) {
+            return;
+        }
+        let exemplar = Exemplar {
+            timestamp: rfc3339(SystemTime::now()),
+            method: ctx.method().to_string(),
+            request_id: ctx.request_id().to_string(),
+            trace_id: ctx.trace().trace_id(),
+            status,
+            duration_ms: elapsed.as_millis() as u64,
+            request: redact(
+                ctx.params(),
+                &self.redacted,
+                ctx.sensitive(),
+                MAX_PAYLOAD_LEN,
+            ),
+            response: redact(response, &self.redacted, &[], MAX_PAYLOAD_LEN),
+        };
+        let mut exemplars = self.exemplars.lock().unwrap();
+        while exemplars.len() >= self.capacity {
+            exemplars.pop_front();
+        }
+        exemplars.push_back(exemplar);
+    }
+
+    /// Whether calls in the trace `trace_id` are sampled, from its random low 64 bits.
+    fn sampled(&self, trace_id: &str) -> bool {
+        let low = trace_id
+            .get(trace_id.len().saturating_sub(16)..)
+            .and_then(|low| u64::from_str_radix(low, 16).ok())
+            .unwrap_or_default();
+        self.rate >= 1.0 || (low as f64) < self.rate * u64::MAX as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Resources;
+
+    #[test]
+    fn test_sampling_follows_the_trace_id() {
+        let sampler = PayloadSampler::new(0.25);
+        assert!(sampler.sampled("4bf92f3577b34da6000000000000ffff"));
+        assert!(sampler.sampled("4bf92f3577b34da63ff0000000000000"));
+        assert!(!sampler.sampled("4bf92f3577b34da64000000000000001"));
+        assert!(!PayloadSampler::new(0.0).sampled("00000000000000000000000000000001"));
+        assert!(PayloadSampler::new(1.0).sampled("ffffffffffffffffffffffffffffffff"));
+    }
+
+    #[test]
+    fn test_keeps_the_latest_redacted_exemplars() {
+        let sampler = PayloadSampler::new(1.0).capacity(2).redact(["ssn"]);
+        for n in 0..3 {
+            let params = format!(r#"{{"n": {}, "password": "hunter2"}}"#, n);
+            let ctx = RequestContext::new("save", &params, Resources::default());
+            sampler.observe(&ctx, 200, Duration::from_millis(5), r#"{"ssn": "123"}"#);
+        }
+
+        let exemplars = sampler.exemplars();
+        assert_eq!(exemplars.len(), 2);
+        assert_eq!(exemplars[0].request, r#"{"n":1,"password":"[REDACTED]"}"#);
+        assert_eq!(exemplars[1].response, r#"{"ssn":"[REDACTED]"}"#);
+        assert_eq!(exemplars[1].method, "save");
+        assert_eq!(exemplars[1].duration_ms, 5);
+    }
+}
@@ -0,0 +1,219 @@
+//! Scrubbing of string parameters before a method sees them.
+//!
+//! `#[sanitize(trim, lowercase, max_len = 100)]` on a parameter cleans up its value after it is
+//! deserialized, so every method treats input the same way instead of each doing its own.  The
+//! parameter may be a `String`, or an `Option` or `Vec` of sanitized values.
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor};
+//!
+//! pub struct Accounts;
+//!
+//! #[actor]
+//! impl Accounts {
+//!     pub async fn register(
+//!         &self,
+//!         #[sanitize(trim, lowercase, max_len = 254)] email: String,
+//!         #[sanitize(normalize, collapse_whitespace, strip_control, max_len = 100)] name: String,
+//!     ) -> String {
+//!         format!("{} <{}>", name, email)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! The steps run in a fixed order whatever order they are written in:
+//!
+//! 1. `strip_control` removes control characters, such as NUL and escape sequences; line breaks
+//!    and tabs become spaces.
+//! 2. `normalize` puts the text in Unicode normalization form C, so the same word always has
+//!    the same bytes.
+//! 3. `trim` removes leading and trailing whitespace.
+//! 4. `collapse_whitespace` replaces each run of whitespace with one space.
+//! 5. `lowercase` or `uppercase` changes the case.
+//!
+//! A value longer than `max_len` characters once sanitized is refused with `400 Bad Request`.
+//! The same steps are available to code with [`Sanitizer`].
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A set of sanitizing steps, applied with [`Sanitizer::apply`] or to a parameter with
+/// `#[sanitize(...)]`; see the [module documentation](self).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sanitizer {
+    strip_control: bool,
+    normalize: bool,
+    trim: bool,
+    collapse_whitespace: bool,
+    case: Option<Case>,
+    max_len: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    Lower,
+    Upper,
+}
+
+impl Sanitizer {
+    /// A sanitizer that changes nothing.
+    pub const fn new() -> Self {
+        Self {
+            strip_control: false,
+            normalize: false,
+            trim: false,
+            collapse_whitespace: false,
+            case: None,
+            max_len: None,
+        }
+    }
+
+    /// Remove control characters, turning line breaks and tabs into spaces.
+    pub const fn strip_control(mut self) -> Self {
+        self.strip_control = true;
+        self
+    }
+
+    /// Put the text in Unicode normalization form C.
+    pub const fn normalize(mut self) -> Self {
+        self.normalize = true;
+        self
+    }
+
+    /// Remove leading and trailing whitespace.
+    pub const fn trim(mut self) -> Self {
+        self.trim = true;
+        self
+    }
+
+    /// Replace each run of whitespace with one space.
+    pub const fn collapse_whitespace(mut self) -> Self {
+        self.collapse_whitespace = true;
+        self
+    }
+
+    /// Convert the text to lowercase.
+    pub const fn lowercase(mut self) -> Self {
+        self.case = Some(Case::Lower);
+        self
+    }
+
+    /// Convert the text to uppercase.
+    pub const fn uppercase(mut self) -> Self {
+        self.case = Some(Case::Upper);
+        self
+    }
+
+    /// Refuse text longer than `max_len` characters once sanitized.
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// The longest text allowed, in characters.
+    pub(crate) fn limit(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// `text` sanitized, or its length in characters if that is over the limit.
+    pub fn apply(&self, text: &str) -> Result<String, usize> {
+        let mut text = text.to_string();
+        if self.strip_control {
+            text = text
+                .chars()
+                .filter_map(|c| match c {
+                    '\t' | '\n' | '\r' => Some(' '),
+                    c if c.is_control() => None,
+                    c => Some(c),
+                })
+                .collect();
+        }
+        if self.normalize {
+            text = text.nfc().collect();
+        }
+        if self.trim {
+            text = text.trim().to_string();
+        }
+        if self.collapse_whitespace {
+            text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        match self.case {
+            Some(Case::Lower) => text = text.to_lowercase(),
+            Some(Case::Upper) => text = text.to_uppercase(),
+            None => {}
+        }
+        let len = text.chars().count();
+        match self.max_len {
+            Some(max_len) if len > max_len => Err(len),
+            _ => Ok(text),
+        }
+    }
+}
+
+/// Values that `#[sanitize(...)]` can be applied to.
+pub trait Sanitize {
+    /// Sanitize the value in place, or return the length in characters of a string over the
+    /// limit.
+    fn sanitize(&mut self, sanitizer: &Sanitizer) -> Result<(), usize>;
+}
+
+impl Sanitize for String {
+    fn sanitize(&mut self, sanitizer: &Sanitizer) -> Result<(), usize> {
+        *self = sanitizer.apply(self)?;
+        Ok(())
+    }
+}
+
+impl<T: Sanitize> Sanitize for Option<T> {
+    fn sanitize(&mut self, sanitizer: &Sanitizer) -> Result<(), usize> {
+        match self {
+            Some(value) => value.sanitize(sanitizer),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T: Sanitize> Sanitize for Vec<T> {
+    fn sanitize(&mut self, sanitizer: &Sanitizer) -> Result<(), usize> {
+        self.iter_mut()
+            .try_for_each(|value| value.sanitize(sanitizer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_run_in_order() {
+        let sanitizer = Sanitizer::new()
+            .lowercase()
+            .collapse_whitespace()
+            .trim()
+            .strip_control();
+        assert_eq!(
+            sanitizer.apply("  Ada\u{0}\tLOVELACE \u{1b}\n ").unwrap(),
+            "ada lovelace"
+        );
+        // "é" as "e" and a combining accent becomes one character
+        let composed = Sanitizer::new().normalize().max_len(4);
+        assert_eq!(composed.apply("Rene\u{301}").unwrap(), "Ren\u{e9}");
+        assert_eq!(composed.apply("Renée!"), Err(6));
+        assert_eq!(
+            Sanitizer::new().uppercase().apply("sku-1").unwrap(),
+            "SKU-1"
+        );
+    }
+
+    #[test]
+    fn test_options_and_lists() {
+        let sanitizer = Sanitizer::new().trim().max_len(3);
+        let mut tags = vec![" a ".to_string(), "b ".to_string()];
+        tags.sanitize(&sanitizer).unwrap();
+        assert_eq!(tags, ["a", "b"]);
+        let mut missing: Option<String> = None;
+        assert_eq!(missing.sanitize(&sanitizer), Ok(()));
+        let mut long = Some("abcd".to_string());
+        assert_eq!(long.sanitize(&sanitizer), Err(4));
+    }
+}
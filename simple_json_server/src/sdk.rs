@@ -0,0 +1,1274 @@
+//! Client packages generated from an actor's method metadata.
+//!
+//! [`JsClient`] generates an ES module package for Deno, Node 18+ and browsers: a `Client`
+//! class with one method per actor method, and TypeScript declarations mapping the Rust
+//! parameter and result types to TypeScript ones.  Calls are sent with `fetch` and can be
+//! cancelled with an `AbortSignal`; calls refused with `429` or `503` are retried, honouring
+//! `Retry-After`, and calls to `#[read]` methods are also retried after network errors.
+//! Subscriptions to [events](crate::events) share one WebSocket per client, connected to the
+//! `websocketUrl` option (by default the client's URL with a `ws` scheme), which reconnects and
+//! subscribes again if it drops.
+//!
+//! ```rust,no_run
+//! use simple_json_server::sdk::JsClient;
+//! use simple_json_server::{actor, Actor};
+//!
+//! struct Greeter;
+//!
+//! #[actor]
+//! impl Greeter {
+//!     /// Greet someone
+//!     #[read]
+//!     pub async fn greet(&self, name: String) -> String {
+//!         format!("Hello {}", name)
+//!     }
+//! }
+//!
+//! # fn main() {
+//! JsClient::new("greeter-client", &Greeter)
+//!     .version("1.2.0")
+//!     .write_to("target/greeter-client")
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! ```js
+//! import { Client } from "greeter-client";
+//!
+//! const greeter = new Client("http://127.0.0.1:8080", {
+//!     websocketUrl: "ws://127.0.0.1:8081",
+//!     retries: 5,
+//! });
+//! console.log(await greeter.greet({ name: "World" }, { signal: AbortSignal.timeout(1000) }));
+//! const stop = greeter.subscribe("greeted", (data) => console.log(data));
+//! ```
+//!
+//! Methods keep their Rust names, with an `_` appended to names the client itself uses, such
+//! as `call`.  Parameters read from headers or cookies are left out; set them with the
+//! `headers` option.  Types the generator doesn't know, such as the actor's own structs, are
+//! declared `unknown`.  `cargo xtask js-client` in this repository generates, and with
+//! `--publish` publishes, the package for the demo actor.
+//!
+//! For mobile apps, [`KotlinClient`] and [`SwiftClient`] each generate one source file with a
+//! client class whose methods take typed arguments and return typed results: Kotlin `suspend`
+//! functions using kotlinx.serialization and kotlinx.coroutines (with the serialization
+//! plugin applied), and Swift `async throws` methods using `Codable` and `URLSession` (iOS 15
+//! and macOS 12 or later).  Methods and parameters are renamed to lowerCamelCase, and calls are
+//! retried as by the JavaScript client.  Rust `Result`s become `ActorResult`s, and types the
+//! generator doesn't know become `JsonElement` in Kotlin and the generated `JSONValue` in
+//! Swift.
+//!
+//! ```rust,no_run
+//! # use simple_json_server::{actor, Actor};
+//! # struct Greeter;
+//! # #[actor]
+//! # impl Greeter {
+//! #     pub async fn greet(&self, name: String) -> String {
+//! #         format!("Hello {}", name)
+//! #     }
+//! # }
+//! use simple_json_server::sdk::{KotlinClient, SwiftClient};
+//!
+//! # fn main() {
+//! // Writes GreeterClient.kt and GreeterClient.swift
+//! KotlinClient::new("com.example.greeter", "GreeterClient", &Greeter)
+//!     .write_to("android/app/src/main/kotlin/com/example/greeter")
+//!     .unwrap();
+//! SwiftClient::new("GreeterClient", &Greeter)
+//!     .write_to("ios/Greeter")
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! ```kotlin
+//! val greeter = GreeterClient("https://greeter.example.com")
+//! val greeting: String = greeter.greet(name = "World")
+//! ```
+//!
+//! ```swift
+//! let greeter = GreeterClient(url: URL(string: "https://greeter.example.com")!)
+//! let greeting = try await greeter.greet(name: "World")
+//! ```
+
+use crate::{Actor, MethodInfo, MethodKind, ParamSource};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+/// The runtime shared by every generated client.
+const JS_RUNTIME: &str = include_str!("sdk/runtime.js");
+const JS_RUNTIME_TYPES: &str = include_str!("sdk/runtime.d.ts");
+
+/// Names `ActorClient` uses itself, which generated methods must not shadow.
+const JS_RESERVED: &[&str] = &[
+    "call",
+    "subscribe",
+    "close",
+    "constructor",
+    "url",
+    "websocketUrl",
+    "retries",
+    "retryDelay",
+    "headers",
+    "fetch",
+    "WebSocket",
+    "topics",
+    "socket",
+    "reconnects",
+];
+
+/// Generates a JavaScript client package for an actor; see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct JsClient {
+    package: String,
+    version: String,
+    description: Option<String>,
+    methods: &'static [MethodInfo],
+}
+
+impl JsClient {
+    /// A package named `package` (the name it is published and imported under) for the
+    /// methods of `actor`, at version 0.1.0.
+    pub fn new(package: impl Into<String>, actor: &impl Actor) -> Self {
+        Self {
+            package: package.into(),
+            version: "0.1.0".to_string(),
+            description: None,
+            methods: actor.methods(),
+        }
+    }
+
+    /// The package's version.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// The package's description, shown by package registries.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// The package's files, by name.
+    pub fn files(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("package.json", self.package_json()),
+            ("index.js", self.index_js()),
+            ("index.d.ts", self.index_types()),
+            ("runtime.js", JS_RUNTIME.to_string()),
+            ("runtime.d.ts", JS_RUNTIME_TYPES.to_string()),
+        ]
+    }
+
+    /// Write the package's files to `dir`, creating it if needed.
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for (name, contents) in self.files() {
+            std::fs::write(dir.join(name), contents)?;
+        }
+        Ok(())
+    }
+
+    fn package_json(&self) -> String {
+        let description = self.description.clone().unwrap_or_else(|| {
+            format!(
+                "A client for the {} actor, generated by simple_json_server",
+                self.package
+            )
+        });
+        // Written from structs rather than `json!`, which would sort the keys: conditional
+        // exports are matched in order, so `types` has to come before `default`
+        #[derive(Serialize)]
+        struct Package<'a> {
+            name: &'a str,
+            version: &'a str,
+            description: String,
+            r#type: &'static str,
+            main: &'static str,
+            types: &'static str,
+            exports: BTreeMap<&'static str, Exports>,
+            files: [&'static str; 4],
+            engines: BTreeMap<&'static str, &'static str>,
+        }
+        #[derive(Serialize)]
+        struct Exports {
+            types: &'static str,
+            default: &'static str,
+        }
+        let package = Package {
+            name: &self.package,
+            version: &self.version,
+            description,
+            r#type: "module",
+            main: "index.js",
+            types: "index.d.ts",
+            exports: BTreeMap::from([(
+                ".",
+                Exports {
+                    types: "./index.d.ts",
+                    default: "./index.js",
+                },
+            )]),
+            files: ["index.js", "index.d.ts", "runtime.js", "runtime.d.ts"],
+            engines: BTreeMap::from([("node", ">=18")]),
+        };
+        serde_json::to_string_pretty(&package).unwrap_or_default() + "\n"
+    }
+
+    fn index_js(&self) -> String {
+        let mut js = String::from(GENERATED);
+        js.push_str("import { ActorClient } from \"./runtime.js\";\n\n");
+        js.push_str("export { ActorClient, CallError } from \"./runtime.js\";\n\n");
+        js.push_str("export class Client extends ActorClient {\n");
+        for (i, info) in self.methods.iter().enumerate() {
+            if i > 0 {
+                js.push('\n');
+            }
+            js.push_str(&js_doc(info.doc));
+            let kind = match info.kind {
+                MethodKind::Read => "read",
+                MethodKind::Write => "write",
+            };
+            let _ = writeln!(
+                js,
+                "  {}(params = {{}}, options = {{}}) {{\n    \
+                 return this.call({:?}, params, options, {:?});\n  }}",
+                js_name(info.name),
+                info.name,
+                kind
+            );
+        }
+        js.push_str("}\n");
+        js
+    }
+
+    fn index_types(&self) -> String {
+        let mut ts = String::from(GENERATED);
+        ts.push_str("import { ActorClient, CallOptions } from \"./runtime.js\";\n\n");
+        ts.push_str(
+            "export { ActorClient, CallError, CallOptions, ClientOptions, EventFrame, \
+             SubscribeOptions } from \"./runtime.js\";\n\n",
+        );
+        ts.push_str("export declare class Client extends ActorClient {\n");
+        for (i, info) in self.methods.iter().enumerate() {
+            if i > 0 {
+                ts.push('\n');
+            }
+            ts.push_str(&js_doc(info.doc));
+            let params: Vec<String> = info
+                .params
+                .iter()
+                .filter(|param| param.source == ParamSource::Body)
+                .map(|param| {
+                    let ty = RustType::parse(param.ty);
+                    let optional = if matches!(ty, RustType::Option(_)) {
+                        "?"
+                    } else {
+                        ""
+                    };
+                    format!("{}{}: {}", param.name, optional, ty.typescript())
+                })
+                .collect();
+            // Methods without required parameters can be called without any
+            let required = params.iter().any(|param| !param.contains("?:"));
+            let params = match params.is_empty() {
+                true => "{}".to_string(),
+                false => format!("{{ {} }}", params.join("; ")),
+            };
+            let _ = writeln!(
+                ts,
+                "  {}(params{}: {}, options?: CallOptions): Promise<{}>;",
+                js_name(info.name),
+                if required { "" } else { "?" },
+                params,
+                RustType::parse(info.returns).typescript()
+            );
+        }
+        ts.push_str("}\n");
+        ts
+    }
+}
+
+const GENERATED: &str =
+    "// Generated by simple_json_server from the actor's methods; do not edit.\n\n";
+
+/// `name` as a method of the generated client.
+fn js_name(name: &str) -> String {
+    match JS_RESERVED.contains(&name) {
+        true => format!("{}_", name),
+        false => name.to_string(),
+    }
+}
+
+/// `doc` as an indented JSDoc comment, or nothing if it is empty.
+fn js_doc(doc: &str) -> String {
+    block_doc(doc, "  ")
+}
+
+/// `doc` as a `/** */` comment indented by `indent`, as JSDoc and KDoc write them.
+fn block_doc(doc: &str, indent: &str) -> String {
+    let doc = doc.trim();
+    if doc.is_empty() {
+        return String::new();
+    }
+    let mut comment = format!("{}/**\n", indent);
+    for line in doc.lines() {
+        let line = line.trim_end().replace("*/", "*\\/");
+        match line.is_empty() {
+            true => {
+                let _ = writeln!(comment, "{} *", indent);
+            }
+            false => {
+                let _ = writeln!(comment, "{} * {}", indent, line);
+            }
+        }
+    }
+    let _ = writeln!(comment, "{} */", indent);
+    comment
+}
+
+/// `doc` as `///` lines indented by `indent`, as Swift writes doc comments.
+fn line_doc(doc: &str, indent: &str) -> String {
+    let doc = doc.trim();
+    let mut comment = String::new();
+    if doc.is_empty() {
+        return comment;
+    }
+    for line in doc.lines() {
+        let line = line.trim_end();
+        let _ = match line.is_empty() {
+            true => writeln!(comment, "{}///", indent),
+            false => writeln!(comment, "{}/// {}", indent, line),
+        };
+    }
+    comment
+}
+
+/// Names the generated Kotlin client uses itself.
+const KOTLIN_RESERVED: &[&str] = &["rpc", "post", "json"];
+
+/// Kotlin's hard keywords, which have to be quoted with backticks to be used as names.
+const KOTLIN_KEYWORDS: &[&str] = &[
+    "as",
+    "break",
+    "class",
+    "continue",
+    "do",
+    "else",
+    "false",
+    "for",
+    "fun",
+    "if",
+    "in",
+    "interface",
+    "is",
+    "null",
+    "object",
+    "package",
+    "return",
+    "super",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typealias",
+    "typeof",
+    "val",
+    "var",
+    "when",
+    "while",
+];
+
+/// Generates a Kotlin client class for an actor; see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct KotlinClient {
+    package: String,
+    class: String,
+    methods: &'static [MethodInfo],
+}
+
+impl KotlinClient {
+    /// A class named `class`, in the Kotlin package `package`, for the methods of `actor`.
+    pub fn new(package: impl Into<String>, class: impl Into<String>, actor: &impl Actor) -> Self {
+        Self {
+            package: package.into(),
+            class: class.into(),
+            methods: actor.methods(),
+        }
+    }
+
+    /// The name of the generated file: the class name with a `.kt` extension.
+    pub fn file_name(&self) -> String {
+        format!("{}.kt", self.class)
+    }
+
+    /// The generated source.
+    pub fn source(&self) -> String {
+        let mut kt = String::from(GENERATED);
+        let _ = writeln!(kt, "package {}\n", self.package);
+        kt.push_str(KOTLIN_PRELUDE);
+        let _ = writeln!(
+            kt,
+            "\nclass {}(\n    url: String,\n    val headers: Map<String, String> = emptyMap(),\n    \
+             val retries: Int = 3,\n    val retryDelayMillis: Long = 200,\n) {{\n    \
+             val url = url.trimEnd('/')\n    \
+             private val json = Json {{ ignoreUnknownKeys = true }}",
+            self.class
+        );
+        for info in self.methods {
+            kt.push('\n');
+            kt.push_str(&block_doc(info.doc, "    "));
+            let params = body_params(info);
+            let args: Vec<String> = params
+                .iter()
+                .map(|(name, ty)| match ty {
+                    RustType::Option(_) => {
+                        format!("{}: {} = null", kotlin_name(name), ty.kotlin())
+                    }
+                    _ => format!("{}: {}", kotlin_name(name), ty.kotlin()),
+                })
+                .collect();
+            let returns = RustType::parse(info.returns);
+            let _ = write!(
+                kt,
+                "    suspend fun {}({})",
+                kotlin_name(&mobile_method(info.name, KOTLIN_RESERVED)),
+                args.join(", ")
+            );
+            if returns != RustType::Unit {
+                let _ = write!(kt, ": {}", returns.kotlin());
+            }
+            kt.push_str(" {\n");
+            match params.is_empty() {
+                true => kt.push_str("        val params = JsonObject(emptyMap())\n"),
+                false => kt.push_str("        val params = buildJsonObject {\n"),
+            }
+            for (name, ty) in &params {
+                let value = kotlin_name(name);
+                match ty {
+                    RustType::Option(inner) => {
+                        let _ = writeln!(
+                            kt,
+                            "            if ({value} != null) put({name:?}, \
+                             json.encodeToJsonElement(serializer<{}>(), {value}))",
+                            inner.kotlin()
+                        );
+                    }
+                    _ => {
+                        let _ = writeln!(
+                            kt,
+                            "            put({name:?}, json.encodeToJsonElement(serializer<{}>(), {value}))",
+                            ty.kotlin()
+                        );
+                    }
+                }
+            }
+            if !params.is_empty() {
+                kt.push_str("        }\n");
+            }
+            let call = format!(
+                "rpc({:?}, params, read = {})",
+                info.name,
+                info.kind == MethodKind::Read
+            );
+            match returns {
+                RustType::Unit => {
+                    let _ = writeln!(kt, "        {}", call);
+                }
+                _ => {
+                    let _ = writeln!(
+                        kt,
+                        "        val result = {}\n        \
+                         return json.decodeFromJsonElement(serializer<{}>(), result)",
+                        call,
+                        returns.kotlin()
+                    );
+                }
+            }
+            kt.push_str("    }\n");
+        }
+        kt.push_str(KOTLIN_RUNTIME);
+        kt.push_str("}\n");
+        kt
+    }
+
+    /// Write the source to `dir`, creating it if needed.
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(self.file_name()), self.source())
+    }
+}
+
+const KOTLIN_PRELUDE: &str = r#"import java.io.IOException
+import java.net.HttpURLConnection
+import java.net.URI
+import kotlinx.coroutines.Dispatchers
+import kotlinx.coroutines.delay
+import kotlinx.coroutines.withContext
+import kotlinx.serialization.SerialName
+import kotlinx.serialization.Serializable
+import kotlinx.serialization.json.Json
+import kotlinx.serialization.json.JsonArray
+import kotlinx.serialization.json.JsonElement
+import kotlinx.serialization.json.JsonNull
+import kotlinx.serialization.json.JsonObject
+import kotlinx.serialization.json.buildJsonObject
+import kotlinx.serialization.serializer
+
+/** A call answered with an error status, after any retries. */
+class CallException(val method: String, val status: Int, val body: String) :
+    Exception("$method failed with $status: $body")
+
+/** A Rust `Result`, serialized as `{"Ok": ...}` or `{"Err": ...}`. */
+@Serializable
+data class ActorResult<T, E>(
+    @SerialName("Ok") val ok: T? = null,
+    @SerialName("Err") val err: E? = null,
+)
+"#;
+
+const KOTLIN_RUNTIME: &str = r#"
+    // Calls refused with 429 or 503 are retried, honouring Retry-After; read methods are also
+    // retried after network errors
+    private suspend fun rpc(method: String, params: JsonObject, read: Boolean): JsonElement {
+        var attempt = 0
+        while (true) {
+            val backoff = retryDelayMillis shl attempt
+            val response = try {
+                withContext(Dispatchers.IO) { post(method, params.toString()) }
+            } catch (e: IOException) {
+                // A write may have run before the connection failed
+                if (!read || attempt >= retries) throw e
+                delay(backoff)
+                attempt++
+                continue
+            }
+            if (response.status in 200..299) {
+                return if (response.body.isEmpty()) JsonNull else json.parseToJsonElement(response.body)
+            }
+            if ((response.status == 429 || response.status == 503) && attempt < retries) {
+                delay(response.retryAfter?.times(1000) ?: backoff)
+                attempt++
+                continue
+            }
+            throw CallException(method, response.status, response.body)
+        }
+    }
+
+    private fun post(method: String, body: String): Response {
+        val connection = URI("$url/$method").toURL().openConnection() as HttpURLConnection
+        try {
+            connection.requestMethod = "POST"
+            connection.doOutput = true
+            connection.setRequestProperty("Content-Type", "application/json")
+            headers.forEach { (name, value) -> connection.setRequestProperty(name, value) }
+            connection.outputStream.use { it.write(body.toByteArray()) }
+            val status = connection.responseCode
+            val stream = if (status < 400) connection.inputStream else connection.errorStream
+            val text = stream?.bufferedReader()?.use { it.readText() } ?: ""
+            return Response(status, text, connection.getHeaderField("Retry-After")?.toLongOrNull())
+        } finally {
+            connection.disconnect()
+        }
+    }
+
+    private class Response(val status: Int, val body: String, val retryAfter: Long?)
+"#;
+
+/// Names the generated Swift client uses itself.
+const SWIFT_RESERVED: &[&str] = &[
+    "url",
+    "headers",
+    "retries",
+    "retryDelay",
+    "session",
+    "rpc",
+    "send",
+];
+
+/// Swift keywords, which have to be quoted with backticks to be used as names.
+const SWIFT_KEYWORDS: &[&str] = &[
+    "associatedtype",
+    "class",
+    "deinit",
+    "enum",
+    "extension",
+    "fileprivate",
+    "func",
+    "import",
+    "init",
+    "inout",
+    "internal",
+    "let",
+    "open",
+    "operator",
+    "private",
+    "protocol",
+    "public",
+    "rethrows",
+    "static",
+    "struct",
+    "subscript",
+    "typealias",
+    "var",
+    "break",
+    "case",
+    "catch",
+    "continue",
+    "default",
+    "defer",
+    "do",
+    "else",
+    "fallthrough",
+    "for",
+    "guard",
+    "if",
+    "in",
+    "repeat",
+    "return",
+    "throw",
+    "switch",
+    "where",
+    "while",
+    "as",
+    "false",
+    "is",
+    "nil",
+    "self",
+    "Self",
+    "super",
+    "throws",
+    "true",
+    "try",
+];
+
+/// Generates a Swift client class for an actor; see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct SwiftClient {
+    class: String,
+    methods: &'static [MethodInfo],
+}
+
+impl SwiftClient {
+    /// A class named `class` for the methods of `actor`.
+    pub fn new(class: impl Into<String>, actor: &impl Actor) -> Self {
+        Self {
+            class: class.into(),
+            methods: actor.methods(),
+        }
+    }
+
+    /// The name of the generated file: the class name with a `.swift` extension.
+    pub fn file_name(&self) -> String {
+        format!("{}.swift", self.class)
+    }
+
+    /// The generated source.
+    pub fn source(&self) -> String {
+        let mut swift = String::from(GENERATED);
+        swift.push_str(SWIFT_PRELUDE);
+        let _ = writeln!(
+            swift,
+            "\npublic final class {} {{\n    \
+             public let url: URL\n    \
+             public var headers: [String: String]\n    \
+             public var retries: Int\n    \
+             public var retryDelay: TimeInterval\n    \
+             private let session: URLSession\n\n    \
+             public init(\n        url: URL,\n        headers: [String: String] = [:],\n        \
+             retries: Int = 3,\n        retryDelay: TimeInterval = 0.2,\n        \
+             session: URLSession = .shared\n    ) {{\n        \
+             self.url = url\n        self.headers = headers\n        self.retries = retries\n        \
+             self.retryDelay = retryDelay\n        self.session = session\n    }}",
+            self.class
+        );
+        for info in self.methods {
+            swift.push('\n');
+            swift.push_str(&line_doc(info.doc, "    "));
+            let params = body_params(info);
+            let args: Vec<String> = params
+                .iter()
+                .map(|(name, ty)| match ty {
+                    RustType::Option(_) => format!("{}: {} = nil", swift_name(name), ty.swift()),
+                    _ => format!("{}: {}", swift_name(name), ty.swift()),
+                })
+                .collect();
+            let returns = RustType::parse(info.returns);
+            let _ = write!(
+                swift,
+                "    public func {}({}) async throws",
+                swift_name(&mobile_method(info.name, SWIFT_RESERVED)),
+                args.join(", ")
+            );
+            if returns != RustType::Unit {
+                let _ = write!(swift, " -> {}", returns.swift());
+            }
+            swift.push_str(" {\n");
+            let value = match params.is_empty() {
+                // A struct without properties doesn't encode as `{}`
+                true => "[String: String]()".to_string(),
+                false => {
+                    swift.push_str("        struct Params: Encodable {\n");
+                    for (name, ty) in &params {
+                        let _ = writeln!(
+                            swift,
+                            "            let {}: {}",
+                            swift_name(name),
+                            ty.swift()
+                        );
+                    }
+                    if params.iter().any(|(name, _)| camel_case(name) != *name) {
+                        let keys: Vec<String> = params
+                            .iter()
+                            .map(|(name, _)| match camel_case(name) == *name {
+                                true => swift_name(name),
+                                false => format!("{} = {:?}", swift_name(name), name),
+                            })
+                            .collect();
+                        let _ = writeln!(
+                            swift,
+                            "            enum CodingKeys: String, CodingKey {{\n                \
+                             case {}\n            }}",
+                            keys.join(", ")
+                        );
+                    }
+                    swift.push_str("        }\n");
+                    let fields: Vec<String> = params
+                        .iter()
+                        .map(|(name, _)| format!("{0}: {0}", swift_name(name)))
+                        .collect();
+                    format!("Params({})", fields.join(", "))
+                }
+            };
+            let call = format!(
+                "try await rpc({:?}, {}, read: {})",
+                info.name,
+                value,
+                info.kind == MethodKind::Read
+            );
+            match returns {
+                RustType::Unit => {
+                    let _ = writeln!(swift, "        _ = {}", call);
+                }
+                _ => {
+                    let _ = writeln!(
+                        swift,
+                        "        let data = {}\n        \
+                         return try JSONDecoder().decode({}.self, from: data)",
+                        call,
+                        returns.swift()
+                    );
+                }
+            }
+            swift.push_str("    }\n");
+        }
+        swift.push_str(SWIFT_RUNTIME);
+        swift.push_str("}\n");
+        swift
+    }
+
+    /// Write the source to `dir`, creating it if needed.
+    pub fn write_to(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(self.file_name()), self.source())
+    }
+}
+
+const SWIFT_PRELUDE: &str = r#"import Foundation
+#if canImport(FoundationNetworking)
+import FoundationNetworking
+#endif
+
+/// A call answered with an error status, after any retries.
+public struct CallError: Error, CustomStringConvertible {
+    public let method: String
+    public let status: Int
+    public let body: String
+
+    public var description: String { "\(method) failed with \(status): \(body)" }
+}
+
+/// A Rust `Result`, serialized as `{"Ok": ...}` or `{"Err": ...}`.
+public enum ActorResult<Success: Codable, Failure: Codable>: Codable {
+    case ok(Success)
+    case err(Failure)
+
+    private enum CodingKeys: String, CodingKey {
+        case ok = "Ok"
+        case err = "Err"
+    }
+
+    public init(from decoder: Decoder) throws {
+        let container = try decoder.container(keyedBy: CodingKeys.self)
+        if container.contains(.ok) {
+            self = .ok(try container.decode(Success.self, forKey: .ok))
+        } else {
+            self = .err(try container.decode(Failure.self, forKey: .err))
+        }
+    }
+
+    public func encode(to encoder: Encoder) throws {
+        var container = encoder.container(keyedBy: CodingKeys.self)
+        switch self {
+        case .ok(let value): try container.encode(value, forKey: .ok)
+        case .err(let error): try container.encode(error, forKey: .err)
+        }
+    }
+}
+
+/// Any JSON value, for types the generator doesn't know.
+public enum JSONValue: Codable, Equatable {
+    case null
+    case bool(Bool)
+    case number(Double)
+    case string(String)
+    case array([JSONValue])
+    case object([String: JSONValue])
+
+    public init(from decoder: Decoder) throws {
+        let container = try decoder.singleValueContainer()
+        if container.decodeNil() {
+            self = .null
+        } else if let value = try? container.decode(Bool.self) {
+            self = .bool(value)
+        } else if let value = try? container.decode(Double.self) {
+            self = .number(value)
+        } else if let value = try? container.decode(String.self) {
+            self = .string(value)
+        } else if let value = try? container.decode([JSONValue].self) {
+            self = .array(value)
+        } else {
+            self = .object(try container.decode([String: JSONValue].self))
+        }
+    }
+
+    public func encode(to encoder: Encoder) throws {
+        var container = encoder.singleValueContainer()
+        switch self {
+        case .null: try container.encodeNil()
+        case .bool(let value): try container.encode(value)
+        case .number(let value): try container.encode(value)
+        case .string(let value): try container.encode(value)
+        case .array(let value): try container.encode(value)
+        case .object(let value): try container.encode(value)
+        }
+    }
+}
+"#;
+
+const SWIFT_RUNTIME: &str = r#"
+    // Calls refused with 429 or 503 are retried, honouring Retry-After; read methods are also
+    // retried after network errors
+    private func rpc<Params: Encodable>(_ method: String, _ params: Params, read: Bool) async throws -> Data {
+        var request = URLRequest(url: url.appendingPathComponent(method))
+        request.httpMethod = "POST"
+        request.setValue("application/json", forHTTPHeaderField: "Content-Type")
+        for (name, value) in headers {
+            request.setValue(value, forHTTPHeaderField: name)
+        }
+        request.httpBody = try JSONEncoder().encode(params)
+        var attempt = 0
+        while true {
+            let backoff = retryDelay * pow(2, Double(attempt))
+            let result: (Data, URLResponse)
+            do {
+                result = try await session.data(for: request)
+            } catch {
+                // A write may have run before the connection failed
+                if !read || attempt >= retries || error is CancellationError {
+                    throw error
+                }
+                try await Task.sleep(nanoseconds: UInt64(backoff * 1_000_000_000))
+                attempt += 1
+                continue
+            }
+            let (data, response) = result
+            let http = response as? HTTPURLResponse
+            let status = http?.statusCode ?? 0
+            if (200..<300).contains(status) {
+                return data
+            }
+            if (status == 429 || status == 503) && attempt < retries {
+                let retryAfter = http?.value(forHTTPHeaderField: "Retry-After").flatMap(Double.init)
+                try await Task.sleep(nanoseconds: UInt64((retryAfter ?? backoff) * 1_000_000_000))
+                attempt += 1
+                continue
+            }
+            throw CallError(method: method, status: status, body: String(decoding: data, as: UTF8.self))
+        }
+    }
+"#;
+
+/// The parameters of `info` sent in the body, by name, with their types.
+fn body_params(info: &MethodInfo) -> Vec<(&'static str, RustType)> {
+    info.params
+        .iter()
+        .filter(|param| param.source == ParamSource::Body)
+        .map(|param| (param.name, RustType::parse(param.ty)))
+        .collect()
+}
+
+/// `name` in lowerCamelCase: `user_id` becomes `userId`.
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    for (i, word) in name.split('_').filter(|word| !word.is_empty()).enumerate() {
+        let mut chars = word.chars();
+        match (i, chars.next()) {
+            (0, _) => camel.push_str(word),
+            (_, Some(first)) => {
+                camel.extend(first.to_uppercase());
+                camel.push_str(chars.as_str());
+            }
+            (_, None) => {}
+        }
+    }
+    match camel.is_empty() {
+        true => name.to_string(),
+        false => camel,
+    }
+}
+
+/// `name` as a method of a generated mobile client, which mustn't be one of `reserved`.
+fn mobile_method(name: &str, reserved: &[&str]) -> String {
+    let name = camel_case(name);
+    match reserved.contains(&name.as_str()) {
+        true => format!("{}_", name),
+        false => name,
+    }
+}
+
+/// `name` in lowerCamelCase, quoted if it is a Kotlin keyword.
+fn kotlin_name(name: &str) -> String {
+    let name = camel_case(name);
+    match KOTLIN_KEYWORDS.contains(&name.as_str()) {
+        true => format!("`{}`", name),
+        false => name,
+    }
+}
+
+/// `name` in lowerCamelCase, quoted if it is a Swift keyword.
+fn swift_name(name: &str) -> String {
+    let name = camel_case(name);
+    match SWIFT_KEYWORDS.contains(&name.as_str()) {
+        true => format!("`{}`", name),
+        false => name,
+    }
+}
+
+/// The JSON shape of a Rust type, as written in a method signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RustType {
+    String,
+    Integer,
+    Float,
+    Bool,
+    Unit,
+    /// `serde_json::Value`: any JSON.
+    Json,
+    Option(Box<RustType>),
+    List(Box<RustType>),
+    /// A map with string keys.
+    Map(Box<RustType>),
+    Tuple(Vec<RustType>),
+    /// Serialized as `{"Ok": ...}` or `{"Err": ...}`.
+    Result(Box<RustType>, Box<RustType>),
+    /// A type the generator doesn't know, such as one of the actor's own structs.
+    Unknown(String),
+}
+
+impl RustType {
+    /// Parse `ty`, as the `#[actor]` macro records it.
+    pub(crate) fn parse(ty: &str) -> Self {
+        let tokens = tokenize(ty);
+        let mut parser = TypeParser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        match parser.ty() {
+            Some(parsed) if parser.pos == tokens.len() => parsed,
+            _ => RustType::Unknown(ty.to_string()),
+        }
+    }
+
+    /// The equivalent TypeScript type.
+    pub(crate) fn typescript(&self) -> String {
+        match self {
+            RustType::String => "string".to_string(),
+            RustType::Integer | RustType::Float => "number".to_string(),
+            RustType::Bool => "boolean".to_string(),
+            RustType::Unit => "null".to_string(),
+            RustType::Json | RustType::Unknown(_) => "unknown".to_string(),
+            RustType::Option(inner) => format!("{} | null", inner.typescript()),
+            RustType::List(item) => match **item {
+                RustType::Option(_) | RustType::Result(..) => format!("({})[]", item.typescript()),
+                _ => format!("{}[]", item.typescript()),
+            },
+            RustType::Map(value) => format!("Record<string, {}>", value.typescript()),
+            RustType::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(RustType::typescript).collect();
+                format!("[{}]", items.join(", "))
+            }
+            RustType::Result(ok, err) => format!(
+                "{{ Ok: {} }} | {{ Err: {} }}",
+                ok.typescript(),
+                err.typescript()
+            ),
+        }
+    }
+
+    /// The equivalent Kotlin type, for kotlinx.serialization.  `()` is only written this way
+    /// inside other types; methods returning it return `Unit`.
+    pub(crate) fn kotlin(&self) -> String {
+        match self {
+            RustType::String => "String".to_string(),
+            RustType::Integer => "Long".to_string(),
+            RustType::Float => "Double".to_string(),
+            RustType::Bool => "Boolean".to_string(),
+            RustType::Unit | RustType::Json | RustType::Unknown(_) => "JsonElement".to_string(),
+            RustType::Option(inner) => nullable(inner.kotlin()),
+            RustType::List(item) => format!("List<{}>", item.kotlin()),
+            RustType::Map(value) => format!("Map<String, {}>", value.kotlin()),
+            RustType::Tuple(_) => "JsonArray".to_string(),
+            RustType::Result(ok, err) => format!("ActorResult<{}, {}>", ok.kotlin(), err.kotlin()),
+        }
+    }
+
+    /// The equivalent Swift type, for `Codable`.  `()` is only written this way inside other
+    /// types; methods returning it return nothing.
+    pub(crate) fn swift(&self) -> String {
+        match self {
+            RustType::String => "String".to_string(),
+            RustType::Integer => "Int".to_string(),
+            RustType::Float => "Double".to_string(),
+            RustType::Bool => "Bool".to_string(),
+            RustType::Unit | RustType::Json | RustType::Unknown(_) => "JSONValue".to_string(),
+            RustType::Option(inner) => nullable(inner.swift()),
+            RustType::List(item) => format!("[{}]", item.swift()),
+            RustType::Map(value) => format!("[String: {}]", value.swift()),
+            RustType::Tuple(_) => "[JSONValue]".to_string(),
+            RustType::Result(ok, err) => format!("ActorResult<{}, {}>", ok.swift(), err.swift()),
+        }
+    }
+}
+
+/// `ty` made nullable, unless it already is: JSON can't tell `Some(None)` from `None`.
+fn nullable(ty: String) -> String {
+    match ty.ends_with('?') {
+        true => ty,
+        false => ty + "?",
+    }
+}
+
+/// Split a type into identifiers, lifetimes and punctuation.
+fn tokenize(ty: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = ty.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' || c == '\'' {
+            let mut token = c.to_string();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            tokens.push(c.to_string());
+        }
+    }
+    tokens
+}
+
+struct TypeParser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl TypeParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let matched = self.peek() == Some(token);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn ty(&mut self) -> Option<RustType> {
+        if self.eat("&") {
+            if self.peek().is_some_and(|token| token.starts_with('\'')) {
+                self.pos += 1;
+            }
+            self.eat("mut");
+            return self.ty();
+        }
+        if self.eat("(") {
+            let mut items = Vec::new();
+            while !self.eat(")") {
+                items.push(self.ty()?);
+                if !self.eat(",") && self.peek() != Some(")") {
+                    return None;
+                }
+            }
+            return Some(match items.len() {
+                0 => RustType::Unit,
+                _ => RustType::Tuple(items),
+            });
+        }
+        if self.eat("[") {
+            let item = self.ty()?;
+            // Array lengths don't change the JSON
+            if self.eat(";") {
+                while self.peek().is_some_and(|token| token != "]") {
+                    self.pos += 1;
+                }
+            }
+            self.eat("]").then_some(())?;
+            return Some(RustType::List(Box::new(item)));
+        }
+        self.path()
+    }
+
+    /// A path such as `std::collections::HashMap<String, u32>`, by its last segment.
+    fn path(&mut self) -> Option<RustType> {
+        loop {
+            let name = self.peek()?.to_string();
+            if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                return None;
+            }
+            self.pos += 1;
+            let mut args = Vec::new();
+            if self.eat("<") {
+                while !self.eat(">") {
+                    if self.peek().is_some_and(|token| token.starts_with('\'')) {
+                        self.pos += 1;
+                    } else {
+                        args.push(self.ty()?);
+                    }
+                    if !self.eat(",") && self.peek() != Some(">") {
+                        return None;
+                    }
+                }
+            }
+            if self.eat(":") {
+                self.eat(":").then_some(())?;
+                continue;
+            }
+            return Some(Self::named(&name, args));
+        }
+    }
+
+    fn named(name: &str, mut args: Vec<RustType>) -> RustType {
+        let mut arg = |i: usize| {
+            Box::new(match args.get_mut(i) {
+                Some(arg) => std::mem::replace(arg, RustType::Unit),
+                None => RustType::Unknown(String::new()),
+            })
+        };
+        match name {
+            "String" | "str" | "char" | "Cow" | "Url" | "EmailAddress" | "PhoneNumber" | "Uuid"
+            | "DateTime" | "NaiveDate" | "NaiveDateTime" | "NaiveTime" | "Decimal" => {
+                RustType::String
+            }
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64"
+            | "u128" | "usize" => RustType::Integer,
+            "f32" | "f64" => RustType::Float,
+            "bool" => RustType::Bool,
+            "Value" => RustType::Json,
+            "Option" => RustType::Option(arg(0)),
+            "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => RustType::List(arg(0)),
+            "HashMap" | "BTreeMap" => RustType::Map(arg(1)),
+            "Box" | "Arc" | "Rc" => *arg(0),
+            "Result" => RustType::Result(arg(0), arg(1)),
+            _ => RustType::Unknown(name.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typescript_types() {
+        for (rust, typescript) in [
+            ("String", "string"),
+            ("& 'a str", "string"),
+            ("u64", "number"),
+            ("()", "null"),
+            ("Option < Vec < f64 > >", "number[] | null"),
+            ("Vec < Option < bool > >", "(boolean | null)[]"),
+            (
+                "std :: collections :: HashMap < String , (u32 , String) >",
+                "Record<string, [number, string]>",
+            ),
+            ("[u8 ; 32]", "number[]"),
+            (
+                "Result < Arc < String > , String >",
+                "{ Ok: string } | { Err: string }",
+            ),
+            ("serde_json :: Value", "unknown"),
+            ("Inventory", "unknown"),
+            ("impl Trait", "unknown"),
+        ] {
+            assert_eq!(RustType::parse(rust).typescript(), typescript, "{}", rust);
+        }
+    }
+
+    #[test]
+    fn test_mobile_types() {
+        for (rust, kotlin, swift) in [
+            ("String", "String", "String"),
+            ("u64", "Long", "Int"),
+            ("Option < Option < f32 > >", "Double?", "Double?"),
+            ("Vec < Option < bool > >", "List<Boolean?>", "[Bool?]"),
+            (
+                "BTreeMap < String , Vec < u8 > >",
+                "Map<String, List<Long>>",
+                "[String: [Int]]",
+            ),
+            ("(u32 , String)", "JsonArray", "[JSONValue]"),
+            (
+                "Result < () , String >",
+                "ActorResult<JsonElement, String>",
+                "ActorResult<JSONValue, String>",
+            ),
+            ("Inventory", "JsonElement", "JSONValue"),
+        ] {
+            let ty = RustType::parse(rust);
+            assert_eq!(ty.kotlin(), kotlin, "{}", rust);
+            assert_eq!(ty.swift(), swift, "{}", rust);
+        }
+        assert_eq!(camel_case("max_len"), "maxLen");
+        assert_eq!(camel_case("get_id_2"), "getId2");
+        assert_eq!(camel_case("_"), "_");
+        assert_eq!(kotlin_name("in_"), "`in`");
+        assert_eq!(swift_name("default"), "`default`");
+        assert_eq!(mobile_method("send", SWIFT_RESERVED), "send_");
+    }
+
+    #[test]
+    fn test_doc_comments_are_escaped() {
+        assert_eq!(js_doc(""), "");
+        assert_eq!(
+            js_doc("Add things\n\nEnds a comment: */"),
+            "  /**\n   * Add things\n   *\n   * Ends a comment: *\\/\n   */\n"
+        );
+        assert_eq!(js_name("call"), "call_");
+        assert_eq!(js_name("greet"), "greet");
+    }
+}
@@ -0,0 +1,65 @@
+// Types of the runtime shared by clients generated with simple_json_server's `sdk::JsClient`.
+
+export interface ClientOptions {
+  /**
+   * Where subscriptions connect: a server started with `ServerOptions::websocket` and the
+   * actor's event bus.  By default the client's URL with a `ws` scheme.
+   */
+  websocketUrl?: string;
+  /** How many times a call is retried; 3 by default. */
+  retries?: number;
+  /** Milliseconds before the first retry, doubling after each; 200 by default. */
+  retryDelay?: number;
+  /** Headers sent with every call, such as `Authorization`. */
+  headers?: Record<string, string>;
+  /** The fetch implementation; the global one by default. */
+  fetch?: typeof fetch;
+  /** The WebSocket implementation, such as the `ws` package's on Node before 22. */
+  WebSocket?: unknown;
+}
+
+export interface CallOptions {
+  /** Aborts the call, including any wait before a retry. */
+  signal?: AbortSignal;
+  /** Headers sent with this call only. */
+  headers?: Record<string, string>;
+}
+
+export interface SubscribeOptions {
+  /** Ends the subscription. */
+  signal?: AbortSignal;
+  /** Subscribe under this id, so events are acknowledged and resent after a reconnect. */
+  subscriber?: string;
+  /** Replay the logged events after this sequence number first. */
+  since?: number;
+}
+
+/** An event as sent by the server. */
+export interface EventFrame<T = unknown> {
+  event: string;
+  data: T;
+  seq?: number;
+}
+
+/** A call answered with an error status, after any retries. */
+export declare class CallError extends Error {
+  readonly method: string;
+  readonly status: number;
+  readonly body: unknown;
+}
+
+export declare class ActorClient {
+  constructor(url: string, options?: ClientOptions);
+  call<T = unknown>(
+    method: string,
+    params?: object,
+    options?: CallOptions,
+    kind?: "read" | "write",
+  ): Promise<T>;
+  subscribe<T = unknown>(
+    topic: string,
+    listener: (data: T, event: EventFrame<T>) => void,
+    options?: SubscribeOptions,
+  ): () => void;
+  close(): void;
+}
@@ -0,0 +1,201 @@
+// The runtime shared by clients generated with simple_json_server's `sdk::JsClient`.  Unary
+// calls are sent with fetch; event subscriptions share one WebSocket per client.
+
+/** A call answered with an error status, after any retries. */
+export class CallError extends Error {
+  constructor(method, status, body) {
+    const detail = typeof body === "string" ? body : JSON.stringify(body);
+    super(`${method} failed with ${status}: ${detail}`);
+    this.name = "CallError";
+    this.method = method;
+    this.status = status;
+    this.body = body;
+  }
+}
+
+// Refused before the method ran, so safe to send again
+const RETRY_STATUSES = new Set([429, 503]);
+
+function sleep(ms, signal) {
+  return new Promise((resolve, reject) => {
+    if (signal?.aborted) {
+      reject(signal.reason);
+      return;
+    }
+    const onAbort = () => {
+      clearTimeout(timer);
+      reject(signal.reason);
+    };
+    const timer = setTimeout(() => {
+      signal?.removeEventListener("abort", onAbort);
+      resolve();
+    }, ms);
+    signal?.addEventListener("abort", onAbort, { once: true });
+  });
+}
+
+export class ActorClient {
+  constructor(url, options = {}) {
+    this.url = url.replace(/\/+$/, "");
+    this.websocketUrl = options.websocketUrl ?? this.url.replace(/^http/, "ws");
+    this.retries = options.retries ?? 3;
+    this.retryDelay = options.retryDelay ?? 200;
+    this.headers = options.headers ?? {};
+    this.fetch = options.fetch ?? globalThis.fetch.bind(globalThis);
+    this.WebSocket = options.WebSocket ?? globalThis.WebSocket;
+    this.topics = new Map();
+    this.socket = null;
+    this.reconnects = 0;
+  }
+
+  /**
+   * Call `method` with `params`.  Calls refused with 429 or 503 are retried, honouring
+   * `Retry-After`; read methods are also retried after network errors.
+   */
+  async call(method, params = {}, options = {}, kind = "write") {
+    const { signal, headers } = options;
+    for (let attempt = 0; ; attempt++) {
+      const delay = this.retryDelay * 2 ** attempt;
+      let response;
+      try {
+        response = await this.fetch(`${this.url}/${method}`, {
+          method: "POST",
+          headers: { "Content-Type": "application/json", ...this.headers, ...headers },
+          body: JSON.stringify(params),
+          signal,
+        });
+      } catch (error) {
+        // A write may have run before the connection failed
+        if (signal?.aborted || kind !== "read" || attempt >= this.retries) {
+          throw error;
+        }
+        await sleep(delay, signal);
+        continue;
+      }
+      const text = await response.text();
+      let body;
+      try {
+        body = text ? JSON.parse(text) : null;
+      } catch {
+        body = text;
+      }
+      if (response.ok) {
+        return body;
+      }
+      if (RETRY_STATUSES.has(response.status) && attempt < this.retries) {
+        const retryAfter = Number(response.headers.get("Retry-After"));
+        await sleep(retryAfter > 0 ? retryAfter * 1000 : delay, signal);
+        continue;
+      }
+      throw new CallError(method, response.status, body);
+    }
+  }
+
+  /**
+   * Call `listener(data, event)` with every event published on `topic`, until the returned
+   * function is called or `options.signal` aborts.  All subscriptions of a client share one
+   * WebSocket, which reconnects and subscribes again if it drops.
+   */
+  subscribe(topic, listener, options = {}) {
+    const { signal, subscriber, since } = options;
+    let entry = this.topics.get(topic);
+    if (!entry) {
+      entry = { listeners: new Set(), subscriber, since };
+      this.topics.set(topic, entry);
+      this.#send(this.#subscribeFrame(topic, entry));
+    }
+    entry.listeners.add(listener);
+    this.#connect();
+
+    const unsubscribe = () => {
+      signal?.removeEventListener("abort", unsubscribe);
+      if (!entry.listeners.delete(listener) || entry.listeners.size > 0) {
+        return;
+      }
+      this.topics.delete(topic);
+      this.#send({ unsubscribe: topic });
+      if (this.topics.size === 0) {
+        this.close();
+      }
+    };
+    if (signal?.aborted) {
+      unsubscribe();
+    }
+    signal?.addEventListener("abort", unsubscribe, { once: true });
+    return unsubscribe;
+  }
+
+  /** Close the WebSocket, dropping every subscription. */
+  close() {
+    this.topics.clear();
+    const socket = this.socket;
+    this.socket = null;
+    socket?.close();
+  }
+
+  #subscribeFrame(topic, entry) {
+    const frame = { subscribe: topic };
+    if (entry.subscriber !== undefined) {
+      frame.subscriber = entry.subscriber;
+    }
+    if (entry.since !== undefined) {
+      frame.since = entry.since;
+    }
+    return frame;
+  }
+
+  #send(frame) {
+    if (this.socket?.readyState === 1) {
+      this.socket.send(JSON.stringify(frame));
+    }
+  }
+
+  #connect() {
+    if (this.socket || this.topics.size === 0) {
+      return;
+    }
+    if (!this.WebSocket) {
+      throw new Error("No WebSocket implementation; pass one as the `WebSocket` option");
+    }
+    const socket = new this.WebSocket(this.websocketUrl);
+    this.socket = socket;
+    socket.onopen = () => {
+      this.reconnects = 0;
+      for (const [topic, entry] of this.topics) {
+        this.#send(this.#subscribeFrame(topic, entry));
+      }
+    };
+    socket.onmessage = (message) => {
+      let frame;
+      try {
+        frame = JSON.parse(message.data);
+      } catch {
+        return;
+      }
+      const entry = frame.event !== undefined && this.topics.get(frame.event);
+      if (!entry) {
+        return;
+      }
+      if (frame.seq !== undefined) {
+        // Resubscribing after a reconnect picks up where this left off
+        if (entry.since !== undefined) {
+          entry.since = frame.seq;
+        }
+        if (entry.subscriber !== undefined) {
+          this.#send({ ack: frame.seq });
+        }
+      }
+      for (const listener of entry.listeners) {
+        listener(frame.data, frame);
+      }
+    };
+    socket.onclose = () => {
+      if (this.socket !== socket) {
+        return;
+      }
+      this.socket = null;
+      const delay = Math.min(this.retryDelay * 2 ** this.reconnects++, 30000);
+      setTimeout(() => this.#connect(), delay);
+    };
+  }
+}
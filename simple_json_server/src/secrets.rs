@@ -0,0 +1,367 @@
+//! Secrets read from the environment, files or a secrets manager, rather than written into code.
+//!
+//! A [`Secret`] says where a secret lives: an environment variable ([`Secret::env`]), a file
+//! ([`Secret::file`]), or a [`SecretStore`] such as Vault or AWS Secrets Manager
+//! ([`Secret::store`]).  Servers take their TLS certificate and key with
+//! [`TlsConfig::from_secrets`](crate::TlsConfig::from_secrets) and their admin token with
+//! [`ServerOptions::admin_token_secret`](crate::ServerOptions::admin_token_secret), fetching
+//! them when they start; a server that can't fetch them fails to start.
+//!
+//! ```rust,no_run
+//! use simple_json_server::secrets::Secret;
+//! use simple_json_server::{actor, Actor, ServerOptions, TlsConfig};
+//! use std::time::Duration;
+//!
+//! struct Calculator;
+//!
+//! #[actor]
+//! impl Calculator {
+//!     pub async fn add(&self, a: i32, b: i32) -> i32 {
+//!         a + b
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let every_hour = Duration::from_secs(60 * 60);
+//! let tls = TlsConfig::from_secrets(
+//!     Secret::file("/etc/calculator/cert.pem").refresh(every_hour),
+//!     Secret::file("/etc/calculator/key.pem").refresh(every_hour),
+//! );
+//! let token = Secret::env("CALCULATOR_ADMIN_TOKEN")
+//!     .on_rotate(|_| println!("The admin token changed"));
+//! Calculator.create_with(ServerOptions::new(8443).tls(tls).admin_token_secret(token));
+//! # }
+//! ```
+//!
+//! Files must not be readable or writable by other users (on Unix, mode `0600` or `0400`)
+//! unless [`Secret::any_permissions`] says otherwise, and lose one trailing newline when read.
+//!
+//! A secret given a [`Secret::refresh`] interval is fetched again that often while the server
+//! runs.  When its value changes the server switches to the new one (a new certificate is used
+//! for new connections) and calls the secret's [`Secret::on_rotate`] callbacks; a value that
+//! can't be fetched or used is logged and the old one kept.  [`Secret::watch`] does the same
+//! for secrets used elsewhere, e.g. to rotate a [`Signing`](crate::signing::Signing) secret
+//! with [`Signing::set_secret`](crate::signing::Signing::set_secret).
+//!
+//! Secrets managers are supported by implementing [`SecretStore`]; [`StaticSecrets`] keeps
+//! secrets set from code.
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Why a secret couldn't be fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretError {
+    /// The secret doesn't exist: an unset variable, a missing file or an unknown name.
+    Missing(String),
+    /// The file holding the secret can be read or written by other users.
+    Exposed(String),
+    /// The secrets manager failed to answer.
+    Store(String),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Missing(e) => write!(f, "Secret not found: {}", e),
+            SecretError::Exposed(e) => write!(f, "Secret is exposed: {}", e),
+            SecretError::Store(e) => write!(f, "Secret store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// A secrets manager, such as Vault or AWS Secrets Manager.
+pub trait SecretStore: Send + Sync {
+    /// The current value of the secret `name`.
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<u8>, SecretError>>;
+}
+
+/// Secrets set from code, e.g. in tests.  Clones share the same secrets.
+#[derive(Debug, Clone, Default)]
+pub struct StaticSecrets {
+    secrets: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+}
+
+impl StaticSecrets {
+    /// A store with no secrets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the secret `name` to `value`.
+    pub fn set(&self, name: impl Into<String>, value: impl Into<Vec<u8>>) {
+        self.secrets
+            .write()
+            .unwrap()
+            .insert(name.into(), value.into());
+    }
+
+    /// Remove the secret `name`.
+    pub fn remove(&self, name: &str) {
+        self.secrets.write().unwrap().remove(name);
+    }
+}
+
+impl SecretStore for StaticSecrets {
+    fn fetch<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Vec<u8>, SecretError>> {
+        let value = self.secrets.read().unwrap().get(name).cloned();
+        async move { value.ok_or_else(|| SecretError::Missing(name.to_string())) }.boxed()
+    }
+}
+
+type Callback = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+#[derive(Clone)]
+enum Source {
+    Env(String),
+    File { path: PathBuf, checked: bool },
+    Store(Arc<dyn SecretStore>, String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "${}", var),
+            Source::File { path, .. } => write!(f, "{}", path.display()),
+            Source::Store(_, name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Where a secret lives, how often to fetch it again, and who to tell when it changes; see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct Secret {
+    source: Source,
+    refresh: Option<Duration>,
+    on_rotate: Vec<Callback>,
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secret")
+            .field("source", &self.source.to_string())
+            .field("refresh", &self.refresh)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Secret {
+    fn new(source: Source) -> Self {
+        Self {
+            source,
+            refresh: None,
+            on_rotate: Vec::new(),
+        }
+    }
+
+    /// The value of the environment variable `var`.
+    pub fn env(var: impl Into<String>) -> Self {
+        Self::new(Source::Env(var.into()))
+    }
+
+    /// The contents of the file at `path`, which other users must not be able to read or write.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::new(Source::File {
+            path: path.into(),
+            checked: true,
+        })
+    }
+
+    /// The secret `name` in `store`.
+    pub fn store(store: impl SecretStore + 'static, name: impl Into<String>) -> Self {
+        Self::new(Source::Store(Arc::new(store), name.into()))
+    }
+
+    /// Read a file whatever its permissions, e.g. one mounted by Kubernetes with its default
+    /// mode of `0644`.
+    pub fn any_permissions(mut self) -> Self {
+        if let Source::File { checked, .. } = &mut self.source {
+            *checked = false;
+        }
+        self
+    }
+
+    /// Fetch the secret again every `every` while it is in use.
+    pub fn refresh(mut self, every: Duration) -> Self {
+        self.refresh = Some(every);
+        self
+    }
+
+    /// Call `callback` with the new value whenever the secret rotates.
+    pub fn on_rotate(mut self, callback: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        self.on_rotate.push(Arc::new(callback));
+        self
+    }
+
+    /// How often the secret is fetched again, if at all.
+    pub(crate) fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh
+    }
+
+    /// The secret's current value.
+    pub async fn fetch(&self) -> Result<Vec<u8>, SecretError> {
+        match &self.source {
+            Source::Env(var) => std::env::var_os(var)
+                .map(|value| value.into_encoded_bytes())
+                .ok_or_else(|| SecretError::Missing(format!("${} is not set", var))),
+            Source::File { path, checked } => {
+                let missing =
+                    |e: std::io::Error| SecretError::Missing(format!("{}: {}", path.display(), e));
+                if *checked {
+                    check_permissions(&tokio::fs::metadata(path).await.map_err(missing)?)
+                        .map_err(|mode| {
+                            SecretError::Exposed(format!(
+                                "{} has mode {:o}; other users must not be able to read or write it",
+                                path.display(),
+                                mode
+                            ))
+                        })?;
+                }
+                let mut value = tokio::fs::read(path).await.map_err(missing)?;
+                if value.last() == Some(&b'\n') {
+                    value.pop();
+                    if value.last() == Some(&b'\r') {
+                        value.pop();
+                    }
+                }
+                Ok(value)
+            }
+            Source::Store(store, name) => store.fetch(name).await,
+        }
+    }
+
+    /// Fetch the secret every refresh interval for as long as the returned future runs, calling
+    /// the [`Secret::on_rotate`] callbacks whenever it changes.  Returns at once, having done
+    /// nothing, without a refresh interval.
+    pub async fn watch(&self) {
+        if self.refresh.is_none() {
+            return;
+        }
+        let current = match self.fetch().await {
+            Ok(value) => value,
+            Err(e) => {
+                log::warn!("Failed to fetch secret {}: {}", self.source, e);
+                Vec::new()
+            }
+        };
+        self.watch_from(current, |_| Ok(())).await
+    }
+
+    /// Fetch the secret every refresh interval, and when it differs from `current` pass it to
+    /// `apply` and, if that accepts it, to the [`Secret::on_rotate`] callbacks.
+    pub(crate) async fn watch_from(
+        &self,
+        mut current: Vec<u8>,
+        mut apply: impl FnMut(&[u8]) -> Result<(), String>,
+    ) {
+        let Some(every) = self.refresh else {
+            return;
+        };
+        loop {
+            tokio::time::sleep(every).await;
+            let value = match self.fetch().await {
+                Ok(value) if value != current => value,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Failed to fetch secret {}: {}", self.source, e);
+                    continue;
+                }
+            };
+            if let Err(e) = apply(&value) {
+                log::warn!("Kept the old value of secret {}: {}", self.source, e);
+                continue;
+            }
+            log::info!("Secret {} rotated", self.source);
+            self.rotated(&value);
+            current = value;
+        }
+    }
+
+    /// Call the [`Secret::on_rotate`] callbacks with `value`.
+    pub(crate) fn rotated(&self, value: &[u8]) {
+        for callback in &self.on_rotate {
+            callback(value);
+        }
+    }
+}
+
+/// `Err` with the file's mode when other users may read or write it.
+#[cfg(unix)]
+fn check_permissions(metadata: &std::fs::Metadata) -> Result<(), u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        return Err(mode);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_metadata: &std::fs::Metadata) -> Result<(), u32> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_files_readable_by_others_are_refused() {
+        let path = std::env::temp_dir().join(format!("sjs_secret_{}", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                Secret::file(&path).fetch().await,
+                Err(SecretError::Exposed(_))
+            ));
+            assert_eq!(
+                Secret::file(&path).any_permissions().fetch().await.unwrap(),
+                b"hunter2"
+            );
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert_eq!(Secret::file(&path).fetch().await.unwrap(), b"hunter2");
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            Secret::file(&path).fetch().await,
+            Err(SecretError::Missing(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_watching_reports_rotations() {
+        let store = StaticSecrets::new();
+        store.set("token", "one");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let secret = Secret::store(store.clone(), "token")
+            .refresh(Duration::from_millis(20))
+            .on_rotate({
+                let seen = Arc::clone(&seen);
+                move |value| seen.lock().unwrap().push(value.to_vec())
+            });
+        let watch = tokio::spawn(async move { secret.watch().await });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(seen.lock().unwrap().is_empty());
+        // A secret that disappears keeps its old value
+        store.remove("token");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        store.set("token", "two");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(*seen.lock().unwrap(), [b"two".to_vec()]);
+        watch.abort();
+    }
+}
@@ -0,0 +1,220 @@
+//! Security response headers and CORS, as presets.
+//!
+//! By default a server sends no security headers and allows cross-origin calls from anywhere
+//! (`Access-Control-Allow-Origin: *`).  [`SecurityPreset::strict`] is a sane baseline for a
+//! public API in one call:
+//!
+//! - `Strict-Transport-Security: max-age=31536000; includeSubDomains` (over TLS only, where
+//!   browsers honour it);
+//! - `X-Frame-Options: DENY` and `Content-Security-Policy: default-src 'none';
+//!   frame-ancestors 'none'`;
+//! - `Referrer-Policy: no-referrer`;
+//! - `X-Content-Type-Options: nosniff`;
+//! - no cross-origin calls, except from origins allowed with [`SecurityPreset::allow_origin`].
+//!
+//! ```rust
+//! use simple_json_server::security::SecurityPreset;
+//! use simple_json_server::ServerOptions;
+//!
+//! let options = ServerOptions::new(8443)
+//!     .security(SecurityPreset::strict().allow_origin("https://app.example.com"));
+//! ```
+//!
+//! Each header can be changed on a preset, or set on [`SecurityPreset::default`] to add only
+//! that one.  The admin API never allows cross-origin calls.
+
+use hyper::header::{self, HeaderMap, HeaderValue};
+use std::time::Duration;
+
+/// The request headers a cross-origin caller may send.
+const ALLOW_HEADERS: &str =
+    "Content-Type, X-Request-Deadline, grpc-timeout, X-Priority, X-Request-Id, X-Api-Key, X-Api-Version";
+
+/// The response headers cross-origin callers may read, besides the CORS-safelisted ones.
+const EXPOSE_HEADERS: &str = "Deprecation, Sunset, Warning, Accept-Post";
+
+/// Security headers added to every HTTP response, and which origins may call cross-origin.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityPreset {
+    hsts: Option<Duration>,
+    frame_options: Option<String>,
+    content_security_policy: Option<String>,
+    referrer_policy: Option<String>,
+    nosniff: bool,
+    /// `None` allows any origin; otherwise only those listed.
+    allowed_origins: Option<Vec<String>>,
+}
+
+impl SecurityPreset {
+    /// The secure baseline described in the [module documentation](self).
+    pub fn strict() -> Self {
+        Self {
+            hsts: Some(Duration::from_secs(365 * 24 * 60 * 60)),
+            frame_options: Some("DENY".to_string()),
+            content_security_policy: Some("default-src 'none'; frame-ancestors 'none'".to_string()),
+            referrer_policy: Some("no-referrer".to_string()),
+            nosniff: true,
+            allowed_origins: Some(Vec::new()),
+        }
+    }
+
+    /// Send `Strict-Transport-Security` with this `max-age`, including subdomains.  It is only
+    /// sent by TLS servers.
+    pub fn hsts(mut self, max_age: Duration) -> Self {
+        self.hsts = Some(max_age);
+        self
+    }
+
+    /// Send `X-Frame-Options` with this value, e.g. `DENY` or `SAMEORIGIN`.
+    pub fn frame_options(mut self, value: impl Into<String>) -> Self {
+        self.frame_options = Some(value.into());
+        self
+    }
+
+    /// Send `Content-Security-Policy` with this value.
+    pub fn content_security_policy(mut self, policy: impl Into<String>) -> Self {
+        self.content_security_policy = Some(policy.into());
+        self
+    }
+
+    /// Send `Referrer-Policy` with this value, e.g. `no-referrer`.
+    pub fn referrer_policy(mut self, policy: impl Into<String>) -> Self {
+        self.referrer_policy = Some(policy.into());
+        self
+    }
+
+    /// Send `X-Content-Type-Options: nosniff`.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    /// Allow cross-origin calls from `origin` (e.g. `https://app.example.com`).  Once any origin
+    /// is listed, only listed origins are allowed.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins
+            .get_or_insert_with(Vec::new)
+            .push(origin.into());
+        self
+    }
+
+    /// Allow cross-origin calls from any origin (the default).
+    pub fn allow_any_origin(mut self) -> Self {
+        self.allowed_origins = None;
+        self
+    }
+
+    /// Add the CORS headers for a call from `origin`, if it may call cross-origin.
+    pub(crate) fn apply_cors(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        let allowed = match &self.allowed_origins {
+            None => HeaderValue::from_static("*"),
+            Some(origins) => {
+                headers.append(header::VARY, HeaderValue::from_static("Origin"));
+                match origin {
+                    Some(origin) if origins.iter().any(|o| o.as_bytes() == origin.as_bytes()) => {
+                        origin.clone()
+                    }
+                    _ => return,
+                }
+            }
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("POST, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOW_HEADERS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(EXPOSE_HEADERS),
+        );
+    }
+
+    /// The security headers for every response, for a server with or without TLS.
+    pub(crate) fn headers(&self, tls: bool) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        self.apply(tls, &mut headers);
+        headers
+    }
+
+    /// Add the security headers to a response; `tls` says whether it is sent over TLS.
+    pub(crate) fn apply(&self, tls: bool, headers: &mut HeaderMap) {
+        if let Some(max_age) = self.hsts.filter(|_| tls) {
+            let value = format!("max-age={}; includeSubDomains", max_age.as_secs());
+            insert(headers, header::STRICT_TRANSPORT_SECURITY, &value);
+        }
+        if let Some(value) = &self.frame_options {
+            insert(headers, header::X_FRAME_OPTIONS, value);
+        }
+        if let Some(value) = &self.content_security_policy {
+            insert(headers, header::CONTENT_SECURITY_POLICY, value);
+        }
+        if let Some(value) = &self.referrer_policy {
+            insert(headers, header::REFERRER_POLICY, value);
+        }
+        if self.nosniff {
+            insert(headers, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        }
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        }
+        Err(_) => log::warn!("Invalid value for {}: {:?}", name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_allows_any_origin() {
+        let mut headers = HeaderMap::new();
+        SecurityPreset::default().apply_cors(None, &mut headers);
+        SecurityPreset::default().apply(true, &mut headers);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none());
+    }
+
+    #[test]
+    fn test_strict_headers() {
+        let strict = SecurityPreset::strict();
+        let mut headers = HeaderMap::new();
+        strict.apply(false, &mut headers);
+        assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none());
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+        strict.apply(true, &mut headers);
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000; includeSubDomains"
+        );
+    }
+
+    #[test]
+    fn test_allowed_origins() {
+        let preset = SecurityPreset::strict().allow_origin("https://app.example.com");
+        let allowed = HeaderValue::from_static("https://app.example.com");
+        let other = HeaderValue::from_static("https://evil.example.com");
+
+        let mut headers = HeaderMap::new();
+        preset.apply_cors(Some(&allowed), &mut headers);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], allowed);
+        assert_eq!(headers[header::VARY], "Origin");
+
+        let mut headers = HeaderMap::new();
+        preset.apply_cors(Some(&other), &mut headers);
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        SecurityPreset::strict().apply_cors(Some(&allowed), &mut headers);
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
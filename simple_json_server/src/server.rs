@@ -0,0 +1,1951 @@
+use crate::admin;
+use crate::backpressure::Outbox;
+use crate::batch;
+use crate::blocking::{BlockingPool, RunError};
+use crate::buffers::Buffer;
+use crate::context;
+use crate::deadline;
+use crate::describe;
+use crate::encryption::EncryptionError;
+use crate::events::{self, Event};
+use crate::fallback::Fallback;
+use crate::fields::{self, Fields};
+use crate::handle::{Lifecycle, Listener, ServerHandle};
+#[cfg(feature = "http3")]
+use crate::http3;
+use crate::ingest;
+use crate::journal::{Journal, JournalEntry};
+use crate::options::Shared;
+use crate::pretty::Format;
+#[cfg(feature = "profiling")]
+use crate::profiling;
+use crate::query;
+use crate::quota::QuotaError;
+use crate::registry::Registration;
+use crate::replication::{self, Role};
+use crate::shedding::Priority;
+use crate::signing::SigningError;
+use crate::socket::SocketOptions;
+use crate::startup::StartupEvent;
+use crate::streaming::{self, Streamed};
+use crate::trace::{self, TraceContext};
+use crate::warmup;
+use crate::{
+    cluster, Actor, ClientError, Deprecation, MethodInfo, MethodKind, ParamSource, RequestContext,
+    ServerOptions,
+};
+use futures_util::stream::FuturesUnordered;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use hyper::service::service_fn;
+use hyper::{HeaderMap, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use hyper_util::server::conn::auto::Builder;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, RwLock};
+use tokio_tungstenite::tungstenite::handshake::server::Request as WsRequest;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message};
+
+/// Everything a connection needs to serve requests: the actor and the options it was started with.
+pub(crate) struct ServerState<T> {
+    pub(crate) actor: Arc<T>,
+    pub(crate) options: ServerOptions,
+    /// The actor's mailbox: calls hold it shared, while `#[write]` methods and pausing the actor
+    /// (e.g. for a snapshot) hold it exclusively.
+    pub(crate) mailbox: RwLock<()>,
+    pub(crate) lifecycle: Arc<Lifecycle>,
+    /// Headers added to every response, built once rather than per request.
+    pub(crate) response_headers: HeaderMap,
+    /// Where `#[blocking]` methods run, if the actor has any.
+    pub(crate) blocking: Option<BlockingPool>,
+}
+
+pub(crate) const JSON: &str = "application/json";
+
+/// The outcome of a call: an HTTP status and a JSON body.  WebSocket clients only see the body.
+pub(crate) struct Reply {
+    pub(crate) status: StatusCode,
+    pub(crate) body: String,
+    /// Seconds the caller should wait before trying again, sent as `Retry-After`.
+    pub(crate) retry_after: Option<u64>,
+    pub(crate) content_type: &'static str,
+    /// The result of a `#[streaming]` method, for the transport to serialize as it sends it.
+    pub(crate) stream: Option<Streamed>,
+}
+
+impl Reply {
+    pub(crate) fn ok(body: String) -> Self {
+        Self {
+            status: StatusCode::OK,
+            body,
+            retry_after: None,
+            content_type: JSON,
+            stream: None,
+        }
+    }
+
+    /// A failure reported as a JSON string, like the errors produced by `dispatch` itself.
+    pub(crate) fn error(status: StatusCode, message: String) -> Self {
+        Self {
+            status,
+            body: serde_json::to_string(&message).unwrap_or_default(),
+            retry_after: None,
+            content_type: JSON,
+            stream: None,
+        }
+    }
+}
+
+/// What the transport knows about a call besides its method and parameters.  Only HTTP
+/// requests carry these, in headers; WebSocket calls use the defaults.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CallMeta {
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) priority: Priority,
+    pub(crate) request_id: Option<String>,
+    /// The client id used for quotas, if quotas are configured.
+    pub(crate) client: Option<String>,
+    /// The payload version from the request headers, if versioning is configured.
+    pub(crate) version: Option<String>,
+    /// The `Idempotency-Key` header, for deduplicating journaled writes.
+    pub(crate) idempotency_key: Option<String>,
+    /// The caller's trace, from the `traceparent` and `tracestate` headers.
+    pub(crate) trace: Option<TraceContext>,
+    /// Whether the transport can send the result of a `#[streaming]` method as it is serialized.
+    pub(crate) streaming: bool,
+    /// The request's headers, for parameters bound with `#[from_header]` and `#[from_cookie]`.
+    pub(crate) headers: HeaderMap,
+    /// Whether the parameters came from the query string of a `GET` request.
+    pub(crate) from_query: bool,
+}
+
+impl CallMeta {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            deadline: deadline::from_headers(headers),
+            priority: Priority::from_headers(headers),
+            request_id: context::request_id_from_headers(headers),
+            client: None,
+            version: None,
+            idempotency_key: headers
+                .get("idempotency-key")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            trace: headers
+                .get(trace::TRACEPARENT)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|traceparent| {
+                    let tracestate = headers.get(trace::TRACESTATE).and_then(|v| v.to_str().ok());
+                    TraceContext::from_headers(traceparent, tracestate)
+                }),
+            streaming: false,
+            headers: headers.clone(),
+            from_query: false,
+        }
+    }
+}
+
+/// What the headers of an HTTP request say about the calls it carries.
+pub(crate) fn call_meta<T: Actor>(state: &ServerState<T>, headers: &HeaderMap) -> CallMeta {
+    let mut meta = CallMeta::from_headers(headers);
+    meta.client = state
+        .options
+        .quotas
+        .as_ref()
+        .and_then(|quotas| quotas.client(headers));
+    meta.version = state
+        .options
+        .versioning
+        .as_ref()
+        .and_then(|versioning| versioning.version(headers));
+    meta
+}
+
+impl<T: Actor> ServerState<T> {
+    pub(crate) fn new(actor: T, options: ServerOptions, lifecycle: Arc<Lifecycle>) -> Self {
+        let tls = options.tls.is_some();
+        #[cfg_attr(not(feature = "http3"), allow(unused_mut))]
+        let mut response_headers = options.security.headers(tls);
+        // Advertise the HTTP/3 listener so clients can switch to it
+        #[cfg(feature = "http3")]
+        if options.http3 && tls {
+            let alt_svc = format!("h3=\":{}\"; ma=86400", options.port);
+            if let Ok(alt_svc) = HeaderValue::from_str(&alt_svc) {
+                response_headers.insert(hyper::header::ALT_SVC, alt_svc);
+            }
+        }
+        let blocking = options.blocking_pool.clone().or_else(|| {
+            let methods = actor.methods();
+            methods
+                .iter()
+                .any(|info| info.blocking)
+                .then(BlockingPool::default)
+        });
+        Self {
+            actor: Arc::new(actor),
+            options,
+            mailbox: RwLock::new(()),
+            lifecycle,
+            response_headers,
+            blocking,
+        }
+    }
+}
+
+impl<T: Actor> ServerState<T> {
+    pub(crate) fn method_info(&self, method: &str) -> Option<&'static MethodInfo> {
+        self.actor.methods().iter().find(|info| info.name == method)
+    }
+
+    /// Whether calls to `method` need the mailbox to themselves.
+    pub(crate) fn is_exclusive(&self, method: &str) -> bool {
+        self.method_info(method).is_some_and(|info| info.exclusive)
+    }
+}
+
+/// Dispatch one call to the actor, whichever transport it arrived on.  The method runs with a
+/// [`RequestContext`]; if it forwarded the call, the downstream actor's response is returned
+/// instead of the method's own.  Calls still running at their deadline are abandoned.
+pub(crate) async fn dispatch<T>(
+    state: &ServerState<T>,
+    method: &str,
+    params: &str,
+    mut meta: CallMeta,
+) -> Reply
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let started = Instant::now();
+    let client = meta.client.clone();
+    // Checked before anything parses the parameters
+    let exceeded = state.options.json_limits.check(params).err();
+    // Checked before versioning, whose migrations may round numbers
+    let rejected = state.options.numbers.rejected(params).map(str::to_string);
+    let upgraded = match (&state.options.versioning, &exceeded) {
+        (Some(versioning), None) => versioning.upgrade(method, params, meta.version.as_deref()),
+        _ => Ok(None),
+    };
+    let params = match &upgraded {
+        Ok(Some(upgraded)) => upgraded.as_str(),
+        _ => params,
+    };
+    // Journaled writes record their response, so they are never streamed
+    let journaled = state.options.journal.is_some()
+        && state
+            .method_info(method)
+            .is_some_and(|info| info.kind == MethodKind::Write);
+    let sensitive = state
+        .method_info(method)
+        .map(|info| {
+            let params = info.params.iter().filter(|param| param.sensitive);
+            params.map(|param| param.name).collect()
+        })
+        .unwrap_or_default();
+    let ctx = RequestContext::new(method, params, state.options.resources.clone())
+        .with_sensitive(sensitive)
+        .with_deadline(meta.deadline)
+        .with_request_id(meta.request_id.take())
+        .with_trace(meta.trace.take())
+        .with_headers(std::mem::take(&mut meta.headers))
+        .with_query(meta.from_query)
+        .with_streaming(meta.streaming && !journaled);
+    // Transports drop the calls of callers that went away, and with them this guard
+    let disconnect = ctx.cancel_on_drop();
+    let reply = match (&upgraded, rejected, exceeded) {
+        (_, _, Some(e)) => Reply::error(StatusCode::BAD_REQUEST, e),
+        (_, Some(number), None) => Reply::error(
+            StatusCode::BAD_REQUEST,
+            format!("The number {} can't be represented exactly", number),
+        ),
+        (Err(e), None, None) => Reply::error(StatusCode::BAD_REQUEST, e.clone()),
+        (Ok(_), None, None) => run_call(state, &ctx, method, params, meta).await,
+    };
+    disconnect.disarm();
+    let elapsed = started.elapsed();
+    if let (Some(quotas), Some(client)) = (&state.options.quotas, &client) {
+        if reply.status != StatusCode::TOO_MANY_REQUESTS {
+            quotas.record_bytes(client, params.len() + reply.body.len());
+        }
+    }
+    if let Some(slow_log) = &state.options.slow_log {
+        slow_log.observe(
+            method,
+            params,
+            ctx.sensitive(),
+            reply.status.as_u16(),
+            elapsed,
+            ctx.queue_wait(),
+        );
+    }
+    if let Some(sampler) = &state.options.sampler {
+        sampler.observe(&ctx, reply.status.as_u16(), elapsed, &reply.body);
+    }
+    // Only the actor's own methods are tracked, so unknown paths cannot flood the statistics
+    if let Some(info) = state.method_info(method) {
+        state.options.stats.record(
+            info.name,
+            elapsed,
+            !reply.status.is_success() || ctx.failed(),
+        );
+    }
+    reply
+}
+
+async fn run_call<T>(
+    state: &ServerState<T>,
+    ctx: &RequestContext,
+    method: &str,
+    params: &str,
+    meta: CallMeta,
+) -> Reply
+where
+    T: Actor + Send + Sync + 'static,
+{
+    if let Some(quotas) = &state.options.quotas {
+        match quotas.admit(meta.client.as_deref()) {
+            Ok(()) => {}
+            Err(QuotaError::MissingClient) => {
+                return Reply::error(
+                    StatusCode::UNAUTHORIZED,
+                    "Calls must identify their client".to_string(),
+                )
+            }
+            Err(QuotaError::UnknownClient) => {
+                return Reply::error(StatusCode::UNAUTHORIZED, "Unknown client".to_string())
+            }
+            Err(QuotaError::TooManyClients(reset_secs)) => {
+                return Reply {
+                    retry_after: Some(reset_secs),
+                    ..Reply::error(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many clients today".to_string(),
+                    )
+                }
+            }
+            Err(QuotaError::Exceeded(reset_secs)) => {
+                return Reply {
+                    retry_after: Some(reset_secs),
+                    ..Reply::error(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Daily quota exceeded".to_string(),
+                    )
+                }
+            }
+        }
+    }
+
+    if let (None, Some(fallback)) = (state.method_info(method), &state.options.fallback) {
+        return fall_back(ctx, fallback, method, params).await;
+    }
+
+    let is_write = state
+        .method_info(method)
+        .is_some_and(|info| info.kind == MethodKind::Write);
+    if is_write && state.options.read_only {
+        return Reply::error(
+            StatusCode::FORBIDDEN,
+            format!(
+                "Method {} changes state and this server is read-only",
+                method
+            ),
+        );
+    }
+
+    if let Some(flag) = state.method_info(method).and_then(|info| info.flag) {
+        let flags = &state.options.feature_flags;
+        let enabled = match flags {
+            Some(flags) => flags.is_enabled(flag).await,
+            None => false,
+        };
+        if !enabled {
+            // Dark-launched methods look like any other unknown method unless told otherwise
+            return if flags.as_ref().is_some_and(|flags| flags.forbids()) {
+                Reply::error(
+                    StatusCode::FORBIDDEN,
+                    format!("Method {} is not enabled", method),
+                )
+            } else {
+                match &state.options.fallback {
+                    Some(fallback) => fall_back(ctx, fallback, method, params).await,
+                    None => {
+                        Reply::error(StatusCode::NOT_FOUND, format!("Unknown method: {}", method))
+                    }
+                }
+            };
+        }
+    }
+
+    if let Some(reason) = state.lifecycle.disabled(method) {
+        return Reply::error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Method {} is disabled: {}", method, reason),
+        );
+    }
+
+    let deadline = meta.deadline;
+    let idempotency_key = meta.idempotency_key;
+    if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+        return deadline_exceeded(method);
+    }
+    if let Some(shedding) = &state.options.load_shedding {
+        if shedding.should_shed(meta.priority) {
+            state.lifecycle.saturated(method, "overloaded");
+            return Reply {
+                retry_after: Some(shedding.retry_after_secs()),
+                ..Reply::error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Server is overloaded; {} was not run", method),
+                )
+            };
+        }
+    }
+
+    if let Some(memory) = &state.options.memory {
+        if memory.should_shed(meta.priority) {
+            state.lifecycle.saturated(method, "low on memory");
+            return Reply {
+                retry_after: Some(memory.retry_after_secs()),
+                ..Reply::error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Server is low on memory; {} was not run", method),
+                )
+            };
+        }
+    }
+
+    // Held until the call is answered
+    let _charge = state
+        .options
+        .memory
+        .as_ref()
+        .map(|memory| memory.charge_mailbox(params.len()));
+    for middleware in &state.options.middleware {
+        middleware.0.before(ctx).await;
+    }
+    let call = async {
+        let waiting = Instant::now();
+        let (_shared, _exclusive);
+        if state.is_exclusive(method) {
+            _exclusive = state.mailbox.write().await;
+        } else {
+            _shared = state.mailbox.read().await;
+        }
+        let wait = waiting.elapsed();
+        ctx.set_queue_wait(wait);
+        if let Some(shedding) = &state.options.load_shedding {
+            shedding.record_wait(wait);
+        }
+        match (&state.options.journal, is_write) {
+            (Some(journal), true) => {
+                let _writes = journal.lock_writes().await;
+                let key = journal.key_for(method, params, idempotency_key.as_deref());
+                if let Some(response) = key.as_deref().and_then(|key| journal.recorded(key)) {
+                    log::info!("Not repeating {} with dedup key {:?}", method, key);
+                    return Ok(response);
+                }
+                let response = invoke(state, ctx, method, params).await;
+                if let Ok(response) = &response {
+                    let headers = bound_headers(state, ctx, method);
+                    journal.append(method, params, key, headers, response);
+                }
+                response
+            }
+            _ => invoke(state, ctx, method, params).await,
+        }
+    };
+    let response = match deadline {
+        None => Some(call.await),
+        // A journaled write is never abandoned part way through, or replicas would miss a
+        // change the primary made
+        Some(_) if is_write && state.options.journal.is_some() => Some(call.await),
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), call).await.ok(),
+    };
+    if !matches!(response, Some(Ok(_))) {
+        ctx.set_failed();
+    }
+    for middleware in state.options.middleware.iter().rev() {
+        middleware.0.after(ctx).await;
+    }
+    let response = match response {
+        Some(Ok(response)) => response,
+        Some(Err(reply)) => return reply,
+        None => return deadline_exceeded(method),
+    };
+
+    match ctx.take_forward() {
+        None => Reply {
+            status: ctx.status().unwrap_or(StatusCode::OK),
+            stream: ctx.take_stream(),
+            ..Reply::ok(response)
+        },
+        Some(target) => match target
+            .call_with_deadline(method, params.to_string(), deadline)
+            .await
+        {
+            Ok(response) => Reply::ok(response),
+            Err(ClientError::Status(status, body)) => Reply {
+                status: StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+                body,
+                retry_after: None,
+                content_type: JSON,
+                stream: None,
+            },
+            Err(e) => Reply::error(
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to forward {} to {}: {}", method, target.url(), e),
+            ),
+        },
+    }
+}
+
+/// Run the method with the call's context, on the blocking pool if it is marked `#[blocking]`.
+async fn invoke<T>(
+    state: &ServerState<T>,
+    ctx: &RequestContext,
+    method: &str,
+    params: &str,
+) -> Result<String, Reply>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let params = decrypt_params(state, method, params)?;
+    let blocking = state.method_info(method).is_some_and(|info| info.blocking);
+    let Some(pool) = state.blocking.as_ref().filter(|_| blocking) else {
+        let call = ctx.clone().scope(state.actor.dispatch(method, &params));
+        if !state.options.stop_on_panic {
+            return Ok(call.await);
+        }
+        return AssertUnwindSafe(call)
+            .catch_unwind()
+            .await
+            .map_err(|_| panicked(state, method));
+    };
+    let call = {
+        let (actor, ctx) = (Arc::clone(&state.actor), ctx.clone());
+        let (method, params) = (method.to_string(), params.to_string());
+        let runtime = tokio::runtime::Handle::current();
+        move || runtime.block_on(ctx.scope(actor.dispatch(&method, &params)))
+    };
+    pool.run(call).await.map_err(|e| match e {
+        RunError::Full => {
+            state.lifecycle.saturated(method, "out of blocking threads");
+            Reply {
+                retry_after: Some(pool.retry_after_secs()),
+                ..Reply::error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Too many blocking calls; {} was not run", method),
+                )
+            }
+        }
+        RunError::Panicked => panicked(state, method),
+    })
+}
+
+/// The request headers `method`'s `#[from_header]` and `#[from_cookie]` parameters read in
+/// `ctx`, for its journal entry.  The cookies are kept as one `cookie` header of just those.
+fn bound_headers<T: Actor>(
+    state: &ServerState<T>,
+    ctx: &RequestContext,
+    method: &str,
+) -> BTreeMap<String, String> {
+    let mut headers = BTreeMap::new();
+    let mut cookies = Vec::new();
+    for param in state
+        .method_info(method)
+        .map_or(&[][..], |info| info.params)
+    {
+        match param.source {
+            ParamSource::Body => {}
+            ParamSource::Header(name) => {
+                if let Some(value) = ctx.header(name) {
+                    headers.insert(name.to_ascii_lowercase(), value.to_string());
+                }
+            }
+            ParamSource::Cookie(name) => {
+                if let Some(value) = ctx.cookie(name) {
+                    cookies.push(format!("{}={}", name, value));
+                }
+            }
+        }
+    }
+    if !cookies.is_empty() {
+        headers.insert("cookie".to_string(), cookies.join("; "));
+    }
+    headers
+}
+
+/// A context for replaying a journaled call, carrying the headers its bound parameters read.
+pub(crate) fn replay_context<T: Actor>(
+    state: &ServerState<T>,
+    entry: &JournalEntry,
+) -> RequestContext {
+    let headers = entry
+        .headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = hyper::header::HeaderName::from_bytes(name.as_bytes()).ok()?;
+            Some((name, HeaderValue::from_str(value).ok()?))
+        })
+        .collect();
+    RequestContext::new(
+        &entry.method,
+        &entry.params,
+        state.options.resources.clone(),
+    )
+    .with_headers(headers)
+}
+
+/// `params` with the method's parameters marked `#[sensitive(encrypted)]` decrypted with the
+/// server's field encryption key.  Parameters that are missing or `null` are left to the
+/// method, as are parameters that are not a JSON object.
+pub(crate) fn decrypt_params<'a, T: Actor>(
+    state: &ServerState<T>,
+    method: &str,
+    params: &'a str,
+) -> Result<Cow<'a, str>, Reply> {
+    let Some(info) = state.method_info(method) else {
+        return Ok(Cow::Borrowed(params));
+    };
+    if !info.params.iter().any(|param| param.encrypted) {
+        return Ok(Cow::Borrowed(params));
+    }
+    let Ok(Value::Object(mut object)) = serde_json::from_str::<Value>(params) else {
+        return Ok(Cow::Borrowed(params));
+    };
+    for param in info.params.iter().filter(|param| param.encrypted) {
+        let Some(value) = object.get_mut(param.name).filter(|value| !value.is_null()) else {
+            continue;
+        };
+        let Some(encryption) = &state.options.field_encryption else {
+            log::error!(
+                "Parameter `{}` of {} is encrypted but the server has no field encryption key",
+                param.name,
+                method
+            );
+            return Err(Reply::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "Parameter `{}` of {} can't be decrypted",
+                    param.name, method
+                ),
+            ));
+        };
+        *value = encryption
+            .decrypt_param(method, param.name, value)
+            .map_err(|e| {
+                let message = match e {
+                    EncryptionError::NotEncrypted => {
+                        format!("Parameter `{}` of {} must be encrypted", param.name, method)
+                    }
+                    _ => format!(
+                        "Parameter `{}` of {} can't be decrypted",
+                        param.name, method
+                    ),
+                };
+                Reply::error(StatusCode::BAD_REQUEST, message)
+            })?;
+    }
+    Ok(Cow::Owned(Value::Object(object).to_string()))
+}
+
+/// The reply to a call whose method panicked, stopping the server if it is set to stop on
+/// panics.
+fn panicked<T>(state: &ServerState<T>, method: &str) -> Reply {
+    let message = format!("Method {} panicked", method);
+    if state.options.stop_on_panic {
+        state.lifecycle.crash(message.as_str());
+    }
+    Reply::error(StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+/// Answer a call to a method the actor doesn't have with the configured fallback.
+async fn fall_back(
+    ctx: &RequestContext,
+    fallback: &Shared<dyn Fallback>,
+    method: &str,
+    params: &str,
+) -> Reply {
+    let response = ctx.clone().scope(fallback.0.call(method, params)).await;
+    Reply {
+        status: StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        ..Reply::ok(response.body)
+    }
+}
+
+/// The headers telling callers that `method` is deprecated and when it goes away.
+fn deprecation_headers(method: &str, deprecated: &Deprecation) -> Vec<(&'static str, HeaderValue)> {
+    let mut headers = vec![("Deprecation", HeaderValue::from_static("true"))];
+    if let Some(sunset) = deprecated.sunset.and_then(http_date) {
+        headers.extend(HeaderValue::from_str(&sunset).map(|v| ("Sunset", v)));
+    }
+    let mut warning = format!("{} is deprecated", method);
+    if let Some(note) = deprecated.note {
+        warning.push_str(": ");
+        warning.push_str(note);
+    }
+    // Header values are visible ASCII, and the warning text is a quoted string
+    let warning: String = warning
+        .chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control())
+        .map(|c| if c == '"' { '\'' } else { c })
+        .collect();
+    headers
+        .extend(HeaderValue::from_str(&format!("299 - \"{}\"", warning)).map(|v| ("Warning", v)));
+    headers
+}
+
+/// Format a `YYYY-MM-DD` date as an HTTP date at midnight UTC, or `None` if there is no such
+/// date.
+fn http_date(date: &str) -> Option<String> {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let mut parts = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    let month_name = MONTHS.get(usize::try_from(month - 1).ok()?)?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_len = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=month_len).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01, a Thursday (Howard Hinnant's days_from_civil)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(format!(
+        "{}, {:02} {} {} 00:00:00 GMT",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        month_name,
+        year
+    ))
+}
+
+fn deadline_exceeded(method: &str) -> Reply {
+    Reply::error(
+        StatusCode::GATEWAY_TIMEOUT,
+        format!("Deadline exceeded while calling {}", method),
+    )
+}
+
+/// Run a server for `actor` until the process exits or `lifecycle` is shut down.
+pub(crate) async fn run<T>(mut actor: T, mut options: ServerOptions, lifecycle: Arc<Lifecycle>)
+where
+    T: Actor + Send + Sync + 'static,
+{
+    lifecycle.watch(options.monitor.clone());
+    let _running = lifecycle.running();
+    if let Err(e) = actor.init().await {
+        log::error!("Failed to initialize the actor: {}", e);
+        lifecycle.fail(format!("Failed to initialize the actor: {}", e));
+        lifecycle.set_listening(Vec::new());
+        return;
+    }
+    if let Some(secret) = options.admin_token_secret.take() {
+        match admin::fetch_token(secret, &lifecycle).await {
+            Ok(token) => options.admin_token = Some(token),
+            Err(e) => {
+                log::error!("{}", e);
+                lifecycle.fail(e);
+                lifecycle.set_listening(Vec::new());
+                return;
+            }
+        }
+    }
+
+    if let Some(cluster) = &options.cluster {
+        lifecycle.spawn_background(cluster.clone().run_election());
+    }
+
+    // The last journal entry the actor's state includes
+    let mut restored_seq = 0;
+    if options.restore_on_start {
+        if let Some(store) = &options.snapshot_store {
+            match store.0.latest().await {
+                Ok(Some(snapshot)) => match actor.restore_state(&snapshot.state) {
+                    Ok(()) => {
+                        log::info!("Restored snapshot taken at {}", snapshot.taken_at_ms);
+                        restored_seq = snapshot.journal_seq;
+                    }
+                    Err(e) => log::error!("Failed to restore snapshot: {}", e),
+                },
+                Ok(None) => log::info!("No snapshot to restore"),
+                Err(e) => log::error!("Failed to load snapshot: {}", e),
+            }
+        }
+    }
+
+    let role = options.replication.as_ref().map(|r| r.role().clone());
+    if let Some(Role::Primary { .. }) = role {
+        options.journal.get_or_insert_with(Journal::new);
+    }
+    if let Some(journal) = &options.journal {
+        journal.start_at(restored_seq + 1);
+    }
+    if let (Some(Role::Primary { port }), Some(journal)) = (role.clone(), options.journal.clone()) {
+        // Replicas connect on the host the server listens on
+        let addr = SocketAddr::new(options.host_addr(), port);
+        match bind(addr, options.ipv6_only, false, &options.socket) {
+            Ok((listener, addrs)) => {
+                let primary =
+                    replication::serve_primary(journal, listener, addrs, Arc::clone(&lifecycle));
+                lifecycle.spawn_background(primary);
+            }
+            Err(e) => log::error!("Failed to bind replication port {addr:?}: {}", e),
+        }
+    }
+    if let Some(Role::Replica { .. }) = role {
+        options.read_only = true;
+    }
+
+    if options.read_only {
+        options.durable_ingest = None;
+    }
+
+    let state = Arc::new(ServerState::new(actor, options, lifecycle));
+    warmup::warm_up(&state).await;
+    if let Some(ingest) = &state.options.durable_ingest {
+        let ingest = ingest::process(Arc::clone(&state), ingest.clone());
+        state.lifecycle.spawn_background(ingest);
+    }
+
+    let follower = match role {
+        Some(Role::Replica { primary }) => Some(state.lifecycle.spawn(replication::follow(
+            Arc::clone(&state),
+            primary,
+            restored_seq + 1,
+        ))),
+        _ => None,
+    };
+
+    serve(Arc::clone(&state)).await;
+    if let Some(follower) = follower {
+        follower.abort();
+    }
+    shut_down(state).await;
+}
+
+/// Once the last connection has closed and the server's background tasks have stopped, run
+/// the actor's shutdown hook with the mailbox to itself, then drop the actor.
+async fn shut_down<T: Actor>(state: Arc<ServerState<T>>) {
+    state.lifecycle.idle().await;
+    state.lifecycle.finish_tasks().await;
+    {
+        let _drained = state.mailbox.write().await;
+        state.actor.on_shutdown().await;
+    }
+
+    // Connection tasks let go of the state just after counting themselves closed
+    let mut state = state;
+    for _ in 0..100 {
+        match Arc::try_unwrap(state) {
+            Ok(state) => {
+                drop(state);
+                log::info!("Server stopped");
+                return;
+            }
+            Err(shared) => state = shared,
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    log::warn!("The actor is still in use after shutting down; it is dropped once released");
+}
+
+/// Bind the listener and accept connections, optionally wrapping each one in TLS.
+async fn serve<T>(state: Arc<ServerState<T>>)
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let options = &state.options;
+    let label = match options.scheme() {
+        "wss" => "WSS",
+        "ws" => "WebSocket",
+        "https" => "HTTPS",
+        _ => "HTTP",
+    };
+
+    let addr = SocketAddr::new(options.host_addr(), options.port);
+    let (listener, addrs) = bind(addr, options.ipv6_only, options.reuse_port, &options.socket)
+        .unwrap_or_else(|e| {
+            let reason = format!("Failed to bind {label} server to {addr:?}: {}", e);
+            state.lifecycle.fail(reason.as_str());
+            state.lifecycle.set_listening(Vec::new());
+            panic!("{}", reason);
+        });
+
+    let tls_acceptor = match &options.tls {
+        Some(tls_config) => match tls_config.load_server_config().await {
+            Ok(tls_server_config) => {
+                if let Some(rotation) = tls_config.rotation() {
+                    state.lifecycle.spawn_background(rotation);
+                }
+                #[cfg(feature = "http3")]
+                if options.http3 && !options.websocket {
+                    let tls = tls_server_config.clone();
+                    state
+                        .lifecycle
+                        .spawn(http3::serve(Arc::clone(&state), addrs[0], tls));
+                }
+                Some(tokio_rustls::TlsAcceptor::from(Arc::new(tls_server_config)))
+            }
+            Err(e) => {
+                log::error!("Failed to load TLS configuration: {}", e);
+                state.lifecycle.set_listening(Vec::new());
+                return;
+            }
+        },
+        None => None,
+    };
+
+    #[cfg(feature = "http3")]
+    if options.http3 && (options.websocket || options.tls.is_none()) {
+        log::warn!("HTTP/3 needs TLS and is not available for WebSocket servers; not serving it");
+    }
+
+    let urls: Vec<String> = addrs
+        .iter()
+        .map(|addr| format!("{}://{}", options.scheme(), addr))
+        .collect();
+    log::info!("{label} server listening on {}", urls.join(" and "));
+    if let Some(on_startup) = &options.on_startup {
+        #[cfg_attr(not(feature = "http3"), allow(unused_mut))]
+        let mut transports = vec![options.scheme().to_string()];
+        #[cfg(feature = "http3")]
+        if options.http3 && !options.websocket && options.tls.is_some() {
+            transports.push("http3".to_string());
+        }
+        let methods = state
+            .actor
+            .methods()
+            .iter()
+            .map(|method| method.name.to_string())
+            .collect();
+        (on_startup.0)(&StartupEvent::new(
+            urls,
+            transports,
+            options.tls.is_some(),
+            methods,
+        ));
+    }
+    state.lifecycle.set_listening(addrs);
+
+    if let Some(binding) = &options.registry {
+        let registration = Registration {
+            name: binding.name.clone(),
+            address: binding.address.clone(),
+            methods: state
+                .actor
+                .methods()
+                .iter()
+                .map(|method| method.name.to_string())
+                .collect(),
+            ttl_ms: binding.ttl.as_millis() as u64,
+        };
+        state
+            .lifecycle
+            .spawn_background(binding.client.clone().keep_registered(registration));
+    }
+
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = state.lifecycle.closing(Listener::Tcp) => {
+                log::info!("{label} server draining; no longer accepting connections");
+                break;
+            }
+        };
+        let (stream, _) = match accepted {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::error!("Failed to accept {label} connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = options.socket.apply_to_stream(&stream) {
+            log::warn!(
+                "Failed to set socket options on a {label} connection: {}",
+                e
+            );
+        }
+
+        let state = Arc::clone(&state);
+        let tls_acceptor = tls_acceptor.clone();
+
+        let connection = state.lifecycle.connection(Listener::Tcp);
+
+        let lifecycle = Arc::clone(&state.lifecycle);
+        lifecycle.spawn(async move {
+            let _connection = connection;
+            match tls_acceptor {
+                Some(tls_acceptor) => match tls_acceptor.accept(stream).await {
+                    Ok(tls_stream) => serve_stream(state, tls_stream, label).await,
+                    Err(e) if is_disconnect(&e) => {
+                        log::debug!("Client went away during the TLS handshake: {}", e);
+                    }
+                    Err(e) => {
+                        log::error!("TLS handshake error: {}", e);
+                    }
+                },
+                None => serve_stream(state, stream, label).await,
+            }
+        });
+    }
+}
+
+/// Bind the listening socket, returning it with the addresses it accepts connections on.  The
+/// unspecified IPv6 address `::` is dual-stack unless `ipv6_only` is set, and falls back to
+/// `0.0.0.0` where IPv6 is unavailable.
+pub(crate) fn bind(
+    addr: SocketAddr,
+    ipv6_only: bool,
+    reuse_port: bool,
+    socket: &SocketOptions,
+) -> std::io::Result<(TcpListener, Vec<SocketAddr>)> {
+    let dual_stack = addr.ip() == IpAddr::V6(Ipv6Addr::UNSPECIFIED) && !ipv6_only;
+    let addr = match bind_socket(addr, ipv6_only, reuse_port, socket) {
+        Ok(listener) => {
+            let bound = listener.local_addr()?;
+            let mut addrs = vec![bound];
+            if dual_stack {
+                addrs.push(SocketAddr::from((Ipv4Addr::UNSPECIFIED, bound.port())));
+            }
+            return Ok((listener, addrs));
+        }
+        Err(e) if dual_stack => {
+            log::warn!("IPv6 is unavailable ({}); listening on IPv4 only", e);
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, addr.port()))
+        }
+        Err(e) => return Err(e),
+    };
+    let listener = bind_socket(addr, false, reuse_port, socket)?;
+    let bound = listener.local_addr()?;
+    Ok((listener, vec![bound]))
+}
+
+fn bind_socket(
+    addr: SocketAddr,
+    ipv6_only: bool,
+    reuse_port: bool,
+    options: &SocketOptions,
+) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(reuse_port)?;
+    #[cfg(not(unix))]
+    if reuse_port {
+        log::warn!("SO_REUSEPORT is only supported on Unix; listening without it");
+    }
+    options.apply_to_listener(&socket)?;
+    socket.bind(&addr.into())?;
+    socket.listen(options.listen_backlog())?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Serve one (possibly encrypted) connection with the configured protocol.
+async fn serve_stream<T, S>(state: Arc<ServerState<T>>, stream: S, label: &str)
+where
+    T: Actor + Send + Sync + 'static,
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let lifecycle = Arc::clone(&state.lifecycle);
+    if state.options.websocket {
+        // Handle WebSocket upgrade and connection
+        if let Err(e) = handle_websocket_connection(state, stream).await {
+            log_connection_error(label, &*e);
+        }
+    } else {
+        let io = TokioIo::new(stream);
+        let service = service_fn(move |req| {
+            let state = Arc::clone(&state);
+            async move { handle_http_request(state, req).await }
+        });
+
+        // When the server drains, finish the request in progress and then close the connection,
+        // or close it at once if the drain deadline passes first
+        let builder = Builder::new(hyper_util::rt::TokioExecutor::new());
+        let connection = builder.serve_connection(io, service);
+        tokio::pin!(connection);
+        let result = tokio::select! {
+            result = connection.as_mut() => result,
+            _ = lifecycle.closing(Listener::Tcp) => {
+                connection.as_mut().graceful_shutdown();
+                tokio::select! {
+                    result = connection => result,
+                    _ = lifecycle.forced(Listener::Tcp) => Ok(()),
+                }
+            }
+        };
+        if let Err(e) = result {
+            log_connection_error(label, &*e);
+        }
+    }
+}
+
+/// Log the error a connection ended with.  Clients going away part way through a request are
+/// routine, so they are only logged at debug level.
+pub(crate) fn log_connection_error(label: &str, error: &(dyn std::error::Error + 'static)) {
+    if is_disconnect(error) {
+        log::debug!("{label} client went away: {}", error);
+    } else {
+        log::error!("{label} connection error: {}", error);
+    }
+}
+
+/// Whether `error`, or an error it wraps, means the peer closed or reset the connection.
+fn is_disconnect(error: &(dyn std::error::Error + 'static)) -> bool {
+    use std::io::ErrorKind;
+    use tokio_tungstenite::tungstenite::{error::ProtocolError, Error as WsError};
+
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(e) = error.downcast_ref::<hyper::Error>() {
+            if e.is_incomplete_message() || e.is_canceled() {
+                return true;
+            }
+        }
+        if let Some(e) = error.downcast_ref::<std::io::Error>() {
+            let kind = e.kind();
+            if matches!(
+                kind,
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            ) {
+                return true;
+            }
+        }
+        if let Some(e) = error.downcast_ref::<WsError>() {
+            if matches!(
+                e,
+                WsError::ConnectionClosed
+                    | WsError::AlreadyClosed
+                    | WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake)
+            ) {
+                return true;
+            }
+        }
+        #[cfg(feature = "http3")]
+        if let Some(e) = error.downcast_ref::<quinn::ConnectionError>() {
+            if matches!(
+                e,
+                quinn::ConnectionError::ApplicationClosed(_)
+                    | quinn::ConnectionError::ConnectionClosed(_)
+                    | quinn::ConnectionError::Reset
+                    | quinn::ConnectionError::TimedOut
+            ) {
+                return true;
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Handle individual HTTP requests (unified for HTTP and HTTPS)
+async fn handle_http_request<T>(
+    state: Arc<ServerState<T>>,
+    req: Request<hyper::body::Incoming>,
+) -> Result<Response<streaming::Body>, Infallible>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    // Read the request body, keeping the rest of the request to borrow from
+    let (parts, body) = req.into_parts();
+    let pool = state.options.buffer_pool.as_ref();
+    let Ok(body) = Buffer::take(pool).read(body).await else {
+        return Ok(streaming::into_body(bad_request(
+            "Failed to read request body",
+        )));
+    };
+    let Ok(body_str) = std::str::from_utf8(&body) else {
+        return Ok(streaming::into_body(bad_request(
+            "Invalid UTF-8 in request body",
+        )));
+    };
+
+    let response = respond(
+        &state,
+        parts.method.as_str(),
+        parts.uri.path(),
+        parts.uri.query(),
+        &parts.headers,
+        body_str,
+        true,
+    )
+    .await;
+    Ok(streaming::into_body(response))
+}
+
+/// Answers the calls of an in-process [`ActorRef`](crate::ActorRef) without a socket.
+pub(crate) trait Loopback: Send + Sync {
+    fn call(
+        &self,
+        request: Request<Full<Bytes>>,
+    ) -> Pin<Box<dyn Future<Output = Response<Full<Bytes>>> + Send + '_>>;
+}
+
+impl<T> Loopback for Arc<ServerState<T>>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    fn call(
+        &self,
+        request: Request<Full<Bytes>>,
+    ) -> Pin<Box<dyn Future<Output = Response<Full<Bytes>>> + Send + '_>> {
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            let Ok(body) = body.collect().await;
+            let body = body.to_bytes();
+            let Ok(body_str) = std::str::from_utf8(&body) else {
+                return bad_request("Invalid UTF-8 in request body");
+            };
+            respond(
+                self,
+                parts.method.as_str(),
+                parts.uri.path(),
+                parts.uri.query(),
+                &parts.headers,
+                body_str,
+                false,
+            )
+            .await
+        })
+    }
+}
+
+/// Serve `actor` to in-process callers only, as it would be served over HTTP.
+pub(crate) fn loopback<T>(actor: T, mut options: ServerOptions) -> Arc<dyn Loopback>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    // In-process callers wait for their calls, and nothing would run logged ones
+    options.durable_ingest = None;
+    Arc::new(Arc::new(ServerState::new(
+        actor,
+        options,
+        ServerHandle::new().lifecycle(),
+    )))
+}
+
+pub(crate) fn bad_request(message: &'static str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Full::new(Bytes::from(message)))
+        .unwrap()
+}
+
+/// Answer an HTTP request whose body has been read, whichever transport it arrived on.
+pub(crate) async fn respond<T>(
+    state: &Arc<ServerState<T>>,
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    body_str: &str,
+    streaming: bool,
+) -> Response<Full<Bytes>>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    let format = Format::from_request(query, headers, state.options.debug);
+    // Reformatting needs the whole body
+    let streaming = streaming && format == Format::default();
+    let started = Instant::now();
+    // HEAD is answered like GET, without the body
+    let head = method == "HEAD";
+    let method = if head { "GET" } else { method };
+    let mut response = route(state, method, path, query, headers, body_str, streaming).await;
+    let elapsed = started.elapsed();
+    let security = &state.options.security;
+    if !path.starts_with(admin::PREFIX) && !path.starts_with("/debug/pprof/") {
+        security.apply_cors(headers.get(hyper::header::ORIGIN), response.headers_mut());
+    }
+    let response_headers = response.headers_mut();
+    for (name, value) in &state.response_headers {
+        response_headers.insert(name, value.clone());
+    }
+    let response = format.apply(response, path, elapsed).await;
+    if !head {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let len = hyper::body::Body::size_hint(&body)
+        .exact()
+        .unwrap_or_default();
+    parts
+        .headers
+        .insert(hyper::header::CONTENT_LENGTH, HeaderValue::from(len));
+    Response::from_parts(parts, Full::new(Bytes::new()))
+}
+
+async fn route<T>(
+    state: &Arc<ServerState<T>>,
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+    body_str: &str,
+    streaming: bool,
+) -> Response<Full<Bytes>>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    // Stateful sessions are pinned to one node of the cluster
+    if let Some(cluster) = &state.options.cluster {
+        let session = headers
+            .get(cluster::SESSION_HEADER)
+            .and_then(|v| v.to_str().ok());
+        if let Some(session) = session {
+            match cluster.route(session).await {
+                Ok(None) => {}
+                Ok(Some(owner)) => {
+                    let body = serde_json::to_string(&format!(
+                        "Session {} is owned by node {}",
+                        session, owner.id
+                    ))
+                    .unwrap_or_default();
+                    let location = match query.filter(|query| !query.is_empty()) {
+                        Some(query) => format!("{}{}?{}", owner.url, path, query),
+                        None => format!("{}{}", owner.url, path),
+                    };
+                    return Response::builder()
+                        .status(StatusCode::TEMPORARY_REDIRECT)
+                        .header("Location", location)
+                        .header("Content-Type", "application/json")
+                        .body(Full::new(Bytes::from(body)))
+                        .unwrap();
+                }
+                Err(e) => {
+                    log::error!("Failed to route session {}: {}", session, e);
+                    return Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .header("Content-Type", "text/plain")
+                        .body(Full::new(Bytes::from("Cluster backend unavailable")))
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    if method == "GET" {
+        if let Some(kubernetes) = &state.options.kubernetes {
+            if let Some(reply) = kubernetes.probe(state, path).await {
+                return Response::builder()
+                    .status(reply.status)
+                    .header("Content-Type", reply.content_type)
+                    .body(Full::new(Bytes::from(reply.body)))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    if method == "GET" && path.starts_with(profiling::PREFIX) {
+        let op = &path[profiling::PREFIX.len()..];
+        return profiling::handle(state, op, query, headers).await;
+    }
+
+    if let Some(signing) = &state.options.signing {
+        if (method == "POST" || method == "GET") && !path.starts_with(admin::PREFIX) {
+            let signed_path = match query {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.to_string(),
+            };
+            if let Err(e) = signing.verify(&signed_path, headers, body_str) {
+                log::warn!("Refused a request to {}: {}", path, e);
+                let status = match e {
+                    SigningError::Full => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::UNAUTHORIZED,
+                };
+                let reply = Reply::error(status, e.to_string());
+                return Response::builder()
+                    .status(reply.status)
+                    .header("Content-Type", reply.content_type)
+                    .body(Full::new(Bytes::from(reply.body)))
+                    .unwrap();
+            }
+        }
+    }
+
+    // Process the HTTP request
+    let info = state.method_info(path.trim_start_matches('/'));
+    if (method == "POST" || method == "GET") && path.starts_with(admin::PREFIX) {
+        let op = &path[admin::PREFIX.len()..];
+        let reply = admin::handle(state, method, op, headers, body_str).await;
+
+        Response::builder()
+            .status(reply.status)
+            .header("Content-Type", reply.content_type)
+            .body(Full::new(Bytes::from(reply.body)))
+            .unwrap()
+    } else if method == "POST" && path == batch::PATH && state.options.max_batch != Some(0) {
+        let reply = batch::handle(state, headers, body_str).await;
+
+        Response::builder()
+            .status(reply.status)
+            .header("Content-Type", reply.content_type)
+            .body(Full::new(Bytes::from(reply.body)))
+            .unwrap()
+    } else if method == "POST" || (method == "GET" && info.is_some_and(|info| info.get)) {
+        // Extract method name from path (e.g., "/add" -> "add")
+        let method_name = path.trim_start_matches('/');
+        let from_query = method == "GET";
+
+        // Read methods may be asked for part of their result with `?fields=`
+        let projection = match info.filter(|info| info.kind == MethodKind::Read) {
+            Some(info) if !info.params.iter().any(|param| param.name == fields::PARAM) => {
+                match Fields::from_query(query.unwrap_or_default()) {
+                    Ok(projection) => projection,
+                    Err(e) => {
+                        return Response::builder()
+                            .status(StatusCode::BAD_REQUEST)
+                            .header("Content-Type", "application/json")
+                            .body(Full::new(Bytes::from(
+                                serde_json::to_string(&e).unwrap_or_default(),
+                            )))
+                            .unwrap()
+                    }
+                }
+            }
+            _ => None,
+        };
+        let query = match (&projection, query) {
+            (Some(_), Some(query)) => Some(Fields::strip(query)),
+            (_, query) => query.map(str::to_string),
+        };
+
+        // `#[get]` methods take their parameters from the query string
+        let query_params;
+        let params = if from_query {
+            query_params = match query::to_json(query.as_deref().unwrap_or_default()) {
+                Ok(params) => params,
+                Err(e) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .header("Content-Type", "application/json")
+                        .body(Full::new(Bytes::from(
+                            serde_json::to_string(&e).unwrap_or_default(),
+                        )))
+                        .unwrap()
+                }
+            };
+            query_params.as_str()
+        } else {
+            body_str
+        };
+
+        if let (false, Some(content_types)) = (from_query, &state.options.content_types) {
+            let accepts = state
+                .method_info(method_name)
+                .map_or(&[][..], |i| i.accepts);
+            let content_type = headers
+                .get(hyper::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok());
+            if !content_types.admits(content_type, accepts) {
+                let accept_post = content_types.accept_post(accepts);
+                let body = serde_json::to_string(&format!(
+                    "Unsupported Content-Type; {} takes {}",
+                    method_name, accept_post
+                ))
+                .unwrap_or_default();
+                let mut response = Response::builder().status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+                if let Ok(accept_post) = HeaderValue::from_str(&accept_post) {
+                    response = response.header("Accept-Post", accept_post);
+                }
+                return response
+                    .header("Content-Type", "application/json")
+                    .body(Full::new(Bytes::from(body)))
+                    .unwrap();
+            }
+        }
+
+        // With durable ingest, writes are logged and answered before they run
+        if let (Some(ingest), false, Some(info)) = (&state.options.durable_ingest, from_query, info)
+        {
+            if info.kind == MethodKind::Write {
+                let (status, body) = match ingest.accept(method_name, params, headers).await {
+                    Ok(seq) => (StatusCode::ACCEPTED, serde_json::json!({ "accepted": seq })),
+                    Err(e) => {
+                        log::error!("Failed to log a call to {}: {}", method_name, e);
+                        let message = format!("Failed to accept a call to {}", method_name);
+                        (StatusCode::SERVICE_UNAVAILABLE, Value::String(message))
+                    }
+                };
+                return Response::builder()
+                    .status(status)
+                    .header("Content-Type", "application/json")
+                    .body(Full::new(Bytes::from(body.to_string())))
+                    .unwrap();
+            }
+        }
+
+        let mut meta = call_meta(state, headers);
+        meta.streaming = streaming;
+        meta.from_query = from_query;
+
+        // Process the message using the actor
+        let mut reply = dispatch(state, method_name, params, meta).await;
+        if let (Some(projection), true, None) = (
+            &projection,
+            reply.status.is_success() && reply.content_type == JSON,
+            &reply.stream,
+        ) {
+            reply.body = projection.project(std::mem::take(&mut reply.body));
+        }
+
+        let mut response = Response::builder().status(reply.status);
+        if let Some(seconds) = reply.retry_after {
+            response = response.header("Retry-After", seconds);
+        }
+        if let Some(deprecated) = state.method_info(method_name).and_then(|i| i.deprecated) {
+            for (name, value) in deprecation_headers(method_name, &deprecated) {
+                response = response.header(name, value);
+            }
+        }
+        if let Some(stream) = reply.stream {
+            response = response.extension(stream);
+        }
+        response
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(reply.body)))
+            .unwrap()
+    } else if method == "OPTIONS" {
+        let preflight = headers.contains_key(hyper::header::ACCESS_CONTROL_REQUEST_METHOD);
+        if let (false, Some(info)) = (preflight, info) {
+            return describe_method(state, info).await;
+        }
+        // Handle CORS preflight requests
+        Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Length", "0")
+            .body(Full::new(Bytes::new()))
+            .unwrap()
+    } else if let Some(custom) = &state.options.method_not_allowed {
+        Response::builder()
+            .status(StatusCode::from_u16(custom.status).unwrap_or(StatusCode::METHOD_NOT_ALLOWED))
+            .header("Allow", describe::allow(info))
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(custom.body.clone())))
+            .unwrap()
+    } else {
+        Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("Allow", describe::allow(info))
+            .header("Content-Type", "text/plain")
+            .body(Full::new(Bytes::from("Method Not Allowed")))
+            .unwrap()
+    }
+}
+
+/// Answer `OPTIONS` for one of the actor's methods with its description.  Dark-launched methods
+/// that are switched off are not described.
+async fn describe_method<T>(state: &ServerState<T>, info: &MethodInfo) -> Response<Full<Bytes>>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    if let Some(flag) = info.flag {
+        let enabled = match &state.options.feature_flags {
+            Some(flags) => flags.is_enabled(flag).await,
+            None => false,
+        };
+        if !enabled {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("Content-Type", "application/json")
+                .body(Full::new(Bytes::from(
+                    serde_json::to_string(&format!("Unknown method: {}", info.name))
+                        .unwrap_or_default(),
+                )))
+                .unwrap();
+        }
+    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Allow", describe::allow(Some(info)))
+        .header("Content-Type", "application/json")
+        .body(Full::new(Bytes::from(
+            describe::describe(state, info).to_string(),
+        )))
+        .unwrap()
+}
+
+/// A WebSocket reply.  Requests carrying an `id` get `{"id": ..., "status": ..., "result": ...}`
+/// so clients can match replies to requests; others get the bare body.
+fn ws_reply(id: Option<&serde_json::Value>, status: StatusCode, body: String) -> String {
+    match id {
+        None => body,
+        Some(id) => {
+            let result = serde_json::from_str(&body).unwrap_or(serde_json::Value::String(body));
+            serde_json::json!({ "id": id, "status": status.as_u16(), "result": result }).to_string()
+        }
+    }
+}
+
+/// The raw parameters of a WebSocket call.
+#[derive(Deserialize)]
+struct WsCall<'a> {
+    #[serde(borrow)]
+    params: &'a RawValue,
+}
+
+/// Handle individual WebSocket connections (unified for both TLS and non-TLS)
+///
+/// Calls carrying an `id` run concurrently and are answered as they finish; calls without one
+/// are answered before the next message is read.  A client connecting with `?ordered=true` in
+/// the URL gets every reply in the order of its requests, even though calls still run
+/// concurrently.
+#[allow(clippy::result_large_err)] // The handshake callback's signature is tungstenite's
+async fn handle_websocket_connection<T, S>(
+    state: Arc<ServerState<T>>,
+    stream: S,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    T: Actor + Send + Sync + 'static,
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let mut ordered = false;
+    let ws_stream = accept_hdr_async(stream, |request: &WsRequest, response| {
+        ordered = is_ordered(request.uri().query());
+        Ok(response)
+    })
+    .await?;
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    // Frames are written by their own task, so a client that stops reading can't stall this one
+    let outbox = Arc::new(
+        Outbox::new(state.options.send_queue.clone()).with_memory(state.options.memory.as_ref()),
+    );
+    let writer = state.lifecycle.spawn({
+        let outbox = Arc::clone(&outbox);
+        async move {
+            while let Some(message) = outbox.next().await {
+                if let Err(e) = ws_sender.send(message).await {
+                    log::error!("Failed to send WebSocket message: {}", e);
+                    outbox.abandon();
+                    break;
+                }
+            }
+        }
+    });
+    let mut replies = Replies::new(ordered);
+    let mut in_flight = FuturesUnordered::new();
+
+    // Subscribed topics, and the events received for them while any are subscribed
+    let mut topics = HashSet::new();
+    let mut events: Option<broadcast::Receiver<Event>> = None;
+    // Set once the client subscribes as an acknowledging subscriber
+    let mut attached: Option<events::Attached> = None;
+    // Topics subscribed to with a replay, whose events are sent with their sequence numbers
+    let mut replaying = HashSet::new();
+
+    // Errors end the connection like a close does, once the writer is dealt with below
+    let result: Result<(), Box<dyn std::error::Error + Send + Sync>> = async {
+        loop {
+            let msg = tokio::select! {
+                msg = ws_receiver.next() => match msg {
+                    Some(msg) => msg?,
+                    None => break,
+                },
+                Some((seq, reply)) = in_flight.next(), if !in_flight.is_empty() => {
+                    if !replies.complete(seq, reply).into_iter().all(|reply| outbox.reply(reply)) {
+                        break;
+                    }
+                    continue;
+                }
+                _ = state.lifecycle.closing(Listener::Tcp) => {
+                    // Draining: answer the calls in progress before closing, so none are cut off
+                    // unless the drain deadline passes first
+                    let answered = async {
+                        while let Some((seq, reply)) = in_flight.next().await {
+                            for reply in replies.complete(seq, reply) {
+                                outbox.reply(reply);
+                            }
+                        }
+                    };
+                    tokio::select! {
+                        _ = answered => {}
+                        _ = state.lifecycle.forced(Listener::Tcp) => {}
+                    }
+                    outbox.close(Some(Message::Close(Some(CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "Server is draining".into(),
+                    }))));
+                    break;
+                }
+                event = async { events.as_mut().unwrap().recv().await }, if events.is_some() => {
+                    let (topic, frame) = match event {
+                        Ok(event) if topics.contains(&event.topic) => {
+                            let numbered = replaying.contains(&event.topic)
+                                || attached
+                                    .as_ref()
+                                    .is_some_and(|attached| attached.acknowledges(&event.topic));
+                            let frame = if numbered {
+                                event.to_acked_frame()
+                            } else {
+                                serde_json::to_string(&event)?
+                            };
+                            (Some(event.topic), frame)
+                        }
+                        Ok(_) => continue,
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            (None, serde_json::json!({ "lagged": missed }).to_string())
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            events = None;
+                            continue;
+                        }
+                    };
+                    let queued = match &topic {
+                        Some(topic) => outbox.event(topic, frame),
+                        None => outbox.reply(frame),
+                    };
+                    if !queued {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let text = match msg {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                // Ignore other message types (binary, ping, pong)
+                _ => continue,
+            };
+            let seq = replies.next_seq();
+            // Unacknowledged or replayed events to send after the reply to a subscription
+            let mut resend = Vec::new();
+            let reply = match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(json) => {
+                    let id = json.get("id").cloned();
+                    if let Some(request) = events::Request::parse(&json) {
+                        let answer = match (&state.options.events, request) {
+                            (None, _) => Some((
+                                StatusCode::BAD_REQUEST,
+                                serde_json::json!({
+                                    "error": "Subscriptions are not enabled on this server"
+                                }),
+                            )),
+                            // Acknowledgements are not answered
+                            (Some(_), events::Request::Ack(acked)) => {
+                                if let Some(attached) = &attached {
+                                    attached.ack(acked);
+                                }
+                                None
+                            }
+                            (
+                                Some(bus),
+                                events::Request::Subscribe {
+                                    topic,
+                                    subscriber,
+                                    since,
+                                },
+                            ) => {
+                                events.get_or_insert_with(|| bus.receiver());
+                                topics.insert(topic.clone());
+                                if let Some(subscriber) = subscriber {
+                                    if attached.as_ref().map(|a| a.subscriber()) != Some(&subscriber) {
+                                        attached = Some(bus.attach(&subscriber));
+                                    }
+                                }
+                                if let Some(attached) = &attached {
+                                    resend = attached.subscribe(&topic);
+                                }
+                                let mut reply = serde_json::json!({ "subscribed": topic });
+                                if let Some(since) = since {
+                                    let (replayed, complete) = bus.replay(&topic, since);
+                                    reply["replayed"] = replayed.len().into();
+                                    reply["complete"] = complete.into();
+                                    resend.extend(replayed.iter().map(Event::to_acked_frame));
+                                    replaying.insert(topic);
+                                }
+                                Some((StatusCode::OK, reply))
+                            }
+                            (Some(_), events::Request::Unsubscribe(topic)) => {
+                                topics.remove(&topic);
+                                replaying.remove(&topic);
+                                if topics.is_empty() {
+                                    events = None;
+                                }
+                                if let Some(attached) = &attached {
+                                    attached.unsubscribe(&topic);
+                                }
+                                Some((StatusCode::OK, serde_json::json!({ "unsubscribed": topic })))
+                            }
+                        };
+                        answer.map(|(status, reply)| ws_reply(id.as_ref(), status, reply.to_string()))
+                    } else if let (Some(method), Some(params)) = (
+                        json.get("method").and_then(|v| v.as_str()),
+                        json.get("params"),
+                    ) {
+                        let concurrent = id.is_some() || ordered;
+                        let method = method.to_string();
+                        // The parameters as sent, rather than reserialized with rounded numbers
+                        let params_str = serde_json::from_str::<WsCall>(&text)
+                            .map(|call| call.params.get().to_string())
+                            .unwrap_or_else(|_| params.to_string());
+                        let call = {
+                            let state = Arc::clone(&state);
+                            async move {
+                                let reply =
+                                    dispatch(&state, &method, &params_str, CallMeta::default()).await;
+                                (seq, Some(ws_reply(id.as_ref(), reply.status, reply.body)))
+                            }
+                        };
+                        if concurrent {
+                            in_flight.push(Box::pin(call));
+                            continue;
+                        }
+                        call.await.1
+                    } else {
+                        let error_response = serde_json::json!({
+                                "error": "Invalid message format. Expected {\"method\": \"method_name\", \"params\": {...}}"
+                            }).to_string();
+                        Some(ws_reply(
+                            id.as_ref(),
+                            StatusCode::BAD_REQUEST,
+                            error_response,
+                        ))
+                    }
+                }
+                Err(e) => {
+                    Some(serde_json::json!({"error": format!("JSON parse error: {}", e)}).to_string())
+                }
+            };
+            if !replies
+                .complete(seq, reply)
+                .into_iter()
+                .all(|reply| outbox.reply(reply))
+            {
+                break;
+            }
+            outbox.resend(resend);
+        }
+        Ok(())
+    }
+    .await;
+
+    outbox.close(None);
+    if outbox.overflowed() {
+        // The client is not reading, so the writer could wait forever; dropping it disconnects
+        writer.abort();
+    } else {
+        let _ = writer.await;
+    }
+    result
+}
+
+/// Whether a WebSocket URL's query string asks for replies in request order.
+fn is_ordered(query: Option<&str>) -> bool {
+    query.is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| matches!(pair, "ordered" | "ordered=true" | "ordered=1"))
+    })
+}
+
+/// The replies of a WebSocket connection, numbered in request order.  On ordered connections
+/// replies that finish early are held back until those of earlier requests have been sent.
+struct Replies {
+    ordered: bool,
+    next_seq: u64,
+    next_release: u64,
+    held: BTreeMap<u64, Option<String>>,
+}
+
+impl Replies {
+    fn new(ordered: bool) -> Self {
+        Self {
+            ordered,
+            next_seq: 0,
+            next_release: 0,
+            held: BTreeMap::new(),
+        }
+    }
+
+    /// Number the next request.
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// Record the reply to request `seq`, if it has one, and return the replies that can be sent
+    /// now.
+    fn complete(&mut self, seq: u64, reply: Option<String>) -> Vec<String> {
+        if !self.ordered {
+            return reply.into_iter().collect();
+        }
+        self.held.insert(seq, reply);
+        let mut ready = Vec::new();
+        while let Some(reply) = self.held.remove(&self.next_release) {
+            ready.extend(reply);
+            self.next_release += 1;
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_date() {
+        assert_eq!(
+            http_date("2025-12-31").as_deref(),
+            Some("Wed, 31 Dec 2025 00:00:00 GMT")
+        );
+        assert_eq!(
+            http_date("2024-02-29").as_deref(),
+            Some("Thu, 29 Feb 2024 00:00:00 GMT")
+        );
+        assert_eq!(
+            http_date("2000-02-29").as_deref(),
+            Some("Tue, 29 Feb 2000 00:00:00 GMT")
+        );
+        for date in [
+            "2025-02-29",
+            "1900-02-29",
+            "2025-02-31",
+            "2025-04-31",
+            "2025-01-00",
+        ] {
+            assert_eq!(http_date(date), None, "{}", date);
+        }
+        assert_eq!(http_date("2025-13-01"), None);
+        assert_eq!(http_date("2025-12"), None);
+    }
+}
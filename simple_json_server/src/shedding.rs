@@ -0,0 +1,212 @@
+//! Adaptive load shedding based on how long calls wait for the actor's mailbox.
+//!
+//! When a percentile of recent mailbox wait times (p95 by default) climbs above the target, the
+//! server starts turning calls away with `503 Service Unavailable` and a `Retry-After` header
+//! instead of letting the queue, and every caller's latency, grow without bound.  Calls carry a
+//! priority in the `X-Priority` header (`low`, `normal` or `high`; `normal` if absent):
+//!
+//! - above the target, `low` priority calls are shed;
+//! - above twice the target, `normal` priority calls are shed as well;
+//! - `high` priority calls are never shed.
+//!
+//! Wait times older than the window are forgotten, so shedding stops by itself once the queue
+//! drains.
+//!
+//! ```rust
+//! use simple_json_server::shedding::LoadShedding;
+//! use simple_json_server::ServerOptions;
+//! use std::time::Duration;
+//!
+//! let shedding = LoadShedding::new(Duration::from_millis(100));
+//! let options = ServerOptions::new(8080).load_shedding(shedding.clone());
+//!
+//! // Later, e.g. from a metrics exporter
+//! println!("p95 wait {:?}, shed {}", shedding.queue_latency(), shedding.shed_count());
+//! ```
+
+use hyper::HeaderMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub(crate) const PRIORITY_HEADER: &str = "x-priority";
+
+/// The most wait times kept, however short the window is.
+const MAX_SAMPLES: usize = 1024;
+
+/// How important a call is, from the `X-Priority` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Shed first.
+    Low,
+    /// The default.
+    #[default]
+    Normal,
+    /// Never shed.
+    High,
+}
+
+impl Priority {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        match headers.get(PRIORITY_HEADER).and_then(|v| v.to_str().ok()) {
+            Some(v) if v.eq_ignore_ascii_case("low") => Priority::Low,
+            Some(v) if v.eq_ignore_ascii_case("high") => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+}
+
+/// Load shedding settings, plus the wait times and counters they are applied to.  Clones share
+/// the same measurements, so keep one to read the metrics of a running server.
+#[derive(Debug, Clone)]
+pub struct LoadShedding {
+    target: Duration,
+    percentile: f64,
+    window: Duration,
+    retry_after: Duration,
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    waits: Mutex<VecDeque<(Instant, Duration)>>,
+    shed: AtomicU64,
+}
+
+impl LoadShedding {
+    /// Shed load when the p95 mailbox wait over the last 10 seconds exceeds `target`.
+    pub fn new(target: Duration) -> Self {
+        Self {
+            target,
+            percentile: 0.95,
+            window: Duration::from_secs(10),
+            retry_after: Duration::from_secs(1),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Which percentile of wait times is compared with the target, between 0 and 1 (default
+    /// 0.95).
+    pub fn percentile(mut self, percentile: f64) -> Self {
+        self.percentile = percentile.clamp(0.0, 1.0);
+        self
+    }
+
+    /// How far back wait times are remembered (default 10 seconds).
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// How long rejected callers are asked to wait before retrying (default 1 second).
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// The configured percentile of recent mailbox wait times.
+    pub fn queue_latency(&self) -> Duration {
+        let mut waits = self.stats.waits.lock().unwrap();
+        let cutoff = Instant::now().checked_sub(self.window);
+        while waits
+            .front()
+            .is_some_and(|(at, _)| cutoff.is_some_and(|cutoff| *at < cutoff))
+        {
+            waits.pop_front();
+        }
+        if waits.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut sorted: Vec<Duration> = waits.iter().map(|(_, wait)| *wait).collect();
+        drop(waits);
+        let rank = ((sorted.len() - 1) as f64 * self.percentile).round() as usize;
+        *sorted.select_nth_unstable(rank).1
+    }
+
+    /// How many calls have been shed.
+    pub fn shed_count(&self) -> u64 {
+        self.stats.shed.load(Ordering::Relaxed)
+    }
+
+    /// Record that a call waited `wait` for the mailbox.
+    pub(crate) fn record_wait(&self, wait: Duration) {
+        let mut waits = self.stats.waits.lock().unwrap();
+        if waits.len() == MAX_SAMPLES {
+            waits.pop_front();
+        }
+        waits.push_back((Instant::now(), wait));
+    }
+
+    /// Whether a call of `priority` should be turned away now.
+    pub(crate) fn should_shed(&self, priority: Priority) -> bool {
+        let threshold = match priority {
+            Priority::High => return false,
+            Priority::Normal => self.target * 2,
+            Priority::Low => self.target,
+        };
+        let shed = self.queue_latency() > threshold;
+        if shed {
+            self.stats.shed.fetch_add(1, Ordering::Relaxed);
+        }
+        shed
+    }
+
+    pub(crate) fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheds_by_priority() {
+        let shedding = LoadShedding::new(Duration::from_millis(100));
+        for _ in 0..20 {
+            shedding.record_wait(Duration::from_millis(150));
+        }
+        assert!(shedding.should_shed(Priority::Low));
+        assert!(!shedding.should_shed(Priority::Normal));
+
+        for _ in 0..20 {
+            shedding.record_wait(Duration::from_millis(300));
+        }
+        assert!(shedding.should_shed(Priority::Normal));
+        assert!(!shedding.should_shed(Priority::High));
+        assert_eq!(shedding.shed_count(), 2);
+    }
+
+    #[test]
+    fn test_percentile_ignores_outliers() {
+        let shedding = LoadShedding::new(Duration::from_millis(100));
+        for _ in 0..99 {
+            shedding.record_wait(Duration::from_millis(1));
+        }
+        shedding.record_wait(Duration::from_secs(5));
+        assert_eq!(shedding.queue_latency(), Duration::from_millis(1));
+        assert!(!shedding.should_shed(Priority::Low));
+    }
+
+    #[test]
+    fn test_old_waits_are_forgotten() {
+        let shedding =
+            LoadShedding::new(Duration::from_millis(10)).window(Duration::from_millis(20));
+        shedding.record_wait(Duration::from_secs(1));
+        assert!(shedding.should_shed(Priority::Low));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(shedding.queue_latency(), Duration::ZERO);
+        assert!(!shedding.should_shed(Priority::Low));
+    }
+
+    #[test]
+    fn test_priority_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Priority::from_headers(&headers), Priority::Normal);
+        headers.insert(PRIORITY_HEADER, "LOW".parse().unwrap());
+        assert_eq!(Priority::from_headers(&headers), Priority::Low);
+        headers.insert(PRIORITY_HEADER, "high".parse().unwrap());
+        assert_eq!(Priority::from_headers(&headers), Priority::High);
+    }
+}
@@ -0,0 +1,338 @@
+//! HMAC-signed requests with replay protection, for webhooks and other calls between services
+//! that share a secret.
+//!
+//! A server given [`Signing`] with [`ServerOptions::signing`](crate::ServerOptions::signing)
+//! only serves HTTP requests that carry three headers: `X-Signature-Timestamp`, the Unix time in
+//! seconds the request was signed at; `X-Signature-Nonce`, a value the sender never uses twice;
+//! and `X-Signature`, `sha256=` followed by the lowercase hex HMAC-SHA256, keyed with the
+//! shared secret, of
+//!
+//! ```text
+//! <timestamp>\n<nonce>\n<path, with the query string if any>\n<body>
+//! ```
+//!
+//! A request is refused with `401 Unauthorized` when a header is missing, the signature doesn't
+//! match, its timestamp is further than [`Signing::window`] (5 minutes by default) from the
+//! server's clock, or its nonce has already been seen within the window, so a captured request
+//! can't be sent again.  Nonces are remembered only while their timestamps are within the
+//! window, at most [`Signing::max_nonces`] of them; while the cache is full of live nonces,
+//! signed requests are turned away with `503 Service Unavailable` rather than risk accepting a
+//! replay.
+//!
+//! ```rust,no_run
+//! use simple_json_server::signing::Signing;
+//! use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+//! use std::time::Duration;
+//!
+//! struct Payments;
+//!
+//! #[actor]
+//! impl Payments {
+//!     pub async fn settled(&self, invoice: String) {}
+//! }
+//!
+//! # async fn example() {
+//! let signing = Signing::new("shared secret").window(Duration::from_secs(60));
+//! Payments.create_with(ServerOptions::new(8080).signing(signing.clone()));
+//!
+//! // The sender signs with the same secret
+//! let payments = ActorRef::new("http://127.0.0.1:8080").with_signing(signing);
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! [`Signing::headers`] makes the headers for senders that aren't an [`ActorRef`](crate::ActorRef).
+//! The admin API, health probes and profiles keep their own authentication and need no
+//! signature.  WebSocket messages carry no per-message headers and are not signed.
+
+use crate::trace;
+use hmac::{Hmac, Mac};
+use hyper::HeaderMap;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The header carrying the signature, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+/// The header carrying the Unix time, in seconds, the request was signed at.
+pub const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+/// The header carrying the request's nonce.
+pub const NONCE_HEADER: &str = "X-Signature-Nonce";
+
+/// Why a request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SigningError {
+    /// A signing header is missing or malformed.
+    Missing(&'static str),
+    /// The signature doesn't match the request.
+    Invalid,
+    /// The timestamp is outside the window.
+    Stale,
+    /// The nonce was already used.
+    Replayed,
+    /// Too many live nonces are remembered to tell whether this one is new.
+    Full,
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningError::Missing(header) => write!(f, "Missing or malformed {} header", header),
+            SigningError::Invalid => write!(f, "Invalid signature"),
+            SigningError::Stale => write!(f, "Request timestamp is outside the allowed window"),
+            SigningError::Replayed => write!(f, "Request nonce was already used"),
+            SigningError::Full => write!(f, "Too many signed requests; try again later"),
+        }
+    }
+}
+
+/// The shared secret and replay protection settings, plus the nonces seen; see the
+/// [module documentation](self).  Clones share the secret and the nonces seen.
+#[derive(Clone)]
+pub struct Signing {
+    secret: Arc<RwLock<Arc<[u8]>>>,
+    window: Duration,
+    max_nonces: usize,
+    /// Nonces seen, with the Unix time in seconds after which they can be forgotten.
+    nonces: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl fmt::Debug for Signing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signing")
+            .field("window", &self.window)
+            .field("max_nonces", &self.max_nonces)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Signing {
+    /// Sign and verify with `secret`, accepting timestamps up to 5 minutes from the server's
+    /// clock and remembering up to 100,000 nonces.
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: Arc::new(RwLock::new(Arc::from(secret.as_ref()))),
+            window: Duration::from_secs(5 * 60),
+            max_nonces: 100_000,
+            nonces: Arc::default(),
+        }
+    }
+
+    /// Accept timestamps up to `window` before or after the server's clock.  Shorter windows
+    /// need fewer nonces remembered but tolerate less clock skew between sender and server.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Remember at most `nonces` nonces at a time.
+    pub fn max_nonces(mut self, nonces: usize) -> Self {
+        self.max_nonces = nonces;
+        self
+    }
+
+    /// Sign and verify with `secret` from now on, e.g. from a
+    /// [`Secret::on_rotate`](crate::secrets::Secret::on_rotate) callback.  Requests signed with
+    /// the old secret are refused.
+    pub fn set_secret(&self, secret: impl AsRef<[u8]>) {
+        *self.secret.write().unwrap() = Arc::from(secret.as_ref());
+    }
+
+    /// The headers signing a request to `path` (with its query string, if any) with `body`,
+    /// timestamped now and with a fresh nonce.
+    pub fn headers(&self, path: &str, body: &str) -> [(&'static str, String); 3] {
+        let timestamp = now().to_string();
+        let nonce = format!(
+            "{}{}",
+            trace::hex(&trace::random_id()),
+            trace::hex(&trace::random_id())
+        );
+        let signature = self.signature(&timestamp, &nonce, path, body);
+        [
+            (TIMESTAMP_HEADER, timestamp),
+            (NONCE_HEADER, nonce),
+            (SIGNATURE_HEADER, signature),
+        ]
+    }
+
+    fn mac(&self, timestamp: &str, nonce: &str, path: &str, body: &str) -> Hmac<Sha256> {
+        let secret = Arc::clone(&self.secret.read().unwrap());
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&secret).expect("HMAC takes keys of any length");
+        for part in [timestamp, nonce, path] {
+            mac.update(part.as_bytes());
+            mac.update(b"\n");
+        }
+        mac.update(body.as_bytes());
+        mac
+    }
+
+    fn signature(&self, timestamp: &str, nonce: &str, path: &str, body: &str) -> String {
+        let mac = self.mac(timestamp, nonce, path, body);
+        format!("sha256={}", trace::hex(&mac.finalize().into_bytes()))
+    }
+
+    /// Check the signature of a request to `path` (with its query string, if any), then that
+    /// it isn't a replay.
+    pub(crate) fn verify(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Result<(), SigningError> {
+        let header = |name: &'static str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .filter(|v| !v.is_empty())
+                .ok_or(SigningError::Missing(name))
+        };
+        let timestamp = header(TIMESTAMP_HEADER)?;
+        let nonce = header(NONCE_HEADER)?;
+        let signature = header(SIGNATURE_HEADER)?
+            .strip_prefix("sha256=")
+            .and_then(parse_hex)
+            .ok_or(SigningError::Missing(SIGNATURE_HEADER))?;
+        // Checked first, so unsigned requests can't fill the nonce cache
+        self.mac(timestamp, nonce, path, body)
+            .verify_slice(&signature)
+            .map_err(|_| SigningError::Invalid)?;
+
+        let signed_at: u64 = timestamp
+            .parse()
+            .map_err(|_| SigningError::Missing(TIMESTAMP_HEADER))?;
+        let now = now();
+        let window = self.window.as_secs();
+        if signed_at.abs_diff(now) > window {
+            return Err(SigningError::Stale);
+        }
+
+        let mut nonces = self.nonces.lock().unwrap();
+        if nonces.contains_key(nonce) {
+            return Err(SigningError::Replayed);
+        }
+        if nonces.len() >= self.max_nonces {
+            nonces.retain(|_, expires| *expires >= now);
+            if nonces.len() >= self.max_nonces {
+                return Err(SigningError::Full);
+            }
+        }
+        nonces.insert(nonce.to_string(), signed_at + window);
+        Ok(())
+    }
+}
+
+/// The Unix time in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Decode lowercase or uppercase hex of any even length.
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn signed(signing: &Signing, path: &str, body: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in signing.headers(path, body) {
+            headers.insert(name, HeaderValue::from_str(&value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_signed_requests_are_accepted_once() {
+        let signing = Signing::new("secret");
+        let headers = signed(&signing, "/pay", r#"{"cents": 1}"#);
+        assert_eq!(signing.verify("/pay", &headers, r#"{"cents": 1}"#), Ok(()));
+        assert_eq!(
+            signing.verify("/pay", &headers, r#"{"cents": 1}"#),
+            Err(SigningError::Replayed)
+        );
+
+        // Changing anything signed breaks the signature
+        let headers = signed(&signing, "/pay", r#"{"cents": 1}"#);
+        assert_eq!(
+            signing.verify("/pay", &headers, r#"{"cents": 100}"#),
+            Err(SigningError::Invalid)
+        );
+        assert_eq!(
+            signing.verify("/refund", &headers, r#"{"cents": 1}"#),
+            Err(SigningError::Invalid)
+        );
+        assert_eq!(
+            Signing::new("other").verify("/pay", &headers, r#"{"cents": 1}"#),
+            Err(SigningError::Invalid)
+        );
+
+        // Clones share the secret as it rotates
+        signing.clone().set_secret("rotated");
+        let headers = signed(&Signing::new("rotated"), "/pay", "{}");
+        assert_eq!(signing.verify("/pay", &headers, "{}"), Ok(()));
+        let headers = signed(&Signing::new("secret"), "/pay", "{}");
+        assert_eq!(
+            signing.verify("/pay", &headers, "{}"),
+            Err(SigningError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_stale_and_unsigned_requests_are_refused() {
+        let signing = Signing::new("secret").window(Duration::from_secs(60));
+        let old = (now() - 120).to_string();
+        let mut headers = HeaderMap::new();
+        let signature = signing.signature(&old, "n1", "/pay", "{}");
+        headers.insert(TIMESTAMP_HEADER, HeaderValue::from_str(&old).unwrap());
+        headers.insert(NONCE_HEADER, HeaderValue::from_static("n1"));
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_str(&signature).unwrap());
+        assert_eq!(
+            signing.verify("/pay", &headers, "{}"),
+            Err(SigningError::Stale)
+        );
+
+        headers.remove(NONCE_HEADER);
+        assert_eq!(
+            signing.verify("/pay", &headers, "{}"),
+            Err(SigningError::Missing(NONCE_HEADER))
+        );
+    }
+
+    #[test]
+    fn test_a_full_cache_refuses_rather_than_forgets() {
+        let signing = Signing::new("secret").max_nonces(2);
+        for _ in 0..2 {
+            let headers = signed(&signing, "/pay", "{}");
+            assert_eq!(signing.verify("/pay", &headers, "{}"), Ok(()));
+        }
+        let headers = signed(&signing, "/pay", "{}");
+        assert_eq!(
+            signing.verify("/pay", &headers, "{}"),
+            Err(SigningError::Full)
+        );
+
+        // Nonces whose timestamps have left the window are forgotten
+        signing
+            .nonces
+            .lock()
+            .unwrap()
+            .values_mut()
+            .for_each(|expires| *expires = 0);
+        assert_eq!(signing.verify("/pay", &headers, "{}"), Ok(()));
+    }
+}
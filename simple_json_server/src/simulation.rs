@@ -0,0 +1,225 @@
+//! Deterministic simulation of an actor, for reproducing bugs that depend on timing and on how
+//! concurrent calls interleave.
+//!
+//! A [`Simulation`] runs a script of calls against an actor on a single thread with a virtual
+//! clock.  Calls are scheduled at virtual times; time only moves forward when every call is
+//! waiting on a timer, so `tokio::time::sleep` and timeouts in the actor take no real time.
+//! Whenever several calls can make progress they are polled in an order drawn from a seeded
+//! generator, so a given seed always produces the same interleaving, and trying many seeds
+//! explores many interleavings.  Calls go through the same dispatch path as over HTTP, including
+//! the mailbox that lets reads run together and `#[write]` methods run alone.
+//!
+//! ```rust
+//! use simple_json_server::simulation::Simulation;
+//! use simple_json_server::{actor, Actor};
+//! use std::sync::atomic::{AtomicU32, Ordering};
+//! use std::time::Duration;
+//!
+//! #[derive(Default)]
+//! pub struct Counter {
+//!     count: AtomicU32,
+//! }
+//!
+//! #[actor]
+//! impl Counter {
+//!     // Racy: the read and the write are separated by an await
+//!     pub async fn increment(&self) -> u32 {
+//!         let count = self.count.load(Ordering::SeqCst);
+//!         tokio::time::sleep(Duration::from_millis(10)).await;
+//!         self.count.store(count + 1, Ordering::SeqCst);
+//!         count + 1
+//!     }
+//! }
+//!
+//! # fn main() {
+//! for seed in 0..10 {
+//!     let calls = Simulation::new(Counter::default(), seed)
+//!         .call("increment", "{}")
+//!         .call("increment", "{}")
+//!         .run();
+//!     // Both calls read 0 before either writes, so an increment is lost
+//!     assert!(calls.iter().all(|call| call.body == "1"), "seed {}", seed);
+//! }
+//! # }
+//! ```
+//!
+//! Only the actor's own calls are simulated: tasks it spawns run on the simulation's thread but
+//! are scheduled by Tokio, and code using `std::time` instead of `tokio::time` sees real time.
+
+use crate::handle::ServerHandle;
+use crate::server::{self, CallMeta, ServerState};
+use crate::{Actor, ServerOptions};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A script of calls to run against an actor.
+pub struct Simulation<T> {
+    actor: T,
+    options: ServerOptions,
+    seed: u64,
+    calls: Vec<Scheduled>,
+}
+
+struct Scheduled {
+    method: String,
+    params: String,
+    at: Duration,
+}
+
+/// The outcome of one simulated call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedCall {
+    /// The position of the call in the script.
+    pub index: usize,
+    /// The method called.
+    pub method: String,
+    /// The HTTP status the call would have been answered with.
+    pub status: u16,
+    /// The JSON response body.
+    pub body: String,
+    /// The virtual time the call finished, since the start of the simulation.
+    pub finished_at: Duration,
+}
+
+impl<T> Simulation<T>
+where
+    T: Actor + Send + Sync + 'static,
+{
+    /// Simulate `actor` with default options, interleaving calls according to `seed`.
+    pub fn new(actor: T, seed: u64) -> Self {
+        Self {
+            actor,
+            options: ServerOptions::default(),
+            seed,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Serve the actor with `options`, as far as they apply to calls.
+    pub fn options(mut self, options: ServerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Call `method` with the JSON `params` at the start of the simulation.
+    pub fn call(self, method: impl Into<String>, params: impl Into<String>) -> Self {
+        self.call_at(Duration::ZERO, method, params)
+    }
+
+    /// Call `method` with the JSON `params` at virtual time `at`.
+    pub fn call_at(
+        mut self,
+        at: Duration,
+        method: impl Into<String>,
+        params: impl Into<String>,
+    ) -> Self {
+        self.calls.push(Scheduled {
+            method: method.into(),
+            params: params.into(),
+            at,
+        });
+        self
+    }
+
+    /// Run the calls to completion and return their outcomes in the order they finished.
+    pub fn run(self) -> Vec<SimulatedCall> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .expect("failed to build the simulation runtime");
+        let state = Arc::new(ServerState::new(
+            self.actor,
+            self.options,
+            ServerHandle::new().lifecycle(),
+        ));
+        let calls = self.calls;
+        let seed = self.seed;
+        runtime.block_on(async move {
+            let start = tokio::time::Instant::now();
+            let pending = calls
+                .iter()
+                .enumerate()
+                .map(|(index, call)| {
+                    let state = &state;
+                    let future: Pin<Box<dyn Future<Output = SimulatedCall> + '_>> =
+                        Box::pin(async move {
+                            tokio::time::sleep_until(start + call.at).await;
+                            let reply = server::dispatch(
+                                state,
+                                &call.method,
+                                &call.params,
+                                CallMeta::default(),
+                            )
+                            .await;
+                            SimulatedCall {
+                                index,
+                                method: call.method.clone(),
+                                status: reply.status.as_u16(),
+                                body: reply.body,
+                                finished_at: start.elapsed(),
+                            }
+                        });
+                    future
+                })
+                .collect();
+            Interleave {
+                pending,
+                finished: Vec::new(),
+                rng: SplitMix64(seed),
+            }
+            .await
+        })
+    }
+}
+
+/// Polls its futures in a seeded random order every time it is woken.
+struct Interleave<'a> {
+    pending: Vec<Pin<Box<dyn Future<Output = SimulatedCall> + 'a>>>,
+    finished: Vec<SimulatedCall>,
+    rng: SplitMix64,
+}
+
+impl Future for Interleave<'_> {
+    type Output = Vec<SimulatedCall>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for i in (1..this.pending.len()).rev() {
+            let j = (this.rng.next() % (i as u64 + 1)) as usize;
+            this.pending.swap(i, j);
+        }
+        let mut i = 0;
+        while i < this.pending.len() {
+            match this.pending[i].as_mut().poll(cx) {
+                Poll::Ready(call) => {
+                    this.finished.push(call);
+                    // Keep the shuffled order of the rest
+                    drop(this.pending.remove(i));
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+        if this.pending.is_empty() {
+            Poll::Ready(std::mem::take(&mut this.finished))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// A small seeded generator; the quality needed to shuffle a few futures is modest.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,238 @@
+//! Logging of calls that take longer than a threshold.
+//!
+//! Each slow call is reported with its method, its parameters (with sensitive fields and
+//! parameters marked `#[sensitive]` redacted) and where the time went: waiting for the actor's mailbox, or running the handler.  By default
+//! reports are logged with `log::warn!`; [`SlowLog::on_slow`] sends them somewhere else instead.
+//!
+//! ```rust
+//! use simple_json_server::slowlog::SlowLog;
+//! use simple_json_server::ServerOptions;
+//! use std::time::Duration;
+//!
+//! let options = ServerOptions::new(8080).slow_log(
+//!     SlowLog::new(Duration::from_millis(250)).redact(["card_number"]),
+//! );
+//! ```
+
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Parameter fields redacted by default, matched case-insensitively at any depth.
+pub(crate) const DEFAULT_REDACTED: &[&str] =
+    &["password", "secret", "token", "authorization", "api_key"];
+
+/// Longest parameter text included in a report; longer parameters are truncated.
+const MAX_PARAMS_LEN: usize = 1024;
+
+/// A call that exceeded the slow log threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowCall {
+    /// The method called.
+    pub method: String,
+    /// The call's JSON parameters, with sensitive fields redacted.
+    pub params: String,
+    /// The HTTP status the call was answered with.
+    pub status: u16,
+    /// Time from the call arriving to the response being ready.
+    pub total: Duration,
+    /// Time spent waiting for the actor's mailbox.
+    pub queue_wait: Duration,
+    /// Time spent running the call once admitted, including middleware.
+    pub handler: Duration,
+}
+
+impl fmt::Display for SlowCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Slow call to {} took {:?} (queue {:?}, handler {:?}, status {}) with params {}",
+            self.method, self.total, self.queue_wait, self.handler, self.status, self.params
+        )
+    }
+}
+
+type SlowCallHook = Arc<dyn Fn(&SlowCall) + Send + Sync>;
+
+/// Slow log settings, passed to [`crate::ServerOptions::slow_log`].
+#[derive(Clone)]
+pub struct SlowLog {
+    threshold: Duration,
+    redacted: Vec<String>,
+    on_slow: Option<SlowCallHook>,
+}
+
+impl fmt::Debug for SlowLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowLog")
+            .field("threshold", &self.threshold)
+            .field("redacted", &self.redacted)
+            .finish()
+    }
+}
+
+impl SlowLog {
+    /// Report calls that take longer than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            redacted: DEFAULT_REDACTED.iter().map(|f| f.to_string()).collect(),
+            on_slow: None,
+        }
+    }
+
+    /// Also redact parameter fields named `fields`, in addition to the defaults (`password`,
+    /// `secret`, `token`, `authorization` and `api_key`).
+    pub fn redact<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.redacted.extend(fields.into_iter().map(Into::into));
+        self
+    }
+
+    /// Call `hook` with each slow call instead of logging it, e.g. to emit an event or count it.
+    pub fn on_slow(mut self, hook: impl Fn(&SlowCall) + Send + Sync + 'static) -> Self {
+        self.on_slow = Some(Arc::new(hook));
+        self
+    }
+
+    /// Report the call if it was slow, redacting the parameters named `sensitive` as well.
+    pub(crate) fn observe(
+        &self,
+        method: &str,
+        params: &str,
+        sensitive: &[&str],
+        status: u16,
+        total: Duration,
+        queue_wait: Duration,
+    ) {
+        if total <= self.threshold {
+            return;
+        }
+        let call = SlowCall {
+            method: method.to_string(),
+            params: self.redacted_params(params, sensitive),
+            status,
+            total,
+            queue_wait,
+            handler: total.saturating_sub(queue_wait),
+        };
+        match &self.on_slow {
+            Some(hook) => hook(&call),
+            None => log::warn!(
+                method = call.method.as_str(),
+                status = call.status,
+                total_ms = call.total.as_millis() as u64,
+                queue_ms = call.queue_wait.as_millis() as u64,
+                handler_ms = call.handler.as_millis() as u64;
+                "{}",
+                call
+            ),
+        }
+    }
+
+    fn redacted_params(&self, params: &str, sensitive: &[&str]) -> String {
+        redact(params, &self.redacted, sensitive, MAX_PARAMS_LEN)
+    }
+}
+
+/// `text` with the JSON fields named `fields` redacted, and the top-level fields named
+/// `sensitive` (the parameters marked `#[sensitive]`), truncated to about `max_len` bytes.
+pub(crate) fn redact(text: &str, fields: &[String], sensitive: &[&str], max_len: usize) -> String {
+    let mut text = match serde_json::from_str::<Value>(text) {
+        Ok(mut value) => {
+            if let Value::Object(object) = &mut value {
+                for (name, field) in object.iter_mut() {
+                    if sensitive.contains(&name.as_str()) {
+                        *field = Value::String("[REDACTED]".to_string());
+                    }
+                }
+            }
+            redact_value(&mut value, fields);
+            value.to_string()
+        }
+        // Not JSON, so there are no fields to find; leave out the content entirely
+        Err(_) => format!("<{} bytes of invalid JSON>", text.len()),
+    };
+    if text.len() > max_len {
+        let mut end = max_len;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    text
+}
+
+fn redact_value(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(object) => {
+            for (name, field) in object.iter_mut() {
+                if fields
+                    .iter()
+                    .any(|redacted| redacted.eq_ignore_ascii_case(name))
+                {
+                    *field = Value::String("[REDACTED]".to_string());
+                } else {
+                    redact_value(field, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(item, fields)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_redacts_nested_fields() {
+        let slow_log = SlowLog::new(Duration::ZERO).redact(["ssn"]);
+        let params = r#"{"user": {"name": "ada", "Password": "hunter2", "ssn": "123"}, "items": [{"token": "t"}]}"#;
+        let redacted: Value =
+            serde_json::from_str(&slow_log.redacted_params(params, &["items"])).unwrap();
+        assert_eq!(redacted["user"]["name"], "ada");
+        assert_eq!(redacted["user"]["Password"], "[REDACTED]");
+        assert_eq!(redacted["user"]["ssn"], "[REDACTED]");
+        assert_eq!(redacted["items"], "[REDACTED]");
+    }
+
+    #[test]
+    fn test_long_and_invalid_params() {
+        let slow_log = SlowLog::new(Duration::ZERO);
+        let long = serde_json::json!({ "data": "x".repeat(5000) }).to_string();
+        assert_eq!(
+            slow_log.redacted_params(&long, &[]).len(),
+            MAX_PARAMS_LEN + 3
+        );
+        assert_eq!(
+            slow_log.redacted_params("password=hunter2", &[]),
+            "<16 bytes of invalid JSON>"
+        );
+    }
+
+    #[test]
+    fn test_only_slow_calls_are_reported() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&reported);
+        let slow_log = SlowLog::new(Duration::from_millis(100))
+            .on_slow(move |call| seen.lock().unwrap().push(call.clone()));
+
+        let fast = Duration::from_millis(50);
+        let slow = Duration::from_millis(150);
+        slow_log.observe("add", "{}", &[], 200, fast, Duration::ZERO);
+        slow_log.observe("add", "{}", &[], 200, slow, Duration::from_millis(100));
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].queue_wait, Duration::from_millis(100));
+        assert_eq!(reported[0].handler, Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,675 @@
+//! Consistent backups of actor state.
+//!
+//! An actor opts in by defining a `snapshot` and a `restore` hook in its `#[actor]` impl block:
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor};
+//! use std::collections::HashMap;
+//! use std::sync::Mutex;
+//!
+//! struct Inventory {
+//!     stock: Mutex<HashMap<String, u32>>,
+//! }
+//!
+//! #[actor]
+//! impl Inventory {
+//!     pub async fn add(&self, item: String, count: u32) {
+//!         *self.stock.lock().unwrap().entry(item).or_default() += count;
+//!     }
+//!
+//!     fn snapshot(&self) -> HashMap<String, u32> {
+//!         self.stock.lock().unwrap().clone()
+//!     }
+//!
+//!     fn restore(&self, stock: HashMap<String, u32>) {
+//!         *self.stock.lock().unwrap() = stock;
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! With a [`SnapshotStore`] configured (see [`crate::ServerOptions::snapshots`]) and an admin
+//! token set, `POST /__admin/snapshot` pauses the actor's mailbox, captures its state, and writes
+//! it to the store; no call runs while the state is being captured, so the snapshot is
+//! consistent.  `POST /__admin/restore` loads the latest snapshot back, and
+//! [`crate::ServerOptions::restore_on_start`] does the same when the server starts.  A server
+//! with a [journal](crate::journal) drops the entries a snapshot includes once it is written.
+//!
+//! [`FileSnapshotStore`] keeps snapshots on local disk.  Other destinations, such as S3, are
+//! supported by implementing [`SnapshotStore`], or with the `object-store` feature by an
+//! `ObjectStoreSnapshots`, which keeps them in S3, GCS or any other
+//! [object store](https://docs.rs/object_store).  Wrapping any store in an
+//! [`EncryptedSnapshots`] encrypts the state it holds; see [`crate::encryption`].  With the
+//! `compression` feature, snapshots can also be compressed; see `crate::compression`.
+
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::encryption::Encryption;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+/// A captured copy of an actor's state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// When the snapshot was taken, in milliseconds since the Unix epoch.
+    pub taken_at_ms: u64,
+    /// The state, as serialized by the actor's `snapshot` hook.
+    pub state: String,
+    /// The sequence number of the last [journal](crate::journal) entry the state includes, or
+    /// 0 if the server keeps no journal.  A replica restored from the snapshot follows the
+    /// primary from the next entry.
+    #[serde(default)]
+    pub journal_seq: u64,
+}
+
+impl Snapshot {
+    pub(crate) fn now(state: String, journal_seq: u64) -> Self {
+        Self {
+            taken_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            state,
+            journal_seq,
+        }
+    }
+}
+
+/// Somewhere snapshots can be written to and read back from.
+pub trait SnapshotStore: Send + Sync {
+    /// Store a snapshot.
+    fn save<'a>(
+        &'a self,
+        snapshot: &'a Snapshot,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+
+    /// The most recently stored snapshot, if there is one.
+    fn latest(
+        &self,
+    ) -> BoxFuture<'_, Result<Option<Snapshot>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// Writes each snapshot to its own JSON file in a directory.
+#[derive(Debug, Clone)]
+pub struct FileSnapshotStore {
+    dir: PathBuf,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+}
+
+impl FileSnapshotStore {
+    /// Store snapshots in `dir`, which is created on first save if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            #[cfg(feature = "compression")]
+            compression: None,
+        }
+    }
+
+    /// Compress the snapshot files written from now on, streaming each snapshot through the
+    /// compressor as it is written.  Files written uncompressed, or compressed another way,
+    /// are still read back.
+    #[cfg(feature = "compression")]
+    pub fn compressed(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Whether `name` is a snapshot file, possibly compressed.
+    fn is_snapshot(name: &str) -> bool {
+        let Some(rest) = name.strip_prefix("snapshot-") else {
+            return false;
+        };
+        #[cfg(feature = "compression")]
+        if let Some((rest, extension)) = rest.rsplit_once('.') {
+            if Compression::from_extension(extension).is_some() {
+                return rest.ends_with(".json");
+            }
+        }
+        rest.ends_with(".json")
+    }
+}
+
+impl SnapshotStore for FileSnapshotStore {
+    fn save<'a>(
+        &'a self,
+        snapshot: &'a Snapshot,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Zero-padded so file names sort in the order the snapshots were taken
+            let path = self
+                .dir
+                .join(format!("snapshot-{:020}.json", snapshot.taken_at_ms));
+            #[cfg(feature = "compression")]
+            if let Some(compression) = self.compression {
+                let path = path.with_extension(format!("json.{}", compression.extension()));
+                let snapshot = snapshot.clone();
+                return tokio::task::spawn_blocking(move || {
+                    let tmp = path.with_extension(format!("{}.tmp", compression.extension()));
+                    let file = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+                    let file = compression.write_json(file, &snapshot)?;
+                    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                    std::fs::rename(&tmp, &path)
+                })
+                .await?
+                .map_err(Into::into);
+            }
+            let tmp = path.with_extension("json.tmp");
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            file.write_all(&serde_json::to_vec(snapshot)?).await?;
+            // On disk before the rename, so a crash can't leave an empty snapshot behind it
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            Ok(())
+        })
+    }
+
+    fn latest(
+        &self,
+    ) -> BoxFuture<'_, Result<Option<Snapshot>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            let mut dir = match tokio::fs::read_dir(&self.dir).await {
+                Ok(dir) => dir,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let mut latest: Option<PathBuf> = None;
+            while let Some(entry) = dir.next_entry().await? {
+                let path = entry.path();
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if Self::is_snapshot(&name) && latest.as_ref().is_none_or(|latest| path > *latest) {
+                    latest = Some(path);
+                }
+            }
+            let Some(path) = latest else {
+                return Ok(None);
+            };
+            #[cfg(feature = "compression")]
+            if let Some(compression) = path
+                .extension()
+                .and_then(|extension| Compression::from_extension(&extension.to_string_lossy()))
+            {
+                return tokio::task::spawn_blocking(move || {
+                    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+                    compression.read_json(file)
+                })
+                .await?
+                .map(Some)
+                .map_err(Into::into);
+            }
+            Ok(Some(serde_json::from_slice(&tokio::fs::read(path).await?)?))
+        })
+    }
+}
+
+/// A [`SnapshotStore`] that encrypts the state of the snapshots it saves in another store, and
+/// decrypts it as it reads them back.  A snapshot that was not encrypted, or not with one of
+/// its keys, is refused rather than restored.
+#[derive(Clone)]
+pub struct EncryptedSnapshots {
+    inner: Arc<dyn SnapshotStore>,
+    encryption: Encryption,
+}
+
+impl fmt::Debug for EncryptedSnapshots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedSnapshots")
+            .field("encryption", &self.encryption)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedSnapshots {
+    /// Keep snapshots in `inner`, encrypted with `encryption`.
+    pub fn new(inner: impl SnapshotStore + 'static, encryption: Encryption) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            encryption,
+        }
+    }
+}
+
+impl SnapshotStore for EncryptedSnapshots {
+    fn save<'a>(
+        &'a self,
+        snapshot: &'a Snapshot,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            let context = snapshot.taken_at_ms.to_string();
+            let sealed = Snapshot {
+                taken_at_ms: snapshot.taken_at_ms,
+                journal_seq: snapshot.journal_seq,
+                state: self
+                    .encryption
+                    .encrypt(snapshot.state.as_bytes(), context.as_bytes()),
+            };
+            self.inner.save(&sealed).await
+        })
+    }
+
+    fn latest(
+        &self,
+    ) -> BoxFuture<'_, Result<Option<Snapshot>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            let Some(sealed) = self.inner.latest().await? else {
+                return Ok(None);
+            };
+            let context = sealed.taken_at_ms.to_string();
+            let state = self
+                .encryption
+                .decrypt_string(&sealed.state, context.as_bytes())?;
+            Ok(Some(Snapshot {
+                taken_at_ms: sealed.taken_at_ms,
+                state,
+                journal_seq: sealed.journal_seq,
+            }))
+        })
+    }
+}
+
+/// A [`SnapshotStore`] that compresses the state of the snapshots it saves in another store, and
+/// decompresses it as it reads them back.  The compressed state is kept as base64, marked with
+/// its algorithm, so snapshots saved uncompressed or compressed another way are still read back.
+#[cfg(feature = "compression")]
+#[derive(Clone)]
+pub struct CompressedSnapshots {
+    inner: Arc<dyn SnapshotStore>,
+    compression: Compression,
+}
+
+#[cfg(feature = "compression")]
+impl fmt::Debug for CompressedSnapshots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedSnapshots")
+            .field("compression", &self.compression)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "compression")]
+impl CompressedSnapshots {
+    /// Keep snapshots in `inner`, compressed with `compression`.
+    pub fn new(inner: impl SnapshotStore + 'static, compression: Compression) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            compression,
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl SnapshotStore for CompressedSnapshots {
+    fn save<'a>(
+        &'a self,
+        snapshot: &'a Snapshot,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        use base64::Engine;
+        Box::pin(async move {
+            let compressed = self.compression.compress(snapshot.state.as_bytes())?;
+            let packed = Snapshot {
+                taken_at_ms: snapshot.taken_at_ms,
+                journal_seq: snapshot.journal_seq,
+                state: format!(
+                    "{}:{}",
+                    self.compression.extension(),
+                    base64::engine::general_purpose::STANDARD.encode(compressed)
+                ),
+            };
+            self.inner.save(&packed).await
+        })
+    }
+
+    fn latest(
+        &self,
+    ) -> BoxFuture<'_, Result<Option<Snapshot>, Box<dyn std::error::Error + Send + Sync>>> {
+        use base64::Engine;
+        Box::pin(async move {
+            let Some(mut snapshot) = self.inner.latest().await? else {
+                return Ok(None);
+            };
+            let compressed = snapshot.state.split_once(':').and_then(|(marker, data)| {
+                Compression::from_extension(marker).map(|compression| (compression, data))
+            });
+            if let Some((compression, data)) = compressed {
+                let data = base64::engine::general_purpose::STANDARD.decode(data)?;
+                snapshot.state = String::from_utf8(compression.decompress(&data)?)?;
+            }
+            Ok(Some(snapshot))
+        })
+    }
+}
+
+/// Keeps snapshots as JSON objects under a prefix of an object store, such as an S3 or GCS
+/// bucket, so actors on containers without durable disks can still persist their state.
+///
+/// Snapshots larger than the [part size](Self::part_size) are sent as multipart uploads.  Old
+/// snapshots are never deleted; leave that to the bucket's lifecycle rules, which can match
+/// the prefix, or the [tags](Self::tag) and [storage class](Self::storage_class) given here.
+///
+/// ```rust,no_run
+/// use simple_json_server::snapshot::ObjectStoreSnapshots;
+///
+/// // Credentials and region from `AWS_*` environment variables, for an `s3://` URL
+/// let store = ObjectStoreSnapshots::from_url("s3://backups/inventory")
+///     .unwrap()
+///     .storage_class("STANDARD_IA")
+///     .tag("retention", "30d");
+/// ```
+#[cfg(feature = "object-store")]
+#[derive(Clone)]
+pub struct ObjectStoreSnapshots {
+    store: Arc<dyn object_store::ObjectStore>,
+    prefix: object_store::path::Path,
+    part_size: usize,
+    tags: Vec<(String, String)>,
+    storage_class: Option<String>,
+}
+
+#[cfg(feature = "object-store")]
+impl fmt::Debug for ObjectStoreSnapshots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectStoreSnapshots")
+            .field("store", &self.store.to_string())
+            .field("prefix", &self.prefix)
+            .field("part_size", &self.part_size)
+            .field("tags", &self.tags)
+            .field("storage_class", &self.storage_class)
+            .finish()
+    }
+}
+
+#[cfg(feature = "object-store")]
+impl ObjectStoreSnapshots {
+    /// Store snapshots in `store`, under `prefix`.
+    pub fn new(store: impl object_store::ObjectStore, prefix: &str) -> Self {
+        Self {
+            store: Arc::new(store),
+            prefix: object_store::path::Path::from(prefix),
+            part_size: 8 * 1024 * 1024,
+            tags: Vec::new(),
+            storage_class: None,
+        }
+    }
+
+    /// Store snapshots under the location `url`, such as `s3://bucket/prefix` or
+    /// `gs://bucket/prefix`, configured from the environment (for example `AWS_REGION` or
+    /// `GOOGLE_SERVICE_ACCOUNT`).  S3 needs the `s3` feature and GCS the `gcs` feature.
+    pub fn from_url(url: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let url = url::Url::parse(url)?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(&url, options)?;
+        Ok(Self {
+            store: Arc::from(store),
+            prefix,
+            ..Self::new(object_store::memory::InMemory::new(), "")
+        })
+    }
+
+    /// Upload snapshots larger than `bytes` in parts of `bytes` each, 8 MiB by default.  S3
+    /// refuses parts smaller than 5 MiB.
+    pub fn part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(1);
+        self
+    }
+
+    /// Tag every snapshot with `key` = `value`, for lifecycle rules to match.  Stores without
+    /// tags ignore them.
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Write snapshots with this storage class, such as `STANDARD_IA` on S3 or `NEARLINE` on
+    /// GCS.
+    pub fn storage_class(mut self, class: impl Into<String>) -> Self {
+        self.storage_class = Some(class.into());
+        self
+    }
+
+    fn tag_set(&self) -> object_store::TagSet {
+        let mut tags = object_store::TagSet::default();
+        for (key, value) in &self.tags {
+            tags.push(key, value);
+        }
+        tags
+    }
+
+    fn attributes(&self) -> object_store::Attributes {
+        let mut attributes = object_store::Attributes::new();
+        attributes.insert(
+            object_store::Attribute::ContentType,
+            "application/json".into(),
+        );
+        if let Some(class) = &self.storage_class {
+            attributes.insert(object_store::Attribute::StorageClass, class.clone().into());
+        }
+        attributes
+    }
+}
+
+#[cfg(feature = "object-store")]
+impl SnapshotStore for ObjectStoreSnapshots {
+    fn save<'a>(
+        &'a self,
+        snapshot: &'a Snapshot,
+    ) -> BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            // Zero-padded so names sort in the order the snapshots were taken
+            let path = self
+                .prefix
+                .child(format!("snapshot-{:020}.json", snapshot.taken_at_ms));
+            let body = serde_json::to_vec(snapshot)?;
+            if body.len() <= self.part_size {
+                let options = object_store::PutOptions {
+                    tags: self.tag_set(),
+                    attributes: self.attributes(),
+                    ..Default::default()
+                };
+                self.store.put_opts(&path, body.into(), options).await?;
+                return Ok(());
+            }
+            let options = object_store::PutMultipartOptions {
+                tags: self.tag_set(),
+                attributes: self.attributes(),
+                ..Default::default()
+            };
+            let upload = self.store.put_multipart_opts(&path, options).await?;
+            let mut upload =
+                object_store::WriteMultipart::new_with_chunk_size(upload, self.part_size);
+            for part in body.chunks(self.part_size) {
+                if let Err(e) = upload.wait_for_capacity(4).await {
+                    upload.abort().await.ok();
+                    return Err(e.into());
+                }
+                upload.write(part);
+            }
+            upload.finish().await?;
+            Ok(())
+        })
+    }
+
+    fn latest(
+        &self,
+    ) -> BoxFuture<'_, Result<Option<Snapshot>, Box<dyn std::error::Error + Send + Sync>>> {
+        use futures_util::StreamExt;
+        Box::pin(async move {
+            let mut objects = self.store.list(Some(&self.prefix));
+            let mut latest: Option<object_store::path::Path> = None;
+            while let Some(object) = objects.next().await {
+                let path = object?.location;
+                if path
+                    .filename()
+                    .is_some_and(|name| name.starts_with("snapshot-") && name.ends_with(".json"))
+                    && latest.as_ref().is_none_or(|latest| path > *latest)
+                {
+                    latest = Some(path);
+                }
+            }
+            match latest {
+                Some(path) => {
+                    let body = self.store.get(&path).await?.bytes().await?;
+                    Ok(Some(serde_json::from_slice(&body)?))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store_returns_latest() {
+        let dir = std::env::temp_dir().join(format!("snapshot_store_test_{}", std::process::id()));
+        let store = FileSnapshotStore::new(&dir);
+        assert!(store.latest().await.unwrap().is_none());
+
+        for (taken_at_ms, state) in [(5, "old"), (20, "new"), (9, "middle")] {
+            store
+                .save(&Snapshot {
+                    taken_at_ms,
+                    journal_seq: 0,
+                    state: state.to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.latest().await.unwrap().unwrap().state, "new");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_snapshots() {
+        let dir =
+            std::env::temp_dir().join(format!("encrypted_snapshot_test_{}", std::process::id()));
+        let plain = FileSnapshotStore::new(&dir);
+        let encrypted = EncryptedSnapshots::new(plain.clone(), Encryption::new([1; 32]).unwrap());
+        let snapshot = Snapshot {
+            taken_at_ms: 5,
+            journal_seq: 0,
+            state: r#"{"balance": 100}"#.to_string(),
+        };
+        encrypted.save(&snapshot).await.unwrap();
+        assert!(!plain
+            .latest()
+            .await
+            .unwrap()
+            .unwrap()
+            .state
+            .contains("balance"));
+        assert_eq!(encrypted.latest().await.unwrap(), Some(snapshot));
+
+        // A snapshot written in the clear is refused
+        plain
+            .save(&Snapshot {
+                taken_at_ms: 6,
+                journal_seq: 0,
+                state: "{}".to_string(),
+            })
+            .await
+            .unwrap();
+        let error = encrypted.latest().await.unwrap_err();
+        assert_eq!(error.to_string(), "The value is not encrypted");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_compressed_snapshots() {
+        let dir =
+            std::env::temp_dir().join(format!("compressed_snapshot_test_{}", std::process::id()));
+        let state = format!(
+            "[{}]",
+            vec![r#"{"item": "apple", "count": 3}"#; 1000].join(",")
+        );
+        let plain = FileSnapshotStore::new(&dir);
+        plain
+            .save(&Snapshot {
+                taken_at_ms: 1,
+                journal_seq: 0,
+                state: "{}".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // Compressed files, read back along with the uncompressed one before them
+        let files = FileSnapshotStore::new(&dir).compressed(Compression::zstd(19));
+        assert_eq!(files.latest().await.unwrap().unwrap().state, "{}");
+        let snapshot = Snapshot {
+            taken_at_ms: 2,
+            journal_seq: 0,
+            state: state.clone(),
+        };
+        files.save(&snapshot).await.unwrap();
+        let written = dir.join("snapshot-00000000000000000002.json.zst");
+        assert!(std::fs::metadata(&written).unwrap().len() < state.len() as u64 / 10);
+        assert_eq!(plain.latest().await.unwrap(), Some(snapshot.clone()));
+        std::fs::remove_file(written).unwrap();
+
+        // Compressed state inside another store, outside encryption
+        let encrypted = EncryptedSnapshots::new(plain.clone(), Encryption::new([1; 32]).unwrap());
+        let compressed = CompressedSnapshots::new(encrypted, Compression::lz4());
+        assert_eq!(
+            compressed.latest().await.unwrap_err().to_string(),
+            "The value is not encrypted"
+        );
+        compressed.save(&snapshot).await.unwrap();
+        assert!(plain.latest().await.unwrap().unwrap().state.len() < state.len() / 5);
+        assert_eq!(compressed.latest().await.unwrap(), Some(snapshot));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "object-store")]
+    #[tokio::test]
+    async fn test_object_store_snapshots() {
+        use object_store::memory::InMemory;
+        use object_store::ObjectStore;
+
+        let bucket: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = ObjectStoreSnapshots::new(Arc::clone(&bucket), "inventory/snapshots")
+            .part_size(1024)
+            .storage_class("STANDARD_IA")
+            .tag("retention", "30d");
+        assert!(store.latest().await.unwrap().is_none());
+
+        let large = "x".repeat(5000);
+        for (taken_at_ms, state) in [(5, "old"), (20, large.as_str()), (9, "middle")] {
+            store
+                .save(&Snapshot {
+                    taken_at_ms,
+                    journal_seq: 0,
+                    state: state.to_string(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.latest().await.unwrap().unwrap().state, large);
+
+        let path = object_store::path::Path::from(
+            "inventory/snapshots/snapshot-00000000000000000020.json",
+        );
+        let object = bucket.get(&path).await.unwrap();
+        assert_eq!(
+            object
+                .attributes
+                .get(&object_store::Attribute::StorageClass),
+            Some(&"STANDARD_IA".into())
+        );
+
+        // Objects elsewhere in the bucket are not snapshots
+        let other = ObjectStoreSnapshots::new(Arc::clone(&bucket), "orders/snapshots");
+        assert!(other.latest().await.unwrap().is_none());
+    }
+}
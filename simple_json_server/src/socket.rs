@@ -0,0 +1,233 @@
+//! TCP settings for the listening socket and the connections it accepts.
+//!
+//! By default a server leaves Nagle's algorithm on, doesn't send keepalive probes, queues up to
+//! 1024 connections waiting to be accepted and takes the operating system's socket buffer
+//! sizes.  Latency-sensitive deployments usually want `TCP_NODELAY`, so small responses aren't
+//! held back waiting for more to send, and servers behind load balancers that silently drop idle
+//! connections want keepalive probes sent sooner than the system default of two hours:
+//!
+//! ```rust
+//! use simple_json_server::socket::SocketOptions;
+//! use simple_json_server::ServerOptions;
+//! use std::time::Duration;
+//!
+//! let socket = SocketOptions::new()
+//!     .nodelay(true)
+//!     .keepalive(Duration::from_secs(60))
+//!     .keepalive_interval(Duration::from_secs(10))
+//!     .keepalive_retries(3)
+//!     .backlog(4096);
+//! let options = ServerOptions::new(8080).socket(socket);
+//! ```
+//!
+//! The settings apply to plain and TLS listeners alike.  Buffer sizes are set on the listening
+//! socket, so accepted connections inherit them; the operating system may round them, and Linux
+//! doubles them.  Keepalive intervals and retries are ignored, with a warning, on platforms that
+//! don't support setting them.  HTTP/3 runs over UDP and is not affected.
+
+use socket2::{SockRef, Socket, TcpKeepalive};
+use std::io;
+use std::time::Duration;
+
+/// TCP settings for a server's listener and connections; see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    backlog: i32,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: false,
+            keepalive: None,
+            keepalive_interval: None,
+            keepalive_retries: None,
+            backlog: 1024,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// The default settings: Nagle's algorithm on, no keepalive, a backlog of 1024 and the
+    /// system's buffer sizes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `TCP_NODELAY` on accepted connections, sending small writes at once instead of
+    /// coalescing them.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Turn on `SO_KEEPALIVE` for accepted connections, sending the first probe after a
+    /// connection has been idle for `idle`.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// How long to wait between keepalive probes that go unanswered.  Only used with
+    /// [`SocketOptions::keepalive`].
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// How many unanswered keepalive probes close the connection.  Only used with
+    /// [`SocketOptions::keepalive`].
+    pub fn keepalive_retries(mut self, retries: u32) -> Self {
+        self.keepalive_retries = Some(retries);
+        self
+    }
+
+    /// How many connections may wait to be accepted (default 1024).  The system may cap it,
+    /// e.g. at `net.core.somaxconn` on Linux.
+    pub fn backlog(mut self, backlog: i32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Set `SO_RCVBUF`, the receive buffer size in bytes.
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set `SO_SNDBUF`, the send buffer size in bytes.
+    pub fn send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+        self
+    }
+
+    /// The backlog to listen with.
+    pub(crate) fn listen_backlog(&self) -> i32 {
+        self.backlog
+    }
+
+    /// Apply the buffer sizes to a listening socket, before it listens.
+    pub(crate) fn apply_to_listener(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(bytes) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(bytes)?;
+        }
+        if let Some(bytes) = self.send_buffer_size {
+            socket.set_send_buffer_size(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Apply `TCP_NODELAY` and keepalive to an accepted connection.
+    pub(crate) fn apply_to_stream<'s>(&self, stream: impl Into<SockRef<'s>>) -> io::Result<()> {
+        let socket = stream.into();
+        if self.nodelay {
+            socket.set_tcp_nodelay(true)?;
+        }
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&self.tcp_keepalive(idle))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "windows",
+    ))]
+    fn tcp_keepalive(&self, idle: Duration) -> TcpKeepalive {
+        let mut keepalive = TcpKeepalive::new().with_time(idle);
+        if let Some(interval) = self.keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        if let Some(retries) = self.keepalive_retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+        keepalive
+    }
+
+    #[cfg(not(any(
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "ios",
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "netbsd",
+        target_os = "windows",
+    )))]
+    fn tcp_keepalive(&self, idle: Duration) -> TcpKeepalive {
+        if self.keepalive_interval.is_some() || self.keepalive_retries.is_some() {
+            log::warn!("Keepalive intervals and retries are not supported here; ignoring them");
+        }
+        TcpKeepalive::new().with_time(idle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_defaults_change_nothing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let options = SocketOptions::new();
+        assert_eq!(options.listen_backlog(), 1024);
+        options.apply_to_stream(&stream).unwrap();
+        assert!(!stream.nodelay().unwrap());
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[test]
+    fn test_stream_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let options = SocketOptions::new()
+            .nodelay(true)
+            .keepalive(Duration::from_secs(30))
+            .keepalive_interval(Duration::from_secs(5))
+            .keepalive_retries(4);
+        options.apply_to_stream(&stream).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                socket.tcp_keepalive_time().unwrap(),
+                Duration::from_secs(30)
+            );
+            assert_eq!(
+                socket.tcp_keepalive_interval().unwrap(),
+                Duration::from_secs(5)
+            );
+            assert_eq!(socket.tcp_keepalive_retries().unwrap(), 4);
+        }
+    }
+
+    #[test]
+    fn test_buffer_sizes() {
+        let socket = Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        let options = SocketOptions::new()
+            .recv_buffer_size(64 * 1024)
+            .send_buffer_size(32 * 1024);
+        options.apply_to_listener(&socket).unwrap();
+        // Systems round the sizes, and Linux doubles them
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= 32 * 1024);
+    }
+}
@@ -0,0 +1,378 @@
+//! DNS SRV lookups, for clients following a service's instances as they come and go.
+//!
+//! Only what [`ActorRef::connect`](crate::ActorRef::connect) needs: one SRV question, asked over
+//! UDP and again over TCP when the answer is truncated, of the name servers in
+//! `/etc/resolv.conf` or a given one (e.g. a Consul agent's DNS interface).
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+const SRV: u16 = 33;
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One instance of a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SrvRecord {
+    pub(crate) priority: u16,
+    pub(crate) weight: u16,
+    pub(crate) port: u16,
+    pub(crate) target: String,
+    pub(crate) ttl: u32,
+}
+
+/// A service name and where to ask for it, parsed from `srv://[dns-server/]name` (plain HTTP to
+/// the instances) or `srvs://[dns-server/]name` (HTTPS).
+#[derive(Debug)]
+pub(crate) struct Service {
+    pub(crate) name: String,
+    scheme: &'static str,
+    server: Option<SocketAddr>,
+    records: RwLock<Vec<SrvRecord>>,
+}
+
+impl Service {
+    /// The service named by `url`, or `None` if it isn't an SRV URL.
+    pub(crate) fn parse(url: &str) -> Option<Result<Self, String>> {
+        let (scheme, rest) = match url.split_once("://")? {
+            ("srv", rest) => ("http", rest),
+            ("srvs", rest) => ("https", rest),
+            _ => return None,
+        };
+        let rest = rest.trim_end_matches('/');
+        let (server, name) = match rest.split_once('/') {
+            Some((server, name)) => match server.parse::<SocketAddr>() {
+                Ok(server) => (Some(server), name),
+                Err(_) => return Some(Err(format!("Bad DNS server address in {}", url))),
+            },
+            None => (None, rest),
+        };
+        if name.is_empty() {
+            return Some(Err(format!("No service name in {}", url)));
+        }
+        Some(Ok(Self {
+            name: name.to_string(),
+            scheme,
+            server,
+            records: RwLock::default(),
+        }))
+    }
+
+    /// Look the service up again, keeping the instances already known if that fails.  Returns
+    /// when to look it up next.
+    pub(crate) async fn refresh(&self) -> Result<Duration, String> {
+        let servers = match self.server {
+            Some(server) => vec![server],
+            None => name_servers().await,
+        };
+        let records = lookup(&servers, &self.name)
+            .await
+            .map_err(|e| format!("Failed to look up {}: {}", self.name, e))?;
+        if records.is_empty() {
+            return Err(format!("{} has no instances", self.name));
+        }
+        let ttl = records.iter().map(|r| r.ttl).min().unwrap_or_default();
+        *self.records.write().unwrap() = records;
+        Ok(Duration::from_secs(u64::from(ttl).clamp(1, 300)))
+    }
+
+    /// The base URL of an instance for the next call, chosen as RFC 2782 says: among the
+    /// instances of the lowest priority, at random in proportion to their weights.
+    pub(crate) fn pick(&self) -> Option<String> {
+        let records = self.records.read().unwrap();
+        let priority = records.iter().map(|r| r.priority).min()?;
+        let candidates: Vec<&SrvRecord> =
+            records.iter().filter(|r| r.priority == priority).collect();
+        let total: u64 = candidates.iter().map(|r| u64::from(r.weight) + 1).sum();
+        let mut roll = random() % total;
+        let chosen = candidates.iter().find(|r| {
+            let share = u64::from(r.weight) + 1;
+            if roll < share {
+                return true;
+            }
+            roll -= share;
+            false
+        })?;
+        Some(format!(
+            "{}://{}:{}",
+            self.scheme,
+            chosen.target.trim_end_matches('.'),
+            chosen.port
+        ))
+    }
+}
+
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// The name servers of `/etc/resolv.conf`, or the local one if there are none.
+async fn name_servers() -> Vec<SocketAddr> {
+    let conf = tokio::fs::read_to_string("/etc/resolv.conf")
+        .await
+        .unwrap_or_default();
+    let servers: Vec<SocketAddr> = conf
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| address.trim().parse().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect();
+    if servers.is_empty() {
+        vec![SocketAddr::from(([127, 0, 0, 1], 53))]
+    } else {
+        servers
+    }
+}
+
+/// Ask `servers` in turn for the SRV records of `name`.
+async fn lookup(servers: &[SocketAddr], name: &str) -> io::Result<Vec<SrvRecord>> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "No name servers");
+    for &server in servers {
+        match tokio::time::timeout(TIMEOUT, ask(server, name)).await {
+            Ok(Ok(records)) => return Ok(records),
+            Ok(Err(e)) => last_error = e,
+            Err(_) => last_error = io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out"),
+        }
+    }
+    Err(last_error)
+}
+
+async fn ask(server: SocketAddr, name: &str) -> io::Result<Vec<SrvRecord>> {
+    let id = random() as u16;
+    let query = query(id, name)?;
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(&query).await?;
+    let mut buffer = vec![0; 4096];
+    let len = socket.recv(&mut buffer).await?;
+    match parse(id, &buffer[..len]) {
+        Err(Answer::Truncated) => {
+            // Too many instances for a datagram: ask again over TCP
+            let mut stream = TcpStream::connect(server).await?;
+            stream.write_u16(query.len() as u16).await?;
+            stream.write_all(&query).await?;
+            let len = stream.read_u16().await?;
+            let mut buffer = vec![0; usize::from(len)];
+            stream.read_exact(&mut buffer).await?;
+            parse(id, &buffer).map_err(Answer::into_io)
+        }
+        result => result.map_err(Answer::into_io),
+    }
+}
+
+/// Why a DNS response has no records to offer.
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+    Truncated,
+    Malformed,
+    Failed(u8),
+}
+
+impl Answer {
+    fn into_io(self) -> io::Error {
+        let message = match self {
+            Answer::Truncated => "Truncated DNS response".to_string(),
+            Answer::Malformed => "Malformed DNS response".to_string(),
+            Answer::Failed(rcode) => format!("DNS error code {}", rcode),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+}
+
+/// A recursive query for the SRV records of `name`.
+fn query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(18 + name.len());
+    message.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question
+    message.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Bad DNS name {}", name),
+            ));
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    message.extend_from_slice(&SRV.to_be_bytes());
+    message.extend_from_slice(&1u16.to_be_bytes());
+    Ok(message)
+}
+
+/// The SRV records in the response to query `id`.  A name that doesn't exist has none.
+fn parse(id: u16, message: &[u8]) -> Result<Vec<SrvRecord>, Answer> {
+    let u16_at = |pos: usize| -> Result<u16, Answer> {
+        let bytes = message.get(pos..pos + 2).ok_or(Answer::Malformed)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    if message.len() < 12 || u16_at(0)? != id || message[2] & 0x80 == 0 {
+        return Err(Answer::Malformed);
+    }
+    if message[2] & 0x02 != 0 {
+        return Err(Answer::Truncated);
+    }
+    match message[3] & 0x0f {
+        0 => {}
+        3 => return Ok(Vec::new()),
+        rcode => return Err(Answer::Failed(rcode)),
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(message, pos)?.1 + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        pos = read_name(message, pos)?.1;
+        let kind = u16_at(pos)?;
+        let ttl = (u32::from(u16_at(pos + 4)?) << 16) | u32::from(u16_at(pos + 6)?);
+        let len = usize::from(u16_at(pos + 8)?);
+        let data = pos + 10;
+        if kind == SRV {
+            records.push(SrvRecord {
+                priority: u16_at(data)?,
+                weight: u16_at(data + 2)?,
+                port: u16_at(data + 4)?,
+                target: read_name(message, data + 6)?.0,
+                ttl,
+            });
+        }
+        pos = data + len;
+    }
+    Ok(records)
+}
+
+/// The name at `pos`, following compression pointers, and the position just after it.
+fn read_name(message: &[u8], mut pos: usize) -> Result<(String, usize), Answer> {
+    let mut name = String::new();
+    let mut end = None;
+    // Bounds the pointers followed, so a looping message can't hang the client
+    for _ in 0..128 {
+        let len = *message.get(pos).ok_or(Answer::Malformed)?;
+        match len {
+            0 => return Ok((name, end.unwrap_or(pos + 1))),
+            len if len & 0xc0 == 0xc0 => {
+                let low = *message.get(pos + 1).ok_or(Answer::Malformed)?;
+                end.get_or_insert(pos + 2);
+                pos = (usize::from(len & 0x3f) << 8) | usize::from(low);
+            }
+            len => {
+                let label = message
+                    .get(pos + 1..pos + 1 + usize::from(len))
+                    .ok_or(Answer::Malformed)?;
+                name.push_str(&String::from_utf8_lossy(label));
+                name.push('.');
+                pos += 1 + usize::from(len);
+            }
+        }
+    }
+    Err(Answer::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A response to `query` carrying `records`, as a name server would send it.
+    fn respond(query: &[u8], records: &[(u16, u16, u16, &str)], ttl: u32) -> Vec<u8> {
+        let mut message = query.to_vec();
+        message[2] = 0x81;
+        message[3] = 0x80;
+        message[6..8].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        for &(priority, weight, port, target) in records {
+            // The question's name, compressed
+            message.extend_from_slice(&[0xc0, 12]);
+            message.extend_from_slice(&SRV.to_be_bytes());
+            message.extend_from_slice(&1u16.to_be_bytes());
+            message.extend_from_slice(&ttl.to_be_bytes());
+            let mut data = Vec::new();
+            for value in [priority, weight, port] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            for label in target.split('.').filter(|label| !label.is_empty()) {
+                data.push(label.len() as u8);
+                data.extend_from_slice(label.as_bytes());
+            }
+            data.push(0);
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(&data);
+        }
+        message
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let query = query(7, "_calc._tcp.service.consul").unwrap();
+        let response = respond(
+            &query,
+            &[(1, 10, 8080, "a.node"), (2, 0, 9090, "b.node")],
+            30,
+        );
+        let records = parse(7, &response).unwrap();
+        assert_eq!(
+            records[0],
+            SrvRecord {
+                priority: 1,
+                weight: 10,
+                port: 8080,
+                target: "a.node.".to_string(),
+                ttl: 30,
+            }
+        );
+        assert_eq!(records[1].target, "b.node.");
+        assert_eq!(parse(8, &response), Err(Answer::Malformed));
+
+        let mut truncated = response.clone();
+        truncated[2] |= 0x02;
+        assert_eq!(parse(7, &truncated), Err(Answer::Truncated));
+    }
+
+    #[test]
+    fn test_service_urls() {
+        let service = Service::parse("srv://127.0.0.1:8600/_calc._tcp.service.consul")
+            .unwrap()
+            .unwrap();
+        assert_eq!(service.name, "_calc._tcp.service.consul");
+        assert_eq!(
+            service.server,
+            Some(SocketAddr::from(([127, 0, 0, 1], 8600)))
+        );
+        assert!(Service::parse("http://calc:8080").is_none());
+        assert!(Service::parse("srv://not-an-address/_calc._tcp")
+            .unwrap()
+            .is_err());
+
+        // Only the lowest priority is picked while it has instances
+        *service.records.write().unwrap() = vec![
+            SrvRecord {
+                priority: 1,
+                weight: 5,
+                port: 8080,
+                target: "a.node.".to_string(),
+                ttl: 30,
+            },
+            SrvRecord {
+                priority: 2,
+                weight: 5,
+                port: 9090,
+                target: "b.node.".to_string(),
+                ttl: 30,
+            },
+        ];
+        for _ in 0..10 {
+            assert_eq!(service.pick().as_deref(), Some("http://a.node:8080"));
+        }
+    }
+}
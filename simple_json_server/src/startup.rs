@@ -0,0 +1,63 @@
+//! A machine-readable record of what a server came up with.
+//!
+//! Once a server is listening it logs where, and calls the hook given to
+//! [`ServerOptions::on_startup`](crate::ServerOptions::on_startup) with a [`StartupEvent`]: the
+//! addresses it bound, the transports it serves, whether it uses TLS and the actor's methods.
+//! Orchestration tooling can check these against what it expects before sending traffic.
+//! [`print_json`] writes the event to standard output as one line of JSON:
+//!
+//! ```rust
+//! use simple_json_server::{startup, ServerOptions};
+//!
+//! let options = ServerOptions::new(8080).on_startup(startup::print_json);
+//! ```
+//!
+//! ```json
+//! {"event":"started","version":"1.0.2","addresses":["http://0.0.0.0:8080"],"transports":["http"],"tls":false,"methods":["add","divide"]}
+//! ```
+
+use serde::Serialize;
+use std::io::Write;
+
+/// What a server is serving, once it is listening.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StartupEvent {
+    /// Always `"started"`, so the event can be picked out of other output.
+    pub event: &'static str,
+    /// The version of this crate.
+    pub version: &'static str,
+    /// The URLs the server accepts connections on.
+    pub addresses: Vec<String>,
+    /// The protocols served: `http`, `https`, `ws` or `wss`, and `http3`.
+    pub transports: Vec<String>,
+    /// Whether connections use TLS.
+    pub tls: bool,
+    /// The actor's methods, by name.
+    pub methods: Vec<String>,
+}
+
+impl StartupEvent {
+    pub(crate) fn new(
+        addresses: Vec<String>,
+        transports: Vec<String>,
+        tls: bool,
+        methods: Vec<String>,
+    ) -> Self {
+        Self {
+            event: "started",
+            version: env!("CARGO_PKG_VERSION"),
+            addresses,
+            transports,
+            tls,
+            methods,
+        }
+    }
+}
+
+/// Write `event` to standard output as one line of JSON.
+pub fn print_json(event: &StartupEvent) {
+    let line = serde_json::to_string(event).unwrap_or_default();
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
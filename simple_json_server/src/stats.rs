@@ -0,0 +1,491 @@
+//! Per-method call counts, error counts and latency histograms.
+//!
+//! Every server records how long each call takes and whether it failed (a non-success status,
+//! or a method returning `Err`).  Latencies go into HDR-style histograms: buckets are
+//! exponentially sized with 8 linear sub-buckets each, so percentiles are accurate to within
+//! about 12% at any scale, from microseconds to hours, in constant memory.
+//!
+//! Pass a [`Stats`] to [`ServerOptions::stats`](crate::ServerOptions::stats) and keep a clone
+//! to read the numbers from Rust:
+//!
+//! ```rust
+//! use simple_json_server::stats::Stats;
+//! use simple_json_server::ServerOptions;
+//!
+//! let stats = Stats::new();
+//! let options = ServerOptions::new(8080).stats(stats.clone());
+//!
+//! // Later
+//! for (method, s) in stats.methods() {
+//!     println!("{}: {} calls, p99 {}us", method, s.calls, s.p99_us);
+//! }
+//! ```
+//!
+//! With an admin token configured, `GET /__admin/stats` returns the same numbers as JSON and
+//! `GET /__admin/metrics` in the Prometheus text format.
+//!
+//! Each method gets its own series in the metrics, so an actor with many methods, or methods
+//! that are rarely called, can add a lot of them.  [`Stats::exclude`] leaves methods out of the
+//! metrics and [`Stats::allow_only`] keeps only the ones listed; a pattern may contain one `*`,
+//! e.g. `admin_*`.  The metrics' names and labels can be changed too:
+//!
+//! ```rust
+//! use simple_json_server::stats::Stats;
+//!
+//! let stats = Stats::new()
+//!     .metric_prefix("billing")
+//!     .method_label("rpc")
+//!     .label("service", "invoices")
+//!     .exclude(["debug_*", "report_for_customer"]);
+//! // billing_calls_total{rpc="add",service="invoices"} 3
+//! ```
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const SUB_BUCKETS: usize = 8;
+const BUCKETS: usize = 62 * SUB_BUCKETS;
+
+/// Latency histogram and counters of one method.
+struct MethodHistogram {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+    buckets: Box<[AtomicU64]>,
+}
+
+impl MethodHistogram {
+    fn new() -> Self {
+        Self {
+            calls: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            total_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn record(&self, latency: Duration, failed: bool) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        self.buckets[bucket_index(us)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The smallest bucket bound at or below which a `quantile` of the calls fall.
+    fn percentile(&self, counts: &[u64], total: u64, quantile: f64) -> u64 {
+        let rank = ((total as f64 * quantile).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper(index).min(self.max_us.load(Ordering::Relaxed));
+            }
+        }
+        self.max_us.load(Ordering::Relaxed)
+    }
+
+    fn summary(&self) -> MethodStats {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        let calls = self.calls.load(Ordering::Relaxed);
+        if total == 0 {
+            return MethodStats::default();
+        }
+        MethodStats {
+            calls,
+            errors: self.errors.load(Ordering::Relaxed),
+            total_us: self.total_us.load(Ordering::Relaxed),
+            mean_us: self.total_us.load(Ordering::Relaxed) / calls.max(1),
+            p50_us: self.percentile(&counts, total, 0.50),
+            p90_us: self.percentile(&counts, total, 0.90),
+            p99_us: self.percentile(&counts, total, 0.99),
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Values below `SUB_BUCKETS` get a bucket each; above that, each power of two is split into
+/// `SUB_BUCKETS` equal parts.
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros() as usize;
+    let sub_bucket = (value >> (exponent - 3)) as usize & (SUB_BUCKETS - 1);
+    (exponent - 2) * SUB_BUCKETS + sub_bucket
+}
+
+/// The largest value that falls into bucket `index`.
+fn bucket_upper(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let exponent = index / SUB_BUCKETS + 2;
+    let sub_bucket = (index % SUB_BUCKETS) as u128;
+    let upper = ((SUB_BUCKETS as u128 + sub_bucket + 1) << (exponent - 3)) - 1;
+    upper.min(u64::MAX as u128) as u64
+}
+
+/// A summary of the calls to one method.  Latencies are in microseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MethodStats {
+    /// Calls completed, successful or not.
+    pub calls: u64,
+    /// Calls that failed.
+    pub errors: u64,
+    /// Sum of all latencies.
+    pub total_us: u64,
+    /// Mean latency.
+    pub mean_us: u64,
+    /// Median latency.
+    pub p50_us: u64,
+    /// 90th percentile latency.
+    pub p90_us: u64,
+    /// 99th percentile latency.
+    pub p99_us: u64,
+    /// Slowest call.
+    pub max_us: u64,
+}
+
+impl MethodStats {
+    /// The fraction of calls that failed, between 0 and 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+}
+
+/// Call statistics of a server, by method.  Clones share the same statistics.
+#[derive(Clone, Default)]
+pub struct Stats {
+    methods: Arc<RwLock<HashMap<String, Arc<MethodHistogram>>>>,
+    metrics: Metrics,
+}
+
+/// How statistics are named and which methods are included in Prometheus metrics.
+#[derive(Debug, Clone)]
+struct Metrics {
+    prefix: String,
+    method_label: String,
+    labels: Vec<(String, String)>,
+    excluded: Vec<String>,
+    allowed: Option<Vec<String>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            prefix: "simple_json_server".to_string(),
+            method_label: "method".to_string(),
+            labels: Vec::new(),
+            excluded: Vec::new(),
+            allowed: None,
+        }
+    }
+}
+
+impl Metrics {
+    fn includes(&self, method: &str) -> bool {
+        let matching = |patterns: &[String]| patterns.iter().any(|p| matches(p, method));
+        !matching(&self.excluded) && self.allowed.as_deref().is_none_or(matching)
+    }
+}
+
+/// Whether `method` matches `pattern`, which may contain one `*` standing for any text.
+fn matches(pattern: &str, method: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == method,
+        Some((prefix, suffix)) => {
+            method.len() >= prefix.len() + suffix.len()
+                && method.starts_with(prefix)
+                && method.ends_with(suffix)
+        }
+    }
+}
+
+impl fmt::Debug for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let methods = self.methods.read().unwrap();
+        f.debug_struct("Stats")
+            .field("methods", &methods.keys().collect::<Vec<_>>())
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl Stats {
+    /// Empty statistics, to be filled in by a server.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name the metrics `<prefix>_calls_total` and so on, instead of
+    /// `simple_json_server_calls_total`.
+    pub fn metric_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.metrics.prefix = prefix.into();
+        self
+    }
+
+    /// Put the method's name in the label `name`, instead of `method`.
+    pub fn method_label(mut self, name: impl Into<String>) -> Self {
+        self.metrics.method_label = name.into();
+        self
+    }
+
+    /// Add the label `name` with `value` to every metric.
+    pub fn label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metrics.labels.push((name.into(), value.into()));
+        self
+    }
+
+    /// Leave the methods matching `patterns` out of the metrics.  They are still counted, and
+    /// reported by [`Stats::methods`].
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metrics
+            .excluded
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only include the methods matching `patterns` in the metrics.
+    pub fn allow_only<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metrics
+            .allowed
+            .get_or_insert_with(Vec::new)
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// The statistics of `method`, if it has been called.
+    pub fn method(&self, method: &str) -> Option<MethodStats> {
+        let methods = self.methods.read().unwrap();
+        methods.get(method).map(|histogram| histogram.summary())
+    }
+
+    /// The statistics of every method that has been called, ordered by name.
+    pub fn methods(&self) -> BTreeMap<String, MethodStats> {
+        let methods = self.methods.read().unwrap();
+        methods
+            .iter()
+            .map(|(name, histogram)| (name.clone(), histogram.summary()))
+            .collect()
+    }
+
+    /// Record a call to `method` that took `latency`.
+    pub(crate) fn record(&self, method: &str, latency: Duration, failed: bool) {
+        let existing = self.methods.read().unwrap().get(method).cloned();
+        let histogram = match existing {
+            Some(histogram) => histogram,
+            None => Arc::clone(
+                self.methods
+                    .write()
+                    .unwrap()
+                    .entry(method.to_string())
+                    .or_insert_with(|| Arc::new(MethodHistogram::new())),
+            ),
+        };
+        histogram.record(latency, failed);
+    }
+
+    /// The statistics in the Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        self.prometheus_labeled(&[])
+    }
+
+    /// Like [`Stats::prometheus`], with `labels` added to every series, e.g. the pod's metadata
+    /// from [`Kubernetes::labels`](crate::k8s::Kubernetes::labels).
+    pub fn prometheus_labeled(&self, labels: &[(String, String)]) -> String {
+        let Metrics {
+            prefix,
+            method_label,
+            ..
+        } = &self.metrics;
+        let mut methods = self.methods();
+        methods.retain(|method, _| self.metrics.includes(method));
+        let extra: String = self
+            .metrics
+            .labels
+            .iter()
+            .chain(labels)
+            .map(|(name, value)| format!(",{}=\"{}\"", name, escape_label(value)))
+            .collect();
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE {prefix}_calls_total counter");
+        for (method, s) in &methods {
+            let _ = writeln!(
+                out,
+                "{prefix}_calls_total{{{method_label}=\"{}\"{}}} {}",
+                method, extra, s.calls
+            );
+        }
+        let _ = writeln!(out, "# TYPE {prefix}_errors_total counter");
+        for (method, s) in &methods {
+            let _ = writeln!(
+                out,
+                "{prefix}_errors_total{{{method_label}=\"{}\"{}}} {}",
+                method, extra, s.errors
+            );
+        }
+        let _ = writeln!(out, "# TYPE {prefix}_latency_seconds summary");
+        for (method, s) in &methods {
+            for (quantile, us) in [("0.5", s.p50_us), ("0.9", s.p90_us), ("0.99", s.p99_us)] {
+                let _ = writeln!(
+                    out,
+                    "{prefix}_latency_seconds{{{method_label}=\"{}\"{},quantile=\"{}\"}} {}",
+                    method,
+                    extra,
+                    quantile,
+                    us as f64 / 1e6
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{prefix}_latency_seconds_sum{{{method_label}=\"{}\"{}}} {}",
+                method,
+                extra,
+                s.total_us as f64 / 1e6
+            );
+            let _ = writeln!(
+                out,
+                "{prefix}_latency_seconds_count{{{method_label}=\"{}\"{}}} {}",
+                method, extra, s.calls
+            );
+        }
+        out
+    }
+
+    /// A gauge `name`, named with the metric prefix, with one sample per `(value of label,
+    /// sample)` pair and the extra `labels` on each.
+    pub(crate) fn gauge(
+        &self,
+        name: &str,
+        label: &str,
+        samples: &[(String, u64)],
+        labels: &[(String, String)],
+    ) -> String {
+        let prefix = &self.metrics.prefix;
+        let extra: String = self
+            .metrics
+            .labels
+            .iter()
+            .chain(labels)
+            .map(|(name, value)| format!(",{}=\"{}\"", name, escape_label(value)))
+            .collect();
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE {prefix}_{name} gauge");
+        for (value, sample) in samples {
+            let _ = writeln!(
+                out,
+                "{prefix}_{name}{{{label}=\"{}\"{}}} {}",
+                escape_label(value),
+                extra,
+                sample
+            );
+        }
+        out
+    }
+}
+
+/// Escape a label value for the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_cover_their_values() {
+        for value in [0, 1, 7, 8, 9, 15, 16, 17, 1000, 123_456, u64::MAX / 3] {
+            let index = bucket_index(value);
+            assert!(bucket_upper(index) >= value);
+            assert!(index == 0 || bucket_upper(index - 1) < value);
+        }
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_percentiles_and_errors() {
+        let stats = Stats::new();
+        for ms in 1..=100 {
+            stats.record("add", Duration::from_millis(ms), ms > 95);
+        }
+        let add = stats.method("add").unwrap();
+        assert_eq!(add.calls, 100);
+        assert_eq!(add.errors, 5);
+        assert_eq!(add.max_us, 100_000);
+        // Within the histogram's precision
+        assert!((50_000..=57_000).contains(&add.p50_us));
+        assert!((99_000..=100_000).contains(&add.p99_us));
+        assert!((add.error_rate() - 0.05).abs() < 1e-9);
+        assert!(stats.method("other").is_none());
+    }
+
+    #[test]
+    fn test_prometheus_format() {
+        let stats = Stats::new();
+        stats.record("add", Duration::from_millis(2), false);
+        let text = stats.prometheus();
+        assert!(text.contains("simple_json_server_calls_total{method=\"add\"} 1\n"));
+        assert!(text.contains("simple_json_server_latency_seconds_count{method=\"add\"} 1\n"));
+    }
+
+    #[test]
+    fn test_metrics_naming_and_filtering() {
+        let stats = Stats::new()
+            .metric_prefix("billing")
+            .method_label("rpc")
+            .label("service", "invoices")
+            .exclude(["debug_*"]);
+        for method in ["add", "debug_dump", "report"] {
+            stats.record(method, Duration::from_millis(1), false);
+        }
+        let text = stats.prometheus();
+        assert!(text.contains("billing_calls_total{rpc=\"add\",service=\"invoices\"} 1\n"));
+        assert!(text.contains("billing_calls_total{rpc=\"report\""));
+        assert!(!text.contains("debug_dump"));
+        assert!(stats.method("debug_dump").is_some());
+
+        let text = stats.allow_only(["add"]).prometheus();
+        assert!(text.contains("rpc=\"add\""));
+        assert!(!text.contains("rpc=\"report\""));
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert!(matches("debug_*", "debug_dump"));
+        assert!(matches("*_v2", "add_v2"));
+        assert!(!matches("a*a", "a"));
+        assert!(!matches("add", "add_v2"));
+    }
+}
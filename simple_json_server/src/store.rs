@@ -0,0 +1,465 @@
+//! A small persistent key-value store for actors, backed by SQLite (feature `store`).
+//!
+//! Values are any serializable type, stored as JSON documents under string keys.  Keys are
+//! grouped into namespaces so several actors can share one database file without colliding;
+//! a store opened with [`ActorStore::open`] uses the `default` namespace, and
+//! [`ActorStore::namespace`] returns a view of another one.
+//!
+//! With [`ActorStore::encrypted`], values are encrypted before they are written; keys, which
+//! [`ActorStore::scan_prefix`] searches, are not.  See [`crate::encryption`].
+//!
+//! [`ActorStore::transaction`] makes several changes at once, all or none of them, and can
+//! emit events that are delivered once the changes are committed; see [`crate::outbox`].
+//!
+//! Calls are synchronous and hold a lock on the database connection while they run, which is
+//! fine for the modest workloads this store is aimed at.  Actors that need more should bring
+//! their own database layer.
+//!
+//! ```rust
+//! # #[cfg(feature = "store")]
+//! # {
+//! use simple_json_server::store::ActorStore;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! let store = ActorStore::in_memory().unwrap().namespace("users");
+//! store.put("user/1", &User { name: "Ada".into() }).unwrap();
+//! store.put("user/2", &User { name: "Grace".into() }).unwrap();
+//!
+//! let ada: Option<User> = store.get("user/1").unwrap();
+//! assert_eq!(ada.unwrap().name, "Ada");
+//! let users: Vec<(String, User)> = store.scan_prefix("user/").unwrap();
+//! assert_eq!(users.len(), 2);
+//! # }
+//! ```
+
+use crate::encryption::Encryption;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Errors returned by an [`ActorStore`].
+#[derive(Debug)]
+pub enum StoreError {
+    /// The database could not be opened, read or written.
+    Database(String),
+    /// A value could not be serialized, or a stored value could not be deserialized.
+    Serialization(String),
+    /// A stored value could not be decrypted.
+    Encryption(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Database(e) => write!(f, "Database error: {}", e),
+            StoreError::Serialization(e) => write!(f, "Serialization error: {}", e),
+            StoreError::Encryption(e) => write!(f, "Encryption error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Database(e.to_string())
+    }
+}
+
+/// A namespace of a SQLite-backed document store.  Cloning is cheap and clones share the
+/// database connection.
+#[derive(Clone)]
+pub struct ActorStore {
+    conn: Arc<Mutex<Connection>>,
+    namespace: String,
+    encryption: Option<Encryption>,
+    /// Woken when a transaction commits events to the outbox.
+    emitted: Arc<Notify>,
+}
+
+impl fmt::Debug for ActorStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActorStore")
+            .field("namespace", &self.namespace)
+            .field("encrypted", &self.encryption.is_some())
+            .finish()
+    }
+}
+
+impl ActorStore {
+    /// Open (or create) the database file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Open a database that lives only as long as the store; useful for tests.
+    pub fn in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS actor_store (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (namespace, key)
+            );
+            CREATE TABLE IF NOT EXISTS actor_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                topic TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT
+            )",
+        )?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            namespace: "default".to_string(),
+            encryption: None,
+            emitted: Arc::new(Notify::new()),
+        })
+    }
+
+    /// A view of the namespace `namespace` in the same database, encrypted like this one.
+    pub fn namespace(&self, namespace: impl Into<String>) -> Self {
+        Self {
+            conn: Arc::clone(&self.conn),
+            namespace: namespace.into(),
+            encryption: self.encryption.clone(),
+            emitted: Arc::clone(&self.emitted),
+        }
+    }
+
+    /// Encrypt values written through this view with `encryption`, and decrypt values read.
+    /// Values that were not encrypted, or not with one of its keys, fail to read.
+    pub fn encrypted(mut self, encryption: Encryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// The value stored under `key`, if any.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        self.get_in(&self.conn.lock().unwrap(), key)
+    }
+
+    /// Store `value` under `key`, replacing any earlier value.
+    pub fn put<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), StoreError> {
+        self.put_in(&self.conn.lock().unwrap(), key, value)
+    }
+
+    /// Remove `key`.  Returns false if it was not stored.
+    pub fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        self.delete_in(&self.conn.lock().unwrap(), key)
+    }
+
+    /// Run `f` in a transaction: the changes it makes through the [`Transaction`], and the
+    /// events it emits, are committed together if it returns `Ok`, and discarded if it returns
+    /// `Err`.  Other calls on the store wait until it is done.
+    pub fn transaction<R>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<R, StoreError>,
+    ) -> Result<R, StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut transaction = Transaction {
+            store: self,
+            tx: conn.transaction()?,
+            emitted: false,
+        };
+        let result = f(&mut transaction)?;
+        let emitted = transaction.emitted;
+        transaction.tx.commit()?;
+        if emitted {
+            self.emitted.notify_one();
+        }
+        Ok(result)
+    }
+
+    /// Every key starting with `prefix` and its value, ordered by key.
+    pub fn scan_prefix<T: DeserializeOwned>(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<(String, T)>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT key, value FROM actor_store
+             WHERE namespace = ?1 AND key >= ?2 AND substr(key, 1, length(?2)) = ?2
+             ORDER BY key",
+        )?;
+        let rows = statement.query_map(params![self.namespace, prefix], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| {
+            let (key, value) = row?;
+            let value = self.decode(&key, &value)?;
+            Ok((key, value))
+        })
+        .collect()
+    }
+}
+
+/// The changes being made by [`ActorStore::transaction`].
+pub struct Transaction<'a> {
+    store: &'a ActorStore,
+    tx: rusqlite::Transaction<'a>,
+    emitted: bool,
+}
+
+impl Transaction<'_> {
+    /// The value stored under `key`, including changes made earlier in the transaction.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        self.store.get_in(&self.tx, key)
+    }
+
+    /// Store `value` under `key` when the transaction commits.
+    pub fn put<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), StoreError> {
+        self.store.put_in(&self.tx, key, value)
+    }
+
+    /// Remove `key` when the transaction commits.  Returns false if it is not stored.
+    pub fn delete(&mut self, key: &str) -> Result<bool, StoreError> {
+        self.store.delete_in(&self.tx, key)
+    }
+
+    /// Emit an event on `topic` when the transaction commits, for an
+    /// [`Outbox`](crate::outbox::Outbox) to deliver.  Nothing is emitted if it doesn't commit.
+    pub fn emit<T: Serialize + ?Sized>(
+        &mut self,
+        topic: &str,
+        payload: &T,
+    ) -> Result<(), StoreError> {
+        let payload =
+            serde_json::to_string(payload).map_err(|e| StoreError::Serialization(e.to_string()))?;
+        let payload = match &self.store.encryption {
+            Some(encryption) => encryption.encrypt(payload.as_bytes(), &outbox_context(topic)),
+            None => payload,
+        };
+        self.tx.execute(
+            "INSERT INTO actor_outbox (topic, payload) VALUES (?1, ?2)",
+            params![topic, payload],
+        )?;
+        self.emitted = true;
+        Ok(())
+    }
+}
+
+impl ActorStore {
+    fn get_in<T: DeserializeOwned>(
+        &self,
+        conn: &Connection,
+        key: &str,
+    ) -> Result<Option<T>, StoreError> {
+        let value: Option<String> = conn
+            .query_row(
+                "SELECT value FROM actor_store WHERE namespace = ?1 AND key = ?2",
+                params![self.namespace, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        value.map(|value| self.decode(key, &value)).transpose()
+    }
+
+    fn put_in<T: Serialize + ?Sized>(
+        &self,
+        conn: &Connection,
+        key: &str,
+        value: &T,
+    ) -> Result<(), StoreError> {
+        let value =
+            serde_json::to_string(value).map_err(|e| StoreError::Serialization(e.to_string()))?;
+        let value = match &self.encryption {
+            Some(encryption) => encryption.encrypt(value.as_bytes(), &self.context(key)),
+            None => value,
+        };
+        conn.execute(
+            "INSERT OR REPLACE INTO actor_store (namespace, key, value) VALUES (?1, ?2, ?3)",
+            params![self.namespace, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn delete_in(&self, conn: &Connection, key: &str) -> Result<bool, StoreError> {
+        let deleted = conn.execute(
+            "DELETE FROM actor_store WHERE namespace = ?1 AND key = ?2",
+            params![self.namespace, key],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// The database connection, for the outbox relay.
+    pub(crate) fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    /// Woken when a transaction commits events.
+    pub(crate) fn emitted(&self) -> &Notify {
+        &self.emitted
+    }
+
+    /// Decode the payload of an outbox event on `topic`.
+    pub(crate) fn decode_event(&self, topic: &str, payload: &str) -> Result<String, StoreError> {
+        match &self.encryption {
+            Some(encryption) => encryption
+                .decrypt_string(payload, &outbox_context(topic))
+                .map_err(|e| StoreError::Encryption(e.to_string())),
+            None => Ok(payload.to_string()),
+        }
+    }
+
+    /// What an encrypted value is bound to, so it can't be moved to another key.
+    fn context(&self, key: &str) -> Vec<u8> {
+        format!("{}\0{}", self.namespace, key).into_bytes()
+    }
+
+    fn decode<T: DeserializeOwned>(&self, key: &str, value: &str) -> Result<T, StoreError> {
+        let decrypted;
+        let value = match &self.encryption {
+            Some(encryption) => {
+                decrypted = encryption
+                    .decrypt_string(value, &self.context(key))
+                    .map_err(|e| StoreError::Encryption(e.to_string()))?;
+                &decrypted
+            }
+            None => value,
+        };
+        serde_json::from_str(value).map_err(|e| StoreError::Serialization(e.to_string()))
+    }
+}
+
+/// What an encrypted outbox payload is bound to, so it can't be moved to another topic.
+fn outbox_context(topic: &str) -> Vec<u8> {
+    format!("\0outbox\0{}", topic).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_delete() {
+        let store = ActorStore::in_memory().unwrap();
+        assert_eq!(store.get::<i32>("a").unwrap(), None);
+
+        store.put("a", &1).unwrap();
+        store.put("a", &2).unwrap();
+        assert_eq!(store.get::<i32>("a").unwrap(), Some(2));
+
+        assert!(store.delete("a").unwrap());
+        assert!(!store.delete("a").unwrap());
+        assert_eq!(store.get::<i32>("a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_scan_prefix_and_namespaces() {
+        let store = ActorStore::in_memory().unwrap();
+        let other = store.namespace("other");
+        store.put("order/2", "b").unwrap();
+        store.put("order/1", "a").unwrap();
+        store.put("orders", "not a match").unwrap();
+        store.put("user/1", "c").unwrap();
+        other.put("order/3", "d").unwrap();
+
+        let orders: Vec<(String, String)> = store.scan_prefix("order/").unwrap();
+        assert_eq!(
+            orders,
+            vec![
+                ("order/1".to_string(), "a".to_string()),
+                ("order/2".to_string(), "b".to_string())
+            ]
+        );
+        assert_eq!(other.scan_prefix::<String>("").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_encrypted_values() {
+        let plain = ActorStore::in_memory().unwrap();
+        let store = plain.clone().encrypted(Encryption::new([3; 32]).unwrap());
+        store.put("card", "4111 1111 1111 1111").unwrap();
+        assert_eq!(
+            store.get::<String>("card").unwrap().as_deref(),
+            Some("4111 1111 1111 1111")
+        );
+        let raw: String = plain
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT value FROM actor_store", [], |row| row.get(0))
+            .unwrap();
+        assert!(!raw.contains("4111"));
+
+        // Values can't be read in the clear, or moved to another key
+        assert!(matches!(
+            plain.get::<String>("card"),
+            Err(StoreError::Serialization(_))
+        ));
+        plain
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO actor_store (namespace, key, value) VALUES ('default', 'moved', ?1)",
+                params![raw],
+            )
+            .unwrap();
+        assert!(matches!(
+            store.get::<String>("moved"),
+            Err(StoreError::Encryption(_))
+        ));
+        plain.put("clear", "text").unwrap();
+        assert!(matches!(
+            store.get::<String>("clear"),
+            Err(StoreError::Encryption(_))
+        ));
+        assert!(matches!(
+            store.scan_prefix::<String>(""),
+            Err(StoreError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn test_transactions_commit_or_roll_back() {
+        let store = ActorStore::in_memory().unwrap();
+        store
+            .transaction(|tx| {
+                tx.put("a", &1)?;
+                assert_eq!(tx.get::<i32>("a")?, Some(1));
+                tx.emit("changed", "a")
+            })
+            .unwrap();
+        let failed = store.transaction(|tx| {
+            tx.put("a", &2)?;
+            tx.delete("a")?;
+            tx.emit("changed", "a")?;
+            Err::<(), _>(StoreError::Database("abandoned".to_string()))
+        });
+        assert!(failed.is_err());
+        assert_eq!(store.get::<i32>("a").unwrap(), Some(1));
+        let events: i64 = store
+            .connection()
+            .query_row("SELECT COUNT(*) FROM actor_outbox", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(events, 1);
+    }
+
+    #[test]
+    fn test_values_persist_across_opens() {
+        let path = std::env::temp_dir().join(format!("actor_store_test_{}.db", std::process::id()));
+        ActorStore::open(&path)
+            .unwrap()
+            .put("k", &[1, 2, 3])
+            .unwrap();
+        let value: Vec<i32> = ActorStore::open(&path).unwrap().get("k").unwrap().unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
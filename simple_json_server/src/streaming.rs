@@ -0,0 +1,209 @@
+//! Responses serialized as they are sent, for methods marked `#[streaming]`.
+//!
+//! A response is normally serialized into a `String` before the first byte goes out, so an
+//! export-style method returning a million rows briefly holds them twice: once as values and
+//! once as JSON text.  The result of a streaming method is instead handed to the HTTP transport
+//! as a value and serialized on a blocking thread into chunks of [`CHUNK_SIZE`] bytes, of which
+//! only [`CHUNKS_AHEAD`] wait for the client at a time.  The serializer stops when the client goes
+//! away.
+//!
+//! Responses are streamed over HTTP/1 and HTTP/2 only.  WebSocket and HTTP/3 calls, journaled
+//! writes and requests asking for `?pretty=1` or `?debug=1` get the whole body as usual.  A
+//! streamed response is sent before it is known whether serialization succeeds, so a failure
+//! part way through aborts the response rather than turning it into an error status, and its
+//! bytes are not counted towards the caller's quota.
+
+use crate::RequestContext;
+use http_body_util::Full;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use hyper::Response;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// How many bytes are sent to the client at a time.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many serialized chunks may wait for a slow client before the serializer waits too.
+pub(crate) const CHUNKS_AHEAD: usize = 2;
+
+/// A result that serializes itself as JSON.
+trait WriteJson: Send {
+    fn write_json(&self, out: &mut dyn Write) -> serde_json::Result<()>;
+}
+
+impl<T: Serialize + Send> WriteJson for T {
+    fn write_json(&self, out: &mut dyn Write) -> serde_json::Result<()> {
+        serde_json::to_writer(out, self)
+    }
+}
+
+/// A streaming method's result, waiting to be serialized into the response.  Carried from the
+/// call to the transport in the [`RequestContext`] and then in the response's extensions.
+#[derive(Clone)]
+pub(crate) struct Streamed(Arc<Mutex<Option<Box<dyn WriteJson>>>>);
+
+impl Streamed {
+    fn take(&self) -> Option<Box<dyn WriteJson>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl std::fmt::Debug for Streamed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Streamed")
+    }
+}
+
+/// The JSON response for a streaming method's `result`: empty if the transport will serialize it
+/// as it is sent, otherwise serialized as for any other method.
+pub fn stream_result<R>(method: &str, result: R) -> String
+where
+    R: Serialize + Send + 'static,
+{
+    if let Some(ctx) = RequestContext::current().filter(RequestContext::streams) {
+        ctx.set_stream(Streamed(Arc::new(Mutex::new(Some(Box::new(result))))));
+        return String::new();
+    }
+    match serde_json::to_string(&result) {
+        Ok(json_result) => json_result,
+        Err(e) => {
+            serde_json::to_string(&format!("Failed to serialize result for {}: {}", method, e))
+                .unwrap_or_else(|_| "\"Serialization error\"".to_string())
+        }
+    }
+}
+
+/// An HTTP response body, either complete or serialized as it is sent.
+pub(crate) enum Body {
+    Full(Full<Bytes>),
+    Streamed(mpsc::Receiver<io::Result<Bytes>>),
+}
+
+impl HttpBody for Body {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        match self.get_mut() {
+            Body::Full(full) => Pin::new(full)
+                .poll_frame(cx)
+                .map_err(|never| match never {}),
+            Body::Streamed(chunks) => chunks
+                .poll_recv(cx)
+                .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Body::Full(full) => full.is_end_stream(),
+            Body::Streamed(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Body::Full(full) => full.size_hint(),
+            Body::Streamed(_) => SizeHint::default(),
+        }
+    }
+}
+
+/// Give `response` its body, starting to serialize a streaming method's result if it carries
+/// one.
+pub(crate) fn into_body(response: Response<Full<Bytes>>) -> Response<Body> {
+    let (mut parts, body) = response.into_parts();
+    let Some(result) = parts.extensions.remove::<Streamed>().and_then(|s| s.take()) else {
+        return Response::from_parts(parts, Body::Full(body));
+    };
+    let (sender, chunks) = mpsc::channel(CHUNKS_AHEAD);
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            sender,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let written = result
+            .write_json(&mut writer)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.flush());
+        if let Err(e) = written {
+            // Nobody is listening if the client has gone away
+            log::debug!("Stopped streaming a response: {}", e);
+            let _ = writer.sender.blocking_send(Err(e));
+        }
+    });
+    Response::from_parts(parts, Body::Streamed(chunks))
+}
+
+/// Collects serialized JSON into chunks and sends them to the response body.
+struct ChunkWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+    chunk: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the client went away"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn streamed(rows: usize) -> Response<Body> {
+        let rows: Vec<String> = (0..rows).map(|i| format!("row {i}")).collect();
+        let result: Box<dyn WriteJson> = Box::new(rows);
+        let response = Response::builder()
+            .extension(Streamed(Arc::new(Mutex::new(Some(result)))))
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        into_body(response)
+    }
+
+    #[tokio::test]
+    async fn test_results_are_sent_in_chunks() {
+        let mut body = streamed(50_000).into_body();
+        let mut json = Vec::new();
+        let mut chunks = 0;
+        while let Some(frame) = body.frame().await {
+            let chunk = frame.unwrap().into_data().unwrap();
+            assert!(chunk.len() < 2 * CHUNK_SIZE);
+            json.extend_from_slice(&chunk);
+            chunks += 1;
+        }
+        assert!(chunks > 5);
+        let rows: Vec<String> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(rows.len(), 50_000);
+        assert_eq!(rows[49_999], "row 49999");
+    }
+
+    #[tokio::test]
+    async fn test_complete_bodies_keep_their_length() {
+        let body = into_body(Response::new(Full::new(Bytes::from("[1,2]")))).into_body();
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "[1,2]");
+    }
+}
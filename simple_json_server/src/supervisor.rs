@@ -0,0 +1,437 @@
+//! Restarting failed actors, in trees of supervisors.
+//!
+//! A [`Supervisor`] starts the servers of several actors and restarts them with fresh actors
+//! when they fail, so one bad call or a lost connection to a database doesn't take a process's
+//! actors down for good.  A server fails when its actor fails to initialize, when it can't
+//! listen, or when a method panics: supervised servers always stop on panics (see
+//! [`ServerOptions::stop_on_panic`]).  A server that is drained has stopped on purpose and is not
+//! restarted.
+//!
+//! What happens to the other children when one fails depends on the [`Strategy`]: with
+//! [`Strategy::OneForOne`] only the failed child is restarted, while with
+//! [`Strategy::AllForOne`] the others are drained and all are restarted together, for actors
+//! that depend on each other.  Supervisors can themselves be children of supervisors:
+//!
+//! ```rust,no_run
+//! use simple_json_server::supervisor::{Strategy, Supervisor};
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//! use std::time::Duration;
+//!
+//! #[derive(Default)]
+//! struct Orders;
+//!
+//! #[actor]
+//! impl Orders {
+//!     pub async fn place(&self, sku: String) -> u64 {
+//!         1
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! struct Inventory;
+//!
+//! #[actor]
+//! impl Inventory {
+//!     pub async fn count(&self, sku: String) -> u32 {
+//!         3
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let stock = Supervisor::new(Strategy::AllForOne)
+//!         .child("inventory", ServerOptions::new(8081), Inventory::default);
+//!     let root = Supervisor::new(Strategy::OneForOne)
+//!         .max_restarts(5, Duration::from_secs(60))
+//!         .child("orders", ServerOptions::new(8080), Orders::default)
+//!         .supervisor("stock", stock)
+//!         .start();
+//!
+//!     let inventory = root.server("stock/inventory").unwrap();
+//!     tokio::signal::ctrl_c().await.unwrap();
+//!     root.shut_down().await;
+//! }
+//! ```
+//!
+//! A supervisor whose children fail more than [`Supervisor::max_restarts`] times within the
+//! period escalates: it drains the children still running and fails itself, and its own
+//! supervisor then restarts it, and with it all its children, from scratch.  A top-level
+//! supervisor that escalates stops for good, with [`SupervisorHandle::failure`] saying why.
+//! Servers started from a [manifest](crate::manifest) can be supervised too, and
+//! [`Supervisor::monitor`] reports restarts as [lifecycle events](crate::monitor).
+
+use crate::monitor::{LifecycleEvent, Monitor};
+use crate::runtime::{self, Runtime};
+use crate::{Actor, ServerHandle, ServerOptions};
+use futures_util::future::{select_all, BoxFuture, FutureExt};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Which children a supervisor restarts when one fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Restart only the failed child.
+    #[default]
+    OneForOne,
+    /// Drain the other children and restart them all, in order.
+    AllForOne,
+}
+
+/// How many times children can fail within [`RESTARTS_WITHIN`] before a supervisor escalates,
+/// unless set with [`Supervisor::max_restarts`].
+pub(crate) const MAX_RESTARTS: u32 = 3;
+pub(crate) const RESTARTS_WITHIN: Duration = Duration::from_secs(5);
+
+/// Starts a child's server with a fresh actor and the given options.
+pub(crate) type Start = Arc<dyn Fn(ServerOptions) -> ServerHandle + Send + Sync>;
+
+#[derive(Clone)]
+enum Spec {
+    Server(Box<ServerOptions>, Start),
+    Supervisor(Supervisor),
+}
+
+/// The children of a supervisor and how it restarts them; see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: u32,
+    within: Duration,
+    children: Vec<(String, Spec)>,
+    monitor: Option<Monitor>,
+    /// The path of the supervisor in its tree, followed by `/`; empty at the top.
+    prefix: String,
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("strategy", &self.strategy)
+            .field("max_restarts", &self.max_restarts)
+            .field("within", &self.within)
+            .field(
+                "children",
+                &self
+                    .children
+                    .iter()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Supervisor {
+    /// A supervisor without children, restarting them with `strategy` up to 3 times in 5
+    /// seconds.
+    pub fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            max_restarts: MAX_RESTARTS,
+            within: RESTARTS_WITHIN,
+            children: Vec::new(),
+            monitor: None,
+            prefix: String::new(),
+        }
+    }
+
+    /// Escalate once children have failed more than `restarts` times within `within`.
+    pub fn max_restarts(mut self, restarts: u32, within: Duration) -> Self {
+        self.max_restarts = restarts;
+        self.within = within;
+        self
+    }
+
+    /// Serve an actor made by `actor` with `options`, and again with a new one from `actor`
+    /// whenever the server fails.  Children are started in the order they are added.
+    pub fn child<T, F>(self, name: impl Into<String>, options: ServerOptions, actor: F) -> Self
+    where
+        T: Actor + Send + Sync + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let start: Start = Arc::new(move |options| actor().start(options));
+        self.server(name, options.stop_on_panic(true), start)
+    }
+
+    /// Supervise `supervisor` as a child, restarting it with all its children when it
+    /// escalates.
+    pub fn supervisor(mut self, name: impl Into<String>, supervisor: Supervisor) -> Self {
+        self.children
+            .push((name.into(), Spec::Supervisor(supervisor)));
+        self
+    }
+
+    /// Report the children restarted to `monitor`, and pass it on to the children without one;
+    /// see [`crate::monitor`].
+    pub fn monitor(mut self, monitor: Monitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Add a child started by `start` with `options`, which must stop on panics.
+    pub(crate) fn server(
+        mut self,
+        name: impl Into<String>,
+        options: ServerOptions,
+        start: Start,
+    ) -> Self {
+        self.children
+            .push((name.into(), Spec::Server(Box::new(options), start)));
+        self
+    }
+
+    /// Start the children, in order, and supervise them.  Must be called within a Tokio
+    /// runtime.
+    pub fn start(self) -> SupervisorHandle {
+        let handle = SupervisorHandle {
+            tree: Arc::new(Tree {
+                children: Mutex::new(Vec::new()),
+                restarts: AtomicU32::new(0),
+                failure: Mutex::new(None),
+                stopping: watch::channel(false).0,
+                stopped: watch::channel(false).0,
+            }),
+        };
+        let children = self
+            .children
+            .iter()
+            .map(|(name, spec)| (name.clone(), Some(self.start_child(name, spec))))
+            .collect();
+        *handle.tree.children.lock().unwrap() = children;
+        runtime::current().spawn(supervise(self, Arc::clone(&handle.tree)));
+        handle
+    }
+
+    /// Start the child `name`, passing on the monitor.
+    fn start_child(&self, name: &str, spec: &Spec) -> Child {
+        let path = format!("{}{}", self.prefix, name);
+        match spec {
+            Spec::Server(options, start) => {
+                let mut options = ServerOptions::clone(options);
+                if let (None, Some(monitor)) = (&options.monitor, &self.monitor) {
+                    options = options.monitor(monitor.clone(), path);
+                }
+                Child::Server(start(options))
+            }
+            Spec::Supervisor(supervisor) => {
+                let mut supervisor = supervisor.clone();
+                supervisor.prefix = format!("{}/", path);
+                supervisor.monitor = supervisor.monitor.or_else(|| self.monitor.clone());
+                Child::Supervisor(supervisor.start())
+            }
+        }
+    }
+}
+
+/// A running child.
+#[derive(Debug, Clone)]
+enum Child {
+    Server(ServerHandle),
+    Supervisor(SupervisorHandle),
+}
+
+impl Child {
+    fn stopped(&self) -> BoxFuture<'static, ()> {
+        let child = self.clone();
+        async move {
+            match &child {
+                Child::Server(server) => server.stopped().await,
+                Child::Supervisor(supervisor) => supervisor.stopped().await,
+            }
+        }
+        .boxed()
+    }
+
+    fn failure(&self) -> Option<String> {
+        match self {
+            Child::Server(server) => server.failure(),
+            Child::Supervisor(supervisor) => supervisor.failure(),
+        }
+    }
+
+    async fn stop(&self) {
+        match self {
+            Child::Server(server) => server.drain().await,
+            Child::Supervisor(supervisor) => supervisor.shut_down().await,
+        }
+    }
+}
+
+/// Shared between a supervisor and its handles.
+#[derive(Debug)]
+struct Tree {
+    /// The children in order; `None` for those that stopped without failing.
+    children: Mutex<Vec<(String, Option<Child>)>>,
+    restarts: AtomicU32,
+    failure: Mutex<Option<String>>,
+    /// Set when the supervisor is told to shut down.
+    stopping: watch::Sender<bool>,
+    /// Set once it has stopped, with all its children.
+    stopped: watch::Sender<bool>,
+}
+
+/// A handle to a running supervisor.  Clones refer to the same supervisor.
+#[derive(Debug, Clone)]
+pub struct SupervisorHandle {
+    tree: Arc<Tree>,
+}
+
+impl SupervisorHandle {
+    /// The server currently serving the child at `path`: its name, or for the children of
+    /// nested supervisors, their names joined with `/`, e.g. `stock/inventory`.  `None` if there
+    /// is no such child or it stopped without failing.  Restarts replace the server, so look it
+    /// up again after one.
+    pub fn server(&self, path: &str) -> Option<ServerHandle> {
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let child = self
+            .tree
+            .children
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, child)| child.clone())?;
+        match (child, rest) {
+            (Child::Server(server), None) => Some(server),
+            (Child::Supervisor(supervisor), Some(rest)) => supervisor.server(rest),
+            _ => None,
+        }
+    }
+
+    /// How many times this supervisor has restarted children, not counting those of nested
+    /// supervisors.
+    pub fn restarts(&self) -> u32 {
+        self.tree.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Why the supervisor escalated, if it did.
+    pub fn failure(&self) -> Option<String> {
+        self.tree.failure.lock().unwrap().clone()
+    }
+
+    /// Whether the supervisor has stopped, with all its children.
+    pub fn is_stopped(&self) -> bool {
+        *self.tree.stopped.borrow()
+    }
+
+    /// Resolves once the supervisor has stopped, after escalating or being shut down.
+    pub async fn stopped(&self) {
+        let mut stopped = self.tree.stopped.subscribe();
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+
+    /// Stop supervising and drain the children, in the reverse of the order they were
+    /// started, resolving once all have stopped.
+    pub async fn shut_down(&self) {
+        self.tree.stopping.send_replace(true);
+        self.stopped().await;
+    }
+}
+
+/// Watch the children of `supervisor` and restart them as they fail, until told to stop or
+/// they fail too often.
+async fn supervise(supervisor: Supervisor, tree: Arc<Tree>) {
+    let mut stopping = tree.stopping.subscribe();
+    let mut failures = VecDeque::new();
+    loop {
+        let running: Vec<_> = tree
+            .children
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, child))| Some((index, child.clone()?)))
+            .collect();
+        if running.is_empty() {
+            break;
+        }
+        let exits = running
+            .iter()
+            .map(|(index, child)| child.stopped().map(|()| *index).boxed());
+        let index = tokio::select! {
+            _ = stopping.wait_for(|stopping| *stopping) => break,
+            (index, _, _) = select_all(exits) => index,
+        };
+
+        let (name, child) = {
+            let mut children = tree.children.lock().unwrap();
+            let (name, child) = &mut children[index];
+            (name.clone(), child.take())
+        };
+        let Some(reason) = child.and_then(|child| child.failure()) else {
+            log::info!("{} stopped", name);
+            continue;
+        };
+        log::warn!("{} failed: {}", name, reason);
+
+        let now = Instant::now();
+        failures.push_back(now);
+        while failures
+            .front()
+            .is_some_and(|&failed| now.duration_since(failed) > supervisor.within)
+        {
+            failures.pop_front();
+        }
+        if failures.len() > supervisor.max_restarts as usize {
+            let message = format!(
+                "{} failed more than {} times in {:?}, last with: {}",
+                name, supervisor.max_restarts, supervisor.within, reason
+            );
+            log::error!("Escalating: {}", message);
+            tree.failure.lock().unwrap().replace(message);
+            break;
+        }
+
+        let restart = match supervisor.strategy {
+            Strategy::OneForOne => vec![index],
+            Strategy::AllForOne => {
+                let others: Vec<_> = running.into_iter().filter(|(i, _)| *i != index).collect();
+                for (_, child) in others.iter().rev() {
+                    child.stop().await;
+                }
+                let mut restart: Vec<_> = others.into_iter().map(|(i, _)| i).collect();
+                restart.push(index);
+                restart.sort_unstable();
+                restart
+            }
+        };
+        for restarted in restart {
+            let (child, spec) = &supervisor.children[restarted];
+            let event = LifecycleEvent::Restarted {
+                name: format!("{}{}", supervisor.prefix, child),
+                reason: if restarted == index {
+                    reason.clone()
+                } else {
+                    format!("{} failed: {}", name, reason)
+                },
+            };
+            match &supervisor.monitor {
+                Some(monitor) => monitor.report(event),
+                None => log::info!("{}", event),
+            }
+            let child = supervisor.start_child(child, spec);
+            tree.children.lock().unwrap()[restarted].1 = Some(child);
+        }
+        tree.restarts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let running: Vec<_> = tree
+        .children
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|(_, child)| child.clone())
+        .collect();
+    for child in running.iter().rev() {
+        child.stop().await;
+    }
+    tree.stopped.send_replace(true);
+}
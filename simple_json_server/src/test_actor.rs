@@ -1,4 +1,4 @@
-use crate::{actor, Actor};
+use crate::{actor, Actor, MethodKind};
 
 #[derive(Debug, Clone)]
 pub struct TestActor {
@@ -17,6 +17,7 @@ impl TestActor {
         a + b
     }
 
+    #[read]
     pub async fn get_counter(&self) -> i32 {
         self.counter
     }
@@ -117,4 +118,26 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_methods_listing() {
+        let actor = TestActor::new();
+        let names: Vec<_> = actor.methods().iter().map(|m| m.name).collect();
+        assert_eq!(names, vec!["add", "get_counter", "greet", "no_params"]);
+    }
+
+    #[test]
+    fn test_method_kinds() {
+        let actor = TestActor::new();
+        let kinds: Vec<_> = actor.methods().iter().map(|m| m.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MethodKind::Write,
+                MethodKind::Read,
+                MethodKind::Write,
+                MethodKind::Write
+            ]
+        );
+    }
 }
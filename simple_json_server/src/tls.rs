@@ -1,3 +1,14 @@
+use crate::secrets::Secret;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 /// TLS configuration for secure connections
 ///
 /// # Example
@@ -9,10 +20,11 @@
 /// ```
 #[derive(Debug, Clone)]
 pub struct TlsConfig {
-    /// Path to the certificate file (PEM format)
+    /// Path to the certificate file (PEM format); empty when loaded from secrets
     pub cert_path: String,
-    /// Path to the private key file (PEM format)
+    /// Path to the private key file (PEM format); empty when loaded from secrets
     pub key_path: String,
+    secrets: Option<Arc<TlsSecrets>>,
 }
 
 impl TlsConfig {
@@ -21,48 +33,147 @@ impl TlsConfig {
         Self {
             cert_path: cert_path.into(),
             key_path: key_path.into(),
+            secrets: None,
         }
     }
 
-    /// Load the TLS configuration and create a rustls ServerConfig
-    pub(crate) async fn load_server_config(
-        &self,
-    ) -> Result<rustls::ServerConfig, Box<dyn std::error::Error + Send + Sync>> {
-        use rustls_pemfile::{certs, pkcs8_private_keys};
-        use std::io::BufReader;
-        use tokio::fs::File;
-        use tokio::io::AsyncReadExt;
-
-        // Read certificate file
-        let mut cert_file = File::open(&self.cert_path).await?;
-        let mut cert_data = Vec::new();
-        cert_file.read_to_end(&mut cert_data).await?;
-        let mut cert_reader = BufReader::new(cert_data.as_slice());
-        let cert_chain: Vec<rustls::pki_types::CertificateDer> =
-            certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
-
-        // Read private key file
-        let mut key_file = File::open(&self.key_path).await?;
-        let mut key_data = Vec::new();
-        key_file.read_to_end(&mut key_data).await?;
-        let mut key_reader = BufReader::new(key_data.as_slice());
-        let mut keys: Vec<rustls::pki_types::PrivateKeyDer> = pkcs8_private_keys(&mut key_reader)
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(rustls::pki_types::PrivateKeyDer::Pkcs8)
-            .collect();
-
-        if keys.is_empty() {
-            return Err("No private key found".into());
+    /// Create a TLS configuration with the certificate chain and private key (PEM format)
+    /// fetched from secrets when the server starts.  While the server runs they are fetched
+    /// again at the shorter of their refresh intervals, and new connections use the new
+    /// certificate once both have changed consistently.  See [`crate::secrets`].
+    pub fn from_secrets(cert: Secret, key: Secret) -> Self {
+        Self {
+            cert_path: String::new(),
+            key_path: String::new(),
+            secrets: Some(Arc::new(TlsSecrets {
+                cert,
+                key,
+                current: RwLock::default(),
+                fetched: Mutex::default(),
+            })),
         }
+    }
+
+    /// Load the TLS configuration and create a rustls ServerConfig
+    pub(crate) async fn load_server_config(&self) -> Result<rustls::ServerConfig, BoxError> {
+        let builder = rustls::ServerConfig::builder().with_no_client_auth();
+        let Some(secrets) = &self.secrets else {
+            let cert_data = tokio::fs::read(&self.cert_path).await?;
+            let key_data = tokio::fs::read(&self.key_path).await?;
+            let (cert_chain, private_key) = parse(&cert_data, &key_data)?;
+            return Ok(builder.with_single_cert(cert_chain, private_key)?);
+        };
+
+        let cert = secrets.cert.fetch().await?;
+        let key = secrets.key.fetch().await?;
+        secrets.install(cert, key)?;
+        Ok(builder.with_cert_resolver(Arc::clone(secrets) as Arc<dyn ResolvesServerCert>))
+    }
+
+    /// Fetch the certificate and key again for as long as the server runs, if they come from
+    /// secrets that refresh.
+    pub(crate) fn rotation(&self) -> Option<impl Future<Output = ()> + Send + 'static> {
+        let secrets = Arc::clone(self.secrets.as_ref()?);
+        let every = [
+            secrets.cert.refresh_interval(),
+            secrets.key.refresh_interval(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()?;
+        Some(async move { secrets.rotate(every).await })
+    }
+}
+
+/// Read the certificate chain and the first private key from PEM.
+fn parse(
+    cert_data: &[u8],
+    key_data: &[u8],
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), BoxError> {
+    use rustls_pemfile::{certs, pkcs8_private_keys};
+    use std::io::BufReader;
+
+    let mut cert_reader = BufReader::new(cert_data);
+    let cert_chain: Vec<CertificateDer> = certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
 
-        let private_key = keys.remove(0);
+    let mut key_reader = BufReader::new(key_data);
+    let mut keys: Vec<PrivateKeyDer> = pkcs8_private_keys(&mut key_reader)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(PrivateKeyDer::Pkcs8)
+        .collect();
 
-        // Create server config
-        let config = rustls::ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(cert_chain, private_key)?;
+    if keys.is_empty() {
+        return Err("No private key found".into());
+    }
+    Ok((cert_chain, keys.remove(0)))
+}
+
+/// The certificate and key of a [`TlsConfig::from_secrets`], serving the current pair to
+/// new connections.
+struct TlsSecrets {
+    cert: Secret,
+    key: Secret,
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+    /// The certificate and key last installed, as fetched.
+    fetched: Mutex<(Vec<u8>, Vec<u8>)>,
+}
+
+impl fmt::Debug for TlsSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsSecrets")
+            .field("cert", &self.cert)
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsSecrets {
+    /// Serve `cert` and `key` to new connections, if they make a valid pair.
+    fn install(&self, cert: Vec<u8>, key: Vec<u8>) -> Result<(), BoxError> {
+        let (cert_chain, private_key) = parse(&cert, &key)?;
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        let certified = CertifiedKey::from_der(cert_chain, private_key, &provider)?;
+        *self.current.write().unwrap() = Some(Arc::new(certified));
+        *self.fetched.lock().unwrap() = (cert, key);
+        Ok(())
+    }
+
+    async fn rotate(&self, every: Duration) {
+        loop {
+            tokio::time::sleep(every).await;
+            let (cert, key) = match (self.cert.fetch().await, self.key.fetch().await) {
+                (Ok(cert), Ok(key)) => (cert, key),
+                (Err(e), _) | (_, Err(e)) => {
+                    log::warn!("Failed to fetch the TLS certificate and key: {}", e);
+                    continue;
+                }
+            };
+            let (cert_changed, key_changed) = {
+                let fetched = self.fetched.lock().unwrap();
+                (fetched.0 != cert, fetched.1 != key)
+            };
+            if !cert_changed && !key_changed {
+                continue;
+            }
+            // A certificate and key rotated separately don't match in between
+            if let Err(e) = self.install(cert.clone(), key.clone()) {
+                log::warn!("Kept the old TLS certificate: {}", e);
+                continue;
+            }
+            log::info!("Rotated the TLS certificate");
+            if cert_changed {
+                self.cert.rotated(&cert);
+            }
+            if key_changed {
+                self.key.rotated(&key);
+            }
+        }
+    }
+}
 
-        Ok(config)
+impl ResolvesServerCert for TlsSecrets {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().unwrap().clone()
     }
 }
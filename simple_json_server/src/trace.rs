@@ -0,0 +1,235 @@
+//! W3C Trace Context propagation, so calls between actors show up as one distributed trace.
+//!
+//! Every call a server serves is a span of a trace.  When the request carries a
+//! [`traceparent`](https://www.w3.org/TR/trace-context/) header the span joins the caller's
+//! trace, as a child of the caller's span; otherwise it starts a new trace.  The span is
+//! available to methods as [`RequestContext::trace`](crate::RequestContext::trace), and the
+//! [`JsonLogger`](crate::logging::JsonLogger) adds its `trace_id` and `span_id` to every line
+//! logged while serving the call.
+//!
+//! Calls made with an [`ActorRef`](crate::ActorRef) while serving a call send `traceparent`
+//! (naming the current span as their parent) and `tracestate`, so the downstream actor's span
+//! continues the same trace:
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor, ActorRef, RequestContext};
+//!
+//! struct Orders {
+//!     payments: ActorRef,
+//! }
+//!
+//! #[actor]
+//! impl Orders {
+//!     pub async fn place(&self, total: f64) -> Result<String, String> {
+//!         if let Some(ctx) = RequestContext::current() {
+//!             log::info!("placing order in trace {}", ctx.trace().trace_id());
+//!         }
+//!         // Carries this call's trace context
+//!         self.payments
+//!             .call("charge", &serde_json::json!({ "amount": total }))
+//!             .await
+//!             .map_err(|e| e.to_string())
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The header naming the trace and the caller's span.
+pub const TRACEPARENT: &str = "traceparent";
+/// The header carrying vendor-specific trace data, passed on unchanged.
+pub const TRACESTATE: &str = "tracestate";
+
+/// Longest `tracestate` value passed on; longer ones are dropped, as the spec allows.
+const MAX_TRACESTATE: usize = 512;
+
+/// The span of one call within a distributed trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    flags: u8,
+    state: Option<String>,
+}
+
+impl TraceContext {
+    /// A span starting a new, sampled trace.
+    pub fn new_root() -> Self {
+        let mut trace_id = [0; 16];
+        trace_id[..8].copy_from_slice(&random_id());
+        trace_id[8..].copy_from_slice(&random_id());
+        Self {
+            trace_id,
+            span_id: random_id(),
+            parent_span_id: None,
+            flags: 1,
+            state: None,
+        }
+    }
+
+    /// A new span, a child of the one described by `traceparent` and `tracestate` headers, or
+    /// `None` if `traceparent` is not valid.
+    pub fn from_headers(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let parent = parse_traceparent(traceparent)?;
+        let state = tracestate
+            .map(str::trim)
+            .filter(|state| !state.is_empty() && state.len() <= MAX_TRACESTATE)
+            .map(str::to_string);
+        Some(Self {
+            trace_id: parent.0,
+            span_id: random_id(),
+            parent_span_id: Some(parent.1),
+            flags: parent.2,
+            state,
+        })
+    }
+
+    /// A new span in the same trace, a child of this one.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_id(),
+            parent_span_id: Some(self.span_id),
+            ..self.clone()
+        }
+    }
+
+    /// The trace's id, as 32 lowercase hex digits.
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// This span's id, as 16 lowercase hex digits.
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// The id of the caller's span, if the trace started elsewhere.
+    pub fn parent_span_id(&self) -> Option<String> {
+        self.parent_span_id.as_ref().map(|id| hex(id))
+    }
+
+    /// Whether the caller records the trace.
+    pub fn sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+
+    /// The `tracestate` the caller sent.
+    pub fn tracestate(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    /// The `traceparent` header value for calls made from this span.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id(),
+            self.span_id(),
+            self.flags
+        )
+    }
+}
+
+/// The trace id, parent span id and flags of a `traceparent` header.
+fn parse_traceparent(header: &str) -> Option<([u8; 16], [u8; 8], u8)> {
+    let mut fields = header.trim().split('-');
+    let version = parse_hex::<1>(fields.next()?)?[0];
+    let trace_id = parse_hex::<16>(fields.next()?)?;
+    let span_id = parse_hex::<8>(fields.next()?)?;
+    let flags = parse_hex::<1>(fields.next()?)?[0];
+    // Version 00 has exactly four fields; later versions may add more
+    let valid = match version {
+        0 => fields.next().is_none(),
+        0xff => false,
+        _ => true,
+    };
+    let valid = valid && trace_id != [0; 16] && span_id != [0; 8];
+    valid.then_some((trace_id, span_id, flags))
+}
+
+/// Decode exactly `N` bytes of lowercase hex.
+fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let text = text.as_bytes();
+    if text.len() != N * 2 {
+        return None;
+    }
+    let digit = |c: u8| match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    };
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(text.chunks(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(bytes)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+/// Eight random bytes, never all zero.
+pub(crate) fn random_id() -> [u8; 8] {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(NEXT.fetch_add(1, Ordering::Relaxed));
+    hasher.finish().max(1).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_children_continue_the_trace() {
+        let span = TraceContext::from_headers(PARENT, Some("congo=t61rcWkgMzE")).unwrap();
+        assert_eq!(span.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span.parent_span_id().unwrap(), "00f067aa0ba902b7");
+        assert_ne!(span.span_id(), "00f067aa0ba902b7");
+        assert!(span.sampled());
+        assert_eq!(span.tracestate(), Some("congo=t61rcWkgMzE"));
+
+        let outbound = span.traceparent();
+        assert_eq!(
+            outbound,
+            format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01", span.span_id())
+        );
+        let downstream = TraceContext::from_headers(&outbound, None).unwrap();
+        assert_eq!(downstream.parent_span_id(), Some(span.span_id()));
+    }
+
+    #[test]
+    fn test_invalid_traceparents() {
+        for header in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert!(
+                TraceContext::from_headers(header, None).is_none(),
+                "{}",
+                header
+            );
+        }
+        // Later versions may add fields
+        assert!(TraceContext::from_headers(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra",
+            None
+        )
+        .is_some_and(|span| !span.sampled()));
+    }
+}
@@ -0,0 +1,331 @@
+//! Parameters that are checked as they are deserialized: URLs, email addresses and phone
+//! numbers.
+//!
+//! A parameter of one of these types only reaches the method if its value is well formed, and
+//! it arrives in a canonical form, so methods don't each carry their own parsing:
+//!
+//! ```rust
+//! use simple_json_server::validated::{EmailAddress, PhoneNumber, Url};
+//! use simple_json_server::{actor, Actor};
+//!
+//! pub struct Contacts;
+//!
+//! #[actor]
+//! impl Contacts {
+//!     pub async fn add(&self, email: EmailAddress, phone: PhoneNumber, site: Option<Url>) -> String {
+//!         format!("{} at {}", email.domain(), phone)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! - [`Url`] takes an absolute URL.  Internationalized host names are converted to their ASCII
+//!   (punycode) form, so `https://bücher.example/` arrives as `https://xn--bcher-kva.example/`.
+//! - [`EmailAddress`] takes `local@domain`, with the domain converted the same way and
+//!   lowercased; the local part is kept as given.
+//! - [`PhoneNumber`] takes an international number starting with `+`, with spaces, dots,
+//!   dashes and parentheses allowed between digits, and keeps it in E.164 form (`+15551234567`).
+//!
+//! A call with a malformed value is refused with `400 Bad Request`, and the message starts with
+//! the error's [code](InvalidValue::code) (`invalid_url`, `invalid_email_address` or
+//! `invalid_phone_number`) so clients can tell these failures apart.  The message never quotes
+//! the value.  Parameters of these types are marked as validated in the generated
+//! documentation.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// The longest email address accepted, in bytes.
+const MAX_EMAIL_LEN: usize = 254;
+
+/// The longest local part of an email address accepted, in bytes.
+const MAX_LOCAL_PART_LEN: usize = 64;
+
+/// The fewest and most digits in an E.164 phone number.
+const PHONE_DIGITS: std::ops::RangeInclusive<usize> = 7..=15;
+
+/// Why a value was refused by one of this module's types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidValue {
+    code: &'static str,
+    reason: String,
+}
+
+impl InvalidValue {
+    fn new(code: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+        }
+    }
+
+    /// The error code: `invalid_url`, `invalid_email_address` or `invalid_phone_number`.
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// What is wrong with the value, without quoting it.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidValue {}
+
+/// An absolute URL, with an internationalized host name in ASCII form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Url(url::Url);
+
+impl Url {
+    /// The URL as text.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// The parsed URL.
+    pub fn into_inner(self) -> url::Url {
+        self.0
+    }
+}
+
+impl FromStr for Url {
+    type Err = InvalidValue;
+
+    fn from_str(text: &str) -> Result<Self, InvalidValue> {
+        url::Url::parse(text)
+            .map(Self)
+            .map_err(|e| InvalidValue::new("invalid_url", e.to_string()))
+    }
+}
+
+impl std::ops::Deref for Url {
+    type Target = url::Url;
+
+    fn deref(&self) -> &url::Url {
+        &self.0
+    }
+}
+
+/// An email address `local@domain`, with the domain in lowercase ASCII form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EmailAddress {
+    address: String,
+    at: usize,
+}
+
+impl EmailAddress {
+    /// The address as text.
+    pub fn as_str(&self) -> &str {
+        &self.address
+    }
+
+    /// The part before the `@`.
+    pub fn local_part(&self) -> &str {
+        &self.address[..self.at]
+    }
+
+    /// The part after the `@`.
+    pub fn domain(&self) -> &str {
+        &self.address[self.at + 1..]
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = InvalidValue;
+
+    fn from_str(text: &str) -> Result<Self, InvalidValue> {
+        let invalid = |reason: &str| InvalidValue::new("invalid_email_address", reason);
+        let (local, domain) = text
+            .rsplit_once('@')
+            .ok_or_else(|| invalid("missing `@`"))?;
+        if local.is_empty() {
+            return Err(invalid("empty local part"));
+        }
+        if local.len() > MAX_LOCAL_PART_LEN {
+            return Err(invalid("local part longer than 64 bytes"));
+        }
+        if local
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || c == '@')
+        {
+            return Err(invalid("local part contains whitespace or `@`"));
+        }
+        if local.starts_with('.') || local.ends_with('.') || local.contains("..") {
+            return Err(invalid("misplaced `.` in local part"));
+        }
+        let domain = match url::Host::parse(domain) {
+            Ok(url::Host::Domain(domain)) if domain.contains('.') && !domain.ends_with('.') => {
+                domain
+            }
+            _ => return Err(invalid("invalid domain")),
+        };
+        let address = format!("{}@{}", local, domain);
+        if address.len() > MAX_EMAIL_LEN {
+            return Err(invalid("longer than 254 bytes"));
+        }
+        Ok(Self {
+            at: local.len(),
+            address,
+        })
+    }
+}
+
+/// An international phone number in E.164 form, such as `+15551234567`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    /// The number as text, `+` and digits.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for PhoneNumber {
+    type Err = InvalidValue;
+
+    fn from_str(text: &str) -> Result<Self, InvalidValue> {
+        let invalid = |reason: &str| InvalidValue::new("invalid_phone_number", reason);
+        let rest = text
+            .trim()
+            .strip_prefix('+')
+            .ok_or_else(|| invalid("must start with `+` and the country code"))?;
+        let mut number = String::from("+");
+        for c in rest.chars() {
+            match c {
+                '0'..='9' => number.push(c),
+                ' ' | '-' | '.' | '(' | ')' => {}
+                _ => return Err(invalid("may only contain digits and separators")),
+            }
+        }
+        let digits = number.len() - 1;
+        if !PHONE_DIGITS.contains(&digits) {
+            return Err(invalid("must have 7 to 15 digits"));
+        }
+        if number.starts_with("+0") {
+            return Err(invalid("country code can't start with 0"));
+        }
+        Ok(Self(number))
+    }
+}
+
+macro_rules! text_type {
+    ($ty:ty, $expecting:literal) => {
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl AsRef<str> for $ty {
+            fn as_ref(&self) -> &str {
+                self.as_str()
+            }
+        }
+
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_str(ParseVisitor::<$ty>::new($expecting))
+            }
+        }
+    };
+}
+
+text_type!(Url, "a URL");
+text_type!(EmailAddress, "an email address");
+text_type!(PhoneNumber, "a phone number");
+
+/// Deserializes a string with `T`'s `FromStr`.
+struct ParseVisitor<T> {
+    expecting: &'static str,
+    ty: PhantomData<T>,
+}
+
+impl<T> ParseVisitor<T> {
+    fn new(expecting: &'static str) -> Self {
+        Self {
+            expecting,
+            ty: PhantomData,
+        }
+    }
+}
+
+impl<T: FromStr<Err = InvalidValue>> Visitor<'_> for ParseVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<T, E> {
+        text.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        let url: Url = "https://bücher.example/a b?q=1".parse().unwrap();
+        assert_eq!(url.as_str(), "https://xn--bcher-kva.example/a%20b?q=1");
+        assert_eq!(url.host_str(), Some("xn--bcher-kva.example"));
+        let e = "/relative".parse::<Url>().unwrap_err();
+        assert_eq!(e.code(), "invalid_url");
+    }
+
+    #[test]
+    fn test_email_addresses() {
+        let email: EmailAddress = "Ada.L@Bücher.Example".parse().unwrap();
+        assert_eq!(email.as_str(), "Ada.L@xn--bcher-kva.example");
+        assert_eq!(email.local_part(), "Ada.L");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+        for bad in [
+            "ada",
+            "@example.com",
+            "ada@localhost",
+            "a..b@example.com",
+            "a b@x.io",
+        ] {
+            let e = bad.parse::<EmailAddress>().unwrap_err();
+            assert_eq!(e.code(), "invalid_email_address", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_phone_numbers() {
+        let phone: PhoneNumber = " +1 (555) 123-4567".parse().unwrap();
+        assert_eq!(phone.as_str(), "+15551234567");
+        for bad in ["555 1234567", "+1 555 CALL NOW", "+12345", "+0123456789"] {
+            let e = bad.parse::<PhoneNumber>().unwrap_err();
+            assert_eq!(e.code(), "invalid_phone_number", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let phone: PhoneNumber = serde_json::from_str(r#""+44 20 7946 0018""#).unwrap();
+        assert_eq!(serde_json::to_string(&phone).unwrap(), r#""+442079460018""#);
+        let e = serde_json::from_str::<EmailAddress>(r#""nobody""#).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("invalid_email_address: missing `@`"));
+        assert!(serde_json::from_str::<Url>("42").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! Versioned request payloads, upgraded by migration functions before deserialization.
+//!
+//! Clients send the payload version they were built against, in the `X-Api-Version` header or
+//! a `_version` field of the parameters (which is removed before the method sees them).  Calls
+//! without a version are taken to be current.  An older payload is passed through each
+//! registered migration between its version and the current one, so the server can rename,
+//! restructure or add parameters without breaking clients that are already deployed:
+//!
+//! ```rust
+//! use serde_json::json;
+//! use simple_json_server::versioning::Versioning;
+//! use simple_json_server::ServerOptions;
+//!
+//! // Version 2 renamed `add`'s parameters from `x` and `y` to `a` and `b`
+//! let versioning = Versioning::new(2).migrate("add", 1, |params| {
+//!     Ok(json!({"a": params["x"], "b": params["y"]}))
+//! });
+//! let options = ServerOptions::new(8080).versioning(versioning);
+//! ```
+//!
+//! Calls claiming a version newer than the current one, or whose migration fails, are answered
+//! `400 Bad Request`.
+
+use hyper::HeaderMap;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Migration = Arc<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+
+/// Payload versioning settings, passed to [`crate::ServerOptions::versioning`].
+#[derive(Clone)]
+pub struct Versioning {
+    current: u32,
+    header: String,
+    field: String,
+    /// Migrations by method and the version they upgrade from.
+    migrations: HashMap<(String, u32), Migration>,
+}
+
+impl fmt::Debug for Versioning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Versioning")
+            .field("current", &self.current)
+            .field("header", &self.header)
+            .field("field", &self.field)
+            .field("migrations", &self.migrations.len())
+            .finish()
+    }
+}
+
+impl Versioning {
+    /// Payloads are at version `current`.
+    pub fn new(current: u32) -> Self {
+        Self {
+            current,
+            header: "x-api-version".to_string(),
+            field: "_version".to_string(),
+            migrations: HashMap::new(),
+        }
+    }
+
+    /// The header carrying the payload version (default `X-Api-Version`).
+    pub fn header(mut self, header: impl Into<String>) -> Self {
+        self.header = header.into().to_ascii_lowercase();
+        self
+    }
+
+    /// The parameter field carrying the payload version (default `_version`).  It takes
+    /// precedence over the header, and is how WebSocket clients send a version.
+    pub fn field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+
+    /// Upgrade `method`'s parameters from version `from` to `from + 1`.  Versions without a
+    /// migration for a method leave its parameters unchanged.
+    pub fn migrate(
+        mut self,
+        method: impl Into<String>,
+        from: u32,
+        migration: impl Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        self.migrations
+            .insert((method.into(), from), Arc::new(migration));
+        self
+    }
+
+    /// The payload version sent in `headers`, if any.
+    pub(crate) fn version(&self, headers: &HeaderMap) -> Option<String> {
+        let version = headers.get(&self.header)?.to_str().ok()?.trim();
+        Some(version.to_string())
+    }
+
+    /// Bring `method`'s `params` up to the current version, given the version from the header.
+    /// Returns `None` if they need no change.
+    pub(crate) fn upgrade(
+        &self,
+        method: &str,
+        params: &str,
+        header: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        // Parameters that aren't JSON are left for deserialization to report
+        let Ok(mut params) = serde_json::from_str::<Value>(params) else {
+            return Ok(None);
+        };
+        let field = params
+            .as_object_mut()
+            .and_then(|params| params.remove(&self.field));
+        let version = match (&field, header) {
+            (Some(version), _) => Some(version.as_u64().and_then(|v| u32::try_from(v).ok())),
+            (None, Some(version)) => Some(version.parse().ok()),
+            (None, None) => None,
+        };
+        let version = match version {
+            None => self.current,
+            Some(Some(version)) => version,
+            Some(None) => return Err("Invalid API version".to_string()),
+        };
+        if version > self.current {
+            return Err(format!(
+                "Unsupported API version {}; the current version is {}",
+                version, self.current
+            ));
+        }
+
+        let mut migrated = field.is_some();
+        for from in version..self.current {
+            if let Some(migration) = self.migrations.get(&(method.to_string(), from)) {
+                params = migration(params).map_err(|e| {
+                    format!(
+                        "Failed to migrate {} parameters from version {}: {}",
+                        method, from, e
+                    )
+                })?;
+                migrated = true;
+            }
+        }
+        Ok(migrated.then(|| params.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn versioning() -> Versioning {
+        Versioning::new(3)
+            .migrate("greet", 1, |mut params| {
+                params["name"] = params["who"].take();
+                Ok(params)
+            })
+            .migrate("greet", 2, |mut params| {
+                params["greeting"] = json!("Hello");
+                Ok(params)
+            })
+            .migrate("fail", 2, |_| Err("no longer supported".to_string()))
+    }
+
+    fn upgrade(method: &str, params: Value, header: Option<&str>) -> Result<Option<Value>, String> {
+        versioning()
+            .upgrade(method, &params.to_string(), header)
+            .map(|params| params.map(|p| serde_json::from_str(&p).unwrap()))
+    }
+
+    #[test]
+    fn test_migrations_are_chained() {
+        let upgraded = upgrade("greet", json!({"who": "Ann", "_version": 1}), None);
+        assert_eq!(
+            upgraded,
+            Ok(Some(
+                json!({"who": null, "name": "Ann", "greeting": "Hello"})
+            ))
+        );
+        let upgraded = upgrade("greet", json!({"name": "Ann"}), Some("2"));
+        assert_eq!(
+            upgraded,
+            Ok(Some(json!({"name": "Ann", "greeting": "Hello"})))
+        );
+    }
+
+    #[test]
+    fn test_current_payloads_are_unchanged() {
+        assert_eq!(upgrade("greet", json!({"name": "Ann"}), None), Ok(None));
+        assert_eq!(
+            upgrade("greet", json!({"name": "Ann"}), Some("3")),
+            Ok(None)
+        );
+        // The version field is removed even when there is nothing to migrate
+        assert_eq!(
+            upgrade("other", json!({"_version": 1}), None),
+            Ok(Some(json!({})))
+        );
+    }
+
+    #[test]
+    fn test_bad_versions() {
+        assert!(upgrade("greet", json!({}), Some("4"))
+            .unwrap_err()
+            .contains("current version is 3"));
+        assert!(upgrade("greet", json!({"_version": "one"}), None).is_err());
+        assert!(upgrade("fail", json!({}), Some("1"))
+            .unwrap_err()
+            .contains("no longer supported"));
+    }
+}
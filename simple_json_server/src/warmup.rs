@@ -0,0 +1,97 @@
+//! Getting an actor ready before its server takes traffic.
+//!
+//! A server binds its listener only once its actor is ready, so the first real call doesn't
+//! pay for opening connections or filling caches, and (with [`crate::k8s`]) the pod isn't
+//! marked ready before then.  Three hooks run in order:
+//!
+//! 1. [`Actor::init`], generated by `#[actor]` from a private `async fn init(&mut self)`.  It has
+//!    the actor to itself, and an error stops the server from starting.
+//! 2. Restoring the latest snapshot, with
+//!    [`ServerOptions::restore_on_start`](crate::ServerOptions::restore_on_start).
+//! 3. The calls listed with [`ServerOptions::warm_up`](crate::ServerOptions::warm_up), made
+//!    directly on the actor: they aren't journaled, counted or subject to quotas.  A call that
+//!    fails is logged and does not stop the server.
+//!
+//! ```rust
+//! use simple_json_server::{actor, Actor, ServerOptions};
+//! use serde_json::json;
+//! use std::collections::HashMap;
+//!
+//! struct Prices {
+//!     cache: HashMap<String, f64>,
+//! }
+//!
+//! #[actor]
+//! impl Prices {
+//!     async fn init(&mut self) -> Result<(), String> {
+//!         self.cache.insert("ACME".to_string(), 12.5);
+//!         Ok(())
+//!     }
+//!
+//!     pub async fn price(&self, symbol: String) -> Option<f64> {
+//!         self.cache.get(&symbol).copied()
+//!     }
+//! }
+//!
+//! # fn main() {}
+//! # async fn example() {
+//! let options = ServerOptions::new(8080).warm_up("price", json!({"symbol": "ACME"}));
+//! Prices { cache: HashMap::new() }.create_with(options);
+//! # }
+//! ```
+//!
+//! An actor that can only be built asynchronously, e.g. from a database, can be started with
+//! [`start_with`], which returns a [`ServerHandle`] straight away and builds the actor in the
+//! background.
+
+use crate::server::{self, ServerState};
+use crate::{Actor, ServerHandle, ServerOptions};
+use std::future::Future;
+use std::time::Instant;
+
+/// Build an actor with `factory` and serve it with `options`, as [`Actor::start`] does.  If
+/// `factory` fails the server doesn't start, and [`ServerHandle::listening`] returns no addresses.
+/// Must be called within a Tokio runtime.
+pub fn start_with<T, F>(factory: F, options: ServerOptions) -> ServerHandle
+where
+    T: Actor + Send + Sync + 'static,
+    F: Future<Output = Result<T, String>> + Send + 'static,
+{
+    let server = ServerHandle::new();
+    let lifecycle = server.lifecycle();
+    tokio::spawn(async move {
+        match factory.await {
+            Ok(actor) => server::run(actor, options, lifecycle).await,
+            Err(e) => {
+                log::error!("Failed to create the actor: {}", e);
+                lifecycle.set_listening(Vec::new());
+                // There is nothing to shut down
+                drop(lifecycle.running());
+            }
+        }
+    });
+    server
+}
+
+/// Make the warm-up calls, in order.
+pub(crate) async fn warm_up<T: Actor>(state: &ServerState<T>) {
+    for (method, params) in &state.options.warm_up {
+        if state.method_info(method).is_none() && !state.actor.methods().is_empty() {
+            log::warn!("Not warming up unknown method {}", method);
+            continue;
+        }
+        let started = Instant::now();
+        let response = state.actor.dispatch(method, params).await;
+        let failed = serde_json::from_str::<serde_json::Value>(&response)
+            .is_ok_and(|response| response.get("Err").is_some());
+        if failed {
+            log::warn!("Warm-up call to {} failed: {}", method, response);
+        } else {
+            log::info!(
+                "Warmed up {} in {}ms",
+                method,
+                started.elapsed().as_millis()
+            );
+        }
+    }
+}
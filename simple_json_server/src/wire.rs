@@ -0,0 +1,41 @@
+//! Support for the wire format tests the `#[actor]` macro generates from `#[example]`s.
+
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+/// Checks that an example response deserializes into `T`.  Return types that only implement
+/// `Serialize` are skipped: the generated test calls `(&ResponseCheck::<T>::new()).check(..)`,
+/// which resolves to [`CheckResponse`] when `T` can be deserialized and to
+/// [`SkipResponseCheck`] otherwise.
+pub struct ResponseCheck<T>(PhantomData<T>);
+
+impl<T> ResponseCheck<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+pub trait CheckResponse {
+    fn check(&self, method: &str, response: &str);
+}
+
+impl<T: DeserializeOwned> CheckResponse for ResponseCheck<T> {
+    fn check(&self, method: &str, response: &str) {
+        if let Err(e) = serde_json::from_str::<T>(response) {
+            panic!(
+                "Example response {} of `{}` does not match its return type {}: {}",
+                response,
+                method,
+                std::any::type_name::<T>(),
+                e
+            );
+        }
+    }
+}
+
+pub trait SkipResponseCheck {
+    fn check(&self, _method: &str, _response: &str) {}
+}
+
+impl<T> SkipResponseCheck for &ResponseCheck<T> {}
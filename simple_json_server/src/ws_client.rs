@@ -0,0 +1,399 @@
+//! Calling actors over WebSocket, with automatic reconnection.
+//!
+//! A [`WsActorRef`] keeps one WebSocket connection to an actor served with
+//! [`ServerOptions::websocket`](crate::ServerOptions::websocket).  Each call carries an `id` the
+//! server echoes back, so replies are matched to calls and events pushed by the server are told
+//! apart from replies.  When the connection drops it is reopened with exponential backoff, the
+//! topics subscribed to are subscribed to again, and ids carry on from where they were.
+//!
+//! ```rust,no_run
+//! use simple_json_server::{Reconnect, WsActorRef};
+//! use serde_json::json;
+//! use std::time::Duration;
+//!
+//! # async fn example() -> Result<(), simple_json_server::ClientError> {
+//! let ticker = WsActorRef::connect(
+//!     "ws://127.0.0.1:8080",
+//!     Reconnect::new().max_delay(Duration::from_secs(10)),
+//! );
+//! let mut events = ticker.events();
+//! ticker.subscribe("price").await?;
+//! let _: () = ticker.call("set_price", &json!({"symbol": "ACME", "price": 12.5})).await?;
+//! let event = events.recv().await.unwrap();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Calls made while the connection is down wait for it to be reopened.  Calls in flight when it
+//! drops fail with [`ClientError::Transport`] rather than being sent twice, as the actor may
+//! already have run them.
+
+use crate::events::Event;
+use crate::ClientError;
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+
+/// How many events a receiver may fall behind before it starts missing them.
+const EVENT_CAPACITY: usize = 1024;
+
+/// How a [`WsActorRef`] reconnects: the delay doubles after each failed attempt, from
+/// `initial_delay` up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct Reconnect {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_attempts: Option<u32>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reconnect {
+    /// Retry forever, starting after 100ms and waiting at most 30 seconds between attempts.
+    pub fn new() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+
+    /// The delay before the first retry.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// The longest delay between retries.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Give up after `attempts` consecutive failed attempts to connect; calls then fail with
+    /// [`ClientError::Transport`].
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// The delay before retry number `attempt` (counting from 0).
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.min(31));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+/// A handle to an actor reachable over WebSocket.  Clones share the connection, which is
+/// closed once every clone has been dropped.
+#[derive(Clone)]
+pub struct WsActorRef {
+    url: Arc<str>,
+    commands: mpsc::UnboundedSender<Command>,
+    events: broadcast::Sender<Event>,
+    reconnects: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for WsActorRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsActorRef")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+/// A request for the connection task, with where to send its outcome.
+struct Command {
+    request: Request,
+    reply: oneshot::Sender<Result<Value, ClientError>>,
+}
+
+enum Request {
+    Call { method: String, params: Value },
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+impl WsActorRef {
+    /// Connect to the actor served at `url`, e.g. `ws://127.0.0.1:8080`, in the background.
+    /// Must be called within a Tokio runtime.
+    pub fn connect(url: impl Into<String>, reconnect: Reconnect) -> Self {
+        let url: Arc<str> = url.into().into();
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let reconnects = Arc::new(AtomicU64::new(0));
+        tokio::spawn(
+            Connection {
+                url: Arc::clone(&url),
+                reconnect,
+                commands: receiver,
+                events: events.clone(),
+                reconnects: Arc::clone(&reconnects),
+                next_id: 1,
+                topics: BTreeSet::new(),
+            }
+            .run(),
+        );
+        Self {
+            url,
+            commands,
+            events,
+            reconnects,
+        }
+    }
+
+    /// The URL of the actor.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// How many times the connection has been reopened after dropping.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::SeqCst)
+    }
+
+    /// Call `method` with `params` and deserialize the response.
+    pub async fn call<P, R>(&self, method: &str, params: &P) -> Result<R, ClientError>
+    where
+        P: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let params =
+            serde_json::to_value(params).map_err(|e| ClientError::Serialization(e.to_string()))?;
+        let result = self
+            .send(Request::Call {
+                method: method.to_string(),
+                params,
+            })
+            .await?;
+        serde_json::from_value(result).map_err(|e| ClientError::Serialization(e.to_string()))
+    }
+
+    /// Receive the events of the topics subscribed to from now on.
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Subscribe to `topic`; its events arrive on [`WsActorRef::events`] receivers, including
+    /// after the connection has been reopened.
+    pub async fn subscribe(&self, topic: &str) -> Result<(), ClientError> {
+        self.send(Request::Subscribe(topic.to_string()))
+            .await
+            .map(drop)
+    }
+
+    /// Stop receiving the events of `topic`.
+    pub async fn unsubscribe(&self, topic: &str) -> Result<(), ClientError> {
+        self.send(Request::Unsubscribe(topic.to_string()))
+            .await
+            .map(drop)
+    }
+
+    async fn send(&self, request: Request) -> Result<Value, ClientError> {
+        let (reply, receiver) = oneshot::channel();
+        let closed = || ClientError::Transport(format!("Connection to {} is closed", self.url));
+        self.commands
+            .send(Command { request, reply })
+            .map_err(|_| closed())?;
+        receiver.await.map_err(|_| closed())?
+    }
+}
+
+/// The task owning the WebSocket connection.
+struct Connection {
+    url: Arc<str>,
+    reconnect: Reconnect,
+    commands: mpsc::UnboundedReceiver<Command>,
+    events: broadcast::Sender<Event>,
+    reconnects: Arc<AtomicU64>,
+    /// The id of the next request; ids keep increasing across connections.
+    next_id: u64,
+    /// Topics to subscribe to again after reconnecting.
+    topics: BTreeSet<String>,
+}
+
+/// A request sent and waiting for its reply.
+enum Waiting {
+    Caller(oneshot::Sender<Result<Value, ClientError>>),
+    /// A subscription renewed after reconnecting; nobody waits for it.
+    Renewal,
+}
+
+impl Connection {
+    async fn run(mut self) {
+        let mut attempt = 0;
+        let mut connected_before = false;
+        loop {
+            let stream = match tokio_tungstenite::connect_async(&*self.url).await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    if self
+                        .reconnect
+                        .max_attempts
+                        .is_some_and(|max| attempt >= max)
+                    {
+                        log::error!("Giving up connecting to {}: {}", self.url, e);
+                        return self.refuse().await;
+                    }
+                    let delay = self.reconnect.delay(attempt);
+                    log::warn!(
+                        "Failed to connect to {}, retrying in {:?}: {}",
+                        self.url,
+                        delay,
+                        e
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+            attempt = 0;
+            if connected_before {
+                self.reconnects.fetch_add(1, Ordering::SeqCst);
+                log::info!("Reconnected to {}", self.url);
+            }
+            connected_before = true;
+            if !self.serve(stream).await {
+                return;
+            }
+        }
+    }
+
+    /// Serve calls over `stream` until it drops (returning true) or every handle is gone.
+    async fn serve<S>(&mut self, stream: tokio_tungstenite::WebSocketStream<S>) -> bool
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let (mut sender, mut receiver) = stream.split();
+        let mut pending = HashMap::new();
+
+        let renewals: Vec<_> = self.topics.iter().cloned().collect();
+        for topic in renewals {
+            let id = self.next_id();
+            let frame = json!({ "id": id, "subscribe": topic }).to_string();
+            if sender.send(Message::Text(frame)).await.is_err() {
+                return true;
+            }
+            pending.insert(id, Waiting::Renewal);
+        }
+
+        let open = loop {
+            tokio::select! {
+                command = self.commands.recv() => {
+                    let Some(Command { request, reply }) = command else {
+                        let _ = sender.send(Message::Close(None)).await;
+                        break false;
+                    };
+                    let id = self.next_id();
+                    let frame = match request {
+                        Request::Call { method, params } => {
+                            json!({ "id": id, "method": method, "params": params })
+                        }
+                        Request::Subscribe(topic) => {
+                            self.topics.insert(topic.clone());
+                            json!({ "id": id, "subscribe": topic })
+                        }
+                        Request::Unsubscribe(topic) => {
+                            self.topics.remove(&topic);
+                            json!({ "id": id, "unsubscribe": topic })
+                        }
+                    };
+                    pending.insert(id, Waiting::Caller(reply));
+                    if sender.send(Message::Text(frame.to_string())).await.is_err() {
+                        break true;
+                    }
+                }
+                message = receiver.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.receive(&text, &mut pending),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break true,
+                    Some(Ok(_)) => {}
+                },
+            }
+        };
+
+        for (_, waiting) in pending {
+            if let Waiting::Caller(reply) = waiting {
+                let error = format!("Connection to {} was lost during the call", self.url);
+                let _ = reply.send(Err(ClientError::Transport(error)));
+            }
+        }
+        open
+    }
+
+    /// Handle a frame from the server: a reply to a request, or an event.
+    fn receive(&mut self, text: &str, pending: &mut HashMap<u64, Waiting>) {
+        let Ok(message) = serde_json::from_str::<Value>(text) else {
+            log::warn!("Ignoring malformed frame from {}", self.url);
+            return;
+        };
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            match serde_json::from_value::<Event>(message) {
+                Ok(event) => {
+                    let _ = self.events.send(event);
+                }
+                Err(_) => log::debug!("Ignoring frame from {}: {}", self.url, text),
+            }
+            return;
+        };
+        let status = message.get("status").and_then(Value::as_u64).unwrap_or(200);
+        let result = message.get("result").cloned().unwrap_or(Value::Null);
+        match pending.remove(&id) {
+            Some(Waiting::Caller(reply)) if (200..300).contains(&status) => {
+                let _ = reply.send(Ok(result));
+            }
+            Some(Waiting::Caller(reply)) => {
+                let _ = reply.send(Err(ClientError::Status(status as u16, result.to_string())));
+            }
+            Some(Waiting::Renewal) if !(200..300).contains(&status) => {
+                log::warn!("Failed to subscribe again on {}: {}", self.url, result);
+            }
+            _ => {}
+        }
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Fail every request once reconnecting has been given up.
+    async fn refuse(mut self) {
+        while let Some(command) = self.commands.recv().await {
+            let error = format!("Gave up connecting to {}", self.url);
+            let _ = command.reply.send(Err(ClientError::Transport(error)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let reconnect = Reconnect::new()
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1));
+        let delays: Vec<_> = (0..6).map(|attempt| reconnect.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(reconnect.delay(u32::MAX), Duration::from_secs(1));
+    }
+}
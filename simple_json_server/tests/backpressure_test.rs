@@ -0,0 +1,130 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use simple_json_server::backpressure::{SendQueue, SlowConsumer};
+use simple_json_server::events::EventBus;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43600);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    events: EventBus,
+}
+
+#[actor]
+impl Feed {
+    /// Publish a large event
+    pub async fn post(&self, size: usize) {
+        self.events.publish("feed", &"x".repeat(size)).unwrap();
+    }
+}
+
+/// Publish large events to a client that subscribed and then stopped reading, until `done`.
+async fn flood(events: &EventBus, done: impl Fn() -> bool) {
+    let payload = "x".repeat(64 * 1024);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(
+            Instant::now() < deadline,
+            "the slow consumer was never noticed"
+        );
+        for _ in 0..16 {
+            events.publish("feed", &payload).unwrap();
+        }
+        sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_slow_consumers_are_disconnected() {
+    let port = get_next_port();
+    let events = EventBus::new();
+    let queue = SendQueue::new(8);
+    Feed {
+        events: events.clone(),
+    }
+    .create_with(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone())
+            .send_queue(queue.clone()),
+    );
+    sleep(Duration::from_millis(200)).await;
+
+    let (mut socket, _) = connect_async(format!("ws://127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    socket
+        .send(Message::Text(json!({"subscribe": "feed"}).to_string()))
+        .await
+        .unwrap();
+    // Stop reading, and let the events pile up
+    flood(&events, || queue.disconnected() > 0).await;
+    assert_eq!(queue.disconnected(), 1);
+
+    // Other clients are unaffected
+    let (mut other, _) = connect_async(format!("ws://127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    other
+        .send(Message::Text(
+            json!({"method": "post", "params": {"size": 1}}).to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        other.next().await.unwrap().unwrap(),
+        Message::Text("null".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_slow_consumers_lose_old_events() {
+    let port = get_next_port();
+    let events = EventBus::new();
+    let queue = SendQueue::new(8).policy(SlowConsumer::DropOldest);
+    Feed {
+        events: events.clone(),
+    }
+    .create_with(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone())
+            .send_queue(queue.clone()),
+    );
+    sleep(Duration::from_millis(200)).await;
+
+    let (mut socket, _) = connect_async(format!("ws://127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    socket
+        .send(Message::Text(json!({"subscribe": "feed"}).to_string()))
+        .await
+        .unwrap();
+    flood(&events, || queue.dropped() > 0).await;
+    assert_eq!(queue.disconnected(), 0);
+
+    // The connection survives: a call is still answered, after the events still queued
+    socket
+        .send(Message::Text(
+            json!({"method": "post", "params": {"size": 1}}).to_string(),
+        ))
+        .await
+        .unwrap();
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) if text == "null" => break,
+            Message::Text(_) => continue,
+            other => panic!("Expected a text message, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use simple_json_server::batch::Batching;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    lookups: Arc<AtomicU32>,
+}
+
+#[actor]
+impl Catalog {
+    /// Add two numbers
+    #[read]
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Add stock for an item
+    #[write]
+    pub async fn restock(&self, count: u32) -> u32 {
+        count
+    }
+
+    /// Look an item up, slowly
+    #[read]
+    pub async fn lookup(&self, sku: String) -> String {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        format!("item {}", sku)
+    }
+}
+
+async fn post_batch(port: u16, body: Value) -> (StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/__batch"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    (status, response.json().await.unwrap())
+}
+
+/// Serve a stand-in for an actor with a `double` method, recording the paths it is asked for.
+/// It answers `/__batch` only if `batches` is set.
+async fn recorder(batches: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&paths);
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let paths = Arc::clone(&recorded);
+            let service = service_fn(move |request: Request<hyper::body::Incoming>| {
+                let paths = Arc::clone(&paths);
+                async move {
+                    let path = request.uri().path().to_string();
+                    paths.lock().unwrap().push(path.clone());
+                    let body = request.into_body().collect().await.unwrap().to_bytes();
+                    let body: Value = serde_json::from_slice(&body).unwrap();
+                    let (status, answer) = match path.as_str() {
+                        "/double" => (200, json!(body["n"].as_i64().unwrap() * 2)),
+                        "/__batch" if batches => {
+                            let answers: Vec<Value> = body
+                                .as_array()
+                                .unwrap()
+                                .iter()
+                                .map(|call| {
+                                    let n = call["params"]["n"].as_i64().unwrap();
+                                    json!({"status": 200, "result": n * 2})
+                                })
+                                .collect();
+                            (200, json!(answers))
+                        }
+                        _ => (404, json!("Unknown method")),
+                    };
+                    let response = Response::builder()
+                        .status(status)
+                        .header("Content-Type", "application/json")
+                        .body(Full::new(Bytes::from(answer.to_string())))
+                        .unwrap();
+                    Ok::<_, Infallible>(response)
+                }
+            });
+            tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service));
+        }
+    });
+    (url, paths)
+}
+
+async fn double_three(actor: &ActorRef) -> Vec<i64> {
+    let params = [json!({"n": 1}), json!({"n": 2}), json!({"n": 3})];
+    let (a, b, c) = tokio::join!(
+        actor.call::<_, i64>("double", &params[0]),
+        actor.call::<_, i64>("double", &params[1]),
+        actor.call::<_, i64>("double", &params[2]),
+    );
+    vec![a.unwrap(), b.unwrap(), c.unwrap()]
+}
+
+#[tokio::test]
+async fn test_batch_endpoint() {
+    let server = Catalog::default().start(ServerOptions::new(0).read_only(true));
+    let port = server.listening().await[0].port();
+
+    let (status, answers) = post_batch(
+        port,
+        json!([
+            {"method": "add", "params": {"a": 1, "b": 2}},
+            {"method": "add", "params": {"a": 3, "b": 4}},
+            {"method": "restock", "params": {"count": 4}},
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        answers,
+        json!([
+            {"status": 200, "result": 3},
+            {"status": 200, "result": 7},
+            {
+                "status": 403,
+                "result": "Method restock changes state and this server is read-only"
+            },
+        ])
+    );
+
+    let (status, _) = post_batch(port, json!({"method": "add"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_batch_size_is_limited() {
+    let server = Catalog::default().start(ServerOptions::new(0).max_batch(2));
+    let port = server.listening().await[0].port();
+    let add = json!({"method": "add", "params": {"a": 1, "b": 2}});
+    let (status, _) = post_batch(port, json!([add, add])).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_batch(port, json!([add, add, add])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Without batches, `__batch` is just a method the actor doesn't have
+    let server = Catalog::default().start(ServerOptions::new(0).max_batch(0));
+    let port = server.listening().await[0].port();
+    let (_, answer) = post_batch(port, json!([add])).await;
+    assert_eq!(answer, "Unknown method: __batch");
+}
+
+#[tokio::test]
+async fn test_calls_close_together_share_a_request() {
+    let (url, paths) = recorder(true).await;
+    let actor = ActorRef::new(url).with_batching(Batching::new().window(Duration::from_millis(50)));
+
+    assert_eq!(double_three(&actor).await, vec![2, 4, 6]);
+    assert_eq!(*paths.lock().unwrap(), vec!["/__batch"]);
+
+    // A call alone in its window is sent as usual
+    let six: i64 = actor.call("double", &json!({"n": 3})).await.unwrap();
+    assert_eq!(six, 6);
+    assert_eq!(*paths.lock().unwrap(), vec!["/__batch", "/double"]);
+}
+
+#[tokio::test]
+async fn test_full_batches_are_sent_at_once() {
+    let (url, paths) = recorder(true).await;
+    let actor = ActorRef::new(url)
+        .with_batching(Batching::new().window(Duration::from_secs(60)).max_calls(3));
+    let doubled = tokio::time::timeout(Duration::from_secs(5), double_three(&actor))
+        .await
+        .unwrap();
+    assert_eq!(doubled, vec![2, 4, 6]);
+    assert_eq!(*paths.lock().unwrap(), vec!["/__batch"]);
+}
+
+#[tokio::test]
+async fn test_servers_without_batches_get_single_calls() {
+    let (url, paths) = recorder(false).await;
+    let actor = ActorRef::new(url).with_batching(Batching::new().window(Duration::from_millis(50)));
+
+    assert_eq!(double_three(&actor).await, vec![2, 4, 6]);
+    let paths = paths.lock().unwrap();
+    assert_eq!(paths[0], "/__batch");
+    assert_eq!(paths[1..], ["/double", "/double", "/double"]);
+}
+
+#[tokio::test]
+async fn test_batched_calls_fail_alone() {
+    let server = Catalog::default().start(ServerOptions::new(0).read_only(true));
+    let port = server.listening().await[0].port();
+    let actor = ActorRef::new(format!("http://127.0.0.1:{port}"))
+        .with_batching(Batching::new().window(Duration::from_millis(50)));
+
+    let add = json!({"a": 1, "b": 2});
+    let restock = json!({"count": 4});
+    let (sum, refused) = tokio::join!(
+        actor.call::<_, i32>("add", &add),
+        actor.call::<_, u32>("restock", &restock),
+    );
+    assert_eq!(sum.unwrap(), 3);
+    assert!(matches!(refused, Err(ClientError::Status(403, _))));
+}
+
+#[tokio::test]
+async fn test_identical_calls_are_coalesced() {
+    let catalog = Catalog::default();
+    let lookups = Arc::clone(&catalog.lookups);
+    let server = catalog.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let actor = ActorRef::new(format!("http://127.0.0.1:{port}"))
+        .with_batching(Batching::new().max_calls(1).coalesce("lookup"));
+
+    let a1 = json!({"sku": "a1"});
+    let b2 = json!({"sku": "b2"});
+    let clone = actor.clone();
+    let (first, second, third, other) = tokio::join!(
+        actor.call::<_, String>("lookup", &a1),
+        actor.call::<_, String>("lookup", &a1),
+        clone.call::<_, String>("lookup", &a1),
+        actor.call::<_, String>("lookup", &b2),
+    );
+    assert_eq!(first.unwrap(), "item a1");
+    assert_eq!(second.unwrap(), "item a1");
+    assert_eq!(third.unwrap(), "item a1");
+    assert_eq!(other.unwrap(), "item b2");
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+    // Answers aren't kept once the call is done
+    let again: String = actor.call("lookup", &a1).await.unwrap();
+    assert_eq!(again, "item a1");
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+}
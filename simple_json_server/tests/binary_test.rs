@@ -0,0 +1,75 @@
+use hyper::body::Bytes;
+use serde_json::json;
+use simple_json_server::binary::Base64;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42400);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Signer;
+
+#[actor]
+impl Signer {
+    /// Sign a small document
+    #[max_size(document = 16, key = 4)]
+    pub async fn sign(&self, document: Base64<Bytes>, key: Base64) -> Base64 {
+        let mut signature = key.into_inner();
+        signature.extend(document.iter().rev());
+        Base64(signature)
+    }
+}
+
+async fn post(port: u16, params: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/sign"))
+        .json(&params)
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_base64_parameters_and_results() {
+    let port = get_next_port();
+    Signer.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, json!({"document": "aGVsbG8=", "key": "AAE"})).await;
+    assert_eq!(response.status(), 200);
+    let signature: Base64 = response.json().await.unwrap();
+    assert_eq!(signature.into_inner(), b"\x00\x01olleh");
+
+    let response = post(port, json!({"document": "not base64!", "key": "AAE="})).await;
+    assert_eq!(response.status(), 400);
+    assert!(response
+        .json::<String>()
+        .await
+        .unwrap()
+        .contains("invalid base64"));
+}
+
+#[tokio::test]
+async fn test_oversized_parameters_are_rejected() {
+    let port = get_next_port();
+    Signer.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let document = Base64(vec![7u8; 17]);
+    let response = post(port, json!({"document": document, "key": "AAE="})).await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        "Parameter `document` of sign is 17 bytes; at most 16 are allowed"
+    );
+
+    let document = Base64(vec![7u8; 16]);
+    let response = post(port, json!({"document": document, "key": "AAE="})).await;
+    assert_eq!(response.status(), 200);
+}
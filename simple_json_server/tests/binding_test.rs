@@ -0,0 +1,98 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ParamSource, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Documents;
+
+#[actor]
+impl Documents {
+    /// Create a document for the caller's tenant
+    pub async fn create(
+        &self,
+        #[from_header("X-Tenant-Id")] tenant: String,
+        title: String,
+        #[from_header("X-Priority")] priority: Option<u8>,
+    ) -> String {
+        format!("{tenant}/{title}/{}", priority.unwrap_or(0))
+    }
+
+    /// Who is signed in
+    pub async fn whoami(&self, #[from_cookie("session")] session: String) -> String {
+        session
+    }
+}
+
+async fn post(
+    port: u16,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: serde_json::Value,
+) -> (u16, serde_json::Value) {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .json(&body);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = request.send().await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[test]
+fn test_bound_params_are_described() {
+    let info = Documents.methods();
+    let create = info.iter().find(|m| m.name == "create").unwrap();
+    let sources: Vec<_> = create.params.iter().map(|p| p.source).collect();
+    assert_eq!(
+        sources,
+        [
+            ParamSource::Header("X-Tenant-Id"),
+            ParamSource::Body,
+            ParamSource::Header("X-Priority"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_params_from_headers() {
+    let server = Documents.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let tenant = [("X-Tenant-Id", "acme")];
+    let (status, body) = post(port, "create", &tenant, json!({"title": "plan"})).await;
+    assert_eq!((status, body), (200, json!("acme/plan/0")));
+
+    let headers = [("X-Tenant-Id", "acme"), ("X-Priority", "3")];
+    let (status, body) = post(port, "create", &headers, json!({"title": "plan"})).await;
+    assert_eq!((status, body), (200, json!("acme/plan/3")));
+
+    // The header, not the body, supplies the value
+    let body = json!({"title": "plan", "tenant": "other"});
+    let (status, body) = post(port, "create", &[], body).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body,
+        json!("Missing header `X-Tenant-Id` for parameter `tenant` of create")
+    );
+
+    let headers = [("X-Tenant-Id", "acme"), ("X-Priority", "urgent")];
+    let (status, body) = post(port, "create", &headers, json!({"title": "plan"})).await;
+    assert_eq!(status, 400);
+    assert!(body
+        .as_str()
+        .unwrap()
+        .starts_with("Invalid value for header `X-Priority` of create"));
+}
+
+#[tokio::test]
+async fn test_params_from_cookies() {
+    let server = Documents.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let cookies = [("Cookie", "theme=dark; session=s3cr3t")];
+    let (status, body) = post(port, "whoami", &cookies, json!({})).await;
+    assert_eq!((status, body), (200, json!("s3cr3t")));
+
+    let (status, _) = post(port, "whoami", &[], json!({})).await;
+    assert_eq!(status, 400);
+}
@@ -0,0 +1,108 @@
+use serde_json::Value;
+use simple_json_server::blocking::BlockingPool;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct Reports;
+
+#[actor]
+impl Reports {
+    /// Blocks its thread, as a synchronous database driver would
+    #[blocking]
+    pub async fn render(&self, millis: u64) -> String {
+        std::thread::sleep(Duration::from_millis(millis));
+        std::thread::current()
+            .name()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Cheap
+    pub async fn ping(&self) -> String {
+        "pong".to_string()
+    }
+}
+
+async fn call(port: u16, method: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn admin(port: u16, op: &str) -> String {
+    reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/{op}"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_blocking_methods_are_marked() {
+    let info = Reports.methods();
+    assert!(info.iter().find(|m| m.name == "render").unwrap().blocking);
+    assert!(!info.iter().find(|m| m.name == "ping").unwrap().blocking);
+}
+
+#[tokio::test]
+async fn test_blocking_calls_run_on_the_pool() {
+    let pool = BlockingPool::new(1).queue(1);
+    let server = Reports.start(
+        ServerOptions::new(0)
+            .blocking_pool(pool.clone())
+            .admin_token("secret"),
+    );
+    let port = server.listening().await[0].port();
+
+    let running = tokio::spawn(async move { call(port, "render", r#"{"millis": 500}"#).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let queued = tokio::spawn(async move { call(port, "render", r#"{"millis": 10}"#).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The async runtime is not held up by the blocked thread
+    let started = Instant::now();
+    assert_eq!(
+        call(port, "ping", "{}").await.text().await.unwrap(),
+        "\"pong\""
+    );
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    // The one thread and the one queue slot are taken
+    let rejected = call(port, "render", r#"{"millis": 10}"#).await;
+    assert_eq!(rejected.status(), 503);
+    assert_eq!(rejected.headers()["retry-after"], "1");
+
+    let stats: Value = serde_json::from_str(&admin(port, "blocking").await).unwrap();
+    assert_eq!(stats["threads"], 1);
+    assert_eq!(stats["queued"], 1);
+    assert_eq!(stats["rejected"], 1);
+    let metrics = admin(port, "metrics").await;
+    assert!(metrics.contains("simple_json_server_blocking_pool{measure=\"busy\"} 1\n"));
+
+    for call in [running, queued] {
+        let response = call.await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.text().await.unwrap(), "\"blocking-pool\"");
+    }
+    assert_eq!(pool.stats().completed, 2);
+}
+
+#[tokio::test]
+async fn test_default_pool_for_blocking_methods() {
+    let server = Reports.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let response = call(port, "render", r#"{"millis": 1}"#).await;
+    assert_eq!(response.text().await.unwrap(), "\"blocking-pool\"");
+    let stats: Value = serde_json::from_str(&admin(port, "blocking").await).unwrap();
+    assert_eq!(stats["max_threads"], 16);
+    assert_eq!(stats["completed"], 1);
+}
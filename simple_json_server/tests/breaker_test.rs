@@ -0,0 +1,66 @@
+use serde_json::json;
+use simple_json_server::breaker::{CircuitBreaker, CircuitState};
+use simple_json_server::{actor, Actor, ActorRef, ClientError};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41800);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Echo;
+
+#[actor]
+impl Echo {
+    /// Return the message
+    pub async fn echo(&self, message: String) -> String {
+        message
+    }
+}
+
+#[tokio::test]
+async fn test_circuit_opens_and_recovers() {
+    let port = get_next_port();
+    let echo = ActorRef::new(format!("http://127.0.0.1:{port}")).with_circuit_breaker(
+        CircuitBreaker::new()
+            .failure_threshold(2)
+            .reset_timeout(Duration::from_millis(200)),
+    );
+    let params = json!({"message": "hi"});
+
+    // Nothing is listening yet, so calls fail until the circuit opens
+    for _ in 0..2 {
+        let result = echo.call::<_, String>("echo", &params).await;
+        assert!(matches!(result, Err(ClientError::Transport(_))));
+    }
+    assert_eq!(echo.circuit_state(), Some(CircuitState::Open));
+    let result = echo.call::<_, String>("echo", &params).await;
+    assert!(matches!(result, Err(ClientError::CircuitOpen(_))));
+
+    // Clones share the circuit
+    let clone = echo.clone();
+    assert_eq!(clone.circuit_state(), Some(CircuitState::Open));
+
+    // Once the actor is up and the timeout has passed, the trial call closes the circuit
+    Echo.create(port);
+    sleep(Duration::from_millis(250)).await;
+    let reply: String = echo.call("echo", &params).await.unwrap();
+    assert_eq!(reply, "hi");
+    assert_eq!(clone.circuit_state(), Some(CircuitState::Closed));
+}
+
+#[tokio::test]
+async fn test_no_breaker_by_default() {
+    let echo = ActorRef::new(format!("http://127.0.0.1:{}", get_next_port()));
+    assert_eq!(echo.circuit_state(), None);
+    for _ in 0..10 {
+        let result = echo
+            .call::<_, String>("echo", &json!({"message": "hi"}))
+            .await;
+        assert!(matches!(result, Err(ClientError::Transport(_))));
+    }
+}
@@ -0,0 +1,128 @@
+use serde_json::json;
+use simple_json_server::cluster::{Cluster, MemoryBackend, SESSION_HEADER};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41000);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeServer {
+    pub node: String,
+}
+
+#[actor]
+impl NodeServer {
+    /// Report which node served the request
+    pub async fn whoami(&self) -> String {
+        self.node.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_sessions_redirect_to_owner() {
+    let backend = MemoryBackend::new();
+    let port_a = get_next_port();
+    let port_b = get_next_port();
+
+    for (node, port) in [("a", port_a), ("b", port_b)] {
+        let cluster = Cluster::new(node, format!("http://127.0.0.1:{port}"), backend.clone());
+        NodeServer {
+            node: node.to_string(),
+        }
+        .create_with(ServerOptions::new(port).cluster(cluster));
+    }
+    sleep(Duration::from_millis(200)).await;
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // The first node to see a session owns it
+    let response = client
+        .post(format!("http://127.0.0.1:{port_a}/whoami"))
+        .header(SESSION_HEADER, "cart-42")
+        .json(&json!({}))
+        .send()
+        .await
+        .expect("Failed to send request to node a");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.json::<String>().await.unwrap(), "a");
+
+    // Other nodes point the client at the owner
+    let response = client
+        .post(format!("http://127.0.0.1:{port_b}/whoami"))
+        .header(SESSION_HEADER, "cart-42")
+        .json(&json!({}))
+        .send()
+        .await
+        .expect("Failed to send request to node b");
+    assert_eq!(response.status(), 307);
+    assert_eq!(
+        response.headers().get("location").unwrap(),
+        &format!("http://127.0.0.1:{port_a}/whoami")
+    );
+
+    // ... keeping the query string
+    let response = client
+        .post(format!(
+            "http://127.0.0.1:{port_b}/whoami?pretty=true&lang=en"
+        ))
+        .header(SESSION_HEADER, "cart-42")
+        .json(&json!({}))
+        .send()
+        .await
+        .expect("Failed to send request to node b");
+    assert_eq!(response.status(), 307);
+    assert_eq!(
+        response.headers().get("location").unwrap(),
+        &format!("http://127.0.0.1:{port_a}/whoami?pretty=true&lang=en")
+    );
+
+    // Clients following redirects end up at the owner transparently
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port_b}/whoami"))
+        .header(SESSION_HEADER, "cart-42")
+        .json(&json!({}))
+        .send()
+        .await
+        .expect("Failed to send request to node b");
+    assert_eq!(response.json::<String>().await.unwrap(), "a");
+
+    // Requests without a session are served locally
+    let response = client
+        .post(format!("http://127.0.0.1:{port_b}/whoami"))
+        .json(&json!({}))
+        .send()
+        .await
+        .expect("Failed to send request to node b");
+    assert_eq!(response.json::<String>().await.unwrap(), "b");
+}
+
+#[tokio::test]
+async fn test_servers_elect_a_leader() {
+    let backend = MemoryBackend::new();
+    let port = get_next_port();
+    let server_cluster = Cluster::new(
+        "server",
+        format!("http://127.0.0.1:{port}"),
+        backend.clone(),
+    );
+    NodeServer {
+        node: "server".to_string(),
+    }
+    .create_with(ServerOptions::new(port).cluster(server_cluster.clone()));
+    sleep(Duration::from_millis(200)).await;
+
+    assert!(server_cluster.is_leader());
+
+    let other = Cluster::new("other", "http://127.0.0.1:1", backend);
+    assert_eq!(other.refresh_leadership().await.unwrap().id, "server");
+    assert!(!other.is_leader());
+}
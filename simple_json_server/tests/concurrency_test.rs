@@ -0,0 +1,155 @@
+use futures_util::future::join_all;
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef};
+use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Tracks how many calls are running at once and the most seen so far.
+#[derive(Debug, Default)]
+pub struct Gauge {
+    running: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Gauge {
+    async fn enter(&self) {
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(running, Ordering::SeqCst);
+        sleep(Duration::from_millis(20)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    compactions: Gauge,
+    uploads: Gauge,
+    reads: Gauge,
+}
+
+#[actor]
+impl Maintenance {
+    /// Compact storage; never runs alongside another compaction
+    #[serialized]
+    pub async fn compact(&self) {
+        self.compactions.enter().await;
+    }
+
+    /// Upload a file, two at a time
+    #[concurrency(max = 2)]
+    pub async fn upload(&self, name: String) -> String {
+        self.uploads.enter().await;
+        name
+    }
+
+    /// Unlimited
+    pub async fn read(&self) {
+        self.reads.enter().await;
+    }
+}
+
+async fn run_concurrently(actor: &Maintenance, method: &str, msg: &str, calls: usize) {
+    join_all((0..calls).map(|_| actor.dispatch(method, msg))).await;
+}
+
+#[tokio::test]
+async fn test_serialized_method_never_overlaps() {
+    let actor = Maintenance::default();
+    run_concurrently(&actor, "compact", "{}", 5).await;
+    assert_eq!(actor.compactions.peak.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_concurrency_limit() {
+    let actor = Maintenance::default();
+    run_concurrently(&actor, "upload", r#"{"name": "a.txt"}"#, 6).await;
+    assert_eq!(actor.uploads.peak.load(Ordering::SeqCst), 2);
+
+    run_concurrently(&actor, "read", "{}", 4).await;
+    assert_eq!(actor.reads.peak.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn test_limits_are_reported() {
+    let limits: Vec<_> = Maintenance::default()
+        .methods()
+        .iter()
+        .map(|m| (m.name, m.max_concurrency))
+        .collect();
+    assert_eq!(
+        limits,
+        vec![("compact", Some(1)), ("upload", Some(2)), ("read", None)]
+    );
+}
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41600);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Default)]
+pub struct Ledger {
+    readers: Gauge,
+    overlapping_writes: AtomicUsize,
+}
+
+#[actor]
+impl Ledger {
+    /// Read the balance
+    #[read]
+    pub async fn balance(&self) -> u32 {
+        self.readers.enter().await;
+        0
+    }
+
+    /// Post an entry; must not overlap any other call
+    #[write]
+    pub async fn post(&self) {
+        if self.readers.running.load(Ordering::SeqCst) > 0 {
+            self.overlapping_writes.fetch_add(1, Ordering::SeqCst);
+        }
+        sleep(Duration::from_millis(20)).await;
+        if self.readers.running.load(Ordering::SeqCst) > 0 {
+            self.overlapping_writes.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// How many writes saw a read in progress
+    #[read]
+    pub async fn overlaps(&self) -> usize {
+        self.overlapping_writes.load(Ordering::SeqCst)
+    }
+
+    /// The most reads seen running at once
+    #[read]
+    pub async fn peak_readers(&self) -> usize {
+        self.readers.peak.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn test_reads_share_and_writes_are_exclusive() {
+    let port = get_next_port();
+    Ledger::default().create(port);
+    sleep(Duration::from_millis(100)).await;
+
+    let ledger = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let calls = (0..12).map(|i| {
+        let ledger = ledger.clone();
+        async move {
+            if i % 4 == 0 {
+                ledger.call::<_, ()>("post", &json!({})).await.unwrap();
+            } else {
+                ledger.call::<_, u32>("balance", &json!({})).await.unwrap();
+            }
+        }
+    });
+    join_all(calls).await;
+
+    let overlaps: usize = ledger.call("overlaps", &json!({})).await.unwrap();
+    let peak_readers: usize = ledger.call("peak_readers", &json!({})).await.unwrap();
+    assert_eq!(overlaps, 0);
+    assert!(peak_readers > 1);
+}
@@ -0,0 +1,102 @@
+use serde_json::json;
+use simple_json_server::content_type::ContentTypes;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Notes;
+
+#[actor]
+impl Notes {
+    /// Save a note
+    pub async fn save(&self, text: String) -> usize {
+        text.len()
+    }
+
+    /// Save a note from a client that can't set JSON headers
+    #[accepts("text/plain", "application/octet-stream")]
+    pub async fn save_plain(&self, text: String) -> usize {
+        text.len()
+    }
+}
+
+async fn post(port: u16, method: &str, content_type: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(r#"{"text": "hello"}"#);
+    if let Some(content_type) = content_type {
+        request = request.header("Content-Type", content_type);
+    }
+    request.send().await.unwrap()
+}
+
+#[test]
+fn test_accepted_types_are_listed() {
+    let info = Notes.methods();
+    let save_plain = info.iter().find(|m| m.name == "save_plain").unwrap();
+    assert_eq!(
+        save_plain.accepts,
+        ["text/plain", "application/octet-stream"]
+    );
+    assert!(info
+        .iter()
+        .find(|m| m.name == "save")
+        .unwrap()
+        .accepts
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_any_content_type_by_default() {
+    let server = Notes.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    assert_eq!(post(port, "save", None).await.status(), 200);
+    assert_eq!(post(port, "save", Some("text/plain")).await.status(), 200);
+}
+
+#[tokio::test]
+async fn test_required_content_types() {
+    let content_types = ContentTypes::json().allow("application/vnd.notes+json");
+    let server = Notes.start(ServerOptions::new(0).content_types(content_types));
+    let port = server.listening().await[0].port();
+
+    for accepted in [
+        "application/json",
+        "application/json; charset=utf-8",
+        "application/vnd.notes+json",
+    ] {
+        assert_eq!(post(port, "save", Some(accepted)).await.status(), 200);
+    }
+    for rejected in [
+        None,
+        Some("text/plain"),
+        Some("application/x-www-form-urlencoded"),
+    ] {
+        let response = post(port, "save", rejected).await;
+        assert_eq!(response.status(), 415);
+        assert_eq!(
+            response.headers()["accept-post"],
+            "application/json, application/vnd.notes+json"
+        );
+        assert_eq!(
+            response.json::<String>().await.unwrap(),
+            "Unsupported Content-Type; save takes application/json, application/vnd.notes+json"
+        );
+    }
+
+    // The method's own list replaces the server's
+    assert_eq!(
+        post(port, "save_plain", Some("text/plain")).await.status(),
+        200
+    );
+    let response = post(port, "save_plain", Some("application/json")).await;
+    assert_eq!(response.status(), 415);
+    assert_eq!(
+        response.headers()["accept-post"],
+        "text/plain, application/octet-stream"
+    );
+
+    // Clients send JSON
+    let notes = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let saved: usize = notes.call("save", &json!({"text": "hi"})).await.unwrap();
+    assert_eq!(saved, 2);
+}
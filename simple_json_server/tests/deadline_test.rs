@@ -0,0 +1,121 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, RequestContext};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41700);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Worker;
+
+#[actor]
+impl Worker {
+    /// Takes half a second
+    pub async fn slow(&self) -> String {
+        sleep(Duration::from_millis(500)).await;
+        "done".to_string()
+    }
+
+    /// Milliseconds left before the caller's deadline
+    pub async fn remaining(&self) -> Option<u64> {
+        RequestContext::current()
+            .and_then(|ctx| ctx.remaining())
+            .map(|remaining| remaining.as_millis() as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frontend {
+    worker: ActorRef,
+}
+
+#[actor]
+impl Frontend {
+    /// Asks the worker how much budget reached it
+    pub async fn remaining(&self) -> Option<u64> {
+        self.worker.call("remaining", &json!({})).await.unwrap()
+    }
+}
+
+async fn post(port: u16, method: &str, header: (&str, String)) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .header(header.0, header.1)
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+fn unix_ms_from_now(offset: Duration) -> String {
+    (SystemTime::now() + offset)
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_calls_past_their_deadline_time_out() {
+    let port = get_next_port();
+    Worker.create(port);
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "slow", ("grpc-timeout", "100m".to_string())).await;
+    assert_eq!(response.status(), 504);
+
+    // A deadline that has already passed is rejected without running the method
+    let expired = (SystemTime::now() - Duration::from_secs(1))
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        .to_string();
+    let response = post(port, "remaining", ("X-Request-Deadline", expired)).await;
+    assert_eq!(response.status(), 504);
+
+    // Without a deadline the call completes
+    let worker = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let done: String = worker.call("slow", &json!({})).await.unwrap();
+    assert_eq!(done, "done");
+}
+
+#[tokio::test]
+async fn test_remaining_budget_is_exposed_and_propagated() {
+    let worker_port = get_next_port();
+    let frontend_port = get_next_port();
+    Worker.create(worker_port);
+    Frontend {
+        worker: ActorRef::new(format!("http://127.0.0.1:{worker_port}")),
+    }
+    .create(frontend_port);
+    sleep(Duration::from_millis(100)).await;
+
+    let deadline = (
+        "X-Request-Deadline",
+        unix_ms_from_now(Duration::from_secs(5)),
+    );
+    let remaining: Option<u64> = post(worker_port, "remaining", deadline.clone())
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(remaining.is_some_and(|ms| ms > 4000 && ms <= 5000));
+
+    // The frontend's outbound call carries the deadline on to the worker
+    let remaining: Option<u64> = post(frontend_port, "remaining", deadline)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(remaining.is_some_and(|ms| ms > 4000 && ms <= 5000));
+
+    // No deadline, no budget
+    let worker = ActorRef::new(format!("http://127.0.0.1:{worker_port}"));
+    let remaining: Option<u64> = worker.call("remaining", &json!({})).await.unwrap();
+    assert_eq!(remaining, None);
+}
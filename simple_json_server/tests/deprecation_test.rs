@@ -0,0 +1,78 @@
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43200);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    #[deprecated(note = "Use sum instead", sunset = "2025-12-31")]
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Subtract two numbers
+    #[deprecated]
+    pub async fn sub(&self, a: i32, b: i32) -> i32 {
+        a - b
+    }
+
+    /// Add any numbers
+    pub async fn sum(&self, numbers: Vec<i32>) -> i32 {
+        numbers.iter().sum()
+    }
+}
+
+async fn post(port: u16, method: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_deprecated_methods_send_headers() {
+    let port = get_next_port();
+    Calculator.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "add", r#"{"a": 2, "b": 3}"#).await;
+    let headers = response.headers();
+    assert_eq!(headers["Deprecation"], "true");
+    assert_eq!(headers["Sunset"], "Wed, 31 Dec 2025 00:00:00 GMT");
+    assert_eq!(
+        headers["Warning"],
+        r#"299 - "add is deprecated: Use sum instead""#
+    );
+    assert_eq!(response.text().await.unwrap(), "5");
+
+    let response = post(port, "sub", r#"{"a": 2, "b": 3}"#).await;
+    assert_eq!(response.headers()["Deprecation"], "true");
+    assert!(response.headers().get("Sunset").is_none());
+
+    let response = post(port, "sum", r#"{"numbers": [1, 2]}"#).await;
+    assert!(response.headers().get("Deprecation").is_none());
+}
+
+#[test]
+fn test_method_info_records_deprecation() {
+    let add = Calculator
+        .methods()
+        .iter()
+        .find(|method| method.name == "add")
+        .unwrap();
+    let deprecated = add.deprecated.unwrap();
+    assert_eq!(deprecated.note, Some("Use sum instead"));
+    assert_eq!(deprecated.sunset, Some("2025-12-31"));
+}
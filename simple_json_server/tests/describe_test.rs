@@ -0,0 +1,131 @@
+use serde_json::{json, Value};
+use simple_json_server::content_type::ContentTypes;
+use simple_json_server::quota::Quotas;
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    #[read]
+    #[example(params = r#"{"a": 1, "b": 2}"#, response = "3")]
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Not ready yet
+    #[flag("beta")]
+    pub async fn beta(&self) -> bool {
+        true
+    }
+}
+
+async fn request(port: u16, method: &str, path: &str) -> reqwest::Response {
+    let method = reqwest::Method::from_bytes(method.as_bytes()).unwrap();
+    reqwest::Client::new()
+        .request(method, format!("http://127.0.0.1:{port}{path}"))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_signatures_are_recorded() {
+    let add = &Calculator.methods()[0];
+    assert_eq!(add.params.len(), 2);
+    assert_eq!((add.params[1].name, add.params[1].ty), ("b", "i32"));
+    assert_eq!(add.returns, "i32");
+    assert_eq!(add.doc, "Add two numbers");
+}
+
+#[tokio::test]
+async fn test_options_describes_methods() {
+    let options = ServerOptions::new(0)
+        .content_types(ContentTypes::json())
+        .quotas(Quotas::new().require_client(true));
+    let server = Calculator.start(options);
+    let port = server.listening().await[0].port();
+
+    let response = request(port, "OPTIONS", "/add").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["allow"], "POST, OPTIONS");
+    let description: Value = response.json().await.unwrap();
+    assert_eq!(
+        description,
+        json!({
+            "name": "add",
+            "doc": "Add two numbers",
+            "kind": "read",
+            "params": [
+                {"name": "a", "type": "i32", "in": "body", "key": null},
+                {"name": "b", "type": "i32", "in": "body", "key": null},
+            ],
+            "returns": "i32",
+            "exclusive": false,
+            "max_concurrency": null,
+            "blocking": false,
+            "streaming": false,
+            "get": false,
+            "deprecated": null,
+            "examples": [{"params": {"a": 1, "b": 2}, "response": 3}],
+            "requires": {"content_type": ["application/json"], "client_header": "x-api-key"},
+        })
+    );
+
+    // Switched off methods stay hidden
+    assert_eq!(request(port, "OPTIONS", "/beta").await.status(), 404);
+
+    // CORS preflights and other paths are answered as before
+    let preflight = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("http://127.0.0.1:{port}/add"),
+        )
+        .header("Origin", "https://app.example.com")
+        .header("Access-Control-Request-Method", "POST")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(preflight.status(), 200);
+    assert_eq!(preflight.content_length(), Some(0));
+    assert_eq!(
+        request(port, "OPTIONS", "/missing").await.content_length(),
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn test_head_answers_like_get() {
+    let server = Calculator.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let get = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/stats"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    let head = reqwest::Client::new()
+        .head(format!("http://127.0.0.1:{port}/__admin/stats"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(head.status(), 200);
+    assert_eq!(
+        head.headers()["content-type"],
+        get.headers()["content-type"]
+    );
+    assert_eq!(
+        head.headers()["content-length"],
+        get.content_length().unwrap().to_string().as_str()
+    );
+    assert!(head.text().await.unwrap().is_empty());
+
+    // Methods are called with POST only
+    let response = request(port, "HEAD", "/add").await;
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["allow"], "POST, OPTIONS");
+}
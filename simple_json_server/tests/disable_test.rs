@@ -0,0 +1,123 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Ledger;
+
+#[actor]
+impl Ledger {
+    /// Record a payment
+    pub async fn pay(&self, cents: u64) -> u64 {
+        cents
+    }
+
+    /// The balance
+    pub async fn balance(&self) -> u64 {
+        42
+    }
+}
+
+async fn call(port: u16, method: &str, body: Value) -> (u16, String) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+async fn admin(port: u16, op: &str, body: Option<Value>) -> (u16, String) {
+    let client = reqwest::Client::new();
+    let url = format!("http://127.0.0.1:{port}/__admin/{op}");
+    let request = match body {
+        Some(body) => client.post(url).json(&body),
+        None => client.get(url),
+    };
+    let response = request.bearer_auth("secret").send().await.unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_admin_api_disables_and_enables_methods() {
+    let server = Ledger.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let (status, body) = admin(
+        port,
+        "disable",
+        Some(json!({"method": "pay", "reason": "double charges, see INC-12"})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        body,
+        json!({"disabled": "pay", "reason": "double charges, see INC-12"})
+    );
+
+    let (status, body) = call(port, "pay", json!({"cents": 100})).await;
+    assert_eq!(status, 503);
+    assert_eq!(
+        body,
+        r#""Method pay is disabled: double charges, see INC-12""#
+    );
+    assert_eq!(
+        call(port, "balance", json!({})).await,
+        (200, "42".to_string())
+    );
+
+    let (_, disabled) = admin(port, "disabled", None).await;
+    assert_eq!(
+        serde_json::from_str::<Value>(&disabled).unwrap(),
+        json!({"pay": "double charges, see INC-12"})
+    );
+
+    let (status, body) = admin(port, "enable", Some(json!({"method": "pay"}))).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        serde_json::from_str::<Value>(&body).unwrap(),
+        json!({"enabled": "pay", "was_disabled": true})
+    );
+    assert_eq!(
+        call(port, "pay", json!({"cents": 100})).await,
+        (200, "100".to_string())
+    );
+    assert_eq!(server.disabled_methods().len(), 0);
+}
+
+#[tokio::test]
+async fn test_disabling_needs_a_known_method_and_post() {
+    let server = Ledger.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let (status, body) = admin(port, "disable", Some(json!({"method": "refund"}))).await;
+    assert_eq!(
+        (status, body.as_str()),
+        (404, r#""Unknown method: refund""#)
+    );
+    let (status, _) = admin(port, "disable", Some(json!({"reason": "no method"}))).await;
+    assert_eq!(status, 400);
+    let (status, _) = admin(port, "disable", None).await;
+    assert_eq!(status, 405);
+
+    // Without a reason, one is filled in
+    admin(port, "disable", Some(json!({"method": "balance"}))).await;
+    let (_, body) = call(port, "balance", json!({})).await;
+    assert_eq!(
+        body,
+        r#""Method balance is disabled: disabled by an operator""#
+    );
+}
+
+#[tokio::test]
+async fn test_handles_disable_methods_from_code() {
+    let server = Ledger.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    server.disable_method("pay", "maintenance");
+    assert_eq!(call(port, "pay", json!({"cents": 1})).await.0, 503);
+    assert!(server.enable_method("pay"));
+    assert!(!server.enable_method("pay"));
+    assert_eq!(call(port, "pay", json!({"cents": 1})).await.0, 200);
+}
@@ -0,0 +1,110 @@
+use simple_json_server::{actor, Actor, RequestContext, ServerOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct Reports {
+    stopped: Arc<AtomicBool>,
+}
+
+#[actor]
+impl Reports {
+    /// Crunch numbers until done or until nobody is waiting
+    #[blocking]
+    pub async fn crunch(&self) -> u32 {
+        let ctx = RequestContext::current().unwrap();
+        for _ in 0..500 {
+            if ctx.is_cancelled() {
+                self.stopped.store(true, Ordering::SeqCst);
+                return 0;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        1
+    }
+
+    /// Start background work that stops when the caller goes away
+    pub async fn export(&self) -> u32 {
+        let ctx = RequestContext::current().unwrap();
+        let stopped = Arc::clone(&self.stopped);
+        tokio::spawn(async move {
+            ctx.cancelled().await;
+            stopped.store(true, Ordering::SeqCst);
+        });
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        1
+    }
+
+    /// Answered at once
+    pub async fn ping(&self) -> u32 {
+        let ctx = RequestContext::current().unwrap();
+        let stopped = Arc::clone(&self.stopped);
+        tokio::spawn(async move {
+            ctx.cancelled().await;
+            stopped.store(true, Ordering::SeqCst);
+        });
+        1
+    }
+}
+
+/// Call `method` and give up on it after 200ms.
+async fn call_and_leave(port: u16, method: &str) {
+    let result = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body("{}")
+        .timeout(Duration::from_millis(200))
+        .send()
+        .await;
+    assert!(result.unwrap_err().is_timeout());
+}
+
+async fn wait_for(stopped: &AtomicBool) -> bool {
+    for _ in 0..100 {
+        if stopped.load(Ordering::SeqCst) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_blocking_methods_see_the_caller_leave() {
+    let actor = Reports::default();
+    let stopped = Arc::clone(&actor.stopped);
+    let server = actor.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    call_and_leave(port, "crunch").await;
+    assert!(wait_for(&stopped).await);
+}
+
+#[tokio::test]
+async fn test_spawned_work_sees_the_caller_leave() {
+    let actor = Reports::default();
+    let stopped = Arc::clone(&actor.stopped);
+    let server = actor.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    call_and_leave(port, "export").await;
+    assert!(wait_for(&stopped).await);
+}
+
+#[tokio::test]
+async fn test_answered_calls_are_not_cancelled() {
+    let actor = Reports::default();
+    let stopped = Arc::clone(&actor.stopped);
+    let server = actor.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/ping"))
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!stopped.load(Ordering::SeqCst));
+}
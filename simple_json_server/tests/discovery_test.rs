@@ -0,0 +1,84 @@
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::sleep;
+
+/// Says which instance answered
+#[derive(Debug, Clone)]
+pub struct Instance {
+    name: String,
+}
+
+#[actor]
+impl Instance {
+    /// The instance's name
+    pub async fn whoami(&self) -> String {
+        self.name.clone()
+    }
+}
+
+/// Start an instance on a free port and return the port.
+async fn instance(name: &str) -> u16 {
+    let handle = Instance {
+        name: name.to_string(),
+    }
+    .start(ServerOptions::new(0));
+    handle.listening().await[0].port()
+}
+
+/// A name server answering every SRV question with one record for `localhost` at the port in
+/// `port`, with a TTL of one second.
+async fn name_server(port: Arc<AtomicU16>) -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buffer = [0; 512];
+        while let Ok((len, client)) = socket.recv_from(&mut buffer).await {
+            let mut response = buffer[..len].to_vec();
+            response[2] = 0x81;
+            response[3] = 0x80;
+            response[7] = 1;
+            // The question's name, compressed; SRV, IN, TTL 1
+            response.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 1]);
+            let mut data = vec![0, 1, 0, 1];
+            data.extend_from_slice(&port.load(Ordering::SeqCst).to_be_bytes());
+            data.extend_from_slice(b"\x09localhost\x00");
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+            let _ = socket.send_to(&response, client).await;
+        }
+    });
+    address.port()
+}
+
+#[tokio::test]
+async fn test_calls_follow_srv_records() {
+    let (first, second) = (instance("first").await, instance("second").await);
+    let port = Arc::new(AtomicU16::new(first));
+    let dns = name_server(Arc::clone(&port)).await;
+
+    let calc = ActorRef::connect(format!("srv://127.0.0.1:{dns}/_calc._tcp.service.consul"))
+        .await
+        .unwrap();
+    let name: String = calc.call("whoami", &serde_json::json!({})).await.unwrap();
+    assert_eq!(name, "first");
+
+    // The instance moves; once the record expires, calls follow it
+    port.store(second, Ordering::SeqCst);
+    sleep(Duration::from_millis(1500)).await;
+    let name: String = calc.call("whoami", &serde_json::json!({})).await.unwrap();
+    assert_eq!(name, "second");
+}
+
+#[tokio::test]
+async fn test_plain_urls_and_bad_services() {
+    let calc = ActorRef::connect("http://127.0.0.1:8080/").await.unwrap();
+    assert_eq!(calc.url(), "http://127.0.0.1:8080");
+    assert!(
+        ActorRef::connect("srv://127.0.0.1:1/_calc._tcp.service.consul")
+            .await
+            .is_err()
+    );
+}
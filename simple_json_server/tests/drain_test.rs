@@ -0,0 +1,85 @@
+use futures_util::StreamExt;
+use simple_json_server::handle::{Drain, Listener};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, Clone)]
+pub struct Worker;
+
+#[actor]
+impl Worker {
+    /// A call that outlasts any reasonable drain
+    pub async fn stuck(&self) {
+        sleep(Duration::from_secs(30)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_deadline_cuts_off_calls_in_progress() {
+    let server = Worker.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let call = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{port}/stuck"))
+            .body("{}")
+            .send(),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    server
+        .drain_with(Drain::new().deadline(Duration::from_millis(200)))
+        .await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(server.is_stopped());
+    assert!(call.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_websocket_clients_are_told_the_server_is_going_away() {
+    let server = Worker.start(ServerOptions::new(0).websocket(true));
+    let port = server.listening().await[0].port();
+    let (mut ws, _) = connect_async(format!("ws://127.0.0.1:{port}"))
+        .await
+        .unwrap();
+    assert_eq!(server.connections_on(Listener::Tcp), 1);
+
+    let draining = tokio::spawn({
+        let server = server.clone();
+        async move { server.drain().await }
+    });
+    match ws.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Away),
+        other => panic!("Expected a close frame, got {:?}", other),
+    }
+    drop(ws);
+    draining.await.unwrap();
+    assert!(server.is_stopped());
+}
+
+#[tokio::test]
+async fn test_drain_through_the_admin_api() {
+    let server = Worker.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/__admin/drain"))
+        .bearer_auth("secret")
+        .body(r#"{"listeners": ["tcp"], "deadline_ms": 1000}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.text().await.unwrap(), r#"{"draining":["tcp"]}"#);
+
+    sleep(Duration::from_millis(200)).await;
+    assert!(server.is_draining());
+    assert_eq!(server.connections_on(Listener::Tcp), 0);
+    assert!(reqwest::get(format!("http://127.0.0.1:{port}/"))
+        .await
+        .is_err());
+}
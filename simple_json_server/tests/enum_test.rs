@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use serde_json::json;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42300);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+/// A paint color
+#[actor]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    DarkRed,
+    Green,
+}
+
+/// A shape to draw
+#[actor]
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Shape {
+    Circle { radius: f64 },
+    Square { side: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Painter;
+
+#[actor]
+impl Painter {
+    /// Paint a shape
+    pub async fn paint(&self, shape: Shape, color: Option<Color>) -> String {
+        format!("{:?} in {:?}", shape, color)
+    }
+}
+
+async fn post(port: u16, params: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/paint"))
+        .json(&params)
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_bad_enum_values_name_the_allowed_values() {
+    let port = get_next_port();
+    Painter.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let shape = json!({"kind": "Circle", "radius": 1.0});
+    let response = post(port, json!({"shape": shape, "color": "dark_red"})).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        "Circle { radius: 1.0 } in Some(DarkRed)"
+    );
+
+    let response = post(port, json!({"shape": shape, "color": "purple"})).await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        r#"Invalid value for parameter `color` of paint: expected one of "dark_red", "green""#
+    );
+
+    let response = post(port, json!({"shape": {"kind": "Triangle"}})).await;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        r#"Invalid value for parameter `shape` of paint: expected an object with "kind" set to one of "Circle", "Square""#
+    );
+
+    // Other bad parameters keep serde's description
+    let response = post(port, json!({"color": "green"})).await;
+    assert_eq!(response.status(), 400);
+    assert!(response
+        .json::<String>()
+        .await
+        .unwrap()
+        .contains("missing field `shape`"));
+}
+
+#[tokio::test]
+async fn test_dispatch_reports_bad_enum_values() {
+    let response = Painter
+        .dispatch(
+            "paint",
+            r#"{"shape": {"kind": "Square", "side": 2}, "color": 7}"#,
+        )
+        .await;
+    assert_eq!(
+        response,
+        r#""Invalid value for parameter `color` of paint: expected one of \"dark_red\", \"green\"""#
+    );
+}
@@ -0,0 +1,230 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use simple_json_server::events::EventBus;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42200);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    events: EventBus,
+}
+
+#[actor]
+#[event(topic = "price", payload = r#"{"symbol": "ACME", "price": 12.5}"#)]
+#[event(topic = "halt", payload = r#"{"symbol": "ACME"}"#)]
+impl Ticker {
+    /// Set a price, notifying subscribers
+    pub async fn set_price(&self, symbol: String, price: f64) {
+        self.events
+            .publish("price", &json!({ "symbol": symbol, "price": price }))
+            .unwrap();
+    }
+
+    /// Halt trading, notifying subscribers
+    pub async fn halt(&self, symbol: String) {
+        self.events
+            .publish("halt", &json!({ "symbol": symbol }))
+            .unwrap();
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(port: u16) -> Socket {
+    sleep(Duration::from_millis(200)).await;
+    connect_async(format!("ws://127.0.0.1:{}", port))
+        .await
+        .expect("Failed to connect")
+        .0
+}
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("Expected a text message, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_subscribers_receive_events_on_their_topics() {
+    let port = get_next_port();
+    let events = EventBus::new();
+    Ticker {
+        events: events.clone(),
+    }
+    .create_with(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone()),
+    );
+    let mut socket = connect(port).await;
+
+    send(&mut socket, json!({"subscribe": "price"})).await;
+    assert_eq!(receive(&mut socket).await, json!({"subscribed": "price"}));
+
+    // Published from a call on the same connection: the response and the event both arrive
+    send(
+        &mut socket,
+        json!({"method": "set_price", "params": {"symbol": "ACME", "price": 13.0}}),
+    )
+    .await;
+    let mut frames = [receive(&mut socket).await, receive(&mut socket).await];
+    frames.sort_by_key(|frame| frame.is_object());
+    assert_eq!(frames[0], Value::Null);
+    assert_eq!(
+        frames[1],
+        json!({"event": "price", "data": {"symbol": "ACME", "price": 13.0}})
+    );
+
+    // Events on other topics are not delivered, events published elsewhere are
+    events.publish("halt", &json!({"symbol": "ACME"})).unwrap();
+    events.publish("price", &json!({"symbol": "XYZ"})).unwrap();
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"event": "price", "data": {"symbol": "XYZ"}})
+    );
+
+    send(&mut socket, json!({"unsubscribe": "price"})).await;
+    assert_eq!(receive(&mut socket).await, json!({"unsubscribed": "price"}));
+    events.publish("price", &json!({"symbol": "ACME"})).unwrap();
+    send(
+        &mut socket,
+        json!({"method": "halt", "params": {"symbol": "ACME"}}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await, Value::Null);
+}
+
+#[tokio::test]
+async fn test_subscribing_without_an_event_bus() {
+    let port = get_next_port();
+    Ticker {
+        events: EventBus::new(),
+    }
+    .create_ws(port);
+    let mut socket = connect(port).await;
+
+    send(&mut socket, json!({"subscribe": "price"})).await;
+    let reply = receive(&mut socket).await;
+    assert!(reply["error"].as_str().unwrap().contains("not enabled"));
+}
+
+#[tokio::test]
+async fn test_unacknowledged_events_are_resent() {
+    let port = get_next_port();
+    let events = EventBus::new();
+    Ticker {
+        events: events.clone(),
+    }
+    .create_with(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone()),
+    );
+    let mut socket = connect(port).await;
+
+    send(
+        &mut socket,
+        json!({"subscribe": "price", "subscriber": "app"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await, json!({"subscribed": "price"}));
+    events.publish("price", &json!({"symbol": "ACME"})).unwrap();
+    let first = receive(&mut socket).await;
+    assert_eq!(first["event"], "price");
+    assert_eq!(first["data"], json!({"symbol": "ACME"}));
+    let first_seq = first["seq"].as_u64().unwrap();
+
+    // Disconnect without acknowledging, and miss an event while away
+    drop(socket);
+    sleep(Duration::from_millis(100)).await;
+    events.publish("price", &json!({"symbol": "XYZ"})).unwrap();
+
+    let mut socket = connect(port).await;
+    send(
+        &mut socket,
+        json!({"subscribe": "price", "subscriber": "app"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await, json!({"subscribed": "price"}));
+    assert_eq!(receive(&mut socket).await, first);
+    let second = receive(&mut socket).await;
+    assert_eq!(second["data"], json!({"symbol": "XYZ"}));
+    assert!(second["seq"].as_u64().unwrap() > first_seq);
+
+    // Acknowledged events are not sent again
+    send(&mut socket, json!({"ack": second["seq"]})).await;
+    drop(socket);
+    sleep(Duration::from_millis(100)).await;
+    let mut socket = connect(port).await;
+    send(
+        &mut socket,
+        json!({"subscribe": "price", "subscriber": "app"}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await, json!({"subscribed": "price"}));
+    send(
+        &mut socket,
+        json!({"method": "halt", "params": {"symbol": "ACME"}}),
+    )
+    .await;
+    assert_eq!(receive(&mut socket).await, Value::Null);
+}
+
+#[tokio::test]
+async fn test_replay_since_a_sequence_number() {
+    let port = get_next_port();
+    let events = EventBus::new().history(10);
+    Ticker {
+        events: events.clone(),
+    }
+    .create_with(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone()),
+    );
+    for symbol in ["A", "B", "C"] {
+        events.publish("price", &json!({"symbol": symbol})).unwrap();
+    }
+    events.publish("halt", &json!({"symbol": "A"})).unwrap();
+    let mut socket = connect(port).await;
+
+    send(&mut socket, json!({"subscribe": "price", "since": 1})).await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"subscribed": "price", "replayed": 2, "complete": true})
+    );
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"event": "price", "seq": 2, "data": {"symbol": "B"}})
+    );
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"event": "price", "seq": 3, "data": {"symbol": "C"}})
+    );
+
+    // New events follow, numbered so the client can resume from them
+    events.publish("price", &json!({"symbol": "D"})).unwrap();
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"event": "price", "seq": 5, "data": {"symbol": "D"}})
+    );
+}
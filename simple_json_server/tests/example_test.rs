@@ -0,0 +1,74 @@
+use serde::Serialize;
+use simple_json_server::{actor, Actor, MethodExample};
+
+#[derive(Debug, Default)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    #[example(params = r#"{"a": 1, "b": 2}"#, response = "3")]
+    #[example(params = r#"{"a": -5, "b": 5}"#, response = "0")]
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Say hello
+    #[example(params = r#"{"name": "Ada"}"#)]
+    pub async fn greet(&self, name: String) -> String {
+        format!("Hello, {}!", name)
+    }
+
+    /// No examples
+    pub async fn reset(&self) {}
+
+    /// The generated wire format test can only check responses of types it can deserialize
+    #[example(params = "{}", response = r#"{"total": 0}"#)]
+    pub async fn summary(&self) -> Summary {
+        Summary { total: 0 }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Summary {
+    total: i32,
+}
+
+#[test]
+fn test_examples_are_reported() {
+    let methods = Calculator.methods();
+    assert_eq!(
+        methods[0].examples,
+        &[
+            MethodExample {
+                params: r#"{"a":1,"b":2}"#,
+                response: Some("3"),
+            },
+            MethodExample {
+                params: r#"{"a":-5,"b":5}"#,
+                response: Some("0"),
+            },
+        ]
+    );
+    assert_eq!(
+        methods[1].examples,
+        &[MethodExample {
+            params: r#"{"name":"Ada"}"#,
+            response: None,
+        }]
+    );
+    assert!(methods[2].examples.is_empty());
+}
+
+#[tokio::test]
+async fn test_examples_match_the_methods() {
+    let actor = Calculator;
+    for method in actor.methods() {
+        for example in method.examples {
+            let result = actor.dispatch(method.name, example.params).await;
+            if let Some(response) = example.response {
+                assert_eq!(result, response, "example of {}", method.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use simple_json_server::fallback::{Fallback, FallbackResponse};
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43900);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Legacy;
+
+#[actor]
+impl Legacy {
+    /// Multiply two numbers
+    pub async fn multiply(&self, a: i32, b: i32) -> i32 {
+        a * b
+    }
+}
+
+/// Answers unknown methods with an application-specific error shape.
+struct NotFound;
+
+impl Fallback for NotFound {
+    fn call<'a>(&'a self, method: &'a str, _params: &'a str) -> BoxFuture<'a, FallbackResponse> {
+        let error = serde_json::json!({"error": "not_found", "method": method});
+        async move { FallbackResponse::json(404, &error) }.boxed()
+    }
+}
+
+async fn post(port: u16, method: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_unknown_methods_are_proxied() {
+    let port = get_next_port();
+    let legacy = ActorRef::loopback(Legacy, ServerOptions::default());
+    Calculator.create_with(ServerOptions::new(port).fallback(legacy));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "add", r#"{"a": 2, "b": 3}"#).await;
+    assert_eq!(response.text().await.unwrap(), "5");
+    let response = post(port, "multiply", r#"{"a": 2, "b": 3}"#).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "6");
+
+    // Neither actor knows this one, so the downstream actor's answer comes back
+    let response = post(port, "divide", r#"{"a": 2, "b": 3}"#).await;
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#""Unknown method: divide""#
+    );
+}
+
+#[tokio::test]
+async fn test_custom_not_found_and_method_not_allowed() {
+    let port = get_next_port();
+    let options = ServerOptions::new(port)
+        .fallback(NotFound)
+        .method_not_allowed(FallbackResponse::json(
+            405,
+            &serde_json::json!({"error": "method_not_allowed"}),
+        ));
+    Calculator.create_with(options);
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "divide", "{}").await;
+    assert_eq!(response.status(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"error": "not_found", "method": "divide"})
+    );
+
+    let response = reqwest::get(format!("http://127.0.0.1:{port}/add"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["Allow"], "POST, OPTIONS");
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"error":"method_not_allowed"}"#
+    );
+}
+
+#[tokio::test]
+async fn test_default_responses() {
+    let port = get_next_port();
+    Calculator.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "divide", "{}").await;
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#""Unknown method: divide""#
+    );
+    let response = reqwest::get(format!("http://127.0.0.1:{port}/add"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.text().await.unwrap(), "Method Not Allowed");
+}
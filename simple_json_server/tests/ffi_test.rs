@@ -0,0 +1,129 @@
+#![cfg(feature = "ffi")]
+
+use simple_json_server::{actor, export_actor, ffi, Actor};
+use std::ffi::{c_char, CStr, CString};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    count: AtomicU64,
+    ready: bool,
+}
+
+#[actor]
+impl Counter {
+    async fn init(&mut self) -> Result<(), String> {
+        self.ready = true;
+        Ok(())
+    }
+
+    async fn on_shutdown(&self) {
+        SHUT_DOWN.store(true, Ordering::SeqCst);
+    }
+
+    /// Add `by` to the count
+    pub async fn add(&self, by: u64) -> u64 {
+        self.count.fetch_add(by, Ordering::SeqCst) + by
+    }
+
+    /// Whether `init` ran
+    #[read]
+    pub async fn ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Spawns a task on the actor's runtime and waits for it
+    #[read]
+    pub async fn spawned(&self) -> u64 {
+        tokio::spawn(async { 7 }).await.unwrap()
+    }
+
+    #[read]
+    pub async fn explode(&self) -> u64 {
+        panic!("boom")
+    }
+}
+
+export_actor!(Counter, Counter::default());
+
+#[derive(Debug, Default)]
+pub struct Broken;
+
+#[actor]
+impl Broken {
+    async fn init(&mut self) -> Result<(), String> {
+        Err("no database".to_string())
+    }
+
+    pub async fn ping(&self) -> bool {
+        true
+    }
+}
+
+/// Call `method` through the exported functions and take the result.
+fn call(actor: *mut ffi::FfiActor<Counter>, method: &str, json: Option<&str>) -> Option<String> {
+    let method = CString::new(method).unwrap();
+    let json = json.map(|json| CString::new(json).unwrap());
+    let json_ptr = json.as_ref().map_or(std::ptr::null(), |json| json.as_ptr());
+    unsafe {
+        let result: *mut c_char = actor_dispatch(actor, method.as_ptr(), json_ptr);
+        if result.is_null() {
+            return None;
+        }
+        let text = CStr::from_ptr(result).to_str().unwrap().to_string();
+        actor_free_string(result);
+        Some(text)
+    }
+}
+
+#[test]
+fn test_exported_functions_call_the_actor() {
+    let actor = actor_create();
+    assert!(!actor.is_null());
+
+    assert_eq!(call(actor, "ready", None).as_deref(), Some("true"));
+    assert_eq!(
+        call(actor, "add", Some(r#"{"by": 2}"#)).as_deref(),
+        Some("2")
+    );
+    assert_eq!(call(actor, "spawned", Some("{}")).as_deref(), Some("7"));
+    assert!(call(actor, "missing", Some("{}"))
+        .unwrap()
+        .contains("Unknown method: missing"));
+    assert!(call(actor, "add", Some(r#"{"by": "two"}"#))
+        .unwrap()
+        .contains("Failed to deserialize parameters for add"));
+    assert_eq!(call(actor, "explode", None), None);
+    assert_eq!(call(std::ptr::null_mut(), "add", None), None);
+
+    // Calls from several threads at once
+    let shared = actor as usize;
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            std::thread::spawn(move || {
+                let actor = shared as *mut ffi::FfiActor<Counter>;
+                for _ in 0..10 {
+                    call(actor, "add", Some(r#"{"by": 1}"#)).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(
+        call(actor, "add", Some(r#"{"by": 0}"#)).as_deref(),
+        Some("42")
+    );
+
+    unsafe { actor_destroy(actor) };
+    assert!(SHUT_DOWN.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_failed_creation_returns_null() {
+    assert!(ffi::create(|| Broken).is_null());
+    assert!(ffi::create::<Broken>(|| panic!("no config")).is_null());
+}
@@ -0,0 +1,95 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Profiles;
+
+#[actor]
+impl Profiles {
+    /// A user's profile
+    #[read]
+    #[get]
+    pub async fn profile(&self, id: u32) -> Value {
+        json!({
+            "id": id,
+            "name": "Ada",
+            "address": {"city": "London", "street": "St James's Square"},
+            "orders": [{"sku": "A1", "qty": 2}, {"sku": "B2", "qty": 1}],
+        })
+    }
+
+    /// Rename a user, returning the new profile
+    pub async fn rename(&self, name: String) -> Value {
+        json!({"name": name, "renamed": true})
+    }
+
+    /// A method with a parameter of its own named `fields`
+    #[read]
+    #[get]
+    pub async fn echo(&self, fields: String) -> Value {
+        json!({"fields": fields, "other": 1})
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> (u16, Value) {
+    let response = request.send().await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_read_methods_return_the_fields_asked_for() {
+    let server = Profiles.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let client = reqwest::Client::new();
+    let url = |path: &str| format!("http://127.0.0.1:{port}{path}");
+
+    let expected = json!({"name": "Ada", "address": {"city": "London"}, "orders": [{"sku": "A1"}, {"sku": "B2"}]});
+    assert_eq!(
+        send(client.get(url("/profile?id=7&fields=name,address.city,orders.sku"))).await,
+        (200, expected.clone())
+    );
+    assert_eq!(
+        send(
+            client
+                .post(url("/profile?fields=name%2Caddress.city,orders.sku"))
+                .json(&json!({"id": 7}))
+        )
+        .await,
+        (200, expected)
+    );
+
+    // Without `fields` the whole result is sent
+    let (status, profile) = send(client.get(url("/profile?id=7"))).await;
+    assert_eq!(
+        (status, profile["address"]["street"].is_string()),
+        (200, true)
+    );
+
+    let (status, _) = send(client.get(url("/profile?id=7&fields=address..city"))).await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn test_other_methods_are_not_projected() {
+    let server = Profiles.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let client = reqwest::Client::new();
+    let url = |path: &str| format!("http://127.0.0.1:{port}{path}");
+
+    // Write methods return their whole result
+    assert_eq!(
+        send(
+            client
+                .post(url("/rename?fields=name"))
+                .json(&json!({"name": "Ada"}))
+        )
+        .await,
+        (200, json!({"name": "Ada", "renamed": true}))
+    );
+
+    // A method's own `fields` parameter is left to it
+    assert_eq!(
+        send(client.get(url("/echo?fields=name"))).await,
+        (200, json!({"fields": "name", "other": 1}))
+    );
+}
@@ -0,0 +1,90 @@
+use simple_json_server::flags::{FeatureFlags, StaticFlags};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42500);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Reports;
+
+#[actor]
+impl Reports {
+    /// Always available
+    pub async fn daily(&self) -> String {
+        "daily".to_string()
+    }
+
+    /// Still in beta
+    #[flag("beta_reports")]
+    pub async fn quarterly(&self) -> String {
+        "quarterly".to_string()
+    }
+}
+
+async fn post(port: u16, method: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body("{}")
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_flagged_methods_follow_their_flag() {
+    let port = get_next_port();
+    let flags = StaticFlags::new();
+    Reports.create_with(ServerOptions::new(port).feature_flags(FeatureFlags::new(flags.clone())));
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(post(port, "daily").await.status(), 200);
+    let response = post(port, "quarterly").await;
+    assert_eq!(response.status(), 404);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        "Unknown method: quarterly"
+    );
+
+    flags.enable("beta_reports");
+    let response = post(port, "quarterly").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.json::<String>().await.unwrap(), "quarterly");
+
+    flags.disable("beta_reports");
+    assert_eq!(post(port, "quarterly").await.status(), 404);
+}
+
+#[tokio::test]
+async fn test_disabled_methods_can_be_forbidden() {
+    let port = get_next_port();
+    Reports.create_with(
+        ServerOptions::new(port).feature_flags(FeatureFlags::new(StaticFlags::new()).forbid()),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "quarterly").await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        "Method quarterly is not enabled"
+    );
+
+    // Without feature flags configured, flagged methods stay dark
+    let port = get_next_port();
+    Reports.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(post(port, "quarterly").await.status(), 404);
+    assert_eq!(post(port, "daily").await.status(), 200);
+}
+
+#[test]
+fn test_flags_are_listed_in_method_info() {
+    let flags: Vec<_> = Reports.methods().iter().map(|info| info.flag).collect();
+    assert_eq!(flags, vec![None, Some("beta_reports")]);
+}
@@ -0,0 +1,115 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, Forward, RequestContext};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41200);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Backend {
+    name: String,
+}
+
+#[actor]
+impl Backend {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Report which backend served the call
+    pub async fn whoami(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Router {
+    even: ActorRef,
+    odd: ActorRef,
+}
+
+#[actor]
+impl Router {
+    /// Route additions to a backend by the parity of their sum
+    pub async fn add(&self, a: i32, b: i32) -> Forward {
+        let target = if (a + b) % 2 == 0 {
+            &self.even
+        } else {
+            &self.odd
+        };
+        RequestContext::current().unwrap().forward(target)
+    }
+
+    /// Always routes to the odd backend
+    pub async fn whoami(&self) -> Forward {
+        RequestContext::current().unwrap().forward(&self.odd)
+    }
+
+    /// Served by the router itself
+    pub async fn ping(&self) -> String {
+        "pong".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_router_forwards_calls() {
+    let even_port = get_next_port();
+    let odd_port = get_next_port();
+    let router_port = get_next_port();
+
+    Backend {
+        name: "even".to_string(),
+    }
+    .create(even_port);
+    Backend {
+        name: "odd".to_string(),
+    }
+    .create(odd_port);
+    Router {
+        even: ActorRef::new(format!("http://127.0.0.1:{even_port}")),
+        odd: ActorRef::new(format!("http://127.0.0.1:{odd_port}")),
+    }
+    .create(router_port);
+    sleep(Duration::from_millis(100)).await;
+
+    let router = ActorRef::new(format!("http://127.0.0.1:{router_port}"));
+    let sum: i32 = router
+        .call("add", &json!({"a": 2, "b": 3}))
+        .await
+        .expect("Failed to call add");
+    assert_eq!(sum, 5);
+
+    let name: String = router.call("whoami", &json!({})).await.unwrap();
+    assert_eq!(name, "odd");
+
+    let pong: String = router.call("ping", &json!({})).await.unwrap();
+    assert_eq!(pong, "pong");
+}
+
+#[tokio::test]
+async fn test_forward_to_unreachable_actor() {
+    let router_port = get_next_port();
+    let dead = format!("http://127.0.0.1:{}", get_next_port());
+
+    Router {
+        even: ActorRef::new(dead.clone()),
+        odd: ActorRef::new(dead),
+    }
+    .create(router_port);
+    sleep(Duration::from_millis(100)).await;
+
+    let router = ActorRef::new(format!("http://127.0.0.1:{router_port}"));
+    match router.call_raw("whoami", "{}".to_string()).await {
+        Err(ClientError::Status(status, body)) => {
+            assert_eq!(status, 502);
+            assert!(body.contains("Failed to forward whoami"));
+        }
+        other => panic!("Expected a 502, got {:?}", other),
+    }
+}
@@ -0,0 +1,112 @@
+use serde_json::json;
+use simple_json_server::gateway::{GatewayActor, GatewayConfig, GatewayHealth, Route};
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(44000);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Billing;
+
+#[actor]
+impl Billing {
+    /// Charge a customer
+    pub async fn charge(&self, cents: u32) -> Result<u32, String> {
+        if cents == 0 {
+            Err("Nothing to charge".to_string())
+        } else {
+            Ok(cents)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+#[tokio::test]
+async fn test_routes_and_rewrites() {
+    let billing = ActorRef::loopback(Billing, ServerOptions::default());
+    let calculator = ActorRef::loopback(Calculator, ServerOptions::default());
+    let gateway = GatewayActor::new()
+        .route(Route::new("billing_*", billing).rewrite("*"))
+        .route(Route::new("add", calculator));
+    let front = ActorRef::loopback(gateway.clone(), ServerOptions::default());
+
+    let charged: serde_json::Value = front
+        .call("billing_charge", &json!({"cents": 250}))
+        .await
+        .unwrap();
+    assert_eq!(charged, json!({"Ok": 250}));
+    let sum: i32 = front.call("add", &json!({"a": 2, "b": 3})).await.unwrap();
+    assert_eq!(sum, 5);
+    let unknown: String = front.call("subtract", &json!({})).await.unwrap();
+    assert_eq!(unknown, "Unknown method: subtract");
+
+    let health = gateway.health();
+    assert!(health.healthy);
+    assert_eq!(health.routes[0].calls, 1);
+    assert_eq!(health.routes[1].calls, 1);
+}
+
+#[tokio::test]
+async fn test_retries_and_health() {
+    let gateway = GatewayActor::new()
+        .route(Route::new("*", ActorRef::new("http://127.0.0.1:1")))
+        .retries(2)
+        .retry_delay(Duration::from_millis(10))
+        .health_method("health");
+    let front = ActorRef::loopback(gateway.clone(), ServerOptions::default());
+
+    let result = front.call_raw("add", "{}".to_string()).await;
+    assert!(
+        matches!(&result, Err(ClientError::Status(502, body)) if body.contains("Failed to forward")),
+        "{:?}",
+        result
+    );
+    let route = &gateway.health().routes[0];
+    assert_eq!((route.calls, route.failures), (3, 3));
+
+    let Err(ClientError::Status(503, body)) = front.call_raw("health", "{}".to_string()).await
+    else {
+        panic!("An unreachable route should make the gateway unhealthy");
+    };
+    let health: GatewayHealth = serde_json::from_str(&body).unwrap();
+    assert!(!health.healthy);
+    assert!(health.routes[0].last_error.is_some());
+}
+
+#[tokio::test]
+async fn test_gateway_from_config() {
+    let (billing_port, gateway_port) = (get_next_port(), get_next_port());
+    Billing.create_with(ServerOptions::new(billing_port));
+    let config: GatewayConfig = serde_json::from_value(json!({
+        "routes": [
+            {"methods": "pay", "url": format!("http://127.0.0.1:{billing_port}"), "rewrite": "charge"}
+        ],
+        "health_method": "health"
+    }))
+    .unwrap();
+    GatewayActor::from_config(config).create_with(ServerOptions::new(gateway_port));
+    sleep(Duration::from_millis(100)).await;
+
+    let front = ActorRef::new(format!("http://127.0.0.1:{gateway_port}"));
+    let charged: serde_json::Value = front.call("pay", &json!({"cents": 0})).await.unwrap();
+    assert_eq!(charged, json!({"Err": "Nothing to charge"}));
+    let health: GatewayHealth = front.call("health", &json!({})).await.unwrap();
+    assert!(health.healthy);
+    assert_eq!(health.routes[0].calls, 1);
+}
@@ -0,0 +1,98 @@
+use serde_json::json;
+use simple_json_server::hooks::ActorError;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Inventory {
+    /// How many of `sku` are in stock
+    pub async fn count(&self, sku: String) -> u32 {
+        self.log.lock().unwrap().push(format!("count {sku}"));
+        3
+    }
+
+    fn before_dispatch(&self, method: &str, params: &str) -> Result<(), ActorError> {
+        if params.contains("locked") {
+            return Err(ActorError::forbidden(format!("{method} is locked")));
+        }
+        self.log.lock().unwrap().push(format!("before {method}"));
+        Ok(())
+    }
+
+    async fn after_dispatch(&self, method: &str, result: &str) {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("after {method} {result}"));
+    }
+}
+
+/// Only refuses calls
+#[derive(Debug, Clone)]
+pub struct Closed;
+
+#[actor]
+impl Closed {
+    /// Never served
+    pub async fn ping(&self) -> bool {
+        true
+    }
+
+    async fn before_dispatch(&self, _method: &str, _params: &str) -> Result<(), ActorError> {
+        Err(ActorError::new(503, "closed for maintenance"))
+    }
+}
+
+#[tokio::test]
+async fn test_hooks_run_around_calls() {
+    let actor = Inventory::default();
+    let log = Arc::clone(&actor.log);
+
+    assert_eq!(actor.dispatch("count", r#"{"sku": "a1"}"#).await, "3");
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["before count", "count a1", "after count 3"]
+    );
+
+    // Refused calls don't reach the method or the after hook
+    log.lock().unwrap().clear();
+    let response = actor.dispatch("count", r#"{"sku": "locked"}"#).await;
+    assert_eq!(response, r#""count is locked""#);
+    assert!(log.lock().unwrap().is_empty());
+
+    // Nor do calls of unknown methods reach the hooks
+    actor.dispatch("missing", "{}").await;
+    assert!(log.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_refused_calls_get_the_status() {
+    let server = Inventory::default().start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/count"))
+        .json(&json!({"sku": "locked"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap(),
+        "count is locked"
+    );
+
+    let server = Closed.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/ping"))
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "http3")]
+
+use hyper::body::{Buf, Bytes};
+use hyper::{Request, StatusCode};
+use simple_json_server::{actor, Actor, ServerOptions, TlsConfig};
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42900);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+/// Write a self-signed certificate for `localhost`, returning its TLS config and DER encoding.
+fn certificate(name: &str) -> (TlsConfig, Vec<u8>) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_path = format!("/tmp/http3_test_{name}_cert.pem");
+    let key_path = format!("/tmp/http3_test_{name}_key.pem");
+    fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    (
+        TlsConfig::new(cert_path, key_path),
+        cert.serialize_der().unwrap(),
+    )
+}
+
+/// POST `body` to `path` over HTTP/3, returning the status and response body.
+async fn post_h3(port: u16, cert: Vec<u8>, path: &str, body: &str) -> (StatusCode, String) {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.into()).unwrap();
+    let mut tls = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls).unwrap();
+
+    let mut endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+    let addr: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+    let connection = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+
+    let (mut driver, mut sender) = h3::client::new(h3_quinn::Connection::new(connection))
+        .await
+        .unwrap();
+    tokio::spawn(async move { driver.wait_idle().await });
+
+    let request = Request::post(format!("https://localhost:{port}{path}"))
+        .body(())
+        .unwrap();
+    let mut stream = sender.send_request(request).await.unwrap();
+    stream
+        .send_data(Bytes::from(body.to_string()))
+        .await
+        .unwrap();
+    stream.finish().await.unwrap();
+
+    let response = stream.recv_response().await.unwrap();
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    (response.status(), String::from_utf8(body).unwrap())
+}
+
+#[tokio::test]
+async fn test_http3_calls() {
+    let port = get_next_port();
+    let (tls, cert) = certificate("calls");
+    Calculator.create_with(ServerOptions::new(port).tls(tls).http3(true));
+    sleep(Duration::from_millis(100)).await;
+
+    let (status, body) = post_h3(port, cert.clone(), "/add", r#"{"a": 2, "b": 3}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "5");
+
+    // Errors are reported exactly as over HTTPS
+    let (status, _) = post_h3(port, cert, "/add", r#"{"a": "two"}"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_https_advertises_http3() {
+    let port = get_next_port();
+    let (tls, _) = certificate("alt_svc");
+    Calculator.create_with(ServerOptions::new(port).tls(tls).http3(true));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .post(format!("https://127.0.0.1:{port}/add"))
+        .body(r#"{"a": 2, "b": 3}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["Alt-Svc"],
+        format!("h3=\":{port}\"; ma=86400").as_str()
+    );
+}
@@ -0,0 +1,92 @@
+use serde_json::{json, Value};
+use simple_json_server::ingest::DurableIngest;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// A webhook receiver whose deliveries wait for a permit.
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    permits: Arc<Semaphore>,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Hooks {
+    /// Record a delivery
+    #[write]
+    pub async fn deliver(&self, id: String) {
+        self.permits.acquire().await.unwrap().forget();
+        self.received.lock().unwrap().push(id);
+    }
+
+    /// The deliveries received so far
+    #[read]
+    pub async fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn post(port: u16, method: &str, params: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .json(&params)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_accepted_calls_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("ingest_test_{}.wal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let received = Arc::new(Mutex::new(Vec::new()));
+
+    // Deliveries are accepted, though none can finish
+    let hooks = Hooks {
+        permits: Arc::new(Semaphore::new(0)),
+        received: Arc::clone(&received),
+    };
+    let ingest = DurableIngest::open(&path).unwrap();
+    let server = hooks.start(ServerOptions::new(0).durable_ingest(ingest.clone()));
+    let port = server.listening().await[0].port();
+    for (id, seq) in [("a", 1), ("b", 2), ("c", 3)] {
+        assert_eq!(
+            post(port, "deliver", json!({ "id": id })).await,
+            (202, json!({ "accepted": seq }))
+        );
+    }
+    assert_eq!(ingest.pending(), 3);
+    server.drain().await;
+    server.stopped().await;
+
+    // A new server runs them in order
+    let hooks = Hooks {
+        permits: Arc::new(Semaphore::new(10)),
+        received: Arc::clone(&received),
+    };
+    let ingest = DurableIngest::open(&path).unwrap();
+    assert_eq!(ingest.pending(), 3);
+    let server = hooks.start(ServerOptions::new(0).durable_ingest(ingest.clone()));
+    let port = server.listening().await[0].port();
+    assert_eq!(
+        post(port, "deliver", json!({ "id": "d" })).await,
+        (202, json!({ "accepted": 4 }))
+    );
+    for _ in 0..100 {
+        if ingest.pending() == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Reads are answered as usual
+    assert_eq!(
+        post(port, "received", json!({})).await,
+        (200, json!(["a", "b", "c", "d"]))
+    );
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    server.drain().await;
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,74 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42700);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Greeter;
+
+#[actor]
+impl Greeter {
+    /// Say hello
+    pub async fn greet(&self, name: String) -> String {
+        format!("Hello, {}!", name)
+    }
+}
+
+fn ipv6_available() -> bool {
+    std::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).is_ok()
+}
+
+async fn greet(url: String) -> Result<String, simple_json_server::ClientError> {
+    ActorRef::new(url)
+        .call("greet", &json!({"name": "World"}))
+        .await
+}
+
+#[tokio::test]
+async fn test_dual_stack_accepts_ipv4_and_ipv6() {
+    let port = get_next_port();
+    Greeter.create_with(ServerOptions::new(port).host(Ipv6Addr::UNSPECIFIED));
+    sleep(Duration::from_millis(100)).await;
+
+    // Where IPv6 is unavailable the server falls back to IPv4, so this always works
+    let hello = greet(format!("http://127.0.0.1:{port}")).await.unwrap();
+    assert_eq!(hello, "Hello, World!");
+    if ipv6_available() {
+        let hello = greet(format!("http://[::1]:{port}")).await.unwrap();
+        assert_eq!(hello, "Hello, World!");
+    }
+}
+
+#[tokio::test]
+async fn test_ipv6_only() {
+    if !ipv6_available() {
+        return;
+    }
+    let port = get_next_port();
+    Greeter.create_with(
+        ServerOptions::new(port)
+            .host(Ipv6Addr::UNSPECIFIED)
+            .ipv6_only(true),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    assert!(greet(format!("http://[::1]:{port}")).await.is_ok());
+    assert!(greet(format!("http://127.0.0.1:{port}")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_specific_ipv4_host() {
+    let port = get_next_port();
+    Greeter.create_with(ServerOptions::new(port).host(Ipv4Addr::LOCALHOST));
+    sleep(Duration::from_millis(100)).await;
+
+    assert!(greet(format!("http://127.0.0.1:{port}")).await.is_ok());
+}
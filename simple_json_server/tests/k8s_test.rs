@@ -0,0 +1,86 @@
+use simple_json_server::k8s::Kubernetes;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone)]
+pub struct Ledger;
+
+#[actor]
+impl Ledger {
+    /// Holds the actor to itself for a while, like a restore
+    #[write]
+    pub async fn compact(&self) {
+        sleep(Duration::from_millis(500)).await;
+    }
+}
+
+async fn probe(port: u16, path: &str) -> u16 {
+    reqwest::get(format!("http://127.0.0.1:{port}{path}"))
+        .await
+        .expect("Failed to probe server")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_readiness_follows_the_mailbox() {
+    let k8s = Kubernetes::new()
+        .pod("ledger-0")
+        .mailbox_wait(Duration::from_millis(100));
+    let server = Ledger.start(ServerOptions::new(0).kubernetes(k8s).admin_token("secret"));
+    let port = server.listening().await[0].port();
+    assert_eq!(probe(port, "/healthz").await, 200);
+    assert_eq!(probe(port, "/readyz").await, 200);
+
+    let compacting = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{port}/compact"))
+            .body("{}")
+            .send(),
+    );
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(probe(port, "/readyz").await, 503);
+    assert_eq!(probe(port, "/healthz").await, 200);
+    compacting.await.unwrap().unwrap();
+    assert_eq!(probe(port, "/readyz").await, 200);
+
+    // Metrics carry the pod's name
+    let metrics = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/metrics"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(
+        metrics.contains(r#"simple_json_server_calls_total{method="compact",pod="ledger-0"} 1"#)
+    );
+}
+
+#[tokio::test]
+async fn test_termination_fails_readiness_then_drains() {
+    let k8s = Kubernetes::new()
+        .pre_stop_delay(Duration::from_secs(1))
+        .grace_period(Duration::from_secs(5));
+    let server = Ledger.start(ServerOptions::new(0).kubernetes(k8s.clone()));
+    let port = server.listening().await[0].port();
+
+    let terminating = tokio::spawn({
+        let (k8s, server) = (k8s.clone(), server.clone());
+        async move { k8s.terminate(&server).await }
+    });
+    sleep(Duration::from_millis(100)).await;
+    // Still serving during the pre-stop delay, but no longer ready
+    assert!(k8s.is_terminating());
+    assert_eq!(probe(port, "/readyz").await, 503);
+    assert_eq!(probe(port, "/healthz").await, 200);
+
+    terminating.await.unwrap();
+    assert!(server.is_draining());
+    assert!(reqwest::get(format!("http://127.0.0.1:{port}/healthz"))
+        .await
+        .is_err());
+}
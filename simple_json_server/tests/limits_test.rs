@@ -0,0 +1,78 @@
+use serde_json::json;
+use simple_json_server::limits::JsonLimits;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Tags;
+
+#[actor]
+impl Tags {
+    /// How many tags there are
+    pub async fn count(&self, tags: Vec<String>) -> usize {
+        tags.len()
+    }
+
+    /// Accepts anything
+    pub async fn store(&self, document: serde_json::Value) -> bool {
+        document.is_object()
+    }
+}
+
+async fn rejection(tags: &ActorRef, method: &str, params: String) -> String {
+    match tags.call_raw(method, params).await {
+        Err(ClientError::Status(400, body)) => serde_json::from_str(&body).unwrap(),
+        other => panic!("expected 400 Bad Request, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_limits_reject_calls_with_400() {
+    let limits = JsonLimits::new()
+        .max_depth(4)
+        .max_string_len(8)
+        .max_array_len(3);
+    let tags = ActorRef::loopback(Tags, ServerOptions::default().json_limits(limits));
+
+    let count: usize = tags
+        .call("count", &json!({"tags": ["a", "b", "c"]}))
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+
+    let params = json!({"tags": ["a", "b", "c", "d"]}).to_string();
+    assert_eq!(
+        rejection(&tags, "count", params).await,
+        "An array in the parameters has more than 3 elements"
+    );
+    let params = json!({"tags": ["a very long tag"]}).to_string();
+    assert_eq!(
+        rejection(&tags, "count", params).await,
+        "A string in the parameters is longer than 8 bytes"
+    );
+    let params = json!({"document": {"a": {"b": {"c": {}}}}}).to_string();
+    assert_eq!(
+        rejection(&tags, "store", params).await,
+        "Parameters are nested more than 4 levels deep"
+    );
+}
+
+#[tokio::test]
+async fn test_deep_nesting_is_rejected_by_default() {
+    let tags = ActorRef::loopback(Tags, ServerOptions::default());
+    let nested = format!(
+        r#"{{"document": {}1{}}}"#,
+        "[".repeat(100_000),
+        "]".repeat(100_000)
+    );
+    assert_eq!(
+        rejection(&tags, "store", nested).await,
+        "Parameters are nested more than 64 levels deep"
+    );
+
+    let document = json!({"tags": vec!["x"; 1000]});
+    let stored: bool = tags
+        .call("store", &json!({ "document": document }))
+        .await
+        .unwrap();
+    assert!(stored);
+}
@@ -0,0 +1,45 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    count: AtomicI32,
+}
+
+#[actor]
+impl Counter {
+    /// Add to the count
+    pub async fn add(&self, amount: i32) -> i32 {
+        self.count.fetch_add(amount, Ordering::SeqCst) + amount
+    }
+
+    /// The current count
+    #[read]
+    pub async fn get(&self) -> i32 {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn test_loopback_calls_the_actor() {
+    let counter = ActorRef::loopback(Counter::default(), ServerOptions::default());
+    let count: i32 = counter.call("add", &json!({"amount": 2})).await.unwrap();
+    assert_eq!(count, 2);
+    let count: i32 = counter.call("get", &json!({})).await.unwrap();
+    assert_eq!(count, 2);
+
+    // Errors are reported as over HTTP
+    let result = counter
+        .call::<_, i32>("add", &json!({"amount": "two"}))
+        .await;
+    assert!(matches!(result, Err(ClientError::Status(400, _))));
+}
+
+#[tokio::test]
+async fn test_loopback_applies_options() {
+    let counter = ActorRef::loopback(Counter::default(), ServerOptions::default().read_only(true));
+    let result = counter.call::<_, i32>("add", &json!({"amount": 1})).await;
+    assert!(matches!(result, Err(ClientError::Status(_, _))));
+    assert!(counter.call::<_, i32>("get", &json!({})).await.is_ok());
+}
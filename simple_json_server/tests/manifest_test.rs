@@ -0,0 +1,326 @@
+#![cfg(feature = "manifest")]
+
+use serde_json::json;
+use simple_json_server::manifest::{serve_manifest, Actors, Manifest, ManifestError};
+use simple_json_server::{actor, Actor};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    #[read]
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Inventory;
+
+#[actor]
+impl Inventory {
+    /// Count an item in stock
+    #[read]
+    pub async fn count(&self, sku: String) -> u32 {
+        sku.len() as u32
+    }
+}
+
+fn temp_file(name: &str, text: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("manifest_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+async fn post(port: u16, path: &str, body: serde_json::Value) -> (u16, String) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{path}"))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+fn actors() -> Actors {
+    Actors::new()
+        .actor("calculator", Calculator)
+        .actor("inventory", Inventory)
+}
+
+#[tokio::test]
+async fn test_serves_every_server_of_a_toml_manifest() {
+    let path = temp_file(
+        "servers.toml",
+        r#"
+        [servers.calculator]
+        port = 0
+        host = "127.0.0.1"
+
+        [servers.inventory]
+        port = 0
+        auth = { client_header = "X-Api-Key" }
+        "#,
+    );
+    let servers = serve_manifest(&path, actors()).unwrap();
+    let names: Vec<_> = servers.iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["calculator", "inventory"]);
+
+    let calculator = servers.get("calculator").unwrap().listening().await[0].port();
+    let (status, sum) = post(calculator, "add", json!({"a": 1, "b": 2})).await;
+    assert_eq!((status, sum.as_str()), (200, "3"));
+
+    // The inventory requires callers to identify themselves
+    let inventory = servers.get("inventory").unwrap().listening().await[0].port();
+    let (status, _) = post(inventory, "count", json!({"sku": "a1"})).await;
+    assert_eq!(status, 401);
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{inventory}/count"))
+        .header("X-Api-Key", "client-1")
+        .json(&json!({"sku": "a1"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "2");
+
+    servers.drain().await;
+    assert!(servers.iter().all(|(_, server)| server.is_stopped()));
+}
+
+#[tokio::test]
+async fn test_yaml_manifests_and_code_options() {
+    std::env::set_var("MANIFEST_TEST_ADMIN_TOKEN", "secret");
+    let manifest = Manifest::from_yaml(
+        r#"
+        servers:
+          calculator:
+            port: 0
+            auth:
+              admin_token_env: MANIFEST_TEST_ADMIN_TOKEN
+          inventory:
+            port: 0
+            read_only: true
+        "#,
+    )
+    .unwrap();
+    let actors = Actors::new().actor("calculator", Calculator).actor_with(
+        "inventory",
+        Inventory,
+        |options| options.max_batch(0),
+    );
+    let servers = manifest.serve(actors).unwrap();
+
+    let calculator = servers.get("calculator").unwrap().listening().await[0].port();
+    let response = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{calculator}/__admin/stats"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let inventory = servers.get("inventory").unwrap().listening().await[0].port();
+    let (_, answer) = post(inventory, "__batch", json!([])).await;
+    assert_eq!(answer, r#""Unknown method: __batch""#);
+}
+
+#[test]
+fn test_invalid_manifests_are_refused() {
+    let parse = |text: &str| Manifest::from_toml(text);
+    assert!(matches!(
+        parse("[servers.calculator]\nport = 0\nwebsockets = true"),
+        Err(ManifestError::Parse(_))
+    ));
+
+    // http3 needs TLS
+    let manifest = parse("[servers.calculator]\nport = 0\nhttp3 = true").unwrap();
+    assert!(matches!(
+        manifest.options("calculator"),
+        Err(ManifestError::Invalid(_))
+    ));
+
+    let manifest = parse(
+        "[servers.calculator]\nport = 0\nauth = { admin_token_env = \"MANIFEST_TEST_UNSET\" }",
+    )
+    .unwrap();
+    assert!(matches!(
+        manifest.options("calculator"),
+        Err(ManifestError::Invalid(_))
+    ));
+
+    assert!(matches!(
+        Manifest::load(temp_file("servers.ini", "")),
+        Err(ManifestError::Read(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_actors_and_servers_must_match() {
+    let manifest = Manifest::from_toml("[servers.calculator]\nport = 0").unwrap();
+    let error = manifest.serve(actors()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid manifest: The manifest has no server for the actor inventory"
+    );
+
+    let manifest =
+        Manifest::from_toml("[servers.calculator]\nport = 0\n[servers.inventory]\nport = 0")
+            .unwrap();
+    let error = manifest
+        .serve(Actors::new().actor("calculator", Calculator))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid manifest: No actor was given for the server inventory"
+    );
+}
+
+#[test]
+fn test_tls_paths_are_relative_to_the_manifest() {
+    let path = temp_file(
+        "tls.toml",
+        "[servers.calculator]\nport = 0\ntls = { cert = \"cert.pem\", key = \"/keys/key.pem\" }",
+    );
+    let options = Manifest::load(&path)
+        .unwrap()
+        .options("calculator")
+        .unwrap();
+    let debug = format!("{:?}", options);
+    let cert = path.parent().unwrap().join("cert.pem");
+    assert!(debug.contains(&format!("{:?}", cert.to_string_lossy())));
+    assert!(debug.contains("\"/keys/key.pem\""));
+}
+
+#[derive(Debug, Default)]
+pub struct Flaky;
+
+#[actor]
+impl Flaky {
+    /// Panics
+    pub async fn crash(&self) -> u32 {
+        panic!("crashed")
+    }
+}
+
+#[tokio::test]
+async fn test_supervised_servers_are_restarted() {
+    let manifest = Manifest::from_toml(
+        r#"
+        [servers.calculator]
+        port = 0
+        [servers.inventory]
+        port = 0
+        [servers.flaky]
+        port = 0
+
+        [supervisors.root]
+        servers = ["inventory"]
+        supervisors = ["edge"]
+
+        [supervisors.edge]
+        strategy = "all_for_one"
+        max_restarts = 2
+        within_secs = 30
+        servers = ["flaky"]
+        "#,
+    )
+    .unwrap();
+    let actors = Actors::new()
+        .actor("calculator", Calculator)
+        .restartable("inventory", Inventory::default)
+        .restartable("flaky", Flaky::default);
+    let servers = manifest.serve(actors).unwrap();
+    assert!(servers.get("inventory").is_none());
+    let root = servers.supervisor("root").unwrap();
+    assert!(servers.supervisor("edge").is_none());
+
+    let flaky = root.server("edge/flaky").unwrap();
+    let port = flaky.listening().await[0].port();
+    let (status, _) = post(port, "crash", json!({})).await;
+    assert_eq!(status, 500);
+    tokio::time::timeout(Duration::from_secs(5), flaky.stopped())
+        .await
+        .unwrap();
+    for _ in 0..250 {
+        if root
+            .server("edge/flaky")
+            .is_some_and(|server| !server.is_stopped())
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!root.server("edge/flaky").unwrap().is_stopped());
+    assert_eq!(root.restarts(), 0);
+
+    let inventory = root.server("inventory").unwrap().listening().await[0].port();
+    let (_, count) = post(inventory, "count", json!({"sku": "abc"})).await;
+    assert_eq!(count, "3");
+
+    servers.drain().await;
+    assert!(root.is_stopped());
+    assert!(servers.get("calculator").unwrap().is_stopped());
+}
+
+#[tokio::test]
+async fn test_supervisors_must_fit_together() {
+    let serve = |text: &str, actors: Actors| {
+        Manifest::from_toml(text)
+            .unwrap()
+            .serve(actors)
+            .unwrap_err()
+            .to_string()
+    };
+    let servers = "[servers.calculator]\nport = 0\n[servers.inventory]\nport = 0\n";
+    let restartable = || {
+        Actors::new()
+            .restartable("calculator", || Calculator)
+            .restartable("inventory", Inventory::default)
+    };
+
+    assert_eq!(
+        serve(
+            &format!("{servers}[supervisors.root]\nservers = [\"calculator\"]"),
+            actors()
+        ),
+        "Invalid manifest: The server calculator is supervised, so its actor must be restartable"
+    );
+    assert_eq!(
+        serve(
+            &format!("{servers}[supervisors.root]\nservers = [\"pricing\"]"),
+            restartable()
+        ),
+        "Invalid manifest: The supervisor root has no server pricing"
+    );
+    assert_eq!(
+        serve(
+            &format!(
+                "{servers}[supervisors.a]\nservers = [\"calculator\"]\n\
+                 [supervisors.b]\nservers = [\"calculator\"]"
+            ),
+            restartable()
+        ),
+        "Invalid manifest: calculator is supervised by both a and b"
+    );
+    assert_eq!(
+        serve(
+            &format!(
+                "{servers}[supervisors.a]\nsupervisors = [\"b\"]\n\
+                 [supervisors.b]\nsupervisors = [\"a\"]"
+            ),
+            restartable()
+        ),
+        "Invalid manifest: The supervisors supervise each other in a cycle"
+    );
+    assert!(matches!(
+        Manifest::from_toml(&format!(
+            "{servers}[supervisors.a]\nstrategy = \"rest_for_one\""
+        )),
+        Err(ManifestError::Parse(_))
+    ));
+}
@@ -0,0 +1,90 @@
+use serde_json::Value;
+use simple_json_server::memory::MemoryBudget;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone)]
+pub struct Uploads;
+
+#[actor]
+impl Uploads {
+    /// Hold on to an upload for a while
+    pub async fn upload(&self, data: String) -> usize {
+        sleep(Duration::from_millis(300)).await;
+        data.len()
+    }
+
+    /// Cheap
+    pub async fn ping(&self) -> String {
+        "pong".to_string()
+    }
+}
+
+async fn call(port: u16, method: &str, body: String, priority: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .header("X-Priority", priority)
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_calls_in_flight_count_against_the_ceiling() {
+    let memory = MemoryBudget::new().ceiling(10_000);
+    let server = Uploads.start(
+        ServerOptions::new(0)
+            .memory(memory.clone())
+            .admin_token("secret"),
+    );
+    let port = server.listening().await[0].port();
+
+    let body = format!(r#"{{"data": "{}"}}"#, "x".repeat(9_000));
+    let upload = tokio::spawn(async move { call(port, "upload", body, "normal").await });
+    sleep(Duration::from_millis(100)).await;
+    assert!(memory.usage().mailbox > 9_000);
+
+    // Above 80% of the ceiling, only low priority calls are shed
+    let shed = call(port, "ping", "{}".to_string(), "low").await;
+    assert_eq!(shed.status(), 503);
+    assert_eq!(shed.headers()["retry-after"], "1");
+    assert!(call(port, "ping", "{}".to_string(), "normal")
+        .await
+        .status()
+        .is_success());
+
+    let usage: Value = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/memory"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(usage["mailbox"].as_u64().unwrap() > 9_000);
+    assert_eq!(usage["ceiling"], 10_000);
+
+    assert!(upload.await.unwrap().status().is_success());
+    assert_eq!(memory.usage().mailbox, 0);
+    assert_eq!(memory.shed_count(), 1);
+    assert!(call(port, "ping", "{}".to_string(), "low")
+        .await
+        .status()
+        .is_success());
+
+    memory.gauge("cache").set(2_048);
+    let metrics = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/metrics"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("simple_json_server_memory_bytes{account=\"cache\"} 2048\n"));
+    assert!(metrics.contains("simple_json_server_memory_bytes{account=\"mailbox\"} 0\n"));
+}
@@ -0,0 +1,90 @@
+use simple_json_server::message::Message;
+use simple_json_server::{actor, Actor};
+
+#[derive(Debug, Clone)]
+pub struct Calculator {
+    offset: i32,
+}
+
+#[actor(mock, messages)]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b + self.offset
+    }
+
+    /// Who is asking
+    pub async fn whoami(&self, #[from_header("X-User")] user: String) -> String {
+        user
+    }
+
+    /// Say hello
+    pub async fn hello(&self) -> String {
+        "hello".to_string()
+    }
+}
+
+/// Answer a call chosen by name with a typed message
+async fn answer(calculator: &Calculator, call: &str) -> String {
+    match call {
+        "add" => calculator
+            .handle(AddMessage { a: 1, b: 2 })
+            .await
+            .to_string(),
+        "whoami" => {
+            let message = WhoamiMessage {
+                user: "ada".to_string(),
+            };
+            message.handle(calculator).await
+        }
+        _ => calculator.handle(HelloMessage {}).await,
+    }
+}
+
+#[tokio::test]
+async fn test_typed_messages() {
+    let calculator = Calculator { offset: 10 };
+    assert_eq!(answer(&calculator, "add").await, "13");
+    assert_eq!(answer(&calculator, "whoami").await, "ada");
+    assert_eq!(answer(&calculator, "hello").await, "hello");
+
+    // JSON dispatch still works alongside
+    assert_eq!(
+        calculator.dispatch("add", r#"{"a": 1, "b": 2}"#).await,
+        "13"
+    );
+}
+
+#[tokio::test]
+async fn test_messages_parse_from_json() {
+    let message: AddMessage = serde_json::from_str(r#"{"a": 4, "b": 5}"#).unwrap();
+    assert_eq!((message.a, message.b), (4, 5));
+    assert_eq!(Calculator { offset: 0 }.handle(message).await, 9);
+}
+
+/// Every call, matched exhaustively: a new method is a compile error here
+fn describe(request: &CalculatorRequest) -> String {
+    match request {
+        CalculatorRequest::Add { a, b } => format!("{a} + {b}"),
+        CalculatorRequest::Whoami {} => "whoami".to_string(),
+        CalculatorRequest::Hello {} => "hello".to_string(),
+    }
+}
+
+#[test]
+fn test_requests_parse_from_the_websocket_envelope() {
+    let text = r#"{"id": 7, "method": "add", "params": {"a": 1, "b": 2}}"#;
+    let request: CalculatorRequest = serde_json::from_str(text).unwrap();
+    assert_eq!(request.method(), "add");
+    assert_eq!(describe(&request), "1 + 2");
+
+    // Parameters bound to headers are not part of the envelope
+    let text = r#"{"method": "whoami", "params": {}}"#;
+    let request: CalculatorRequest = serde_json::from_str(text).unwrap();
+    assert_eq!(request.method(), "whoami");
+
+    let text = r#"{"method": "divide", "params": {"a": 1, "b": 2}}"#;
+    assert!(serde_json::from_str::<CalculatorRequest>(text).is_err());
+    let text = r#"{"method": "add", "params": {"a": 1}}"#;
+    assert!(serde_json::from_str::<CalculatorRequest>(text).is_err());
+}
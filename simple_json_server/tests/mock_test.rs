@@ -0,0 +1,74 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43300);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor(mock)]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Say hello
+    pub async fn greet(&self, name: String) -> String {
+        format!("Hello, {}!", name)
+    }
+
+    /// Reset
+    pub async fn reset(&self) {}
+}
+
+#[tokio::test]
+async fn test_mock_answers_from_expectations() {
+    let mock = MockCalculator::new();
+    mock.expect_add().times(1).returning(|(a, b)| a * b);
+    mock.expect_greet()
+        .returning(|(name,)| format!("Hi {}", name));
+    mock.expect_reset().returning(|()| ());
+
+    assert_eq!(mock.methods(), Calculator.methods());
+    assert_eq!(mock.dispatch("add", r#"{"a": 3, "b": 4}"#).await, "12");
+    assert_eq!(
+        mock.dispatch("greet", r#"{"name": "Ada"}"#).await,
+        r#""Hi Ada""#
+    );
+    assert_eq!(mock.dispatch("reset", "{}").await, "null");
+    assert_eq!(mock.calls("greet"), 1);
+    mock.verify();
+
+    // The one expected call to `add` has been made
+    assert_eq!(
+        mock.dispatch("add", r#"{"a": 3, "b": 4}"#).await,
+        r#""No expectation for add""#
+    );
+    let result = std::panic::catch_unwind(|| mock.verify());
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_mock_served_over_http() {
+    let port = get_next_port();
+    let mock = MockCalculator::new();
+    mock.expect_add().returning(|(a, b)| a - b);
+    mock.clone().create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let calculator = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let difference: i32 = calculator
+        .call("add", &json!({"a": 5, "b": 2}))
+        .await
+        .unwrap();
+    assert_eq!(difference, 3);
+    mock.verify();
+}
@@ -0,0 +1,222 @@
+use simple_json_server::blocking::BlockingPool;
+use simple_json_server::events::EventBus;
+use simple_json_server::monitor::{LifecycleEvent, LifecycleEvents, Monitor, TOPIC};
+use simple_json_server::supervisor::{Strategy, Supervisor};
+use simple_json_server::{actor, Actor, ActorRef, ServerHandle, ServerOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct Worker;
+
+#[actor]
+impl Worker {
+    /// Blocks its thread for a while
+    #[blocking]
+    pub async fn work(&self, millis: u64) -> u64 {
+        std::thread::sleep(Duration::from_millis(millis));
+        millis
+    }
+
+    /// Panics
+    pub async fn crash(&self) -> u32 {
+        panic!("crashed")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Recorder {
+    /// Record a lifecycle event
+    pub async fn record(&self, event: String, name: String) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", event, name));
+    }
+}
+
+async fn call(server: &ServerHandle, method: &str, body: &str) -> u16 {
+    let port = server.listening().await[0].port();
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+async fn next(events: &mut LifecycleEvents) -> LifecycleEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_servers_report_starting_draining_and_stopping() {
+    let monitor = Monitor::new();
+    let mut events = monitor.subscribe();
+    let server = Worker.start(ServerOptions::new(0).monitor(monitor, "worker"));
+    let addrs = server.listening().await;
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::Started {
+            name: "worker".to_string(),
+            addrs
+        }
+    );
+
+    server.drain().await;
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::Draining {
+            name: "worker".to_string()
+        }
+    );
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::Stopped {
+            name: "worker".to_string(),
+            failure: None
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_failures_and_saturation_are_reported() {
+    let monitor = Monitor::new();
+    let mut events = monitor.subscribe();
+    let options = ServerOptions::new(0)
+        .blocking_pool(BlockingPool::new(1).queue(0))
+        .stop_on_panic(true)
+        .monitor(monitor, "worker");
+    let server = Worker.start(options);
+    assert!(matches!(
+        next(&mut events).await,
+        LifecycleEvent::Started { .. }
+    ));
+
+    let busy = tokio::spawn({
+        let server = server.clone();
+        async move { call(&server, "work", r#"{"millis": 500}"#).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(call(&server, "work", r#"{"millis": 1}"#).await, 503);
+    assert_eq!(call(&server, "work", r#"{"millis": 1}"#).await, 503);
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::MailboxSaturated {
+            name: "worker".to_string(),
+            method: "work".to_string(),
+            reason: "out of blocking threads".to_string(),
+        }
+    );
+    assert_eq!(busy.await.unwrap(), 200);
+
+    // The second rejection, within a second of the first, was not reported
+    assert_eq!(call(&server, "crash", "{}").await, 500);
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::Draining {
+            name: "worker".to_string()
+        }
+    );
+    assert_eq!(
+        next(&mut events).await,
+        LifecycleEvent::Stopped {
+            name: "worker".to_string(),
+            failure: Some("Method crash panicked".to_string())
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_supervisors_report_restarts_by_path() {
+    let monitor = Monitor::new();
+    let mut events = monitor.subscribe();
+    let inner = Supervisor::new(Strategy::AllForOne)
+        .child("a", ServerOptions::new(0), Worker::default)
+        .child("b", ServerOptions::new(0), Worker::default);
+    let root = Supervisor::new(Strategy::OneForOne)
+        .monitor(monitor)
+        .supervisor("inner", inner)
+        .start();
+    let mut started = Vec::new();
+    for _ in 0..2 {
+        started.push(next(&mut events).await.name().to_string());
+    }
+    started.sort();
+    assert_eq!(started, ["inner/a", "inner/b"]);
+
+    let a = root.server("inner/a").unwrap();
+    assert_eq!(call(&a, "crash", "{}").await, 500);
+    let mut seen = Vec::new();
+    while seen.len() < 8 {
+        let event = next(&mut events).await;
+        let kind = match &event {
+            LifecycleEvent::Started { .. } => "started",
+            LifecycleEvent::Restarted { .. } => "restarted",
+            LifecycleEvent::Draining { .. } => "draining",
+            LifecycleEvent::Stopped { failure: None, .. } => "stopped",
+            LifecycleEvent::Stopped { .. } => "failed",
+            LifecycleEvent::MailboxSaturated { .. } => "saturated",
+        };
+        if let LifecycleEvent::Restarted { name, reason } = &event {
+            let expected = match name.as_str() {
+                "inner/a" => "Method crash panicked",
+                _ => "a failed: Method crash panicked",
+            };
+            assert_eq!(reason, expected);
+        }
+        seen.push(format!("{} {}", kind, event.name()));
+    }
+    // The restarted servers may start listening in either order
+    seen[6..].sort();
+    assert_eq!(
+        seen,
+        [
+            "draining inner/a",
+            "failed inner/a",
+            "draining inner/b",
+            "stopped inner/b",
+            "restarted inner/a",
+            "restarted inner/b",
+            "started inner/a",
+            "started inner/b",
+        ]
+    );
+    root.shut_down().await;
+}
+
+#[tokio::test]
+async fn test_events_are_published_on_the_bus() {
+    let bus = EventBus::new();
+    let recorder = Recorder::default();
+    let _subscription = bus.subscribe(
+        TOPIC,
+        ActorRef::loopback(recorder.clone(), ServerOptions::default()),
+        "record",
+    );
+    let monitor = Monitor::new().events(bus);
+    let server = Worker.start(ServerOptions::new(0).monitor(monitor, "worker"));
+    server.listening().await;
+    server.drain().await;
+
+    for _ in 0..250 {
+        if recorder.events.lock().unwrap().len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        *recorder.events.lock().unwrap(),
+        ["started worker", "draining worker", "stopped worker"]
+    );
+}
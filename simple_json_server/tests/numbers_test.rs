@@ -0,0 +1,62 @@
+use serde_json::json;
+use simple_json_server::numbers::Numbers;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Ledger;
+
+#[actor]
+impl Ledger {
+    /// An account id, as text
+    pub async fn account(&self, id: u64) -> String {
+        id.to_string()
+    }
+
+    /// Twice an amount
+    pub async fn double(&self, amount: f64) -> f64 {
+        amount * 2.0
+    }
+}
+
+#[tokio::test]
+async fn test_large_integers_are_exact() {
+    let ledger = ActorRef::loopback(Ledger, ServerOptions::default());
+    for id in [9_007_199_254_740_993_u64, u64::MAX] {
+        let account: String = ledger.call("account", &json!({ "id": id })).await.unwrap();
+        assert_eq!(account, id.to_string());
+    }
+
+    // Fractions are rejected, not truncated
+    let result = ledger
+        .call_raw("account", r#"{"id": 1.5}"#.to_string())
+        .await;
+    assert!(matches!(result, Err(ClientError::Status(400, _))));
+}
+
+#[tokio::test]
+async fn test_strict_numbers() {
+    let params = r#"{"amount": 0.30000000000000000001}"#;
+    let lenient = ActorRef::loopback(Ledger, ServerOptions::default());
+    assert_eq!(
+        lenient
+            .call_raw("double", params.to_string())
+            .await
+            .unwrap(),
+        "0.6"
+    );
+
+    let strict = ActorRef::loopback(Ledger, ServerOptions::default().numbers(Numbers::Strict));
+    match strict.call_raw("double", params.to_string()).await {
+        Err(ClientError::Status(400, body)) => {
+            assert!(body.contains("0.30000000000000000001"), "{}", body)
+        }
+        other => panic!("Expected the call to be rejected, got {:?}", other),
+    }
+    assert_eq!(
+        strict
+            .call_raw("double", r#"{"amount": 0.25}"#.to_string())
+            .await
+            .unwrap(),
+        "0.5"
+    );
+}
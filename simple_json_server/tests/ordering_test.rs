@@ -0,0 +1,106 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43500);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Sleeper;
+
+#[actor]
+impl Sleeper {
+    /// Sleep, then return how long
+    pub async fn sleep(&self, ms: u64) -> u64 {
+        sleep(Duration::from_millis(ms)).await;
+        ms
+    }
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn connect(url: String) -> Socket {
+    sleep(Duration::from_millis(100)).await;
+    connect_async(url).await.expect("Failed to connect").0
+}
+
+async fn send(socket: &mut Socket, message: Value) {
+    socket
+        .send(Message::Text(message.to_string()))
+        .await
+        .unwrap();
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    match socket.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("Expected a text message, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_calls_with_ids_are_answered_as_they_finish() {
+    let port = get_next_port();
+    Sleeper.create_with(ServerOptions::new(port).websocket(true));
+    let mut socket = connect(format!("ws://127.0.0.1:{port}")).await;
+
+    send(
+        &mut socket,
+        json!({"id": 1, "method": "sleep", "params": {"ms": 300}}),
+    )
+    .await;
+    send(
+        &mut socket,
+        json!({"id": 2, "method": "sleep", "params": {"ms": 10}}),
+    )
+    .await;
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"id": 2, "status": 200, "result": 10})
+    );
+    assert_eq!(
+        receive(&mut socket).await,
+        json!({"id": 1, "status": 200, "result": 300})
+    );
+}
+
+#[tokio::test]
+async fn test_ordered_connections_get_replies_in_request_order() {
+    let port = get_next_port();
+    Sleeper.create_with(ServerOptions::new(port).websocket(true));
+    let mut socket = connect(format!("ws://127.0.0.1:{port}/?ordered=true")).await;
+
+    send(
+        &mut socket,
+        json!({"id": 1, "method": "sleep", "params": {"ms": 300}}),
+    )
+    .await;
+    send(
+        &mut socket,
+        json!({"method": "sleep", "params": {"ms": 10}}),
+    )
+    .await;
+    send(
+        &mut socket,
+        json!({"id": 3, "method": "sleep", "params": {"ms": 20}}),
+    )
+    .await;
+    send(&mut socket, json!({"unknown": true})).await;
+
+    let started = std::time::Instant::now();
+    assert_eq!(receive(&mut socket).await["id"], 1);
+    assert_eq!(receive(&mut socket).await, json!(10));
+    assert_eq!(receive(&mut socket).await["id"], 3);
+    assert!(receive(&mut socket).await["error"].is_string());
+    // The calls ran concurrently
+    assert!(started.elapsed() < Duration::from_millis(600));
+}
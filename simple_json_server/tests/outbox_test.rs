@@ -0,0 +1,183 @@
+#![cfg(feature = "store")]
+
+use serde_json::{json, Value};
+use simple_json_server::events::EventBus;
+use simple_json_server::outbox::Outbox;
+use simple_json_server::store::ActorStore;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Orders {
+    store: ActorStore,
+}
+
+#[actor]
+impl Orders {
+    /// Place an order, emitting `order_placed` with it; refused if `total` is zero
+    pub async fn place(&self, id: String, total: u64) -> Result<(), String> {
+        self.store
+            .transaction(|tx| {
+                tx.put(&format!("order/{}", id), &total)?;
+                tx.emit("order_placed", &json!({ "id": id, "total": total }))?;
+                if total == 0 {
+                    return Err(simple_json_server::store::StoreError::Database(
+                        "empty order".to_string(),
+                    ));
+                }
+                Ok(())
+            })
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// A webhook receiver that fails until it is switched on.
+#[derive(Debug, Clone, Default)]
+pub struct Billing {
+    up: Arc<AtomicBool>,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Billing {
+    /// Record a placed order
+    pub async fn order_placed(&self, id: String, total: u64) -> Result<(), String> {
+        if !self.up.load(Ordering::SeqCst) {
+            return Err("billing is down".to_string());
+        }
+        self.received
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", id, total));
+        Ok(())
+    }
+}
+
+async fn eventually(mut done: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("timed out");
+}
+
+#[tokio::test]
+async fn test_events_are_delivered_after_commit_with_retries() {
+    let store = ActorStore::in_memory().unwrap();
+    let billing = Billing::default();
+    let billing_server = billing.clone().start(ServerOptions::new(0));
+    let billing_port = billing_server.listening().await[0].port();
+    let orders_server = Orders {
+        store: store.clone(),
+    }
+    .start(ServerOptions::new(0));
+    let orders_port = orders_server.listening().await[0].port();
+
+    let events = EventBus::new();
+    let published = Arc::new(Mutex::new(Vec::new()));
+    let outbox = Outbox::new(store.clone())
+        .publish_to(events.clone())
+        .route(
+            "order_placed",
+            ActorRef::new(format!("http://127.0.0.1:{billing_port}")),
+            "order_placed",
+        )
+        .retry_delay(Duration::from_millis(50));
+    let relay = outbox.clone().start();
+    let recorder = Recorder {
+        published: Arc::clone(&published),
+    };
+    let recorder = ActorRef::loopback(recorder, ServerOptions::default());
+    events.subscribe("order_placed", recorder, "record");
+
+    let client = reqwest::Client::new();
+    let place = |params: Value| {
+        client
+            .post(format!("http://127.0.0.1:{orders_port}/place"))
+            .json(&params)
+            .send()
+    };
+    let response: Value = place(json!({"id": "a", "total": 12}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response, json!({"Ok": null}));
+    // A rolled back transaction emits nothing
+    let response: Value = place(json!({"id": "b", "total": 0}))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(response.get("Err").is_some());
+
+    // Billing is down: the event is published, and kept for the webhook
+    eventually(|| {
+        outbox
+            .pending()
+            .unwrap()
+            .first()
+            .is_some_and(|event| event.attempts > 0)
+    })
+    .await;
+    let pending = outbox.pending().unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].topic, "order_placed");
+    assert_eq!(pending[0].payload, json!({"id": "a", "total": 12}));
+    assert!(pending[0]
+        .last_error
+        .as_deref()
+        .unwrap()
+        .contains("billing is down"));
+
+    billing.up.store(true, Ordering::SeqCst);
+    eventually(|| outbox.pending().unwrap().is_empty()).await;
+    assert_eq!(*billing.received.lock().unwrap(), ["a 12"]);
+    assert!(published
+        .lock()
+        .unwrap()
+        .contains(&json!({"id": "a", "total": 12})));
+    relay.stop();
+}
+
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    published: Arc<Mutex<Vec<Value>>>,
+}
+
+#[actor]
+impl Recorder {
+    /// Record a published event
+    pub async fn record(&self, id: String, total: u64) {
+        self.published
+            .lock()
+            .unwrap()
+            .push(json!({"id": id, "total": total}));
+    }
+}
+
+#[tokio::test]
+async fn test_events_survive_until_delivered() {
+    let path = std::env::temp_dir().join(format!("outbox_test_{}.db", std::process::id()));
+    let store = ActorStore::open(&path).unwrap();
+    store
+        .transaction(|tx| {
+            tx.put("k", &1)?;
+            tx.emit("changed", &json!({"key": "k"}))
+        })
+        .unwrap();
+    drop(store);
+
+    // Nothing is routed to the topic, so delivering it only removes it
+    let outbox = Outbox::new(ActorStore::open(&path).unwrap());
+    assert_eq!(outbox.pending().unwrap().len(), 1);
+    assert_eq!(outbox.deliver_due().await.unwrap(), 1);
+    assert!(outbox.pending().unwrap().is_empty());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,65 @@
+use serde_json::json;
+use simple_json_server::pool::ActorPool;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::collections::HashSet;
+
+/// Says which replica answered
+#[derive(Debug, Clone)]
+pub struct Replica {
+    name: String,
+}
+
+#[actor]
+impl Replica {
+    /// The replica's name
+    pub async fn whoami(&self) -> String {
+        self.name.clone()
+    }
+}
+
+fn replica(name: &str) -> ActorRef {
+    ActorRef::loopback(
+        Replica {
+            name: name.to_string(),
+        },
+        ServerOptions::default(),
+    )
+}
+
+#[tokio::test]
+async fn test_calls_are_spread_and_fail_over() {
+    let pool = ActorPool::from_actors([
+        ActorRef::new("http://127.0.0.1:1"),
+        replica("a"),
+        replica("b"),
+    ]);
+
+    let mut answered = HashSet::new();
+    for _ in 0..6 {
+        let name: String = pool.call("whoami", &json!({})).await.unwrap();
+        answered.insert(name);
+    }
+    assert_eq!(answered, HashSet::from(["a".to_string(), "b".to_string()]));
+    assert_eq!(pool.healthy().len(), 2);
+
+    // Probing keeps the unreachable server out
+    pool.probe("whoami").await;
+    assert!(!pool.healthy().contains(&"http://127.0.0.1:1".to_string()));
+}
+
+#[tokio::test]
+async fn test_keyed_calls_are_sticky() {
+    let pool = ActorPool::from_actors([replica("a"), replica("b"), replica("c")]);
+    let mut answered = HashSet::new();
+    for user in 0..20 {
+        let user = format!("user-{}", user);
+        let first: String = pool.call_keyed(&user, "whoami", &json!({})).await.unwrap();
+        for _ in 0..3 {
+            let again: String = pool.call_keyed(&user, "whoami", &json!({})).await.unwrap();
+            assert_eq!(again, first);
+        }
+        answered.insert(first);
+    }
+    // The keys are spread over the replicas
+    assert!(answered.len() > 1);
+}
@@ -0,0 +1,142 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+pub struct Documents {
+    docs: Mutex<HashMap<String, (u64, String)>>,
+}
+
+#[actor]
+impl Documents {
+    /// Create or replace a document, returning its new version
+    #[write]
+    pub async fn save(&self, id: String, #[if_match] version: Option<u64>, text: String) -> u64 {
+        let mut docs = self.docs.lock().unwrap();
+        let doc = docs.entry(id).or_insert((0, String::new()));
+        // Stale versions never get this far
+        assert!(version.is_none_or(|version| version == doc.0));
+        *doc = (doc.0 + 1, text);
+        doc.0
+    }
+
+    /// A document's version and text
+    #[read]
+    pub async fn load(&self, id: String) -> Option<(u64, String)> {
+        self.docs.lock().unwrap().get(&id).cloned()
+    }
+
+    async fn current_version(&self, _method: &str, params: &str) -> Option<u64> {
+        let params: Value = serde_json::from_str(params).ok()?;
+        let id = params["id"].as_str()?;
+        self.docs
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|(version, _)| *version)
+    }
+}
+
+async fn save(port: u16, params: Value, if_match: Option<&str>) -> (u16, Value) {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/save"))
+        .json(&params);
+    if let Some(if_match) = if_match {
+        request = request.header("If-Match", if_match);
+    }
+    let response = request.send().await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_expected_versions_are_checked() {
+    let server = Documents::default().start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    // Without an expected version the call is unconditional
+    assert_eq!(
+        save(port, json!({"id": "a", "text": "one"}), None).await,
+        (200, json!(1))
+    );
+    assert_eq!(
+        save(port, json!({"id": "a", "version": 1, "text": "two"}), None).await,
+        (200, json!(2))
+    );
+    assert_eq!(
+        save(
+            port,
+            json!({"id": "a", "version": 1, "text": "stale"}),
+            None
+        )
+        .await,
+        (
+            409,
+            json!("Version conflict in save: `version` is 1, but the current version is 2")
+        )
+    );
+    assert_eq!(
+        save(port, json!({"id": "b", "version": 1, "text": "new"}), None).await,
+        (
+            409,
+            json!("Version conflict in save: `version` is 1, but there is no current version")
+        )
+    );
+
+    let client = reqwest::Client::new();
+    let loaded: Value = client
+        .post(format!("http://127.0.0.1:{port}/load"))
+        .json(&json!({"id": "a"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(loaded, json!([2, "two"]));
+}
+
+#[tokio::test]
+async fn test_if_match_headers_are_checked() {
+    let server = Documents::default().start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    assert_eq!(
+        save(port, json!({"id": "a", "text": "one"}), Some("*")).await,
+        (
+            412,
+            json!("Precondition failed for save: there is no current version")
+        )
+    );
+    assert_eq!(
+        save(port, json!({"id": "a", "text": "one"}), None).await,
+        (200, json!(1))
+    );
+    assert_eq!(
+        save(
+            port,
+            json!({"id": "a", "text": "two"}),
+            Some(r#"W/"0", "1""#)
+        )
+        .await,
+        (200, json!(2))
+    );
+    assert_eq!(
+        save(port, json!({"id": "a", "text": "stale"}), Some(r#""1""#)).await,
+        (
+            412,
+            json!("Precondition failed for save: the current version is 2")
+        )
+    );
+
+    // The parameter takes precedence over the header
+    assert_eq!(
+        save(
+            port,
+            json!({"id": "a", "version": 2, "text": "three"}),
+            Some(r#""1""#)
+        )
+        .await,
+        (200, json!(3))
+    );
+}
@@ -0,0 +1,84 @@
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43800);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Divide two numbers
+    pub async fn divide(&self, a: i32, b: i32) -> Result<i32, String> {
+        if b == 0 {
+            Err("Division by zero".to_string())
+        } else {
+            Ok(a / b)
+        }
+    }
+}
+
+async fn post(url: String, accept: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new().post(url).body(r#"{"a": 1, "b": 0}"#);
+    if let Some(accept) = accept {
+        request = request.header("Accept", accept);
+    }
+    request.send().await.expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_pretty_responses() {
+    let port = get_next_port();
+    Calculator.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let url = format!("http://127.0.0.1:{port}/divide");
+    let pretty = "{\n  \"Err\": \"Division by zero\"\n}";
+    let response = post(format!("{url}?pretty=1"), None).await;
+    assert_eq!(response.text().await.unwrap(), pretty);
+    let response = post(url.clone(), Some("application/json+pretty")).await;
+    assert_eq!(response.text().await.unwrap(), pretty);
+
+    // Debug mode is off unless the server enables it
+    let response = post(format!("{url}?debug=1"), None).await;
+    assert!(response.headers().get("Server-Timing").is_none());
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"Err":"Division by zero"}"#
+    );
+}
+
+#[tokio::test]
+async fn test_debug_responses() {
+    let port = get_next_port();
+    Calculator.create_with(ServerOptions::new(port).debug(true));
+    sleep(Duration::from_millis(100)).await;
+
+    let url = format!("http://127.0.0.1:{port}/divide");
+    let response = post(format!("{url}?debug=1&pretty=1"), None).await;
+    let timing = response.headers()["Server-Timing"].to_str().unwrap();
+    assert!(timing.starts_with("dispatch;dur="), "{}", timing);
+    let text = response.text().await.unwrap();
+    assert!(text.contains("\n  \"debug\": {\n"), "{}", text);
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(
+        body["result"],
+        serde_json::json!({"Err": "Division by zero"})
+    );
+    assert_eq!(body["debug"]["path"], "/divide");
+    assert_eq!(body["debug"]["status"], 200);
+    assert!(body["debug"]["elapsed_us"].is_u64());
+
+    // Plain requests are unchanged
+    let response = post(url, None).await;
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"{"Err":"Division by zero"}"#
+    );
+}
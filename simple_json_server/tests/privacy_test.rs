@@ -0,0 +1,117 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Orders per customer
+#[derive(Debug, Clone, Default)]
+pub struct Orders {
+    orders: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+#[actor]
+impl Orders {
+    /// Place an order
+    pub async fn order(&self, customer: String, item: String) {
+        let mut orders = self.orders.lock().unwrap();
+        orders.entry(customer).or_default().push(item);
+    }
+
+    async fn export_subject_data(&self, subject_id: &str) -> Vec<String> {
+        let orders = self.orders.lock().unwrap();
+        orders.get(subject_id).cloned().unwrap_or_default()
+    }
+
+    async fn delete_subject_data(&self, subject_id: &str) -> Result<(), String> {
+        if subject_id == "locked" {
+            return Err("the account is under legal hold".to_string());
+        }
+        self.orders.lock().unwrap().remove(subject_id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+async fn admin(port: u16, op: &str, body: Option<Value>) -> (u16, Value) {
+    let client = reqwest::Client::new();
+    let url = format!("http://127.0.0.1:{port}/__admin/{op}");
+    let request = match body {
+        Some(body) => client.post(url).json(&body),
+        None => client.get(url),
+    };
+    let response = request.bearer_auth("secret").send().await.unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_subject_data_is_exported_and_deleted() {
+    let orders = Orders::default();
+    let server = orders
+        .clone()
+        .start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/order"))
+        .json(&json!({"customer": "ada", "item": "lamp"}))
+        .send()
+        .await
+        .unwrap();
+
+    let subject = json!({"subject_id": "ada"});
+    assert_eq!(
+        admin(port, "export_subject", Some(subject.clone())).await,
+        (200, json!({"subject_id": "ada", "data": ["lamp"]}))
+    );
+    assert_eq!(
+        admin(port, "delete_subject", Some(subject.clone())).await,
+        (200, json!({"subject_id": "ada", "deleted": true}))
+    );
+    assert!(orders.orders.lock().unwrap().is_empty());
+    assert_eq!(
+        admin(port, "export_subject", Some(subject.clone())).await,
+        (200, json!({"subject_id": "ada", "data": []}))
+    );
+
+    let (status, error) = admin(
+        port,
+        "delete_subject",
+        Some(json!({"subject_id": "locked"})),
+    )
+    .await;
+    assert_eq!(status, 500);
+    assert_eq!(
+        error,
+        "Failed to delete the subject's data: the account is under legal hold"
+    );
+    assert_eq!(admin(port, "export_subject", Some(json!({}))).await.0, 400);
+    assert_eq!(admin(port, "delete_subject", None).await.0, 405);
+}
+
+#[tokio::test]
+async fn test_actors_without_hooks_say_so() {
+    let server = Calculator.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+    assert_eq!(
+        admin(port, "export_subject", Some(json!({"subject_id": "ada"}))).await,
+        (
+            501,
+            json!("This actor does not support data-subject requests")
+        )
+    );
+    assert_eq!(
+        admin(port, "delete_subject", Some(json!({"subject_id": "ada"})))
+            .await
+            .0,
+        501
+    );
+}
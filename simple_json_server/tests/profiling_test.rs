@@ -0,0 +1,61 @@
+#![cfg(feature = "profiling")]
+
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Hasher;
+
+#[actor]
+impl Hasher {
+    /// Burn some CPU
+    pub async fn spin(&self, rounds: u64) -> u64 {
+        (0..rounds).fold(0u64, |acc, n| acc.wrapping_mul(31).wrapping_add(n))
+    }
+}
+
+async fn get(port: u16, path: &str, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}{path}"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_cpu_profile_behind_the_admin_token() {
+    let server = Hasher.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    let unauthorized = get(port, "/debug/pprof/profile?seconds=1", "wrong").await;
+    assert_eq!(unauthorized.status(), 401);
+
+    let profile =
+        tokio::spawn(async move { get(port, "/debug/pprof/profile?seconds=1", "secret").await });
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/spin"))
+        .body(r#"{"rounds": 10000000}"#)
+        .send()
+        .await
+        .unwrap();
+    let profile = profile.await.unwrap();
+    assert_eq!(profile.status(), 200);
+    assert_eq!(
+        profile.headers()["content-type"],
+        "application/octet-stream"
+    );
+    assert!(!profile.bytes().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_heap_profile_needs_jemalloc() {
+    let server = Hasher.start(ServerOptions::new(0).admin_token("secret"));
+    let port = server.listening().await[0].port();
+
+    // This test binary uses the system allocator
+    assert_eq!(get(port, "/debug/pprof/heap", "secret").await.status(), 404);
+    assert_eq!(
+        get(port, "/debug/pprof/goroutine", "secret").await.status(),
+        404
+    );
+}
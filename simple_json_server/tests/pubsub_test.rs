@@ -0,0 +1,138 @@
+use serde_json::json;
+use simple_json_server::deadletter::DeadLetters;
+use simple_json_server::events::EventBus;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Publishes an event for each order placed
+#[derive(Debug, Clone)]
+pub struct Orders {
+    events: EventBus,
+}
+
+#[actor]
+impl Orders {
+    /// Place an order
+    pub async fn place(&self, item: String, quantity: u32) {
+        self.events
+            .publish("placed", &json!({ "item": item, "quantity": quantity }))
+            .unwrap();
+    }
+}
+
+/// Counts what has been ordered, learning of orders from events
+#[derive(Debug, Clone, Default)]
+pub struct Stock {
+    ordered: Arc<Mutex<Vec<(String, u32)>>>,
+}
+
+#[actor]
+impl Stock {
+    /// Note an order
+    pub async fn reserve(&self, item: String, quantity: u32) {
+        self.ordered.lock().unwrap().push((item, quantity));
+    }
+}
+
+#[tokio::test]
+async fn test_events_are_delivered_as_method_calls() {
+    let events = EventBus::new();
+    let stock = Stock::default();
+    let subscription = events.subscribe(
+        "placed",
+        ActorRef::loopback(stock.clone(), ServerOptions::default()),
+        "reserve",
+    );
+    let orders = ActorRef::loopback(
+        Orders {
+            events: events.clone(),
+        },
+        ServerOptions::default(),
+    );
+
+    for (item, quantity) in [("apple", 3), ("pear", 1)] {
+        orders
+            .call::<_, ()>("place", &json!({ "item": item, "quantity": quantity }))
+            .await
+            .unwrap();
+    }
+    // Events on other topics are not delivered
+    events
+        .publish("cancelled", &json!({"item": "apple"}))
+        .unwrap();
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(
+        *stock.ordered.lock().unwrap(),
+        [("apple".to_string(), 3), ("pear".to_string(), 1)]
+    );
+
+    subscription.cancel();
+    sleep(Duration::from_millis(10)).await;
+    events
+        .publish("placed", &json!({"item": "plum", "quantity": 1}))
+        .unwrap();
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(stock.ordered.lock().unwrap().len(), 2);
+}
+
+/// Ships orders, failing while the warehouse is closed
+#[derive(Debug, Clone, Default)]
+pub struct Shipping {
+    open: Arc<AtomicBool>,
+    shipped: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Shipping {
+    /// Ship an order
+    pub async fn ship(&self, item: String, quantity: u32) -> Result<u32, String> {
+        if !self.open.load(Ordering::SeqCst) {
+            return Err("The warehouse is closed".to_string());
+        }
+        self.shipped.lock().unwrap().push(item);
+        Ok(quantity)
+    }
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_become_dead_letters() {
+    let dead_letters = DeadLetters::new()
+        .attempts(2)
+        .retry_delay(Duration::from_millis(10));
+    let events = EventBus::new().dead_letters(dead_letters.clone());
+    let shipping = Shipping::default();
+    events.subscribe(
+        "placed",
+        ActorRef::loopback(shipping.clone(), ServerOptions::default()),
+        "ship",
+    );
+
+    for item in ["apple", "pear"] {
+        events
+            .publish("placed", &json!({ "item": item, "quantity": 1 }))
+            .unwrap();
+    }
+    sleep(Duration::from_millis(200)).await;
+    let letters = dead_letters.list();
+    assert_eq!(letters.len(), 2);
+    assert_eq!(letters[0].method, "ship");
+    assert_eq!(letters[0].params, json!({"item": "apple", "quantity": 1}));
+    assert_eq!(letters[0].attempts, 2);
+    assert!(letters[0].error.contains("The warehouse is closed"));
+    assert_eq!(letters[0].url(), "loopback://actor");
+
+    // Still failing: the letter stays, with one more attempt
+    let retried = dead_letters.requeue(letters[0].id).await.unwrap();
+    assert!(retried.is_err());
+    assert_eq!(dead_letters.list()[1].attempts, 3);
+
+    assert!(dead_letters.discard(letters[1].id).is_some());
+    shipping.open.store(true, Ordering::SeqCst);
+    assert_eq!(dead_letters.requeue_all().await, 1);
+    assert!(dead_letters.is_empty());
+    assert_eq!(*shipping.shipped.lock().unwrap(), ["apple"]);
+    assert!(dead_letters.requeue(letters[0].id).await.is_none());
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Filter {
+    min_price: u32,
+    sizes: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Catalog;
+
+#[actor]
+impl Catalog {
+    /// Search the catalog
+    #[read]
+    #[get]
+    pub async fn search(
+        &self,
+        text: String,
+        tags: Vec<String>,
+        filter: Option<Filter>,
+        limit: Option<u32>,
+    ) -> serde_json::Value {
+        json!({"text": text, "tags": tags, "filter": filter, "limit": limit})
+    }
+
+    /// Only served by POST
+    #[read]
+    pub async fn count(&self) -> u32 {
+        3
+    }
+}
+
+async fn get(port: u16, path_and_query: &str) -> (u16, serde_json::Value) {
+    let response = reqwest::get(format!("http://127.0.0.1:{port}{path_and_query}"))
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[test]
+fn test_get_methods_are_listed() {
+    let info = Catalog.methods();
+    assert!(info.iter().find(|m| m.name == "search").unwrap().get);
+    assert!(!info.iter().find(|m| m.name == "count").unwrap().get);
+}
+
+#[tokio::test]
+async fn test_params_from_the_query_string() {
+    let server = Catalog.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let (status, body) = get(port, "/search?text=red+shoes&tags=sale&tags=new&limit=5").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({"text": "red shoes", "tags": ["sale", "new"], "filter": null, "limit": 5})
+    );
+
+    let query = "/search?text=x&tags[]=sale&filter[min_price]=10&filter[sizes]=42&filter[sizes]=43";
+    let (status, body) = get(port, query).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({
+            "text": "x",
+            "tags": ["sale"],
+            "filter": {"min_price": 10, "sizes": [42, 43]},
+            "limit": null,
+        })
+    );
+
+    // The same method is still served by POST with a JSON body
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/search"))
+        .json(&json!({"text": "x", "tags": []}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_invalid_query_strings() {
+    let server = Catalog.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let (status, body) = get(port, "/search?text=x&tags=a&limit=many").await;
+    assert_eq!(status, 400);
+    assert!(body.as_str().unwrap().contains("expected u32"));
+
+    let (status, body) = get(port, "/search?tags=a").await;
+    assert_eq!(status, 400);
+    assert!(body.as_str().unwrap().contains("missing field `text`"));
+
+    let (status, body) = get(port, "/search?text=x&text[a]=y").await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body,
+        json!("Query parameter `text[a]` conflicts with another")
+    );
+}
+
+#[tokio::test]
+async fn test_other_methods_are_not_served_by_get() {
+    let server = Catalog.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let response = reqwest::get(format!("http://127.0.0.1:{port}/count"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["allow"], "POST, OPTIONS");
+
+    let response = reqwest::Client::new()
+        .delete(format!("http://127.0.0.1:{port}/search"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["allow"], "GET, HEAD, POST, OPTIONS");
+}
@@ -0,0 +1,109 @@
+use simple_json_server::quota::Quotas;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42600);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+const TOKEN: &str = "quota-token";
+
+#[derive(Debug, Clone)]
+pub struct Echo;
+
+#[actor]
+impl Echo {
+    /// Echo some text
+    pub async fn echo(&self, text: String) -> String {
+        text
+    }
+}
+
+async fn post(port: u16, key: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/echo"))
+        .body(r#"{"text": "hello"}"#);
+    if let Some(key) = key {
+        request = request.header("X-Api-Key", key);
+    }
+    request.send().await.expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_calls_over_quota_are_rejected() {
+    let port = get_next_port();
+    let quotas = Quotas::new().daily_requests(2);
+    Echo.create_with(
+        ServerOptions::new(port)
+            .admin_token(TOKEN)
+            .quotas(quotas.clone()),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(post(port, Some("alice")).await.status(), 200);
+    assert_eq!(post(port, Some("alice")).await.status(), 200);
+    let response = post(port, Some("alice")).await;
+    assert_eq!(response.status(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 24 * 60 * 60);
+
+    // Other clients, and anonymous callers, are unaffected
+    assert_eq!(post(port, Some("bob")).await.status(), 200);
+    assert_eq!(post(port, None).await.status(), 200);
+
+    let alice = quotas.usage("alice").unwrap();
+    assert_eq!((alice.requests, alice.rejected), (2, 1));
+    // Two requests of 17 bytes and two responses of 7
+    assert_eq!(alice.bytes, 48);
+
+    let usage: serde_json::Value = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/usage"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(usage["alice"]["requests"], 2);
+    assert_eq!(usage["bob"]["requests"], 1);
+}
+
+#[tokio::test]
+async fn test_client_id_can_be_required() {
+    let port = get_next_port();
+    Echo.create_with(ServerOptions::new(port).quotas(Quotas::new().require_client(true)));
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(post(port, None).await.status(), 401);
+    assert_eq!(post(port, Some("alice")).await.status(), 200);
+}
+
+#[tokio::test]
+async fn test_unknown_and_surplus_clients_are_refused() {
+    let port = get_next_port();
+    let quotas = Quotas::new()
+        .allowed_clients(["alice", "bob", "carol"])
+        .max_clients(2);
+    Echo.create_with(ServerOptions::new(port).quotas(quotas.clone()));
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(post(port, Some("mallory")).await.status(), 401);
+    assert_eq!(post(port, Some("alice")).await.status(), 200);
+    assert_eq!(post(port, Some("bob")).await.status(), 200);
+    let response = post(port, Some("carol")).await;
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+
+    assert_eq!(quotas.usage("mallory"), None);
+    assert_eq!(quotas.usage("carol"), None);
+    assert_eq!(quotas.all_usage().len(), 2);
+}
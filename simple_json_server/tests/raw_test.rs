@@ -0,0 +1,88 @@
+use serde::Deserialize;
+use serde_json::json;
+use simple_json_server::raw::RawJson;
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Deserialize)]
+pub struct Order {
+    pub total: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Relay;
+
+#[actor]
+impl Relay {
+    /// Hand the order back as sent, reading its total only if asked to
+    #[max_size(order = 64)]
+    pub async fn relay(&self, inspect: bool, order: RawJson<Order>) -> String {
+        if !inspect {
+            return order.get().to_string();
+        }
+        match order.parse() {
+            Ok(order) => format!("total {}", order.total),
+            Err(e) => format!("invalid order: {e}"),
+        }
+    }
+}
+
+async fn post(port: u16, method: &str, body: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_raw_parameters_are_passed_through_unparsed() {
+    let server = Relay.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    // Not a valid Order, but never parsed
+    let body = r#"{"inspect": false, "order": {"total": 1.50,  "lines": [ ]}}"#;
+    let response = post(port, "relay", body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.json::<String>().await.unwrap(),
+        r#"{"total": 1.50,  "lines": [ ]}"#
+    );
+
+    let body = r#"{"inspect": true, "order": {"total": 1.50}}"#;
+    let response = post(port, "relay", body).await;
+    assert_eq!(response.json::<String>().await.unwrap(), "total 1.5");
+
+    let body = r#"{"inspect": true, "order": {"lines": []}}"#;
+    let response = post(port, "relay", body).await;
+    assert!(response
+        .json::<String>()
+        .await
+        .unwrap()
+        .contains("missing field `total`"));
+}
+
+#[tokio::test]
+async fn test_raw_parameters_are_checked() {
+    let server = Relay.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let padding = "x".repeat(64);
+    let params = json!({"inspect": false, "order": {"note": padding}});
+    let response = post(port, "relay", &params.to_string()).await;
+    assert_eq!(response.status(), 400);
+
+    let response = post(port, "relay", r#"{"inspect": false}"#).await;
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("order"));
+
+    let response = post(port, "relay", r#"{"inspect": false, "order": [1,"#).await;
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Failed to parse JSON"));
+
+    let response = post(port, "missing", "{}").await;
+    assert!(response.text().await.unwrap().contains("Unknown method"));
+}
@@ -0,0 +1,70 @@
+use serde_json::json;
+use simple_json_server::registry::{Registry, RegistryClient};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41100);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Multiply two numbers
+    pub async fn multiply(&self, a: i32, b: i32) -> i32 {
+        a * b
+    }
+}
+
+#[tokio::test]
+async fn test_servers_register_and_clients_resolve() {
+    let registry_port = get_next_port();
+    let calc_port = get_next_port();
+
+    Registry::new().create(registry_port);
+    sleep(Duration::from_millis(100)).await;
+
+    let registry = RegistryClient::new(format!("http://127.0.0.1:{registry_port}"));
+    let address = format!("http://127.0.0.1:{calc_port}");
+    Calculator.create_with(
+        ServerOptions::new(calc_port)
+            .register(registry.clone(), "calculator", address.clone())
+            .registry_ttl(Duration::from_millis(300)),
+    );
+    sleep(Duration::from_millis(200)).await;
+
+    let instances = registry
+        .lookup("calculator")
+        .await
+        .expect("Failed to look up calculator");
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].address, address);
+    assert_eq!(instances[0].methods, vec!["add", "multiply"]);
+
+    // Heartbeats keep the registration alive well past its TTL
+    sleep(Duration::from_millis(600)).await;
+    assert_eq!(registry.resolve("calculator").await.unwrap(), vec![address]);
+
+    let calculator = registry
+        .connect("calculator")
+        .await
+        .expect("Failed to connect to calculator");
+    let product: i32 = calculator
+        .call("multiply", &json!({"a": 6, "b": 7}))
+        .await
+        .expect("Failed to call multiply");
+    assert_eq!(product, 42);
+
+    assert!(registry.connect("missing").await.is_err());
+}
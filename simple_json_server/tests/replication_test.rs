@@ -0,0 +1,336 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use simple_json_server::journal::Journal;
+use simple_json_server::replication::Replication;
+use simple_json_server::snapshot::FileSnapshotStore;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, RequestContext, ServerOptions};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41400);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    items: Arc<Mutex<HashMap<u32, String>>>,
+}
+
+#[actor]
+impl Catalog {
+    /// Look up an item
+    #[read]
+    pub async fn get(&self, id: u32) -> Option<String> {
+        self.items.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Add or replace an item
+    #[write]
+    pub async fn put(&self, id: u32, name: String) -> bool {
+        self.items.lock().unwrap().insert(id, name).is_none()
+    }
+
+    /// Add an item named by the caller's tenant and user
+    #[write]
+    pub async fn put_own(
+        &self,
+        id: u32,
+        #[from_header("X-Tenant-Id")] tenant: String,
+        #[from_cookie("user")] user: Option<String>,
+    ) -> bool {
+        let method = RequestContext::current().unwrap().method().to_string();
+        let name = format!("{}/{}/{}", method, tenant, user.unwrap_or_default());
+        self.items.lock().unwrap().insert(id, name).is_none()
+    }
+
+    /// Remove an item
+    pub async fn remove(&self, id: u32) -> bool {
+        self.items.lock().unwrap().remove(&id).is_some()
+    }
+
+    fn snapshot(&self) -> HashMap<u32, String> {
+        self.items.lock().unwrap().clone()
+    }
+
+    fn restore(&self, items: HashMap<u32, String>) {
+        *self.items.lock().unwrap() = items;
+    }
+}
+
+/// Poll `actor` until `get(id)` returns `expected` or a second passes.
+async fn wait_for(actor: &ActorRef, id: u32, expected: Option<&str>) -> Option<String> {
+    let mut value = None;
+    for _ in 0..50 {
+        value = actor.call("get", &json!({ "id": id })).await.unwrap();
+        if value.as_deref() == expected {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    value
+}
+
+#[tokio::test]
+async fn test_replicas_follow_the_primary() {
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+    let replica_port = get_next_port();
+
+    Catalog::default().create_with(
+        ServerOptions::new(primary_port).replication(Replication::primary(replication_port)),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let primary = ActorRef::new(format!("http://127.0.0.1:{primary_port}"));
+    let _: bool = primary
+        .call("put", &json!({"id": 1, "name": "kettle"}))
+        .await
+        .unwrap();
+
+    // A replica that starts late catches up on earlier writes
+    Catalog::default().create_with(ServerOptions::new(replica_port).replication(
+        Replication::replica(format!("ws://127.0.0.1:{replication_port}")),
+    ));
+    sleep(Duration::from_millis(100)).await;
+
+    let replica = ActorRef::new(format!("http://127.0.0.1:{replica_port}"));
+    assert_eq!(
+        wait_for(&replica, 1, Some("kettle")).await.as_deref(),
+        Some("kettle")
+    );
+
+    // ... and then receives new ones as they happen
+    let _: bool = primary
+        .call("put", &json!({"id": 2, "name": "toaster"}))
+        .await
+        .unwrap();
+    let _: bool = primary.call("remove", &json!({"id": 1})).await.unwrap();
+    assert_eq!(
+        wait_for(&replica, 2, Some("toaster")).await.as_deref(),
+        Some("toaster")
+    );
+    assert_eq!(wait_for(&replica, 1, None).await, None);
+}
+
+#[tokio::test]
+async fn test_replicas_reject_writes() {
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+    let replica_port = get_next_port();
+
+    Catalog::default().create_with(
+        ServerOptions::new(primary_port).replication(Replication::primary(replication_port)),
+    );
+    Catalog::default().create_with(ServerOptions::new(replica_port).replication(
+        Replication::replica(format!("ws://127.0.0.1:{replication_port}")),
+    ));
+    sleep(Duration::from_millis(100)).await;
+
+    let replica = ActorRef::new(format!("http://127.0.0.1:{replica_port}"));
+    for (method, params) in [
+        ("put", r#"{"id": 1, "name": "kettle"}"#),
+        ("remove", r#"{"id": 1}"#),
+    ] {
+        match replica.call_raw(method, params.to_string()).await {
+            Err(ClientError::Status(status, body)) => {
+                assert_eq!(status, 403);
+                assert!(body.contains("read-only"));
+            }
+            other => panic!("Expected {} to be rejected, got {:?}", method, other),
+        }
+    }
+
+    let value: Option<String> = replica.call("get", &json!({"id": 1})).await.unwrap();
+    assert_eq!(value, None);
+}
+
+async fn put_with_key(port: u16, id: u32, key: &str) -> String {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/put"))
+        .header("Idempotency-Key", key)
+        .body(json!({"id": id, "name": "kettle"}).to_string())
+        .send()
+        .await
+        .expect("Failed to call server")
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_duplicate_writes_are_not_journaled_twice() {
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+    let replica_port = get_next_port();
+
+    let journal = Journal::new().dedup_key(|method, params| {
+        let params: serde_json::Value = serde_json::from_str(params).ok()?;
+        Some(format!("{}:{}", method, params.get("request")?))
+    });
+    Catalog::default().create_with(
+        ServerOptions::new(primary_port)
+            .journal(journal.clone())
+            .replication(Replication::primary(replication_port)),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    // A retried write is answered as the first time, without running again
+    assert_eq!(put_with_key(primary_port, 1, "k-1").await, "true");
+    assert_eq!(put_with_key(primary_port, 1, "k-1").await, "true");
+    assert_eq!(put_with_key(primary_port, 1, "k-2").await, "false");
+    assert_eq!(journal.last_seq(), 2);
+
+    // Keys can also come from the parameters
+    let primary = ActorRef::new(format!("http://127.0.0.1:{primary_port}"));
+    let params = json!({"id": 2, "name": "toaster", "request": 7});
+    for _ in 0..2 {
+        let added: bool = primary.call("put", &params).await.unwrap();
+        assert!(added);
+    }
+    let entries = journal.entries_since(0);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].key.as_deref(), Some("k-1"));
+    assert_eq!(entries[2].key.as_deref(), Some("put:7"));
+
+    // Replicas apply each entry once
+    Catalog::default().create_with(ServerOptions::new(replica_port).replication(
+        Replication::replica(format!("ws://127.0.0.1:{replication_port}")),
+    ));
+    sleep(Duration::from_millis(100)).await;
+    let replica = ActorRef::new(format!("http://127.0.0.1:{replica_port}"));
+    assert_eq!(
+        wait_for(&replica, 2, Some("toaster")).await.as_deref(),
+        Some("toaster")
+    );
+}
+
+#[tokio::test]
+async fn test_shutting_down_closes_replica_connections() {
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+
+    let primary = Catalog::default().start(
+        ServerOptions::new(primary_port).replication(Replication::primary(replication_port)),
+    );
+    primary.listening().await;
+    let (mut replica, _) = connect_async(format!("ws://127.0.0.1:{replication_port}"))
+        .await
+        .unwrap();
+    replica
+        .send(Message::Text(r#"{"from": 1}"#.to_string()))
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(100)).await;
+
+    timeout(Duration::from_secs(5), primary.drain())
+        .await
+        .expect("the primary should stop with a replica connected");
+    assert!(primary.is_stopped());
+    let closed = timeout(Duration::from_secs(5), async {
+        while let Some(Ok(_)) = replica.next().await {}
+    })
+    .await;
+    assert!(closed.is_ok(), "the replica connection should be closed");
+}
+
+#[tokio::test]
+async fn test_snapshots_truncate_the_journal() {
+    let dir = std::env::temp_dir().join(format!("replication_snapshots_{}", std::process::id()));
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+    let url = format!("ws://127.0.0.1:{replication_port}");
+
+    let journal = Journal::new();
+    Catalog::default().create_with(
+        ServerOptions::new(primary_port)
+            .journal(journal.clone())
+            .admin_token("let-me-in")
+            .snapshots(FileSnapshotStore::new(&dir))
+            .replication(Replication::primary(replication_port)),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let primary = ActorRef::new(format!("http://127.0.0.1:{primary_port}"));
+    for (id, name) in [(1, "kettle"), (2, "toaster")] {
+        let _: bool = primary
+            .call("put", &json!({"id": id, "name": name}))
+            .await
+            .unwrap();
+    }
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{primary_port}/__admin/snapshot"))
+        .bearer_auth("let-me-in")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    // The snapshot holds the first two writes, so the journal lets go of them
+    assert_eq!(journal.first_seq(), 3);
+    assert!(journal.entries_since(0).is_empty());
+    let _: bool = primary
+        .call("put", &json!({"id": 3, "name": "grater"}))
+        .await
+        .unwrap();
+
+    // A replica needing the dropped entries catches up from the snapshot, and one restoring
+    // the snapshot on start follows from the entry after it
+    for restore_on_start in [false, true] {
+        let port = get_next_port();
+        Catalog::default().create_with(
+            ServerOptions::new(port)
+                .snapshots(FileSnapshotStore::new(&dir))
+                .restore_on_start(restore_on_start)
+                .replication(Replication::replica(&url)),
+        );
+        sleep(Duration::from_millis(100)).await;
+        let replica = ActorRef::new(format!("http://127.0.0.1:{port}"));
+        for (id, name) in [(1, "kettle"), (3, "grater")] {
+            assert_eq!(
+                wait_for(&replica, id, Some(name)).await.as_deref(),
+                Some(name)
+            );
+        }
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_replicas_replay_bound_params() {
+    let primary_port = get_next_port();
+    let replication_port = get_next_port();
+    let replica_port = get_next_port();
+
+    Catalog::default().create_with(
+        ServerOptions::new(primary_port).replication(Replication::primary(replication_port)),
+    );
+    Catalog::default().create_with(ServerOptions::new(replica_port).replication(
+        Replication::replica(format!("ws://127.0.0.1:{replication_port}")),
+    ));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{primary_port}/put_own"))
+        .header("X-Tenant-Id", "acme")
+        .header("Cookie", "theme=dark; user=alice")
+        .body(r#"{"id": 1}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let replica = ActorRef::new(format!("http://127.0.0.1:{replica_port}"));
+    assert_eq!(
+        wait_for(&replica, 1, Some("put_own/acme/alice"))
+            .await
+            .as_deref(),
+        Some("put_own/acme/alice")
+    );
+}
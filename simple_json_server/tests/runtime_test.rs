@@ -0,0 +1,36 @@
+#![cfg(feature = "smol")]
+
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Greeter;
+
+#[actor]
+impl Greeter {
+    /// Greet after a short wait, using tokio's timers
+    pub async fn greet(&self, name: String) -> String {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        format!("Hello, {}!", name)
+    }
+}
+
+#[test]
+fn test_serves_and_calls_from_smol() {
+    smol::block_on(async {
+        assert!(tokio::runtime::Handle::try_current().is_err());
+        let server = Greeter.start(ServerOptions::new(0));
+        let port = server.listening().await[0].port();
+
+        let greeter = ActorRef::new(format!("http://127.0.0.1:{port}"));
+        let greeting: String = greeter
+            .call("greet", &json!({"name": "smol"}))
+            .await
+            .unwrap();
+        assert_eq!(greeting, "Hello, smol!");
+
+        server.drain().await;
+        assert!(server.is_stopped());
+    });
+}
@@ -0,0 +1,161 @@
+use serde_json::json;
+use simple_json_server::saga::{
+    Call, MemorySagaStore, Saga, SagaError, SagaProgress, SagaStatus, SagaStore,
+};
+use simple_json_server::{actor, Actor, ActorRef};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41300);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+/// Orders, payments and stock in one actor, recording every call so tests can check the order of
+/// steps and compensations.
+#[derive(Debug, Clone, Default)]
+pub struct Shop {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[actor]
+impl Shop {
+    /// Create an order and return its id
+    pub async fn create_order(&self, item: String) -> u32 {
+        self.log.lock().unwrap().push(format!("create {item}"));
+        42
+    }
+
+    /// Cancel an order
+    pub async fn cancel_order(&self, order: u32) -> bool {
+        self.log.lock().unwrap().push(format!("cancel {order}"));
+        true
+    }
+
+    /// Charge an order and return the charge id
+    pub async fn charge(&self, order: u32) -> String {
+        self.log.lock().unwrap().push(format!("charge {order}"));
+        format!("ch_{order}")
+    }
+
+    /// Refund a charge
+    pub async fn refund(&self, charge: String) -> bool {
+        self.log.lock().unwrap().push(format!("refund {charge}"));
+        true
+    }
+
+    /// Reserve stock for an item
+    pub async fn reserve(&self, item: String) -> bool {
+        self.log.lock().unwrap().push(format!("reserve {item}"));
+        true
+    }
+
+    /// The calls made so far
+    pub async fn history(&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+}
+
+fn order_saga(id: &str, shop: &ActorRef, stock: &ActorRef) -> Saga {
+    let charges = shop.clone();
+    let refunds = shop.clone();
+    Saga::new(id)
+        .step(Call::new(shop, "create_order", json!({"item": "book"})))
+        .compensate_with({
+            let shop = shop.clone();
+            move |order| Call::new(&shop, "cancel_order", json!({ "order": order }))
+        })
+        .step_with(move |results| Call::new(&charges, "charge", json!({ "order": results[0] })))
+        .compensate_with(move |charge| Call::new(&refunds, "refund", json!({ "charge": charge })))
+        .step(Call::new(stock, "reserve", json!({"item": "book"})))
+}
+
+#[tokio::test]
+async fn test_saga_completes() {
+    let port = get_next_port();
+    Shop::default().create(port);
+    sleep(Duration::from_millis(100)).await;
+
+    let shop = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let results = Saga::new("complete")
+        .step(Call::new(&shop, "create_order", json!({"item": "pen"})))
+        .step_with({
+            let shop = shop.clone();
+            move |results| Call::new(&shop, "charge", json!({ "order": results[0] }))
+        })
+        .step(Call::new(&shop, "reserve", json!({"item": "pen"})))
+        .run()
+        .await
+        .expect("Saga should complete");
+
+    assert_eq!(results[0], json!(42));
+    assert_eq!(results[1], json!("ch_42"));
+    assert_eq!(results[2], json!(true));
+
+    let history: Vec<String> = shop.call("history", &json!({})).await.unwrap();
+    assert_eq!(history, vec!["create pen", "charge 42", "reserve pen"]);
+}
+
+#[tokio::test]
+async fn test_failed_step_runs_compensations_in_reverse() {
+    let port = get_next_port();
+    Shop::default().create(port);
+    sleep(Duration::from_millis(100)).await;
+
+    let shop = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    // Nothing serves the stock service, so reserving fails
+    let stock = ActorRef::new(format!("http://127.0.0.1:{}", get_next_port()));
+    let store = MemorySagaStore::new();
+
+    let result = order_saga("order-1", &shop, &stock)
+        .store(store.clone())
+        .run()
+        .await;
+    assert!(matches!(result, Err(SagaError::Aborted { step: 2, .. })));
+
+    let history: Vec<String> = shop.call("history", &json!({})).await.unwrap();
+    assert_eq!(
+        history,
+        vec!["create book", "charge 42", "refund ch_42", "cancel 42"]
+    );
+
+    let progress = store.load("order-1").await.unwrap().unwrap();
+    assert_eq!(progress.compensated, 2);
+    assert!(matches!(
+        progress.status,
+        SagaStatus::Compensated { step: 2, .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_saga_resumes_from_stored_progress() {
+    let port = get_next_port();
+    Shop::default().create(port);
+    sleep(Duration::from_millis(100)).await;
+
+    let shop = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let store = MemorySagaStore::new();
+    store
+        .save(&SagaProgress {
+            id: "order-2".to_string(),
+            status: SagaStatus::Running,
+            results: vec![json!(42), json!("ch_42")],
+            compensated: 0,
+        })
+        .await
+        .unwrap();
+
+    // Only the last step is left
+    let results = order_saga("order-2", &shop, &shop)
+        .store(store)
+        .run()
+        .await
+        .expect("Saga should complete");
+    assert_eq!(results, vec![json!(42), json!("ch_42"), json!(true)]);
+
+    let history: Vec<String> = shop.call("history", &json!({})).await.unwrap();
+    assert_eq!(history, vec!["reserve book"]);
+}
@@ -0,0 +1,78 @@
+use serde_json::{json, Value};
+use simple_json_server::sampling::PayloadSampler;
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Accounts;
+
+#[actor]
+impl Accounts {
+    /// Sign a user in, returning a session token
+    pub async fn login(&self, user: String, password: String) -> Value {
+        json!({ "user": user, "token": password.len().to_string() })
+    }
+}
+
+async fn login(port: u16, traceparent: &str) {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/login"))
+        .header("traceparent", traceparent)
+        .json(&json!({"user": "ada", "password": "hunter2"}))
+        .send()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_sampled_calls_are_kept_redacted() {
+    let sampler = PayloadSampler::new(0.5);
+    let server = Accounts.start(
+        ServerOptions::new(0)
+            .sample_payloads(sampler.clone())
+            .admin_token("secret"),
+    );
+    let port = server.listening().await[0].port();
+
+    // Sampled by the low bits of the trace id
+    login(
+        port,
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    )
+    .await;
+    login(
+        port,
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    )
+    .await;
+    login(
+        port,
+        "00-4bf92f3577b34da61000000000000000-00f067aa0ba902b7-01",
+    )
+    .await;
+
+    let exemplars = sampler.exemplars();
+    assert_eq!(exemplars.len(), 1);
+    assert_eq!(exemplars[0].method, "login");
+    assert_eq!(exemplars[0].trace_id, "4bf92f3577b34da61000000000000000");
+    assert_eq!(exemplars[0].status, 200);
+    assert_eq!(
+        exemplars[0].request,
+        r#"{"password":"[REDACTED]","user":"ada"}"#
+    );
+    assert_eq!(
+        exemplars[0].response,
+        r#"{"token":"[REDACTED]","user":"ada"}"#
+    );
+
+    let listed: Value = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/exemplars"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["request_id"], exemplars[0].request_id.as_str());
+    assert_eq!(listed[0]["request"], exemplars[0].request.as_str());
+}
@@ -0,0 +1,80 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Accounts;
+
+#[actor]
+impl Accounts {
+    /// Register an account, returning what was stored
+    pub async fn register(
+        &self,
+        #[sanitize(trim, lowercase, max_len = 20)] email: String,
+        #[sanitize(strip_control, collapse_whitespace, trim)] name: String,
+        #[sanitize(trim, uppercase)] tags: Vec<String>,
+        #[sanitize(trim)] nickname: Option<String>,
+        #[from_header("X-Region")]
+        #[sanitize(trim, lowercase)]
+        region: String,
+    ) -> Value {
+        json!({
+            "email": email,
+            "name": name,
+            "tags": tags,
+            "nickname": nickname,
+            "region": region,
+        })
+    }
+}
+
+async fn register(port: u16, params: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/register"))
+        .header("X-Region", " EU-West ")
+        .json(&params)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_params_are_sanitized_before_the_method_runs() {
+    let server = Accounts.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let params = json!({
+        "email": "  Ada@Example.COM ",
+        "name": " Ada\u{0}\n  Lovelace ",
+        "tags": [" admin", "ops "],
+        "nickname": null,
+    });
+    assert_eq!(
+        register(port, params).await,
+        (
+            200,
+            json!({
+                "email": "ada@example.com",
+                "name": "Ada Lovelace",
+                "tags": ["ADMIN", "OPS"],
+                "nickname": null,
+                "region": "eu-west",
+            })
+        )
+    );
+
+    // The limit applies once the value is trimmed
+    let params = json!({
+        "email": "   a.very.long.address@example.com",
+        "name": "Ada",
+        "tags": [],
+        "nickname": " ada ",
+    });
+    assert_eq!(
+        register(port, params).await,
+        (
+            400,
+            json!("Parameter `email` of register is 31 characters; at most 20 are allowed")
+        )
+    );
+}
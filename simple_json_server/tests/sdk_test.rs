@@ -0,0 +1,203 @@
+use simple_json_server::events::EventBus;
+use simple_json_server::sdk::{JsClient, KotlinClient, SwiftClient};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Default)]
+pub struct Counter {
+    count: AtomicU64,
+    events: EventBus,
+}
+
+#[actor]
+impl Counter {
+    /// Add `by` to the count, publishing the new count on `counted`
+    pub async fn add(&self, by: u64, note: Option<String>) -> u64 {
+        let _ = note;
+        let count = self.count.fetch_add(by, Ordering::SeqCst) + by;
+        let _ = self
+            .events
+            .publish("counted", &serde_json::json!({ "count": count }));
+        count
+    }
+
+    /// The current count
+    #[read]
+    pub async fn count(&self) -> u64 {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// The counts added, the last first
+    #[read]
+    pub async fn history(&self, max_len: Option<usize>) -> Vec<u64> {
+        let _ = max_len;
+        vec![self.count.load(Ordering::SeqCst)]
+    }
+
+    /// Refuses to close anything
+    #[read]
+    pub async fn close(&self) -> Result<(), String> {
+        Err("not closing".to_string())
+    }
+}
+
+fn generate(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("sdk_test_{}_{}", name, std::process::id()));
+    JsClient::new("counter-client", &Counter::default())
+        .version("2.0.0")
+        .write_to(&dir)
+        .unwrap();
+    dir
+}
+
+#[test]
+fn test_package_is_generated() {
+    let dir = generate("package");
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+
+    let package: serde_json::Value = serde_json::from_str(&read("package.json")).unwrap();
+    assert_eq!(package["name"], "counter-client");
+    assert_eq!(package["version"], "2.0.0");
+    assert_eq!(package["type"], "module");
+
+    let types = read("index.d.ts");
+    assert!(types.contains(
+        "  add(params: { by: number; note?: string | null }, options?: CallOptions): Promise<number>;"
+    ));
+    assert!(types.contains("  count(params?: {}, options?: CallOptions): Promise<number>;"));
+    assert!(types.contains("   * The current count\n"));
+    // Methods can't shadow the client's own
+    assert!(types.contains(
+        "  close_(params?: {}, options?: CallOptions): Promise<{ Ok: null } | { Err: string }>;"
+    ));
+    let js = read("index.js");
+    assert!(js.contains("return this.call(\"count\", params, options, \"read\");"));
+    assert!(read("runtime.js").contains("export class ActorClient"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_mobile_clients_are_generated() {
+    let kotlin = KotlinClient::new("com.example.counter", "CounterClient", &Counter::default());
+    assert_eq!(kotlin.file_name(), "CounterClient.kt");
+    let kotlin = kotlin.source();
+    assert!(kotlin.contains("package com.example.counter\n"));
+    assert!(kotlin.contains("class CounterClient(\n"));
+    assert!(kotlin.contains("    suspend fun add(by: Long, note: String? = null): Long {\n"));
+    assert!(kotlin.contains(
+        "            if (note != null) put(\"note\", json.encodeToJsonElement(serializer<String>(), note))\n"
+    ));
+    assert!(kotlin.contains("        val result = rpc(\"add\", params, read = false)\n"));
+    assert!(kotlin.contains("        val params = JsonObject(emptyMap())\n"));
+    assert!(kotlin.contains("    suspend fun history(maxLen: Long? = null): List<Long> {\n"));
+    assert!(kotlin.contains("    suspend fun close(): ActorResult<JsonElement, String> {\n"));
+    assert!(kotlin.contains("    /**\n     * The current count\n     */\n"));
+
+    let swift = SwiftClient::new("CounterClient", &Counter::default());
+    assert_eq!(swift.file_name(), "CounterClient.swift");
+    let swift = swift.source();
+    assert!(swift.contains("public final class CounterClient {\n"));
+    assert!(
+        swift.contains("    public func add(by: Int, note: String? = nil) async throws -> Int {\n")
+    );
+    assert!(swift.contains(
+        "        let data = try await rpc(\"add\", Params(by: by, note: note), read: false)\n"
+    ));
+    assert!(swift.contains("            case maxLen = \"max_len\"\n"));
+    assert!(swift.contains("    public func history(maxLen: Int? = nil) async throws -> [Int] {\n"));
+    // Methods without parameters send `{}`
+    assert!(swift.contains("rpc(\"count\", [String: String](), read: true)"));
+    assert!(swift
+        .contains("    public func close() async throws -> ActorResult<JSONValue, String> {\n"));
+    assert!(swift.contains("    /// The current count\n"));
+}
+
+#[tokio::test]
+async fn test_generated_client_calls_the_actor() {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("Skipping: node is not installed");
+        return;
+    }
+    let dir = generate("node");
+    let events = EventBus::new();
+    let counter = Counter {
+        events: events.clone(),
+        ..Counter::default()
+    };
+    let server = counter.start(ServerOptions::new(0).events(events.clone()));
+    let port = server.listening().await[0].port();
+    // Subscriptions go to a WebSocket server sharing the bus
+    let ws_server = Counter::default().start(ServerOptions::new(0).websocket(true).events(events));
+    let ws_port = ws_server.listening().await[0].port();
+
+    let script = r#"
+        import { Client, CallError } from "./index.js";
+
+        const client = new Client(process.argv[2], {
+            websocketUrl: process.argv[3],
+            retries: 1,
+            retryDelay: 10,
+        });
+        const results = [];
+        // Node only has a WebSocket from version 22
+        let event = null;
+        if (globalThis.WebSocket) {
+            event = new Promise((resolve) => {
+                const stop = client.subscribe("counted", (data) => {
+                    stop();
+                    resolve(data);
+                });
+            });
+            await new Promise((resolve) => setTimeout(resolve, 200));
+        }
+        results.push(await client.add({ by: 2 }));
+        results.push(await client.add({ by: 3, note: "again" }));
+        results.push(await client.count());
+        results.push(await client.close_());
+        try {
+            await client.add({ by: "three" });
+        } catch (error) {
+            results.push(error instanceof CallError ? error.status : String(error));
+        }
+        const aborted = new AbortController();
+        aborted.abort();
+        try {
+            await client.count({}, { signal: aborted.signal });
+        } catch (error) {
+            results.push(error.name);
+        }
+        console.log(JSON.stringify({ results, event: await event }));
+    "#;
+    std::fs::write(dir.join("test.mjs"), script).unwrap();
+    let (url, ws_url) = (
+        format!("http://127.0.0.1:{port}"),
+        format!("ws://127.0.0.1:{ws_port}"),
+    );
+    let cwd = dir.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new("node")
+            .args(["test.mjs", &url, &ws_url])
+            .current_dir(cwd)
+            .output()
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let output: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        output["results"],
+        serde_json::json!([2, 5, 5, {"Err": "not closing"}, 400, "AbortError"])
+    );
+    if !output["event"].is_null() {
+        assert_eq!(output["event"], serde_json::json!({ "count": 2 }));
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,133 @@
+use simple_json_server::secrets::{Secret, StaticSecrets};
+use simple_json_server::{actor, Actor, ServerOptions, TlsConfig};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+/// A self-signed certificate for localhost and its key, as PEM.
+fn certificate() -> (String, String) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    (
+        cert.serialize_pem().unwrap(),
+        cert.serialize_private_key_pem(),
+    )
+}
+
+/// The certificate a fresh connection to `port` is served, as DER.
+async fn served_certificate(port: u16) -> Vec<u8> {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .tls_info(true)
+        .build()
+        .unwrap()
+        .post(format!("https://127.0.0.1:{port}/add"))
+        .body(r#"{"a": 1, "b": 2}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .unwrap()
+        .to_vec()
+}
+
+fn der(pem: &str) -> Vec<u8> {
+    rustls_pemfile::certs(&mut pem.as_bytes())
+        .next()
+        .unwrap()
+        .unwrap()
+        .to_vec()
+}
+
+async fn admin_status(port: u16, token: &str) -> u16 {
+    reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/disabled"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn test_tls_certificates_rotate() {
+    let (first_cert, first_key) = certificate();
+    let store = StaticSecrets::new();
+    store.set("cert", first_cert.clone());
+    store.set("key", first_key);
+    let rotations = Arc::new(AtomicUsize::new(0));
+    let every = Duration::from_millis(50);
+    let tls = TlsConfig::from_secrets(
+        Secret::store(store.clone(), "cert")
+            .refresh(every)
+            .on_rotate({
+                let rotations = Arc::clone(&rotations);
+                move |_| {
+                    rotations.fetch_add(1, Ordering::SeqCst);
+                }
+            }),
+        Secret::store(store.clone(), "key").refresh(every),
+    );
+    let server = Calculator.start(ServerOptions::new(0).tls(tls));
+    let port = server.listening().await[0].port();
+    assert_eq!(served_certificate(port).await, der(&first_cert));
+
+    // A certificate without its key doesn't match, so the old pair is kept
+    let (second_cert, second_key) = certificate();
+    store.set("cert", second_cert.clone());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(served_certificate(port).await, der(&first_cert));
+    assert_eq!(rotations.load(Ordering::SeqCst), 0);
+
+    store.set("key", second_key);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(served_certificate(port).await, der(&second_cert));
+    assert_eq!(rotations.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_admin_tokens_rotate() {
+    let store = StaticSecrets::new();
+    store.set("admin", "first");
+    let secret = Secret::store(store.clone(), "admin").refresh(Duration::from_millis(50));
+    let server = Calculator.start(ServerOptions::new(0).admin_token_secret(secret));
+    let port = server.listening().await[0].port();
+    assert_eq!(admin_status(port, "first").await, 200);
+
+    store.set("admin", "second");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(admin_status(port, "first").await, 401);
+    assert_eq!(admin_status(port, "second").await, 200);
+
+    // An empty token is never accepted
+    store.set("admin", "");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(admin_status(port, "second").await, 200);
+}
+
+#[tokio::test]
+async fn test_servers_fail_without_their_secrets() {
+    let secret = Secret::env("SECRETS_TEST_UNSET_TOKEN");
+    let server = Calculator.start(ServerOptions::new(0).admin_token_secret(secret));
+    assert!(server.listening().await.is_empty());
+    server.stopped().await;
+    assert_eq!(
+        server.failure().as_deref(),
+        Some("Failed to fetch the admin token: Secret not found: $SECRETS_TEST_UNSET_TOKEN is not set")
+    );
+}
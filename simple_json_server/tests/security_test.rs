@@ -0,0 +1,84 @@
+use simple_json_server::security::SecurityPreset;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43000);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Echo;
+
+#[actor]
+impl Echo {
+    /// Echo some text
+    pub async fn echo(&self, text: String) -> String {
+        text
+    }
+}
+
+async fn post(port: u16, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/echo"))
+        .header("Origin", origin)
+        .body(r#"{"text": "hello"}"#)
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_default_allows_any_origin() {
+    let port = get_next_port();
+    Echo.create_with(ServerOptions::new(port));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "https://anywhere.example.com").await;
+    assert_eq!(response.headers()["Access-Control-Allow-Origin"], "*");
+    assert!(response.headers().get("X-Frame-Options").is_none());
+}
+
+#[tokio::test]
+async fn test_strict_preset() {
+    let port = get_next_port();
+    let preset = SecurityPreset::strict().allow_origin("https://app.example.com");
+    Echo.create_with(ServerOptions::new(port).security(preset));
+    sleep(Duration::from_millis(100)).await;
+
+    let response = post(port, "https://app.example.com").await;
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(
+        headers["Access-Control-Allow-Origin"],
+        "https://app.example.com"
+    );
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(headers["Referrer-Policy"], "no-referrer");
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+    // HSTS is only sent over TLS
+    assert!(headers.get("Strict-Transport-Security").is_none());
+
+    let response = post(port, "https://evil.example.com").await;
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+
+    let preflight = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("http://127.0.0.1:{port}/echo"),
+        )
+        .header("Origin", "https://app.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        preflight.headers()["Access-Control-Allow-Methods"],
+        "POST, OPTIONS"
+    );
+}
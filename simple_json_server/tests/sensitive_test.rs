@@ -0,0 +1,115 @@
+use serde_json::{json, Value};
+use simple_json_server::encryption::Encryption;
+use simple_json_server::sampling::PayloadSampler;
+use simple_json_server::slowlog::SlowLog;
+use simple_json_server::{actor, Actor, RequestContext, ServerOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Payments;
+
+#[actor]
+impl Payments {
+    /// Pay with a card, returning the parameters as they may be logged
+    pub async fn pay(
+        &self,
+        amount: u32,
+        #[sensitive] card: String,
+        #[sensitive] pin: Option<u32>,
+    ) -> String {
+        assert!(amount > 0 && !card.is_empty() && pin.is_none());
+        RequestContext::current().unwrap().redacted_params()
+    }
+
+    /// Charge a card sent encrypted, returning its last four digits
+    pub async fn charge(&self, amount: u32, #[sensitive(encrypted)] card: String) -> String {
+        format!("{} to {}", amount, &card[card.len() - 4..])
+    }
+}
+
+const KEY: [u8; 32] = [3; 32];
+
+async fn call(port: u16, method: &str, params: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .json(&params)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_sensitive_params_are_never_logged() {
+    let info = &Payments.methods()[0];
+    assert!(!info.params[0].sensitive);
+    assert!(info.params[1].sensitive);
+    assert!(!info.params[1].encrypted);
+
+    let sampler = PayloadSampler::new(1.0);
+    let slow_calls = Arc::new(Mutex::new(Vec::new()));
+    let slow_log = SlowLog::new(Duration::ZERO).on_slow({
+        let slow_calls = Arc::clone(&slow_calls);
+        move |call| slow_calls.lock().unwrap().push(call.params.clone())
+    });
+    let server = Payments.start(
+        ServerOptions::new(0)
+            .sample_payloads(sampler.clone())
+            .slow_log(slow_log),
+    );
+    let port = server.listening().await[0].port();
+
+    let (status, logged) = call(port, "pay", json!({"amount": 5, "card": "4111"})).await;
+    assert_eq!(status, 200);
+    let redacted = r#"{"amount":5,"card":"[REDACTED]"}"#;
+    assert_eq!(logged, redacted);
+    assert_eq!(sampler.exemplars()[0].request, redacted);
+    assert_eq!(slow_calls.lock().unwrap()[0], redacted);
+
+    // Serde would quote the rejected value
+    let params = json!({"amount": 5, "card": "4111", "pin": "9876"});
+    let (status, error) = call(port, "pay", params).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        error,
+        "Failed to deserialize parameters for pay (details withheld to protect sensitive parameters)"
+    );
+    let (status, error) = call(port, "pay", json!({"amount": -5, "card": "4111"})).await;
+    assert_eq!(status, 400);
+    // Errors that don't show a sensitive value keep their details
+    assert!(error
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to deserialize parameters for pay: "));
+}
+
+#[tokio::test]
+async fn test_encrypted_params_are_decrypted_for_the_method() {
+    let encryption = Encryption::new(KEY).unwrap();
+    let server = Payments.start(ServerOptions::new(0).field_encryption(encryption.clone()));
+    let port = server.listening().await[0].port();
+
+    let card = encryption.encrypt_param("charge", "card", &json!("4111 1111 1111 1234"));
+    let (status, charged) = call(port, "charge", json!({"amount": 5, "card": card})).await;
+    assert_eq!((status, charged), (200, json!("5 to 1234")));
+
+    let plaintext = json!({"amount": 5, "card": "4111 1111 1111 1234"});
+    let (status, error) = call(port, "charge", plaintext).await;
+    assert_eq!(
+        (status, error),
+        (400, json!("Parameter `card` of charge must be encrypted"))
+    );
+    let moved = encryption.encrypt_param("pay", "card", &json!("4111 1111 1111 1234"));
+    let (status, error) = call(port, "charge", json!({"amount": 5, "card": moved})).await;
+    assert_eq!(
+        (status, error),
+        (400, json!("Parameter `card` of charge can't be decrypted"))
+    );
+
+    // A server without the key can't serve the method
+    let keyless = Payments.start(ServerOptions::new(0));
+    let port = keyless.listening().await[0].port();
+    let (status, _) = call(port, "charge", json!({"amount": 5, "card": card})).await;
+    assert_eq!(status, 500);
+}
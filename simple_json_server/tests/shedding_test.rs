@@ -0,0 +1,61 @@
+use futures_util::future::join_all;
+use simple_json_server::shedding::LoadShedding;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41900);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch;
+
+#[actor]
+impl Batch {
+    /// Holds the actor to itself for a while
+    #[write]
+    pub async fn process(&self) {
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    /// Cheap read
+    #[read]
+    pub async fn status(&self) -> String {
+        "ok".to_string()
+    }
+}
+
+async fn post(port: u16, method: &str, priority: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body("{}");
+    if let Some(priority) = priority {
+        request = request.header("X-Priority", priority);
+    }
+    request.send().await.expect("Failed to call server")
+}
+
+#[tokio::test]
+async fn test_sheds_low_priority_calls_when_queueing() {
+    let port = get_next_port();
+    let shedding = LoadShedding::new(Duration::from_millis(20)).retry_after(Duration::from_secs(2));
+    Batch.create_with(ServerOptions::new(port).load_shedding(shedding.clone()));
+    sleep(Duration::from_millis(100)).await;
+
+    // Idle server: nothing is shed
+    assert_eq!(post(port, "status", Some("low")).await.status(), 200);
+
+    // Exclusive calls queue behind each other, pushing the wait time up
+    join_all((0..6).map(|_| post(port, "process", Some("high")))).await;
+    assert!(shedding.queue_latency() > Duration::from_millis(40));
+
+    let response = post(port, "status", Some("low")).await;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["Retry-After"], "2");
+    assert_eq!(post(port, "status", Some("high")).await.status(), 200);
+    assert!(shedding.shed_count() >= 1);
+}
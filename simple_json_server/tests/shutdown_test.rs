@@ -0,0 +1,110 @@
+use simple_json_server::cluster::{Cluster, MemoryBackend};
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Records what happens to it, in order
+#[derive(Debug)]
+pub struct Recorder {
+    events: Arc<Mutex<Vec<&'static str>>>,
+}
+
+#[actor]
+impl Recorder {
+    async fn on_shutdown(&self) {
+        self.events.lock().unwrap().push("on_shutdown");
+    }
+
+    /// A call that takes a while
+    pub async fn slow(&self) {
+        sleep(Duration::from_millis(200)).await;
+        self.events.lock().unwrap().push("call finished");
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.events.lock().unwrap().push("dropped");
+    }
+}
+
+#[tokio::test]
+async fn test_drain_shuts_down_and_drops_the_actor() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let server = Recorder {
+        events: Arc::clone(&events),
+    }
+    .start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let call = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{port}/slow"))
+            .body("{}")
+            .send(),
+    );
+    sleep(Duration::from_millis(50)).await;
+    server.drain().await;
+
+    assert!(server.is_stopped());
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["call finished", "on_shutdown", "dropped"]
+    );
+    assert!(call.await.unwrap().unwrap().status().is_success());
+}
+
+#[tokio::test]
+async fn test_drain_stops_every_task() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let cluster = Cluster::new("node-a", "http://127.0.0.1:1", MemoryBackend::new());
+    let server = Recorder {
+        events: Arc::clone(&events),
+    }
+    .start(ServerOptions::new(0).cluster(cluster).admin_token("secret"));
+    let port = server.listening().await[0].port();
+    // The cluster election runs in the background
+    assert_eq!(server.tasks(), 1);
+
+    let call = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{port}/slow"))
+            .body("{}")
+            .send(),
+    );
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(server.tasks(), 2);
+    // The metrics request has a connection of its own
+    let metrics = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/metrics"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("simple_json_server_server{measure=\"connections\"} 2\n"));
+    assert!(metrics.contains("simple_json_server_server{measure=\"tasks\"} 3\n"));
+
+    server.drain().await;
+    assert!(call.await.unwrap().unwrap().status().is_success());
+    assert_eq!(server.tasks(), 0);
+}
+
+#[tokio::test]
+async fn test_servers_that_fail_to_start_count_as_stopped() {
+    let taken = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+    let port = taken.local_addr().unwrap().port();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let server = Recorder {
+        events: Arc::clone(&events),
+    }
+    .start(ServerOptions::new(port));
+
+    assert!(server.listening().await.is_empty());
+    server.drain().await;
+    assert!(server.is_stopped());
+    assert_eq!(*events.lock().unwrap(), ["dropped"]);
+}
@@ -0,0 +1,93 @@
+use simple_json_server::signing::Signing;
+use simple_json_server::{actor, Actor, ActorRef, ClientError, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Payments;
+
+#[actor]
+impl Payments {
+    /// Settle an invoice
+    pub async fn settle(&self, cents: u64) -> u64 {
+        cents
+    }
+}
+
+async fn post(port: u16, path: &str, headers: &[(&str, String)], body: &str) -> (u16, String) {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}{path}"))
+        .body(body.to_string());
+    for (name, value) in headers {
+        request = request.header(*name, value);
+    }
+    let response = request.send().await.unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_signed_calls_are_served() {
+    let signing = Signing::new("shared secret");
+    let server = Payments.start(ServerOptions::new(0).signing(signing.clone()));
+    let port = server.listening().await[0].port();
+
+    let payments = ActorRef::new(format!("http://127.0.0.1:{port}")).with_signing(signing);
+    let cents: u64 = payments
+        .call("settle", &serde_json::json!({"cents": 125}))
+        .await
+        .unwrap();
+    assert_eq!(cents, 125);
+
+    // Signed with the wrong secret
+    let forged =
+        ActorRef::new(format!("http://127.0.0.1:{port}")).with_signing(Signing::new("guess"));
+    match forged
+        .call::<_, u64>("settle", &serde_json::json!({"cents": 125}))
+        .await
+    {
+        Err(ClientError::Status(401, body)) => assert_eq!(body, r#""Invalid signature""#),
+        other => panic!("expected a 401, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unsigned_and_replayed_requests_are_refused() {
+    let signing = Signing::new("shared secret");
+    let server = Payments.start(ServerOptions::new(0).signing(signing.clone()));
+    let port = server.listening().await[0].port();
+    let body = r#"{"cents": 10}"#;
+
+    assert_eq!(
+        post(port, "/settle", &[], body).await,
+        (
+            401,
+            r#""Missing or malformed X-Signature-Timestamp header""#.to_string()
+        )
+    );
+
+    let headers = signing.headers("/settle", body);
+    assert_eq!(
+        post(port, "/settle", &headers, body).await,
+        (200, "10".to_string())
+    );
+    assert_eq!(
+        post(port, "/settle", &headers, body).await,
+        (401, r#""Request nonce was already used""#.to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_the_admin_api_needs_no_signature() {
+    let server = Payments.start(
+        ServerOptions::new(0)
+            .signing(Signing::new("shared secret"))
+            .admin_token("secret"),
+    );
+    let port = server.listening().await[0].port();
+
+    let response = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/disabled"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "simulation")]
+
+use simple_json_server::simulation::{SimulatedCall, Simulation};
+use simple_json_server::{actor, Actor};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+pub struct Log {
+    entries: Mutex<Vec<String>>,
+    count: Mutex<u32>,
+}
+
+#[actor]
+impl Log {
+    /// Append to the log in two steps
+    pub async fn append(&self, entry: String) {
+        self.entries.lock().unwrap().push(format!("{entry} start"));
+        tokio::task::yield_now().await;
+        self.entries.lock().unwrap().push(format!("{entry} end"));
+    }
+
+    /// Read the log
+    #[read]
+    pub async fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Increment after a delay, running alone
+    #[write]
+    pub async fn increment(&self) -> u32 {
+        let count = *self.count.lock().unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        *self.count.lock().unwrap() = count + 1;
+        count + 1
+    }
+}
+
+fn appends(seed: u64) -> Vec<String> {
+    let calls = Simulation::new(Log::default(), seed)
+        .call("append", r#"{"entry": "a"}"#)
+        .call("append", r#"{"entry": "b"}"#)
+        .call("append", r#"{"entry": "c"}"#)
+        .call_at(Duration::from_secs(1), "entries", "{}")
+        .run();
+    let last = calls.last().unwrap();
+    assert_eq!(last.method, "entries");
+    serde_json::from_str(&last.body).unwrap()
+}
+
+#[test]
+fn test_seed_determines_interleaving() {
+    assert_eq!(appends(7), appends(7));
+    let interleavings: std::collections::HashSet<_> = (0..20).map(appends).collect();
+    assert!(interleavings.len() > 1);
+}
+
+#[test]
+fn test_virtual_clock() {
+    let started = std::time::Instant::now();
+    let calls = Simulation::new(Log::default(), 1)
+        .call("increment", "{}")
+        .call("increment", "{}")
+        .run();
+    // `#[write]` calls run one after the other, two minutes of virtual time in no real time
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let outcomes: Vec<_> = calls
+        .iter()
+        .map(|call| (call.body.as_str(), call.finished_at))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("1", Duration::from_secs(60)),
+            ("2", Duration::from_secs(120))
+        ]
+    );
+}
+
+#[test]
+fn test_errors_are_reported() {
+    let calls = Simulation::new(Log::default(), 1)
+        .call("append", r#"{"entry": 1}"#)
+        .run();
+    assert!(matches!(calls[..], [SimulatedCall { status: 400, .. }]));
+}
@@ -0,0 +1,65 @@
+use futures_util::future::join_all;
+use serde_json::json;
+use simple_json_server::slowlog::{SlowCall, SlowLog};
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42100);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Accounts;
+
+#[actor]
+impl Accounts {
+    /// Slow because it hashes the password
+    #[write]
+    pub async fn login(&self, user: String, password: String) -> bool {
+        sleep(Duration::from_millis(100)).await;
+        !user.is_empty() && !password.is_empty()
+    }
+
+    /// Fast
+    pub async fn ping(&self) -> String {
+        "pong".to_string()
+    }
+}
+
+#[tokio::test]
+async fn test_slow_calls_are_reported_with_timing_breakdown() {
+    let port = get_next_port();
+    let reported: Arc<Mutex<Vec<SlowCall>>> = Arc::default();
+    let seen = Arc::clone(&reported);
+    Accounts.create_with(
+        ServerOptions::new(port).slow_log(
+            SlowLog::new(Duration::from_millis(50))
+                .on_slow(move |call| seen.lock().unwrap().push(call.clone())),
+        ),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let accounts = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let _: String = accounts.call("ping", &json!({})).await.unwrap();
+    // Two exclusive calls at once: the second queues behind the first
+    let credentials = json!({"user": "ada", "password": "hunter2"});
+    join_all((0..2).map(|_| accounts.call::<_, bool>("login", &credentials))).await;
+
+    let mut reported = reported.lock().unwrap().clone();
+    reported.sort_by_key(|call| call.queue_wait);
+    assert_eq!(reported.len(), 2);
+    assert!(reported
+        .iter()
+        .all(|call| call.method == "login" && call.status == 200));
+    assert!(reported
+        .iter()
+        .all(|call| call.handler >= Duration::from_millis(100)));
+    assert!(reported[1].queue_wait >= Duration::from_millis(80));
+    assert!(!reported[0].params.contains("hunter2"));
+    assert!(reported[0].params.contains("ada"));
+}
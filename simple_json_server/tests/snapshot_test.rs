@@ -0,0 +1,205 @@
+use serde_json::json;
+use simple_json_server::encryption::Encryption;
+use simple_json_server::snapshot::{EncryptedSnapshots, FileSnapshotStore};
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(41500);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+const TOKEN: &str = "let-me-in";
+
+#[derive(Debug, Default)]
+pub struct Inventory {
+    stock: Mutex<HashMap<String, u32>>,
+}
+
+#[actor]
+impl Inventory {
+    /// Add stock for an item
+    pub async fn add(&self, item: String, count: u32) -> u32 {
+        let mut stock = self.stock.lock().unwrap();
+        let total = stock.entry(item).or_default();
+        *total += count;
+        *total
+    }
+
+    /// Current stock of an item
+    #[read]
+    pub async fn count(&self, item: String) -> u32 {
+        self.stock
+            .lock()
+            .unwrap()
+            .get(&item)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn snapshot(&self) -> HashMap<String, u32> {
+        self.stock.lock().unwrap().clone()
+    }
+
+    fn restore(&self, stock: HashMap<String, u32>) {
+        *self.stock.lock().unwrap() = stock;
+    }
+}
+
+async fn admin(port: u16, op: &str, token: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/__admin/{op}"))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to call admin API")
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("{}_{}", name, std::process::id()))
+}
+
+#[tokio::test]
+async fn test_snapshot_and_restore_on_start() {
+    let dir = temp_dir("snapshot_restore_on_start");
+    let port = get_next_port();
+    Inventory::default().create_with(
+        ServerOptions::new(port)
+            .admin_token(TOKEN)
+            .snapshots(FileSnapshotStore::new(&dir)),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let inventory = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let _: u32 = inventory
+        .call("add", &json!({"item": "kettle", "count": 3}))
+        .await
+        .unwrap();
+
+    let response = admin(port, "snapshot", TOKEN).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["bytes"].as_u64().unwrap() > 0);
+
+    // A new server restores the snapshot before serving
+    let restored_port = get_next_port();
+    Inventory::default().create_with(
+        ServerOptions::new(restored_port)
+            .snapshots(FileSnapshotStore::new(&dir))
+            .restore_on_start(true),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let restored = ActorRef::new(format!("http://127.0.0.1:{restored_port}"));
+    let count: u32 = restored
+        .call("count", &json!({"item": "kettle"}))
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_restore_through_admin_api() {
+    let dir = temp_dir("snapshot_admin_restore");
+    let port = get_next_port();
+    Inventory::default().create_with(
+        ServerOptions::new(port)
+            .admin_token(TOKEN)
+            .snapshots(FileSnapshotStore::new(&dir)),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let inventory = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let _: u32 = inventory
+        .call("add", &json!({"item": "toaster", "count": 1}))
+        .await
+        .unwrap();
+    assert_eq!(admin(port, "snapshot", TOKEN).await.status(), 200);
+
+    let _: u32 = inventory
+        .call("add", &json!({"item": "toaster", "count": 10}))
+        .await
+        .unwrap();
+    assert_eq!(admin(port, "restore", TOKEN).await.status(), 200);
+
+    let count: u32 = inventory
+        .call("count", &json!({"item": "toaster"}))
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_api_requires_token() {
+    let port = get_next_port();
+    let disabled_port = get_next_port();
+    Inventory::default().create_with(
+        ServerOptions::new(port)
+            .admin_token(TOKEN)
+            .snapshots(FileSnapshotStore::new(temp_dir("snapshot_unused"))),
+    );
+    Inventory::default().create(disabled_port);
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(admin(port, "snapshot", "wrong").await.status(), 401);
+    assert_eq!(admin(port, "unknown", TOKEN).await.status(), 404);
+    assert_eq!(admin(disabled_port, "snapshot", TOKEN).await.status(), 404);
+}
+
+#[tokio::test]
+async fn test_encrypted_snapshots_need_their_key() {
+    let dir = temp_dir("snapshot_encrypted");
+    let encrypted =
+        |key| EncryptedSnapshots::new(FileSnapshotStore::new(&dir), Encryption::new(key).unwrap());
+    let server = Inventory::default().start(
+        ServerOptions::new(0)
+            .admin_token(TOKEN)
+            .snapshots(encrypted([1; 32])),
+    );
+    let port = server.listening().await[0].port();
+    let inventory = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let _: u32 = inventory
+        .call("add", &json!({"item": "kettle", "count": 3}))
+        .await
+        .unwrap();
+    assert_eq!(admin(port, "snapshot", TOKEN).await.status(), 200);
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let written = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        assert!(!written.contains("kettle"));
+    }
+
+    // The right key restores the snapshot; another can't read it
+    let restored = Inventory::default().start(
+        ServerOptions::new(0)
+            .snapshots(encrypted([1; 32]))
+            .restore_on_start(true),
+    );
+    let restored = ActorRef::new(format!(
+        "http://127.0.0.1:{}",
+        restored.listening().await[0].port()
+    ));
+    let count: u32 = restored
+        .call("count", &json!({"item": "kettle"}))
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+
+    let wrong_key = Inventory::default().start(
+        ServerOptions::new(0)
+            .admin_token(TOKEN)
+            .snapshots(encrypted([2; 32])),
+    );
+    let response = admin(wrong_key.listening().await[0].port(), "restore", TOKEN).await;
+    assert_eq!(response.status(), 500);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,40 @@
+use simple_json_server::socket::SocketOptions;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct Echo;
+
+#[actor]
+impl Echo {
+    /// Say it back
+    pub async fn echo(&self, text: String) -> String {
+        text
+    }
+}
+
+#[tokio::test]
+async fn test_serving_with_socket_options() {
+    let socket = SocketOptions::new()
+        .nodelay(true)
+        .keepalive(Duration::from_secs(30))
+        .keepalive_interval(Duration::from_secs(5))
+        .keepalive_retries(3)
+        .backlog(16)
+        .recv_buffer_size(128 * 1024)
+        .send_buffer_size(128 * 1024);
+    let server = Echo.start(ServerOptions::new(0).socket(socket));
+    let port = server.listening().await[0].port();
+
+    let client = reqwest::Client::new();
+    for _ in 0..3 {
+        let response = client
+            .post(format!("http://127.0.0.1:{port}/echo"))
+            .body(r#"{"text": "hi"}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), r#""hi""#);
+    }
+}
@@ -0,0 +1,41 @@
+use simple_json_server::startup::StartupEvent;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+
+    /// Multiply two numbers
+    pub async fn multiply(&self, a: i32, b: i32) -> i32 {
+        a * b
+    }
+}
+
+#[tokio::test]
+async fn test_startup_event_describes_the_server() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let server = Calculator.start(ServerOptions::new(0).on_startup({
+        let events = Arc::clone(&events);
+        move |event: &StartupEvent| events.lock().unwrap().push(event.clone())
+    }));
+    let port = server.listening().await[0].port();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.addresses, [format!("http://0.0.0.0:{port}")]);
+    assert_eq!(event.transports, ["http"]);
+    assert!(!event.tls);
+    assert_eq!(event.methods, ["add", "multiply"]);
+
+    let json: serde_json::Value = serde_json::to_value(event).unwrap();
+    assert_eq!(json["event"], "started");
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+}
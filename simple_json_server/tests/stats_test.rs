@@ -0,0 +1,87 @@
+use serde_json::json;
+use simple_json_server::stats::Stats;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42000);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+const TOKEN: &str = "stats-token";
+
+#[derive(Debug, Clone)]
+pub struct Parser;
+
+#[actor]
+impl Parser {
+    /// Parse a number
+    pub async fn parse(&self, text: String) -> Result<i64, String> {
+        text.parse().map_err(|_| format!("not a number: {}", text))
+    }
+
+    /// Takes a little while
+    pub async fn slow(&self) {
+        sleep(Duration::from_millis(30)).await;
+    }
+}
+
+async fn admin_get(port: u16, op: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{port}/__admin/{op}"))
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .expect("Failed to call admin API")
+}
+
+#[tokio::test]
+async fn test_stats_are_collected_per_method() {
+    let port = get_next_port();
+    let stats = Stats::new();
+    Parser.create_with(
+        ServerOptions::new(port)
+            .admin_token(TOKEN)
+            .stats(stats.clone()),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let parser = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    for text in ["1", "2", "three", "4"] {
+        let _: Result<i64, String> = parser
+            .call("parse", &json!({ "text": text }))
+            .await
+            .unwrap();
+    }
+    parser.call::<_, ()>("slow", &json!({})).await.unwrap();
+    // Unknown methods are not tracked
+    let _: String = parser.call("missing", &json!({})).await.unwrap();
+
+    let parse = stats.method("parse").unwrap();
+    assert_eq!(parse.calls, 4);
+    assert_eq!(parse.errors, 1);
+    let slow = stats.method("slow").unwrap();
+    assert!(slow.p99_us >= 30_000);
+    assert!(stats.method("missing").is_none());
+
+    let response = admin_get(port, "stats").await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["parse"]["calls"], 4);
+    assert_eq!(body["parse"]["errors"], 1);
+
+    let response = admin_get(port, "metrics").await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let text = response.text().await.unwrap();
+    assert!(text.contains("simple_json_server_errors_total{method=\"parse\"} 1"));
+
+    // State-changing admin operations still need POST
+    assert_eq!(admin_get(port, "snapshot").await.status(), 405);
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Row {
+    pub id: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exporter;
+
+#[actor]
+impl Exporter {
+    /// Every row, serialized as it is sent
+    #[streaming]
+    pub async fn export(&self, rows: usize) -> Vec<Row> {
+        (0..rows)
+            .map(|id| Row {
+                id,
+                name: format!("row {id}"),
+            })
+            .collect()
+    }
+
+    /// Built in memory
+    pub async fn count(&self, rows: usize) -> usize {
+        rows
+    }
+}
+
+async fn post(port: u16, path: &str, params: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{path}"))
+        .json(&params)
+        .send()
+        .await
+        .expect("Failed to call server")
+}
+
+#[test]
+fn test_streaming_methods_are_marked() {
+    let info = Exporter.methods();
+    assert!(info.iter().find(|m| m.name == "export").unwrap().streaming);
+    assert!(!info.iter().find(|m| m.name == "count").unwrap().streaming);
+}
+
+#[tokio::test]
+async fn test_large_results_are_streamed() {
+    let server = Exporter.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let response = post(port, "export", json!({"rows": 100_000})).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.headers()["transfer-encoding"], "chunked");
+    assert!(response.content_length().is_none());
+    let rows: Vec<Row> = response.json().await.unwrap();
+    assert_eq!(rows.len(), 100_000);
+    assert_eq!(rows[99_999].name, "row 99999");
+
+    let response = post(port, "count", json!({"rows": 3})).await;
+    assert_eq!(response.content_length(), Some(1));
+
+    let exporter = ActorRef::new(format!("http://127.0.0.1:{port}"));
+    let rows: Vec<Row> = exporter.call("export", &json!({"rows": 2})).await.unwrap();
+    assert_eq!(
+        rows[1],
+        Row {
+            id: 1,
+            name: "row 1".to_string()
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_reformatted_results_are_not_streamed() {
+    let server = Exporter.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let response = post(port, "export?pretty=1", json!({"rows": 1})).await;
+    assert!(response.content_length().is_some());
+    assert_eq!(
+        response.text().await.unwrap(),
+        "[\n  {\n    \"id\": 0,\n    \"name\": \"row 0\"\n  }\n]"
+    );
+
+    // Called directly there is no transport to leave the result to
+    let response = Exporter.dispatch("export", r#"{"rows": 1}"#).await;
+    assert_eq!(response, r#"[{"id":0,"name":"row 0"}]"#);
+}
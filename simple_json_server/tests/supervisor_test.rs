@@ -0,0 +1,210 @@
+use simple_json_server::supervisor::{Strategy, Supervisor, SupervisorHandle};
+use simple_json_server::{actor, Actor, ServerHandle, ServerOptions};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct Worker {
+    generation: u32,
+}
+
+#[actor]
+impl Worker {
+    /// Which start of the worker this is
+    pub async fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Panics
+    pub async fn crash(&self) -> u32 {
+        panic!("Worker {} crashed", self.generation)
+    }
+}
+
+/// Makes workers, counting how many it has made.
+fn workers() -> (Arc<AtomicU32>, impl Fn() -> Worker + Send + Sync + 'static) {
+    let starts = Arc::new(AtomicU32::new(0));
+    let counter = Arc::clone(&starts);
+    let factory = move || Worker {
+        generation: counter.fetch_add(1, Ordering::SeqCst) + 1,
+    };
+    (starts, factory)
+}
+
+async fn call(server: &ServerHandle, method: &str) -> (u16, String) {
+    let port = server.listening().await[0].port();
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+/// Crash the server at `path` and wait for it to stop.
+async fn crash(supervisor: &SupervisorHandle, path: &str) {
+    let server = supervisor.server(path).unwrap();
+    let (status, _) = call(&server, "crash").await;
+    assert_eq!(status, 500);
+    tokio::time::timeout(Duration::from_secs(5), server.stopped())
+        .await
+        .unwrap();
+}
+
+/// Wait until `supervisor` has restarted children `restarts` times.
+async fn restarted(supervisor: &SupervisorHandle, restarts: u32) {
+    for _ in 0..250 {
+        if supervisor.restarts() >= restarts {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "Expected {} restarts, saw {}",
+        restarts,
+        supervisor.restarts()
+    );
+}
+
+#[tokio::test]
+async fn test_stop_on_panic_fails_the_server() {
+    let server = Worker { generation: 1 }.start(ServerOptions::new(0).stop_on_panic(true));
+    assert_eq!(call(&server, "generation").await, (200, "1".to_string()));
+    assert_eq!(server.failure(), None);
+
+    let (status, body) = call(&server, "crash").await;
+    assert_eq!((status, body.as_str()), (500, r#""Method crash panicked""#));
+    tokio::time::timeout(Duration::from_secs(5), server.stopped())
+        .await
+        .unwrap();
+    assert_eq!(server.failure().as_deref(), Some("Method crash panicked"));
+
+    // Without it only the call's connection fails
+    let server = Worker { generation: 1 }.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+    let crashed = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/crash"))
+        .body("{}")
+        .send()
+        .await;
+    assert!(crashed.is_err());
+    assert_eq!(call(&server, "generation").await.0, 200);
+    assert!(!server.is_stopped());
+    assert_eq!(server.failure(), None);
+}
+
+#[tokio::test]
+async fn test_one_for_one_restarts_only_the_failed_child() {
+    let (a_starts, a) = workers();
+    let (b_starts, b) = workers();
+    let supervisor = Supervisor::new(Strategy::OneForOne)
+        .child("a", ServerOptions::new(0), a)
+        .child("b", ServerOptions::new(0), b)
+        .start();
+
+    crash(&supervisor, "a").await;
+    restarted(&supervisor, 1).await;
+    let a = supervisor.server("a").unwrap();
+    assert_eq!(call(&a, "generation").await, (200, "2".to_string()));
+    assert_eq!(a_starts.load(Ordering::SeqCst), 2);
+    assert_eq!(b_starts.load(Ordering::SeqCst), 1);
+    assert!(!supervisor.server("b").unwrap().is_stopped());
+
+    supervisor.shut_down().await;
+    assert!(a.is_stopped());
+    assert!(supervisor.server("b").unwrap().is_stopped());
+    assert_eq!(supervisor.failure(), None);
+}
+
+#[tokio::test]
+async fn test_all_for_one_restarts_every_child() {
+    let (a_starts, a) = workers();
+    let (b_starts, b) = workers();
+    let supervisor = Supervisor::new(Strategy::AllForOne)
+        .child("a", ServerOptions::new(0), a)
+        .child("b", ServerOptions::new(0), b)
+        .start();
+    let first_b = supervisor.server("b").unwrap();
+
+    crash(&supervisor, "a").await;
+    restarted(&supervisor, 1).await;
+    assert!(first_b.is_stopped());
+    assert_eq!(first_b.failure(), None);
+    assert_eq!(a_starts.load(Ordering::SeqCst), 2);
+    assert_eq!(b_starts.load(Ordering::SeqCst), 2);
+    let b = supervisor.server("b").unwrap();
+    assert_eq!(call(&b, "generation").await, (200, "2".to_string()));
+    supervisor.shut_down().await;
+}
+
+#[tokio::test]
+async fn test_too_many_failures_escalate() {
+    let (_, a) = workers();
+    let (_, b) = workers();
+    let supervisor = Supervisor::new(Strategy::OneForOne)
+        .max_restarts(1, Duration::from_secs(60))
+        .child("a", ServerOptions::new(0), a)
+        .child("b", ServerOptions::new(0), b)
+        .start();
+
+    crash(&supervisor, "a").await;
+    restarted(&supervisor, 1).await;
+    crash(&supervisor, "a").await;
+    tokio::time::timeout(Duration::from_secs(5), supervisor.stopped())
+        .await
+        .unwrap();
+    assert!(supervisor.server("b").unwrap().is_stopped());
+    assert_eq!(supervisor.restarts(), 1);
+    assert_eq!(
+        supervisor.failure().as_deref(),
+        Some("a failed more than 1 times in 60s, last with: Method crash panicked")
+    );
+}
+
+#[tokio::test]
+async fn test_nested_supervisors_are_restarted_when_they_escalate() {
+    let (_, a) = workers();
+    let (b_starts, b) = workers();
+    let (c_starts, c) = workers();
+    let inner = Supervisor::new(Strategy::OneForOne)
+        .max_restarts(0, Duration::from_secs(60))
+        .child("a", ServerOptions::new(0), a)
+        .child("b", ServerOptions::new(0), b);
+    let root = Supervisor::new(Strategy::OneForOne)
+        .child("c", ServerOptions::new(0), c)
+        .supervisor("inner", inner)
+        .start();
+    assert!(root.server("inner").is_none());
+    assert!(root.server("c/a").is_none());
+
+    crash(&root, "inner/a").await;
+    restarted(&root, 1).await;
+    let a = root.server("inner/a").unwrap();
+    assert_eq!(call(&a, "generation").await, (200, "2".to_string()));
+    assert_eq!(b_starts.load(Ordering::SeqCst), 2);
+    assert_eq!(c_starts.load(Ordering::SeqCst), 1);
+    assert_eq!(root.failure(), None);
+
+    root.shut_down().await;
+    assert!(a.is_stopped());
+}
+
+#[tokio::test]
+async fn test_drained_children_are_not_restarted() {
+    let (starts, a) = workers();
+    let supervisor = Supervisor::new(Strategy::AllForOne)
+        .child("a", ServerOptions::new(0), a)
+        .start();
+    supervisor.server("a").unwrap().drain().await;
+
+    // With nothing left to supervise, the supervisor stops too
+    tokio::time::timeout(Duration::from_secs(5), supervisor.stopped())
+        .await
+        .unwrap();
+    assert!(supervisor.server("a").is_none());
+    assert_eq!(supervisor.restarts(), 0);
+    assert_eq!(supervisor.failure(), None);
+    assert_eq!(starts.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,84 @@
+use serde_json::{json, Value};
+use simple_json_server::{actor, Actor, ActorRef, RequestContext, ServerOptions};
+
+/// Reports the span it was called in
+#[derive(Debug)]
+pub struct Payments;
+
+#[actor]
+impl Payments {
+    /// The trace id and parent span id of this call
+    pub async fn charge(&self) -> (String, Option<String>) {
+        let ctx = RequestContext::current().unwrap();
+        (ctx.trace().trace_id(), ctx.trace().parent_span_id())
+    }
+}
+
+/// Calls `Payments` while serving a call
+#[derive(Debug)]
+pub struct Orders {
+    payments: ActorRef,
+}
+
+#[actor]
+impl Orders {
+    /// This call's span, and the span `Payments` saw
+    pub async fn place(&self) -> Value {
+        let ctx = RequestContext::current().unwrap();
+        let (trace_id, parent): (String, Option<String>) =
+            self.payments.call("charge", &json!({})).await.unwrap();
+        json!({
+            "trace_id": ctx.trace().trace_id(),
+            "span_id": ctx.trace().span_id(),
+            "parent": ctx.trace().parent_span_id(),
+            "payments_trace_id": trace_id,
+            "payments_parent": parent,
+        })
+    }
+}
+
+async fn place(port: u16, traceparent: Option<&str>) -> Value {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/place"))
+        .body("{}");
+    if let Some(traceparent) = traceparent {
+        request = request.header("traceparent", traceparent);
+    }
+    request.send().await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn test_calls_join_the_callers_trace() {
+    let orders = Orders {
+        payments: ActorRef::loopback(Payments, ServerOptions::new(0)),
+    };
+    let server = orders.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let spans = place(
+        port,
+        Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+    )
+    .await;
+    assert_eq!(spans["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(spans["parent"], "00f067aa0ba902b7");
+    assert_eq!(spans["payments_trace_id"], spans["trace_id"]);
+    assert_eq!(spans["payments_parent"], spans["span_id"]);
+}
+
+#[tokio::test]
+async fn test_calls_without_a_traceparent_start_a_trace() {
+    let orders = Orders {
+        payments: ActorRef::loopback(Payments, ServerOptions::new(0)),
+    };
+    let server = orders.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    for traceparent in [None, Some("not a traceparent")] {
+        let spans = place(port, traceparent).await;
+        assert_eq!(spans["trace_id"].as_str().unwrap().len(), 32);
+        assert_eq!(spans["parent"], Value::Null);
+        assert_eq!(spans["payments_trace_id"], spans["trace_id"]);
+        assert_eq!(spans["payments_parent"], spans["span_id"]);
+    }
+}
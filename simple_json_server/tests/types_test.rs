@@ -0,0 +1,108 @@
+#![cfg(all(feature = "chrono", feature = "uuid", feature = "decimal"))]
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use simple_json_server::chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use simple_json_server::rust_decimal::Decimal;
+use simple_json_server::uuid::Uuid;
+use simple_json_server::{actor, Actor};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43700);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Billing;
+
+#[actor]
+impl Billing {
+    /// When an invoice issued at `issued` for `days` is due
+    pub async fn due(&self, issued: DateTime<Utc>, days: i64) -> DateTime<Utc> {
+        issued + Duration::days(days)
+    }
+
+    /// The first day of the month after `date`
+    pub async fn next_month(&self, date: NaiveDate) -> Option<NaiveDate> {
+        date.checked_add_months(Months::new(1))?.with_day0(0)
+    }
+
+    /// Echo an invoice id
+    pub async fn invoice(&self, id: Uuid) -> Uuid {
+        id
+    }
+
+    /// Total of `amounts` with `tax_rate` applied
+    pub async fn total(&self, amounts: Vec<Decimal>, tax_rate: Decimal) -> Decimal {
+        amounts.into_iter().sum::<Decimal>() * (Decimal::ONE + tax_rate)
+    }
+}
+
+async fn call(method: &str, params: Value) -> Value {
+    let response = Billing.dispatch(method, &params.to_string()).await;
+    serde_json::from_str(&response).unwrap()
+}
+
+#[tokio::test]
+async fn test_dates_and_times() {
+    assert_eq!(
+        call("due", json!({"issued": "2024-02-20T09:30:00Z", "days": 10})).await,
+        json!("2024-03-01T09:30:00Z")
+    );
+    assert_eq!(
+        call("next_month", json!({"date": "2024-01-31"})).await,
+        json!("2024-02-01")
+    );
+}
+
+#[tokio::test]
+async fn test_uuids() {
+    let id = Uuid::new_v4();
+    assert_eq!(
+        call("invoice", json!({"id": id})).await,
+        json!(id.to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_decimals_keep_their_precision() {
+    // Amounts may be sent as strings or numbers; results are strings so no precision is lost
+    let total = call(
+        "total",
+        json!({"amounts": ["0.10", 0.20, "19.99"], "tax_rate": "0.08"}),
+    )
+    .await;
+    assert_eq!(
+        Decimal::from_str(total.as_str().unwrap()).unwrap(),
+        Decimal::from_str("21.9132").unwrap()
+    );
+}
+
+// Without `arbitrary_precision`, numbers in WebSocket messages are parsed as `f64`
+#[cfg(feature = "arbitrary_precision")]
+#[tokio::test]
+async fn test_websocket_decimals_keep_their_precision() {
+    let port = get_next_port();
+    Billing.create_ws(port);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let (mut socket, _) = connect_async(format!("ws://127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    socket
+        .send(Message::Text(
+            r#"{"method": "total", "params": {"amounts": [0.10000000000000000001], "tax_rate": 0}}"#
+                .to_string(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        Message::Text(r#""0.10000000000000000001""#.to_string())
+    );
+}
@@ -0,0 +1,89 @@
+use serde_json::json;
+use simple_json_server::{actor, Actor, ActorRef, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(42800);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Versioned {
+    version: u32,
+}
+
+#[actor]
+impl Versioned {
+    /// Which version of the server answered
+    pub async fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Answer after a while
+    pub async fn slow(&self, millis: u64) -> u32 {
+        sleep(Duration::from_millis(millis)).await;
+        self.version
+    }
+}
+
+async fn call(port: u16, method: &str, params: serde_json::Value) -> Option<u32> {
+    ActorRef::new(format!("http://127.0.0.1:{port}"))
+        .call(method, &params)
+        .await
+        .ok()
+}
+
+#[tokio::test]
+async fn test_listening_reports_bound_address() {
+    let port = get_next_port();
+    let server = Versioned { version: 1 }.start(ServerOptions::new(port));
+    let addrs = server.listening().await;
+    assert_eq!(addrs.len(), 1);
+    assert_eq!(addrs[0].port(), port);
+    assert!(!server.is_draining());
+}
+
+#[tokio::test]
+async fn test_drain_finishes_calls_in_progress() {
+    let port = get_next_port();
+    let server = Versioned { version: 1 }.start(ServerOptions::new(port));
+    server.listening().await;
+
+    let slow = tokio::spawn(call(port, "slow", json!({"millis": 300})));
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.connections(), 1);
+
+    timeout(Duration::from_secs(5), server.drain())
+        .await
+        .unwrap();
+    assert_eq!(slow.await.unwrap(), Some(1));
+    assert_eq!(server.connections(), 0);
+
+    // Once drained, the server no longer accepts connections
+    assert_eq!(call(port, "version", json!({})).await, None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_new_version_takes_over_port() {
+    let port = get_next_port();
+    let old = Versioned { version: 1 }.start(ServerOptions::new(port).reuse_port(true));
+    old.listening().await;
+    assert_eq!(call(port, "version", json!({})).await, Some(1));
+
+    let new = Versioned { version: 2 }.start(ServerOptions::new(port).reuse_port(true));
+    assert_eq!(new.listening().await[0].port(), port);
+    let in_flight = tokio::spawn(call(port, "slow", json!({"millis": 300})));
+    sleep(Duration::from_millis(100)).await;
+
+    timeout(Duration::from_secs(5), old.drain()).await.unwrap();
+    assert!(in_flight.await.unwrap().is_some());
+
+    // Every call after the handoff reaches the new version
+    for _ in 0..10 {
+        assert_eq!(call(port, "version", json!({})).await, Some(2));
+    }
+}
@@ -0,0 +1,95 @@
+use serde_json::{json, Value};
+use simple_json_server::validated::{EmailAddress, PhoneNumber, Url};
+use simple_json_server::{actor, Actor, ServerOptions};
+
+#[derive(Debug, Clone)]
+pub struct Contacts;
+
+#[actor]
+impl Contacts {
+    /// Add a contact, returning it as stored
+    pub async fn add(
+        &self,
+        email: EmailAddress,
+        phone: PhoneNumber,
+        site: Option<Url>,
+        #[from_header("X-Referrer")] referrer: Option<Url>,
+    ) -> Value {
+        json!({
+            "email": email,
+            "domain": email.domain(),
+            "phone": phone,
+            "site": site,
+            "referrer": referrer.map(|url| url.host_str().map(str::to_string)),
+        })
+    }
+}
+
+async fn add(port: u16, params: Value, referrer: &str) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/add"))
+        .header("X-Referrer", referrer)
+        .json(&params)
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_validated_params_arrive_canonical() {
+    let server = Contacts.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let params = json!({
+        "email": "Ada@Bücher.Example",
+        "phone": "+44 (20) 7946-0018",
+        "site": "https://bücher.example/about",
+    });
+    assert_eq!(
+        add(port, params, "https://example.com/signup").await,
+        (
+            200,
+            json!({
+                "email": "Ada@xn--bcher-kva.example",
+                "domain": "xn--bcher-kva.example",
+                "phone": "+442079460018",
+                "site": "https://xn--bcher-kva.example/about",
+                "referrer": "example.com",
+            })
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_values_are_refused_with_a_code() {
+    let server = Contacts.start(ServerOptions::new(0));
+    let port = server.listening().await[0].port();
+
+    let cases = [
+        (
+            json!({"email": "ada.example.com", "phone": "+15551234567"}),
+            "invalid_email_address: missing `@`",
+        ),
+        (
+            json!({"email": "ada@example.com", "phone": "555-1234"}),
+            "invalid_phone_number: must start with `+`",
+        ),
+        (
+            json!({"email": "ada@example.com", "phone": "+15551234567", "site": "example.com"}),
+            "invalid_url: ",
+        ),
+    ];
+    for (params, code) in cases {
+        let (status, message) = add(port, params, "https://example.com/").await;
+        assert_eq!(status, 400);
+        let message = message.as_str().unwrap();
+        assert!(message.contains(code), "{}", message);
+    }
+
+    // Bound parameters are checked the same way
+    let params = json!({"email": "ada@example.com", "phone": "+15551234567"});
+    let (status, message) = add(port, params, "not a url").await;
+    assert_eq!(status, 400);
+    assert!(message.as_str().unwrap().contains("invalid_url: "));
+}
@@ -0,0 +1,68 @@
+use serde_json::json;
+use simple_json_server::versioning::Versioning;
+use simple_json_server::{actor, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43100);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Calculator;
+
+#[actor]
+impl Calculator {
+    /// Add two numbers
+    pub async fn add(&self, a: i32, b: i32) -> i32 {
+        a + b
+    }
+}
+
+fn start() -> u16 {
+    let port = get_next_port();
+    let versioning = Versioning::new(2).migrate("add", 1, |params| {
+        Ok(json!({"a": params["x"], "b": params["y"]}))
+    });
+    Calculator.create_with(ServerOptions::new(port).versioning(versioning));
+    port
+}
+
+async fn post(port: u16, version: Option<&str>, body: serde_json::Value) -> (u16, String) {
+    let mut request = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/add"))
+        .body(body.to_string());
+    if let Some(version) = version {
+        request = request.header("X-Api-Version", version);
+    }
+    let response = request.send().await.expect("Failed to call server");
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn test_old_payloads_are_migrated() {
+    let port = start();
+    sleep(Duration::from_millis(100)).await;
+
+    let (status, body) = post(port, Some("1"), json!({"x": 2, "y": 3})).await;
+    assert_eq!((status, body.as_str()), (200, "5"));
+    let (status, body) = post(port, None, json!({"x": 2, "y": 3, "_version": 1})).await;
+    assert_eq!((status, body.as_str()), (200, "5"));
+
+    // Current clients need no version
+    let (status, body) = post(port, None, json!({"a": 2, "b": 3})).await;
+    assert_eq!((status, body.as_str()), (200, "5"));
+}
+
+#[tokio::test]
+async fn test_future_versions_are_rejected() {
+    let port = start();
+    sleep(Duration::from_millis(100)).await;
+
+    let (status, body) = post(port, Some("3"), json!({"a": 2, "b": 3})).await;
+    assert_eq!(status, 400);
+    assert!(body.contains("Unsupported API version 3"));
+}
@@ -0,0 +1,105 @@
+use serde_json::json;
+use simple_json_server::stats::Stats;
+use simple_json_server::{actor, warmup, Actor, ServerOptions};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+#[derive(Debug, Default)]
+pub struct Prices {
+    connected: bool,
+    lookups: AtomicU32,
+}
+
+#[actor]
+impl Prices {
+    async fn init(&mut self) -> Result<(), String> {
+        self.connected = true;
+        Ok(())
+    }
+
+    /// Look up a price, warming the cache
+    pub async fn price(&self, symbol: String) -> Result<f64, String> {
+        if !self.connected {
+            return Err("Not connected".to_string());
+        }
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        Ok(symbol.len() as f64)
+    }
+
+    /// How many lookups have been made
+    pub async fn lookups(&self) -> u32 {
+        self.lookups.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub struct Broken;
+
+#[actor]
+impl Broken {
+    async fn init(&mut self) -> Result<(), String> {
+        Err("database unreachable".to_string())
+    }
+
+    /// Never served
+    pub async fn ping(&self) {}
+}
+
+async fn post(port: u16, method: &str, body: serde_json::Value) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{port}/{method}"))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to call server")
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_init_and_warm_up_run_before_serving() {
+    let stats = Stats::new();
+    let server = Prices::default().start(
+        ServerOptions::new(0)
+            .stats(stats.clone())
+            .warm_up("price", json!({"symbol": "ACME"}))
+            .warm_up("missing", json!({})),
+    );
+    let port = server.listening().await[0].port();
+
+    assert_eq!(
+        post(port, "price", json!({"symbol": "XY"})).await,
+        json!({"Ok": 2.0})
+    );
+    // The warm-up call was made, but isn't counted as traffic
+    assert_eq!(post(port, "lookups", json!({})).await, json!(2));
+    assert_eq!(stats.method("price").unwrap().calls, 1);
+}
+
+#[tokio::test]
+async fn test_failed_init_stops_the_server() {
+    let server = Broken.start(ServerOptions::new(0));
+    assert!(server.listening().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_actors_built_asynchronously() {
+    let server = warmup::start_with(
+        async {
+            tokio::task::yield_now().await;
+            Ok(Prices::default())
+        },
+        ServerOptions::new(0),
+    );
+    let port = server.listening().await[0].port();
+    assert_eq!(
+        post(port, "price", json!({"symbol": "ACME"})).await,
+        json!({"Ok": 4.0})
+    );
+
+    let failed = warmup::start_with(
+        async { Err::<Prices, _>("no config".to_string()) },
+        ServerOptions::new(0),
+    );
+    assert!(failed.listening().await.is_empty());
+}
@@ -0,0 +1,116 @@
+use serde_json::json;
+use simple_json_server::events::EventBus;
+use simple_json_server::{actor, Actor, ClientError, Reconnect, ServerOptions, WsActorRef};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+static PORT_COUNTER: AtomicU16 = AtomicU16::new(43400);
+
+fn get_next_port() -> u16 {
+    PORT_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    events: EventBus,
+}
+
+#[actor]
+impl Ticker {
+    /// Publish a price
+    pub async fn set_price(&self, price: f64) -> f64 {
+        let _ = self.events.publish("price", &json!({ "price": price }));
+        price
+    }
+}
+
+fn serve(port: u16, events: &EventBus) -> simple_json_server::ServerHandle {
+    Ticker {
+        events: events.clone(),
+    }
+    .start(
+        ServerOptions::new(port)
+            .websocket(true)
+            .events(events.clone()),
+    )
+}
+
+fn reconnect() -> Reconnect {
+    Reconnect::new()
+        .initial_delay(Duration::from_millis(20))
+        .max_delay(Duration::from_millis(100))
+}
+
+#[tokio::test]
+async fn test_calls_and_events() {
+    let port = get_next_port();
+    let server = serve(port, &EventBus::new());
+    server.listening().await;
+
+    let ticker = WsActorRef::connect(format!("ws://127.0.0.1:{port}"), reconnect());
+    let mut events = ticker.events();
+    ticker.subscribe("price").await.unwrap();
+    let price: f64 = ticker
+        .call("set_price", &json!({"price": 12.5}))
+        .await
+        .unwrap();
+    assert_eq!(price, 12.5);
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.topic, "price");
+    assert_eq!(event.data, json!({"price": 12.5}));
+
+    let result = ticker
+        .call::<_, f64>("set_price", &json!({"price": "high"}))
+        .await;
+    assert!(matches!(result, Err(ClientError::Status(400, _))));
+}
+
+#[tokio::test]
+async fn test_reconnects_and_resubscribes() {
+    let port = get_next_port();
+    let events = EventBus::new();
+    let first = serve(port, &events);
+    first.listening().await;
+
+    let ticker = WsActorRef::connect(format!("ws://127.0.0.1:{port}"), reconnect());
+    let mut received = ticker.events();
+    ticker.subscribe("price").await.unwrap();
+
+    // Replace the server, as a deploy would
+    first.drain().await;
+    sleep(Duration::from_millis(100)).await;
+    let second = serve(port, &events);
+    second.listening().await;
+
+    timeout(Duration::from_secs(5), async {
+        while ticker.reconnects() == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("client did not reconnect");
+    let price: f64 = ticker
+        .call("set_price", &json!({"price": 3.0}))
+        .await
+        .unwrap();
+    assert_eq!(price, 3.0);
+    let event = timeout(Duration::from_secs(5), received.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.data, json!({"price": 3.0}));
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_attempts() {
+    let port = get_next_port();
+    let ticker = WsActorRef::connect(
+        format!("ws://127.0.0.1:{port}"),
+        reconnect().max_attempts(2),
+    );
+    let result = ticker
+        .call::<_, f64>("set_price", &json!({"price": 1.0}))
+        .await;
+    assert!(matches!(result, Err(ClientError::Transport(_))));
+}
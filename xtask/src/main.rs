@@ -0,0 +1,114 @@
+//! Repository tasks, run with `cargo xtask <task>`.
+//!
+//! - `js-client [--out DIR] [--version VERSION] [--publish] [--dry-run]` generates the
+//!   JavaScript client package for the demo actor (see `simple_json_server::sdk`), in
+//!   `target/js-client` by default, and with `--publish` publishes it with `npm publish`.
+//! - `kotlin-client [--out DIR]` and `swift-client [--out DIR]` generate the demo actor's
+//!   Kotlin and Swift clients, in `target/kotlin-client` and `target/swift-client` by default.
+
+use simple_json_server::sdk::{JsClient, KotlinClient, SwiftClient};
+use simple_json_server_demo::SimpleServerDemo;
+use std::path::PathBuf;
+use std::process::{Command, ExitCode};
+
+const USAGE: &str =
+    "Usage: cargo xtask js-client [--out DIR] [--version VERSION] [--publish] [--dry-run]
+       cargo xtask kotlin-client [--out DIR]
+       cargo xtask swift-client [--out DIR]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("js-client") => js_client(&args[1..]),
+        Some("kotlin-client") => kotlin_client(&args[1..]),
+        Some("swift-client") => swift_client(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn js_client(args: &[String]) -> Result<(), String> {
+    let mut out = PathBuf::from("target/js-client");
+    let mut version = None;
+    let (mut publish, mut dry_run) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = args.next().ok_or(USAGE)?.into(),
+            "--version" => version = Some(args.next().ok_or(USAGE)?.clone()),
+            "--publish" => publish = true,
+            "--dry-run" => dry_run = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let mut client = JsClient::new(
+        "simple-json-server-demo-client",
+        &SimpleServerDemo::new(String::new()),
+    )
+    .description("A JavaScript client for the simple_json_server demo actor");
+    if let Some(version) = version {
+        client = client.version(version);
+    }
+    client
+        .write_to(&out)
+        .map_err(|e| format!("Failed to write the client to {}: {}", out.display(), e))?;
+    println!("Generated the JavaScript client in {}", out.display());
+
+    if publish {
+        let mut npm = Command::new("npm");
+        npm.arg("publish").arg(&out);
+        if dry_run {
+            npm.arg("--dry-run");
+        }
+        let status = npm
+            .status()
+            .map_err(|e| format!("Failed to run npm: {}", e))?;
+        if !status.success() {
+            return Err(format!("npm publish failed: {}", status));
+        }
+    }
+    Ok(())
+}
+
+fn kotlin_client(args: &[String]) -> Result<(), String> {
+    let out = out_dir(args, "target/kotlin-client")?;
+    let client = KotlinClient::new(
+        "com.example.simplejsonserver.demo",
+        "SimpleServerDemoClient",
+        &SimpleServerDemo::new(String::new()),
+    );
+    client
+        .write_to(&out)
+        .map_err(|e| format!("Failed to write the client to {}: {}", out.display(), e))?;
+    println!("Generated {}", out.join(client.file_name()).display());
+    Ok(())
+}
+
+fn swift_client(args: &[String]) -> Result<(), String> {
+    let out = out_dir(args, "target/swift-client")?;
+    let client = SwiftClient::new(
+        "SimpleServerDemoClient",
+        &SimpleServerDemo::new(String::new()),
+    );
+    client
+        .write_to(&out)
+        .map_err(|e| format!("Failed to write the client to {}: {}", out.display(), e))?;
+    println!("Generated {}", out.join(client.file_name()).display());
+    Ok(())
+}
+
+/// The directory given with `--out`, the only option, or `default`.
+fn out_dir(args: &[String], default: &str) -> Result<PathBuf, String> {
+    match args {
+        [] => Ok(PathBuf::from(default)),
+        [flag, dir] if flag == "--out" => Ok(PathBuf::from(dir)),
+        _ => Err(USAGE.to_string()),
+    }
+}